@@ -189,6 +189,104 @@ impl MemoryFormat {
         }
     }
 
+    /// Counterpart of the format with straight alpha
+    ///
+    /// Formats that are not premultiplied are returned unchanged.
+    pub const fn straight_alpha(self) -> Self {
+        match self {
+            MemoryFormat::B8g8r8a8Premultiplied => MemoryFormat::B8g8r8a8,
+            MemoryFormat::A8r8g8b8Premultiplied => MemoryFormat::A8r8g8b8,
+            MemoryFormat::R8g8b8a8Premultiplied => MemoryFormat::R8g8b8a8,
+            MemoryFormat::R16g16b16a16Premultiplied => MemoryFormat::R16g16b16a16,
+            MemoryFormat::R32g32b32a32FloatPremultiplied => MemoryFormat::R32g32b32a32Float,
+            MemoryFormat::G8a8Premultiplied => MemoryFormat::G8a8,
+            MemoryFormat::G16a16Premultiplied => MemoryFormat::G16a16,
+            other => other,
+        }
+    }
+
+    /// Counterpart of the format with premultiplied alpha
+    ///
+    /// Formats without alpha channel or that are already premultiplied are
+    /// returned unchanged. If no premultiplied format with the same channel
+    /// order exists, the closest premultiplied format is returned.
+    pub const fn premultiplied_alpha(self) -> Self {
+        match self {
+            MemoryFormat::B8g8r8a8 => MemoryFormat::B8g8r8a8Premultiplied,
+            MemoryFormat::A8r8g8b8 => MemoryFormat::A8r8g8b8Premultiplied,
+            MemoryFormat::R8g8b8a8 | MemoryFormat::A8b8g8r8 => MemoryFormat::R8g8b8a8Premultiplied,
+            MemoryFormat::R16g16b16a16 => MemoryFormat::R16g16b16a16Premultiplied,
+            MemoryFormat::R16g16b16a16Float | MemoryFormat::R32g32b32a32Float => {
+                MemoryFormat::R32g32b32a32FloatPremultiplied
+            }
+            MemoryFormat::G8a8 => MemoryFormat::G8a8Premultiplied,
+            MemoryFormat::G16a16 => MemoryFormat::G16a16Premultiplied,
+            other => other,
+        }
+    }
+
+    /// Human-readable name of the format
+    ///
+    /// The name contains the channels in memory order with their bit depth.
+    /// The names are stable and can be shown to users.
+    pub const fn name(self) -> &'static str {
+        match self {
+            MemoryFormat::B8g8r8a8Premultiplied => "B8G8R8A8 premultiplied",
+            MemoryFormat::A8r8g8b8Premultiplied => "A8R8G8B8 premultiplied",
+            MemoryFormat::R8g8b8a8Premultiplied => "R8G8B8A8 premultiplied",
+            MemoryFormat::B8g8r8a8 => "B8G8R8A8",
+            MemoryFormat::A8r8g8b8 => "A8R8G8B8",
+            MemoryFormat::R8g8b8a8 => "R8G8B8A8",
+            MemoryFormat::A8b8g8r8 => "A8B8G8R8",
+            MemoryFormat::R8g8b8 => "R8G8B8",
+            MemoryFormat::B8g8r8 => "B8G8R8",
+            MemoryFormat::R16g16b16 => "R16G16B16",
+            MemoryFormat::R16g16b16a16Premultiplied => "R16G16B16A16 premultiplied",
+            MemoryFormat::R16g16b16a16 => "R16G16B16A16",
+            MemoryFormat::R16g16b16Float => "R16G16B16 float",
+            MemoryFormat::R16g16b16a16Float => "R16G16B16A16 float",
+            MemoryFormat::R32g32b32Float => "R32G32B32 float",
+            MemoryFormat::R32g32b32a32FloatPremultiplied => "R32G32B32A32 float premultiplied",
+            MemoryFormat::R32g32b32a32Float => "R32G32B32A32 float",
+            MemoryFormat::G8a8Premultiplied => "G8A8 premultiplied",
+            MemoryFormat::G8a8 => "G8A8",
+            MemoryFormat::G8 => "G8",
+            MemoryFormat::G16a16Premultiplied => "G16A16 premultiplied",
+            MemoryFormat::G16a16 => "G16A16",
+            MemoryFormat::G16 => "G16",
+        }
+    }
+
+    /// DRM FourCC code of the format
+    ///
+    /// DRM formats describe little endian words, so the channel order in the
+    /// code is reversed compared to the memory order. Grayscale formats map to
+    /// the single channel formats `R8` and `R16`. Whether the alpha channel is
+    /// premultiplied is not part of DRM formats. Returns `None` if no
+    /// equivalent DRM format exists, and for formats with more than 8 bits per
+    /// channel on big endian systems.
+    pub const fn fourcc(self) -> Option<[u8; 4]> {
+        let fourcc = match self {
+            MemoryFormat::B8g8r8a8Premultiplied | MemoryFormat::B8g8r8a8 => *b"AR24",
+            MemoryFormat::A8r8g8b8Premultiplied | MemoryFormat::A8r8g8b8 => *b"BA24",
+            MemoryFormat::R8g8b8a8Premultiplied | MemoryFormat::R8g8b8a8 => *b"AB24",
+            MemoryFormat::A8b8g8r8 => *b"RA24",
+            MemoryFormat::R8g8b8 => *b"BG24",
+            MemoryFormat::B8g8r8 => *b"RG24",
+            MemoryFormat::G8 => *b"R8  ",
+            MemoryFormat::R16g16b16a16Premultiplied | MemoryFormat::R16g16b16a16
+                if cfg!(target_endian = "little") =>
+            {
+                *b"AB48"
+            }
+            MemoryFormat::R16g16b16a16Float if cfg!(target_endian = "little") => *b"AB4H",
+            MemoryFormat::G16 if cfg!(target_endian = "little") => *b"R16 ",
+            _ => return None,
+        };
+
+        Some(fourcc)
+    }
+
     /// Defines from which channels to get the RGBA values
     ///
     /// The return value is in the order `[R, G, B, A]`.
@@ -314,7 +412,7 @@ impl MemoryFormat {
     }
 
     #[inline]
-    pub(crate) fn from_f32(channels_f32: [f32; 4], target_format: Self, target: &mut [u8]) {
+    pub fn from_f32(channels_f32: [f32; 4], target_format: Self, target: &mut [u8]) {
         match target_format.channel_type() {
             ChannelType::U8 => Self::from_f32_internal::<u8>(channels_f32, target_format, target),
             ChannelType::U16 => Self::from_f32_internal::<u16>(channels_f32, target_format, target),
@@ -565,4 +663,45 @@ mod tests {
 
         assert_eq!(*target, [255, 255, 0, 0, 127, 127]);
     }
+
+    #[test]
+    fn alpha_counterparts() {
+        for format in (0..).map_while(|x: i32| MemoryFormat::try_from(x).ok()) {
+            let straight = format.straight_alpha();
+            let premultiplied = format.premultiplied_alpha();
+
+            assert!(!straight.is_premultiplied());
+            assert_eq!(straight.has_alpha(), format.has_alpha());
+            assert_eq!(premultiplied.is_premultiplied(), format.has_alpha());
+            assert_eq!(premultiplied.has_alpha(), format.has_alpha());
+        }
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(MemoryFormat::R8g8b8a8.name(), "R8G8B8A8");
+        assert_eq!(
+            MemoryFormat::R32g32b32a32FloatPremultiplied.name(),
+            "R32G32B32A32 float premultiplied"
+        );
+
+        let mut names = (0..)
+            .map_while(|x: i32| MemoryFormat::try_from(x).ok())
+            .map(MemoryFormat::name)
+            .collect::<Vec<_>>();
+        let n_formats = names.len();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), n_formats);
+    }
+
+    #[test]
+    fn fourcc() {
+        assert_eq!(MemoryFormat::R8g8b8a8.fourcc(), Some(*b"AB24"));
+        assert_eq!(MemoryFormat::B8g8r8a8Premultiplied.fourcc(), Some(*b"AR24"));
+        assert_eq!(MemoryFormat::R8g8b8.fourcc(), Some(*b"BG24"));
+        assert_eq!(MemoryFormat::G8.fourcc(), Some(*b"R8  "));
+        assert_eq!(MemoryFormat::G8a8.fourcc(), None);
+        assert_eq!(MemoryFormat::R32g32b32Float.fourcc(), None);
+    }
 }
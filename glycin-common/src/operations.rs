@@ -95,6 +95,32 @@ impl Operations {
         Ok(buf)
     }
 
+    /// Human-readable representation, for example, to store edits in a file
+    ///
+    /// ```
+    /// # use glycin_common::{Operations, Operation};
+    /// # use gufo_common::orientation::Rotation;
+    /// let operations = Operations::new(vec![
+    ///     Operation::Clip((10, 20, 300, 200)),
+    ///     Operation::Rotate(Rotation::_90),
+    ///     Operation::MirrorHorizontally,
+    /// ]);
+    ///
+    /// let json = operations.to_json().unwrap();
+    /// assert_eq!(Operations::from_json(&json).unwrap(), operations);
+    /// ```
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Reads operations from the representation of [`Self::to_json`]
+    ///
+    /// Like for the other formats, operations that are unknown to this
+    /// version are skipped and listed in [`Self::unknown_operations`].
+    pub fn from_json(s: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(s)
+    }
+
     pub fn operations(&self) -> &[Operation] {
         &self.operations
     }
@@ -247,3 +273,55 @@ impl FromStr for OperationId {
         Self::deserialize(slice.into_deserializer())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_roundtrip() {
+        let operations = Operations::new(vec![
+            Operation::Clip((1, 2, 30, 40)),
+            Operation::MirrorHorizontally,
+            Operation::MirrorVertically,
+            Operation::Rotate(Rotation::_0),
+            Operation::Rotate(Rotation::_90),
+            Operation::Rotate(Rotation::_180),
+            Operation::Rotate(Rotation::_270),
+        ]);
+
+        let json = operations.to_json().unwrap();
+        let decoded = Operations::from_json(&json).unwrap();
+
+        assert_eq!(decoded, operations);
+        assert!(decoded.unknown_operations().is_empty());
+    }
+
+    #[test]
+    fn json_unknown_operation() {
+        let operations = Operations::from_json(
+            r#"{"operations": ["MirrorVertically", "Sharpen", "MirrorHorizontally"]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            operations.operations(),
+            &[Operation::MirrorVertically, Operation::MirrorHorizontally]
+        );
+        assert_eq!(operations.unknown_operations().len(), 1);
+    }
+
+    #[test]
+    fn json_malformed() {
+        for json in [
+            "",
+            "operations",
+            "[]",
+            r#"{"operations": "MirrorVertically"}"#,
+            r#"{"operations": ["MirrorVertically""#,
+            r#"{"operation": ["MirrorVertically"]}"#,
+        ] {
+            assert!(Operations::from_json(json).is_err(), "{json}");
+        }
+    }
+}
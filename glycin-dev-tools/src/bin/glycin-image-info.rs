@@ -61,6 +61,12 @@ async fn run() -> Result<(), glycin::ErrorCtx> {
         "dimensions_text = {}",
         info.info_dimensions_text().as_ref().cloned().unwrap_or("-")
     );
+    println!(
+        "alpha_channel = {}",
+        info.info_alpha_channel()
+            .map(|x| x.to_string())
+            .unwrap_or("-".into())
+    );
     println!(
         "dimensions_inch = {}",
         info.dimensions_inch()
@@ -68,6 +74,9 @@ async fn run() -> Result<(), glycin::ErrorCtx> {
             .map(|(x, y)| format!("{:.3}” x {:.3}”", x, y))
             .unwrap_or("-".into())
     );
+    if let Some(background_color) = info.animation_background_color() {
+        println!("animation_background_color = {background_color:?}");
+    }
 
     for _ in 0..n_frames {
         let frame = image.next_frame().await.unwrap();
@@ -110,6 +119,9 @@ async fn run() -> Result<(), glycin::ErrorCtx> {
                 .map(|x| format!("{} bit", x))
                 .unwrap_or("-".into())
         );
+        if let Some(channel_bit_depths) = frame.details().channel_bit_depths() {
+            println!("channel_bit_depths = {channel_bit_depths:?}");
+        }
         println!(
             "alpha_channel = {}",
             frame
@@ -126,6 +138,14 @@ async fn run() -> Result<(), glycin::ErrorCtx> {
                 .map(|x| x.to_string())
                 .unwrap_or("-".into())
         );
+        println!(
+            "cmyk = {}",
+            frame
+                .details()
+                .info_cmyk()
+                .map(|x| x.to_string())
+                .unwrap_or("-".into())
+        );
     }
 
     Ok(())
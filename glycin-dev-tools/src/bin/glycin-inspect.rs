@@ -0,0 +1,192 @@
+// SPDX-License-Identifier: MPL-2.0 OR LGPL-2.1-or-later
+
+//! Print information about an image
+//!
+//! Usage: `glycin-inspect [--json] <IMAGE PATH>`
+//!
+//! The output is meant to be included in bug reports.
+
+use glycin::{ColorState, FrameRequest, Loader};
+use tracing_subscriber::prelude::*;
+
+enum Value {
+    String(String),
+    Number(u64),
+    Bool(bool),
+    None,
+}
+
+impl Value {
+    fn human(&self) -> String {
+        match self {
+            Self::String(x) => x.clone(),
+            Self::Number(x) => x.to_string(),
+            Self::Bool(true) => String::from("yes"),
+            Self::Bool(false) => String::from("no"),
+            Self::None => String::from("-"),
+        }
+    }
+
+    fn json(&self) -> String {
+        match self {
+            Self::String(x) => json_string(x),
+            Self::Number(x) => x.to_string(),
+            Self::Bool(x) => x.to_string(),
+            Self::None => String::from("null"),
+        }
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::None, Into::into)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl From<u32> for Value {
+    fn from(value: u32) -> Self {
+        Self::Number(value.into())
+    }
+}
+
+impl From<u64> for Value {
+    fn from(value: u64) -> Self {
+        Self::Number(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+fn main() {
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::EnvFilter::builder().from_env_lossy())
+        .with(tracing_subscriber::fmt::Layer::default().compact())
+        .init();
+
+    let mut json = false;
+    let mut path = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            _ if path.is_none() => path = Some(arg),
+            _ => usage(),
+        }
+    }
+
+    let Some(path) = path else { usage() };
+
+    match async_io::block_on(inspect(&path)) {
+        Ok(entries) if json => {
+            let entries = entries
+                .iter()
+                .map(|(key, value)| format!("  {}: {}", json_string(key), value.json()))
+                .collect::<Vec<_>>();
+            println!("{{\n{}\n}}", entries.join(",\n"));
+        }
+        Ok(entries) => {
+            for (key, value) in entries {
+                println!("{key:<14} {}", value.human());
+            }
+        }
+        Err(err) => {
+            eprintln!("Failed to load {path}: {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: glycin-inspect [--json] <IMAGE PATH>");
+    std::process::exit(2);
+}
+
+async fn inspect(path: &str) -> Result<Vec<(&'static str, Value)>, glycin::ErrorCtx> {
+    let file = gio::File::for_path(path);
+    let image = Loader::new(file).load().await?;
+    let details = image.details();
+
+    let frame = image
+        .specific_frame(FrameRequest::new().loop_animation(false))
+        .await?;
+    let frame_details = frame.details();
+
+    let color_state = match frame.color_state() {
+        ColorState::Cicp(cicp) => {
+            let [primaries, transfer, matrix, range] = cicp.to_bytes();
+            format!("CICP {primaries}/{transfer}/{matrix}/{range}")
+        }
+        _ => String::from("sRGB"),
+    };
+
+    // Still images only have one frame that is returned without delay
+    let mut n_frames = 1_u64;
+    if frame.delay().is_some() {
+        loop {
+            match image
+                .specific_frame(FrameRequest::new().loop_animation(false))
+                .await
+            {
+                Ok(_) => n_frames = n_frames.saturating_add(1),
+                Err(err) if err.is_no_more_frames() => break,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    Ok(vec![
+        ("mime_type", image.mime_type().as_str().into()),
+        ("width", details.width().into()),
+        ("height", details.height().into()),
+        ("format_name", details.info_format_name().into()),
+        (
+            "bit_depth",
+            frame_details.info_bit_depth().map(u32::from).into(),
+        ),
+        ("alpha_channel", frame_details.info_alpha_channel().into()),
+        ("grayscale", frame_details.info_grayscale().into()),
+        ("cmyk", frame_details.info_cmyk().into()),
+        (
+            "memory_format",
+            format!("{:?}", frame.memory_format()).into(),
+        ),
+        ("color_state", color_state.into()),
+        ("exif", details.metadata_exif().is_some().into()),
+        ("xmp", details.metadata_xmp().is_some().into()),
+        (
+            "icc_profile",
+            frame_details.color_icc_profile().is_some().into(),
+        ),
+        ("frames", n_frames.into()),
+    ])
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
@@ -36,45 +36,7 @@ impl EditorImplementation for ImgEditor {
         encoding_options: glycin_utils::EncodingOptions,
     ) -> Result<glycin_utils::EncodedImage, glycin_utils::ProcessError> {
         let frame = new_image.frames.remove(0);
-
-        let memory_format = (glycin_utils::MemoryFormatSelection::R8g8b8
-            | glycin_utils::MemoryFormatSelection::R8g8b8a8)
-            .best_format_for(frame.memory_format)
-            .internal_error()?;
-
-        let v = frame.texture.get_full().expected_error()?;
-        let img_buf = glycin_utils::ImgBuf::Vec(v);
-        let (frame, img_buf) =
-            glycin_utils::editing::change_memory_format(img_buf, frame, memory_format)
-                .expected_error()?;
-
-        let width = frame.width;
-        let height = frame.height;
-
-        let heif_chroma = heif_chroma(frame.memory_format).internal_error()?;
-        let mut image = Image::new(width, height, ColorSpace::Rgb(heif_chroma)).expected_error()?;
-
-        image
-            .create_plane(Channel::Interleaved, width, height, 8)
-            .expected_error()?;
-
-        if let Some(icc_profile) = &frame.details.color_icc_profile {
-            image
-                .set_color_profile_raw(&ColorProfileRaw::new(
-                    four_cc::FourCC(*b"prof"),
-                    icc_profile.get_full().internal_error()?,
-                ))
-                .expected_error()?;
-        }
-
-        let plane = image.planes_mut().interleaved.internal_error()?;
-        let new_stride = width as usize * memory_format.n_bytes().usize();
-
-        for y in 0..height as usize {
-            for x in 0..new_stride {
-                plane.data[plane.stride * y + x] = img_buf[y * new_stride + x];
-            }
-        }
+        let image = heif_image(frame)?;
 
         // Encode image and save it into file.
         let lib_heif = LibHeif::new();
@@ -97,10 +59,20 @@ impl EditorImplementation for ImgEditor {
             ))
             .expected_error()?;
 
-        context
+        let handle = context
             .encode_image(&image, &mut encoder, None)
             .expected_error()?;
 
+        if let Some(thumbnail) = new_image.thumbnail {
+            let thumbnail = heif_image(thumbnail)?;
+            let thumbnail_handle = context
+                .encode_image(&thumbnail, &mut encoder, None)
+                .expected_error()?;
+            context
+                .assign_thumbnail(&handle, &thumbnail_handle)
+                .expected_error()?;
+        }
+
         let bytes = context.write_to_bytes().expected_error()?;
         let data = BinaryData::from_data(bytes).expected_error()?;
 
@@ -108,6 +80,50 @@ impl EditorImplementation for ImgEditor {
     }
 }
 
+/// HEIF image with the pixels and ICC profile of `frame`
+fn heif_image(frame: glycin_utils::Frame) -> Result<Image, glycin_utils::ProcessError> {
+    let memory_format = (glycin_utils::MemoryFormatSelection::R8g8b8
+        | glycin_utils::MemoryFormatSelection::R8g8b8a8)
+        .best_format_for(frame.memory_format)
+        .internal_error()?;
+
+    let v = frame.texture.get_full().expected_error()?;
+    let img_buf = glycin_utils::ImgBuf::Vec(v);
+    let (frame, img_buf) =
+        glycin_utils::editing::change_memory_format(img_buf, frame, memory_format)
+            .expected_error()?;
+
+    let width = frame.width;
+    let height = frame.height;
+
+    let heif_chroma = heif_chroma(frame.memory_format).internal_error()?;
+    let mut image = Image::new(width, height, ColorSpace::Rgb(heif_chroma)).expected_error()?;
+
+    image
+        .create_plane(Channel::Interleaved, width, height, 8)
+        .expected_error()?;
+
+    if let Some(icc_profile) = &frame.details.color_icc_profile {
+        image
+            .set_color_profile_raw(&ColorProfileRaw::new(
+                four_cc::FourCC(*b"prof"),
+                icc_profile.get_full().internal_error()?,
+            ))
+            .expected_error()?;
+    }
+
+    let plane = image.planes_mut().interleaved.internal_error()?;
+    let new_stride = width as usize * memory_format.n_bytes().usize();
+
+    for y in 0..height as usize {
+        for x in 0..new_stride {
+            plane.data[plane.stride * y + x] = img_buf[y * new_stride + x];
+        }
+    }
+
+    Ok(image)
+}
+
 fn heif_chroma(memory_format: glycin_utils::MemoryFormat) -> Option<RgbChroma> {
     Some(match memory_format {
         glycin_utils::MemoryFormat::R8g8b8 => RgbChroma::Rgb,
@@ -6,8 +6,8 @@ use glycin_utils::safe_math::*;
 use glycin_utils::*;
 use gufo_common::cicp::Cicp;
 use libheif_rs::{
-    ColorProfile, ColorProfileNCLX, ColorProfileRaw, ColorSpace, HeifContext, LibHeif, RgbChroma,
-    StreamReader,
+    ColorProfile, ColorProfileNCLX, ColorProfileRaw, ColorSpace, HeifContext, ImageHandle, LibHeif,
+    RgbChroma, StreamReader,
 };
 
 use crate::editing::ImgEditor;
@@ -15,20 +15,24 @@ use crate::editing::ImgEditor;
 init_main_loader_editor!(ImgDecoder, ImgEditor);
 
 pub struct ImgDecoder {
-    pub decoder: Option<HeifContext<'static>>,
+    pub decoder: HeifContext<'static>,
     pub mime_type: String,
+    /// ICC profile of the primary image
+    pub icc_profile: Option<Vec<u8>>,
+    /// Report the CICP even if there is an ICC profile
+    pub prefer_cicp: bool,
 }
 
 unsafe impl Sync for ImgDecoder {}
 
 impl LoaderImplementation for ImgDecoder {
     fn init(
-        mut stream: UnixStream,
+        stream: UnixStream,
         mime_type: String,
-        _details: InitializationDetails,
+        details: InitializationDetails,
     ) -> Result<(Self, ImageDetails), ProcessError> {
-        let mut data = Vec::new();
-        let total_size = stream.read_to_end(&mut data).internal_error()?;
+        let data = InputData::read(stream).internal_error()?;
+        let total_size = data.len();
 
         let stream_reader = StreamReader::new(Cursor::new(data), total_size.try_u64()?);
         let context = HeifContext::read_from_reader(Box::new(stream_reader)).expected_error()?;
@@ -47,26 +51,63 @@ impl LoaderImplementation for ImgDecoder {
             .transpose()
             .expected_error()?;
         image_info.info_format_name = Some(format_name.to_string());
+        image_info.info_alpha_channel = Some(handle.has_alpha_channel());
+
+        let top_level_images = context.top_level_image_handles();
+        if top_level_images.len() > 1 {
+            image_info.sub_images = Some(
+                top_level_images
+                    .iter()
+                    .map(|x| SubImageInfo::new(x.width(), x.height(), SubImageKind::Item))
+                    .collect(),
+            );
+        }
 
         // TODO: Later use libheif 1.16 to get info if there is a transformation
         image_info.transformation_ignore_exif = true;
 
+        let icc_profile = raw_icc_profile(handle.color_profile_raw());
+
         let decoder = ImgDecoder {
-            decoder: Some(context),
+            decoder: context,
             mime_type,
+            icc_profile,
+            prefer_cicp: details.prefer_cicp.unwrap_or_default(),
         };
 
         Ok((decoder, image_info))
     }
 
-    fn frame(&mut self, _frame_request: FrameRequest) -> Result<Frame, ProcessError> {
-        decode(self.decoder.take().unwrap(), &self.mime_type)
+    fn frame(&mut self, frame_request: FrameRequest) -> Result<Frame, ProcessError> {
+        // Only borrow the context, since further frames can be requested
+        let context = &self.decoder;
+
+        let handle = if let Some(sub_image) = frame_request.sub_image {
+            context
+                .top_level_image_handles()
+                .into_iter()
+                .nth(sub_image.try_usize()?)
+                .ok_or_else(|| ProcessError::expected(&format!("No sub-image {sub_image}")))?
+        } else {
+            context.primary_image_handle().expected_error()?
+        };
+
+        match frame_request.auxiliary_image {
+            Some(AuxiliaryImage::Alpha) => decode_alpha(&handle, &self.mime_type),
+            _ => decode(&handle, &self.mime_type, self.prefer_cicp),
+        }
+    }
+
+    fn color_profile(&mut self) -> Result<Option<BinaryData>, ProcessError> {
+        self.icc_profile
+            .as_ref()
+            .map(BinaryData::from_data)
+            .transpose()
+            .expected_error()
     }
 }
 
-fn decode(context: HeifContext, mime_type: &str) -> Result<Frame, ProcessError> {
-    let handle = context.primary_image_handle().expected_error()?;
-
+fn decode(handle: &ImageHandle, mime_type: &str, prefer_cicp: bool) -> Result<Frame, ProcessError> {
     let rgb_chroma = if handle.luma_bits_per_pixel() > 8 {
         if handle.has_alpha_channel() {
             #[cfg(target_endian = "little")]
@@ -93,8 +134,10 @@ fn decode(context: HeifContext, mime_type: &str) -> Result<Frame, ProcessError>
         RgbChroma::Rgb
     };
 
+    // By default, libheif applies the transformations stored in the file,
+    // like the `clap` clean aperture crop, `irot`, and `imir`
     let libheif = LibHeif::new();
-    let image_result = libheif.decode(&handle, ColorSpace::Rgb(rgb_chroma), None);
+    let image_result = libheif.decode(handle, ColorSpace::Rgb(rgb_chroma), None);
 
     let mut image = match image_result {
         Err(err) if matches!(err.sub_code, libheif_rs::HeifErrorSubCode::UnsupportedCodec) => {
@@ -103,13 +146,16 @@ fn decode(context: HeifContext, mime_type: &str) -> Result<Frame, ProcessError>
         image => image.expected_error()?,
     };
 
-    let icc_profile = get_icc_profile(image.color_profile_raw())
-        .or_else(|| get_icc_profile(handle.color_profile_raw()));
+    let icc_profile = get_icc_profile(handle, &image);
 
-    let cicp = if icc_profile.is_none() {
-        get_cicp(image.color_profile_nclx()).or_else(|| get_cicp(handle.color_profile_nclx()))
+    // Decoded images always carry NCLX values, so only use the ones actually
+    // stored in the file next to an ICC profile
+    let (cicp, cicp_fallback) = if icc_profile.is_none() {
+        (get_cicp(handle, &image), None)
+    } else if prefer_cicp {
+        (nclx_cicp(handle.color_profile_nclx()), None)
     } else {
-        None
+        (None, nclx_cicp(handle.color_profile_nclx()))
     };
 
     let plane = image.planes_mut().interleaved.expected_error()?;
@@ -161,6 +207,7 @@ fn decode(context: HeifContext, mime_type: &str) -> Result<Frame, ProcessError>
         .transpose()
         .expected_error()?;
     frame.details.color_cicp = cicp.map(|x| x.to_bytes());
+    frame.details.color_cicp_fallback = cicp_fallback.map(|x| x.to_bytes());
     if plane.bits_per_pixel > 8 {
         frame.details.info_bit_depth = Some(plane.bits_per_pixel);
     }
@@ -169,6 +216,118 @@ fn decode(context: HeifContext, mime_type: &str) -> Result<Frame, ProcessError>
     Ok(frame)
 }
 
+/// Decode the alpha channel as grayscale image
+fn decode_alpha(handle: &ImageHandle, mime_type: &str) -> Result<Frame, ProcessError> {
+    // The alpha channel is usually stored as an auxiliary image. The
+    // transformations of the main image, like the `clap` crop, are not
+    // necessarily applied to it as well. In that case, the alpha channel is
+    // taken from the transformed main image instead.
+    if let Some(alpha_handle) = handle
+        .auxiliary_images(None)
+        .into_iter()
+        .find(|x| x.auxiliary_type().is_ok_and(|x| is_alpha_type(&x)))
+        .filter(|x| x.width() == handle.width() && x.height() == handle.height())
+    {
+        return decode_alpha_auxiliary(&alpha_handle, mime_type);
+    }
+
+    if !handle.has_alpha_channel() {
+        return Err(ProcessError::expected(&"Image has no alpha channel"));
+    }
+
+    // Extract alpha from the decoded image if it's not available separately
+    let frame = decode(handle, mime_type, false)?;
+    alpha_from_interleaved(&frame)
+}
+
+/// Grayscale image of the alpha channel of an RGBA frame
+fn alpha_from_interleaved(frame: &Frame) -> Result<Frame, ProcessError> {
+    let img_buf = frame.as_img_buf().expected_error()?;
+
+    let (memory_format, channel_size) = match frame.memory_format {
+        MemoryFormat::R8g8b8a8 | MemoryFormat::R8g8b8a8Premultiplied => (MemoryFormat::G8, 1),
+        MemoryFormat::R16g16b16a16 | MemoryFormat::R16g16b16a16Premultiplied => {
+            (MemoryFormat::G16, 2)
+        }
+        _ => return Err(ProcessError::expected(&"Unsupported alpha channel format")),
+    };
+
+    let pixel_size = frame.memory_format.n_bytes().usize();
+    let width = frame.width.try_usize()?;
+    let stride = frame.stride.try_usize()?;
+    // Alpha is the last of four channels
+    let alpha_offset = channel_size * 3;
+
+    let mut alpha = Vec::with_capacity(width.smul(channel_size)?.smul(frame.height.try_usize()?)?);
+    for row in img_buf.chunks(stride).take(frame.height.try_usize()?) {
+        for pixel in row.chunks_exact(pixel_size).take(width) {
+            alpha.extend_from_slice(&pixel[alpha_offset..]);
+        }
+    }
+
+    let texture = BinaryData::from_data(alpha).expected_error()?;
+    let mut alpha_frame = Frame::new(frame.width, frame.height, memory_format, texture)?;
+    alpha_frame.details.info_bit_depth = frame.details.info_bit_depth;
+    alpha_frame.details.info_grayscale = Some(true);
+
+    Ok(alpha_frame)
+}
+
+fn decode_alpha_auxiliary(handle: &ImageHandle, mime_type: &str) -> Result<Frame, ProcessError> {
+    let libheif = LibHeif::new();
+    let image_result = libheif.decode(handle, ColorSpace::Monochrome, None);
+
+    let mut image = match image_result {
+        Err(err) if matches!(err.sub_code, libheif_rs::HeifErrorSubCode::UnsupportedCodec) => {
+            return Err(ProcessError::UnsupportedImageFormat(mime_type.to_string()));
+        }
+        image => image.expected_error()?,
+    };
+
+    let plane = image.planes_mut().y.expected_error()?;
+
+    let memory_format = if plane.storage_bits_per_pixel > 8 {
+        let Ok(transmuted) = safe_transmute::transmute_many_pedantic_mut::<u16>(plane.data) else {
+            return Err(ProcessError::expected(
+                &"Could not transform alpha (16bit) data to u16",
+            ));
+        };
+
+        // Scale to 16bit like the color channels
+        for pixel in transmuted.iter_mut() {
+            *pixel <<= 16 - plane.bits_per_pixel;
+        }
+
+        MemoryFormat::G16
+    } else {
+        MemoryFormat::G8
+    };
+
+    let mut memory = SharedMemory::new(plane.stride.try_u64()?.smul(u64::from(plane.height))?)
+        .expected_error()?;
+    Cursor::new(plane.data)
+        .read_exact(&mut memory)
+        .expected_error()?;
+    let texture = memory.into_binary_data();
+
+    let mut frame = Frame::new(plane.width, plane.height, memory_format, texture)?;
+    frame.stride = plane.stride.try_u32()?;
+    if plane.bits_per_pixel > 8 {
+        frame.details.info_bit_depth = Some(plane.bits_per_pixel);
+    }
+    frame.details.info_grayscale = Some(true);
+
+    Ok(frame)
+}
+
+fn is_alpha_type(auxiliary_type: &str) -> bool {
+    [
+        "urn:mpeg:mpegB:cicp:systems:auxiliary:alpha",
+        "urn:mpeg:hevc:2015:auxid:1",
+    ]
+    .contains(&auxiliary_type)
+}
+
 fn exif(handle: &libheif_rs::ImageHandle) -> Option<Vec<u8>> {
     let mut meta_ids = vec![0];
     handle.metadata_block_ids(&mut meta_ids, b"Exif");
@@ -197,7 +356,16 @@ fn exif(handle: &libheif_rs::ImageHandle) -> Option<Vec<u8>> {
     None
 }
 
-fn get_cicp(profile: Option<ColorProfileNCLX>) -> Option<Cicp> {
+/// CICP of the item
+///
+/// The item's own `colr` property takes precedence over the color information
+/// of the decoded image. The latter is, for example, set from the coded
+/// bitstream.
+fn get_cicp(handle: &ImageHandle, image: &libheif_rs::Image) -> Option<Cicp> {
+    nclx_cicp(handle.color_profile_nclx()).or_else(|| nclx_cicp(image.color_profile_nclx()))
+}
+
+fn nclx_cicp(profile: Option<ColorProfileNCLX>) -> Option<Cicp> {
     if let Some(nclx) = profile {
         if nclx.profile_type() == libheif_rs::color_profile_types::NCLX {
             Cicp::from_bytes(&[
@@ -216,7 +384,16 @@ fn get_cicp(profile: Option<ColorProfileNCLX>) -> Option<Cicp> {
     }
 }
 
-fn get_icc_profile(profile: Option<ColorProfileRaw>) -> Option<Vec<u8>> {
+/// ICC profile of the item
+///
+/// Each item can have a different profile. The profile of the decoded image
+/// is only used if the item doesn't have one.
+fn get_icc_profile(handle: &ImageHandle, image: &libheif_rs::Image) -> Option<Vec<u8>> {
+    raw_icc_profile(handle.color_profile_raw())
+        .or_else(|| raw_icc_profile(image.color_profile_raw()))
+}
+
+fn raw_icc_profile(profile: Option<ColorProfileRaw>) -> Option<Vec<u8>> {
     if let Some(profile) = profile {
         if [
             libheif_rs::color_profile_types::R_ICC,
@@ -232,3 +409,174 @@ fn get_icc_profile(profile: Option<ColorProfileRaw>) -> Option<Vec<u8>> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use libheif_rs::{Channel, CompressionFormat, EncoderQuality, Image};
+
+    use super::*;
+
+    /// Image with the alpha channel set to `alpha`, if any
+    fn image(width: u32, height: u32, alpha: Option<u8>) -> Image {
+        let (chroma, n_channels) = match alpha {
+            Some(_) => (RgbChroma::Rgba, 4),
+            None => (RgbChroma::Rgb, 3),
+        };
+
+        let mut image = Image::new(width, height, ColorSpace::Rgb(chroma)).unwrap();
+        image
+            .create_plane(Channel::Interleaved, width, height, 8)
+            .unwrap();
+
+        if let Some(alpha) = alpha {
+            let plane = image.planes_mut().interleaved.unwrap();
+            for row in plane.data.chunks_mut(plane.stride) {
+                for pixel in row.chunks_exact_mut(n_channels).take(width as usize) {
+                    pixel[3] = alpha;
+                }
+            }
+        }
+
+        image
+    }
+
+    /// HEIF file with a top-level image for each of the images
+    fn heif(images: &[Image]) -> Vec<u8> {
+        let libheif = LibHeif::new();
+        let mut context = HeifContext::new().unwrap();
+        let mut encoder = libheif.encoder_for_format(CompressionFormat::Av1).unwrap();
+        encoder.set_quality(EncoderQuality::LossLess).unwrap();
+
+        for image in images {
+            context.encode_image(image, &mut encoder, None).unwrap();
+        }
+
+        context.write_to_bytes().unwrap()
+    }
+
+    fn init(data: &[u8]) -> (ImgDecoder, ImageDetails) {
+        let (mut send, recv) = UnixStream::pair().unwrap();
+        send.write_all(data).unwrap();
+        drop(send);
+
+        ImgDecoder::init(recv, String::from("image/avif"), Default::default()).unwrap()
+    }
+
+    fn pixels(frame: &Frame) -> Vec<Vec<u8>> {
+        let width = frame.width as usize * frame.memory_format.n_bytes().usize();
+        frame
+            .texture
+            .get_full()
+            .unwrap()
+            .chunks(frame.stride as usize)
+            .take(frame.height as usize)
+            .map(|x| x[..width].to_vec())
+            .collect()
+    }
+
+    fn sub_image(decoder: &mut ImgDecoder, index: Option<u32>) -> Frame {
+        let mut frame_request = FrameRequest::default();
+        frame_request.sub_image = index;
+        decoder.frame(frame_request).unwrap()
+    }
+
+    #[test]
+    fn sub_images() {
+        let (mut decoder, details) = init(&heif(&[
+            image(4, 2, None),
+            image(2, 6, None),
+            image(8, 4, None),
+        ]));
+
+        let sub_images = details.sub_images.unwrap();
+        assert_eq!(sub_images.len(), 3);
+        assert_eq!((sub_images[1].width, sub_images[1].height), (2, 6));
+
+        // Several frames can be requested from the same image
+        let frame = sub_image(&mut decoder, Some(0));
+        assert_eq!((frame.width, frame.height), (4, 2));
+        let frame = sub_image(&mut decoder, Some(1));
+        assert_eq!((frame.width, frame.height), (2, 6));
+        let frame = sub_image(&mut decoder, None);
+        assert_eq!((frame.width, frame.height), (4, 2));
+        let frame = sub_image(&mut decoder, Some(2));
+        assert_eq!((frame.width, frame.height), (8, 4));
+
+        let mut frame_request = FrameRequest::default();
+        frame_request.sub_image = Some(3);
+        assert!(decoder.frame(frame_request).is_err());
+    }
+
+    #[test]
+    fn alpha_auxiliary() {
+        let (mut decoder, details) = init(&heif(&[image(3, 2, Some(100))]));
+        assert_eq!(details.info_alpha_channel, Some(true));
+
+        // The alpha channel can be requested after the primary frame
+        let frame = sub_image(&mut decoder, None);
+        assert_eq!(frame.memory_format, MemoryFormat::R8g8b8a8);
+
+        let mut frame_request = FrameRequest::default();
+        frame_request.auxiliary_image = Some(AuxiliaryImage::Alpha);
+        let alpha = decoder.frame(frame_request).unwrap();
+        assert_eq!((alpha.width, alpha.height), (3, 2));
+        assert_eq!(alpha.memory_format, MemoryFormat::G8);
+        assert_eq!(alpha.details.info_grayscale, Some(true));
+        assert_eq!(pixels(&alpha), [[100; 3], [100; 3]]);
+    }
+
+    #[test]
+    fn alpha_no_alpha_channel() {
+        let (mut decoder, _) = init(&heif(&[image(3, 2, None)]));
+
+        let mut frame_request = FrameRequest::default();
+        frame_request.auxiliary_image = Some(AuxiliaryImage::Alpha);
+        assert!(decoder.frame(frame_request).is_err());
+    }
+
+    fn rgba_frame(memory_format: MemoryFormat, rows: &[&[u8]], stride: u32) -> Frame {
+        let mut data = Vec::new();
+        for row in rows {
+            data.extend_from_slice(row);
+            data.resize(data.len() + stride as usize - row.len(), 0xff);
+        }
+
+        let texture = BinaryData::from_data(data).unwrap();
+        let pixel_size = memory_format.n_bytes().u32();
+        let width = rows[0].len() as u32 / pixel_size;
+        let mut frame = Frame::new(width, rows.len() as u32, memory_format, texture).unwrap();
+        frame.stride = stride;
+        frame
+    }
+
+    #[test]
+    fn alpha_interleaved() {
+        // Rows are padded to test the stride handling
+        let frame = rgba_frame(
+            MemoryFormat::R8g8b8a8,
+            &[&[1, 2, 3, 10, 4, 5, 6, 20], &[7, 8, 9, 30, 0, 0, 0, 40]],
+            12,
+        );
+        let alpha = alpha_from_interleaved(&frame).unwrap();
+        assert_eq!((alpha.width, alpha.height), (2, 2));
+        assert_eq!(alpha.memory_format, MemoryFormat::G8);
+        assert_eq!(alpha.details.info_grayscale, Some(true));
+        assert_eq!(pixels(&alpha), [[10, 20], [30, 40]]);
+
+        let mut frame = rgba_frame(
+            MemoryFormat::R16g16b16a16Premultiplied,
+            &[&[1, 2, 3, 4, 5, 6, 0xab, 0xcd]],
+            10,
+        );
+        frame.details.info_bit_depth = Some(10);
+        let alpha = alpha_from_interleaved(&frame).unwrap();
+        assert_eq!(alpha.memory_format, MemoryFormat::G16);
+        assert_eq!(alpha.details.info_bit_depth, Some(10));
+        assert_eq!(pixels(&alpha), [[0xab, 0xcd]]);
+
+        let frame = rgba_frame(MemoryFormat::R8g8b8, &[&[1, 2, 3]], 3);
+        assert!(alpha_from_interleaved(&frame).is_err());
+    }
+}
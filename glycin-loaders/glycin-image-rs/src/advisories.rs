@@ -0,0 +1,96 @@
+//! Risky or deprecated features of images
+//!
+//! The features don't prevent loading the image. They are only reported if
+//! requested via [`InitializationDetails::collect_advisories`].
+//!
+//! [`InitializationDetails::collect_advisories`]: glycin_utils::InitializationDetails::collect_advisories
+
+use std::io::Cursor;
+
+use tiff::decoder::Decoder;
+use tiff::tags::Tag;
+
+/// Maximum number of images in a TIFF that are checked
+const MAX_TIFF_IMAGES: usize = 1024;
+
+/// Compression methods that are widely supported
+///
+/// None, LZW, JPEG, Deflate, PackBits, and the old code for Deflate
+const COMMON_TIFF_COMPRESSION: [u16; 6] = [1, 5, 7, 8, 32773, 32946];
+
+/// Old-style JPEG compression that has been replaced in TIFF 6.0
+const OLD_JPEG_COMPRESSION: u16 = 6;
+
+/// Unusual compression methods in any of the images of a TIFF
+pub fn tiff(data: &[u8]) -> Vec<String> {
+    let mut advisories = Vec::new();
+
+    let Ok(mut decoder) = Decoder::new(Cursor::new(data)) else {
+        return advisories;
+    };
+
+    for _ in 0..MAX_TIFF_IMAGES {
+        if let Ok(Some(compression)) = decoder.find_tag_unsigned::<u16>(Tag::Compression) {
+            let advisory = if compression == OLD_JPEG_COMPRESSION {
+                Some(String::from(
+                    "TIFF uses deprecated old-style JPEG compression",
+                ))
+            } else if !COMMON_TIFF_COMPRESSION.contains(&compression) {
+                Some(format!(
+                    "TIFF uses unusual compression method {compression}"
+                ))
+            } else {
+                None
+            };
+
+            if let Some(advisory) = advisory.filter(|x| !advisories.contains(x)) {
+                advisories.push(advisory);
+            }
+        }
+
+        if !decoder.more_images() || decoder.next_image().is_err() {
+            break;
+        }
+    }
+
+    advisories
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// TIFF with two images, with `compression` patched into the second one
+    fn tiff_with_compression(compression: u16) -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        let mut encoder = tiff::encoder::TiffEncoder::new(&mut data).unwrap();
+        for _ in 0..2 {
+            encoder
+                .write_image::<tiff::encoder::colortype::Gray8>(1, 1, &[0])
+                .unwrap();
+        }
+        let mut data = data.into_inner();
+
+        // Compression tag of type SHORT with value "none"
+        let entry = [0x03, 0x01, 0x03, 0x00, 0x01, 0, 0, 0, 0x01, 0x00];
+        let pos = data.windows(entry.len()).rposition(|x| x == entry).unwrap();
+        data[pos + 8..pos + 10].copy_from_slice(&compression.to_le_bytes());
+
+        data
+    }
+
+    #[test]
+    fn tiff_compression() {
+        assert!(tiff(&tiff_with_compression(1)).is_empty());
+        assert!(tiff(&tiff_with_compression(32773)).is_empty());
+
+        assert_eq!(
+            tiff(&tiff_with_compression(6)),
+            ["TIFF uses deprecated old-style JPEG compression"]
+        );
+        assert_eq!(
+            tiff(&tiff_with_compression(34676)),
+            ["TIFF uses unusual compression method 34676"]
+        );
+    }
+}
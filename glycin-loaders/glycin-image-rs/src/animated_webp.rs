@@ -0,0 +1,412 @@
+//! Compositing of animated WebPs
+//!
+//! The frames of an animated WebP can mix lossy and lossless compression and
+//! don't have to cover the complete canvas. Each frame is decoded as a still
+//! image and composited onto the canvas according to its blend and dispose
+//! method.
+
+use std::ops::Range;
+
+use glycin_utils::{FrameBlend, FrameDispose};
+use image::{ImageError, ImageResult};
+
+use crate::frame_methods::Methods;
+
+/// Parsed animated WebP with the location of all frames
+pub struct AnimatedWebP {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    /// Background color from the ANIM chunk as RGBA
+    background_color: Option<[u8; 4]>,
+    frames: Vec<FrameInfo>,
+}
+
+#[derive(Debug, Clone)]
+struct FrameInfo {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    /// Duration in milliseconds
+    duration: u32,
+    /// Alpha-blend onto the canvas instead of replacing the area
+    blend: bool,
+    /// Clear the area to the background after the frame has been shown
+    dispose: bool,
+    /// Range of the chunks with the frame's bitstream
+    chunks: Range<usize>,
+}
+
+impl AnimatedWebP {
+    pub fn new(data: Vec<u8>) -> ImageResult<Self> {
+        if data.get(0..4) != Some(b"RIFF") || data.get(8..12) != Some(b"WEBP") {
+            return Err(error("Not a WebP file"));
+        }
+
+        let mut size = None;
+        let mut background_color = None;
+        let mut frames = Vec::new();
+
+        for (fourcc, chunk) in chunks(&data, 12..data.len())? {
+            let payload = &data[chunk.clone()];
+            match &fourcc {
+                b"VP8X" => {
+                    size = Some((
+                        read_u24(payload, 4)?.saturating_add(1),
+                        read_u24(payload, 7)?.saturating_add(1),
+                    ));
+                }
+                b"ANIM" => {
+                    // Stored as BGRA
+                    if let [b, g, r, a, ..] = *payload {
+                        background_color = Some([r, g, b, a]);
+                    }
+                }
+                b"ANMF" => {
+                    let flags = *payload
+                        .get(15)
+                        .ok_or_else(|| error("ANMF chunk too short"))?;
+                    frames.push(FrameInfo {
+                        x: read_u24(payload, 0)? * 2,
+                        y: read_u24(payload, 3)? * 2,
+                        width: read_u24(payload, 6)? + 1,
+                        height: read_u24(payload, 9)? + 1,
+                        duration: read_u24(payload, 12)?,
+                        blend: flags & 0b10 == 0,
+                        dispose: flags & 0b01 != 0,
+                        chunks: chunk.start + 16..chunk.end,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        let (width, height) = size.ok_or_else(|| error("Missing VP8X chunk"))?;
+
+        for frame in &frames {
+            if frame.x + frame.width > width || frame.y + frame.height > height {
+                return Err(error("Frame outside of canvas"));
+            }
+        }
+
+        Ok(Self {
+            data,
+            width,
+            height,
+            background_color,
+            frames,
+        })
+    }
+
+    /// Background color of the canvas as specified by the file
+    ///
+    /// The frames are composited onto a transparent canvas regardless.
+    pub fn background_color(&self) -> Option<[u8; 4]> {
+        self.background_color
+    }
+
+    /// Blend and dispose methods of all frames
+    pub fn frame_methods(&self) -> Vec<Methods> {
+        self.frames
+            .iter()
+            .map(|frame| {
+                let blend = if frame.blend {
+                    FrameBlend::Over
+                } else {
+                    FrameBlend::Source
+                };
+                let dispose = if frame.dispose {
+                    FrameDispose::Background
+                } else {
+                    FrameDispose::None
+                };
+                (blend, dispose)
+            })
+            .collect()
+    }
+
+    /// Iterator over the composited frames with the size of the canvas
+    pub fn into_frames(self) -> ImageResult<image::Frames<'static>> {
+        let canvas_size = (self.width as usize)
+            .checked_mul(self.height as usize)
+            .and_then(|x| x.checked_mul(4))
+            .ok_or_else(|| error("Canvas too large"))?;
+
+        Ok(image::Frames::new(Box::new(Frames {
+            canvas: vec![0; canvas_size],
+            webp: self,
+            next_frame: 0,
+            dispose: None,
+        })))
+    }
+
+    /// Decode a frame into RGBA data
+    ///
+    /// The frame's chunks are wrapped into a still WebP.
+    fn decode(&self, frame: &FrameInfo) -> ImageResult<Vec<u8>> {
+        let frame_chunks = &self.data[frame.chunks.clone()];
+        let has_alpha_chunk = chunks(&self.data, frame.chunks.clone())?
+            .iter()
+            .any(|(fourcc, _)| fourcc == b"ALPH");
+
+        let mut header = Vec::new();
+        // Separate alpha data are only allowed in extended files
+        if has_alpha_chunk {
+            header.extend_from_slice(b"VP8X");
+            header.extend_from_slice(&10_u32.to_le_bytes());
+            header.extend_from_slice(&[0b1_0000, 0, 0, 0]);
+            header.extend_from_slice(&(frame.width - 1).to_le_bytes()[..3]);
+            header.extend_from_slice(&(frame.height - 1).to_le_bytes()[..3]);
+        }
+
+        let riff_size = u32::try_from(4 + header.len() + frame_chunks.len())
+            .map_err(|_| error("Frame too large"))?;
+
+        let mut still = Vec::with_capacity(8 + riff_size as usize);
+        still.extend_from_slice(b"RIFF");
+        still.extend_from_slice(&riff_size.to_le_bytes());
+        still.extend_from_slice(b"WEBP");
+        still.extend_from_slice(&header);
+        still.extend_from_slice(frame_chunks);
+
+        let image = image::load_from_memory_with_format(&still, image::ImageFormat::WebP)?;
+
+        if image.width() != frame.width || image.height() != frame.height {
+            return Err(error("Frame size inconsistent with ANMF chunk"));
+        }
+
+        Ok(image.into_rgba8().into_raw())
+    }
+}
+
+struct Frames {
+    webp: AnimatedWebP,
+    canvas: Vec<u8>,
+    next_frame: usize,
+    /// Area of the previous frame if it has to be disposed
+    dispose: Option<FrameInfo>,
+}
+
+impl Frames {
+    fn composite(&mut self, frame: &FrameInfo) -> ImageResult<image::Frame> {
+        let canvas_width = self.webp.width as usize;
+
+        // Glycin uses a transparent background instead of the suggested one
+        if let Some(previous) = self.dispose.take() {
+            for y in previous.y..previous.y + previous.height {
+                let start = (y as usize * canvas_width + previous.x as usize) * 4;
+                let end = start + previous.width as usize * 4;
+                self.canvas[start..end].fill(0);
+            }
+        }
+
+        let data = self.webp.decode(frame)?;
+
+        for (y, row) in data.chunks_exact(frame.width as usize * 4).enumerate() {
+            let start = ((frame.y as usize + y) * canvas_width + frame.x as usize) * 4;
+            let output = &mut self.canvas[start..start + row.len()];
+
+            if frame.blend {
+                for (dst, src) in output.chunks_exact_mut(4).zip(row.chunks_exact(4)) {
+                    blend(src, dst);
+                }
+            } else {
+                output.copy_from_slice(row);
+            }
+        }
+
+        if frame.dispose {
+            self.dispose = Some(frame.clone());
+        }
+
+        let buffer =
+            image::RgbaImage::from_raw(self.webp.width, self.webp.height, self.canvas.clone())
+                .ok_or_else(|| error("Canvas size inconsistent"))?;
+
+        Ok(image::Frame::from_parts(
+            buffer,
+            0,
+            0,
+            image::Delay::from_numer_denom_ms(frame.duration, 1),
+        ))
+    }
+}
+
+impl Iterator for Frames {
+    type Item = ImageResult<image::Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let frame = self.webp.frames.get(self.next_frame)?.clone();
+        self.next_frame += 1;
+
+        Some(self.composite(&frame))
+    }
+}
+
+/// Alpha-blend non-premultiplied RGBA `src` over `dst`
+fn blend(src: &[u8], dst: &mut [u8]) {
+    let src_alpha = u32::from(src[3]);
+    let dst_alpha = u32::from(dst[3]) * (255 - src_alpha);
+    // Alpha scaled by 255
+    let alpha = src_alpha * 255 + dst_alpha;
+
+    if alpha == 0 {
+        dst.fill(0);
+        return;
+    }
+
+    for (dst, src) in dst[..3].iter_mut().zip(&src[..3]) {
+        let color = u32::from(*src) * src_alpha * 255 + u32::from(*dst) * dst_alpha;
+        *dst = ((color + alpha / 2) / alpha) as u8;
+    }
+    dst[3] = ((alpha + 127) / 255) as u8;
+}
+
+/// FourCC and payload range of the chunks within `range`
+fn chunks(data: &[u8], range: Range<usize>) -> ImageResult<Vec<([u8; 4], Range<usize>)>> {
+    let mut chunks = Vec::new();
+    let mut pos = range.start;
+
+    // Ignore trailing bytes that can't form a chunk header
+    while pos + 8 <= range.end {
+        let fourcc = data[pos..pos + 4].try_into().unwrap();
+        let size = u32::from_le_bytes(data[pos + 4..pos + 8].try_into().unwrap()) as usize;
+
+        let start = pos + 8;
+        let end = start
+            .checked_add(size)
+            .filter(|end| *end <= range.end)
+            .ok_or_else(|| error("Chunk exceeds file"))?;
+        chunks.push((fourcc, start..end));
+
+        // Chunks are padded to an even size
+        pos = end + size % 2;
+    }
+
+    Ok(chunks)
+}
+
+fn read_u24(data: &[u8], pos: usize) -> ImageResult<u32> {
+    let bytes = data
+        .get(pos..pos + 3)
+        .ok_or_else(|| error("Chunk too short"))?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]))
+}
+
+fn error(msg: &'static str) -> ImageError {
+    ImageError::Decoding(image::error::DecodingError::new(
+        image::ImageFormat::WebP.into(),
+        msg,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// VP8L chunk of a lossless WebP with a single color
+    fn vp8l_chunk(width: u32, height: u32, color: [u8; 4]) -> Vec<u8> {
+        let data = color.repeat((width * height) as usize);
+        let mut webp = Vec::new();
+        image::codecs::webp::WebPEncoder::new_lossless(&mut webp)
+            .encode(&data, width, height, image::ExtendedColorType::Rgba8)
+            .unwrap();
+
+        let (_, chunk) = chunks(&webp, 12..webp.len())
+            .unwrap()
+            .into_iter()
+            .find(|(fourcc, _)| fourcc == b"VP8L")
+            .unwrap();
+
+        let mut vp8l = webp[chunk.start - 8..chunk.end].to_vec();
+        if vp8l.len() % 2 == 1 {
+            vp8l.push(0);
+        }
+        vp8l
+    }
+
+    fn chunk(fourcc: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut chunk = fourcc.to_vec();
+        chunk.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(payload);
+        chunk
+    }
+
+    fn anmf(x: u32, y: u32, width: u32, height: u32, flags: u8, color: [u8; 4]) -> Vec<u8> {
+        let mut payload = Vec::new();
+        for value in [x / 2, y / 2, width - 1, height - 1, 100] {
+            payload.extend_from_slice(&value.to_le_bytes()[..3]);
+        }
+        payload.push(flags);
+        payload.extend(vp8l_chunk(width, height, color));
+        chunk(b"ANMF", &payload)
+    }
+
+    #[test]
+    fn partial_frames() {
+        const RED: [u8; 4] = [255, 0, 0, 255];
+        const BLUE: [u8; 4] = [0, 0, 255, 128];
+        const GREEN: [u8; 4] = [0, 255, 0, 255];
+
+        let mut vp8x = vec![0b1_0010, 0, 0, 0];
+        vp8x.extend_from_slice(&3_u32.to_le_bytes()[..3]);
+        vp8x.extend_from_slice(&3_u32.to_le_bytes()[..3]);
+
+        let body = [
+            b"WEBP".to_vec(),
+            chunk(b"VP8X", &vp8x),
+            chunk(b"ANIM", &[255, 0, 0, 255, 0, 0]),
+            // Full frame that is disposed
+            anmf(0, 0, 4, 4, 0b11, RED),
+            // Blended partial frame
+            anmf(2, 2, 2, 2, 0b00, BLUE),
+            // Partial frame that replaces the area
+            anmf(0, 0, 2, 2, 0b10, GREEN),
+        ]
+        .concat();
+        let webp = [
+            b"RIFF".as_slice(),
+            &(body.len() as u32).to_le_bytes(),
+            &body,
+        ]
+        .concat();
+
+        let webp = AnimatedWebP::new(webp).unwrap();
+        assert_eq!(webp.background_color(), Some([0, 0, 255, 255]));
+        assert_eq!(
+            webp.frame_methods(),
+            [
+                (FrameBlend::Source, FrameDispose::Background),
+                (FrameBlend::Over, FrameDispose::None),
+                (FrameBlend::Source, FrameDispose::None),
+            ]
+        );
+
+        let frames = webp.into_frames().unwrap().collect_frames().unwrap();
+        assert_eq!(frames.len(), 3);
+
+        let pixel = |n: usize, x, y| frames[n].buffer().get_pixel(x, y).0;
+
+        assert_eq!(pixel(0, 0, 0), RED);
+        assert_eq!(pixel(0, 3, 3), RED);
+
+        assert_eq!(pixel(1, 0, 0), [0; 4]);
+        assert_eq!(pixel(1, 3, 3), BLUE);
+
+        assert_eq!(pixel(2, 0, 0), GREEN);
+        assert_eq!(pixel(2, 2, 0), [0; 4]);
+        assert_eq!(pixel(2, 3, 3), BLUE);
+    }
+
+    #[test]
+    fn blending() {
+        let mut dst = [0, 0, 255, 255];
+        blend(&[255, 0, 0, 128], &mut dst);
+        assert_eq!(dst, [128, 0, 127, 255]);
+
+        let mut dst = [0, 0, 0, 0];
+        blend(&[10, 20, 30, 40], &mut dst);
+        assert_eq!(dst, [10, 20, 30, 40]);
+    }
+}
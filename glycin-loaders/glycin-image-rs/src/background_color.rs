@@ -0,0 +1,76 @@
+//! Background color of animations
+//!
+//! Frames are composited onto a transparent canvas, like browsers do. The
+//! background color specified by the file is only reported, such that
+//! renderers can use it to display the animation as authored.
+
+use crate::animated_webp::AnimatedWebP;
+
+/// Background color as non-premultiplied RGBA
+///
+/// Returns `None` if the format has no background color or the file doesn't
+/// specify one.
+pub fn background_color(mime_type: &str, data: &[u8]) -> Option<[u8; 4]> {
+    match mime_type {
+        "image/gif" => gif(data),
+        "image/webp" => AnimatedWebP::new(data.to_vec()).ok()?.background_color(),
+        _ => None,
+    }
+}
+
+fn gif(data: &[u8]) -> Option<[u8; 4]> {
+    if data.get(..3)? != b"GIF" {
+        return None;
+    }
+
+    // The background color is an index into the global color table
+    let packed = *data.get(10)?;
+    if packed & 0x80 == 0 {
+        return None;
+    }
+
+    let index = usize::from(*data.get(11)?);
+    if index >= 2 << (packed & 0b111) {
+        return None;
+    }
+
+    // Color table follows the header and logical screen descriptor
+    let pos = 13 + index * 3;
+    let [r, g, b] = data.get(pos..pos + 3)?.try_into().ok()?;
+
+    Some([r, g, b, 255])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gif_header(packed: u8, background_index: u8) -> Vec<u8> {
+        [
+            b"GIF89a".as_slice(),
+            // Logical screen descriptor
+            &[1, 0, 1, 0, packed, background_index, 0],
+            // Global color table with two colors
+            &[10, 20, 30, 40, 50, 60],
+            // Trailer
+            &[0x3B],
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn gif_test() {
+        assert_eq!(
+            background_color("image/gif", &gif_header(0x80, 1)),
+            Some([40, 50, 60, 255])
+        );
+        assert_eq!(
+            background_color("image/gif", &gif_header(0x80, 0)),
+            Some([10, 20, 30, 255])
+        );
+        // No global color table
+        assert_eq!(background_color("image/gif", &gif_header(0, 1)), None);
+        // Index outside of the color table
+        assert_eq!(background_color("image/gif", &gif_header(0x80, 2)), None);
+    }
+}
@@ -0,0 +1,232 @@
+//! Conversion of CMYK TIFFs via their ICC profile
+//!
+//! image-rs converts CMYK to RGB without considering the ICC profile, which
+//! results in wrong colors. For TIFFs with a CMYK profile, the samples are
+//! converted to sRGB with the profile instead.
+
+use std::io::Cursor;
+
+use glycin_utils::safe_math::*;
+use glycin_utils::*;
+use tiff::decoder::{Decoder, DecodingResult, Limits};
+use tiff::tags::{PlanarConfiguration, Tag};
+
+/// Number of pixels converted at once
+const CHUNK_SIZE: usize = 1 << 16;
+
+pub struct CmykTiff {
+    data: Vec<u8>,
+    details: FrameDetails,
+    decoded: bool,
+}
+
+impl CmykTiff {
+    /// Returns `true` for CMYK TIFFs with a CMYK ICC profile
+    pub fn is_applicable(data: &[u8]) -> bool {
+        let Ok(mut decoder) = Decoder::new(Cursor::new(data)) else {
+            return false;
+        };
+
+        let is_cmyk = matches!(decoder.colortype(), Ok(tiff::ColorType::CMYK(8 | 16)));
+        let is_chunky = decoder
+            .find_tag_unsigned::<u16>(Tag::PlanarConfiguration)
+            .ok()
+            .flatten()
+            .and_then(PlanarConfiguration::from_u16)
+            .is_none_or(|x| x == PlanarConfiguration::Chunky);
+        let has_cmyk_profile = icc_profile(&mut decoder).is_some_and(|x| {
+            lcms2::Profile::new_icc(&x)
+                .is_ok_and(|x| x.color_space() == lcms2::ColorSpaceSignature::CmykData)
+        });
+
+        is_cmyk && is_chunky && has_cmyk_profile
+    }
+
+    pub fn new(data: Vec<u8>, mut details: FrameDetails) -> Self {
+        // The profile is already applied when decoding
+        details.color_icc_profile = None;
+        details.info_cmyk = Some(true);
+
+        Self {
+            data,
+            details,
+            decoded: false,
+        }
+    }
+
+    /// Decode the image and convert it to sRGB
+    ///
+    /// The image is only returned once.
+    pub fn frame(&mut self) -> Result<Frame, ProcessError> {
+        if self.decoded {
+            return Err(ProcessError::NoMoreFrames);
+        }
+        self.decoded = true;
+
+        let mut decoder = Decoder::new(Cursor::new(self.data.as_slice()))
+            .expected_error()?
+            .with_limits(Limits::unlimited());
+
+        let (width, height) = decoder.dimensions().expected_error()?;
+        let icc_profile = icc_profile(&mut decoder)
+            .ok_or_else(|| ProcessError::expected(&"TIFF without ICC profile"))?;
+        let cmyk_profile = lcms2::Profile::new_icc(&icc_profile).expected_error()?;
+        let srgb_profile = lcms2::Profile::new_srgb();
+
+        let (samples, pixel_format) = match decoder.read_image().expected_error()? {
+            DecodingResult::U8(samples) => (samples, lcms2::PixelFormat::CMYK_8),
+            DecodingResult::U16(samples) => (
+                samples.iter().flat_map(|x| x.to_ne_bytes()).collect(),
+                lcms2::PixelFormat::CMYK_16,
+            ),
+            _ => {
+                return Err(ProcessError::UnsupportedImageFormat(String::from(
+                    "TIFF CMYK sample type",
+                )))
+            }
+        };
+
+        let transform = lcms2::Transform::<u8, u8>::new(
+            &cmyk_profile,
+            pixel_format,
+            &srgb_profile,
+            lcms2::PixelFormat::RGB_8,
+            lcms2::Intent::Perceptual,
+        )
+        .expected_error()?;
+
+        let n_pixels = width.try_usize()?.smul(height.try_usize()?)?;
+        let pixel_size = pixel_format.bytes_per_pixel();
+        if samples.len() < n_pixels.smul(pixel_size)? {
+            return Err(ProcessError::expected(&"TIFF data too short"));
+        }
+
+        let mut memory = SharedMemory::new(n_pixels.smul(3)?.try_u64()?).expected_error()?;
+        for (src, dst) in samples
+            .chunks(CHUNK_SIZE.smul(pixel_size)?)
+            .zip(memory.chunks_mut(CHUNK_SIZE.smul(3)?))
+        {
+            transform.transform_pixels(src, dst);
+        }
+
+        let mut frame = Frame::new(
+            width,
+            height,
+            MemoryFormat::R8g8b8,
+            memory.into_binary_data(),
+        )?;
+        frame.details = self.details.clone();
+
+        Ok(frame)
+    }
+}
+
+fn icc_profile<R: std::io::Read + std::io::Seek>(decoder: &mut Decoder<R>) -> Option<Vec<u8>> {
+    decoder.get_tag_u8_vec(Tag::IccProfile).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ICC profile for CMYK with the cyan and magenta inks swapped
+    ///
+    /// Unlike a conversion that ignores the profile, cyan ink is displayed as
+    /// magenta.
+    fn swapped_inks_profile() -> Vec<u8> {
+        let transform = lcms2::Transform::<[u8; 3], [u16; 3]>::new(
+            &lcms2::Profile::new_srgb(),
+            lcms2::PixelFormat::RGB_8,
+            &lcms2::Profile::new_xyz(),
+            lcms2::PixelFormat::XYZ_16,
+            lcms2::Intent::RelativeColorimetric,
+        )
+        .unwrap();
+
+        // Lookup table with two grid points per ink, cyan varies slowest
+        let mut lut = b"mft2\0\0\0\0".to_vec();
+        lut.extend([4, 3, 2, 0]);
+        for i in 0..9 {
+            let value: i32 = if i % 4 == 0 { 0x10000 } else { 0 };
+            lut.extend(value.to_be_bytes());
+        }
+        lut.extend([0, 2, 0, 2]);
+        lut.extend([0, 0, 0xFF, 0xFF].repeat(4));
+        for i in 0..16 {
+            let [c, m, y, k] = [3, 2, 1, 0].map(|shift| (i >> shift) & 1 == 1);
+            let channel = |ink: bool| if ink || k { 0 } else { 255 };
+
+            let mut xyz = [[0; 3]];
+            transform.transform_pixels(&[[channel(m), channel(c), channel(y)]], &mut xyz);
+            lut.extend(xyz[0].iter().flat_map(|x| x.to_be_bytes()));
+        }
+        lut.extend([0, 0, 0xFF, 0xFF].repeat(3));
+
+        let mut profile = vec![0; 128];
+        profile[8..12].copy_from_slice(&[2, 0x10, 0, 0]);
+        profile[12..16].copy_from_slice(b"prtr");
+        profile[16..20].copy_from_slice(b"CMYK");
+        profile[20..24].copy_from_slice(b"XYZ ");
+        profile[36..40].copy_from_slice(b"acsp");
+        // D50 illuminant
+        profile[68..80].copy_from_slice(&[0, 0, 0xF6, 0xD6, 0, 1, 0, 0, 0, 0, 0xD3, 0x2D]);
+
+        profile.extend(1_u32.to_be_bytes());
+        profile.extend(b"A2B0");
+        profile.extend(144_u32.to_be_bytes());
+        profile.extend((lut.len() as u32).to_be_bytes());
+        profile.extend(lut);
+
+        let size = (profile.len() as u32).to_be_bytes();
+        profile[0..4].copy_from_slice(&size);
+
+        profile
+    }
+
+    fn cmyk_tiff(pixels: &[[u8; 4]], icc_profile: &[u8]) -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        let mut encoder = tiff::encoder::TiffEncoder::new(&mut data).unwrap();
+        let mut image = encoder
+            .new_image::<tiff::encoder::colortype::CMYK8>(pixels.len() as u32, 1)
+            .unwrap();
+        image
+            .encoder()
+            .write_tag(Tag::IccProfile, icc_profile)
+            .unwrap();
+        image.write_data(pixels.as_flattened()).unwrap();
+
+        data.into_inner()
+    }
+
+    #[test]
+    fn profile_applied() {
+        let data = cmyk_tiff(
+            &[[0, 0, 0, 0], [255, 0, 0, 0], [0, 0, 0, 255]],
+            &swapped_inks_profile(),
+        );
+        assert!(CmykTiff::is_applicable(&data));
+
+        let mut cmyk_tiff = CmykTiff::new(data, FrameDetails::default());
+        let frame = cmyk_tiff.frame().unwrap();
+
+        assert_eq!(frame.memory_format, MemoryFormat::R8g8b8);
+        assert_eq!(frame.details.info_cmyk, Some(true));
+        assert!(frame.details.color_icc_profile.is_none());
+
+        let pixels = frame.texture.get_full().unwrap();
+        let close = |a: &[u8], b: [u8; 3]| a.iter().zip(b).all(|(a, b)| a.abs_diff(b) < 8);
+
+        assert!(close(&pixels[0..3], [255, 255, 255]), "{pixels:?}");
+        // Cyan ink is shown as magenta
+        assert!(close(&pixels[3..6], [255, 0, 255]), "{pixels:?}");
+        assert!(close(&pixels[6..9], [0, 0, 0]), "{pixels:?}");
+
+        assert!(matches!(cmyk_tiff.frame(), Err(ProcessError::NoMoreFrames)));
+    }
+
+    #[test]
+    fn profile_required() {
+        let data = cmyk_tiff(&[[0, 0, 0, 0]], &[]);
+        assert!(!CmykTiff::is_applicable(&data));
+    }
+}
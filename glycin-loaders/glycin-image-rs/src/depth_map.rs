@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+
+use glycin_utils::*;
+
+use crate::motion_photo::xmp_value;
+
+/// Key for the conversion of the depth values, `RangeInverse` or `RangeLinear`
+pub const KEY_DEPTH_FORMAT: &str = "GDepthFormat";
+/// Key for the distance that is stored as the nearest depth value
+pub const KEY_DEPTH_NEAR: &str = "GDepthNear";
+/// Key for the distance that is stored as the farthest depth value
+pub const KEY_DEPTH_FAR: &str = "GDepthFar";
+
+/// Identifier of APP1 segments with extended XMP data
+const XMP_EXTENSION_IDENTIFIER: &[u8] = b"http://ns.adobe.com/xmp/extension/\0";
+/// Length of the GUID, full length, and offset before the extended XMP data
+const XMP_EXTENSION_HEADER_LEN: usize = 40;
+
+/// Depth map embedded in portrait JPEGs
+///
+/// Google's portrait mode stores the depth map as an encoded image in the
+/// `GDepth:Data` property. Since the data are larger than a single APP1
+/// segment, the property is usually part of the extended XMP.
+pub struct DepthMap {
+    data: Vec<u8>,
+    key_value: BTreeMap<String, String>,
+}
+
+impl DepthMap {
+    pub fn new(jpeg: &gufo_jpeg::Jpeg) -> Option<Self> {
+        let xmp = jpeg
+            .xmp_data()
+            .find_map(|x| std::str::from_utf8(x).ok())
+            .unwrap_or_default();
+        let extended_xmp = extended_xmp(jpeg, xmp_value(xmp, "xmpNote:HasExtendedXMP"));
+        let extended_xmp = std::str::from_utf8(&extended_xmp).unwrap_or_default();
+
+        let property = |name| xmp_value(xmp, name).or_else(|| xmp_value(extended_xmp, name));
+
+        let data = base64_decode(property("GDepth:Data")?)?;
+
+        let key_value = [
+            (KEY_DEPTH_FORMAT, "GDepth:Format"),
+            (KEY_DEPTH_NEAR, "GDepth:Near"),
+            (KEY_DEPTH_FAR, "GDepth:Far"),
+        ]
+        .into_iter()
+        .filter_map(|(key, name)| Some((key.to_string(), property(name)?.to_string())))
+        .collect();
+
+        Some(Self { data, key_value })
+    }
+
+    /// Information required to interpret the depth values
+    pub fn key_value(&self) -> BTreeMap<String, String> {
+        self.key_value.clone()
+    }
+
+    /// Decode the depth map as `G8` or `G16` frame
+    pub fn frame(&self) -> Result<Frame, ProcessError> {
+        let image = image::load_from_memory(&self.data).expected_error()?;
+        let (width, height) = (image.width(), image.height());
+
+        let is_16_bit = matches!(
+            image,
+            image::DynamicImage::ImageLuma16(_)
+                | image::DynamicImage::ImageLumaA16(_)
+                | image::DynamicImage::ImageRgb16(_)
+                | image::DynamicImage::ImageRgba16(_)
+        );
+
+        let (memory_format, data) = if is_16_bit {
+            let data = image
+                .into_luma16()
+                .into_raw()
+                .into_iter()
+                .flat_map(u16::to_ne_bytes)
+                .collect::<Vec<_>>();
+            (MemoryFormat::G16, data)
+        } else {
+            (MemoryFormat::G8, image.into_luma8().into_raw())
+        };
+
+        let texture = BinaryData::from_data(data).expected_error()?;
+
+        Ok(Frame::new(width, height, memory_format, texture)?)
+    }
+}
+
+/// Extended XMP with the given GUID, assembled from its chunks
+///
+/// Each chunk starts with the GUID, the length of the complete extended XMP,
+/// and the offset of the chunk, both stored in big-endian.
+fn extended_xmp(jpeg: &gufo_jpeg::Jpeg, guid: Option<&str>) -> Vec<u8> {
+    let mut chunks = jpeg
+        .segments_marker(gufo_jpeg::Marker::APP1)
+        .filter_map(|segment| {
+            let chunk = segment.data().strip_prefix(XMP_EXTENSION_IDENTIFIER)?;
+            let (header, data) = chunk.split_at_checked(XMP_EXTENSION_HEADER_LEN)?;
+            let (chunk_guid, header) = header.split_at(32);
+            if guid.is_some_and(|guid| guid.as_bytes() != chunk_guid) {
+                return None;
+            }
+            let offset = u32::from_be_bytes(header.get(4..8)?.try_into().ok()?);
+            Some((offset, data))
+        })
+        .collect::<Vec<_>>();
+
+    chunks.sort_by_key(|(offset, _)| *offset);
+
+    chunks
+        .into_iter()
+        .flat_map(|(_, data)| data)
+        .copied()
+        .collect()
+}
+
+/// Decode standard base64, ignoring whitespace
+fn base64_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(encoded.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut n_bits = 0;
+
+    for c in encoded.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            c if c.is_ascii_whitespace() => continue,
+            _ => return None,
+        };
+
+        bits = (bits << 6) | u32::from(value);
+        n_bits += 6;
+
+        if n_bits >= 8 {
+            n_bits -= 8;
+            data.push((bits >> n_bits) as u8);
+            bits &= (1 << n_bits) - 1;
+        }
+    }
+
+    Some(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn base64() {
+        // Test vectors from RFC 4648
+        for (encoded, decoded) in [
+            ("", ""),
+            ("Zg==", "f"),
+            ("Zm8=", "fo"),
+            ("Zm9v", "foo"),
+            ("Zm9vYg==", "foob"),
+            ("Zm9vYmE=", "fooba"),
+            ("Zm9vYmFy", "foobar"),
+        ] {
+            assert_eq!(
+                base64_decode(encoded).as_deref(),
+                Some(decoded.as_bytes()),
+                "{encoded}"
+            );
+        }
+
+        assert_eq!(base64_decode("+/+/"), Some(vec![0xFB, 0xFF, 0xBF]));
+        assert_eq!(base64_decode("AAEC/w=="), Some(vec![0, 1, 2, 255]));
+    }
+
+    #[test]
+    fn base64_whitespace_and_padding() {
+        // Line breaks and indentation as in XMP
+        assert_eq!(
+            base64_decode(" Zm9v\n    YmFy\r\n").as_deref(),
+            Some(b"foobar".as_slice())
+        );
+        // Missing padding
+        assert_eq!(base64_decode("Zm9vYg").as_deref(), Some(b"foob".as_slice()));
+        // Data after the padding is ignored
+        assert_eq!(base64_decode("Zg==Zm9v").as_deref(), Some(b"f".as_slice()));
+    }
+
+    #[test]
+    fn base64_invalid() {
+        assert_eq!(base64_decode("Zm9v-YmFy"), None);
+        assert_eq!(base64_decode("Zm9v_"), None);
+        assert_eq!(base64_decode("Zm9vä"), None);
+    }
+}
@@ -1,5 +1,7 @@
+mod gif;
 mod jpeg;
 mod png;
+mod tiff;
 
 use std::io::Cursor;
 
@@ -15,11 +17,13 @@ impl EditorImplementation for ImgEditor {
     fn edit(
         stream: UnixStream,
         mime_type: String,
-        _details: InitializationDetails,
+        details: InitializationDetails,
     ) -> Result<Self, ProcessError> {
+        let preserve_memory_format = details.preserve_memory_format.unwrap_or_default();
+
         Ok(match mime_type.as_str() {
-            "image/png" => Self::Png(png::load(stream)?),
-            "image/jpeg" => Self::Jpeg(jpeg::load(stream)?),
+            "image/png" => Self::Png(png::load(stream, preserve_memory_format)?),
+            "image/jpeg" => Self::Jpeg(jpeg::load(stream, preserve_memory_format)?),
             mime_type => return Err(ProcessError::UnsupportedImageFormat(mime_type.to_string())),
         })
     }
@@ -48,18 +52,23 @@ impl EditorImplementation for ImgEditor {
         mut new_image: NewImage,
         encoding_options: EncodingOptions,
     ) -> Result<EncodedImage, ProcessError> {
-        let frame = new_image.frames.remove(0);
-
         let image_format = image_format(&mime_type)?;
 
-        let memory_format = (MemoryFormatSelection::G8
-            | MemoryFormatSelection::G8a8
-            | MemoryFormatSelection::R8g8b8
-            | MemoryFormatSelection::R8g8b8a8
-            | MemoryFormatSelection::G16
-            | MemoryFormatSelection::G16a16
-            | MemoryFormatSelection::R16g16b16
-            | MemoryFormatSelection::R16g16b16a16)
+        if image_format == ImageFormat::Tiff {
+            return tiff::create(new_image);
+        }
+
+        if image_format == ImageFormat::Gif && new_image.frames.len() > 1 {
+            return gif::create_animated(new_image);
+        }
+
+        if image_format == ImageFormat::Png && new_image.frames.len() > 1 {
+            return png::create_animated(new_image);
+        }
+
+        let frame = new_image.frames.remove(0);
+
+        let memory_format = encoder_memory_formats(image_format)
             .best_format_for(frame.memory_format)
             .internal_error()?;
 
@@ -77,7 +86,28 @@ impl EditorImplementation for ImgEditor {
                 .ok()
         });
 
+        let progressive = encoding_options.progressive.unwrap_or_default();
+
         let image_buf = match image_format {
+            ImageFormat::Png if progressive => {
+                let compression = match encoding_options.compression {
+                    Some(compression) => {
+                        flate2::Compression::new(u32::from(u8::min(compression, 100)) * 9 / 100)
+                    }
+                    None => flate2::Compression::default(),
+                };
+
+                let out_buf = png::encode_interlaced(
+                    &img_buf,
+                    frame.width,
+                    frame.height,
+                    memory_format,
+                    icc_profile,
+                    compression,
+                )?;
+
+                png::add_metadata(out_buf, &new_image.image_info, &frame.details)
+            }
             ImageFormat::Png => {
                 let compression = if let Some(compression) = encoding_options.compression {
                     if compression < 30 {
@@ -110,20 +140,38 @@ impl EditorImplementation for ImgEditor {
 
                 out_buf
             }
+            ImageFormat::Jpeg if progressive => jpeg::encode_progressive(
+                &img_buf,
+                &frame,
+                memory_format,
+                jpeg_quality(&encoding_options),
+                icc_profile,
+                new_image,
+            )?,
             ImageFormat::Jpeg => {
                 let mut out_buf = Vec::new();
                 let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(
                     &mut out_buf,
-                    encoding_options
-                        .quality
-                        .map(|x| u8::min(x, 100))
-                        .unwrap_or(90),
+                    jpeg_quality(&encoding_options),
                 );
 
                 if let Some(icc_profile) = icc_profile {
                     let _ = encoder.set_icc_profile(icc_profile);
                 }
 
+                if let Some((dpi_x, dpi_y)) = new_image.image_info.resolution_dpi {
+                    encoder.set_pixel_density(image::codecs::jpeg::PixelDensity {
+                        density: (resolution_value(dpi_x)?, resolution_value(dpi_y)?),
+                        unit: image::codecs::jpeg::PixelDensityUnit::Inches,
+                    });
+                }
+
+                if let Some(thumbnail) = new_image.thumbnail {
+                    encoder
+                        .set_exif_metadata(jpeg::exif_thumbnail(thumbnail)?)
+                        .expected_error()?;
+                }
+
                 encoder
                     .write_image(&img_buf, frame.width, frame.height, memory_format)
                     .internal_error()?;
@@ -151,6 +199,77 @@ impl EditorImplementation for ImgEditor {
     }
 }
 
+/// Animation frame with RGBA pixels
+struct AnimationFrame {
+    width: u32,
+    height: u32,
+    offset: (u32, u32),
+    delay: std::time::Duration,
+    blend: Option<FrameBlend>,
+    dispose: Option<FrameDispose>,
+    rgba: Vec<u8>,
+}
+
+/// Frames of an animation converted to RGBA
+///
+/// The canvas has the dimensions of the first frame, which has to be placed
+/// at the origin. All other frames have to fit onto the canvas. Frames
+/// without delay are shown for 100 ms, like most viewers do for a delay of
+/// zero.
+fn animation_frames(frames: Vec<Frame>) -> Result<Vec<AnimationFrame>, ProcessError> {
+    let mut animation_frames = Vec::<AnimationFrame>::new();
+
+    for frame in frames {
+        let offset = frame.details.animation_offset.unwrap_or_default();
+
+        let fits = match animation_frames.first() {
+            None => offset == (0, 0),
+            Some(canvas) => {
+                offset.0.checked_add(frame.width) <= Some(canvas.width)
+                    && offset.1.checked_add(frame.height) <= Some(canvas.height)
+            }
+        };
+
+        if !fits {
+            return Err(ProcessError::expected(&format!(
+                "Frame {}x{} at {offset:?} is outside of the canvas",
+                frame.width, frame.height
+            )));
+        }
+
+        let delay = (*frame.delay).unwrap_or(std::time::Duration::from_millis(100));
+        let blend = frame.details.info_animation_blend;
+        let dispose = frame.details.info_animation_dispose;
+
+        let v = frame.texture.get_full().expected_error()?;
+        let (frame, img_buf) = glycin_utils::editing::change_memory_format(
+            ImgBuf::Vec(v),
+            frame,
+            MemoryFormat::R8g8b8a8,
+        )
+        .expected_error()?;
+
+        animation_frames.push(AnimationFrame {
+            width: frame.width,
+            height: frame.height,
+            offset,
+            delay,
+            blend,
+            dispose,
+            rgba: img_buf.into_vec(),
+        });
+    }
+
+    Ok(animation_frames)
+}
+
+fn jpeg_quality(encoding_options: &EncodingOptions) -> u8 {
+    encoding_options
+        .quality
+        .map(|x| u8::min(x, 100))
+        .unwrap_or(90)
+}
+
 fn image_format(mime_type: &str) -> Result<ImageFormat, ProcessError> {
     Ok(match mime_type {
         "image/bmp" => ImageFormat::Bmp,
@@ -168,6 +287,29 @@ fn image_format(mime_type: &str) -> Result<ImageFormat, ProcessError> {
     })
 }
 
+/// Memory formats that can be written without losing depth
+///
+/// High-depth frames are only downconverted for formats that can't store
+/// 16-bit samples.
+fn encoder_memory_formats(image_format: ImageFormat) -> MemoryFormatSelection {
+    let formats_8bit = MemoryFormatSelection::G8
+        | MemoryFormatSelection::G8a8
+        | MemoryFormatSelection::R8g8b8
+        | MemoryFormatSelection::R8g8b8a8;
+
+    match image_format {
+        ImageFormat::Png => {
+            formats_8bit
+                | MemoryFormatSelection::G16
+                | MemoryFormatSelection::G16a16
+                | MemoryFormatSelection::R16g16b16
+                | MemoryFormatSelection::R16g16b16a16
+        }
+        ImageFormat::Farbfeld => MemoryFormatSelection::R16g16b16a16,
+        _ => formats_8bit,
+    }
+}
+
 fn image_memory_format(memory_format: MemoryFormat) -> Result<ExtendedColorType, ProcessError> {
     Ok(match memory_format {
         MemoryFormat::G8 => ExtendedColorType::L8,
@@ -181,3 +323,473 @@ fn image_memory_format(memory_format: MemoryFormat) -> Result<ExtendedColorType,
         _ => unreachable!(),
     })
 }
+
+/// Rounded resolution that fits into the integer fields of a format
+fn resolution_value<T: TryFrom<u32>>(value: f64) -> Result<T, ProcessError> {
+    let rounded = value.round();
+    let invalid = || ProcessError::expected(&format!("Unsupported resolution {value}"));
+
+    if !(1. ..=f64::from(u32::MAX)).contains(&rounded) {
+        return Err(invalid());
+    }
+
+    T::try_from(rounded as u32).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolution_range() {
+        assert_eq!(resolution_value::<u16>(299.6).unwrap(), 300);
+        assert_eq!(resolution_value::<u32>(70_000.).unwrap(), 70_000);
+
+        for value in [0., 0.4, -72., f64::NAN, f64::INFINITY, 70_000.] {
+            assert!(resolution_value::<u16>(value).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn create_16bit() {
+        // Samples that don't survive conversion to 8-bit
+        let samples = [
+            0x0102_u16, 0xfffe, 0x8001, 0x7f80, 0x00ff, 0xabcd, 0x1234, 0x4321,
+        ];
+
+        let formats = [
+            ("image/png", MemoryFormat::G16),
+            ("image/png", MemoryFormat::G16a16),
+            ("image/png", MemoryFormat::R16g16b16),
+            ("image/png", MemoryFormat::R16g16b16a16),
+            ("image/tiff", MemoryFormat::G16),
+            ("image/tiff", MemoryFormat::R16g16b16),
+            ("image/tiff", MemoryFormat::R16g16b16a16),
+        ];
+
+        for (mime_type, memory_format) in formats {
+            let width = 2;
+            let texture = samples[..usize::from(memory_format.n_channels()) * 2]
+                .iter()
+                .flat_map(|x| x.to_ne_bytes())
+                .collect::<Vec<u8>>();
+
+            let frame = Frame::new(
+                width,
+                1,
+                memory_format,
+                BinaryData::from_data(texture.clone()).unwrap(),
+            )
+            .unwrap();
+            let new_image = NewImage::new(ImageDetails::new(width, 1), vec![frame]);
+
+            let encoded =
+                ImgEditor::create(mime_type.into(), new_image, EncodingOptions::default()).unwrap();
+            let data = encoded.data.get_full().unwrap();
+
+            let image =
+                image::load_from_memory_with_format(&data, image_format(mime_type).unwrap())
+                    .unwrap();
+
+            assert_eq!(
+                image.color(),
+                image_memory_format(memory_format)
+                    .unwrap()
+                    .try_into()
+                    .unwrap(),
+                "{mime_type} {memory_format:?}"
+            );
+            assert_eq!(image.as_bytes(), texture, "{mime_type} {memory_format:?}");
+        }
+    }
+
+    #[test]
+    fn create_animated_gif() {
+        use image::AnimationDecoder;
+
+        let frames = [([255, 0, 0], 40), ([0, 0, 255], 250)]
+            .into_iter()
+            .map(|(color, delay)| {
+                let texture = color.repeat(4);
+                let mut frame = Frame::new(
+                    2,
+                    2,
+                    MemoryFormat::R8g8b8,
+                    BinaryData::from_data(texture).unwrap(),
+                )
+                .unwrap();
+                frame.delay = Some(std::time::Duration::from_millis(delay)).into();
+                frame
+            })
+            .collect();
+        let new_image = NewImage::new(ImageDetails::new(2, 2), frames);
+
+        let encoded =
+            ImgEditor::create("image/gif".into(), new_image, EncodingOptions::default()).unwrap();
+        let data = encoded.data.get_full().unwrap();
+
+        let frames = image::codecs::gif::GifDecoder::new(std::io::Cursor::new(data))
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].buffer().get_pixel(1, 1).0, [255, 0, 0, 255]);
+        assert_eq!(frames[0].delay().numer_denom_ms(), (40, 1));
+        assert_eq!(frames[1].buffer().get_pixel(0, 0).0, [0, 0, 255, 255]);
+        assert_eq!(frames[1].delay().numer_denom_ms(), (250, 1));
+    }
+
+    fn animation_frame(
+        (width, height): (u32, u32),
+        color: [u8; 3],
+        offset: (u32, u32),
+        methods: (Option<FrameBlend>, Option<FrameDispose>),
+    ) -> Frame {
+        let texture = color.repeat((width * height) as usize);
+        let mut frame = Frame::new(
+            width,
+            height,
+            MemoryFormat::R8g8b8,
+            BinaryData::from_data(texture).unwrap(),
+        )
+        .unwrap();
+        frame.delay = Some(std::time::Duration::from_millis(50)).into();
+        frame.details.animation_offset = Some(offset);
+        (
+            frame.details.info_animation_blend,
+            frame.details.info_animation_dispose,
+        ) = methods;
+        frame
+    }
+
+    fn create_animation(mime_type: &str, frames: Vec<Frame>) -> Result<Vec<u8>, ProcessError> {
+        let new_image = NewImage::new(ImageDetails::new(1, 1), frames);
+        let encoded = ImgEditor::create(mime_type.into(), new_image, EncodingOptions::default())?;
+        Ok(encoded.data.get_full().unwrap())
+    }
+
+    #[test]
+    fn create_animated_gif_partial_frames() {
+        use image::AnimationDecoder;
+
+        let data = create_animation(
+            "image/gif",
+            vec![
+                animation_frame((4, 4), [255, 0, 0], (0, 0), (None, None)),
+                animation_frame(
+                    (2, 1),
+                    [0, 0, 255],
+                    (1, 2),
+                    (Some(FrameBlend::Over), Some(FrameDispose::None)),
+                ),
+                animation_frame((1, 1), [0, 255, 0], (3, 3), (None, None)),
+            ],
+        )
+        .unwrap();
+
+        let mut decoder = ::gif::DecodeOptions::new()
+            .read_info(data.as_slice())
+            .unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (4, 4));
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.next_frame_info().unwrap() {
+            frames.push((
+                frame.left,
+                frame.top,
+                frame.width,
+                frame.height,
+                frame.dispose,
+            ));
+        }
+        assert_eq!(
+            frames,
+            [
+                (0, 0, 4, 4, ::gif::DisposalMethod::Background),
+                (1, 2, 2, 1, ::gif::DisposalMethod::Keep),
+                (3, 3, 1, 1, ::gif::DisposalMethod::Background),
+            ]
+        );
+
+        // The kept frame stays on the canvas
+        let frames = image::codecs::gif::GifDecoder::new(Cursor::new(data))
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        let canvas = frames[2].buffer();
+        assert_eq!(canvas.get_pixel(2, 2).0, [0, 0, 255, 255]);
+        assert_eq!(canvas.get_pixel(3, 3).0, [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn create_animation_invalid() {
+        let full = || animation_frame((2, 2), [255, 0, 0], (0, 0), (None, None));
+
+        for mime_type in ["image/gif", "image/png"] {
+            // First frame has to be at the origin
+            let frames = vec![
+                animation_frame((2, 2), [255, 0, 0], (1, 0), (None, None)),
+                full(),
+            ];
+            assert!(create_animation(mime_type, frames).is_err());
+
+            // Frame outside of the canvas
+            let frames = vec![
+                full(),
+                animation_frame((2, 1), [255, 0, 0], (1, 0), (None, None)),
+            ];
+            assert!(create_animation(mime_type, frames).is_err());
+        }
+
+        let frames = vec![
+            full(),
+            animation_frame((1, 1), [0, 0, 0], (0, 0), (Some(FrameBlend::Source), None)),
+        ];
+        assert!(create_animation("image/gif", frames).is_err());
+    }
+
+    #[test]
+    fn create_apng() {
+        use image::AnimationDecoder;
+
+        let data = create_animation(
+            "image/png",
+            vec![
+                animation_frame((4, 3), [255, 0, 0], (0, 0), (None, None)),
+                animation_frame(
+                    (2, 2),
+                    [0, 0, 255],
+                    (2, 1),
+                    (Some(FrameBlend::Over), Some(FrameDispose::Background)),
+                ),
+                animation_frame(
+                    (1, 1),
+                    [0, 255, 0],
+                    (0, 0),
+                    (Some(FrameBlend::Source), Some(FrameDispose::Previous)),
+                ),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            crate::frame_methods::frame_methods("image/png", &data),
+            [
+                (FrameBlend::Source, FrameDispose::None),
+                (FrameBlend::Over, FrameDispose::Background),
+                (FrameBlend::Source, FrameDispose::Previous),
+            ]
+        );
+
+        let mut reader = ::png::Decoder::new(Cursor::new(&data)).read_info().unwrap();
+        let frame_control = *reader.info().frame_control().unwrap();
+        assert_eq!((frame_control.width, frame_control.height), (4, 3));
+        assert_eq!(
+            (frame_control.delay_num, frame_control.delay_den),
+            (50, 1000)
+        );
+        let frame_control = reader.next_frame_info().unwrap();
+        assert_eq!(
+            (
+                frame_control.x_offset,
+                frame_control.y_offset,
+                frame_control.width,
+                frame_control.height
+            ),
+            (2, 1, 2, 2)
+        );
+
+        let frames = image::codecs::png::PngDecoder::new(Cursor::new(data))
+            .unwrap()
+            .apng()
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        assert_eq!(frames.len(), 3);
+        let canvas = frames[1].buffer();
+        assert_eq!(canvas.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(canvas.get_pixel(3, 2).0, [0, 0, 255, 255]);
+        // The previous frame was disposed to the background
+        let canvas = frames[2].buffer();
+        assert_eq!(canvas.get_pixel(0, 0).0, [0, 255, 0, 255]);
+        assert_eq!(canvas.get_pixel(3, 2).0, [0, 0, 0, 0]);
+        assert_eq!(canvas.get_pixel(1, 0).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn create_jpeg_thumbnail() {
+        use gufo_common::exif::{Ifd, Tag, TagIfd};
+        use gufo_exif::internal::{ExifRaw, ValueOffset};
+
+        let frame = |width: u32, color: [u8; 3]| {
+            let texture = color.repeat(width.pow(2) as usize);
+            Frame::new(
+                width,
+                width,
+                MemoryFormat::R8g8b8,
+                BinaryData::from_data(texture).unwrap(),
+            )
+            .unwrap()
+        };
+
+        let mut new_image = NewImage::new(ImageDetails::new(64, 64), vec![frame(64, [255; 3])]);
+        new_image.thumbnail = Some(frame(8, [255, 0, 0]));
+
+        let encoded =
+            ImgEditor::create("image/jpeg".into(), new_image, EncodingOptions::default()).unwrap();
+        let jpeg = gufo_jpeg::Jpeg::new(encoded.data.get_full().unwrap()).unwrap();
+        let exif = jpeg.exif_data().next().unwrap().to_vec();
+
+        let mut exif_raw = ExifRaw::new(exif.clone());
+        exif_raw.decode().unwrap();
+        let mut value = |tag| match exif_raw
+            .lookup_entry(TagIfd::new(Tag(tag), Ifd::Thumbnail))
+            .unwrap()
+            .value_offset
+        {
+            ValueOffset::Value(x) => x as usize,
+            ValueOffset::Offset(x) => x as usize,
+        };
+        let offset = value(0x0201);
+        let len = value(0x0202);
+
+        let thumbnail =
+            image::load_from_memory_with_format(&exif[offset..offset + len], ImageFormat::Jpeg)
+                .unwrap()
+                .into_rgb8();
+
+        assert_eq!(thumbnail.dimensions(), (8, 8));
+        let [r, g, b] = thumbnail.get_pixel(4, 4).0;
+        assert!(r > 240 && g < 16 && b < 16, "{r} {g} {b}");
+    }
+
+    #[test]
+    fn create_progressive() {
+        let mut encoding_options = EncodingOptions::default();
+        encoding_options.progressive = Some(true);
+
+        for memory_format in [MemoryFormat::R8g8b8a8, MemoryFormat::G16] {
+            // Odd size to have passes that don't cover complete rows
+            let (width, height) = (13_u32, 5);
+            let texture = (0..width * height * u32::from(memory_format.n_bytes().u8()))
+                .map(|x| (x * 7) as u8)
+                .collect::<Vec<_>>();
+            let frame = Frame::new(
+                width,
+                height,
+                memory_format,
+                BinaryData::from_data(texture.clone()).unwrap(),
+            )
+            .unwrap();
+            let new_image = NewImage::new(ImageDetails::new(width, height), vec![frame]);
+
+            let encoded =
+                ImgEditor::create("image/png".into(), new_image, encoding_options.clone()).unwrap();
+            let data = encoded.data.get_full().unwrap();
+            assert!(crate::progressive_png::is_interlaced(&data));
+
+            let image = image::load_from_memory_with_format(&data, ImageFormat::Png).unwrap();
+            assert_eq!(image.as_bytes(), texture, "{memory_format:?}");
+        }
+
+        let texture = [200, 100, 50].repeat(16 * 16);
+        let frame = Frame::new(
+            16,
+            16,
+            MemoryFormat::R8g8b8,
+            BinaryData::from_data(texture).unwrap(),
+        )
+        .unwrap();
+        let new_image = NewImage::new(ImageDetails::new(16, 16), vec![frame]);
+
+        let encoded = ImgEditor::create("image/jpeg".into(), new_image, encoding_options).unwrap();
+        let data = encoded.data.get_full().unwrap();
+        let jpeg = gufo_jpeg::Jpeg::new(data.clone()).unwrap();
+        assert!(jpeg.is_progressive().unwrap());
+
+        let image = image::load_from_memory_with_format(&data, ImageFormat::Jpeg)
+            .unwrap()
+            .into_rgb8();
+        let [r, g, b] = image.get_pixel(8, 8).0;
+        assert!(r.abs_diff(200) < 8 && g.abs_diff(100) < 8 && b.abs_diff(50) < 8);
+    }
+
+    #[test]
+    fn create_multi_page_tiff() {
+        let pages = [
+            (3, 2, MemoryFormat::R8g8b8),
+            (2, 5, MemoryFormat::G8),
+            (4, 1, MemoryFormat::R8g8b8a8),
+        ];
+
+        let mut textures = Vec::new();
+        let mut frames = Vec::new();
+        for (i, (width, height, memory_format)) in pages.into_iter().enumerate() {
+            let texture = (0..width * height * u32::from(memory_format.n_bytes().u8()))
+                .map(|x| (x * 11 + i as u32 * 50) as u8)
+                .collect::<Vec<_>>();
+            frames.push(
+                Frame::new(
+                    width,
+                    height,
+                    memory_format,
+                    BinaryData::from_data(texture.clone()).unwrap(),
+                )
+                .unwrap(),
+            );
+            textures.push(texture);
+        }
+        let new_image = NewImage::new(ImageDetails::new(3, 2), frames);
+
+        let encoded =
+            ImgEditor::create("image/tiff".into(), new_image, EncodingOptions::default()).unwrap();
+        let data = encoded.data.get_full().unwrap();
+
+        // Read the pages back like requested sub-images
+        let sub_images = crate::tiled_tiff::pages(&data).unwrap();
+        assert_eq!(sub_images.len(), pages.len());
+        for (i, ((width, height, memory_format), texture)) in
+            pages.iter().zip(&textures).enumerate()
+        {
+            assert_eq!(
+                (sub_images[i].width, sub_images[i].height),
+                (*width, *height),
+                "page {i}"
+            );
+
+            let mut page =
+                crate::tiled_tiff::TiledTiff::page(data.clone(), i as u32, FrameDetails::default())
+                    .unwrap();
+            let frame = page.frame(None).unwrap();
+            assert_eq!((frame.width, frame.height), (*width, *height), "page {i}");
+            assert_eq!(frame.memory_format, *memory_format, "page {i}");
+            assert_eq!(&frame.texture.get_full().unwrap(), texture, "page {i}");
+        }
+        assert!(crate::tiled_tiff::TiledTiff::page(
+            data,
+            pages.len() as u32,
+            FrameDetails::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn create_tiff_with_delay() {
+        let mut frame = Frame::new(
+            1,
+            1,
+            MemoryFormat::G8,
+            BinaryData::from_data(vec![0]).unwrap(),
+        )
+        .unwrap();
+        frame.delay = Some(std::time::Duration::from_millis(100)).into();
+        let new_image = NewImage::new(ImageDetails::new(1, 1), vec![frame]);
+
+        let err = ImgEditor::create("image/tiff".into(), new_image, EncodingOptions::default())
+            .unwrap_err();
+        assert!(err.to_string().contains("doesn't support delays"), "{err}");
+    }
+}
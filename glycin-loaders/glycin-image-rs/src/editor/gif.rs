@@ -0,0 +1,60 @@
+use glycin_utils::*;
+
+use super::animation_frames;
+
+/// Create an animated GIF with one image for each frame
+///
+/// The animation loops infinitely. Frames are disposed to the background
+/// unless a different dispose method is set. GIF frames are always drawn
+/// over the canvas, such that the source blend method is not supported.
+pub fn create_animated(new_image: NewImage) -> Result<EncodedImage, ProcessError> {
+    let frames = animation_frames(new_image.frames)?;
+    let Some(canvas) = frames.first() else {
+        return Err(ProcessError::expected(&"No frames to encode"));
+    };
+
+    let mut out_buf = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(
+            &mut out_buf,
+            gif_dimension(canvas.width)?,
+            gif_dimension(canvas.height)?,
+            &[],
+        )
+        .expected_error()?;
+        encoder.set_repeat(gif::Repeat::Infinite).expected_error()?;
+
+        for mut frame in frames {
+            if frame.blend == Some(FrameBlend::Source) {
+                return Err(ProcessError::expected(
+                    &"GIF doesn't support the source blend method",
+                ));
+            }
+
+            let mut gif_frame = gif::Frame::from_rgba_speed(
+                gif_dimension(frame.width)?,
+                gif_dimension(frame.height)?,
+                &mut frame.rgba,
+                1,
+            );
+            gif_frame.left = gif_dimension(frame.offset.0)?;
+            gif_frame.top = gif_dimension(frame.offset.1)?;
+            // The delay is stored in units of 10 ms
+            gif_frame.delay = u16::try_from(frame.delay.as_millis() / 10).unwrap_or(u16::MAX);
+            gif_frame.dispose = match frame.dispose {
+                Some(FrameDispose::None) => gif::DisposalMethod::Keep,
+                Some(FrameDispose::Previous) => gif::DisposalMethod::Previous,
+                _ => gif::DisposalMethod::Background,
+            };
+
+            encoder.write_frame(&gif_frame).expected_error()?;
+        }
+    }
+
+    let data = BinaryData::from_data(out_buf).expected_error()?;
+    Ok(EncodedImage::new(data))
+}
+
+fn gif_dimension(value: u32) -> Result<u16, ProcessError> {
+    u16::try_from(value).map_err(|_| ProcessError::expected(&"Dimension too large for GIF"))
+}
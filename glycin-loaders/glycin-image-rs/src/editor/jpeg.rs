@@ -1,19 +1,28 @@
 use std::io::Read;
 
 use editing::EditingFrame;
+use glycin_utils::safe_math::*;
 use glycin_utils::*;
 use gufo_common::orientation::Orientation;
 use gufo_jpeg::Jpeg;
+use zune_jpeg::zune_core::colorspace::ColorSpace;
 use zune_jpeg::zune_core::options::DecoderOptions;
 
 pub struct EditJpeg {
     buf: Vec<u8>,
+    preserve_memory_format: bool,
 }
 
-pub fn load(mut stream: glycin_utils::UnixStream) -> Result<EditJpeg, glycin_utils::ProcessError> {
+pub fn load(
+    mut stream: glycin_utils::UnixStream,
+    preserve_memory_format: bool,
+) -> Result<EditJpeg, glycin_utils::ProcessError> {
     let mut buf: Vec<u8> = Vec::new();
     stream.read_to_end(&mut buf).internal_error()?;
-    Ok(EditJpeg { buf })
+    Ok(EditJpeg {
+        buf,
+        preserve_memory_format,
+    })
 }
 
 pub fn apply_sparse(
@@ -35,7 +44,9 @@ pub fn apply_sparse(
     }
 
     Ok(SparseEditorOutput::from(apply_non_sparse(
-        jpeg, operations,
+        jpeg,
+        operations,
+        edit_jpeg.preserve_memory_format,
     )?))
 }
 
@@ -60,29 +71,51 @@ pub fn apply_complete(
         }
     }
 
-    apply_non_sparse(jpeg, operations)
+    apply_non_sparse(jpeg, operations, edit_jpeg.preserve_memory_format)
 }
 
 fn apply_non_sparse(
     jpeg: Jpeg,
     operations: Operations,
+    preserve_memory_format: bool,
 ) -> Result<CompleteEditorOutput, glycin_utils::ProcessError> {
     let mut out_buf = Vec::new();
     let encoder = jpeg.encoder(&mut out_buf).expected_error()?;
     let buf = jpeg.into_inner();
 
     let decoder_options = DecoderOptions::new_fast()
-        .jpeg_set_out_colorspace(zune_jpeg::zune_core::colorspace::ColorSpace::YCbCr)
         .set_max_height(u32::MAX as usize)
         .set_max_width(u32::MAX as usize);
     let mut decoder = zune_jpeg::JpegDecoder::new_with_options(&buf, decoder_options);
+    decoder.decode_headers().expected_error()?;
+
+    let (colorspace, memory_format, color_type) = match decoder.get_input_colorspace() {
+        // Keep grayscale images grayscale instead of expanding them to YCbCr
+        Some(ColorSpace::Luma) if preserve_memory_format => (
+            ColorSpace::Luma,
+            ExtendedMemoryFormat::Basic(MemoryFormat::G8),
+            jpeg_encoder::ColorType::Luma,
+        ),
+        Some(colorspace @ (ColorSpace::CMYK | ColorSpace::YCCK)) if preserve_memory_format => {
+            return Err(ProcessError::expected(&format!(
+                "Can't preserve memory format of JPEG with color space {colorspace:?}"
+            )));
+        }
+        _ => (
+            ColorSpace::YCbCr,
+            ExtendedMemoryFormat::Y8Cb8Cr8,
+            jpeg_encoder::ColorType::Ycbcr,
+        ),
+    };
+
+    decoder.set_options(decoder_options.jpeg_set_out_colorspace(colorspace));
     let mut pixels = decoder.decode().expected_error()?;
     let info: zune_jpeg::ImageInfo = decoder.info().expected_error()?;
     let mut simple_frame = EditingFrame {
         width: info.width as u32,
         height: info.height as u32,
-        stride: info.width as u32 * 3,
-        memory_format: ExtendedMemoryFormat::Y8Cb8Cr8,
+        stride: info.width as u32 * memory_format.n_bytes().u32(),
+        memory_format,
     };
 
     pixels = editing::apply_operations(pixels, &mut simple_frame, &operations).expected_error()?;
@@ -92,7 +125,7 @@ fn apply_non_sparse(
             &pixels,
             simple_frame.width as u16,
             simple_frame.height as u16,
-            jpeg_encoder::ColorType::Ycbcr,
+            color_type,
         )
         .expected_error()?;
 
@@ -146,3 +179,136 @@ fn rotate_sparse(
 
     Ok(None)
 }
+
+/// Maximum size of the Exif data in an APP1 segment
+///
+/// The segment length is stored in 16 bits and includes the length field
+/// itself and the `Exif\0\0` identifier.
+const MAX_EXIF_SIZE: usize = u16::MAX as usize - 2 - 6;
+
+/// Exif data that only contains the JPEG encoded `thumbnail` in IFD1
+pub fn exif_thumbnail(thumbnail: Frame) -> Result<Vec<u8>, ProcessError> {
+    let img_buf = ImgBuf::Vec(thumbnail.texture.get_full().expected_error()?);
+    let (thumbnail, img_buf) =
+        editing::change_memory_format(img_buf, thumbnail, MemoryFormat::R8g8b8).expected_error()?;
+
+    let mut data = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut data, 75)
+        .encode(
+            &img_buf,
+            thumbnail.width,
+            thumbnail.height,
+            image::ExtendedColorType::Rgb8,
+        )
+        .expected_error()?;
+
+    let entry = |tag: u16, data_type: u16, value: u32| {
+        [
+            tag.to_le_bytes().as_slice(),
+            &data_type.to_le_bytes(),
+            &1_u32.to_le_bytes(),
+            &value.to_le_bytes(),
+        ]
+        .concat()
+    };
+
+    // Header, IFD0 with one entry, and IFD1 with three entries
+    let data_offset = 8 + (2 + 12 + 4) + (2 + 3 * 12 + 4);
+    let exif = [
+        b"II\x2a\x00\x08\x00\x00\x00".as_slice(),
+        &1_u16.to_le_bytes(),
+        // Resolution unit in inches
+        &entry(0x0128, 3, 2),
+        &(8_u32 + 2 + 12 + 4).to_le_bytes(),
+        &3_u16.to_le_bytes(),
+        // JPEG compression
+        &entry(0x0103, 3, 6),
+        &entry(0x0201, 4, data_offset),
+        &entry(0x0202, 4, data.len().try_u32()?),
+        &0_u32.to_le_bytes(),
+        &data,
+    ]
+    .concat();
+
+    if exif.len() > MAX_EXIF_SIZE {
+        return Err(ProcessError::expected(&format!(
+            "Encoded thumbnail of {} bytes doesn't fit into the Exif data",
+            data.len()
+        )));
+    }
+
+    Ok(exif)
+}
+
+/// Progressive JPEG with the given image data
+///
+/// The image-rs encoder only writes baseline JPEGs. Therefore, the encoder
+/// that is also used for editing is used.
+pub fn encode_progressive(
+    buf: &[u8],
+    frame: &Frame,
+    color_type: image::ExtendedColorType,
+    quality: u8,
+    icc_profile: Option<Vec<u8>>,
+    new_image: NewImage,
+) -> Result<Vec<u8>, ProcessError> {
+    let too_large = |_| {
+        ProcessError::UnsupportedImageFormat(format!(
+            "JPEG with {}x{} pixels",
+            frame.width, frame.height
+        ))
+    };
+    let width = u16::try_from(frame.width).map_err(too_large)?;
+    let height = u16::try_from(frame.height).map_err(too_large)?;
+
+    let mut out_buf = Vec::new();
+    let mut encoder = jpeg_encoder::Encoder::new(&mut out_buf, quality);
+    encoder.set_progressive(true);
+
+    if let Some(icc_profile) = icc_profile {
+        if let Err(err) = encoder.add_icc_profile(&icc_profile) {
+            log::error!("Can't add ICC profile: {err}");
+        }
+    }
+
+    if let Some((dpi_x, dpi_y)) = new_image.image_info.resolution_dpi {
+        encoder.set_density(jpeg_encoder::Density::Inch {
+            x: super::resolution_value(dpi_x)?,
+            y: super::resolution_value(dpi_y)?,
+        });
+    }
+
+    if let Some(thumbnail) = new_image.thumbnail {
+        let exif = [
+            gufo::jpeg::EXIF_IDENTIFIER_STRING,
+            &exif_thumbnail(thumbnail)?,
+        ]
+        .concat();
+        encoder.add_app_segment(1, &exif).expected_error()?;
+    }
+
+    match color_type {
+        image::ExtendedColorType::L8 => {
+            encoder.encode(buf, width, height, jpeg_encoder::ColorType::Luma)
+        }
+        // JPEG doesn't support alpha channels
+        image::ExtendedColorType::La8 => {
+            let luma = buf.iter().step_by(2).copied().collect::<Vec<_>>();
+            encoder.encode(&luma, width, height, jpeg_encoder::ColorType::Luma)
+        }
+        image::ExtendedColorType::Rgb8 => {
+            encoder.encode(buf, width, height, jpeg_encoder::ColorType::Rgb)
+        }
+        image::ExtendedColorType::Rgba8 => {
+            encoder.encode(buf, width, height, jpeg_encoder::ColorType::Rgba)
+        }
+        color_type => {
+            return Err(ProcessError::UnsupportedImageFormat(format!(
+                "JPEG with color type {color_type:?}"
+            )))
+        }
+    }
+    .expected_error()?;
+
+    Ok(out_buf)
+}
@@ -1,6 +1,7 @@
 use std::io::{Cursor, Read};
 use std::sync::Arc;
 
+use glycin_utils::safe_math::*;
 use glycin_utils::{image_rs, *};
 use gufo::png::NewChunk;
 use gufo_common::error::ErrorWithData;
@@ -14,7 +15,10 @@ pub struct EditorPng {
     editing_frame: glycin_utils::editing::EditingFrame,
 }
 
-pub fn load(mut stream: glycin_utils::UnixStream) -> Result<EditorPng, glycin_utils::ProcessError> {
+pub fn load(
+    mut stream: glycin_utils::UnixStream,
+    preserve_memory_format: bool,
+) -> Result<EditorPng, glycin_utils::ProcessError> {
     let mut old_png_data: Vec<u8> = Vec::new();
     stream.read_to_end(&mut old_png_data).internal_error()?;
     let cursor = Cursor::new(&old_png_data);
@@ -30,6 +34,12 @@ pub fn load(mut stream: glycin_utils::UnixStream) -> Result<EditorPng, glycin_ut
     let png: gufo::png::Png = gufo::png::Png::new(old_png_data).expected_error()?;
     let metadata = gufo::Metadata::for_png(&png);
 
+    if preserve_memory_format && is_expanded(&png) {
+        return Err(ProcessError::expected(
+            &"Can't preserve memory format of PNG with palette, transparency chunk, or less than 8 bits per channel",
+        ));
+    }
+
     Ok(EditorPng {
         png,
         metadata,
@@ -83,6 +93,25 @@ pub fn apply(
     Ok(CompleteEditorOutput::new(data))
 }
 
+/// Returns `true` if the decoder doesn't keep the memory format of the PNG
+///
+/// The decoder expands palettes, `tRNS` transparency, and bit depths below 8 bit.
+fn is_expanded(png: &gufo::png::Png) -> bool {
+    let chunks = png.chunks();
+
+    let expanded_header = chunks
+        .iter()
+        .find(|x| x.chunk_type() == gufo::png::ChunkType::IHDR)
+        .and_then(|x| x.chunk_data().get(8..10))
+        .is_none_or(|x| {
+            let (bit_depth, color_type) = (x[0], x[1]);
+            // Color type 3 is indexed color
+            bit_depth < 8 || color_type == 3
+        });
+
+    expanded_header || chunks.iter().any(|x| x.chunk_type().bytes() == *b"tRNS")
+}
+
 fn reset_exif_orientation(mut png: gufo::png::Png) -> Vec<u8> {
     let ornt = png
         .chunks()
@@ -176,6 +205,82 @@ pub fn add_metadata(
     }
 }
 
+/// Create an APNG with one animation frame for each frame
+///
+/// The first frame is also the default image shown by viewers without APNG
+/// support. The animation loops infinitely. Frames replace their area of the
+/// canvas and are not disposed unless different methods are set.
+pub fn create_animated(new_image: NewImage) -> Result<EncodedImage, ProcessError> {
+    let frames = super::animation_frames(new_image.frames)?;
+    let Some(canvas) = frames.first() else {
+        return Err(ProcessError::expected(&"No frames to encode"));
+    };
+
+    let mut out_buf = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut out_buf, canvas.width, canvas.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .set_animated(frames.len().try_u32()?, 0)
+            .expected_error()?;
+
+        let mut writer = encoder.write_header().expected_error()?;
+
+        for frame in &frames {
+            // Reset the position first, since the dimension is checked against it
+            writer.set_frame_position(0, 0).expected_error()?;
+            writer
+                .set_frame_dimension(frame.width, frame.height)
+                .expected_error()?;
+            writer
+                .set_frame_position(frame.offset.0, frame.offset.1)
+                .expected_error()?;
+
+            let (numerator, denominator) = apng_delay(frame.delay);
+            writer
+                .set_frame_delay(numerator, denominator)
+                .expected_error()?;
+
+            writer
+                .set_blend_op(match frame.blend {
+                    Some(FrameBlend::Over) => png::BlendOp::Over,
+                    _ => png::BlendOp::Source,
+                })
+                .expected_error()?;
+            writer
+                .set_dispose_op(match frame.dispose {
+                    Some(FrameDispose::Background) => png::DisposeOp::Background,
+                    Some(FrameDispose::Previous) => png::DisposeOp::Previous,
+                    _ => png::DisposeOp::None,
+                })
+                .expected_error()?;
+
+            writer.write_image_data(&frame.rgba).expected_error()?;
+        }
+
+        writer.finish().expected_error()?;
+    }
+
+    let out_buf = add_metadata(out_buf, &new_image.image_info, &FrameDetails::default());
+
+    let data = BinaryData::from_data(out_buf).expected_error()?;
+    Ok(EncodedImage::new(data))
+}
+
+/// Delay as fraction of seconds
+///
+/// Milliseconds are used if possible and hundredths of a second otherwise.
+fn apng_delay(delay: std::time::Duration) -> (u16, u16) {
+    match u16::try_from(delay.as_millis()) {
+        Ok(millis) => (millis, 1000),
+        Err(_) => (
+            u16::try_from(delay.as_millis() / 10).unwrap_or(u16::MAX),
+            100,
+        ),
+    }
+}
+
 fn add_metadata_internal(
     buf: Vec<u8>,
     image_info: &ImageDetails,
@@ -183,6 +288,17 @@ fn add_metadata_internal(
 ) -> Result<Vec<u8>, ErrorWithData<gufo::png::Error>> {
     let mut png = gufo::png::Png::new(buf)?;
 
+    if let Some(resolution_dpi) = image_info.resolution_dpi {
+        match phys_chunk(resolution_dpi) {
+            Ok(chunk) => {
+                if let Err(err) = png.insert_chunk(chunk) {
+                    return Err(ErrorWithData::new(err, png.into_inner()));
+                }
+            }
+            Err(err) => log::error!("Can't add resolution: {err}"),
+        }
+    }
+
     if let Some(key_value) = &image_info.metadata_key_value {
         for (key, value) in key_value {
             if let Err(err) = png.insert_chunk(NewChunk::text(key, value)) {
@@ -193,3 +309,110 @@ fn add_metadata_internal(
 
     Ok(png.into_inner())
 }
+
+/// `pHYs` chunk with the resolution in pixels per meter
+fn phys_chunk((dpi_x, dpi_y): (f64, f64)) -> Result<NewChunk, ProcessError> {
+    const INCH_PER_METER: f64 = 1. / 0.0254;
+
+    let mut data = Vec::with_capacity(9);
+    data.extend_from_slice(&super::resolution_value::<u32>(dpi_x * INCH_PER_METER)?.to_be_bytes());
+    data.extend_from_slice(&super::resolution_value::<u32>(dpi_y * INCH_PER_METER)?.to_be_bytes());
+    // Unit is meter
+    data.push(1);
+
+    Ok(NewChunk::new(gufo::png::ChunkType::pHYs, data))
+}
+
+/// Horizontal and vertical start and spacing of the Adam7 passes
+const ADAM7_PASSES: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// Interlaced PNG with the given image data
+///
+/// The png crate can't write interlaced images. Therefore, the Adam7 passes
+/// are assembled here. The scanlines are stored without filter.
+pub fn encode_interlaced(
+    buf: &[u8],
+    width: u32,
+    height: u32,
+    color_type: image::ExtendedColorType,
+    icc_profile: Option<Vec<u8>>,
+    compression: flate2::Compression,
+) -> Result<Vec<u8>, ProcessError> {
+    let (png_color_type, bit_depth) = match color_type {
+        image::ExtendedColorType::L8 => (png::ColorType::Grayscale, png::BitDepth::Eight),
+        image::ExtendedColorType::La8 => (png::ColorType::GrayscaleAlpha, png::BitDepth::Eight),
+        image::ExtendedColorType::Rgb8 => (png::ColorType::Rgb, png::BitDepth::Eight),
+        image::ExtendedColorType::Rgba8 => (png::ColorType::Rgba, png::BitDepth::Eight),
+        image::ExtendedColorType::L16 => (png::ColorType::Grayscale, png::BitDepth::Sixteen),
+        image::ExtendedColorType::La16 => (png::ColorType::GrayscaleAlpha, png::BitDepth::Sixteen),
+        image::ExtendedColorType::Rgb16 => (png::ColorType::Rgb, png::BitDepth::Sixteen),
+        image::ExtendedColorType::Rgba16 => (png::ColorType::Rgba, png::BitDepth::Sixteen),
+        color_type => {
+            return Err(ProcessError::UnsupportedImageFormat(format!(
+                "PNG with color type {color_type:?}"
+            )))
+        }
+    };
+    let is_16_bit = bit_depth == png::BitDepth::Sixteen;
+    let pixel_size = png_color_type
+        .samples()
+        .smul(if is_16_bit { 2 } else { 1 })?;
+    let width_ = width.try_usize()?;
+    let stride = width_.smul(pixel_size)?;
+
+    let mut raw = Vec::new();
+    for (x_start, y_start, x_step, y_step) in ADAM7_PASSES {
+        // Passes without pixels in a row have no scanlines
+        if x_start >= width_ {
+            continue;
+        }
+
+        for y in (y_start..height.try_usize()?).step_by(y_step) {
+            // Filter type none
+            raw.push(0);
+            let row = buf.get(y.smul(stride)?..).internal_error()?;
+            for x in (x_start..width_).step_by(x_step) {
+                let start = x.smul(pixel_size)?;
+                let pixel = row.get(start..start.sadd(pixel_size)?).internal_error()?;
+                if is_16_bit {
+                    // PNG stores samples in big endian
+                    for sample in pixel.chunks_exact(2) {
+                        raw.extend(u16::from_ne_bytes([sample[0], sample[1]]).to_be_bytes());
+                    }
+                } else {
+                    raw.extend_from_slice(pixel);
+                }
+            }
+        }
+    }
+
+    let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), compression);
+    std::io::Write::write_all(&mut zlib, &raw).internal_error()?;
+    let image_data = zlib.finish().internal_error()?;
+
+    let mut info = png::Info::with_size(width, height);
+    info.color_type = png_color_type;
+    info.bit_depth = bit_depth;
+    info.interlaced = true;
+    info.icc_profile = icc_profile.map(Into::into);
+
+    let mut out_buf = Vec::new();
+    let mut writer = png::Encoder::with_info(&mut out_buf, info)
+        .internal_error()?
+        .write_header()
+        .internal_error()?;
+    writer
+        .write_chunk(png::chunk::IDAT, &image_data)
+        .internal_error()?;
+    writer.finish().internal_error()?;
+
+    Ok(out_buf)
+}
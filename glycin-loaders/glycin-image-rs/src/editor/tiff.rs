@@ -0,0 +1,142 @@
+use std::io::{Cursor, Seek, Write};
+
+use glycin_utils::*;
+use tiff::encoder::colortype::{self, ColorType};
+use tiff::encoder::{Rational, TiffEncoder, TiffValue};
+use tiff::tags::{ResolutionUnit, Tag};
+
+/// Create a TIFF with one page for each frame
+///
+/// The pages are stored as a sequence of IFDs and can have different
+/// dimensions.
+pub fn create(new_image: NewImage) -> Result<EncodedImage, ProcessError> {
+    if new_image.frames.is_empty() {
+        return Err(ProcessError::expected(&"No frames to encode"));
+    }
+
+    if new_image.frames.iter().any(|frame| frame.delay.is_some()) {
+        return Err(ProcessError::expected(
+            &"TIFF doesn't support delays between frames",
+        ));
+    }
+
+    let mut out_buf = Cursor::new(Vec::new());
+    {
+        let mut encoder = TiffEncoder::new(&mut out_buf).expected_error()?;
+
+        let resolution_dpi = new_image.image_info.resolution_dpi;
+
+        for frame in new_image.frames {
+            write_page(&mut encoder, frame, resolution_dpi)?;
+        }
+    }
+
+    let data = BinaryData::from_data(out_buf.into_inner()).expected_error()?;
+    Ok(EncodedImage::new(data))
+}
+
+fn write_page<W: Write + Seek>(
+    encoder: &mut TiffEncoder<W>,
+    frame: Frame,
+    resolution_dpi: Option<(f64, f64)>,
+) -> Result<(), ProcessError> {
+    // TIFF has no color type for grayscale with alpha in the encoder
+    let memory_format = (MemoryFormatSelection::G8
+        | MemoryFormatSelection::R8g8b8
+        | MemoryFormatSelection::R8g8b8a8
+        | MemoryFormatSelection::G16
+        | MemoryFormatSelection::R16g16b16
+        | MemoryFormatSelection::R16g16b16a16)
+        .best_format_for(frame.memory_format)
+        .internal_error()?;
+
+    let v = frame.texture.get_full().expected_error()?;
+    let img_buf = ImgBuf::Vec(v);
+    let (frame, img_buf) =
+        glycin_utils::editing::change_memory_format(img_buf, frame, memory_format)
+            .expected_error()?;
+
+    let icc_profile = frame.details.color_icc_profile.as_ref().and_then(|x| {
+        x.get_full()
+            .inspect_err(|err| log::error!("Can't read the ICC profile {err}"))
+            .ok()
+    });
+
+    let page = Page {
+        width: frame.width,
+        height: frame.height,
+        icc_profile: icc_profile.as_deref(),
+        resolution_dpi,
+    };
+
+    match memory_format {
+        MemoryFormat::G8 => write_image::<_, colortype::Gray8>(encoder, &page, &img_buf),
+        MemoryFormat::R8g8b8 => write_image::<_, colortype::RGB8>(encoder, &page, &img_buf),
+        MemoryFormat::R8g8b8a8 => write_image::<_, colortype::RGBA8>(encoder, &page, &img_buf),
+        MemoryFormat::G16 => {
+            write_image::<_, colortype::Gray16>(encoder, &page, &u16_samples(&img_buf))
+        }
+        MemoryFormat::R16g16b16 => {
+            write_image::<_, colortype::RGB16>(encoder, &page, &u16_samples(&img_buf))
+        }
+        MemoryFormat::R16g16b16a16 => {
+            write_image::<_, colortype::RGBA16>(encoder, &page, &u16_samples(&img_buf))
+        }
+        memory_format => Err(ProcessError::expected(&format!(
+            "Unsupported memory format for TIFF: {memory_format:?}"
+        ))),
+    }
+}
+
+struct Page<'a> {
+    width: u32,
+    height: u32,
+    icc_profile: Option<&'a [u8]>,
+    resolution_dpi: Option<(f64, f64)>,
+}
+
+fn write_image<W: Write + Seek, C: ColorType>(
+    encoder: &mut TiffEncoder<W>,
+    page: &Page,
+    data: &[C::Inner],
+) -> Result<(), ProcessError>
+where
+    [C::Inner]: TiffValue,
+{
+    let mut image = encoder
+        .new_image::<C>(page.width, page.height)
+        .expected_error()?;
+
+    if let Some(icc_profile) = page.icc_profile {
+        image
+            .encoder()
+            .write_tag(Tag::IccProfile, icc_profile)
+            .expected_error()?;
+    }
+
+    if let Some((dpi_x, dpi_y)) = page.resolution_dpi {
+        image.resolution_unit(ResolutionUnit::Inch);
+        image.x_resolution(rational(dpi_x)?);
+        image.y_resolution(rational(dpi_y)?);
+    }
+
+    image.write_data(data).expected_error()
+}
+
+/// Resolution as a rational with two decimal places if it fits
+fn rational(value: f64) -> Result<Rational, ProcessError> {
+    match super::resolution_value(value * 100.) {
+        Ok(n) => Ok(Rational { n, d: 100 }),
+        Err(_) => Ok(Rational {
+            n: super::resolution_value(value)?,
+            d: 1,
+        }),
+    }
+}
+
+/// Samples of 16-bit memory formats, which are stored in native endianness
+fn u16_samples(data: &[u8]) -> Vec<u16> {
+    data.chunks_exact(2)
+        .map(|x| u16::from_ne_bytes([x[0], x[1]]))
+        .collect()
+}
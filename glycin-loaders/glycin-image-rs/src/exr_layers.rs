@@ -0,0 +1,239 @@
+//! Layers of multi-layer OpenEXR images
+//!
+//! Renderers store additional passes like depth, normals, or light groups
+//! (AOVs) as channels with a layer prefix, like `diffuse.R` or `depth.Z`.
+//! Multi-part files additionally name each part, which is used as a prefix
+//! for all channels of the part.
+
+use std::io::Cursor;
+
+use exr::prelude::{FlatSamples, ReadChannels, ReadLayers};
+use glycin_utils::safe_math::*;
+use glycin_utils::*;
+
+/// Layers with their channels in the order of the file
+pub fn layers(data: &[u8]) -> Option<Vec<LayerInfo>> {
+    let meta_data = exr::meta::MetaData::read_from_buffered(Cursor::new(data), false)
+        .inspect_err(|err| log::debug!("exr: Failed to read headers: {err}"))
+        .ok()?;
+
+    let mut layers: Vec<LayerInfo> = Vec::new();
+    for header in &meta_data.headers {
+        let part = header.own_attributes.layer_name.as_ref();
+        for channel in &header.channels.list {
+            let name = full_name(part, &channel.name);
+            let (layer, channel) = split_name(&name);
+
+            match layers.iter_mut().find(|x| x.name == layer) {
+                Some(layer) => layer.channels.push(channel.to_string()),
+                None => layers.push(LayerInfo::new(layer.to_string(), vec![channel.to_string()])),
+            }
+        }
+    }
+
+    Some(layers)
+}
+
+/// Decode the layer with the given name as float RGB or RGBA frame
+///
+/// The `A` channel is used as alpha if the layer has it. A single channel
+/// besides `A`, like `Z` or the luminance `Y`, is returned as gray via all
+/// color channels. Otherwise, the `R`, `G`, and `B`, the `X`, `Y`, and `Z`
+/// of normals or positions, or the `U`, `V`, and `W` channels are used as
+/// color. For other channel names, the first three channels besides `A` are
+/// returned as color.
+pub fn frame(data: &[u8], layer: &str) -> Result<Frame, ProcessError> {
+    let image = exr::prelude::read()
+        .no_deep_data()
+        .largest_resolution_level()
+        .all_channels()
+        .all_layers()
+        .all_attributes()
+        .from_buffered(Cursor::new(data))
+        .expected_error()?;
+
+    let mut size = None;
+    let mut channels = Vec::new();
+    for exr_layer in &image.layer_data {
+        let part = exr_layer.attributes.layer_name.as_ref();
+        for channel in &exr_layer.channel_data.list {
+            let name = full_name(part, &channel.name);
+            let (layer_name, channel_name) = split_name(&name);
+            if layer_name != layer {
+                continue;
+            }
+
+            if *size.get_or_insert(exr_layer.size) != exr_layer.size {
+                return Err(ProcessError::UnsupportedImageFormat(format!(
+                    "Layer '{layer}' is stored in parts of different sizes"
+                )));
+            }
+
+            channels.push((channel_name.to_string(), &channel.sample_data));
+        }
+    }
+
+    let size = size.ok_or_else(|| ProcessError::expected(&format!("No layer '{layer}'")))?;
+    let width = size.width().try_u32()?;
+    let height = size.height().try_u32()?;
+    let n_pixels = size.area();
+
+    if channels
+        .iter()
+        .any(|(_, samples)| samples.len() != n_pixels)
+    {
+        return Err(ProcessError::UnsupportedImageFormat(format!(
+            "Layer '{layer}' has subsampled channels"
+        )));
+    }
+
+    let find = |name: &str| channels.iter().position(|(x, _)| x == name);
+    let alpha = find("A");
+    let others = (0..channels.len())
+        .filter(|x| Some(*x) != alpha)
+        .collect::<Vec<_>>();
+    let named_colors = [["R", "G", "B"], ["X", "Y", "Z"], ["U", "V", "W"]]
+        .into_iter()
+        .find(|names| names.iter().any(|x| find(x).is_some()));
+
+    let colors = if let [gray] = others[..] {
+        [Some(gray); 3]
+    } else if let Some(names) = named_colors {
+        names.map(find)
+    } else {
+        [0, 1, 2].map(|i| others.get(i).copied())
+    };
+
+    let memory_format = if alpha.is_some() {
+        MemoryFormat::R32g32b32a32Float
+    } else {
+        MemoryFormat::R32g32b32Float
+    };
+    let sources = colors
+        .into_iter()
+        .chain(alpha.map(Some))
+        .collect::<Vec<_>>();
+
+    let mut memory = SharedMemory::new(n_pixels.smul(memory_format.n_bytes().usize())?.try_u64()?)
+        .expected_error()?;
+
+    for (i, pixel) in memory
+        .chunks_exact_mut(memory_format.n_bytes().usize())
+        .enumerate()
+    {
+        for (sample, source) in pixel.chunks_exact_mut(4).zip(&sources) {
+            let value = source
+                .and_then(|x| channels.get(x))
+                .map(|(_, samples)| samples.value_by_flat_index(i).to_f32())
+                .unwrap_or_default();
+            sample.copy_from_slice(&value.to_ne_bytes());
+        }
+    }
+
+    let mut frame = Frame::new(width, height, memory_format, memory.into_binary_data())?;
+
+    let half_float = sources
+        .iter()
+        .flatten()
+        .filter_map(|x| channels.get(*x))
+        .all(|(_, samples)| matches!(samples, FlatSamples::F16(_)));
+    frame.details.info_bit_depth = Some(if half_float { 16 } else { 32 });
+    frame.details.info_alpha_channel = Some(alpha.is_some());
+
+    Ok(frame)
+}
+
+fn full_name(part: Option<&exr::prelude::Text>, channel: &exr::prelude::Text) -> String {
+    match part {
+        Some(part) => format!("{part}.{channel}"),
+        None => channel.to_string(),
+    }
+}
+
+/// Layer and channel name of a full channel name
+///
+/// Channels without layer prefix belong to the layer with the empty name.
+fn split_name(name: &str) -> (&str, &str) {
+    name.rsplit_once('.').unwrap_or(("", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use exr::prelude::*;
+
+    use super::*;
+
+    fn multi_layer_exr() -> Vec<u8> {
+        let size = (2, 1);
+        let channels = AnyChannels::sort(SmallVec::from_vec(vec![
+            AnyChannel::new("R", FlatSamples::F32(vec![0.5, 0.25])),
+            AnyChannel::new("G", FlatSamples::F32(vec![0.5, 0.25])),
+            AnyChannel::new("B", FlatSamples::F32(vec![0.5, 0.25])),
+            AnyChannel::new("depth.Z", FlatSamples::F32(vec![10., 20.])),
+            AnyChannel::new("N.X", FlatSamples::F16(vec![f16::from_f32(1.); 2])),
+            AnyChannel::new("N.Y", FlatSamples::F16(vec![f16::from_f32(0.); 2])),
+            AnyChannel::new("N.Z", FlatSamples::F16(vec![f16::from_f32(-1.); 2])),
+            AnyChannel::new("P.A", FlatSamples::F32(vec![1., 0.5])),
+            AnyChannel::new("P.X", FlatSamples::F32(vec![1., 4.])),
+            AnyChannel::new("P.Y", FlatSamples::F32(vec![2., 5.])),
+            AnyChannel::new("P.Z", FlatSamples::F32(vec![3., 6.])),
+        ]));
+        let image = exr::prelude::Image::from_channels(size, channels);
+
+        let mut data = Cursor::new(Vec::new());
+        image.write().to_buffered(&mut data).unwrap();
+        data.into_inner()
+    }
+
+    fn samples(frame: &Frame) -> Vec<f32> {
+        frame
+            .texture
+            .get_full()
+            .unwrap()
+            .chunks_exact(4)
+            .map(|x| f32::from_ne_bytes(x.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn list_layers() {
+        let data = multi_layer_exr();
+
+        assert_eq!(
+            layers(&data).unwrap(),
+            vec![
+                LayerInfo::new(String::new(), vec!["B".into(), "G".into(), "R".into()]),
+                LayerInfo::new("N".into(), vec!["X".into(), "Y".into(), "Z".into()]),
+                LayerInfo::new(
+                    "P".into(),
+                    vec!["A".into(), "X".into(), "Y".into(), "Z".into()]
+                ),
+                LayerInfo::new("depth".into(), vec!["Z".into()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_layers() {
+        let data = multi_layer_exr();
+
+        let frame = frame(&data, "depth").unwrap();
+        assert_eq!(frame.memory_format, MemoryFormat::R32g32b32Float);
+        assert_eq!(samples(&frame), [10., 10., 10., 20., 20., 20.]);
+        assert_eq!(frame.details.info_bit_depth, Some(32));
+
+        let frame = super::frame(&data, "N").unwrap();
+        assert_eq!(samples(&frame), [1., 0., -1., 1., 0., -1.]);
+        assert_eq!(frame.details.info_bit_depth, Some(16));
+
+        // Alpha is sorted before the other channels of the layer
+        let frame = super::frame(&data, "P").unwrap();
+        assert_eq!(frame.memory_format, MemoryFormat::R32g32b32a32Float);
+        assert_eq!(samples(&frame), [1., 2., 3., 1., 4., 5., 6., 0.5]);
+
+        let frame = super::frame(&data, "").unwrap();
+        assert_eq!(samples(&frame), [0.5, 0.5, 0.5, 0.25, 0.25, 0.25]);
+
+        assert!(super::frame(&data, "specular").is_err());
+    }
+}
@@ -0,0 +1,140 @@
+//! Blend and dispose methods of animation frames
+//!
+//! Frames are returned composited onto the full canvas. The methods of the
+//! source frames are reported as information about the file. image-rs
+//! doesn't expose them, so they are read via the `gif` and `png` decoders
+//! that image-rs uses internally. WebP frames are composited by
+//! [`AnimatedWebP`], which reports the methods itself.
+
+use glycin_utils::{FrameBlend, FrameDispose};
+
+use crate::animated_webp::AnimatedWebP;
+
+pub type Methods = (FrameBlend, FrameDispose);
+
+/// Methods of all animation frames in order
+///
+/// Returns an empty list if the file can't be parsed.
+pub fn frame_methods(mime_type: &str, data: &[u8]) -> Vec<Methods> {
+    let methods = match mime_type {
+        "image/gif" => gif(data),
+        "image/png" | "image/apng" => apng(data),
+        "image/webp" => AnimatedWebP::new(data.to_vec())
+            .ok()
+            .map(|x| x.frame_methods()),
+        _ => None,
+    };
+
+    methods.unwrap_or_default()
+}
+
+fn gif(data: &[u8]) -> Option<Vec<Methods>> {
+    let mut decoder = gif::DecodeOptions::new().read_info(data).ok()?;
+    let mut methods = Vec::new();
+
+    while let Some(frame) = decoder.next_frame_info().ok()? {
+        let dispose = match frame.dispose {
+            gif::DisposalMethod::Background => FrameDispose::Background,
+            gif::DisposalMethod::Previous => FrameDispose::Previous,
+            gif::DisposalMethod::Any | gif::DisposalMethod::Keep => FrameDispose::None,
+        };
+        // GIF frames are always drawn over the canvas
+        methods.push((FrameBlend::Over, dispose));
+    }
+
+    Some(methods)
+}
+
+fn apng(data: &[u8]) -> Option<Vec<Methods>> {
+    let mut reader = png::Decoder::new(std::io::Cursor::new(data))
+        .read_info()
+        .ok()?;
+    let n_frames = reader.info().animation_control()?.num_frames;
+
+    let methods_png = |frame_control: &png::FrameControl| {
+        let blend = match frame_control.blend_op {
+            png::BlendOp::Over => FrameBlend::Over,
+            png::BlendOp::Source => FrameBlend::Source,
+        };
+        let dispose = match frame_control.dispose_op {
+            png::DisposeOp::Background => FrameDispose::Background,
+            png::DisposeOp::Previous => FrameDispose::Previous,
+            png::DisposeOp::None => FrameDispose::None,
+        };
+        (blend, dispose)
+    };
+
+    let mut methods = Vec::new();
+
+    // The default image is only part of the animation if it has frame control
+    if let Some(frame_control) = reader.info().frame_control() {
+        methods.push(methods_png(frame_control));
+    }
+
+    while methods.len() < usize::try_from(n_frames).ok()? {
+        methods.push(methods_png(reader.next_frame_info().ok()?));
+    }
+
+    Some(methods)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gif_test() {
+        let mut data = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut data, 1, 1, &[0; 6]).unwrap();
+            for dispose in [
+                gif::DisposalMethod::Background,
+                gif::DisposalMethod::Previous,
+                gif::DisposalMethod::Keep,
+            ] {
+                let mut frame = gif::Frame::from_indexed_pixels(1, 1, vec![0], None);
+                frame.dispose = dispose;
+                encoder.write_frame(&frame).unwrap();
+            }
+        }
+
+        assert_eq!(
+            frame_methods("image/gif", &data),
+            [
+                (FrameBlend::Over, FrameDispose::Background),
+                (FrameBlend::Over, FrameDispose::Previous),
+                (FrameBlend::Over, FrameDispose::None),
+            ]
+        );
+    }
+
+    #[test]
+    fn apng_test() {
+        let mut data = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut data, 1, 1);
+            encoder.set_color(png::ColorType::Grayscale);
+            encoder.set_animated(3, 0).unwrap();
+            let mut writer = encoder.write_header().unwrap();
+            for (dispose, blend) in [
+                (png::DisposeOp::None, png::BlendOp::Source),
+                (png::DisposeOp::Background, png::BlendOp::Over),
+                (png::DisposeOp::Previous, png::BlendOp::Source),
+            ] {
+                writer.set_dispose_op(dispose).unwrap();
+                writer.set_blend_op(blend).unwrap();
+                writer.write_image_data(&[0]).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+
+        assert_eq!(
+            frame_methods("image/png", &data),
+            [
+                (FrameBlend::Source, FrameDispose::None),
+                (FrameBlend::Over, FrameDispose::Background),
+                (FrameBlend::Source, FrameDispose::Previous),
+            ]
+        );
+    }
+}
@@ -0,0 +1,1899 @@
+//! Decoder for JPEG variants that image-rs doesn't support
+//!
+//! These are JPEGs with arithmetic coding and JPEGs with 12-bit samples,
+//! both with sequential or progressive DCT. Lossless and hierarchical JPEGs
+//! are not handled here. For progressive JPEGs that image-rs supports, this
+//! decoder is used to get a preview after each scan.
+
+use glycin_utils::safe_math::*;
+use glycin_utils::*;
+
+type Result<T> = std::result::Result<T, ProcessError>;
+
+/// Position of the coefficients in zigzag order
+const NATURAL_ORDER: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+const SOF_EXTENDED_HUFFMAN: u8 = 0xC1;
+const SOF_PROGRESSIVE_HUFFMAN: u8 = 0xC2;
+const SOF_EXTENDED_ARITHMETIC: u8 = 0xC9;
+const SOF_PROGRESSIVE_ARITHMETIC: u8 = 0xCA;
+const SOF_BASELINE: u8 = 0xC0;
+const DHT: u8 = 0xC4;
+const DAC: u8 = 0xCC;
+const DQT: u8 = 0xDB;
+const DRI: u8 = 0xDD;
+const SOS: u8 = 0xDA;
+const EOI: u8 = 0xD9;
+const APP2: u8 = 0xE2;
+const APP14: u8 = 0xEE;
+
+/// Returns `true` for JPEGs that should be decoded with this decoder
+pub fn is_applicable(data: &[u8]) -> bool {
+    let Ok(segments) = Segments::new(data) else {
+        return false;
+    };
+
+    for (marker, payload) in segments {
+        match marker {
+            SOF_EXTENDED_ARITHMETIC | SOF_PROGRESSIVE_ARITHMETIC => return true,
+            SOF_EXTENDED_HUFFMAN | SOF_PROGRESSIVE_HUFFMAN => {
+                return payload.first() == Some(&12);
+            }
+            0xC0 | 0xC3 | 0xC5..=0xC7 | 0xCB | 0xCD..=0xCF | SOS => return false,
+            _ => {}
+        }
+    }
+
+    false
+}
+
+/// Returns `true` for 8-bit progressive JPEGs without arithmetic coding
+///
+/// These are decoded by image-rs, except for requests of intermediate frames.
+/// CMYK JPEGs are always left to image-rs.
+pub fn is_progressive(data: &[u8]) -> bool {
+    let Ok(segments) = Segments::new(data) else {
+        return false;
+    };
+
+    for (marker, payload) in segments {
+        match marker {
+            SOF_PROGRESSIVE_HUFFMAN => return matches!(payload, [8, _, _, _, _, 1 | 3, ..]),
+            0xC0 | 0xC1 | 0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF | SOS => return false,
+            _ => {}
+        }
+    }
+
+    false
+}
+
+pub struct JpegFallback {
+    data: Vec<u8>,
+    frame: FrameHeader,
+    icc_profile: Option<Vec<u8>>,
+    decoded: bool,
+}
+
+impl JpegFallback {
+    pub fn new(data: Vec<u8>) -> Result<Self> {
+        let mut frame = None;
+        let mut adobe_transform = None;
+        let mut icc_chunks = Vec::new();
+
+        for (marker, payload) in Segments::new(&data)? {
+            match marker {
+                SOF_BASELINE
+                | SOF_EXTENDED_HUFFMAN
+                | SOF_PROGRESSIVE_HUFFMAN
+                | SOF_EXTENDED_ARITHMETIC
+                | SOF_PROGRESSIVE_ARITHMETIC => {
+                    frame = Some(FrameHeader::new(marker, payload)?);
+                }
+                APP2 => {
+                    if let Some([n, _, chunk @ ..]) = payload.strip_prefix(b"ICC_PROFILE\0") {
+                        icc_chunks.push((*n, chunk.to_vec()));
+                    }
+                }
+                APP14 => {
+                    if let Some(transform) = payload.strip_prefix(b"Adobe").and_then(|x| x.get(6)) {
+                        adobe_transform = Some(*transform);
+                    }
+                }
+                SOS => break,
+                _ => {}
+            }
+        }
+
+        let mut frame = frame.ok_or_else(|| ProcessError::expected(&"JPEG without frame"))?;
+        frame.adobe_transform = adobe_transform;
+
+        icc_chunks.sort_by_key(|(n, _)| *n);
+        let icc_profile = (!icc_chunks.is_empty())
+            .then(|| icc_chunks.into_iter().flat_map(|(_, data)| data).collect());
+
+        Ok(Self {
+            data,
+            frame,
+            icc_profile,
+            decoded: false,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.frame.width.into()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.frame.height.into()
+    }
+
+    pub fn bit_depth(&self) -> u8 {
+        self.frame.precision
+    }
+
+    pub fn is_grayscale(&self) -> bool {
+        self.frame.components.len() == 1
+    }
+
+    /// ICC profile as embedded, also for CMYK images
+    pub fn icc_profile(&self) -> Option<&[u8]> {
+        self.icc_profile.as_deref()
+    }
+
+    pub fn details(&self) -> Result<FrameDetails> {
+        let mut details = FrameDetails::default();
+
+        // CMYK profiles don't apply to the converted RGB data
+        if self.frame.components.len() != 4 {
+            details.color_icc_profile = self
+                .icc_profile
+                .clone()
+                .map(BinaryData::from_data)
+                .transpose()
+                .expected_error()?;
+        }
+        if self.frame.precision != 8 {
+            details.info_bit_depth = Some(self.frame.precision);
+        }
+        details.info_grayscale = Some(self.is_grayscale());
+
+        Ok(details)
+    }
+
+    /// Decode the image
+    ///
+    /// The image is only returned once.
+    pub fn frame(&mut self) -> Result<Frame> {
+        self.decode_frame(None)
+    }
+
+    /// Decode the image and pass a preview to `progress` after each scan
+    ///
+    /// The result after the last scan is only returned as the final image.
+    /// Sequential JPEGs only have one scan per component.
+    pub fn frame_progressive(&mut self, progress: &mut dyn FnMut(Frame)) -> Result<Frame> {
+        self.decode_frame(Some(progress))
+    }
+
+    fn decode_frame(&mut self, mut progress: Option<&mut dyn FnMut(Frame)>) -> Result<Frame> {
+        if self.decoded {
+            return Err(ProcessError::NoMoreFrames);
+        }
+        self.decoded = true;
+
+        let mut decoder = Decoder::new(self.frame.clone())?;
+        decoder.decode(&self.data, &mut |decoder| {
+            if let Some(progress) = &mut progress {
+                progress(self.output_frame(decoder)?);
+            }
+            Ok(())
+        })?;
+
+        self.output_frame(&decoder)
+    }
+
+    fn output_frame(&self, decoder: &Decoder) -> Result<Frame> {
+        let (memory_format, memory) = decoder.output()?;
+        let mut frame = Frame::new(
+            self.width(),
+            self.height(),
+            memory_format,
+            memory.into_binary_data(),
+        )?;
+        frame.details = self.details()?;
+
+        Ok(frame)
+    }
+}
+
+/// Iterator over marker segments up to the first scan
+///
+/// For scans, only the header is returned as payload.
+struct Segments<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Segments<'a> {
+    fn new(data: &'a [u8]) -> Result<Self> {
+        if !data.starts_with(&[0xFF, 0xD8]) {
+            return Err(ProcessError::expected(&"Not a JPEG"));
+        }
+
+        Ok(Self { data, pos: 2 })
+    }
+}
+
+impl<'a> Iterator for Segments<'a> {
+    type Item = (u8, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Skip fill bytes
+        while self.data.get(self.pos) == Some(&0xFF) && self.data.get(self.pos + 1) == Some(&0xFF) {
+            self.pos += 1;
+        }
+
+        let [0xFF, marker, ..] = *self.data.get(self.pos..)? else {
+            return None;
+        };
+
+        if marker == EOI {
+            return None;
+        }
+
+        let len = usize::from(u16::from_be_bytes([
+            *self.data.get(self.pos + 2)?,
+            *self.data.get(self.pos + 3)?,
+        ]));
+        let payload = self.data.get(self.pos + 4..self.pos + 2 + len.max(2))?;
+        self.pos += 2 + len;
+
+        Some((marker, payload))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FrameHeader {
+    progressive: bool,
+    arithmetic: bool,
+    precision: u8,
+    width: u16,
+    height: u16,
+    components: Vec<ComponentInfo>,
+    adobe_transform: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ComponentInfo {
+    id: u8,
+    h: usize,
+    v: usize,
+    quant_table: usize,
+}
+
+impl FrameHeader {
+    fn new(marker: u8, payload: &[u8]) -> Result<Self> {
+        let [precision, h0, h1, w0, w1, n, ref components @ ..] = *payload else {
+            return Err(ProcessError::expected(&"JPEG frame header too short"));
+        };
+
+        let width = u16::from_be_bytes([w0, w1]);
+        let height = u16::from_be_bytes([h0, h1]);
+
+        if width == 0 || height == 0 {
+            return Err(ProcessError::UnsupportedImageFormat(String::from(
+                "JPEGs with the height defined by a DNL marker",
+            )));
+        }
+
+        if precision != 8 && precision != 12 {
+            return Err(ProcessError::UnsupportedImageFormat(format!(
+                "JPEG with {precision} bit precision"
+            )));
+        }
+
+        if !matches!(n, 1 | 3 | 4) {
+            return Err(ProcessError::UnsupportedImageFormat(format!(
+                "JPEG with {n} components"
+            )));
+        }
+
+        let components = components
+            .chunks_exact(3)
+            .take(n.into())
+            .map(|x| {
+                let component = ComponentInfo {
+                    id: x[0],
+                    h: usize::from(x[1] >> 4),
+                    v: usize::from(x[1] & 0xF),
+                    quant_table: usize::from(x[2]),
+                };
+                if !(1..=4).contains(&component.h)
+                    || !(1..=4).contains(&component.v)
+                    || component.quant_table > 3
+                {
+                    return Err(ProcessError::expected(&"Invalid JPEG component"));
+                }
+                Ok(component)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if components.len() != usize::from(n) {
+            return Err(ProcessError::expected(&"JPEG frame header too short"));
+        }
+
+        Ok(Self {
+            progressive: matches!(marker, SOF_PROGRESSIVE_HUFFMAN | SOF_PROGRESSIVE_ARITHMETIC),
+            arithmetic: matches!(marker, SOF_EXTENDED_ARITHMETIC | SOF_PROGRESSIVE_ARITHMETIC),
+            precision,
+            width,
+            height,
+            components,
+            adobe_transform: None,
+        })
+    }
+
+    fn h_max(&self) -> usize {
+        self.components.iter().map(|x| x.h).max().unwrap_or(1)
+    }
+
+    fn v_max(&self) -> usize {
+        self.components.iter().map(|x| x.v).max().unwrap_or(1)
+    }
+
+    fn mcus_x(&self) -> usize {
+        usize::from(self.width).div_ceil(8 * self.h_max())
+    }
+
+    fn mcus_y(&self) -> usize {
+        usize::from(self.height).div_ceil(8 * self.v_max())
+    }
+}
+
+/// Coefficients of a component
+struct Component {
+    info: ComponentInfo,
+    /// Number of blocks that contain image data
+    blocks_x: usize,
+    blocks_y: usize,
+    /// Number of blocks including the padding to full MCUs
+    stride: usize,
+    coefficients: Vec<[i16; 64]>,
+}
+
+impl Component {
+    fn block_mut(&mut self, x: usize, y: usize) -> Result<&mut [i16; 64]> {
+        self.coefficients
+            .get_mut(y * self.stride + x)
+            .ok_or(ProcessError::ConversionTooLargerError)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ScanComponent {
+    /// Index in the frame
+    index: usize,
+    dc_table: usize,
+    ac_table: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScanKind {
+    Sequential,
+    DcFirst,
+    DcRefine,
+    AcFirst,
+    AcRefine,
+}
+
+#[derive(Debug, Clone)]
+struct Scan {
+    components: Vec<ScanComponent>,
+    kind: ScanKind,
+    ss: usize,
+    se: usize,
+    al: u8,
+}
+
+struct Decoder {
+    frame: FrameHeader,
+    components: Vec<Component>,
+    quant_tables: [[u16; 64]; 4],
+    dc_tables: [Option<HuffmanTable>; 4],
+    ac_tables: [Option<HuffmanTable>; 4],
+    conditioning: Conditioning,
+    restart_interval: usize,
+}
+
+impl Decoder {
+    fn new(frame: FrameHeader) -> Result<Self> {
+        let h_max = frame.h_max();
+        let v_max = frame.v_max();
+        let mcus_x = frame.mcus_x();
+        let mcus_y = frame.mcus_y();
+
+        let components = frame
+            .components
+            .iter()
+            .map(|info| {
+                let width = usize::from(frame.width).smul(info.h)?.div_ceil(h_max);
+                let height = usize::from(frame.height).smul(info.v)?.div_ceil(v_max);
+                let stride = mcus_x.smul(info.h)?;
+                let rows = mcus_y.smul(info.v)?;
+
+                Ok(Component {
+                    info: *info,
+                    blocks_x: width.div_ceil(8),
+                    blocks_y: height.div_ceil(8),
+                    stride,
+                    coefficients: vec![[0; 64]; stride.smul(rows)?],
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            frame,
+            components,
+            quant_tables: [[1; 64]; 4],
+            dc_tables: Default::default(),
+            ac_tables: Default::default(),
+            conditioning: Conditioning::default(),
+            restart_interval: 0,
+        })
+    }
+
+    /// Read all tables and scans
+    ///
+    /// Before each scan except the first, `scan_done` is called with the
+    /// coefficients decoded so far.
+    fn decode(
+        &mut self,
+        data: &[u8],
+        scan_done: &mut dyn FnMut(&Self) -> Result<()>,
+    ) -> Result<()> {
+        let mut pos = 2;
+        let mut first_scan = true;
+
+        loop {
+            // Skip fill bytes
+            while data.get(pos) == Some(&0xFF) && data.get(pos + 1) == Some(&0xFF) {
+                pos += 1;
+            }
+
+            // Missing EOI markers are common for truncated files
+            let Some([0xFF, marker, l0, l1]) = data.get(pos..pos + 4) else {
+                return Ok(());
+            };
+            if *marker == EOI {
+                return Ok(());
+            }
+
+            let len = usize::from(u16::from_be_bytes([*l0, *l1]));
+            let payload = data
+                .get(pos + 4..pos + 2 + len.max(2))
+                .ok_or_else(|| ProcessError::expected(&"Unexpected end of JPEG data"))?;
+            pos += 2 + len;
+
+            match *marker {
+                DQT => self.read_quant_tables(payload)?,
+                DHT => self.read_huffman_tables(payload)?,
+                DAC => self.conditioning.read(payload)?,
+                DRI => {
+                    let [r0, r1] = *payload else {
+                        return Err(ProcessError::expected(&"Invalid JPEG restart interval"));
+                    };
+                    self.restart_interval = usize::from(u16::from_be_bytes([r0, r1]));
+                }
+                SOS => {
+                    if !first_scan {
+                        scan_done(self)?;
+                    }
+                    first_scan = false;
+
+                    let scan = self.read_scan_header(payload)?;
+                    let (segments, end) = entropy_coded_segments(data, pos);
+                    self.decode_scan(&scan, segments)?;
+                    pos = end;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn read_quant_tables(&mut self, mut payload: &[u8]) -> Result<()> {
+        while let [pq_tq, rest @ ..] = payload {
+            let index = usize::from(pq_tq & 0xF);
+            let table = self
+                .quant_tables
+                .get_mut(index)
+                .ok_or_else(|| ProcessError::expected(&"Invalid JPEG quantization table"))?;
+
+            let size = if pq_tq >> 4 == 0 { 1 } else { 2 };
+            let values = rest
+                .get(..64 * size)
+                .ok_or_else(|| ProcessError::expected(&"JPEG quantization table too short"))?;
+
+            for (k, value) in values.chunks_exact(size).enumerate() {
+                table[NATURAL_ORDER[k]] = match *value {
+                    [v] => u16::from(v),
+                    [v0, v1] => u16::from_be_bytes([v0, v1]),
+                    _ => return Err(ProcessError::expected(&"Invalid JPEG quantization table")),
+                };
+            }
+
+            payload = &rest[64 * size..];
+        }
+
+        Ok(())
+    }
+
+    fn read_huffman_tables(&mut self, mut payload: &[u8]) -> Result<()> {
+        while let [tc_th, rest @ ..] = payload {
+            let counts: [u8; 16] = rest
+                .get(..16)
+                .and_then(|x| x.try_into().ok())
+                .ok_or_else(|| ProcessError::expected(&"JPEG Huffman table too short"))?;
+            let n_values = counts.iter().map(|x| usize::from(*x)).sum::<usize>();
+            let values = rest
+                .get(16..16 + n_values)
+                .ok_or_else(|| ProcessError::expected(&"JPEG Huffman table too short"))?;
+
+            let table = HuffmanTable::new(counts, values)?;
+            let tables = if tc_th >> 4 == 0 {
+                &mut self.dc_tables
+            } else {
+                &mut self.ac_tables
+            };
+            *tables
+                .get_mut(usize::from(tc_th & 0xF))
+                .ok_or_else(|| ProcessError::expected(&"Invalid JPEG Huffman table"))? =
+                Some(table);
+
+            payload = &rest[16 + n_values..];
+        }
+
+        Ok(())
+    }
+
+    fn read_scan_header(&self, payload: &[u8]) -> Result<Scan> {
+        let invalid = || ProcessError::expected(&"Invalid JPEG scan header");
+
+        let [n, ref rest @ ..] = *payload else {
+            return Err(invalid());
+        };
+        let n = usize::from(n);
+        let (components, parameters) = rest.split_at_checked(n * 2).ok_or_else(invalid)?;
+        let [ss, se, ah_al] = *parameters else {
+            return Err(invalid());
+        };
+
+        let components = components
+            .chunks_exact(2)
+            .map(|x| {
+                let index = self
+                    .frame
+                    .components
+                    .iter()
+                    .position(|c| c.id == x[0])
+                    .ok_or_else(invalid)?;
+                let dc_table = usize::from(x[1] >> 4);
+                let ac_table = usize::from(x[1] & 0xF);
+                if dc_table > 3 || ac_table > 3 {
+                    return Err(invalid());
+                }
+                Ok(ScanComponent {
+                    index,
+                    dc_table,
+                    ac_table,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if components.is_empty() || components.len() > 4 {
+            return Err(invalid());
+        }
+
+        let ss = usize::from(ss);
+        let se = usize::from(se);
+        let ah = ah_al >> 4;
+        let al = ah_al & 0xF;
+
+        let kind = if !self.frame.progressive {
+            ScanKind::Sequential
+        } else if ss == 0 && se == 0 {
+            if ah == 0 {
+                ScanKind::DcFirst
+            } else {
+                ScanKind::DcRefine
+            }
+        } else if ss > 0 && ss <= se && se < 64 && components.len() == 1 {
+            if ah == 0 {
+                ScanKind::AcFirst
+            } else {
+                ScanKind::AcRefine
+            }
+        } else {
+            return Err(invalid());
+        };
+
+        if al > 13 {
+            return Err(invalid());
+        }
+
+        let (ss, se) = if kind == ScanKind::Sequential {
+            (0, 63)
+        } else {
+            (ss, se)
+        };
+
+        Ok(Scan {
+            components,
+            kind,
+            ss,
+            se,
+            al,
+        })
+    }
+
+    fn decode_scan(&mut self, scan: &Scan, segments: Vec<&[u8]>) -> Result<()> {
+        if self.frame.arithmetic {
+            let mut entropy = ArithmeticDecoder::new(self.conditioning, self.frame.progressive);
+            self.decode_blocks(&mut entropy, scan, segments)
+        } else {
+            let dc_tables = self.dc_tables.clone();
+            let ac_tables = self.ac_tables.clone();
+            let mut entropy = HuffmanDecoder::new(&dc_tables, &ac_tables);
+            self.decode_blocks(&mut entropy, scan, segments)
+        }
+    }
+
+    fn decode_blocks<'a>(
+        &mut self,
+        entropy: &mut impl EntropyDecoder<'a>,
+        scan: &Scan,
+        segments: Vec<&'a [u8]>,
+    ) -> Result<()> {
+        let mut segments = segments.into_iter();
+        entropy.restart(segments.next().unwrap_or_default(), scan);
+        let restart_interval = self.restart_interval;
+        let mut mcus_to_go = restart_interval;
+
+        let mut next_mcu = |entropy: &mut dyn EntropyDecoder<'a>| {
+            if restart_interval > 0 {
+                if mcus_to_go == 0 {
+                    entropy.restart(segments.next().unwrap_or_default(), scan);
+                    mcus_to_go = restart_interval;
+                }
+                mcus_to_go -= 1;
+            }
+        };
+
+        if let [scan_component] = scan.components[..] {
+            // Non-interleaved scans only contain the blocks with image data
+            let component = &mut self.components[scan_component.index];
+
+            for y in 0..component.blocks_y {
+                for x in 0..component.blocks_x {
+                    next_mcu(entropy);
+                    let block = component.block_mut(x, y)?;
+                    entropy.decode_block(block, 0, scan_component, scan)?;
+                }
+            }
+        } else {
+            for mcu_y in 0..self.frame.mcus_y() {
+                for mcu_x in 0..self.frame.mcus_x() {
+                    next_mcu(entropy);
+                    for (n, scan_component) in scan.components.iter().enumerate() {
+                        let component = &mut self.components[scan_component.index];
+                        let (h, v) = (component.info.h, component.info.v);
+                        for y in mcu_y * v..(mcu_y + 1) * v {
+                            for x in mcu_x * h..(mcu_x + 1) * h {
+                                let block = component.block_mut(x, y)?;
+                                entropy.decode_block(block, n, *scan_component, scan)?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Convert the coefficients into the output image
+    fn output(&self) -> Result<(MemoryFormat, SharedMemory)> {
+        let precision = self.frame.precision;
+        let max = (1_i32 << precision) - 1;
+
+        let planes = self
+            .components
+            .iter()
+            .map(|component| {
+                let quant_table = &self.quant_tables[component.info.quant_table];
+                self.plane(component, quant_table)
+            })
+            .collect::<Vec<_>>();
+
+        let width = usize::from(self.frame.width);
+        let height = usize::from(self.frame.height);
+        let h_max = self.frame.h_max();
+        let v_max = self.frame.v_max();
+
+        let samplers = self
+            .components
+            .iter()
+            .zip(&planes)
+            .map(|(component, plane)| {
+                Ok(Sampler {
+                    plane,
+                    stride: component.stride * 8,
+                    width: width.smul(component.info.h)?.div_ceil(h_max),
+                    height: height.smul(component.info.v)?.div_ceil(v_max),
+                    scale_x: component.info.h as f32 / h_max as f32,
+                    scale_y: component.info.v as f32 / v_max as f32,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let is_rgb = self.frame.adobe_transform == Some(0)
+            || self.frame.components.iter().map(|x| x.id).eq(*b"RGB");
+        let center = (1 << (precision - 1)) as f32;
+        let max_f = max as f32;
+
+        let to_rgb = |[y, cb, cr]: [f32; 3]| {
+            let cb = cb - center;
+            let cr = cr - center;
+            [
+                y + 1.402 * cr,
+                y - 0.344_136 * cb - 0.714_136 * cr,
+                y + 1.772 * cb,
+            ]
+        };
+
+        let n_channels = if samplers.len() == 1 { 1 } else { 3 };
+        let memory_format = match (n_channels, precision) {
+            (1, 8) => MemoryFormat::G8,
+            (1, _) => MemoryFormat::G16,
+            (_, 8) => MemoryFormat::R8g8b8,
+            (_, _) => MemoryFormat::R16g16b16,
+        };
+
+        let n_bytes = width.smul(height)?.smul(memory_format.n_bytes().usize())?;
+        let mut memory = SharedMemory::new(n_bytes.try_u64()?).expected_error()?;
+
+        let mut write = {
+            let mut pos = 0;
+            let memory = &mut *memory;
+            move |value: f32| {
+                let value = value.round().clamp(0., max_f) as u16;
+                if precision == 8 {
+                    memory[pos] = value as u8;
+                    pos += 1;
+                } else {
+                    // Scale from 12 to 16 bits
+                    let value = (u32::from(value) * 65535 + 2047) / 4095;
+                    memory[pos..pos + 2].copy_from_slice(&(value as u16).to_ne_bytes());
+                    pos += 2;
+                }
+            }
+        };
+
+        for y in 0..height {
+            for x in 0..width {
+                let mut samples = [0.; 4];
+                for (sample, sampler) in samples.iter_mut().zip(&samplers) {
+                    *sample = sampler.get(x, y);
+                }
+
+                match samplers.len() {
+                    1 => write(samples[0]),
+                    3 => {
+                        let rgb = if is_rgb {
+                            [samples[0], samples[1], samples[2]]
+                        } else {
+                            to_rgb([samples[0], samples[1], samples[2]])
+                        };
+                        rgb.into_iter().for_each(&mut write);
+                    }
+                    _ => {
+                        // Adobe stores inverted CMYK, optionally as YCCK
+                        let cmy = if self.frame.adobe_transform == Some(2) {
+                            to_rgb([samples[0], samples[1], samples[2]]).map(|x| max_f - x)
+                        } else {
+                            [samples[0], samples[1], samples[2]]
+                        };
+                        let k = samples[3];
+                        cmy.into_iter()
+                            .for_each(|x| write(x.clamp(0., max_f) * k / max_f));
+                    }
+                }
+            }
+        }
+
+        Ok((memory_format, memory))
+    }
+
+    /// Samples of a component after the inverse DCT
+    fn plane(&self, component: &Component, quant_table: &[u16; 64]) -> Vec<u16> {
+        let stride = component.stride * 8;
+        let center = 1 << (self.frame.precision - 1);
+        let max = (1 << self.frame.precision) - 1;
+
+        let mut plane = vec![0; component.coefficients.len() * 64];
+        for (i, coefficients) in component.coefficients.iter().enumerate() {
+            let x0 = (i % component.stride) * 8;
+            let y0 = (i / component.stride) * 8;
+
+            let mut block = [0.; 64];
+            for ((value, coefficient), q) in block.iter_mut().zip(coefficients).zip(quant_table) {
+                *value = f32::from(*coefficient) * f32::from(*q);
+            }
+            idct(&mut block);
+
+            for (y, row) in block.chunks_exact(8).enumerate() {
+                let start = (y0 + y) * stride + x0;
+                for (out, value) in plane[start..start + 8].iter_mut().zip(row) {
+                    *out = (value.round() as i32 + center).clamp(0, max) as u16;
+                }
+            }
+        }
+
+        plane
+    }
+}
+
+/// Upsampling of a component plane
+struct Sampler<'a> {
+    plane: &'a [u16],
+    stride: usize,
+    /// Dimensions of the area with image data
+    width: usize,
+    height: usize,
+    scale_x: f32,
+    scale_y: f32,
+}
+
+impl Sampler<'_> {
+    /// Sample at the image position with bilinear interpolation
+    fn get(&self, x: usize, y: usize) -> f32 {
+        // Full resolution components don't need interpolation
+        if self.scale_x == 1. && self.scale_y == 1. {
+            return f32::from(self.plane[y * self.stride + x]);
+        }
+
+        let sx = ((x as f32 + 0.5) * self.scale_x - 0.5).max(0.);
+        let sy = ((y as f32 + 0.5) * self.scale_y - 0.5).max(0.);
+
+        let x0 = (sx as usize).min(self.width - 1);
+        let y0 = (sy as usize).min(self.height - 1);
+        let x1 = (x0 + 1).min(self.width - 1);
+        let y1 = (y0 + 1).min(self.height - 1);
+        let fx = sx - x0 as f32;
+        let fy = sy - y0 as f32;
+
+        let value = |x: usize, y: usize| f32::from(self.plane[y * self.stride + x]);
+
+        let top = value(x0, y0) * (1. - fx) + value(x1, y0) * fx;
+        let bottom = value(x0, y1) * (1. - fx) + value(x1, y1) * fx;
+
+        top * (1. - fy) + bottom * fy
+    }
+}
+
+/// Separable inverse DCT of a block in row-major order
+fn idct(block: &mut [f32; 64]) {
+    // cos((2x + 1) * u * π / 16) scaled by the normalization factors
+    let table: [[f32; 8]; 8] = std::array::from_fn(|x| {
+        std::array::from_fn(|u| {
+            let c = if u == 0 {
+                std::f32::consts::FRAC_1_SQRT_2
+            } else {
+                1.
+            };
+            c / 2. * ((2. * x as f32 + 1.) * u as f32 * std::f32::consts::PI / 16.).cos()
+        })
+    });
+
+    let mut tmp = [0.; 64];
+    for y in 0..8 {
+        for x in 0..8 {
+            tmp[y * 8 + x] = (0..8).map(|u| table[x][u] * block[y * 8 + u]).sum();
+        }
+    }
+
+    for x in 0..8 {
+        for y in 0..8 {
+            block[y * 8 + x] = (0..8).map(|v| table[y][v] * tmp[v * 8 + x]).sum();
+        }
+    }
+}
+
+/// Entropy coded data of a scan, split at restart markers
+///
+/// Also returns the position of the marker after the scan.
+fn entropy_coded_segments(data: &[u8], start: usize) -> (Vec<&[u8]>, usize) {
+    let mut segments = Vec::new();
+    let mut segment_start = start;
+    let mut pos = start;
+
+    while pos < data.len() {
+        if data[pos] != 0xFF {
+            pos += 1;
+            continue;
+        }
+
+        match data.get(pos + 1) {
+            // Stuffed zero byte or fill byte
+            Some(0x00) => pos += 2,
+            Some(0xFF) => pos += 1,
+            // Restart marker
+            Some(0xD0..=0xD7) => {
+                segments.push(&data[segment_start..pos]);
+                pos += 2;
+                segment_start = pos;
+            }
+            _ => break,
+        }
+    }
+
+    segments.push(&data[segment_start..pos.min(data.len())]);
+
+    (segments, pos)
+}
+
+/// Reads bytes of an entropy coded segment
+///
+/// Stuffed zero bytes are removed. After the end of the segment, only zeros
+/// are returned.
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn next(&mut self) -> u8 {
+        let Some(byte) = self.data.get(self.pos).copied() else {
+            return 0;
+        };
+
+        self.pos += 1;
+        if byte == 0xFF {
+            if self.data.get(self.pos) == Some(&0) {
+                self.pos += 1;
+            } else {
+                // Start of a marker
+                self.pos = self.data.len();
+                return 0;
+            }
+        }
+
+        byte
+    }
+}
+
+trait EntropyDecoder<'a> {
+    /// Start decoding the entropy coded segment
+    fn restart(&mut self, segment: &'a [u8], scan: &Scan);
+
+    /// Decode the block of the `n`th component in the scan
+    fn decode_block(
+        &mut self,
+        block: &mut [i16; 64],
+        n: usize,
+        component: ScanComponent,
+        scan: &Scan,
+    ) -> Result<()>;
+}
+
+#[derive(Debug, Clone)]
+struct HuffmanTable {
+    /// Length and value for codes up to 8 bits, indexed by the next 8 bits
+    lookup: [(u8, u8); 256],
+    /// Largest code of each length, -1 if there are none
+    max_code: [i32; 17],
+    /// Offset to get the index in `values` from a code of each length
+    offset: [i32; 17],
+    values: Vec<u8>,
+}
+
+impl HuffmanTable {
+    fn new(counts: [u8; 16], values: &[u8]) -> Result<Self> {
+        let mut lookup = [(0, 0); 256];
+        let mut max_code = [-1; 17];
+        let mut offset = [0; 17];
+
+        let mut code = 0_i32;
+        let mut index = 0_i32;
+        for (len, count) in (1..=16).zip(counts) {
+            let count = i32::from(count);
+            offset[len] = index - code;
+
+            for i in 0..count {
+                if len <= 8 {
+                    let shift = 8 - len;
+                    let first = ((code + i) << shift) as usize;
+                    let value = values[(index + i) as usize];
+                    for entry in lookup
+                        .get_mut(first..first + (1 << shift))
+                        .ok_or_else(|| ProcessError::expected(&"Invalid JPEG Huffman table"))?
+                    {
+                        *entry = (len as u8, value);
+                    }
+                }
+            }
+
+            code += count;
+            index += count;
+            if count > 0 {
+                max_code[len] = code - 1;
+            }
+            if code > 1 << len {
+                return Err(ProcessError::expected(&"Invalid JPEG Huffman table"));
+            }
+            code <<= 1;
+        }
+
+        Ok(Self {
+            lookup,
+            max_code,
+            offset,
+            values: values.to_vec(),
+        })
+    }
+}
+
+struct HuffmanDecoder<'a> {
+    dc_tables: &'a [Option<HuffmanTable>; 4],
+    ac_tables: &'a [Option<HuffmanTable>; 4],
+    reader: ByteReader<'a>,
+    buffer: u64,
+    n_bits: u32,
+    dc_predictions: [i32; 4],
+    eob_run: u32,
+}
+
+impl<'a> HuffmanDecoder<'a> {
+    fn new(
+        dc_tables: &'a [Option<HuffmanTable>; 4],
+        ac_tables: &'a [Option<HuffmanTable>; 4],
+    ) -> Self {
+        Self {
+            dc_tables,
+            ac_tables,
+            reader: ByteReader::new(&[]),
+            buffer: 0,
+            n_bits: 0,
+            dc_predictions: [0; 4],
+            eob_run: 0,
+        }
+    }
+
+    fn fill(&mut self) {
+        while self.n_bits <= 56 {
+            self.buffer |= u64::from(self.reader.next()) << (56 - self.n_bits);
+            self.n_bits += 8;
+        }
+    }
+
+    fn bits(&mut self, n: u32) -> u32 {
+        if n == 0 {
+            return 0;
+        }
+
+        self.fill();
+        let value = (self.buffer >> (64 - n)) as u32;
+        self.buffer <<= n;
+        self.n_bits -= n;
+        value
+    }
+
+    /// Value of `n` bits with the sign extension of the JPEG spec
+    fn extended(&mut self, n: u8) -> i32 {
+        let n = u32::from(n);
+        let value = self.bits(n) as i32;
+        if n > 0 && value < 1 << (n - 1) {
+            value - (1 << n) + 1
+        } else {
+            value
+        }
+    }
+
+    fn decode(&mut self, table: &HuffmanTable) -> Result<u8> {
+        self.fill();
+
+        let (len, value) = table.lookup[(self.buffer >> 56) as usize];
+        if len > 0 {
+            self.buffer <<= len;
+            self.n_bits -= u32::from(len);
+            return Ok(value);
+        }
+
+        for len in 9..=16 {
+            let code = (self.buffer >> (64 - len)) as i32;
+            if code <= table.max_code[len] {
+                self.buffer <<= len;
+                self.n_bits -= len as u32;
+                return table
+                    .values
+                    .get((code + table.offset[len]) as usize)
+                    .copied()
+                    .ok_or_else(corrupt);
+            }
+        }
+
+        Err(corrupt())
+    }
+
+    fn table(tables: &'a [Option<HuffmanTable>; 4], index: usize) -> Result<&'a HuffmanTable> {
+        tables[index]
+            .as_ref()
+            .ok_or_else(|| ProcessError::expected(&"Missing JPEG Huffman table"))
+    }
+
+    fn decode_dc(&mut self, n: usize, table: usize) -> Result<i32> {
+        let table = Self::table(self.dc_tables, table)?;
+        let size = self.decode(table)?;
+        if size > 16 {
+            return Err(corrupt());
+        }
+        let diff = self.extended(size);
+        self.dc_predictions[n] = self.dc_predictions[n].wrapping_add(diff);
+
+        Ok(self.dc_predictions[n])
+    }
+
+    fn decode_ac_first(&mut self, block: &mut [i16; 64], table: usize, scan: &Scan) -> Result<()> {
+        if self.eob_run > 0 {
+            self.eob_run -= 1;
+            return Ok(());
+        }
+
+        let table = Self::table(self.ac_tables, table)?;
+        let mut k = scan.ss;
+        while k <= scan.se {
+            let rs = self.decode(table)?;
+            let (r, s) = (rs >> 4, rs & 0xF);
+
+            if s == 0 {
+                if r < 15 {
+                    // End of band run
+                    self.eob_run = (1 << r) + self.bits(r.into()) - 1;
+                    break;
+                }
+                k += 16;
+                continue;
+            }
+
+            k += usize::from(r);
+            let value = self.extended(s) << scan.al;
+            *block
+                .get_mut(*NATURAL_ORDER.get(k).ok_or_else(corrupt)?)
+                .ok_or_else(corrupt)? = value as i16;
+            k += 1;
+        }
+
+        Ok(())
+    }
+
+    fn decode_ac_refine(&mut self, block: &mut [i16; 64], table: usize, scan: &Scan) -> Result<()> {
+        let table = Self::table(self.ac_tables, table)?;
+        let p1 = 1_i16 << scan.al;
+        let m1 = -1_i16 << scan.al;
+
+        let mut k = scan.ss;
+
+        if self.eob_run == 0 {
+            while k <= scan.se {
+                let rs = self.decode(table)?;
+                let (mut r, s) = (i32::from(rs >> 4), rs & 0xF);
+
+                let value = match s {
+                    0 if r < 15 => {
+                        self.eob_run = (1 << r) + self.bits(r as u32);
+                        break;
+                    }
+                    0 => 0,
+                    _ => {
+                        if self.bits(1) == 1 {
+                            p1
+                        } else {
+                            m1
+                        }
+                    }
+                };
+
+                // Skip `r` zero coefficients and refine all nonzero ones on
+                // the way
+                while k <= scan.se {
+                    let coefficient = &mut block[NATURAL_ORDER[k]];
+                    if *coefficient != 0 {
+                        self.refine(coefficient, p1, m1);
+                    } else {
+                        if r == 0 {
+                            break;
+                        }
+                        r -= 1;
+                    }
+                    k += 1;
+                }
+
+                if value != 0 {
+                    if let Some(index) = NATURAL_ORDER.get(k) {
+                        block[*index] = value;
+                    }
+                }
+                k += 1;
+            }
+        }
+
+        if self.eob_run > 0 {
+            for k in k..=scan.se {
+                let coefficient = &mut block[NATURAL_ORDER[k]];
+                if *coefficient != 0 {
+                    self.refine(coefficient, p1, m1);
+                }
+            }
+            self.eob_run -= 1;
+        }
+
+        Ok(())
+    }
+
+    /// Add the correction bit to a nonzero coefficient
+    fn refine(&mut self, coefficient: &mut i16, p1: i16, m1: i16) {
+        if self.bits(1) == 1 && *coefficient & p1 == 0 {
+            *coefficient = if *coefficient >= 0 {
+                coefficient.wrapping_add(p1)
+            } else {
+                coefficient.wrapping_add(m1)
+            };
+        }
+    }
+}
+
+impl<'a> EntropyDecoder<'a> for HuffmanDecoder<'a> {
+    fn restart(&mut self, segment: &'a [u8], _scan: &Scan) {
+        self.reader = ByteReader::new(segment);
+        self.buffer = 0;
+        self.n_bits = 0;
+        self.dc_predictions = [0; 4];
+        self.eob_run = 0;
+    }
+
+    fn decode_block(
+        &mut self,
+        block: &mut [i16; 64],
+        n: usize,
+        component: ScanComponent,
+        scan: &Scan,
+    ) -> Result<()> {
+        match scan.kind {
+            ScanKind::Sequential => {
+                block[0] = self.decode_dc(n, component.dc_table)? as i16;
+
+                let table = Self::table(self.ac_tables, component.ac_table)?;
+                let mut k = 1;
+                while k < 64 {
+                    let rs = self.decode(table)?;
+                    let (r, s) = (rs >> 4, rs & 0xF);
+
+                    if s == 0 {
+                        if r < 15 {
+                            break;
+                        }
+                        k += 16;
+                        continue;
+                    }
+
+                    k += usize::from(r);
+                    let value = self.extended(s);
+                    *block
+                        .get_mut(*NATURAL_ORDER.get(k).ok_or_else(corrupt)?)
+                        .ok_or_else(corrupt)? = value as i16;
+                    k += 1;
+                }
+            }
+            ScanKind::DcFirst => {
+                block[0] = (self.decode_dc(n, component.dc_table)? << scan.al) as i16;
+            }
+            ScanKind::DcRefine => {
+                if self.bits(1) == 1 {
+                    block[0] |= 1 << scan.al;
+                }
+            }
+            ScanKind::AcFirst => self.decode_ac_first(block, component.ac_table, scan)?,
+            ScanKind::AcRefine => self.decode_ac_refine(block, component.ac_table, scan)?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Conditioning of the arithmetic coding statistics, set via DAC segments
+#[derive(Debug, Clone, Copy)]
+struct Conditioning {
+    dc_l: [u8; 4],
+    dc_u: [u8; 4],
+    ac_k: [u8; 4],
+}
+
+impl Default for Conditioning {
+    fn default() -> Self {
+        Self {
+            dc_l: [0; 4],
+            dc_u: [1; 4],
+            ac_k: [5; 4],
+        }
+    }
+}
+
+impl Conditioning {
+    fn read(&mut self, payload: &[u8]) -> Result<()> {
+        for entry in payload.chunks_exact(2) {
+            let index = usize::from(entry[0] & 0xF);
+            if index > 3 {
+                return Err(ProcessError::expected(
+                    &"Invalid JPEG arithmetic conditioning",
+                ));
+            }
+
+            if entry[0] >> 4 == 0 {
+                self.dc_l[index] = entry[1] & 0xF;
+                self.dc_u[index] = entry[1] >> 4;
+                if self.dc_l[index] > self.dc_u[index] {
+                    return Err(ProcessError::expected(
+                        &"Invalid JPEG arithmetic conditioning",
+                    ));
+                }
+            } else {
+                self.ac_k[index] = entry[1];
+                if !(1..=63).contains(&entry[1]) {
+                    return Err(ProcessError::expected(
+                        &"Invalid JPEG arithmetic conditioning",
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Probability estimation state machine of the arithmetic decoder
+///
+/// Contains the `Qe` value, next state after the LPS, next state after the
+/// MPS, and if the MPS is switched. The last entry is a fixed estimate of
+/// 0.5 that is used for sign and refinement bits.
+#[rustfmt::skip]
+const QE_TABLE: [(u16, u8, u8, u8); 114] = [
+    (0x5a1d, 1, 1, 1), (0x2586, 14, 2, 0), (0x1114, 16, 3, 0), (0x080b, 18, 4, 0),
+    (0x03d8, 20, 5, 0), (0x01da, 23, 6, 0), (0x00e5, 25, 7, 0), (0x006f, 28, 8, 0),
+    (0x0036, 30, 9, 0), (0x001a, 33, 10, 0), (0x000d, 35, 11, 0), (0x0006, 9, 12, 0),
+    (0x0003, 10, 13, 0), (0x0001, 12, 13, 0), (0x5a7f, 15, 15, 1), (0x3f25, 36, 16, 0),
+    (0x2cf2, 38, 17, 0), (0x207c, 39, 18, 0), (0x17b9, 40, 19, 0), (0x1182, 42, 20, 0),
+    (0x0cef, 43, 21, 0), (0x09a1, 45, 22, 0), (0x072f, 46, 23, 0), (0x055c, 48, 24, 0),
+    (0x0406, 49, 25, 0), (0x0303, 51, 26, 0), (0x0240, 52, 27, 0), (0x01b1, 54, 28, 0),
+    (0x0144, 56, 29, 0), (0x00f5, 57, 30, 0), (0x00b7, 59, 31, 0), (0x008a, 60, 32, 0),
+    (0x0068, 62, 33, 0), (0x004e, 63, 34, 0), (0x003b, 32, 35, 0), (0x002c, 33, 9, 0),
+    (0x5ae1, 37, 37, 1), (0x484c, 64, 38, 0), (0x3a0d, 65, 39, 0), (0x2ef1, 67, 40, 0),
+    (0x261f, 68, 41, 0), (0x1f33, 69, 42, 0), (0x19a8, 70, 43, 0), (0x1518, 72, 44, 0),
+    (0x1177, 73, 45, 0), (0x0e74, 74, 46, 0), (0x0bfb, 75, 47, 0), (0x09f8, 77, 48, 0),
+    (0x0861, 78, 49, 0), (0x0706, 79, 50, 0), (0x05cd, 48, 51, 0), (0x04de, 50, 52, 0),
+    (0x040f, 50, 53, 0), (0x0363, 51, 54, 0), (0x02d4, 52, 55, 0), (0x025c, 53, 56, 0),
+    (0x01f8, 54, 57, 0), (0x01a4, 55, 58, 0), (0x0160, 56, 59, 0), (0x0125, 57, 60, 0),
+    (0x00f6, 58, 61, 0), (0x00cb, 59, 62, 0), (0x00ab, 61, 63, 0), (0x008f, 61, 32, 0),
+    (0x5b12, 65, 65, 1), (0x4d04, 80, 66, 0), (0x412c, 81, 67, 0), (0x37d8, 82, 68, 0),
+    (0x2fe8, 83, 69, 0), (0x293c, 84, 70, 0), (0x2379, 86, 71, 0), (0x1edf, 87, 72, 0),
+    (0x1aa9, 87, 73, 0), (0x174e, 72, 74, 0), (0x1424, 72, 75, 0), (0x119c, 74, 76, 0),
+    (0x0f6b, 74, 77, 0), (0x0d51, 75, 78, 0), (0x0bb6, 77, 79, 0), (0x0a40, 77, 48, 0),
+    (0x5832, 80, 81, 1), (0x4d1c, 88, 82, 0), (0x438e, 89, 83, 0), (0x3bdd, 90, 84, 0),
+    (0x34ee, 91, 85, 0), (0x2eae, 92, 86, 0), (0x299a, 93, 87, 0), (0x2516, 86, 71, 0),
+    (0x5570, 88, 89, 1), (0x4ca9, 95, 90, 0), (0x44d9, 96, 91, 0), (0x3e22, 97, 92, 0),
+    (0x3824, 99, 93, 0), (0x32b4, 99, 94, 0), (0x2e17, 93, 86, 0), (0x56a8, 95, 96, 1),
+    (0x4f46, 101, 97, 0), (0x47e5, 102, 98, 0), (0x41cf, 103, 99, 0), (0x3c3d, 104, 100, 0),
+    (0x375e, 99, 93, 0), (0x5231, 105, 102, 0), (0x4c0f, 106, 103, 0), (0x4639, 107, 104, 0),
+    (0x415e, 103, 99, 0), (0x5627, 105, 106, 1), (0x50e7, 108, 107, 0), (0x4b85, 109, 103, 0),
+    (0x5597, 110, 109, 0), (0x504f, 111, 107, 0), (0x5a10, 110, 111, 1), (0x5522, 112, 109, 0),
+    (0x59eb, 112, 111, 1), (0x5a1d, 113, 113, 0),
+];
+
+/// State of the fixed probability estimate
+const FIXED_BIN: u8 = 113;
+
+struct ArithmeticDecoder<'a> {
+    reader: ByteReader<'a>,
+    c: i64,
+    a: i64,
+    ct: i32,
+    conditioning: Conditioning,
+    progressive: bool,
+    dc_stats: [[u8; 64]; 4],
+    ac_stats: [[u8; 256]; 4],
+    dc_context: [usize; 4],
+    dc_predictions: [i32; 4],
+    /// Set for invalid data until the next restart
+    corrupt: bool,
+}
+
+impl<'a> ArithmeticDecoder<'a> {
+    fn new(conditioning: Conditioning, progressive: bool) -> Self {
+        Self {
+            reader: ByteReader::new(&[]),
+            c: 0,
+            a: 0,
+            ct: -16,
+            conditioning,
+            progressive,
+            dc_stats: [[0; 64]; 4],
+            ac_stats: [[0; 256]; 4],
+            dc_context: [0; 4],
+            dc_predictions: [0; 4],
+            corrupt: false,
+        }
+    }
+
+    /// Decode a binary decision with the given statistics bin
+    fn decode_bit(&mut self, st: &mut u8) -> bool {
+        // Renormalization and data input
+        while self.a < 0x8000 {
+            self.ct -= 1;
+            if self.ct < 0 {
+                self.c = (self.c << 8) | i64::from(self.reader.next());
+                self.ct += 8;
+                if self.ct < 0 {
+                    self.ct += 1;
+                    // Got two initial bytes
+                    if self.ct == 0 {
+                        self.a = 0x8000;
+                    }
+                }
+            }
+            self.a <<= 1;
+        }
+
+        let mut sv = *st;
+        let (qe, next_lps, next_mps, switch) = QE_TABLE[usize::from(sv & 0x7F)];
+        let qe = i64::from(qe);
+        let next_lps = next_lps | (switch << 7);
+
+        let temp = self.a - qe;
+        self.a = temp;
+        let temp = temp << self.ct;
+
+        if self.c >= temp {
+            self.c -= temp;
+            // Conditional LPS exchange
+            if self.a < qe {
+                self.a = qe;
+                *st = (sv & 0x80) ^ next_mps;
+            } else {
+                self.a = qe;
+                *st = (sv & 0x80) ^ next_lps;
+                sv ^= 0x80;
+            }
+        } else if self.a < 0x8000 {
+            // Conditional MPS exchange
+            if self.a < qe {
+                *st = (sv & 0x80) ^ next_lps;
+                sv ^= 0x80;
+            } else {
+                *st = (sv & 0x80) ^ next_mps;
+            }
+        }
+
+        sv >> 7 == 1
+    }
+
+    fn decode_fixed(&mut self) -> bool {
+        let mut st = FIXED_BIN;
+        self.decode_bit(&mut st)
+    }
+
+    /// Decode a DC difference
+    fn decode_dc_diff(&mut self, n: usize, table: usize) -> Option<i32> {
+        let mut stats = self.dc_stats[table];
+        let result = self.decode_dc_diff_with(&mut stats, n, table);
+        self.dc_stats[table] = stats;
+        result
+    }
+
+    fn decode_dc_diff_with(&mut self, stats: &mut [u8; 64], n: usize, table: usize) -> Option<i32> {
+        let mut st = self.dc_context[n];
+
+        if !self.decode_bit(&mut stats[st]) {
+            self.dc_context[n] = 0;
+            return Some(0);
+        }
+
+        let sign = usize::from(self.decode_bit(&mut stats[st + 1]));
+        st += 2 + sign;
+
+        let mut m = i32::from(self.decode_bit(&mut stats[st]));
+        if m != 0 {
+            st = 20;
+            while self.decode_bit(&mut stats[st]) {
+                m <<= 1;
+                if m == 0x8000 {
+                    return None;
+                }
+                st += 1;
+            }
+        }
+
+        // Conditioning category for the next difference
+        let l = (1 << self.conditioning.dc_l[table]) >> 1;
+        let u = (1 << self.conditioning.dc_u[table]) >> 1;
+        self.dc_context[n] = if m < l {
+            0
+        } else if m > u {
+            12 + sign * 4
+        } else {
+            4 + sign * 4
+        };
+
+        let mut v = m;
+        st += 14;
+        while m > 1 {
+            m >>= 1;
+            if self.decode_bit(&mut stats[st]) {
+                v |= m;
+            }
+        }
+        v += 1;
+
+        Some(if sign == 1 { -v } else { v })
+    }
+
+    /// Decode AC coefficients from `ss` to `se`
+    fn decode_ac(
+        &mut self,
+        block: &mut [i16; 64],
+        table: usize,
+        ss: usize,
+        se: usize,
+        al: u8,
+    ) -> Option<()> {
+        let mut stats = self.ac_stats[table];
+        let result = self.decode_ac_with(&mut stats, block, table, ss, se, al);
+        self.ac_stats[table] = stats;
+        result
+    }
+
+    fn decode_ac_with(
+        &mut self,
+        stats: &mut [u8; 256],
+        block: &mut [i16; 64],
+        table: usize,
+        ss: usize,
+        se: usize,
+        al: u8,
+    ) -> Option<()> {
+        let mut k = ss;
+        while k <= se {
+            let mut st = 3 * (k - 1);
+            // End of block
+            if self.decode_bit(&mut stats[st]) {
+                break;
+            }
+            while !self.decode_bit(&mut stats[st + 1]) {
+                st += 3;
+                k += 1;
+                if k > se {
+                    return None;
+                }
+            }
+
+            let sign = self.decode_fixed();
+            st += 2;
+
+            let mut m = i32::from(self.decode_bit(&mut stats[st]));
+            if m != 0 && self.decode_bit(&mut stats[st]) {
+                m <<= 1;
+                st = if k <= usize::from(self.conditioning.ac_k[table]) {
+                    189
+                } else {
+                    217
+                };
+                while self.decode_bit(&mut stats[st]) {
+                    m <<= 1;
+                    if m == 0x8000 {
+                        return None;
+                    }
+                    st += 1;
+                }
+            }
+
+            let mut v = m;
+            st += 14;
+            while m > 1 {
+                m >>= 1;
+                if self.decode_bit(&mut stats[st]) {
+                    v |= m;
+                }
+            }
+            v += 1;
+
+            let v = if sign { -v } else { v };
+            block[NATURAL_ORDER[k]] = (v << al) as i16;
+            k += 1;
+        }
+
+        Some(())
+    }
+
+    fn decode_ac_refine(&mut self, block: &mut [i16; 64], table: usize, scan: &Scan) -> Option<()> {
+        let mut stats = self.ac_stats[table];
+        let p1 = 1_i16 << scan.al;
+        let m1 = -1_i16 << scan.al;
+
+        // End of block of the previous stage
+        let kex = (1..=scan.se)
+            .rev()
+            .find(|k| block[NATURAL_ORDER[*k]] != 0)
+            .unwrap_or(0);
+
+        let mut result = Some(());
+        let mut k = scan.ss;
+        'coefficients: while k <= scan.se {
+            let mut st = 3 * (k - 1);
+            if k > kex && self.decode_bit(&mut stats[st]) {
+                break;
+            }
+
+            loop {
+                let coefficient = &mut block[NATURAL_ORDER[k]];
+                if *coefficient != 0 {
+                    // Previously nonzero coefficient
+                    if self.decode_bit(&mut stats[st + 2]) {
+                        *coefficient = if *coefficient < 0 {
+                            coefficient.wrapping_add(m1)
+                        } else {
+                            coefficient.wrapping_add(p1)
+                        };
+                    }
+                    break;
+                }
+
+                if self.decode_bit(&mut stats[st + 1]) {
+                    // Newly nonzero coefficient
+                    *coefficient = if self.decode_fixed() { m1 } else { p1 };
+                    break;
+                }
+
+                st += 3;
+                k += 1;
+                if k > scan.se {
+                    result = None;
+                    break 'coefficients;
+                }
+            }
+
+            k += 1;
+        }
+
+        self.ac_stats[table] = stats;
+        result
+    }
+}
+
+impl<'a> EntropyDecoder<'a> for ArithmeticDecoder<'a> {
+    fn restart(&mut self, segment: &'a [u8], scan: &Scan) {
+        self.reader = ByteReader::new(segment);
+        self.c = 0;
+        self.a = 0;
+        self.ct = -16;
+        self.corrupt = false;
+
+        for (n, component) in scan.components.iter().enumerate() {
+            if !self.progressive || scan.kind == ScanKind::DcFirst {
+                self.dc_stats[component.dc_table] = [0; 64];
+                self.dc_predictions[n] = 0;
+                self.dc_context[n] = 0;
+            }
+            if !self.progressive || scan.ss > 0 {
+                self.ac_stats[component.ac_table] = [0; 256];
+            }
+        }
+    }
+
+    fn decode_block(
+        &mut self,
+        block: &mut [i16; 64],
+        n: usize,
+        component: ScanComponent,
+        scan: &Scan,
+    ) -> Result<()> {
+        // Skip the remaining blocks of corrupt segments like libjpeg
+        if self.corrupt {
+            return Ok(());
+        }
+
+        let result = match scan.kind {
+            ScanKind::Sequential => self.decode_dc_diff(n, component.dc_table).and_then(|diff| {
+                self.dc_predictions[n] = self.dc_predictions[n].wrapping_add(diff);
+                block[0] = self.dc_predictions[n] as i16;
+                self.decode_ac(block, component.ac_table, 1, 63, 0)
+            }),
+            ScanKind::DcFirst => self.decode_dc_diff(n, component.dc_table).map(|diff| {
+                self.dc_predictions[n] = self.dc_predictions[n].wrapping_add(diff);
+                block[0] = (self.dc_predictions[n] << scan.al) as i16;
+            }),
+            ScanKind::DcRefine => {
+                if self.decode_fixed() {
+                    block[0] |= 1 << scan.al;
+                }
+                Some(())
+            }
+            ScanKind::AcFirst => {
+                self.decode_ac(block, component.ac_table, scan.ss, scan.se, scan.al)
+            }
+            ScanKind::AcRefine => self.decode_ac_refine(block, component.ac_table, scan),
+        };
+
+        if result.is_none() {
+            log::warn!("Corrupt arithmetic coded JPEG data");
+            self.corrupt = true;
+        }
+
+        Ok(())
+    }
+}
+
+fn corrupt() -> ProcessError {
+    ProcessError::expected(&"Corrupt JPEG data")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 16×16 grayscale JPEG with arithmetic coding and four flat quadrants
+    const ARITHMETIC: &[u8] = &[
+        0xff, 0xd8, 0xff, 0xdb, 0x00, 0x43, 0x00, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+        0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+        0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+        0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+        0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0xff, 0xc9, 0x00, 0x0b,
+        0x08, 0x00, 0x10, 0x00, 0x10, 0x01, 0x01, 0x11, 0x00, 0xff, 0xcc, 0x00, 0x06, 0x00, 0x10,
+        0x10, 0x05, 0xff, 0xda, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3f, 0x00, 0xff, 0x00, 0xf6,
+        0xf9, 0xa9, 0xe0, 0x82, 0xa6, 0xff, 0xd9,
+    ];
+
+    /// 16×16 grayscale 12-bit JPEG with four flat quadrants
+    const TWELVE_BIT: &[u8] = &[
+        0xff, 0xd8, 0xff, 0xdb, 0x00, 0x43, 0x00, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+        0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+        0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+        0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+        0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0xff, 0xc1, 0x00, 0x0b,
+        0x0c, 0x00, 0x10, 0x00, 0x10, 0x01, 0x01, 0x11, 0x00, 0xff, 0xc4, 0x00, 0x23, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f, 0xff, 0xc4, 0x00, 0x14, 0x10, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xda, 0x00, 0x08, 0x01, 0x01, 0x00,
+        0x00, 0x3f, 0x00, 0x7b, 0xff, 0x00, 0xf3, 0x7e, 0x80, 0x74, 0x18, 0x07, 0x7f, 0xf0, 0xff,
+        0xd9,
+    ];
+
+    fn pixels(frame: &Frame) -> Vec<u8> {
+        frame.texture.get_full().unwrap()
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert!(is_applicable(ARITHMETIC));
+
+        let mut jpeg = JpegFallback::new(ARITHMETIC.to_vec()).unwrap();
+        let frame = jpeg.frame().unwrap();
+        assert_eq!(frame.memory_format, MemoryFormat::G8);
+
+        let pixels = pixels(&frame);
+        assert_eq!(
+            [pixels[0], pixels[15], pixels[16 * 15], pixels[255]],
+            [0x20, 0x80, 0xC0, 0xFF]
+        );
+
+        assert!(matches!(jpeg.frame(), Err(ProcessError::NoMoreFrames)));
+    }
+
+    #[test]
+    fn twelve_bit() {
+        assert!(is_applicable(TWELVE_BIT));
+
+        let mut jpeg = JpegFallback::new(TWELVE_BIT.to_vec()).unwrap();
+        let frame = jpeg.frame().unwrap();
+        assert_eq!(frame.memory_format, MemoryFormat::G16);
+        assert_eq!(frame.details.info_bit_depth, Some(12));
+
+        let pixels = pixels(&frame)
+            .chunks_exact(2)
+            .map(|x| u16::from_ne_bytes([x[0], x[1]]))
+            .collect::<Vec<_>>();
+        // Samples 0, 1000, 2048, and 4095 scaled to 16 bit
+        assert_eq!(
+            [pixels[0], pixels[15], pixels[16 * 15], pixels[255]],
+            [0, 16004, 32776, 65535]
+        );
+    }
+
+    #[test]
+    fn progressive_previews() {
+        let rgb = (0..32 * 32)
+            .flat_map(|i| [(i % 32 * 8) as u8, (i / 32 * 8) as u8, 128])
+            .collect::<Vec<_>>();
+        let encode = |progressive| {
+            let mut data = Vec::new();
+            let mut encoder = jpeg_encoder::Encoder::new(&mut data, 90);
+            encoder.set_progressive(progressive);
+            encoder.set_sampling_factor(jpeg_encoder::SamplingFactor::F_1_1);
+            encoder
+                .encode(&rgb, 32, 32, jpeg_encoder::ColorType::Rgb)
+                .unwrap();
+            data
+        };
+
+        assert!(!is_progressive(&encode(false)));
+
+        let data = encode(true);
+        assert!(is_progressive(&data));
+        assert!(!is_applicable(&data));
+
+        let mut previews = Vec::new();
+        let frame = JpegFallback::new(data.clone())
+            .unwrap()
+            .frame_progressive(&mut |frame| previews.push(frame))
+            .unwrap();
+        assert!(!previews.is_empty());
+        assert!(previews
+            .iter()
+            .all(|x| (x.width, x.height, x.memory_format) == (32, 32, MemoryFormat::R8g8b8)));
+
+        // Only rounding differences to image-rs
+        let expected = image::load_from_memory(&data).unwrap().into_rgb8();
+        let max_difference = pixels(&frame)
+            .iter()
+            .zip(expected.as_raw())
+            .map(|(a, b)| a.abs_diff(*b))
+            .max();
+        assert!(max_difference <= Some(2), "{max_difference:?}");
+    }
+
+    #[test]
+    fn malformed() {
+        // Truncated files fail without panicking
+        for data in [ARITHMETIC, TWELVE_BIT] {
+            for len in 0..data.len() {
+                let _result = JpegFallback::new(data[..len].to_vec()).and_then(|mut x| x.frame());
+            }
+        }
+
+        // Quantization table with 16-bit precision but 8-bit values
+        let mut data = ARITHMETIC.to_vec();
+        data[6] = 0x10;
+        assert!(JpegFallback::new(data).and_then(|mut x| x.frame()).is_err());
+
+        // Quantization table with invalid index
+        let mut data = ARITHMETIC.to_vec();
+        data[6] = 0x04;
+        assert!(JpegFallback::new(data).and_then(|mut x| x.frame()).is_err());
+    }
+
+    #[test]
+    fn baseline_not_applicable() {
+        let mut baseline = TWELVE_BIT.to_vec();
+        // Change SOF1 with 12-bit precision to SOF0 with 8-bit precision
+        let sof = baseline.windows(2).position(|x| x == [0xFF, 0xC1]).unwrap();
+        baseline[sof + 1] = 0xC0;
+        baseline[sof + 4] = 8;
+
+        assert!(!is_applicable(&baseline));
+    }
+}
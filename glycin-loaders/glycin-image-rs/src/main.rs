@@ -1,6 +1,20 @@
 #![allow(clippy::large_enum_variant)]
 
+mod advisories;
+mod animated_webp;
+mod background_color;
+mod cmyk_tiff;
+mod depth_map;
 mod editor;
+mod exr_layers;
+mod frame_methods;
+mod jpeg_fallback;
+mod motion_photo;
+mod png_color;
+mod progressive_png;
+mod radiance;
+mod resolution;
+mod tiled_tiff;
 
 use std::io::{Cursor, Read};
 use std::sync::mpsc::{channel, Receiver, Sender};
@@ -20,17 +34,46 @@ type Reader = Cursor<Vec<u8>>;
 type FrameReceiver = Receiver<Result<(Frame, bool), ProcessError>>;
 type FrameSender = Sender<Result<(Frame, bool), ProcessError>>;
 
+/// Identifier in front of Exif data in JPEG APP1 segments
+const EXIF_PREFIX: &[u8] = b"Exif\0\0";
+
 #[derive(Default)]
 pub struct ImgDecoder {
     pub format: Mutex<Option<ImageRsFormat<Reader>>>,
     pub thread: Mutex<Option<(std::thread::JoinHandle<()>, FrameReceiver)>>,
+    /// CICP of the image
+    ///
+    /// Used for all frames that don't carry their own CICP. This is correct
+    /// for the supported animated formats: APNG's `cICP` chunk applies to
+    /// all frames, and GIF and WebP don't support CICP.
     pub cicp: Mutex<Option<Cicp>>,
+    /// CICP to use if the ICC profile of the image is broken
+    pub cicp_fallback: Mutex<Option<Cicp>>,
+    pub tiled_tiff: Mutex<Option<tiled_tiff::TiledTiff>>,
+    pub cmyk_tiff: Mutex<Option<cmyk_tiff::CmykTiff>>,
+    /// Arithmetic coded and 12-bit JPEGs that image-rs doesn't support
+    pub jpeg_fallback: Mutex<Option<jpeg_fallback::JpegFallback>>,
+    pub depth_map: Mutex<Option<depth_map::DepthMap>>,
+    /// Data of interlaced PNGs for progressive decoding
+    pub interlaced_png: Mutex<Option<Vec<u8>>>,
+    /// Data of progressive JPEGs for progressive decoding
+    pub progressive_jpeg: Mutex<Option<Vec<u8>>>,
+    /// Data of multi-page TIFFs for decoding individual pages
+    pub tiff_pages: Mutex<Option<Vec<u8>>>,
+    /// Data of OpenEXR images for decoding individual layers
+    pub exr: Mutex<Option<Vec<u8>>>,
+    /// ICC profile built from the `gAMA` and `cHRM` chunks of PNGs
+    pub png_chunks_icc_profile: Mutex<Option<BinaryData>>,
+    /// ICC profile as embedded in the image
+    pub icc_profile: Mutex<Option<BinaryData>>,
 }
 
 fn animated_worker(
     format: ImageRsFormat<Reader>,
     data: Reader,
     mime_type: String,
+    assume_still: bool,
+    max_frames: Option<usize>,
     send: FrameSender,
 ) {
     let mut format = Some(format);
@@ -38,6 +81,12 @@ fn animated_worker(
     std::thread::park();
 
     let mut looped = false;
+    let frame_methods = frame_methods::frame_methods(&mime_type, data.get_ref());
+    // Set once more than one frame has been found
+    let mut animation_detected = false;
+    let max_frames = max_frames.unwrap_or(usize::MAX);
+    // Frames sent so far, including previous loops of the animation
+    let mut n_frames = 0_usize;
 
     // Replay animation from beginning
     loop {
@@ -47,13 +96,6 @@ fn animated_worker(
             format = ImageRsFormat::create(data.clone(), &mime_type).ok();
         }
 
-        let mut decoder = format.as_mut().map(|x| &mut x.decoder);
-
-        // Use transparent background instead of suggested background color
-        if let Some(ImageRsDecoder::WebP(webp)) = &mut decoder {
-            let _result = webp.set_background_color(image::Rgba::from([0, 0, 0, 0]));
-        }
-
         let frame_details = match format.as_mut().unwrap().frame_details() {
             Ok(frame_details) => Some(frame_details),
             Err(err) => {
@@ -62,41 +104,77 @@ fn animated_worker(
             }
         };
 
-        let mut frames = std::mem::take(&mut format)
-            .unwrap()
-            .decoder
-            .into_frames()
-            .unwrap();
+        let format = std::mem::take(&mut format).unwrap();
+        // Composite WebP frames ourselves to support partial frames
+        let frames = if mime_type == "image/webp" {
+            animated_webp::AnimatedWebP::new(data.get_ref().clone())
+                .and_then(animated_webp::AnimatedWebP::into_frames)
+        } else {
+            Ok(format.decoder.into_frames().unwrap())
+        };
+        let mut frames = match frames {
+            Ok(frames) => frames,
+            Err(err) => {
+                send.send(Err(ProcessError::expected(&err))).unwrap();
+                return;
+            }
+        };
         let mut first_frames = Vec::new();
 
-        // Decode first two frames to check if actually an animation
-        trace!("animated: Decoding first two frames");
-        for _ in 0..2 {
+        // Decode first two frames to check if actually an animation. If the image is
+        // assumed to be still, only decode the first frame.
+        let n_probe_frames = if assume_still && !animation_detected {
+            1
+        } else {
+            2
+        };
+        trace!("animated: Decoding first {n_probe_frames} frames");
+        for _ in 0..n_probe_frames {
             if let Some(frame) = frames.next() {
                 first_frames.push(frame);
             }
         }
 
-        let is_animated = match first_frames.len() {
+        let mut is_animated = match first_frames.len() {
             0 => {
                 send.send(Err(ProcessError::expected(&"No frame found.")))
                     .unwrap();
                 return;
             }
-            1 => false,
+            1 => animation_detected,
             _ => true,
         };
 
-        for frame in first_frames.into_iter().chain(frames).enumerate() {
+        let mut time = AnimationTime::default();
+
+        for frame in first_frames
+            .into_iter()
+            .chain(frames)
+            .take(max_frames.saturating_sub(n_frames))
+            .enumerate()
+        {
+            n_frames = n_frames.saturating_add(1);
+
+            // Another frame was explicitly requested for an image assumed to be still
+            if frame.0 > 0 {
+                is_animated = true;
+                animation_detected = true;
+            }
+
             // Only use FrameDetails for still images because they might not make too much
             // sense otherwise
             let frame_details = (!is_animated).then(|| frame_details.clone()).flatten();
 
-            let decoded_frame = animated_get_frame(frame, frame_details, is_animated);
+            let methods = is_animated
+                .then(|| frame_methods.get(frame.0).copied())
+                .flatten();
+
+            let decoded_frame =
+                animated_get_frame(frame, frame_details, methods, is_animated, &mut time);
             send.send(decoded_frame.map(|x| (x, looped))).unwrap();
 
             // If not really an animation no need to keep the thread around
-            if !is_animated {
+            if !is_animated && !assume_still {
                 log::debug!("animated: Image is actually not animated");
                 return;
             }
@@ -104,28 +182,93 @@ fn animated_worker(
             std::thread::park();
         }
 
+        if !is_animated {
+            log::debug!("animated: Image assumed to be still has no further frames");
+            send.send(Err(ProcessError::NoMoreFrames)).unwrap();
+            return;
+        }
+
+        if n_frames >= max_frames {
+            log::debug!("animated: Reached maximum number of {max_frames} frames");
+            send.send(Err(ProcessError::NoMoreFrames)).unwrap();
+            return;
+        }
+
         looped = true;
     }
 }
 
+/// Exact sum of frame delays in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationTime {
+    numer: u128,
+    denom: u128,
+}
+
+impl Default for AnimationTime {
+    fn default() -> Self {
+        Self { numer: 0, denom: 1 }
+    }
+}
+
+impl AnimationTime {
+    fn add(&mut self, numer: u32, denom: u32) {
+        let (numer, denom) = (u128::from(numer), u128::from(denom));
+        let sum_numer = self
+            .numer
+            .saturating_mul(denom)
+            .saturating_add(numer.saturating_mul(self.denom));
+        let sum_denom = self.denom.saturating_mul(denom);
+
+        let divisor = gcd(sum_numer, sum_denom).max(1);
+        self.numer = sum_numer.checked_div(divisor).unwrap_or_default();
+        self.denom = sum_denom.checked_div(divisor).unwrap_or(1);
+    }
+
+    fn duration(&self) -> std::time::Duration {
+        let nanos = self
+            .numer
+            .saturating_mul(1_000_000)
+            .checked_div(self.denom)
+            .unwrap_or_default();
+
+        std::time::Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+    }
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while let Some(rem) = a.checked_rem(b) {
+        (a, b) = (b, rem);
+    }
+    a
+}
+
 pub fn animated_get_frame(
     (n_frame, frame): (usize, Result<image::Frame, image::ImageError>),
     frame_details: Option<FrameDetails>,
+    methods: Option<frame_methods::Methods>,
     is_animated: bool,
+    time: &mut AnimationTime,
 ) -> Result<Frame, ProcessError> {
     log::trace!("animated: Treating decoded frame {n_frame}");
     let frame = frame.expected_error()?;
 
-    let (delay_num, delay_den) = frame.delay().numer_denom_ms();
-
-    let delay = if !is_animated {
-        None
-    } else if delay_num == 0 || delay_den == 0 {
+    let (mut delay_num, mut delay_den) = frame.delay().numer_denom_ms();
+    if delay_num == 0 || delay_den == 0 {
         // Other decoders default to this value as well
-        Some(std::time::Duration::from_millis(100))
-    } else {
+        (delay_num, delay_den) = (100, 1);
+    }
+
+    let (delay, timestamp) = if is_animated {
         let micros = f64::round(delay_num as f64 * 1000. / delay_den as f64) as u64;
-        Some(std::time::Duration::from_micros(micros))
+        let timestamp = time.duration();
+        time.add(delay_num, delay_den);
+        (
+            Some(std::time::Duration::from_micros(micros)),
+            Some(timestamp),
+        )
+    } else {
+        (None, None)
     };
 
     let buffer = frame.into_buffer();
@@ -152,6 +295,11 @@ pub fn animated_get_frame(
     };
 
     out_frame.details.n_frame = Some(n_frame.try_u64()?);
+    out_frame.details.timestamp = timestamp;
+    if let Some((blend, dispose)) = methods {
+        out_frame.details.info_animation_blend = Some(blend);
+        out_frame.details.info_animation_dispose = Some(dispose);
+    }
 
     Ok(out_frame)
 }
@@ -160,26 +308,50 @@ impl LoaderImplementation for ImgDecoder {
     fn init(
         mut stream: UnixStream,
         mime_type: String,
-        _details: InitializationDetails,
+        details: InitializationDetails,
     ) -> Result<(Self, ImageDetails), ProcessError> {
+        let assume_still = details.assume_still.unwrap_or_default();
+        let max_frames = details.max_frames.map(|x| x.try_usize()).transpose()?;
+
         let mut buf = Vec::new();
         stream.read_to_end(&mut buf).internal_error()?;
         let data = Cursor::new(buf);
 
+        // Decode arithmetic coded and 12-bit JPEGs with the fallback decoder
+        if mime_type == "image/jpeg" && jpeg_fallback::is_applicable(data.get_ref()) {
+            return Self::init_jpeg_fallback(data.into_inner());
+        }
+
         let mut format = ImageRsFormat::create(data.clone(), &mime_type)?;
         if let Err(err) = format.set_no_limits() {
             eprint!("Failed to unset decoder limits: {err}");
         }
         let mut image_info = format.info();
+        let icc_profile = format
+            .frame_details()
+            .ok()
+            .and_then(|x| x.color_icc_profile);
+
+        let hdr_metadata = match &format.decoder {
+            ImageRsDecoder::Hdr(d) => Some(d.metadata()),
+            _ => None,
+        };
 
         // TODO: Unnecessary clone of data
         let metadata = gufo::RawMetadata::for_guessed(data.into_inner());
 
+        let loader_impelementation = Self::default();
+        *loader_impelementation.icc_profile.lock().unwrap() = icc_profile;
+
         let data = match metadata {
             Ok((metadata, data)) => {
-                image_info.metadata_exif = metadata
+                // Some writers prefix the PNG eXIf chunk like the JPEG APP1 segment
+                let exif = metadata
                     .exif
                     .first()
+                    .map(|x| x.strip_prefix(EXIF_PREFIX).unwrap_or(x));
+
+                image_info.metadata_exif = exif
                     .map(BinaryData::from_data)
                     .transpose()
                     .expected_error()?;
@@ -191,15 +363,49 @@ impl LoaderImplementation for ImgDecoder {
                     .transpose()
                     .expected_error()?;
 
-                image_info.metadata_key_value = Some(metadata.key_value);
+                // Physical size from the resolution
+                image_info.dimensions_inch = resolution::dimensions_inch(
+                    &mime_type,
+                    &data,
+                    exif,
+                    image_info.width,
+                    image_info.height,
+                );
+
+                let mut key_value = metadata.key_value;
+
+                // Video embedded in motion photos
+                if mime_type == "image/jpeg" {
+                    key_value.extend(motion_photo::key_value(
+                        metadata.xmp.first().map(Vec::as_slice),
+                        &data,
+                    ));
+                }
+
+                // Depth map embedded in portrait photos
+                let data = if mime_type == "image/jpeg" {
+                    match gufo_jpeg::Jpeg::new(data) {
+                        Ok(jpeg) => {
+                            let depth_map = depth_map::DepthMap::new(&jpeg);
+                            if let Some(depth_map) = &depth_map {
+                                key_value.extend(depth_map.key_value());
+                            }
+                            *loader_impelementation.depth_map.lock().unwrap() = depth_map;
+                            jpeg.into_inner()
+                        }
+                        Err(err) => err.into_inner(),
+                    }
+                } else {
+                    data
+                };
+
+                image_info.metadata_key_value = Some(key_value);
 
                 data
             }
             Err(err) => err.into_inner(),
         };
 
-        let loader_impelementation = Self::default();
-
         // TODO: Unnecessary clone of data
         let gufo_image = gufo::Image::new(data);
         let data = Cursor::new(match gufo_image {
@@ -210,11 +416,95 @@ impl LoaderImplementation for ImgDecoder {
             Err(err) => err.into_inner(),
         });
 
+        // Color space of PNGs without ICC profile or cICP chunk
+        if mime_type == "image/png" || mime_type == "image/apng" {
+            let mut cicp = loader_impelementation.cicp.lock().unwrap();
+            if cicp.is_none() {
+                *cicp = png_color::cicp(data.get_ref());
+            }
+            *loader_impelementation.cicp_fallback.lock().unwrap() =
+                png_color::fallback_cicp(data.get_ref());
+            *loader_impelementation
+                .png_chunks_icc_profile
+                .lock()
+                .unwrap() =
+                png_color::icc_profile(data.get_ref()).and_then(|x| BinaryData::from_data(x).ok());
+        }
+
+        // Calibration information for Radiance HDR
+        if let Some(hdr_metadata) = hdr_metadata {
+            let key_value = radiance::key_value(&hdr_metadata);
+            if !key_value.is_empty() {
+                image_info
+                    .metadata_key_value
+                    .get_or_insert_with(Default::default)
+                    .extend(key_value);
+            }
+
+            let mut cicp = loader_impelementation.cicp.lock().unwrap();
+            if cicp.is_none() {
+                *cicp = radiance::cicp(&hdr_metadata);
+            }
+        }
+
+        if details.collect_advisories == Some(true) && mime_type == "image/tiff" {
+            image_info.advisories = Some(advisories::tiff(data.get_ref()));
+        }
+
+        // Pages of multi-page TIFFs
+        if mime_type == "image/tiff" {
+            image_info.sub_images = tiled_tiff::pages(data.get_ref());
+            if image_info.sub_images.is_some() {
+                // TODO: Unnecessary clone of data
+                *loader_impelementation.tiff_pages.lock().unwrap() = Some(data.get_ref().clone());
+            }
+        }
+
+        // Convert CMYK TIFFs via their ICC profile
+        if mime_type == "image/tiff" && cmyk_tiff::CmykTiff::is_applicable(data.get_ref()) {
+            let details = format.frame_details()?;
+            drop(format);
+            let cmyk_tiff = cmyk_tiff::CmykTiff::new(data.into_inner(), details);
+            *loader_impelementation.cmyk_tiff.lock().unwrap() = Some(cmyk_tiff);
+
+            return Ok((loader_impelementation, image_info));
+        }
+
+        // Decode tiled TIFFs and BigTIFFs chunk-wise to support clipping
+        if mime_type == "image/tiff" && tiled_tiff::TiledTiff::is_applicable(data.get_ref()) {
+            let details = format.frame_details()?;
+            drop(format);
+            let tiled_tiff = tiled_tiff::TiledTiff::new(data.into_inner(), details)?;
+            image_info.tile_size = tiled_tiff.tile_size();
+            *loader_impelementation.tiled_tiff.lock().unwrap() = Some(tiled_tiff);
+
+            return Ok((loader_impelementation, image_info));
+        }
+
+        // Layers of OpenEXR images, like depth or normals
+        if mime_type == "image/x-exr" {
+            image_info.layers = exr_layers::layers(data.get_ref());
+            if image_info.layers.is_some() {
+                // TODO: Unnecessary clone of data
+                *loader_impelementation.exr.lock().unwrap() = Some(data.get_ref().clone());
+            }
+        }
+
         if format.decoder.is_animated() {
+            image_info.animation_background_color =
+                background_color::background_color(&mime_type, data.get_ref());
+
             let (send, recv) = channel();
-            let thead = std::thread::spawn(move || animated_worker(format, data, mime_type, send));
+            let thead = std::thread::spawn(move || {
+                animated_worker(format, data, mime_type, assume_still, max_frames, send)
+            });
             *loader_impelementation.thread.lock().unwrap() = Some((thead, recv));
         } else {
+            if mime_type == "image/png" && progressive_png::is_interlaced(data.get_ref()) {
+                *loader_impelementation.interlaced_png.lock().unwrap() = Some(data.into_inner());
+            } else if mime_type == "image/jpeg" && jpeg_fallback::is_progressive(data.get_ref()) {
+                *loader_impelementation.progressive_jpeg.lock().unwrap() = Some(data.into_inner());
+            }
             *loader_impelementation.format.lock().unwrap() = Some(format);
         }
 
@@ -222,7 +512,28 @@ impl LoaderImplementation for ImgDecoder {
     }
 
     fn frame(&mut self, frame_request: FrameRequest) -> Result<Frame, ProcessError> {
-        let mut frame = if let Some(decoder) = std::mem::take(&mut *self.format.lock().unwrap()) {
+        if frame_request.auxiliary_image == Some(AuxiliaryImage::Depth) {
+            return match &*self.depth_map.lock().unwrap() {
+                Some(depth_map) => depth_map.frame(),
+                None => Err(ProcessError::expected(&"No depth map found")),
+            };
+        }
+
+        let exr = self.exr.lock().unwrap();
+        let mut frame = if let (Some(layer), Some(exr)) = (&frame_request.layer, &*exr) {
+            exr_layers::frame(exr, layer)?
+        } else if let (Some(index), Some(data)) =
+            (frame_request.sub_image, &*self.tiff_pages.lock().unwrap())
+        {
+            tiled_tiff::TiledTiff::page(data.clone(), index, FrameDetails::default())?
+                .frame(frame_request.clip)?
+        } else if let Some(tiled_tiff) = &mut *self.tiled_tiff.lock().unwrap() {
+            tiled_tiff.frame(frame_request.clip)?
+        } else if let Some(cmyk_tiff) = &mut *self.cmyk_tiff.lock().unwrap() {
+            cmyk_tiff.frame()?
+        } else if let Some(jpeg) = &mut *self.jpeg_fallback.lock().unwrap() {
+            jpeg.frame()?
+        } else if let Some(decoder) = std::mem::take(&mut *self.format.lock().unwrap()) {
             decoder.frame().expected_error()?
         } else if let Some((ref thread, ref recv)) = *self.thread.lock().unwrap() {
             thread.thread().unpark();
@@ -235,17 +546,120 @@ impl LoaderImplementation for ImgDecoder {
             return Err(ProcessError::NoMoreFrames);
         };
 
-        frame.details.color_cicp = self.cicp.lock().unwrap().map(|x| {
-            [
-                x.color_primaries.into(),
-                x.transfer_characteristics.into(),
-                x.matrix_coefficients.into(),
-                x.video_full_range_flag.into(),
-            ]
-        });
+        self.apply_cicp(&mut frame.details);
 
         Ok(frame)
     }
+
+    fn frame_progressive(
+        &mut self,
+        frame_request: FrameRequest,
+        progress: &mut dyn FnMut(Frame),
+    ) -> Result<Frame, ProcessError> {
+        let is_plain_request = frame_request.clip.is_none()
+            && frame_request.scale.is_none()
+            && frame_request.auxiliary_image.is_none();
+
+        let interlaced_png = std::mem::take(&mut *self.interlaced_png.lock().unwrap());
+        let progressive_jpeg = std::mem::take(&mut *self.progressive_jpeg.lock().unwrap());
+        let format = if is_plain_request && (interlaced_png.is_some() || progressive_jpeg.is_some())
+        {
+            std::mem::take(&mut *self.format.lock().unwrap())
+        } else {
+            None
+        };
+
+        match (interlaced_png, progressive_jpeg, format) {
+            (Some(data), _, Some(mut format)) => {
+                let mut details = format.frame_details()?;
+                drop(format);
+                self.apply_cicp(&mut details);
+                progressive_png::decode(&data, details, progress)
+            }
+            (_, Some(data), Some(mut format)) => {
+                let mut details = format.frame_details()?;
+                drop(format);
+                self.apply_cicp(&mut details);
+
+                let mut jpeg = jpeg_fallback::JpegFallback::new(data)?;
+                let mut frame = jpeg.frame_progressive(&mut |mut frame| {
+                    frame.details = details.clone();
+                    progress(frame);
+                })?;
+                frame.details = details;
+
+                Ok(frame)
+            }
+            _ => self.frame(frame_request),
+        }
+    }
+
+    fn color_profile(&mut self) -> Result<Option<BinaryData>, ProcessError> {
+        Ok(self.icc_profile.lock().unwrap().clone())
+    }
+}
+
+impl ImgDecoder {
+    fn init_jpeg_fallback(data: Vec<u8>) -> Result<(Self, ImageDetails), ProcessError> {
+        let (metadata, data) = match gufo::RawMetadata::for_guessed(data) {
+            Ok((metadata, data)) => (Some(metadata), data),
+            Err(err) => (None, err.into_inner()),
+        };
+
+        let jpeg = jpeg_fallback::JpegFallback::new(data)?;
+
+        let mut image_info = ImageDetails::new(jpeg.width(), jpeg.height());
+        image_info.info_format_name = Some(String::from("JPEG"));
+        image_info.info_alpha_channel = Some(false);
+
+        if let Some(metadata) = metadata {
+            image_info.metadata_exif = metadata
+                .exif
+                .first()
+                .map(BinaryData::from_data)
+                .transpose()
+                .expected_error()?;
+
+            image_info.metadata_xmp = metadata
+                .xmp
+                .first()
+                .map(BinaryData::from_data)
+                .transpose()
+                .expected_error()?;
+        }
+
+        let loader_impelementation = Self::default();
+        *loader_impelementation.icc_profile.lock().unwrap() = jpeg
+            .icc_profile()
+            .map(BinaryData::from_data)
+            .transpose()
+            .expected_error()?;
+        *loader_impelementation.jpeg_fallback.lock().unwrap() = Some(jpeg);
+
+        Ok((loader_impelementation, image_info))
+    }
+
+    /// Use CICP of the image if the frame doesn't carry its own
+    fn apply_cicp(&self, details: &mut FrameDetails) {
+        if details.color_icc_profile.is_none() && details.color_cicp.is_none() {
+            details.color_icc_profile = self.png_chunks_icc_profile.lock().unwrap().clone();
+        }
+
+        if details.color_cicp_fallback.is_none() {
+            details.color_cicp_fallback = self.cicp_fallback.lock().unwrap().map(|x| x.to_bytes());
+        }
+
+        if details.color_cicp.is_none() {
+            details.color_cicp = self.cicp.lock().unwrap().map(|x| {
+                [
+                    x.color_primaries.into(),
+                    x.transfer_characteristics.into(),
+                    x.matrix_coefficients.into(),
+                    x.video_full_range_flag.into(),
+                ]
+            });
+        }
+    }
 }
 
 pub enum ImageRsDecoder<T: std::io::BufRead + std::io::Seek> {
@@ -253,6 +667,7 @@ pub enum ImageRsDecoder<T: std::io::BufRead + std::io::Seek> {
     Dds(codecs::dds::DdsDecoder<T>),
     Farbfeld(codecs::farbfeld::FarbfeldDecoder<T>),
     Gif(codecs::gif::GifDecoder<T>),
+    Hdr(codecs::hdr::HdrDecoder<T>),
     Ico(codecs::ico::IcoDecoder<T>),
     Jpeg(codecs::jpeg::JpegDecoder<T>),
     OpenExr(codecs::openexr::OpenExrDecoder<T>),
@@ -284,11 +699,15 @@ impl ImageRsFormat<Reader> {
             ))
             .format_name("BMP")
             .default_bit_depth(8),
-            "image/x-dds" => Self::new(ImageRsDecoder::Dds(
-                codecs::dds::DdsDecoder::new(data).expected_error()?,
-            ))
-            .format_name("DDS")
-            .supports_two_grayscale_modes(true),
+            "image/x-dds" => {
+                let channel_bit_depths = dds_channel_bit_depths(data.get_ref());
+                Self::new(ImageRsDecoder::Dds(
+                    codecs::dds::DdsDecoder::new(data).expected_error()?,
+                ))
+                .format_name("DDS")
+                .supports_two_grayscale_modes(true)
+                .channel_bit_depths(channel_bit_depths)
+            }
             "image/x-ff" => Self::new(ImageRsDecoder::Farbfeld(
                 codecs::farbfeld::FarbfeldDecoder::new(data).expected_error()?,
             ))
@@ -299,6 +718,11 @@ impl ImageRsFormat<Reader> {
             ))
             .format_name("GIF")
             .default_bit_depth(8),
+            "image/vnd.radiance" => Self::new(ImageRsDecoder::Hdr(
+                codecs::hdr::HdrDecoder::new(data).expected_error()?,
+            ))
+            .format_name("Radiance HDR")
+            .default_bit_depth(32),
             "image/vnd.microsoft.icon" => Self::new(ImageRsDecoder::Ico(
                 codecs::ico::IcoDecoder::new(data).expected_error()?,
             ))
@@ -392,6 +816,11 @@ impl<'a, T: std::io::BufRead + std::io::Seek + 'a> ImageRsFormat<T> {
         self
     }
 
+    pub fn channel_bit_depths(mut self, channel_bit_depths: Option<Vec<u8>>) -> Self {
+        self.handler = self.handler.channel_bit_depths(channel_bit_depths);
+        self
+    }
+
     fn new(decoder: ImageRsDecoder<T>) -> Self {
         Self {
             decoder,
@@ -405,6 +834,7 @@ impl<'a, T: std::io::BufRead + std::io::Seek + 'a> ImageRsFormat<T> {
             ImageRsDecoder::Dds(ref mut d) => self.handler.info(d),
             ImageRsDecoder::Farbfeld(ref mut d) => self.handler.info(d),
             ImageRsDecoder::Gif(ref mut d) => self.handler.info(d),
+            ImageRsDecoder::Hdr(ref mut d) => self.handler.info(d),
             ImageRsDecoder::Ico(ref mut d) => self.handler.info(d),
             ImageRsDecoder::Jpeg(ref mut d) => self.handler.info(d),
             ImageRsDecoder::OpenExr(ref mut d) => self.handler.info(d),
@@ -423,6 +853,7 @@ impl<'a, T: std::io::BufRead + std::io::Seek + 'a> ImageRsFormat<T> {
             ImageRsDecoder::Dds(d) => self.handler.frame(d),
             ImageRsDecoder::Farbfeld(d) => self.handler.frame(d),
             ImageRsDecoder::Gif(d) => self.handler.frame(d),
+            ImageRsDecoder::Hdr(d) => self.handler.frame(d),
             ImageRsDecoder::Ico(d) => self.handler.frame(d),
             ImageRsDecoder::Jpeg(d) => self.handler.frame(d),
             ImageRsDecoder::OpenExr(d) => self.handler.frame(d),
@@ -441,6 +872,7 @@ impl<'a, T: std::io::BufRead + std::io::Seek + 'a> ImageRsFormat<T> {
             ImageRsDecoder::Dds(ref mut d) => self.handler.frame_details(d),
             ImageRsDecoder::Farbfeld(ref mut d) => self.handler.frame_details(d),
             ImageRsDecoder::Gif(ref mut d) => self.handler.frame_details(d),
+            ImageRsDecoder::Hdr(ref mut d) => self.handler.frame_details(d),
             ImageRsDecoder::Ico(ref mut d) => self.handler.frame_details(d),
             ImageRsDecoder::Jpeg(ref mut d) => self.handler.frame_details(d),
             ImageRsDecoder::OpenExr(ref mut d) => self.handler.frame_details(d),
@@ -461,6 +893,7 @@ impl<'a, T: std::io::BufRead + std::io::Seek + 'a> ImageRsFormat<T> {
             ImageRsDecoder::Dds(ref mut d) => d.set_limits(limits),
             ImageRsDecoder::Farbfeld(ref mut d) => d.set_limits(limits),
             ImageRsDecoder::Gif(ref mut d) => d.set_limits(limits),
+            ImageRsDecoder::Hdr(ref mut d) => d.set_limits(limits),
             ImageRsDecoder::Ico(ref mut d) => d.set_limits(limits),
             ImageRsDecoder::Jpeg(ref mut d) => d.set_limits(limits),
             ImageRsDecoder::OpenExr(ref mut d) => d.set_limits(limits),
@@ -493,3 +926,207 @@ impl<'a, T: std::io::BufRead + std::io::Seek + 'a> ImageRsDecoder<T> {
         }
     }
 }
+
+/// Bit depths of the channels of the colors stored in a DDS
+///
+/// The supported block compressed formats store their colors as RGB565. The
+/// alpha of DXT3 has 4 bits and DXT5 interpolates between 8 bit values.
+fn dds_channel_bit_depths(data: &[u8]) -> Option<Vec<u8>> {
+    let dxt1 = Some(vec![5, 6, 5]);
+    let dxt3 = Some(vec![5, 6, 5, 4]);
+    let dxt5 = Some(vec![5, 6, 5, 8]);
+
+    match data.get(84..88)? {
+        b"DXT1" => dxt1,
+        b"DXT3" => dxt3,
+        b"DXT5" => dxt5,
+        b"DX10" => match u32::from_le_bytes(data.get(128..132)?.try_into().ok()?) {
+            70..=72 => dxt1,
+            73..=75 => dxt3,
+            76..=78 => dxt5,
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+
+    use gufo_common::orientation::Orientation;
+
+    use super::*;
+
+    /// Exif data with orientation "rotated by 90 degrees clockwise"
+    const EXIF: &[u8] = &[
+        b'M', b'M', 0, 42, 0, 0, 0, 8, // Header
+        0, 1, // One entry
+        0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0, // Orientation
+        0, 0, 0, 0, // No next IFD
+    ];
+
+    const XMP: &[u8] = b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"></x:xmpmeta>";
+
+    fn png(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, 1, 1);
+        encoder.set_color(png::ColorType::Grayscale);
+        let mut writer = encoder.write_header().unwrap();
+        for (chunk_type, chunk_data) in chunks {
+            writer
+                .write_chunk(png::chunk::ChunkType(**chunk_type), chunk_data)
+                .unwrap();
+        }
+        writer.write_image_data(&[0]).unwrap();
+        writer.finish().unwrap();
+
+        data
+    }
+
+    fn init(data: &[u8]) -> ImageDetails {
+        init_with(data, "image/png", Default::default()).1
+    }
+
+    fn init_with(
+        data: &[u8],
+        mime_type: &str,
+        details: InitializationDetails,
+    ) -> (ImgDecoder, ImageDetails) {
+        let (mut send, recv) = UnixStream::pair().unwrap();
+        send.write_all(data).unwrap();
+        drop(send);
+
+        ImgDecoder::init(recv, String::from(mime_type), details).unwrap()
+    }
+
+    fn orientation(details: &ImageDetails) -> Option<Orientation> {
+        let exif = details.metadata_exif.as_ref()?.get_full().unwrap();
+        gufo_exif::Exif::new(exif).unwrap().orientation()
+    }
+
+    #[test]
+    fn png_exif() {
+        let details = init(&png(&[(b"eXIf", EXIF)]));
+        assert_eq!(orientation(&details), Some(Orientation::Rotation270));
+
+        let prefixed = [EXIF_PREFIX, EXIF].concat();
+        let details = init(&png(&[(b"eXIf", &prefixed)]));
+        assert_eq!(orientation(&details), Some(Orientation::Rotation270));
+    }
+
+    #[test]
+    fn alpha_channel() {
+        assert_eq!(init(&png(&[])).info_alpha_channel, Some(false));
+        // Transparency via tRNS chunk
+        assert_eq!(
+            init(&png(&[(b"tRNS", &[0, 0])])).info_alpha_channel,
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn animation_time() {
+        let mut time = AnimationTime::default();
+        assert_eq!(time.duration(), std::time::Duration::ZERO);
+
+        // Delays that can't be represented exactly don't accumulate errors
+        for _ in 0..3000 {
+            time.add(100, 3);
+        }
+        assert_eq!(time.duration(), std::time::Duration::from_secs(100));
+
+        time.add(10, 1);
+        assert_eq!(time.duration(), std::time::Duration::from_millis(100_010));
+    }
+
+    #[test]
+    fn png_xmp() {
+        let itxt = [b"XML:com.adobe.xmp\0\0\0\0\0".as_slice(), XMP].concat();
+        let details = init(&png(&[(b"iTXt", &itxt)]));
+
+        assert_eq!(
+            details.metadata_xmp.unwrap().get_full().unwrap(),
+            XMP.to_vec()
+        );
+    }
+
+    #[test]
+    fn dds_channel_bit_depths() {
+        let mut dds = b"DDS ".to_vec();
+        let header: [u32; 31] = [
+            124,
+            0x1007,
+            4,
+            4,
+            8,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0, // Header
+            32,
+            0x4,
+            u32::from_le_bytes(*b"DXT1"),
+            0,
+            0,
+            0,
+            0,
+            0, // Pixel format
+            0x1000,
+            0,
+            0,
+            0,
+            0, // Caps
+        ];
+        dds.extend(header.iter().flat_map(|x| x.to_le_bytes()));
+        // Red block
+        dds.extend([0x00, 0xf8, 0x00, 0xf8, 0, 0, 0, 0]);
+
+        let (mut decoder, _) = init_with(&dds, "image/x-dds", Default::default());
+        let frame = decoder.frame(FrameRequest::default()).unwrap();
+        assert_eq!(frame.memory_format, MemoryFormat::R8g8b8);
+        assert_eq!(frame.details.channel_bit_depths, Some(vec![5, 6, 5]));
+        assert_eq!(frame.details.info_bit_depth, Some(6));
+    }
+
+    #[test]
+    fn max_frames_across_loops() {
+        let mut gif = Vec::new();
+        {
+            let mut encoder = image::codecs::gif::GifEncoder::new(&mut gif);
+            encoder
+                .set_repeat(image::codecs::gif::Repeat::Infinite)
+                .unwrap();
+            for value in [0, 255] {
+                let buffer = image::RgbaImage::from_pixel(1, 1, image::Rgba([value, 0, 0, 255]));
+                encoder.encode_frame(image::Frame::new(buffer)).unwrap();
+            }
+        }
+
+        let mut details = InitializationDetails::default();
+        details.max_frames = Some(5);
+        let (mut decoder, _) = init_with(&gif, "image/gif", details);
+
+        let mut frame_request = FrameRequest::default();
+        frame_request.loop_animation = true;
+
+        // The animation is looped until the limit is reached
+        for _ in 0..5 {
+            decoder.frame(frame_request.clone()).unwrap();
+        }
+        assert!(matches!(
+            decoder.frame(frame_request),
+            Err(ProcessError::NoMoreFrames)
+        ));
+    }
+}
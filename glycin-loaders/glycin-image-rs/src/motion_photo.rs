@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+
+use glycin_utils::{KEY_MOTION_PHOTO_VIDEO_LENGTH, KEY_MOTION_PHOTO_VIDEO_OFFSET};
+
+/// Marker before the video in Samsung motion photos
+const SAMSUNG_MARKER: &[u8] = b"MotionPhoto_Data";
+/// Marker at the end of Samsung's trailer
+const SAMSUNG_TRAILER_END: &[u8] = b"SEFT";
+
+/// Position of the video embedded in a motion photo as key-value pairs
+///
+/// Google's motion photos announce the video in the XMP data, either via
+/// `GCamera:MicroVideoOffset` or via the `Container:Directory`. Samsung's
+/// motion photos place the video after a `MotionPhoto_Data` marker.
+pub fn key_value(xmp: Option<&[u8]>, data: &[u8]) -> BTreeMap<String, String> {
+    let mut key_value = BTreeMap::new();
+
+    let video = xmp
+        .and_then(|xmp| std::str::from_utf8(xmp).ok())
+        .and_then(|xmp| google_video(xmp, data.len()))
+        .or_else(|| samsung_video(data));
+
+    if let Some((offset, length)) = video {
+        key_value.insert(
+            String::from(KEY_MOTION_PHOTO_VIDEO_OFFSET),
+            offset.to_string(),
+        );
+        key_value.insert(
+            String::from(KEY_MOTION_PHOTO_VIDEO_LENGTH),
+            length.to_string(),
+        );
+    }
+
+    key_value
+}
+
+/// Offset and length of the video at the end of the file
+fn google_video(xmp: &str, file_len: usize) -> Option<(usize, usize)> {
+    // Motion Photo format version 1
+    let length = xmp.split("<Container:Item").skip(1).find_map(|item| {
+        let item = item.split('>').next()?;
+        if xmp_value(item, "Item:Semantic")? != "MotionPhoto" {
+            return None;
+        }
+        xmp_value(item, "Item:Length")?.parse::<usize>().ok()
+    });
+
+    // Older micro video format, the offset is counted from the end of the file
+    let length = length.or_else(|| {
+        xmp_value(xmp, "GCamera:MicroVideoOffset")?
+            .parse::<usize>()
+            .ok()
+    })?;
+
+    if length == 0 {
+        return None;
+    }
+
+    Some((file_len.checked_sub(length)?, length))
+}
+
+/// Offset and length of the video after the marker
+fn samsung_video(data: &[u8]) -> Option<(usize, usize)> {
+    let marker = data
+        .windows(SAMSUNG_MARKER.len())
+        .position(|x| x == SAMSUNG_MARKER)?;
+    let offset = marker.checked_add(SAMSUNG_MARKER.len())?;
+
+    // The trailer ends with its size followed by `SEFT`
+    let mut end = data.len();
+    if data.ends_with(SAMSUNG_TRAILER_END) {
+        let size_pos = data.len().checked_sub(8)?;
+        let size = data.get(size_pos..size_pos.checked_add(4)?)?;
+        let size = u32::from_le_bytes(size.try_into().ok()?);
+        if let Some(trailer_start) = usize::try_from(size)
+            .ok()
+            .and_then(|size| size_pos.checked_sub(size))
+            .filter(|x| *x >= offset)
+        {
+            end = trailer_start;
+        }
+    }
+
+    let length = end.checked_sub(offset).filter(|x| *x > 0)?;
+
+    Some((offset, length))
+}
+
+/// Value of an XMP property in attribute or element form
+pub fn xmp_value<'a>(xmp: &'a str, name: &str) -> Option<&'a str> {
+    let attribute = format!("{name}=\"");
+    if let Some((_, rest)) = xmp.split_once(&attribute) {
+        return rest.split('"').next();
+    }
+
+    let element = format!("<{name}>");
+    let (_, rest) = xmp.split_once(&element)?;
+    rest.split('<').next().map(str::trim)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn video(key_value: &BTreeMap<String, String>) -> Option<(&str, &str)> {
+        Some((
+            key_value.get(KEY_MOTION_PHOTO_VIDEO_OFFSET)?.as_str(),
+            key_value.get(KEY_MOTION_PHOTO_VIDEO_LENGTH)?.as_str(),
+        ))
+    }
+
+    #[test]
+    fn google_container() {
+        let xmp = br#"<Container:Directory><rdf:Seq>
+            <rdf:li><Container:Item Item:Mime="image/jpeg" Item:Semantic="Primary" Item:Length="0"/></rdf:li>
+            <rdf:li><Container:Item Item:Mime="video/mp4" Item:Semantic="MotionPhoto" Item:Length="30"/></rdf:li>
+            </rdf:Seq></Container:Directory>"#;
+
+        let result = key_value(Some(xmp), &[0; 100]);
+        assert_eq!(video(&result), Some(("70", "30")));
+    }
+
+    #[test]
+    fn google_micro_video() {
+        let xmp = br#"<rdf:Description GCamera:MicroVideo="1" GCamera:MicroVideoOffset="40"/>"#;
+        let result = key_value(Some(xmp), &[0; 100]);
+        assert_eq!(video(&result), Some(("60", "40")));
+
+        // Offset larger than the file
+        let result = key_value(Some(xmp), &[0; 10]);
+        assert_eq!(video(&result), None);
+    }
+
+    #[test]
+    fn samsung() {
+        let data = [b"JPEG".as_slice(), SAMSUNG_MARKER, b"video"].concat();
+        let result = key_value(None, &data);
+        assert_eq!(video(&result), Some(("20", "5")));
+
+        // Trailer after the video with its size and `SEFT` at the end
+        let data = [
+            b"JPEG".as_slice(),
+            SAMSUNG_MARKER,
+            b"video",
+            b"trailer",
+            &7_u32.to_le_bytes(),
+            SAMSUNG_TRAILER_END,
+        ]
+        .concat();
+        let result = key_value(None, &data);
+        assert_eq!(video(&result), Some(("20", "5")));
+    }
+
+    #[test]
+    fn no_video() {
+        assert!(key_value(None, b"JPEG").is_empty());
+        assert!(key_value(Some(b"<x:xmpmeta/>"), b"JPEG").is_empty());
+    }
+}
@@ -0,0 +1,288 @@
+//! Color space from the `gAMA` and `cHRM` chunks of PNGs
+
+use gufo_common::cicp::Cicp;
+use png::{ScaledFloat, SourceChromaticities};
+
+/// Maximum difference of chromaticities to match a known color space
+const TOLERANCE: u32 = 500;
+
+/// Chromaticities of red, green, blue, and white with their CICP code
+///
+/// All use the D65 white point.
+const PRIMARIES: &[(u8, [(u32, u32); 4])] = &[
+    // BT.709 and sRGB
+    (
+        1,
+        [
+            (64000, 33000),
+            (30000, 60000),
+            (15000, 6000),
+            (31270, 32900),
+        ],
+    ),
+    // BT.2020
+    (
+        9,
+        [
+            (70800, 29200),
+            (17000, 79700),
+            (13100, 4600),
+            (31270, 32900),
+        ],
+    ),
+    // Display P3
+    (
+        12,
+        [
+            (68000, 32000),
+            (26500, 69000),
+            (15000, 6000),
+            (31270, 32900),
+        ],
+    ),
+];
+
+/// CICP that is equivalent to the `gAMA` and `cHRM` chunks
+///
+/// Returns `None` if the PNG has a `cICP`, `iCCP`, or `sRGB` chunk, if the
+/// chunks describe sRGB anyway, or if there is no equivalent CICP. In the
+/// latter case, [`icc_profile`] describes the color space.
+pub fn cicp(data: &[u8]) -> Option<Cicp> {
+    let reader = png::Decoder::new(std::io::Cursor::new(data))
+        .read_info()
+        .ok()?;
+    let info = reader.info();
+
+    if info.coding_independent_code_points.is_some()
+        || info.icc_profile.is_some()
+        || info.srgb.is_some()
+    {
+        return None;
+    }
+
+    chunks_cicp(info).filter(|x| *x != Cicp::SRGB)
+}
+
+/// ICC profile that is equivalent to the `gAMA` and `cHRM` chunks
+///
+/// Only returns a profile if there is no equivalent CICP, for example, for
+/// a gamma of 1/1.8 or custom chromaticities. Returns `None` if the PNG has
+/// a `cICP`, `iCCP`, or `sRGB` chunk.
+pub fn icc_profile(data: &[u8]) -> Option<Vec<u8>> {
+    let reader = png::Decoder::new(std::io::Cursor::new(data))
+        .read_info()
+        .ok()?;
+    let info = reader.info();
+
+    if info.coding_independent_code_points.is_some()
+        || info.icc_profile.is_some()
+        || info.srgb.is_some()
+    {
+        return None;
+    }
+
+    if info.gama_chunk.is_none() && info.chrm_chunk.is_none() || chunks_cicp(info).is_some() {
+        return None;
+    }
+
+    chunks_icc_profile(info)
+}
+
+/// CICP to use if the `iCCP` chunk can't be applied
+///
+/// The PNG specification allows `sRGB`, `gAMA`, and `cHRM` chunks next to
+/// the ICC profile for decoders that don't support ICC profiles. Returns
+/// `None` if the PNG has no ICC profile or none of these chunks. A `cICP`
+/// chunk takes precedence over all of them, such that `None` is returned as
+/// well.
+pub fn fallback_cicp(data: &[u8]) -> Option<Cicp> {
+    let reader = png::Decoder::new(std::io::Cursor::new(data))
+        .read_info()
+        .ok()?;
+    let info = reader.info();
+
+    if info.icc_profile.is_none() || info.coding_independent_code_points.is_some() {
+        None
+    } else if info.srgb.is_some() {
+        Some(Cicp::SRGB)
+    } else if info.gama_chunk.is_some() || info.chrm_chunk.is_some() {
+        chunks_cicp(info)
+    } else {
+        None
+    }
+}
+
+fn chunks_cicp(info: &png::Info) -> Option<Cicp> {
+    let color_primaries = match &info.chrm_chunk {
+        Some(chromaticities) => color_primaries(chromaticities)?,
+        None => 1,
+    };
+
+    let transfer_characteristics = match info.gama_chunk.map(ScaledFloat::into_scaled) {
+        // Gamma of 1/2.2 is treated as the sRGB transfer function by most
+        // applications
+        Some(45000..=46000) | None => 13,
+        Some(99000..=101000) => 8,
+        Some(_) => return None,
+    };
+
+    Cicp::from_bytes(&[color_primaries, transfer_characteristics, 0, 1]).ok()
+}
+
+fn chunks_icc_profile(info: &png::Info) -> Option<Vec<u8>> {
+    let [red, green, blue, white] = match &info.chrm_chunk {
+        Some(chromaticities) => [
+            chromaticities.red,
+            chromaticities.green,
+            chromaticities.blue,
+            chromaticities.white,
+        ]
+        .map(|(x, y)| (f64::from(x.into_value()), f64::from(y.into_value()))),
+        // Chromaticities of sRGB
+        None => [(0.64, 0.33), (0.30, 0.60), (0.15, 0.06), (0.3127, 0.3290)],
+    };
+    let xyy = |(x, y)| lcms2::CIExyY { x, y, Y: 1. };
+
+    let transfer_function = match info.gama_chunk {
+        // The chunk stores the encoding gamma
+        Some(gamma) if gamma.into_scaled() > 0 => {
+            lcms2::ToneCurve::new(1. / f64::from(gamma.into_value()))
+        }
+        Some(_) => return None,
+        // Transfer function of sRGB
+        None => lcms2::ToneCurve::new_parametric(
+            4,
+            &[2.4, 1. / 1.055, 0.055 / 1.055, 1. / 12.92, 0.04045],
+        )
+        .ok()?,
+    };
+
+    let profile = lcms2::Profile::new_rgb(
+        &xyy(white),
+        &lcms2::CIExyYTRIPLE {
+            Red: xyy(red),
+            Green: xyy(green),
+            Blue: xyy(blue),
+        },
+        &[&transfer_function, &transfer_function, &transfer_function],
+    )
+    .ok()?;
+
+    profile.icc().ok()
+}
+
+fn color_primaries(chromaticities: &SourceChromaticities) -> Option<u8> {
+    let values = [
+        chromaticities.red,
+        chromaticities.green,
+        chromaticities.blue,
+        chromaticities.white,
+    ]
+    .map(|(x, y)| (x.into_scaled(), y.into_scaled()));
+
+    PRIMARIES
+        .iter()
+        .find(|(_, primaries)| {
+            primaries.iter().zip(&values).all(|((x0, y0), (x1, y1))| {
+                x0.abs_diff(*x1) <= TOLERANCE && y0.abs_diff(*y1) <= TOLERANCE
+            })
+        })
+        .map(|(code, _)| *code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(icc_profile: bool, gamma: Option<u32>) -> Vec<u8> {
+        png_with_cicp(icc_profile, gamma, None)
+    }
+
+    fn png_with_cicp(icc_profile: bool, gamma: Option<u32>, cicp: Option<[u8; 4]>) -> Vec<u8> {
+        let mut info = png::Info::with_size(1, 1);
+        if icc_profile {
+            info.icc_profile = Some(b"broken".as_slice().into());
+        }
+
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::with_info(&mut data, info).unwrap();
+        encoder.set_color(png::ColorType::Rgb);
+        if let Some(gamma) = gamma {
+            encoder.set_source_gamma(ScaledFloat::from_scaled(gamma));
+        }
+        let mut writer = encoder.write_header().unwrap();
+        if let Some(cicp) = cicp {
+            writer.write_chunk(png::chunk::cICP, &cicp).unwrap();
+        }
+        writer.write_image_data(&[0; 3]).unwrap();
+        writer.finish().unwrap();
+
+        data
+    }
+
+    #[test]
+    fn fallback() {
+        let linear = Cicp::from_bytes(&[1, 8, 0, 1]).unwrap();
+
+        assert_eq!(cicp(&png(false, Some(100000))), Some(linear));
+        assert_eq!(cicp(&png(true, Some(100000))), None);
+
+        assert_eq!(fallback_cicp(&png(true, Some(100000))), Some(linear));
+        assert_eq!(fallback_cicp(&png(true, Some(45455))), Some(Cicp::SRGB));
+        assert_eq!(fallback_cicp(&png(true, None)), None);
+        assert_eq!(fallback_cicp(&png(false, Some(100000))), None);
+    }
+
+    #[test]
+    fn gamma_without_cicp() {
+        // Gamma of 1/1.8 has no CICP equivalent
+        let data = png(false, Some(55556));
+        assert_eq!(cicp(&data), None);
+
+        let profile = lcms2::Profile::new_icc(&icc_profile(&data).unwrap()).unwrap();
+        let transform = lcms2::Transform::<[u8; 3], [u8; 3]>::new(
+            &profile,
+            lcms2::PixelFormat::RGB_8,
+            &lcms2::Profile::new_srgb(),
+            lcms2::PixelFormat::RGB_8,
+            lcms2::Intent::RelativeColorimetric,
+        )
+        .unwrap();
+
+        // Mid-gray is brighter than with the sRGB transfer function
+        let mut output = [[0; 3]];
+        transform.transform_pixels(&[[128, 128, 128]], &mut output);
+        assert!(
+            output[0].iter().all(|x| (140..152).contains(x)),
+            "{output:?}"
+        );
+
+        // Chunks with CICP equivalent or ICC profile don't need a profile
+        assert_eq!(icc_profile(&png(false, Some(100000))), None);
+        assert_eq!(icc_profile(&png(false, Some(45455))), None);
+        assert_eq!(icc_profile(&png(true, Some(55556))), None);
+        assert_eq!(icc_profile(&png(false, None)), None);
+    }
+
+    #[test]
+    fn cicp_chunk_precedence() {
+        // BT.2100 PQ
+        let pq = Some([9, 16, 0, 1]);
+
+        for (icc, gamma) in [
+            (false, Some(55556)),
+            (false, Some(100000)),
+            (true, Some(100000)),
+        ] {
+            let data = png_with_cicp(icc, gamma, pq);
+            assert_eq!(cicp(&data), None, "{icc} {gamma:?}");
+            assert_eq!(icc_profile(&data), None, "{icc} {gamma:?}");
+            assert_eq!(fallback_cicp(&data), None, "{icc} {gamma:?}");
+        }
+
+        // Without the cICP chunk, the same files provide color information
+        assert!(icc_profile(&png(false, Some(55556))).is_some());
+        assert!(cicp(&png(false, Some(100000))).is_some());
+        assert!(fallback_cicp(&png(true, Some(100000))).is_some());
+    }
+}
@@ -0,0 +1,108 @@
+//! Progressive decoding of interlaced PNGs
+
+use glycin_utils::safe_math::*;
+use glycin_utils::*;
+
+/// Returns if the PNG data use Adam7 interlacing
+///
+/// The interlace method is the last byte of the IHDR chunk, which always
+/// directly follows the PNG signature.
+pub fn is_interlaced(data: &[u8]) -> bool {
+    data.get(12..16) == Some(b"IHDR".as_slice()) && data.get(28) == Some(&1)
+}
+
+/// Decode an interlaced PNG and pass a preview to `progress` after each pass
+///
+/// Pixels that are not decoded yet are filled with the nearest decoded
+/// pixel. All frames use the given `details`.
+pub fn decode(
+    data: &[u8],
+    details: FrameDetails,
+    progress: &mut dyn FnMut(Frame),
+) -> Result<Frame, ProcessError> {
+    let mut decoder = png::Decoder::new(std::io::Cursor::new(data));
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().expected_error()?;
+
+    let (width, height) = (reader.info().width, reader.info().height);
+    let (color_type, bit_depth) = reader.output_color_type();
+    let memory_format = memory_format(color_type, bit_depth)?;
+    let bits_per_pixel = (color_type.samples() * bit_depth as usize)
+        .try_into()
+        .map_err(|_| DimensionTooLargerError)?;
+    let is_16_bit = bit_depth == png::BitDepth::Sixteen;
+
+    let stride = reader
+        .output_line_size(width)
+        .ok_or(DimensionTooLargerError)?;
+    let mut canvas = vec![0; stride.smul(height.try_usize()?)?];
+
+    let mut pass = 1;
+    while let Some(row) = reader.next_interlaced_row().expected_error()? {
+        let png::InterlaceInfo::Adam7(info) = row.interlace() else {
+            return Err(ProcessError::expected(&"PNG is not interlaced"));
+        };
+
+        // The first line of a pass means that all previous passes are complete.
+        // Passes without any pixels are skipped by the decoder.
+        if let Some(next_pass) = (pass + 1..=7).find(|x| *info == png::Adam7Info::new(*x, 0, width))
+        {
+            progress(frame(
+                width,
+                height,
+                memory_format,
+                is_16_bit,
+                canvas.clone(),
+                details.clone(),
+            )?);
+            pass = next_pass;
+        }
+
+        png::splat_interlaced_row(&mut canvas, stride, row.data(), info, bits_per_pixel);
+    }
+
+    frame(width, height, memory_format, is_16_bit, canvas, details)
+}
+
+fn frame(
+    width: u32,
+    height: u32,
+    memory_format: MemoryFormat,
+    is_16_bit: bool,
+    mut data: Vec<u8>,
+    details: FrameDetails,
+) -> Result<Frame, ProcessError> {
+    // PNG stores samples in big-endian
+    if is_16_bit {
+        for sample in data.chunks_exact_mut(2) {
+            let value = u16::from_be_bytes([sample[0], sample[1]]);
+            sample.copy_from_slice(&value.to_ne_bytes());
+        }
+    }
+
+    let texture = BinaryData::from_data(data).expected_error()?;
+
+    let mut frame = Frame::new(width, height, memory_format, texture)?;
+    frame.details = details;
+
+    Ok(frame)
+}
+
+fn memory_format(
+    color_type: png::ColorType,
+    bit_depth: png::BitDepth,
+) -> Result<MemoryFormat, ProcessError> {
+    use png::{BitDepth, ColorType};
+
+    Ok(match (color_type, bit_depth) {
+        (ColorType::Grayscale, BitDepth::Eight) => MemoryFormat::G8,
+        (ColorType::Grayscale, BitDepth::Sixteen) => MemoryFormat::G16,
+        (ColorType::GrayscaleAlpha, BitDepth::Eight) => MemoryFormat::G8a8,
+        (ColorType::GrayscaleAlpha, BitDepth::Sixteen) => MemoryFormat::G16a16,
+        (ColorType::Rgb, BitDepth::Eight) => MemoryFormat::R8g8b8,
+        (ColorType::Rgb, BitDepth::Sixteen) => MemoryFormat::R16g16b16,
+        (ColorType::Rgba, BitDepth::Eight) => MemoryFormat::R8g8b8a8,
+        (ColorType::Rgba, BitDepth::Sixteen) => MemoryFormat::R16g16b16a16,
+        _ => return Err(ProcessError::expected(&"Unsupported PNG color type")),
+    })
+}
@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+
+use glycin_utils::{KEY_RADIANCE_EXPOSURE, KEY_RADIANCE_PRIMARIES};
+use gufo_common::cicp::{
+    Cicp, ColorPrimaries, MatrixCoefficients, TransferCharacteristics, VideoRangeFlag,
+};
+use image::codecs::hdr::HdrMetadata;
+
+/// Chromaticities as `[rx, ry, gx, gy, bx, by, wx, wy]`
+const KNOWN_PRIMARIES: &[([f32; 8], ColorPrimaries)] = &[
+    (
+        [0.640, 0.330, 0.300, 0.600, 0.150, 0.060, 0.3127, 0.3290],
+        ColorPrimaries::Srgb,
+    ),
+    (
+        [0.708, 0.292, 0.170, 0.797, 0.131, 0.046, 0.3127, 0.3290],
+        ColorPrimaries::Rec2020,
+    ),
+    (
+        [0.680, 0.320, 0.265, 0.690, 0.150, 0.060, 0.314, 0.351],
+        ColorPrimaries::DciP3,
+    ),
+    (
+        [0.680, 0.320, 0.265, 0.690, 0.150, 0.060, 0.3127, 0.3290],
+        ColorPrimaries::DisplayP3,
+    ),
+];
+
+/// Tolerance for comparing chromaticities with the known primaries
+const PRIMARIES_TOLERANCE: f32 = 0.002;
+
+/// Calibration information from the Radiance header as key-value pairs
+///
+/// Contains the `EXPOSURE` and `PRIMARIES` as they are found in the header
+/// with the keys [`KEY_RADIANCE_EXPOSURE`] and [`KEY_RADIANCE_PRIMARIES`].
+pub fn key_value(metadata: &HdrMetadata) -> BTreeMap<String, String> {
+    let mut key_value = BTreeMap::new();
+
+    if let Some(exposure) = metadata.exposure {
+        key_value.insert(String::from(KEY_RADIANCE_EXPOSURE), exposure.to_string());
+    }
+
+    if let Some(primaries) = primaries_attribute(metadata) {
+        key_value.insert(
+            String::from(KEY_RADIANCE_PRIMARIES),
+            primaries.trim().to_string(),
+        );
+    }
+
+    key_value
+}
+
+/// CICP for the `PRIMARIES` in the header
+///
+/// Returns `None` if the header doesn't specify primaries or if they don't
+/// correspond to any primaries that can be expressed via CICP. The pixel
+/// values of Radiance images are always linear.
+pub fn cicp(metadata: &HdrMetadata) -> Option<Cicp> {
+    let primaries = primaries_attribute(metadata)?
+        .split_whitespace()
+        .map(|x| x.parse::<f32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    let (_, color_primaries) = KNOWN_PRIMARIES.iter().find(|(known, _)| {
+        primaries.len() == known.len()
+            && primaries
+                .iter()
+                .zip(known)
+                .all(|(x, y)| (x - y).abs() <= PRIMARIES_TOLERANCE)
+    })?;
+
+    Some(Cicp {
+        color_primaries: *color_primaries,
+        transfer_characteristics: TransferCharacteristics::Linear,
+        matrix_coefficients: MatrixCoefficients::Identity,
+        video_full_range_flag: VideoRangeFlag::Full,
+    })
+}
+
+fn primaries_attribute(metadata: &HdrMetadata) -> Option<&str> {
+    metadata
+        .custom_attributes
+        .iter()
+        .rev()
+        .find(|(key, _)| key == "PRIMARIES")
+        .map(|(_, value)| value.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::codecs::hdr::HdrDecoder;
+
+    use super::*;
+
+    const SRGB: &str = "0.640 0.330 0.300 0.600 0.150 0.060 0.3127 0.3290";
+
+    fn hdr_metadata(header: &[&str]) -> HdrMetadata {
+        let mut data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n".to_vec();
+        for line in header {
+            data.extend_from_slice(line.as_bytes());
+            data.push(b'\n');
+        }
+        data.extend_from_slice(b"\n-Y 1 +X 1\n");
+        data.extend_from_slice(&[128, 128, 128, 129]);
+
+        HdrDecoder::new(Cursor::new(data)).unwrap().metadata()
+    }
+
+    #[test]
+    fn primaries_within_tolerance() {
+        let metadata =
+            hdr_metadata(&["PRIMARIES=0.6405 0.3298 0.301 0.599 0.15 0.06 0.3127 0.329"]);
+
+        let result = cicp(&metadata).unwrap();
+        assert_eq!(result.color_primaries, ColorPrimaries::Srgb);
+        assert_eq!(
+            result.transfer_characteristics,
+            TransferCharacteristics::Linear
+        );
+
+        let metadata =
+            hdr_metadata(&["PRIMARIES=0.708 0.292 0.170 0.797 0.131 0.046 0.3127 0.3290"]);
+        assert_eq!(
+            cicp(&metadata).unwrap().color_primaries,
+            ColorPrimaries::Rec2020
+        );
+    }
+
+    #[test]
+    fn primaries_unknown() {
+        for header in [
+            // Outside of the tolerance
+            "PRIMARIES=0.650 0.330 0.300 0.600 0.150 0.060 0.3127 0.3290",
+            // CIE XYZ
+            "PRIMARIES=1 0 0 1 0 0 0.333 0.333",
+            "PRIMARIES=0.640 0.330 0.300 0.600 0.150 0.060",
+            "PRIMARIES=0.640 0.330 0.300 0.600 0.150 0.060 0.3127 white",
+        ] {
+            assert!(cicp(&hdr_metadata(&[header])).is_none(), "{header}");
+        }
+
+        assert!(cicp(&hdr_metadata(&[])).is_none());
+    }
+
+    #[test]
+    fn primaries_last_line() {
+        let metadata = hdr_metadata(&[
+            "PRIMARIES=0.708 0.292 0.170 0.797 0.131 0.046 0.3127 0.3290",
+            &format!("PRIMARIES= {SRGB} "),
+        ]);
+
+        assert_eq!(
+            cicp(&metadata).unwrap().color_primaries,
+            ColorPrimaries::Srgb
+        );
+        assert_eq!(
+            key_value(&metadata).get(KEY_RADIANCE_PRIMARIES).unwrap(),
+            SRGB
+        );
+    }
+
+    #[test]
+    fn exposure() {
+        let values = key_value(&hdr_metadata(&[
+            "EXPOSURE=2.5",
+            &format!("PRIMARIES={SRGB}"),
+        ]));
+
+        assert_eq!(values.get(KEY_RADIANCE_EXPOSURE).unwrap(), "2.5");
+        assert_eq!(values.get(KEY_RADIANCE_PRIMARIES).unwrap(), SRGB);
+        assert_eq!(values.len(), 2);
+
+        // Multiple exposures are multiplied
+        let values = key_value(&hdr_metadata(&["EXPOSURE=2", "EXPOSURE=0.25"]));
+        assert_eq!(values.get(KEY_RADIANCE_EXPOSURE).unwrap(), "0.5");
+
+        assert!(key_value(&hdr_metadata(&[])).is_empty());
+    }
+}
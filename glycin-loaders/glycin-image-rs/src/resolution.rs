@@ -0,0 +1,271 @@
+//! Physical size from the resolution stored in raster images
+//!
+//! JPEG stores the resolution in the JFIF header, PNG in the pHYs chunk, and
+//! TIFF in its resolution tags. For all formats, the resolution tags of the
+//! Exif data are used as fallback.
+
+use std::io::Cursor;
+
+use gufo_common::exif::{Ifd, Tag as ExifTag, TagIfd};
+use tiff::decoder::Decoder;
+use tiff::tags::Tag;
+
+const CM_PER_INCH: f64 = 2.54;
+const M_PER_INCH: f64 = 0.0254;
+
+/// Resolution tags in the primary Exif IFD
+const EXIF_X_RESOLUTION: TagIfd = TagIfd {
+    tag: ExifTag(0x11A),
+    ifd: Ifd::Primary,
+};
+const EXIF_Y_RESOLUTION: TagIfd = TagIfd {
+    tag: ExifTag(0x11B),
+    ifd: Ifd::Primary,
+};
+const EXIF_RESOLUTION_UNIT: TagIfd = TagIfd {
+    tag: ExifTag(0x128),
+    ifd: Ifd::Primary,
+};
+
+/// Unit of resolution tags in TIFF and Exif
+const TIFF_UNIT_INCH: u16 = 2;
+const TIFF_UNIT_CM: u16 = 3;
+
+/// Physical dimensions of the image in inch
+///
+/// Returns `None` if the image doesn't store a resolution with a physical
+/// unit.
+pub fn dimensions_inch(
+    mime_type: &str,
+    data: &[u8],
+    exif: Option<&[u8]>,
+    width: u32,
+    height: u32,
+) -> Option<(f64, f64)> {
+    let resolution = match mime_type {
+        "image/jpeg" => jfif(data),
+        "image/png" | "image/apng" => png(data),
+        "image/tiff" => tiff(data),
+        _ => None,
+    };
+
+    let (dpi_x, dpi_y) = resolution.or_else(|| exif.and_then(self::exif))?;
+
+    if !(dpi_x.is_normal() && dpi_x > 0. && dpi_y.is_normal() && dpi_y > 0.) {
+        return None;
+    }
+
+    Some((f64::from(width) / dpi_x, f64::from(height) / dpi_y))
+}
+
+/// Resolution in pixels per inch from the JFIF APP0 segment
+fn jfif(data: &[u8]) -> Option<(f64, f64)> {
+    // SOI marker followed by APP0 marker
+    if data.get(..4)? != [0xFF, 0xD8, 0xFF, 0xE0] || data.get(6..11)? != b"JFIF\0" {
+        return None;
+    }
+
+    let density = |pos: usize| {
+        Some(f64::from(u16::from_be_bytes(
+            data.get(pos..pos + 2)?.try_into().ok()?,
+        )))
+    };
+    let (x, y) = (density(14)?, density(16)?);
+
+    match data.get(13)? {
+        // Dots per inch
+        1 => Some((x, y)),
+        // Dots per cm
+        2 => Some((x * CM_PER_INCH, y * CM_PER_INCH)),
+        // Only the aspect ratio is given
+        _ => None,
+    }
+}
+
+/// Resolution in pixels per inch from the pHYs chunk
+fn png(data: &[u8]) -> Option<(f64, f64)> {
+    if data.get(..8)? != b"\x89PNG\r\n\x1a\n" {
+        return None;
+    }
+
+    let mut pos = 8_usize;
+
+    while let Some(header) = data.get(pos..pos.checked_add(8)?) {
+        let length = usize::try_from(u32::from_be_bytes(header[..4].try_into().ok()?)).ok()?;
+        let chunk_type = &header[4..8];
+        let chunk = pos.checked_add(8)?;
+
+        if chunk_type == b"pHYs" {
+            let payload = data.get(chunk..chunk.checked_add(9)?)?;
+            let x = f64::from(u32::from_be_bytes(payload[0..4].try_into().ok()?));
+            let y = f64::from(u32::from_be_bytes(payload[4..8].try_into().ok()?));
+
+            // Pixels per meter or only the aspect ratio
+            return (payload[8] == 1).then_some((x * M_PER_INCH, y * M_PER_INCH));
+        } else if chunk_type == b"IDAT" || chunk_type == b"IEND" {
+            // pHYs has to be placed before the image data
+            break;
+        }
+
+        // Chunk data and CRC
+        pos = chunk.checked_add(length)?.checked_add(4)?;
+    }
+
+    None
+}
+
+/// Resolution in pixels per inch from the TIFF tags of the first image
+fn tiff(data: &[u8]) -> Option<(f64, f64)> {
+    let mut decoder = Decoder::new(Cursor::new(data)).ok()?;
+
+    let mut rational = |tag| match decoder.find_tag(tag).ok()?? {
+        tiff::decoder::ifd::Value::Rational(n, d) if d != 0 => Some(f64::from(n) / f64::from(d)),
+        _ => None,
+    };
+
+    let x = rational(Tag::XResolution)?;
+    let y = rational(Tag::YResolution)?;
+    let unit = decoder
+        .find_tag_unsigned::<u16>(Tag::ResolutionUnit)
+        .ok()
+        .flatten();
+
+    to_inch(x, y, unit)
+}
+
+/// Resolution in pixels per inch from the Exif resolution tags
+fn exif(data: &[u8]) -> Option<(f64, f64)> {
+    let mut exif = gufo_exif::internal::ExifRaw::new(data.to_vec());
+    exif.decode().ok()?;
+
+    let mut rational = |tagifd: TagIfd| match exif.lookup_rational(tagifd).ok()?? {
+        (n, d) if d != 0 => Some(f64::from(n) / f64::from(d)),
+        _ => None,
+    };
+
+    let x = rational(EXIF_X_RESOLUTION)?;
+    let y = rational(EXIF_Y_RESOLUTION)?;
+    let unit = exif.lookup_short(EXIF_RESOLUTION_UNIT).ok().flatten();
+
+    to_inch(x, y, unit)
+}
+
+/// Convert TIFF resolution to pixels per inch
+///
+/// The unit defaults to inch if it's not specified.
+fn to_inch(x: f64, y: f64, unit: Option<u16>) -> Option<(f64, f64)> {
+    match unit.unwrap_or(TIFF_UNIT_INCH) {
+        TIFF_UNIT_INCH => Some((x, y)),
+        TIFF_UNIT_CM => Some((x * CM_PER_INCH, y * CM_PER_INCH)),
+        // No absolute unit
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jfif_test() {
+        let jfif = |unit: u8| {
+            [
+                [0xFF, 0xD8, 0xFF, 0xE0, 0, 16].as_slice(),
+                b"JFIF\0",
+                &[1, 2, unit, 0, 150, 0, 75, 0, 0],
+            ]
+            .concat()
+        };
+
+        assert_eq!(
+            dimensions_inch("image/jpeg", &jfif(1), None, 300, 300),
+            Some((2., 4.))
+        );
+        assert_eq!(
+            dimensions_inch("image/jpeg", &jfif(2), None, 381, 381),
+            Some((1., 2.))
+        );
+        assert_eq!(
+            dimensions_inch("image/jpeg", &jfif(0), None, 300, 300),
+            None
+        );
+    }
+
+    #[test]
+    fn png_test() {
+        let png = |unit: u8| {
+            let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+            data.extend([0, 0, 0, 9]);
+            data.extend(b"pHYs");
+            // 3937 pixels per meter are about 100 DPI
+            data.extend(3937_u32.to_be_bytes());
+            data.extend(3937_u32.to_be_bytes());
+            data.push(unit);
+            data.extend([0; 4]);
+            data
+        };
+
+        let (width, height) = dimensions_inch("image/png", &png(1), None, 100, 200).unwrap();
+        assert!((width - 1.).abs() < 0.001);
+        assert!((height - 2.).abs() < 0.001);
+
+        assert_eq!(dimensions_inch("image/png", &png(0), None, 100, 200), None);
+    }
+
+    #[test]
+    fn tiff_test() {
+        let tiff = |unit: tiff::tags::ResolutionUnit| {
+            let mut data = Cursor::new(Vec::new());
+            let mut encoder = tiff::encoder::TiffEncoder::new(&mut data).unwrap();
+            let mut image = encoder
+                .new_image::<tiff::encoder::colortype::Gray8>(1, 1)
+                .unwrap();
+            image.resolution(unit, tiff::encoder::Rational { n: 100, d: 1 });
+            image.write_data(&[0]).unwrap();
+            data.into_inner()
+        };
+
+        assert_eq!(
+            dimensions_inch(
+                "image/tiff",
+                &tiff(tiff::tags::ResolutionUnit::Inch),
+                None,
+                200,
+                100
+            ),
+            Some((2., 1.))
+        );
+        assert_eq!(
+            dimensions_inch(
+                "image/tiff",
+                &tiff(tiff::tags::ResolutionUnit::Centimeter),
+                None,
+                254,
+                254
+            ),
+            Some((1., 1.))
+        );
+        assert_eq!(
+            dimensions_inch(
+                "image/tiff",
+                &tiff(tiff::tags::ResolutionUnit::None),
+                None,
+                200,
+                100
+            ),
+            None
+        );
+
+        // Exif data has the same structure as TIFF
+        assert_eq!(
+            dimensions_inch(
+                "image/webp",
+                &[],
+                Some(&tiff(tiff::tags::ResolutionUnit::Inch)),
+                200,
+                100
+            ),
+            Some((2., 1.))
+        );
+    }
+}
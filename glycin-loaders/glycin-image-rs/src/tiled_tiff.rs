@@ -0,0 +1,300 @@
+//! Chunk-wise decoding of tiled TIFFs and BigTIFFs
+//!
+//! The image is decoded one strip or tile at a time. For clipped frame
+//! requests, only the chunks that intersect the clip are decoded.
+
+use std::io::Cursor;
+
+use glycin_utils::safe_math::*;
+use glycin_utils::*;
+use tiff::decoder::{ChunkType, Decoder, DecodingResult, Limits};
+use tiff::tags::{PlanarConfiguration, SampleFormat, Tag};
+
+pub struct TiledTiff {
+    decoder: Decoder<Cursor<Vec<u8>>>,
+    width: u32,
+    height: u32,
+    chunk_width: u32,
+    chunk_height: u32,
+    memory_format: MemoryFormat,
+    details: FrameDetails,
+    decoded: bool,
+}
+
+impl TiledTiff {
+    /// Returns `true` for TIFFs that should be decoded chunk-wise
+    ///
+    /// These are tiled TIFFs and BigTIFFs with a supported sample layout.
+    pub fn is_applicable(data: &[u8]) -> bool {
+        let is_big_tiff = data.starts_with(b"II\x2B\x00") || data.starts_with(b"MM\x00\x2B");
+
+        let Ok(mut decoder) = Decoder::new(Cursor::new(data)) else {
+            return false;
+        };
+
+        let is_tiled = matches!(decoder.get_chunk_type(), ChunkType::Tile);
+        let is_chunky = decoder
+            .find_tag_unsigned::<u16>(Tag::PlanarConfiguration)
+            .ok()
+            .flatten()
+            .and_then(PlanarConfiguration::from_u16)
+            .is_none_or(|x| x == PlanarConfiguration::Chunky);
+        let is_float = is_float(&mut decoder);
+        let is_supported = decoder
+            .colortype()
+            .is_ok_and(|x| memory_format(x, is_float).is_some());
+
+        (is_big_tiff || is_tiled) && is_chunky && is_supported
+    }
+
+    pub fn new(data: Vec<u8>, details: FrameDetails) -> Result<Self, ProcessError> {
+        Self::page(data, 0, details)
+    }
+
+    /// Decode the page with the given index of a multi-page TIFF
+    pub fn page(data: Vec<u8>, index: u32, details: FrameDetails) -> Result<Self, ProcessError> {
+        let mut decoder = Decoder::new(Cursor::new(data))
+            .expected_error()?
+            .with_limits(Limits::unlimited());
+        if index > 0 {
+            decoder
+                .seek_to_image(index.try_usize()?)
+                .map_err(|_| ProcessError::expected(&format!("No sub-image {index}")))?;
+        }
+
+        let (width, height) = decoder.dimensions().expected_error()?;
+        let (chunk_width, chunk_height) = decoder.chunk_dimensions();
+        if chunk_width == 0 || chunk_height == 0 {
+            return Err(ProcessError::expected(&format!(
+                "Invalid TIFF chunk size {chunk_width}x{chunk_height}"
+            )));
+        }
+        let is_planar = decoder
+            .find_tag_unsigned::<u16>(Tag::PlanarConfiguration)
+            .ok()
+            .flatten()
+            .and_then(PlanarConfiguration::from_u16)
+            .is_some_and(|x| x == PlanarConfiguration::Planar);
+        if is_planar {
+            return Err(ProcessError::UnsupportedImageFormat(String::from(
+                "Planar TIFF sample layout",
+            )));
+        }
+        let is_float = is_float(&mut decoder);
+        let memory_format = memory_format(decoder.colortype().expected_error()?, is_float)
+            .ok_or_else(|| ProcessError::UnsupportedImageFormat(String::from("TIFF color type")))?;
+
+        Ok(Self {
+            decoder,
+            width,
+            height,
+            chunk_width,
+            chunk_height,
+            memory_format,
+            details,
+            decoded: false,
+        })
+    }
+
+    /// Dimensions of the tiles, `None` for images stored in strips
+    pub fn tile_size(&self) -> Option<(u32, u32)> {
+        matches!(self.decoder.get_chunk_type(), ChunkType::Tile)
+            .then_some((self.chunk_width, self.chunk_height))
+    }
+
+    /// Decode the image or the clipped region of it
+    ///
+    /// Clipped regions can be requested repeatedly. Without clip, the image
+    /// is only returned once.
+    pub fn frame(&mut self, clip: Option<(u32, u32, u32, u32)>) -> Result<Frame, ProcessError> {
+        if clip.is_none() {
+            if self.decoded {
+                return Err(ProcessError::NoMoreFrames);
+            }
+            self.decoded = true;
+        }
+
+        let (x0, y0, width, height) = clip.unwrap_or((0, 0, self.width, self.height));
+        let x1 = x0.sadd(width)?;
+        let y1 = y0.sadd(height)?;
+
+        if width == 0 || height == 0 || x1 > self.width || y1 > self.height {
+            return Err(ProcessError::expected(&format!(
+                "Clip {x0},{y0} {width}x{height} is outside of the {}x{} image",
+                self.width, self.height
+            )));
+        }
+
+        let pixel_size = self.memory_format.n_bytes().usize();
+        let stride = width.try_usize()?.smul(pixel_size)?;
+        let mut memory =
+            SharedMemory::new(stride.try_u64()?.smul(height.try_u64()?)?).expected_error()?;
+
+        let chunks_across = self.width.div_ceil(self.chunk_width);
+
+        let first_col = chunk_index(x0, self.chunk_width)?;
+        let last_col = chunk_index(x1.saturating_sub(1), self.chunk_width)?;
+        let first_row = chunk_index(y0, self.chunk_height)?;
+        let last_row = chunk_index(y1.saturating_sub(1), self.chunk_height)?;
+
+        for row in first_row..=last_row {
+            for col in first_col..=last_col {
+                let index = row.smul(chunks_across)?.sadd(col)?;
+                let (data_width, data_height) = self.decoder.chunk_data_dimensions(index);
+                let chunk = native_bytes(self.decoder.read_chunk(index).expected_error()?)?;
+
+                let chunk_x = col.smul(self.chunk_width)?;
+                let chunk_y = row.smul(self.chunk_height)?;
+
+                // Intersection of the chunk with the clip
+                let ix0 = x0.max(chunk_x);
+                let ix1 = x1.min(chunk_x.sadd(data_width)?);
+                let iy0 = y0.max(chunk_y);
+                let iy1 = y1.min(chunk_y.sadd(data_height)?);
+
+                let n_bytes = ix1.saturating_sub(ix0).try_usize()?.smul(pixel_size)?;
+
+                for y in iy0..iy1 {
+                    let src_start = (y.saturating_sub(chunk_y).smul(data_width)?)
+                        .sadd(ix0.saturating_sub(chunk_x))?
+                        .try_usize()?
+                        .smul(pixel_size)?;
+                    let dst_start = y
+                        .saturating_sub(y0)
+                        .try_usize()?
+                        .smul(stride)?
+                        .sadd(ix0.saturating_sub(x0).try_usize()?.smul(pixel_size)?)?;
+
+                    let src = chunk
+                        .get(src_start..src_start.sadd(n_bytes)?)
+                        .internal_error()?;
+                    memory
+                        .get_mut(dst_start..dst_start.sadd(n_bytes)?)
+                        .internal_error()?
+                        .copy_from_slice(src);
+                }
+            }
+        }
+
+        let mut frame = Frame::new(width, height, self.memory_format, memory.into_binary_data())?;
+        frame.details = self.details.clone();
+
+        Ok(frame)
+    }
+}
+
+/// Sizes of the pages of multi-page TIFFs
+///
+/// Returns `None` for TIFFs with a single page.
+pub fn pages(data: &[u8]) -> Option<Vec<SubImageInfo>> {
+    let mut decoder = Decoder::new(Cursor::new(data)).ok()?;
+
+    let mut pages = Vec::new();
+    loop {
+        let (width, height) = decoder.dimensions().ok()?;
+        pages.push(SubImageInfo::new(width, height, SubImageKind::Page));
+
+        if !decoder.more_images() || decoder.next_image().is_err() {
+            break;
+        }
+    }
+
+    (pages.len() > 1).then_some(pages)
+}
+
+fn chunk_index(position: u32, chunk_size: u32) -> Result<u32, ProcessError> {
+    position
+        .checked_div(chunk_size)
+        .ok_or(ProcessError::ConversionTooLargerError)
+}
+
+/// Returns `true` if the samples are floating point numbers
+fn is_float<R: std::io::Read + std::io::Seek>(decoder: &mut Decoder<R>) -> bool {
+    decoder
+        .find_tag_unsigned_vec::<u16>(Tag::SampleFormat)
+        .ok()
+        .flatten()
+        .and_then(|x| x.first().copied())
+        .is_some_and(|x| x == SampleFormat::IEEEFP.to_u16())
+}
+
+fn memory_format(color_type: tiff::ColorType, is_float: bool) -> Option<MemoryFormat> {
+    use tiff::ColorType;
+
+    match color_type {
+        ColorType::RGB(32) | ColorType::RGBA(32) if !is_float => None,
+        ColorType::Gray(8) => Some(MemoryFormat::G8),
+        ColorType::Gray(16) => Some(MemoryFormat::G16),
+        ColorType::GrayA(8) => Some(MemoryFormat::G8a8),
+        ColorType::GrayA(16) => Some(MemoryFormat::G16a16),
+        ColorType::RGB(8) => Some(MemoryFormat::R8g8b8),
+        ColorType::RGB(16) => Some(MemoryFormat::R16g16b16),
+        ColorType::RGB(32) => Some(MemoryFormat::R32g32b32Float),
+        ColorType::RGBA(8) => Some(MemoryFormat::R8g8b8a8),
+        ColorType::RGBA(16) => Some(MemoryFormat::R16g16b16a16),
+        ColorType::RGBA(32) => Some(MemoryFormat::R32g32b32a32Float),
+        _ => None,
+    }
+}
+
+/// Decoded samples as bytes in native byte order
+fn native_bytes(result: DecodingResult) -> Result<Vec<u8>, ProcessError> {
+    match result {
+        DecodingResult::U8(data) => Ok(data),
+        DecodingResult::U16(data) => Ok(data.into_iter().flat_map(u16::to_ne_bytes).collect()),
+        DecodingResult::F32(data) => Ok(data.into_iter().flat_map(f32::to_ne_bytes).collect()),
+        _ => Err(ProcessError::UnsupportedImageFormat(String::from(
+            "TIFF sample format",
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn two_page_tiff() -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        let mut encoder = tiff::encoder::TiffEncoder::new(&mut data).unwrap();
+        encoder
+            .write_image::<tiff::encoder::colortype::Gray8>(2, 1, &[10, 20])
+            .unwrap();
+        encoder
+            .write_image::<tiff::encoder::colortype::Gray8>(1, 3, &[30, 40, 50])
+            .unwrap();
+
+        data.into_inner()
+    }
+
+    #[test]
+    fn pages_listed_and_decoded() {
+        let data = two_page_tiff();
+
+        let pages = pages(&data).unwrap();
+        assert_eq!(
+            pages,
+            [
+                SubImageInfo::new(2, 1, SubImageKind::Page),
+                SubImageInfo::new(1, 3, SubImageKind::Page)
+            ]
+        );
+
+        let mut page = TiledTiff::page(data.clone(), 1, FrameDetails::default()).unwrap();
+        let frame = page.frame(None).unwrap();
+        assert_eq!((frame.width, frame.height), (1, 3));
+        assert_eq!(frame.texture.get_full().unwrap(), [30, 40, 50]);
+
+        assert!(TiledTiff::page(data, 2, FrameDetails::default()).is_err());
+    }
+
+    #[test]
+    fn single_page_not_listed() {
+        let mut data = Cursor::new(Vec::new());
+        tiff::encoder::TiffEncoder::new(&mut data)
+            .unwrap()
+            .write_image::<tiff::encoder::colortype::Gray8>(1, 1, &[0])
+            .unwrap();
+
+        assert!(pages(data.get_ref()).is_none());
+    }
+}
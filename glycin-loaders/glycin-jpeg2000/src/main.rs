@@ -1,5 +1,3 @@
-use std::io::Read;
-
 use glycin_utils::*;
 
 init_main_loader!(ImgDecoder);
@@ -13,15 +11,15 @@ unsafe impl Send for ImgDecoder {}
 
 impl LoaderImplementation for ImgDecoder {
     fn init(
-        mut stream: UnixStream,
+        stream: UnixStream,
         _mime_type: String,
         _details: InitializationDetails,
     ) -> Result<(Self, ImageDetails), ProcessError> {
-        let mut buf = Vec::new();
-        stream.read_to_end(&mut buf).internal_error()?;
+        let buf = InputData::read(stream).internal_error()?;
 
         let image = jpeg2k::Image::from_bytes(&buf).expected_error()?;
-        let details = ImageDetails::new(image.width(), image.height());
+        let mut details = ImageDetails::new(image.width(), image.height());
+        details.info_alpha_channel = Some(image.components().iter().any(|x| x.is_alpha()));
 
         Ok((Self { image }, details))
     }
@@ -0,0 +1,477 @@
+//! Decoder for animated JPEG XL images
+//!
+//! Frames are decoded one after another by a decoder that is kept between
+//! frame requests. The decoder coalesces the frames, such that every returned
+//! frame covers the full canvas.
+
+use std::mem::MaybeUninit;
+use std::time::Duration;
+
+use glycin_utils::safe_math::*;
+use glycin_utils::*;
+use jpegxl_rs::parallel::resizable_runner::ResizableRunner;
+use jpegxl_rs::parallel::ParallelRunner;
+use jpegxl_sys::common::types::{JxlBool, JxlDataType, JxlEndianness, JxlPixelFormat};
+use jpegxl_sys::decode::*;
+use jpegxl_sys::metadata::codestream_header::{JxlBasicInfo, JxlFrameHeader, JxlOrientation};
+
+/// Delay for frames without duration
+///
+/// Other decoders default to this value as well
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+pub struct Animation {
+    decoder: *mut JxlDecoder,
+    runner: ResizableRunner<'static>,
+    /// Input of the decoder, has to stay unchanged while decoding
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    pixel_format: JxlPixelFormat,
+    memory_format: MemoryFormat,
+    bit_depth: u8,
+    tps_numerator: u32,
+    tps_denominator: u32,
+    max_frames: Option<u64>,
+    n_frame: u64,
+    /// Frames decoded so far, including previous loops of the animation
+    n_frames_decoded: u64,
+    /// Sum of the durations of the previous frames in ticks
+    ticks: u64,
+}
+
+// The decoder is only used via `&mut self`
+unsafe impl Send for Animation {}
+unsafe impl Sync for Animation {}
+
+impl Animation {
+    pub fn new(
+        data: Vec<u8>,
+        info: &JxlBasicInfo,
+        max_frames: Option<u64>,
+    ) -> Result<Self, ProcessError> {
+        let alpha_channel = info.alpha_bits > 0;
+        let (data_type, bit_depth) = if info.exponent_bits_per_sample > 0 {
+            (JxlDataType::Float, 32)
+        } else if info.bits_per_sample > 8 {
+            (JxlDataType::Uint16, 16)
+        } else {
+            (JxlDataType::Uint8, 8)
+        };
+
+        let memory_format = match (data_type, info.num_color_channels, alpha_channel) {
+            (JxlDataType::Float, 3, false) => MemoryFormat::R32g32b32Float,
+            (JxlDataType::Float, 3, true) => MemoryFormat::R32g32b32a32Float,
+            (JxlDataType::Uint16, 3, false) => MemoryFormat::R16g16b16,
+            (JxlDataType::Uint16, 3, true) => MemoryFormat::R16g16b16a16,
+            (JxlDataType::Uint16, 1, false) => MemoryFormat::G16,
+            (JxlDataType::Uint16, 1, true) => MemoryFormat::G16a16,
+            (JxlDataType::Uint8, 3, false) => MemoryFormat::R8g8b8,
+            (JxlDataType::Uint8, 3, true) => MemoryFormat::R8g8b8a8,
+            (JxlDataType::Uint8, 1, false) => MemoryFormat::G8,
+            (JxlDataType::Uint8, 1, true) => MemoryFormat::G8a8,
+            (_, channels, _) => {
+                return Err(ProcessError::UnsupportedImageFormat(format!(
+                    "Animation with {channels} {data_type:?} color channels"
+                )))
+            }
+        };
+
+        let pixel_format = JxlPixelFormat {
+            num_channels: info.num_color_channels.saturating_add(alpha_channel.into()),
+            data_type,
+            endianness: JxlEndianness::Native,
+            align: 0,
+        };
+
+        // The decoder applies the orientation
+        let (width, height) = if matches!(
+            info.orientation,
+            JxlOrientation::Transpose
+                | JxlOrientation::Rotate90Cw
+                | JxlOrientation::AntiTranspose
+                | JxlOrientation::Rotate90Ccw
+        ) {
+            (info.ysize, info.xsize)
+        } else {
+            (info.xsize, info.ysize)
+        };
+
+        let runner = ResizableRunner::new(None).internal_error()?;
+        runner.set_num_threads(width.into(), height.into());
+
+        let decoder = unsafe { JxlDecoderCreate(std::ptr::null()) };
+        if decoder.is_null() {
+            return Err(ProcessError::expected(&"Failed to create JPEG XL decoder"));
+        }
+
+        let mut animation = Self {
+            decoder,
+            runner,
+            data,
+            width,
+            height,
+            pixel_format,
+            memory_format,
+            bit_depth,
+            tps_numerator: info.animation.tps_numerator,
+            tps_denominator: info.animation.tps_denominator,
+            max_frames,
+            n_frame: 0,
+            n_frames_decoded: 0,
+            ticks: 0,
+        };
+
+        unsafe {
+            check(JxlDecoderSetParallelRunner(
+                animation.decoder,
+                animation.runner.runner(),
+                animation.runner.as_opaque_ptr(),
+            ))?;
+            check(JxlDecoderSubscribeEvents(
+                animation.decoder,
+                JxlDecoderStatus::Frame as i32 | JxlDecoderStatus::FullImage as i32,
+            ))?;
+        }
+        animation.set_input()?;
+
+        Ok(animation)
+    }
+
+    /// Decode the next frame
+    ///
+    /// After the last frame, the animation starts over if `loop_animation`
+    /// is set. Otherwise [`ProcessError::NoMoreFrames`] is returned once and
+    /// the next request returns the first frame.
+    pub fn frame(&mut self, loop_animation: bool) -> Result<Frame, ProcessError> {
+        if self
+            .max_frames
+            .is_some_and(|max| self.n_frames_decoded >= max)
+        {
+            return Err(ProcessError::NoMoreFrames);
+        }
+
+        if let Some(frame) = self.decode_frame()? {
+            return Ok(frame);
+        }
+
+        if self.n_frame == 0 {
+            return Err(ProcessError::expected(&"No frame found."));
+        }
+
+        self.rewind()?;
+
+        if !loop_animation {
+            return Err(ProcessError::NoMoreFrames);
+        }
+
+        self.decode_frame()?
+            .ok_or_else(|| ProcessError::expected(&"No frame found."))
+    }
+
+    fn decode_frame(&mut self) -> Result<Option<Frame>, ProcessError> {
+        let mut duration = 0;
+        let mut memory = None;
+
+        loop {
+            let status = unsafe { JxlDecoderProcessInput(self.decoder) };
+            match status {
+                JxlDecoderStatus::Frame => {
+                    let mut header = MaybeUninit::<JxlFrameHeader>::uninit();
+                    unsafe {
+                        check(JxlDecoderGetFrameHeader(self.decoder, header.as_mut_ptr()))?;
+                        duration = header.assume_init().duration;
+                    }
+                }
+                JxlDecoderStatus::NeedImageOutBuffer => {
+                    let mut size = 0;
+                    unsafe {
+                        check(JxlDecoderImageOutBufferSize(
+                            self.decoder,
+                            &self.pixel_format,
+                            &mut size,
+                        ))?;
+                    }
+
+                    let buffer =
+                        memory.insert(SharedMemory::new(size.try_u64()?).expected_error()?);
+
+                    unsafe {
+                        check(JxlDecoderSetImageOutBuffer(
+                            self.decoder,
+                            &self.pixel_format,
+                            buffer.as_mut_ptr().cast(),
+                            size,
+                        ))?;
+                    }
+                }
+                JxlDecoderStatus::FullImage => {
+                    let memory = memory.take().internal_error()?;
+                    return self.finish_frame(memory, duration).map(Some);
+                }
+                JxlDecoderStatus::Success => return Ok(None),
+                status => {
+                    return Err(ProcessError::expected(&format!(
+                        "Unexpected decoder status: {status:?}"
+                    )))
+                }
+            }
+        }
+    }
+
+    fn finish_frame(&mut self, memory: SharedMemory, duration: u32) -> Result<Frame, ProcessError> {
+        let timestamp = self.ticks_duration(self.ticks);
+        let delay = Some(self.ticks_duration(duration.into()))
+            .filter(|x| !x.is_zero())
+            .unwrap_or(DEFAULT_DELAY);
+        self.ticks = self.ticks.saturating_add(duration.into());
+
+        let mut frame = Frame::new(
+            self.width,
+            self.height,
+            self.memory_format,
+            memory.into_binary_data(),
+        )
+        .expected_error()?;
+
+        frame.delay = Some(delay).into();
+        frame.details.n_frame = Some(self.n_frame);
+        frame.details.timestamp = Some(timestamp);
+
+        if self.bit_depth != 8 {
+            frame.details.info_bit_depth = Some(self.bit_depth);
+        }
+
+        if self.memory_format.has_alpha() {
+            frame.details.info_alpha_channel = Some(true);
+        }
+
+        if self.pixel_format.num_channels < 3 {
+            frame.details.info_grayscale = Some(true);
+        }
+
+        self.n_frame = self.n_frame.saturating_add(1);
+        self.n_frames_decoded = self.n_frames_decoded.saturating_add(1);
+
+        Ok(frame)
+    }
+
+    /// Exact duration of the ticks
+    fn ticks_duration(&self, ticks: u64) -> Duration {
+        let nanos = u128::from(ticks)
+            .saturating_mul(1_000_000_000)
+            .saturating_mul(self.tps_denominator.into())
+            .checked_div(self.tps_numerator.into())
+            .unwrap_or_default();
+
+        Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+    }
+
+    fn rewind(&mut self) -> Result<(), ProcessError> {
+        unsafe { JxlDecoderRewind(self.decoder) };
+        self.n_frame = 0;
+        self.ticks = 0;
+
+        self.set_input()
+    }
+
+    fn set_input(&mut self) -> Result<(), ProcessError> {
+        unsafe {
+            check(JxlDecoderSetInput(
+                self.decoder,
+                self.data.as_ptr(),
+                self.data.len(),
+            ))?;
+            JxlDecoderCloseInput(self.decoder);
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Animation {
+    fn drop(&mut self) {
+        unsafe { JxlDecoderDestroy(self.decoder) };
+    }
+}
+
+/// Returns `true` if the image is an animation
+pub fn is_animated(info: &JxlBasicInfo) -> bool {
+    info.have_animation == JxlBool::True
+}
+
+fn check(status: JxlDecoderStatus) -> Result<(), ProcessError> {
+    if status == JxlDecoderStatus::Success {
+        Ok(())
+    } else {
+        Err(ProcessError::expected(&format!(
+            "JPEG XL decoder failed: {status:?}"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jpegxl_sys::encoder::encode::*;
+
+    use super::*;
+
+    /// Lossless RGB animation of single pixel frames with the given delays
+    /// in milliseconds
+    fn animated_jxl(frames: &[([u8; 3], u32)]) -> Vec<u8> {
+        let mut data = vec![0; 4096];
+
+        unsafe {
+            let encoder = JxlEncoderCreate(std::ptr::null());
+
+            let mut info = MaybeUninit::uninit();
+            JxlEncoderInitBasicInfo(info.as_mut_ptr());
+            let mut info = info.assume_init();
+            info.xsize = 1;
+            info.ysize = 1;
+            info.bits_per_sample = 8;
+            info.num_color_channels = 3;
+            info.uses_original_profile = JxlBool::True;
+            info.have_animation = JxlBool::True;
+            info.animation.tps_numerator = 1000;
+            info.animation.tps_denominator = 1;
+            assert_eq!(
+                JxlEncoderSetBasicInfo(encoder, &info),
+                JxlEncoderStatus::Success
+            );
+
+            let mut color_encoding = MaybeUninit::uninit();
+            JxlColorEncodingSetToSRGB(color_encoding.as_mut_ptr(), false);
+            assert_eq!(
+                JxlEncoderSetColorEncoding(encoder, color_encoding.as_ptr()),
+                JxlEncoderStatus::Success
+            );
+
+            let settings = JxlEncoderFrameSettingsCreate(encoder, std::ptr::null());
+            JxlEncoderSetFrameLossless(settings, true);
+
+            let pixel_format = JxlPixelFormat {
+                num_channels: 3,
+                data_type: JxlDataType::Uint8,
+                endianness: JxlEndianness::Native,
+                align: 0,
+            };
+
+            for (pixel, duration) in frames {
+                let mut header = MaybeUninit::uninit();
+                JxlEncoderInitFrameHeader(header.as_mut_ptr());
+                let mut header = header.assume_init();
+                header.duration = *duration;
+                JxlEncoderSetFrameHeader(settings, &header);
+
+                assert_eq!(
+                    JxlEncoderAddImageFrame(
+                        settings,
+                        &pixel_format,
+                        pixel.as_ptr().cast(),
+                        pixel.len()
+                    ),
+                    JxlEncoderStatus::Success
+                );
+            }
+            JxlEncoderCloseInput(encoder);
+
+            let mut next_out = data.as_mut_ptr();
+            let mut avail_out = data.len();
+            assert_eq!(
+                JxlEncoderProcessOutput(encoder, &mut next_out, &mut avail_out),
+                JxlEncoderStatus::Success
+            );
+            data.truncate(data.len() - avail_out);
+
+            JxlEncoderDestroy(encoder);
+        }
+
+        data
+    }
+
+    fn basic_info(data: &[u8]) -> JxlBasicInfo {
+        crate::basic_info(data).0.unwrap()
+    }
+
+    const FRAMES: [([u8; 3], u32); 3] = [([255, 0, 0], 100), ([0, 255, 0], 250), ([0, 0, 255], 40)];
+
+    #[test]
+    fn frames() {
+        let data = animated_jxl(&FRAMES);
+        let info = basic_info(&data);
+        assert!(is_animated(&info));
+
+        let mut animation = Animation::new(data, &info, None).unwrap();
+
+        for _ in 0..2 {
+            let mut timestamp = Duration::ZERO;
+            for (n_frame, (pixel, delay)) in FRAMES.into_iter().enumerate() {
+                let frame = animation.frame(true).unwrap();
+                let delay = Duration::from_millis(delay.into());
+
+                assert_eq!(frame.details.n_frame, Some(n_frame as u64));
+                assert_eq!(*frame.delay, Some(delay));
+                assert_eq!(frame.details.timestamp, Some(timestamp));
+                assert_eq!(frame.memory_format, MemoryFormat::R8g8b8);
+                assert_eq!(frame.texture.get_full().unwrap(), pixel);
+
+                timestamp += delay;
+            }
+        }
+
+        assert!(matches!(
+            animation.frame(false),
+            Ok(Frame { details, .. }) if details.n_frame == Some(0)
+        ));
+    }
+
+    #[test]
+    fn no_loop() {
+        let data = animated_jxl(&FRAMES);
+        let mut animation = Animation::new(data.clone(), &basic_info(&data), None).unwrap();
+
+        for _ in FRAMES {
+            assert!(animation.frame(false).is_ok());
+        }
+        assert!(matches!(
+            animation.frame(false),
+            Err(ProcessError::NoMoreFrames)
+        ));
+
+        let mut animation = Animation::new(data.clone(), &basic_info(&data), Some(1)).unwrap();
+        assert!(animation.frame(true).is_ok());
+        assert!(matches!(
+            animation.frame(true),
+            Err(ProcessError::NoMoreFrames)
+        ));
+    }
+
+    #[test]
+    fn max_frames_across_loops() {
+        let data = animated_jxl(&FRAMES);
+        let mut animation = Animation::new(data.clone(), &basic_info(&data), Some(5)).unwrap();
+
+        // The animation is looped until the limit is reached
+        for n_frame in [0, 1, 2, 0, 1] {
+            let frame = animation.frame(true).unwrap();
+            assert_eq!(frame.details.n_frame, Some(n_frame));
+        }
+        assert!(matches!(
+            animation.frame(true),
+            Err(ProcessError::NoMoreFrames)
+        ));
+    }
+
+    #[test]
+    fn still() {
+        let data = jpegxl_rs::encoder_builder()
+            .build()
+            .unwrap()
+            .encode::<u8, u8>(&[255, 0, 0], 1, 1)
+            .unwrap()
+            .data;
+
+        assert!(!is_animated(&basic_info(&data)));
+    }
+}
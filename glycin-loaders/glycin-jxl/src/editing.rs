@@ -35,6 +35,10 @@ impl EditorImplementation for ImgEditor {
         mut new_image: glycin_utils::NewImage,
         encoding_options: glycin_utils::EncodingOptions,
     ) -> Result<glycin_utils::EncodedImage, glycin_utils::ProcessError> {
+        if let Some(jpeg) = new_image.jpeg_lossless {
+            return recompress_jpeg(&jpeg);
+        }
+
         let frame = new_image.frames.remove(0);
 
         let mut encoder = jpegxl_rs::encoder_builder().build().internal_error()?;
@@ -90,3 +94,23 @@ impl EditorImplementation for ImgEditor {
         Ok(glycin_utils::EncodedImage::new(data))
     }
 }
+
+/// Losslessly recompress a JPEG, keeping the data to reconstruct it
+fn recompress_jpeg(
+    jpeg: &BinaryData,
+) -> Result<glycin_utils::EncodedImage, glycin_utils::ProcessError> {
+    // The reconstruction data is only stored in the container format
+    let mut encoder = jpegxl_rs::encoder_builder()
+        .use_container(true)
+        .uses_original_profile(true)
+        .build()
+        .internal_error()?;
+
+    let encoder_result = encoder
+        .encode_jpeg(&jpeg.get_full().expected_error()?)
+        .expected_error()?;
+
+    let data = BinaryData::from_data(encoder_result.data).expected_error()?;
+
+    Ok(glycin_utils::EncodedImage::new(data))
+}
@@ -1,5 +1,6 @@
 #![allow(clippy::large_enum_variant)]
 
+mod animation;
 mod editing;
 
 use std::io::{Cursor, Read, Write};
@@ -15,6 +16,7 @@ use jpegxl_sys::decode::*;
 use jpegxl_sys::metadata::codestream_header::*;
 use zerocopy::IntoBytes;
 
+use crate::animation::Animation;
 use crate::editing::ImgEditor;
 
 init_main_loader_editor!(ImgDecoder, ImgEditor);
@@ -24,38 +26,70 @@ pub struct ImgDecoder {
     data: Vec<u8>,
     icc_profile: Option<Vec<u8>>,
     cicp: Option<Cicp>,
+    animation: Option<Animation>,
 }
 
 impl LoaderImplementation for ImgDecoder {
     fn init(
         mut stream: UnixStream,
         _mime_type: String,
-        _details: InitializationDetails,
+        details: InitializationDetails,
     ) -> Result<(Self, ImageDetails), ProcessError> {
         let mut data = Vec::new();
         stream.read_to_end(&mut data).expected_error()?;
-        let (info, icc_profile, exif, cicp) = basic_info(&data);
+        let (info, icc_profile, exif, cicp, has_jpeg_reconstruction) = basic_info(&data);
 
         let info = info.expected_error()?;
 
         let mut image_info = ImageDetails::new(info.xsize, info.ysize);
         image_info.info_format_name = Some(String::from("JPEG XL"));
+        image_info.info_alpha_channel = Some(info.alpha_bits > 0);
         image_info.metadata_exif = exif
             .map(BinaryData::from_data)
             .transpose()
             .expected_error()?;
         image_info.transformation_ignore_exif = true;
 
+        if details.collect_advisories == Some(true) {
+            image_info.advisories = Some(advisories(info.xsize, info.ysize));
+        }
+
+        if details.reconstruct_jpeg == Some(true) && has_jpeg_reconstruction {
+            // The image can still be loaded if the reconstruction data is broken
+            match reconstruct_jpeg(&data)
+                .and_then(|jpeg| BinaryData::from_data(jpeg).expected_error())
+            {
+                Ok(jpeg) => image_info.jpeg_reconstruction = Some(jpeg),
+                Err(err) => eprintln!("Failed to reconstruct JPEG: {err}"),
+            }
+        }
+
+        let (data, animation) = if animation::is_animated(&info) {
+            (
+                Vec::new(),
+                Some(Animation::new(data, &info, details.max_frames)?),
+            )
+        } else {
+            (data, None)
+        };
+
         let loader_implementation = ImgDecoder {
             data,
             icc_profile,
             cicp,
+            animation,
         };
 
         Ok((loader_implementation, image_info))
     }
 
-    fn frame(&mut self, _frame_request: FrameRequest) -> Result<Frame, ProcessError> {
+    fn frame(&mut self, frame_request: FrameRequest) -> Result<Frame, ProcessError> {
+        if let Some(animation) = &mut self.animation {
+            let mut frame = animation.frame(frame_request.loop_animation)?;
+            self.set_color(&mut frame.details)?;
+            return Ok(frame);
+        }
+
         let runner = jpegxl_rs::parallel::resizable_runner::ResizableRunner::new(None).unwrap();
         let decoder = jpegxl_rs::decoder_builder()
             .parallel_runner(&runner)
@@ -136,14 +170,7 @@ impl LoaderImplementation for ImgDecoder {
 
         let mut frame = Frame::new(width, height, memory_format, texture).expected_error()?;
 
-        frame.details.color_icc_profile = self
-            .icc_profile
-            .clone()
-            .map(BinaryData::from_data)
-            .transpose()
-            .expected_error()?;
-
-        frame.details.color_cicp = self.cicp.map(|x| x.to_bytes());
+        self.set_color(&mut frame.details)?;
 
         if bits != 8 {
             frame.details.info_bit_depth = Some(bits);
@@ -159,6 +186,60 @@ impl LoaderImplementation for ImgDecoder {
 
         Ok(frame)
     }
+
+    fn color_profile(&mut self) -> Result<Option<BinaryData>, ProcessError> {
+        self.icc_profile
+            .as_ref()
+            .map(BinaryData::from_data)
+            .transpose()
+            .expected_error()
+    }
+}
+
+impl ImgDecoder {
+    fn set_color(&self, details: &mut FrameDetails) -> Result<(), ProcessError> {
+        details.color_icc_profile = self
+            .icc_profile
+            .clone()
+            .map(BinaryData::from_data)
+            .transpose()
+            .expected_error()?;
+
+        details.color_cicp = self.cicp.map(|x| x.to_bytes());
+
+        Ok(())
+    }
+}
+
+/// Reconstruct the original JPEG from a recompressed JPEG
+fn reconstruct_jpeg(data: &[u8]) -> Result<Vec<u8>, ProcessError> {
+    let decoder = jpegxl_rs::decoder_builder().build().expected_error()?;
+
+    match decoder.reconstruct(data).expected_error()? {
+        (_, jpegxl_rs::decode::Data::Jpeg(jpeg)) => Ok(jpeg),
+        (_, jpegxl_rs::decode::Data::Pixels(_)) => {
+            Err(ProcessError::expected(&"Failed to reconstruct JPEG"))
+        }
+    }
+}
+
+/// Dimensions that exceed the limits of JPEG XL level 5
+///
+/// Most decoders and encoders only support images within these limits.
+fn advisories(width: u32, height: u32) -> Vec<String> {
+    const MAX_DIMENSION: u32 = 1 << 18;
+    const MAX_AREA: u64 = 1 << 28;
+
+    let exceeds_dimension = width > MAX_DIMENSION || height > MAX_DIMENSION;
+    let exceeds_area = u64::from(width) * u64::from(height) > MAX_AREA;
+
+    if exceeds_dimension || exceeds_area {
+        vec![format!(
+            "JPEG XL dimensions {width}\u{D7}{height} exceed the limits of level 5"
+        )]
+    } else {
+        Vec::new()
+    }
 }
 
 fn basic_info(
@@ -168,6 +249,7 @@ fn basic_info(
     Option<Vec<u8>>,
     Option<Vec<u8>>,
     Option<Cicp>,
+    bool,
 ) {
     unsafe {
         let decoder = JxlDecoderCreate(std::ptr::null());
@@ -186,6 +268,7 @@ fn basic_info(
         let mut icc_profile = None;
         let mut exif = None;
         let mut cicp = None;
+        let mut has_jpeg_reconstruction = false;
 
         let mut exif_buf = Vec::new();
         let mut buf = Vec::new();
@@ -205,9 +288,13 @@ fn basic_info(
                     let mut type_ = JxlBoxType([0; 4]);
                     JxlDecoderGetBoxType(decoder, &mut type_, JxlBool::True);
 
-                    if &type_.0.map(|x| x as u8) == b"Exif" {
+                    let type_ = type_.0.map(|x| x as u8);
+
+                    if &type_ == b"Exif" {
                         buf.resize(65536, 0);
                         JxlDecoderSetBoxBuffer(decoder, buf.as_mut_ptr(), buf.len());
+                    } else if &type_ == b"jbrd" {
+                        has_jpeg_reconstruction = true;
                     }
                 }
                 JxlDecoderStatus::BoxNeedMoreOutput => {
@@ -280,7 +367,7 @@ fn basic_info(
 
         JxlDecoderDestroy(decoder);
 
-        (basic_info, icc_profile, exif, cicp)
+        (basic_info, icc_profile, exif, cicp, has_jpeg_reconstruction)
     }
 }
 
@@ -0,0 +1,1298 @@
+//! Decoder for ASTC blocks
+//!
+//! Implements 2D blocks of the LDR and HDR profiles as specified in the
+//! Khronos Data Format Specification. Invalid blocks are decoded as magenta
+//! texels instead of failing the whole image.
+
+/// Color profile that is used for decoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// Returns UNORM16 values
+    Ldr,
+    /// Returns UNORM16 values with the sRGB rounding of the endpoints
+    LdrSrgb,
+    /// Returns `f16` values as bits
+    Hdr,
+}
+
+impl Profile {
+    fn error_color(self) -> [u16; 4] {
+        match self {
+            Self::Ldr | Self::LdrSrgb => [0xFFFF, 0, 0xFFFF, 0xFFFF],
+            Self::Hdr => [F16_ONE, 0, F16_ONE, F16_ONE],
+        }
+    }
+}
+
+const F16_ONE: u16 = 0x3C00;
+
+/// Value of HDR alpha endpoints that is decoded as 1.0
+const LNS_ONE: i32 = 0x7800;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Packing {
+    Bits,
+    Trits,
+    Quints,
+}
+
+/// Number of values for all quantization levels with their encoding
+///
+/// The second value is the number of bits stored in addition to the trit or
+/// quint, or the number of bits for the whole value.
+const QUANT_LEVELS: [(Packing, u32); 21] = [
+    (Packing::Bits, 1),
+    (Packing::Trits, 0),
+    (Packing::Bits, 2),
+    (Packing::Quints, 0),
+    (Packing::Trits, 1),
+    (Packing::Bits, 3),
+    (Packing::Quints, 1),
+    (Packing::Trits, 2),
+    (Packing::Bits, 4),
+    (Packing::Quints, 2),
+    (Packing::Trits, 3),
+    (Packing::Bits, 5),
+    (Packing::Quints, 3),
+    (Packing::Trits, 4),
+    (Packing::Bits, 6),
+    (Packing::Quints, 4),
+    (Packing::Trits, 5),
+    (Packing::Bits, 7),
+    (Packing::Quints, 5),
+    (Packing::Trits, 6),
+    (Packing::Bits, 8),
+];
+
+/// Lowest quantization level that is allowed for color endpoints
+const MIN_COLOR_QUANT_LEVEL: usize = 4;
+
+const MAX_WEIGHTS: u32 = 64;
+const MIN_WEIGHT_BITS: u32 = 24;
+const MAX_WEIGHT_BITS: u32 = 96;
+
+/// Decode a block into `texels` in row-major order
+///
+/// The `texels` must have space for `block_width * block_height` texels.
+pub fn decode_block(
+    block: [u8; 16],
+    block_width: u32,
+    block_height: u32,
+    profile: Profile,
+    texels: &mut [[u16; 4]],
+) {
+    let block = u128::from_le_bytes(block);
+
+    if decode(block, block_width, block_height, profile, texels).is_none() {
+        texels.fill(profile.error_color());
+    }
+}
+
+fn decode(
+    block: u128,
+    block_width: u32,
+    block_height: u32,
+    profile: Profile,
+    texels: &mut [[u16; 4]],
+) -> Option<()> {
+    let mode = bits(block, 0, 11);
+
+    if mode & 0x1FF == 0x1FC {
+        return decode_void_extent(block, profile, texels);
+    }
+
+    let block_mode = BlockMode::new(mode)?;
+
+    if block_mode.width > block_width || block_mode.height > block_height {
+        return None;
+    }
+
+    let n_partitions = bits(block, 11, 2).checked_add(1)?;
+    if block_mode.dual_plane && n_partitions == 4 {
+        return None;
+    }
+
+    let n_planes = if block_mode.dual_plane { 2 } else { 1 };
+    let weight_count = block_mode
+        .width
+        .checked_mul(block_mode.height)?
+        .checked_mul(n_planes)?;
+    let weight_bits = ise_bit_count(weight_count, block_mode.weight_quant)?;
+
+    if weight_count > MAX_WEIGHTS || !(MIN_WEIGHT_BITS..=MAX_WEIGHT_BITS).contains(&weight_bits) {
+        return None;
+    }
+
+    let mut below_weights = 128_u32.checked_sub(weight_bits)?;
+
+    let mut endpoint_modes = [0; 4];
+    let color_start;
+    if n_partitions == 1 {
+        endpoint_modes[0] = bits(block, 13, 4);
+        color_start = 17;
+    } else {
+        let selector = bits(block, 23, 2);
+        if selector == 0 {
+            endpoint_modes.fill(bits(block, 25, 4));
+        } else {
+            // Bits that don't fit in the block configuration are stored below
+            // the weights
+            let extra_bits = n_partitions.checked_mul(3)?.checked_sub(4)?;
+            below_weights = below_weights.checked_sub(extra_bits)?;
+            let encoded = bits(block, 23, 6) | bits(block, below_weights, extra_bits) << 6;
+
+            let base_class = selector.checked_sub(1)?;
+            for (i, endpoint_mode) in (0..n_partitions).zip(&mut endpoint_modes) {
+                let class = ((encoded >> i.checked_add(2)?) & 1).checked_add(base_class)?;
+                let mode = (encoded
+                    >> i.checked_mul(2)?
+                        .checked_add(n_partitions)?
+                        .checked_add(2)?)
+                    & 3;
+                *endpoint_mode = class << 2 | mode;
+            }
+        }
+        color_start = 29;
+    }
+
+    let plane2_component = if block_mode.dual_plane {
+        below_weights = below_weights.checked_sub(2)?;
+        Some(bits(block, below_weights, 2))
+    } else {
+        None
+    };
+
+    let endpoint_modes = endpoint_modes.get(..n_partitions.try_into().ok()?)?;
+
+    let n_color_values = endpoint_modes
+        .iter()
+        .copied()
+        .map(n_endpoint_values)
+        .sum::<u32>();
+    if n_color_values > 18 {
+        return None;
+    }
+
+    let color_bits = below_weights.checked_sub(color_start)?;
+    let color_quant = (MIN_COLOR_QUANT_LEVEL..QUANT_LEVELS.len())
+        .rev()
+        .find(|level| ise_bit_count(n_color_values, *level).is_some_and(|x| x <= color_bits))?;
+
+    let color_values = decode_ise(block, color_start, n_color_values, color_quant)?
+        .into_iter()
+        .map(|(d, m)| unquantize_color(color_quant, d, m))
+        .collect::<Option<Vec<_>>>()?;
+
+    let mut endpoints = Vec::new();
+    let mut color_values = color_values.as_slice();
+    for mode in endpoint_modes {
+        let (values, rest) =
+            color_values.split_at_checked(n_endpoint_values(*mode).try_into().ok()?)?;
+        let endpoint = Endpoints::new(*mode, values)?;
+        if endpoint.hdr.contains(&true) && profile != Profile::Hdr {
+            return None;
+        }
+        endpoints.push(endpoint);
+        color_values = rest;
+    }
+
+    // Weights are stored in reverse order from the top of the block
+    let weights = decode_ise(
+        block.reverse_bits(),
+        0,
+        weight_count,
+        block_mode.weight_quant,
+    )?
+    .into_iter()
+    .map(|(d, m)| unquantize_weight(block_mode.weight_quant, d, m))
+    .collect::<Option<Vec<_>>>()?;
+
+    let small_block = block_width.checked_mul(block_height)? < 31;
+    let partition_seed = bits(block, 13, 10);
+
+    let mut texels = texels.iter_mut();
+    for y in 0..block_height {
+        for x in 0..block_width {
+            let texel = texels.next()?;
+
+            let partition = if n_partitions > 1 {
+                select_partition(partition_seed, x, y, n_partitions, small_block)
+            } else {
+                0
+            };
+            let endpoint = endpoints.get(partition)?;
+
+            let weight = |plane| {
+                infill_weight(
+                    &weights,
+                    plane,
+                    n_planes,
+                    (x, y),
+                    (block_width, block_height),
+                    (block_mode.width, block_mode.height),
+                )
+            };
+            let weight1 = weight(0)?;
+            let weight2 = if block_mode.dual_plane {
+                weight(1)?
+            } else {
+                weight1
+            };
+
+            for (component, value) in (0..4).zip(texel.iter_mut()) {
+                let weight = if plane2_component == Some(component) {
+                    weight2
+                } else {
+                    weight1
+                };
+                *value = endpoint.interpolate(component.try_into().ok()?, weight, profile)?;
+            }
+        }
+    }
+
+    Some(())
+}
+
+/// Number of color values for an endpoint mode
+///
+/// Each component is stored with two values.
+fn n_endpoint_values(mode: u32) -> u32 {
+    (mode >> 2).saturating_add(1).saturating_mul(2)
+}
+
+/// Block with a single color
+fn decode_void_extent(block: u128, profile: Profile, texels: &mut [[u16; 4]]) -> Option<()> {
+    let hdr = bits(block, 9, 1) == 1;
+    let color = [64, 80, 96, 112].map(|start| bits(block, start, 16).to_le_bytes());
+    let color = color.map(|[b0, b1, _, _]| u16::from_le_bytes([b0, b1]));
+
+    let color = match (profile, hdr) {
+        (Profile::Ldr | Profile::LdrSrgb, false) => color,
+        (Profile::Ldr | Profile::LdrSrgb, true) => return None,
+        (Profile::Hdr, false) => color.map(unorm16_to_f16),
+        (Profile::Hdr, true) => color,
+    };
+
+    texels.fill(color);
+
+    Some(())
+}
+
+/// Size of the weight grid and encoding of the weights
+struct BlockMode {
+    width: u32,
+    height: u32,
+    dual_plane: bool,
+    weight_quant: usize,
+}
+
+impl BlockMode {
+    fn new(mode: u32) -> Option<Self> {
+        let a = (mode >> 5) & 3;
+        let mut high_precision = (mode >> 9) & 1;
+        let mut dual_plane = (mode >> 10) & 1;
+        let mut range = (mode >> 4) & 1;
+
+        let (width, height);
+        if mode & 3 != 0 {
+            range |= (mode & 3) << 1;
+            let b = (mode >> 7) & 3;
+            (width, height) = match (mode >> 2) & 3 {
+                0 => (b | 4, a.saturating_add(2)),
+                1 => (b | 8, a.saturating_add(2)),
+                2 => (a.saturating_add(2), b | 8),
+                _ if mode & 0x100 != 0 => ((b & 1) | 2, a.saturating_add(2)),
+                _ => (a.saturating_add(2), (b & 1) | 6),
+            };
+        } else {
+            range |= ((mode >> 2) & 3) << 1;
+            if (mode >> 2) & 3 == 0 {
+                return None;
+            }
+            let b = (mode >> 9) & 3;
+            (width, height) = match (mode >> 7) & 3 {
+                0 => (12, a.saturating_add(2)),
+                1 => (a.saturating_add(2), 12),
+                2 => {
+                    high_precision = 0;
+                    dual_plane = 0;
+                    (a.saturating_add(6), b.saturating_add(6))
+                }
+                _ => match a {
+                    0 => (6, 10),
+                    1 => (10, 6),
+                    _ => return None,
+                },
+            };
+        }
+
+        // The range is at least 2
+        let weight_quant = usize::try_from(range.checked_sub(2)?).ok()?;
+        let weight_quant = if high_precision == 1 {
+            weight_quant.checked_add(6)?
+        } else {
+            weight_quant
+        };
+
+        Some(Self {
+            width,
+            height,
+            dual_plane: dual_plane == 1,
+            weight_quant,
+        })
+    }
+}
+
+/// Endpoint colors of a partition
+///
+/// LDR components are stored as 8-bit values and HDR components as 16-bit
+/// logarithmic values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Endpoints {
+    e0: [i32; 4],
+    e1: [i32; 4],
+    hdr: [bool; 4],
+}
+
+impl Endpoints {
+    fn new(mode: u32, v: &[i32]) -> Option<Self> {
+        let ldr = |e0, e1| Self {
+            e0,
+            e1,
+            hdr: [false; 4],
+        };
+
+        let endpoints = match (mode, v) {
+            // Luminance, direct
+            (0, &[v0, v1]) => ldr([v0, v0, v0, 255], [v1, v1, v1, 255]),
+            // Luminance, base and offset
+            (1, &[v0, v1]) => {
+                let l0 = (v0 >> 2) | (v1 & 0xC0);
+                let l1 = l0.saturating_add(v1 & 0x3F).min(255);
+                ldr([l0, l0, l0, 255], [l1, l1, l1, 255])
+            }
+            // HDR luminance, large range
+            (2, &[v0, v1]) => {
+                let (y0, y1) = if v1 >= v0 {
+                    (v0 << 4, v1 << 4)
+                } else {
+                    ((v1 << 4) | 8, (v0 << 4).saturating_sub(8))
+                };
+                Self::hdr_luminance(y0, y1)
+            }
+            // HDR luminance, small range
+            (3, &[v0, v1]) => {
+                let (y0, d) = if v0 & 0x80 != 0 {
+                    (((v1 & 0xE0) << 4) | ((v0 & 0x7F) << 2), (v1 & 0x1F) << 2)
+                } else {
+                    (((v1 & 0xF0) << 4) | ((v0 & 0x7F) << 1), (v1 & 0xF) << 1)
+                };
+                Self::hdr_luminance(y0, y0.saturating_add(d).min(0xFFF))
+            }
+            // Luminance and alpha, direct
+            (4, &[v0, v1, v2, v3]) => ldr([v0, v0, v0, v2], [v1, v1, v1, v3]),
+            // Luminance and alpha, base and offset
+            (5, &[v0, v1, v2, v3]) => {
+                let (l, dl) = bit_transfer_signed(v0, v1);
+                let (a, da) = bit_transfer_signed(v2, v3);
+                let l1 = l.saturating_add(dl);
+                let a1 = a.saturating_add(da);
+                ldr([l, l, l, a], [l1, l1, l1, a1]).clamped()
+            }
+            // RGB, base and scale
+            (6, &[v0, v1, v2, v3]) => ldr(
+                [
+                    v0.saturating_mul(v3) >> 8,
+                    v1.saturating_mul(v3) >> 8,
+                    v2.saturating_mul(v3) >> 8,
+                    255,
+                ],
+                [v0, v1, v2, 255],
+            ),
+            // HDR RGB, base and scale
+            (7, &[v0, v1, v2, v3]) => hdr_rgb_base_scale([v0, v1, v2, v3]),
+            // RGB, direct
+            (8, &[v0, v1, v2, v3, v4, v5]) => Self::rgba_direct([v0, v1, v2, v3, v4, v5, 255, 255]),
+            // RGB, base and offset
+            (9, &[v0, v1, v2, v3, v4, v5]) => {
+                Self::rgba_base_offset([v0, v1, v2, v3, v4, v5, 255, 0])
+            }
+            // RGB, base and scale plus two alpha values
+            (10, &[v0, v1, v2, v3, v4, v5]) => ldr(
+                [
+                    v0.saturating_mul(v3) >> 8,
+                    v1.saturating_mul(v3) >> 8,
+                    v2.saturating_mul(v3) >> 8,
+                    v4,
+                ],
+                [v0, v1, v2, v5],
+            ),
+            // HDR RGB, direct
+            (11, &[v0, v1, v2, v3, v4, v5]) => hdr_rgb_direct([v0, v1, v2, v3, v4, v5]),
+            // RGBA, direct
+            (12, &[v0, v1, v2, v3, v4, v5, v6, v7]) => {
+                Self::rgba_direct([v0, v1, v2, v3, v4, v5, v6, v7])
+            }
+            // RGBA, base and offset
+            (13, &[v0, v1, v2, v3, v4, v5, v6, v7]) => {
+                Self::rgba_base_offset([v0, v1, v2, v3, v4, v5, v6, v7])
+            }
+            // HDR RGB, direct plus LDR alpha
+            (14, &[v0, v1, v2, v3, v4, v5, v6, v7]) => {
+                let mut endpoints = hdr_rgb_direct([v0, v1, v2, v3, v4, v5]);
+                endpoints.e0[3] = v6;
+                endpoints.e1[3] = v7;
+                endpoints.hdr[3] = false;
+                endpoints
+            }
+            // HDR RGB, direct plus HDR alpha
+            (15, &[v0, v1, v2, v3, v4, v5, v6, v7]) => {
+                let mut endpoints = hdr_rgb_direct([v0, v1, v2, v3, v4, v5]);
+                (endpoints.e0[3], endpoints.e1[3]) = hdr_alpha(v6, v7);
+                endpoints
+            }
+            _ => return None,
+        };
+
+        Some(endpoints)
+    }
+
+    fn hdr_luminance(y0: i32, y1: i32) -> Self {
+        Self {
+            e0: [y0 << 4, y0 << 4, y0 << 4, LNS_ONE],
+            e1: [y1 << 4, y1 << 4, y1 << 4, LNS_ONE],
+            hdr: [true; 4],
+        }
+    }
+
+    fn rgba_direct([v0, v1, v2, v3, v4, v5, v6, v7]: [i32; 8]) -> Self {
+        let s0 = v0.saturating_add(v2).saturating_add(v4);
+        let s1 = v1.saturating_add(v3).saturating_add(v5);
+
+        let (e0, e1) = if s1 >= s0 {
+            ([v0, v2, v4, v6], [v1, v3, v5, v7])
+        } else {
+            (
+                blue_contract([v1, v3, v5, v7]),
+                blue_contract([v0, v2, v4, v6]),
+            )
+        };
+
+        Self {
+            e0,
+            e1,
+            hdr: [false; 4],
+        }
+    }
+
+    fn rgba_base_offset([v0, v1, v2, v3, v4, v5, v6, v7]: [i32; 8]) -> Self {
+        let (r, dr) = bit_transfer_signed(v0, v1);
+        let (g, dg) = bit_transfer_signed(v2, v3);
+        let (b, db) = bit_transfer_signed(v4, v5);
+        let (a, da) = bit_transfer_signed(v6, v7);
+
+        let base = [r, g, b, a];
+        let offset = [
+            r.saturating_add(dr),
+            g.saturating_add(dg),
+            b.saturating_add(db),
+            a.saturating_add(da),
+        ];
+
+        let (e0, e1) = if dr.saturating_add(dg).saturating_add(db) >= 0 {
+            (base, offset)
+        } else {
+            (blue_contract(offset), blue_contract(base))
+        };
+
+        Self {
+            e0,
+            e1,
+            hdr: [false; 4],
+        }
+        .clamped()
+    }
+
+    fn clamped(self) -> Self {
+        Self {
+            e0: self.e0.map(|x| x.clamp(0, 255)),
+            e1: self.e1.map(|x| x.clamp(0, 255)),
+            ..self
+        }
+    }
+
+    /// Color for a weight in the range of 0 to 64
+    fn interpolate(&self, component: usize, weight: i32, profile: Profile) -> Option<u16> {
+        let hdr = *self.hdr.get(component)?;
+        let [c0, c1] = [self.e0, self.e1].map(|e| {
+            let e = e.get(component).copied().unwrap_or_default();
+            if hdr {
+                e
+            } else if profile == Profile::LdrSrgb {
+                (e << 8) | 0x80
+            } else {
+                (e << 8) | e
+            }
+        });
+
+        let value = c0
+            .saturating_mul(64_i32.saturating_sub(weight))
+            .saturating_add(c1.saturating_mul(weight))
+            .saturating_add(32)
+            >> 6;
+        let value = u16::try_from(value.clamp(0, 0xFFFF)).ok()?;
+
+        Some(match profile {
+            Profile::Ldr | Profile::LdrSrgb => value,
+            Profile::Hdr if hdr => lns_to_f16(value),
+            Profile::Hdr => unorm16_to_f16(value),
+        })
+    }
+}
+
+fn bit_transfer_signed(base: i32, offset: i32) -> (i32, i32) {
+    let base = (base >> 1) | (offset & 0x80);
+    let offset = (offset >> 1) & 0x3F;
+    let offset = if offset & 0x20 != 0 {
+        offset.saturating_sub(0x40)
+    } else {
+        offset
+    };
+
+    (base, offset)
+}
+
+fn blue_contract([r, g, b, a]: [i32; 4]) -> [i32; 4] {
+    [r.saturating_add(b) >> 1, g.saturating_add(b) >> 1, b, a]
+}
+
+/// Endpoints for HDR mode 7
+fn hdr_rgb_base_scale([v0, v1, v2, v3]: [i32; 4]) -> Endpoints {
+    let mode_value = ((v0 & 0xC0) >> 6) | ((v1 & 0x80) >> 5) | ((v2 & 0x80) >> 4);
+
+    let (major_component, mode) = if mode_value & 0xC != 0xC {
+        (mode_value >> 2, mode_value & 3)
+    } else if mode_value != 0xF {
+        (mode_value & 3, 4)
+    } else {
+        (0, 5)
+    };
+
+    let mut red = v0 & 0x3F;
+    let mut green = v1 & 0x1F;
+    let mut blue = v2 & 0x1F;
+    let mut scale = v3 & 0x1F;
+
+    let bit0 = (v1 >> 6) & 1;
+    let bit1 = (v1 >> 5) & 1;
+    let bit2 = (v2 >> 6) & 1;
+    let bit3 = (v2 >> 5) & 1;
+    let bit4 = (v3 >> 7) & 1;
+    let bit5 = (v3 >> 6) & 1;
+    let bit6 = (v3 >> 5) & 1;
+
+    let mode_bit = 1 << mode;
+
+    if mode_bit & 0x30 != 0 {
+        green |= bit0 << 6;
+        blue |= bit2 << 6;
+    }
+    if mode_bit & 0x3A != 0 {
+        green |= bit1 << 5;
+        blue |= bit3 << 5;
+    }
+
+    if mode_bit & 0x3D != 0 {
+        scale |= bit6 << 5;
+    }
+    if mode_bit & 0x2D != 0 {
+        scale |= bit5 << 6;
+    }
+    if mode_bit & 0x04 != 0 {
+        scale |= bit4 << 7;
+    }
+
+    if mode_bit & 0x3B != 0 {
+        red |= bit4 << 6;
+    }
+    if mode_bit & 0x04 != 0 {
+        red |= bit3 << 6;
+    }
+    if mode_bit & 0x10 != 0 {
+        red |= bit5 << 7;
+    }
+    if mode_bit & 0x0F != 0 {
+        red |= bit2 << 7;
+    }
+    if mode_bit & 0x05 != 0 {
+        red |= bit1 << 8;
+        red |= bit0 << 9;
+    }
+    if mode_bit & 0x0A != 0 {
+        red |= bit0 << 8;
+    }
+    if mode_bit & 0x02 != 0 {
+        red |= bit6 << 9;
+        red |= bit5 << 10;
+    }
+    if mode_bit & 0x01 != 0 {
+        red |= bit3 << 10;
+    }
+
+    // Expand to 12 bits
+    let shift = match mode {
+        0 | 1 => 1,
+        mode => mode,
+    };
+    red <<= shift;
+    green <<= shift;
+    blue <<= shift;
+    scale <<= shift;
+
+    // Green and blue are stored as differences, except for mode 5
+    if mode != 5 {
+        green = red.saturating_sub(green);
+        blue = red.saturating_sub(blue);
+    }
+
+    let mut e1 = [red, green, blue];
+    match major_component {
+        1 => e1.swap(0, 1),
+        2 => e1.swap(0, 2),
+        _ => {}
+    }
+    let e0 = e1.map(|x| x.saturating_sub(scale));
+
+    let [e0, e1] = [e0, e1].map(|[r, g, b]| [r, g, b].map(|x| x.clamp(0, 0xFFF) << 4));
+
+    Endpoints {
+        e0: [e0[0], e0[1], e0[2], LNS_ONE],
+        e1: [e1[0], e1[1], e1[2], LNS_ONE],
+        hdr: [true; 4],
+    }
+}
+
+/// Endpoints for HDR mode 11 and the color of modes 14 and 15
+fn hdr_rgb_direct([v0, v1, v2, v3, v4, v5]: [i32; 6]) -> Endpoints {
+    let mode_value = ((v1 & 0x80) >> 7) | ((v2 & 0x80) >> 6) | ((v3 & 0x80) >> 5);
+    let major_component = ((v4 & 0x80) >> 7) | ((v5 & 0x80) >> 6);
+
+    if major_component == 3 {
+        return Endpoints {
+            e0: [v0 << 8, v2 << 8, (v4 & 0x7F) << 9, LNS_ONE],
+            e1: [v1 << 8, v3 << 8, (v5 & 0x7F) << 9, LNS_ONE],
+            hdr: [true; 4],
+        };
+    }
+
+    let mut a = v0 | ((v1 & 0x40) << 2);
+    let mut b0 = v2 & 0x3F;
+    let mut b1 = v3 & 0x3F;
+    let mut c = v1 & 0x3F;
+    let mut d0 = v4 & 0x7F;
+    let mut d1 = v5 & 0x7F;
+
+    let d_bits = match mode_value {
+        0 | 2 => 7,
+        4 | 6 => 5,
+        _ => 6,
+    };
+
+    let bit0 = (v2 >> 6) & 1;
+    let bit1 = (v3 >> 6) & 1;
+    let bit2 = (v4 >> 6) & 1;
+    let bit3 = (v5 >> 6) & 1;
+    let bit4 = (v4 >> 5) & 1;
+    let bit5 = (v5 >> 5) & 1;
+
+    let mode_bit = 1 << mode_value;
+
+    if mode_bit & 0xA4 != 0 {
+        a |= bit0 << 9;
+    }
+    if mode_bit & 0x8 != 0 {
+        a |= bit2 << 9;
+    }
+    if mode_bit & 0x50 != 0 {
+        a |= bit4 << 9;
+        a |= bit5 << 10;
+    }
+    if mode_bit & 0xA0 != 0 {
+        a |= bit1 << 10;
+    }
+    if mode_bit & 0xC0 != 0 {
+        a |= bit2 << 11;
+    }
+
+    if mode_bit & 0x4 != 0 {
+        c |= bit1 << 6;
+    }
+    if mode_bit & 0xE8 != 0 {
+        c |= bit3 << 6;
+    }
+    if mode_bit & 0x20 != 0 {
+        c |= bit2 << 7;
+    }
+
+    if mode_bit & 0x5B != 0 {
+        b0 |= bit0 << 6;
+        b1 |= bit1 << 6;
+    }
+    if mode_bit & 0x12 != 0 {
+        b0 |= bit2 << 7;
+        b1 |= bit3 << 7;
+    }
+
+    if mode_bit & 0xAF != 0 {
+        d0 |= bit4 << 5;
+        d1 |= bit5 << 5;
+    }
+    if mode_bit & 0x5 != 0 {
+        d0 |= bit2 << 6;
+        d1 |= bit3 << 6;
+    }
+
+    // Sign extend the differences
+    let sign_shift = 32_i32.saturating_sub(d_bits);
+    let d0 = (d0 << sign_shift) >> sign_shift;
+    let d1 = (d1 << sign_shift) >> sign_shift;
+
+    // Expand to 12 bits
+    let shift = (mode_value >> 1) ^ 3;
+    let [a, b0, b1, c, d0, d1] = [a, b0, b1, c, d0, d1].map(|x| x << shift);
+
+    let mut e1 = [a, a.saturating_sub(b0), a.saturating_sub(b1)];
+    let mut e0 = [
+        a.saturating_sub(c),
+        a.saturating_sub(b0).saturating_sub(c).saturating_sub(d0),
+        a.saturating_sub(b1).saturating_sub(c).saturating_sub(d1),
+    ];
+
+    match major_component {
+        1 => {
+            e0.swap(0, 1);
+            e1.swap(0, 1);
+        }
+        2 => {
+            e0.swap(0, 2);
+            e1.swap(0, 2);
+        }
+        _ => {}
+    }
+
+    let [e0, e1] = [e0, e1].map(|[r, g, b]| [r, g, b].map(|x| x.clamp(0, 0xFFF) << 4));
+
+    Endpoints {
+        e0: [e0[0], e0[1], e0[2], LNS_ONE],
+        e1: [e1[0], e1[1], e1[2], LNS_ONE],
+        hdr: [true; 4],
+    }
+}
+
+/// HDR alpha endpoints of mode 15
+fn hdr_alpha(v6: i32, v7: i32) -> (i32, i32) {
+    let selector = ((v6 >> 7) & 1) | ((v7 >> 6) & 2);
+    let mut a0 = v6 & 0x7F;
+    let mut a1 = v7 & 0x7F;
+
+    if selector == 3 {
+        a0 <<= 5;
+        a1 <<= 5;
+    } else {
+        a0 |= (a1 << selector.saturating_add(1)) & 0x780;
+        a1 &= 0x3F >> selector;
+        a1 ^= 0x20 >> selector;
+        a1 = a1.saturating_sub(0x20 >> selector);
+        a0 <<= 4_i32.saturating_sub(selector);
+        a1 <<= 4_i32.saturating_sub(selector);
+        a1 = a1.saturating_add(a0).clamp(0, 0xFFF);
+    }
+
+    (a0 << 4, a1 << 4)
+}
+
+/// Bilinear interpolation of the weight grid for a texel
+fn infill_weight(
+    weights: &[i32],
+    plane: usize,
+    n_planes: u32,
+    (x, y): (u32, u32),
+    (block_width, block_height): (u32, u32),
+    (grid_width, grid_height): (u32, u32),
+) -> Option<i32> {
+    let weight_at = |index: u32| {
+        let index = usize::try_from(index.checked_mul(n_planes)?)
+            .ok()?
+            .checked_add(plane)?;
+        Some(weights.get(index).copied().unwrap_or_default())
+    };
+
+    if (block_width, block_height) == (grid_width, grid_height) {
+        return weight_at(y.checked_mul(block_width)?.checked_add(x)?);
+    }
+
+    let ds = (block_width / 2)
+        .checked_add(1024)?
+        .checked_div(block_width.checked_sub(1)?)?;
+    let dt = (block_height / 2)
+        .checked_add(1024)?
+        .checked_div(block_height.checked_sub(1)?)?;
+
+    let gs = ds
+        .checked_mul(x)?
+        .checked_mul(grid_width.checked_sub(1)?)?
+        .checked_add(32)?
+        >> 6;
+    let gt = dt
+        .checked_mul(y)?
+        .checked_mul(grid_height.checked_sub(1)?)?
+        .checked_add(32)?
+        >> 6;
+
+    let (js, fs) = (gs >> 4, gs & 0xF);
+    let (jt, ft) = (gt >> 4, gt & 0xF);
+
+    let v0 = jt.checked_mul(grid_width)?.checked_add(js)?;
+
+    let w11 = fs.checked_mul(ft)?.checked_add(8)? >> 4;
+    let w10 = ft.checked_sub(w11)?;
+    let w01 = fs.checked_sub(w11)?;
+    let w00 = 16_u32.checked_sub(fs)?.checked_sub(ft)?.checked_add(w11)?;
+
+    let p00 = weight_at(v0)?;
+    // Points outside of the grid always have a factor of zero
+    let p01 = weight_at(v0.checked_add(1)?)?;
+    let p10 = weight_at(v0.checked_add(grid_width)?)?;
+    let p11 = weight_at(v0.checked_add(grid_width)?.checked_add(1)?)?;
+
+    let [w00, w01, w10, w11] = [w00, w01, w10, w11].map(|x| i32::try_from(x).unwrap_or_default());
+
+    Some(
+        p00.checked_mul(w00)?
+            .checked_add(p01.checked_mul(w01)?)?
+            .checked_add(p10.checked_mul(w10)?)?
+            .checked_add(p11.checked_mul(w11)?)?
+            .checked_add(8)?
+            >> 4,
+    )
+}
+
+/// Partition index of a texel
+fn select_partition(seed: u32, x: u32, y: u32, n_partitions: u32, small_block: bool) -> usize {
+    let (x, y) = if small_block {
+        (x << 1, y << 1)
+    } else {
+        (x, y)
+    };
+
+    let seed = seed.wrapping_add(n_partitions.wrapping_sub(1).wrapping_mul(1024));
+    let rnum = hash52(seed);
+
+    let mut seeds = [0, 4, 8, 12, 16, 20, 24, 28].map(|shift| {
+        let value = (rnum >> shift) & 0xF;
+        value.wrapping_mul(value)
+    });
+
+    let (sh1, sh2) = if seed & 1 != 0 {
+        (
+            if seed & 2 != 0 { 4 } else { 5 },
+            if n_partitions == 3 { 6 } else { 5 },
+        )
+    } else {
+        (
+            if n_partitions == 3 { 6 } else { 5 },
+            if seed & 2 != 0 { 4 } else { 5 },
+        )
+    };
+
+    for (seed, shift) in seeds.iter_mut().zip([sh1, sh2].into_iter().cycle()) {
+        *seed >>= shift;
+    }
+
+    // The seeds for the z coordinate are not needed for 2D blocks
+    let [s1, s2, s3, s4, s5, s6, s7, s8] = seeds;
+    let value = |sa: u32, sb: u32, shift: u32| {
+        sa.wrapping_mul(x)
+            .wrapping_add(sb.wrapping_mul(y))
+            .wrapping_add(rnum >> shift)
+            & 0x3F
+    };
+
+    let a = value(s1, s2, 14);
+    let b = value(s3, s4, 10);
+    let c = if n_partitions < 3 {
+        0
+    } else {
+        value(s5, s6, 6)
+    };
+    let d = if n_partitions < 4 {
+        0
+    } else {
+        value(s7, s8, 2)
+    };
+
+    if a >= b && a >= c && a >= d {
+        0
+    } else if b >= c && b >= d {
+        1
+    } else if c >= d {
+        2
+    } else {
+        3
+    }
+}
+
+fn hash52(mut value: u32) -> u32 {
+    value ^= value >> 15;
+    value = value.wrapping_mul(0xEEDE_0891);
+    value ^= value >> 5;
+    value = value.wrapping_add(value << 16);
+    value ^= value >> 7;
+    value ^= value >> 3;
+    value ^= value << 6;
+    value ^= value >> 17;
+    value
+}
+
+/// Number of bits for `count` values in the integer sequence encoding
+fn ise_bit_count(count: u32, level: usize) -> Option<u32> {
+    let (packing, bits) = *QUANT_LEVELS.get(level)?;
+    let extra = match packing {
+        Packing::Bits => 0,
+        Packing::Trits => count.checked_mul(8)?.checked_add(4)? / 5,
+        Packing::Quints => count.checked_mul(7)?.checked_add(2)? / 3,
+    };
+
+    count.checked_mul(bits)?.checked_add(extra)
+}
+
+/// Values of the integer sequence encoding
+///
+/// Returns pairs of the trit or quint and the additional bits.
+fn decode_ise(data: u128, start: u32, count: u32, level: usize) -> Option<Vec<(u32, u32)>> {
+    let (packing, n_bits) = *QUANT_LEVELS.get(level)?;
+    let end = start.checked_add(ise_bit_count(count, level)?)?;
+
+    // Missing bits at the end of the sequence are treated as zero
+    let mut reader = BitReader {
+        data: data & !u128::MAX.checked_shl(end).unwrap_or_default(),
+        pos: start,
+    };
+
+    let count = usize::try_from(count).ok()?;
+    let mut values = Vec::with_capacity(count);
+
+    while values.len() < count {
+        match packing {
+            Packing::Bits => values.push((0, reader.read(n_bits))),
+            Packing::Trits => {
+                let mut m = [0; 5];
+                let mut t = 0;
+                for (i, (m, t_bits)) in m.iter_mut().zip([2, 2, 1, 2, 1]).enumerate() {
+                    *m = reader.read(n_bits);
+                    t |= reader.read(t_bits) << [0, 2, 4, 5, 7][i];
+                }
+                values.extend(decode_trits(t).into_iter().zip(m));
+            }
+            Packing::Quints => {
+                let mut m = [0; 3];
+                let mut q = 0;
+                for (i, (m, q_bits)) in m.iter_mut().zip([3, 2, 2]).enumerate() {
+                    *m = reader.read(n_bits);
+                    q |= reader.read(q_bits) << [0, 3, 5][i];
+                }
+                values.extend(decode_quints(q).into_iter().zip(m));
+            }
+        }
+    }
+
+    values.truncate(count);
+
+    Some(values)
+}
+
+fn decode_trits(t: u32) -> [u32; 5] {
+    let bit = |value: u32, i: u32| (value >> i) & 1;
+
+    let (c, t3, t4);
+    if (t >> 2) & 7 == 7 {
+        c = ((t >> 5) & 7) << 2 | (t & 3);
+        (t3, t4) = (2, 2);
+    } else {
+        c = t & 0x1F;
+        if (t >> 5) & 3 == 3 {
+            (t3, t4) = (bit(t, 7), 2);
+        } else {
+            (t3, t4) = ((t >> 5) & 3, bit(t, 7));
+        }
+    }
+
+    let (t0, t1, t2);
+    if c & 3 == 3 {
+        t2 = 2;
+        t1 = bit(c, 4);
+        t0 = bit(c, 3) << 1 | (bit(c, 2) & !bit(c, 3) & 1);
+    } else if (c >> 2) & 3 == 3 {
+        t2 = 2;
+        t1 = 2;
+        t0 = c & 3;
+    } else {
+        t2 = bit(c, 4);
+        t1 = (c >> 2) & 3;
+        t0 = bit(c, 1) << 1 | (bit(c, 0) & !bit(c, 1) & 1);
+    }
+
+    [t0, t1, t2, t3, t4]
+}
+
+fn decode_quints(q: u32) -> [u32; 3] {
+    let bit = |value: u32, i: u32| (value >> i) & 1;
+
+    if (q >> 1) & 3 == 3 && (q >> 5) & 3 == 0 {
+        let q2 = bit(q, 0) << 2 | (bit(q, 4) & !bit(q, 0) & 1) << 1 | (bit(q, 3) & !bit(q, 0) & 1);
+        return [4, 4, q2];
+    }
+
+    let (q2, c) = if (q >> 1) & 3 == 3 {
+        (4, ((q >> 3) & 3) << 3 | (!(q >> 5) & 3) << 1 | bit(q, 0))
+    } else {
+        ((q >> 5) & 3, q & 0x1F)
+    };
+
+    let (q0, q1) = if c & 7 == 5 {
+        ((c >> 3) & 3, 4)
+    } else {
+        (c & 7, (c >> 3) & 3)
+    };
+
+    [q0, q1, q2]
+}
+
+/// Weight in the range of 0 to 64
+fn unquantize_weight(level: usize, d: u32, m: u32) -> Option<i32> {
+    let (packing, n_bits) = *QUANT_LEVELS.get(level)?;
+
+    let value = match (packing, n_bits) {
+        (Packing::Bits, _) => replicate(m, n_bits, 6),
+        (Packing::Trits, 0) => *[0, 32, 63].get(usize::try_from(d).ok()?)?,
+        (Packing::Quints, 0) => *[0, 16, 32, 47, 63].get(usize::try_from(d).ok()?)?,
+        _ => {
+            let (c, pattern): (u32, &[u8]) = match (packing, n_bits) {
+                (Packing::Trits, 1) => (50, b"0000000"),
+                (Packing::Quints, 1) => (28, b"0000000"),
+                (Packing::Trits, 2) => (23, b"b000b0b"),
+                (Packing::Quints, 2) => (13, b"b0000b0"),
+                (Packing::Trits, 3) => (11, b"cb000cb"),
+                _ => return None,
+            };
+            unquantize(d, m, c, pattern, 0x7F, 0x20)?
+        }
+    };
+
+    let value = if value > 32 {
+        value.checked_add(1)?
+    } else {
+        value
+    };
+
+    i32::try_from(value).ok()
+}
+
+/// Color value in the range of 0 to 255
+fn unquantize_color(level: usize, d: u32, m: u32) -> Option<i32> {
+    let (packing, n_bits) = *QUANT_LEVELS.get(level)?;
+
+    let value = match (packing, n_bits) {
+        (Packing::Bits, _) => replicate(m, n_bits, 8),
+        _ => {
+            let (c, pattern): (u32, &[u8]) = match (packing, n_bits) {
+                (Packing::Trits, 1) => (204, b"000000000"),
+                (Packing::Quints, 1) => (113, b"000000000"),
+                (Packing::Trits, 2) => (93, b"b000b0bb0"),
+                (Packing::Quints, 2) => (54, b"b0000bb00"),
+                (Packing::Trits, 3) => (44, b"cb000cbcb"),
+                (Packing::Quints, 3) => (26, b"cb0000cbc"),
+                (Packing::Trits, 4) => (22, b"dcb000dcb"),
+                (Packing::Quints, 4) => (11, b"dcb0000dc"),
+                (Packing::Trits, 5) => (10, b"edcb000ed"),
+                (Packing::Quints, 5) => (5, b"edcb0000e"),
+                (Packing::Trits, 6) => (2, b"fedcb000f"),
+                _ => return None,
+            };
+            unquantize(d, m, c, pattern, 0x1FF, 0x80)?
+        }
+    };
+
+    i32::try_from(value).ok()
+}
+
+/// Unquantization of trit and quint based values
+///
+/// The `pattern` describes the bit layout of the additional bits from the
+/// most significant bit on. The letters `b` to `f` refer to the bits one
+/// to five of `m`.
+fn unquantize(d: u32, m: u32, c: u32, pattern: &[u8], a_mask: u32, top_bit: u32) -> Option<u32> {
+    let a = if m & 1 == 1 { a_mask } else { 0 };
+    let b = pattern.iter().fold(0, |b, x| {
+        let bit = match x {
+            b'0' => 0,
+            x => (m >> x.saturating_sub(b'a')) & 1,
+        };
+        b << 1 | bit
+    });
+
+    let t = d.checked_mul(c)?.checked_add(b)? ^ a;
+
+    Some((a & top_bit) | (t >> 2))
+}
+
+/// Repeat the `n_bits` of `value` until `target_bits` are filled
+fn replicate(value: u32, n_bits: u32, target_bits: u32) -> u32 {
+    if n_bits == 0 {
+        return 0;
+    }
+
+    let mut result = 0;
+    let mut filled = 0;
+    while filled < target_bits {
+        result = result << n_bits | value;
+        filled = filled.saturating_add(n_bits);
+    }
+
+    result >> filled.saturating_sub(target_bits)
+}
+
+/// Convert a value in the logarithmic HDR encoding to `f16`
+fn lns_to_f16(value: u16) -> u16 {
+    let mantissa = value & 0x7FF;
+    let exponent = value >> 11;
+
+    let mantissa = if mantissa < 512 {
+        mantissa.saturating_mul(3)
+    } else if mantissa < 1536 {
+        mantissa.saturating_mul(4).saturating_sub(512)
+    } else {
+        mantissa.saturating_mul(5).saturating_sub(2048)
+    };
+
+    ((exponent << 10) | (mantissa >> 3)).min(0x7BFF)
+}
+
+fn unorm16_to_f16(value: u16) -> u16 {
+    half::f16::from_f32(f32::from(value) / f32::from(u16::MAX)).to_bits()
+}
+
+fn bits(data: u128, start: u32, len: u32) -> u32 {
+    let value = data.checked_shr(start).unwrap_or_default()
+        & !u128::MAX.checked_shl(len).unwrap_or_default();
+    u32::try_from(value).unwrap_or_default()
+}
+
+struct BitReader {
+    data: u128,
+    pos: u32,
+}
+
+impl BitReader {
+    fn read(&mut self, len: u32) -> u32 {
+        let value = bits(self.data, self.pos, len);
+        self.pos = self.pos.saturating_add(len);
+        value
+    }
+}
+
+#[test]
+fn integer_sequence_test() {
+    for t in 0..256 {
+        assert!(decode_trits(t).iter().all(|x| *x < 3));
+    }
+    for q in 0..128 {
+        assert!(decode_quints(q).iter().all(|x| *x < 5));
+    }
+
+    // All combinations of trits and quints are encoded exactly once
+    let mut trits = (0..256).map(decode_trits).collect::<Vec<_>>();
+    trits.sort();
+    trits.dedup();
+    assert_eq!(trits.len(), 243);
+
+    let mut quints = (0..128).map(decode_quints).collect::<Vec<_>>();
+    quints.sort();
+    quints.dedup();
+    assert_eq!(quints.len(), 125);
+}
+
+#[test]
+fn unquantize_test() {
+    // Weights with a range of 0 to 5
+    let weights = (0..6)
+        .map(|x| unquantize_weight(4, x >> 1, x & 1))
+        .collect::<Option<Vec<_>>>();
+    assert_eq!(weights, Some(vec![0, 64, 12, 52, 25, 39]));
+
+    // Weights with a range of 0 to 11
+    let weights = (0..12)
+        .map(|x| unquantize_weight(7, x >> 2, x & 3))
+        .collect::<Option<Vec<_>>>();
+    assert_eq!(
+        weights,
+        Some(vec![0, 64, 17, 47, 5, 59, 23, 41, 11, 53, 28, 36])
+    );
+
+    // Colors with a range of 0 to 11
+    let colors = (0..12)
+        .map(|x| unquantize_color(7, x >> 2, x & 3))
+        .collect::<Option<Vec<_>>>();
+    assert_eq!(
+        colors,
+        Some(vec![0, 255, 69, 186, 23, 232, 92, 163, 46, 209, 116, 139])
+    );
+}
+
+#[test]
+fn void_extent_test() {
+    // Constant color block with all extents set to all ones
+    let mut block = [
+        0xFC, 0xFD, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    block[8..].copy_from_slice(&[0x00, 0xFF, 0x00, 0x80, 0xFF, 0x00, 0xFF, 0xFF]);
+
+    let mut texels = [[0; 4]; 16];
+    decode_block(block, 4, 4, Profile::Ldr, &mut texels);
+    assert!(texels
+        .iter()
+        .all(|x| *x == [0xFF00, 0x8000, 0x00FF, 0xFFFF]));
+
+    // HDR constant color blocks are not allowed in LDR images
+    block[1] |= 0x02;
+    decode_block(block, 4, 4, Profile::Ldr, &mut texels);
+    assert!(texels.iter().all(|x| *x == Profile::Ldr.error_color()));
+}
+
+#[test]
+fn block_test() {
+    // 5×5 weights with 3 bits each and a single partition with direct RGB
+    // endpoints
+    let mut block = 0xF3_u128 | 8 << 13;
+
+    // Color values with 6 bits
+    for (i, value) in [0_u128, 63, 63, 0, 32, 32].into_iter().enumerate() {
+        block |= value << (17 + i * 6);
+    }
+
+    // Weights are stored in reverse bit order from the top of the block
+    let mut weights = 0_u128;
+    weights |= 7 << (11 * 3);
+    block |= weights.reverse_bits();
+
+    let mut texels = [[0; 4]; 25];
+    decode_block(block.to_le_bytes(), 5, 5, Profile::Ldr, &mut texels);
+
+    for (i, texel) in texels.iter().enumerate() {
+        if i == 11 {
+            assert_eq!(*texel, [0xFFFF, 0, 0x8282, 0xFFFF]);
+        } else {
+            assert_eq!(*texel, [0, 0xFFFF, 0x8282, 0xFFFF]);
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Decoder for BC1 to BC5 blocks
+//!
+//! Also known as S3TC, DXT, and RGTC. All blocks contain 4×4 texels that are
+//! returned in row-major order.
+
+/// BC1 block with 8 bytes
+///
+/// If `alpha` is `false`, transparent texels are decoded as opaque black.
+pub fn decode_bc1(block: [u8; 8], alpha: bool) -> [[u8; 4]; 16] {
+    let mut texels = decode_color(block, true);
+
+    if !alpha {
+        for texel in &mut texels {
+            texel[3] = 255;
+        }
+    }
+
+    texels
+}
+
+/// BC2 block with 16 bytes
+pub fn decode_bc2(block: [u8; 16]) -> [[u8; 4]; 16] {
+    let (alpha, color) = split(block);
+    let alpha = u64::from_le_bytes(alpha);
+
+    let mut texels = decode_color(color, false);
+    for (i, texel) in (0..16).zip(&mut texels) {
+        let value = u8::try_from((alpha >> (i << 2)) & 0xF).unwrap_or_default();
+        texel[3] = value << 4 | value;
+    }
+
+    texels
+}
+
+/// BC3 block with 16 bytes
+pub fn decode_bc3(block: [u8; 16]) -> [[u8; 4]; 16] {
+    let (alpha, color) = split(block);
+
+    let mut texels = decode_color(color, false);
+    for (texel, value) in texels.iter_mut().zip(decode_channel(alpha)) {
+        texel[3] = value;
+    }
+
+    texels
+}
+
+/// BC4 block with 8 bytes, decoded into the red channel
+pub fn decode_bc4(block: [u8; 8]) -> [[u8; 4]; 16] {
+    decode_channel(block).map(|r| [r, 0, 0, 255])
+}
+
+/// BC5 block with 16 bytes, decoded into the red and green channels
+pub fn decode_bc5(block: [u8; 16]) -> [[u8; 4]; 16] {
+    let (red, green) = split(block);
+
+    let mut texels = [[0, 0, 0, 255]; 16];
+    for ((texel, r), g) in texels
+        .iter_mut()
+        .zip(decode_channel(red))
+        .zip(decode_channel(green))
+    {
+        texel[0] = r;
+        texel[1] = g;
+    }
+
+    texels
+}
+
+fn split(block: [u8; 16]) -> ([u8; 8], [u8; 8]) {
+    let [a0, a1, a2, a3, a4, a5, a6, a7, b0, b1, b2, b3, b4, b5, b6, b7] = block;
+
+    (
+        [a0, a1, a2, a3, a4, a5, a6, a7],
+        [b0, b1, b2, b3, b4, b5, b6, b7],
+    )
+}
+
+/// Color part of BC1 to BC3 blocks
+///
+/// The three color mode with a transparent texel is only available for BC1.
+fn decode_color(block: [u8; 8], allow_transparent: bool) -> [[u8; 4]; 16] {
+    let [c0_low, c0_high, c1_low, c1_high, i0, i1, i2, i3] = block;
+    let c0 = u16::from_le_bytes([c0_low, c0_high]);
+    let c1 = u16::from_le_bytes([c1_low, c1_high]);
+    let indices = u32::from_le_bytes([i0, i1, i2, i3]);
+
+    let rgb0 = rgb565(c0);
+    let rgb1 = rgb565(c1);
+
+    let palette = if c0 > c1 || !allow_transparent {
+        [
+            with_alpha(rgb0),
+            with_alpha(rgb1),
+            with_alpha(mix(rgb0, rgb1, 2, 1)),
+            with_alpha(mix(rgb0, rgb1, 1, 2)),
+        ]
+    } else {
+        [
+            with_alpha(rgb0),
+            with_alpha(rgb1),
+            with_alpha(mix(rgb0, rgb1, 1, 1)),
+            [0; 4],
+        ]
+    };
+
+    let mut texels = [[0; 4]; 16];
+    for (i, texel) in (0..16).zip(&mut texels) {
+        let index = (indices >> (i << 1)) & 3;
+        *texel = match index {
+            0 => palette[0],
+            1 => palette[1],
+            2 => palette[2],
+            _ => palette[3],
+        };
+    }
+
+    texels
+}
+
+/// Single channel part of BC3 to BC5 blocks
+fn decode_channel(block: [u8; 8]) -> [u8; 16] {
+    let [v0, v1, i0, i1, i2, i3, i4, i5] = block;
+    let indices = u64::from_le_bytes([i0, i1, i2, i3, i4, i5, 0, 0]);
+
+    let v0 = u32::from(v0);
+    let v1 = u32::from(v1);
+
+    let value = |index: u32| -> u32 {
+        match index {
+            0 => v0,
+            1 => v1,
+            // Eight interpolated values
+            i if v0 > v1 => {
+                let i = i.saturating_sub(1);
+                v0.saturating_mul(7_u32.saturating_sub(i))
+                    .saturating_add(v1.saturating_mul(i))
+                    / 7
+            }
+            // Six interpolated values plus zero and one
+            6 => 0,
+            7 => 255,
+            i => {
+                let i = i.saturating_sub(1);
+                v0.saturating_mul(5_u32.saturating_sub(i))
+                    .saturating_add(v1.saturating_mul(i))
+                    / 5
+            }
+        }
+    };
+
+    let mut values = [0; 16];
+    for (shift, value_out) in (0..48).step_by(3).zip(&mut values) {
+        let index = u32::try_from((indices >> shift) & 7).unwrap_or_default();
+        *value_out = u8::try_from(value(index)).unwrap_or(u8::MAX);
+    }
+
+    values
+}
+
+fn rgb565(color: u16) -> [u8; 3] {
+    let [r, g, b] = [color >> 11, (color >> 5) & 0x3F, color & 0x1F].map(|x| x.to_le_bytes()[0]);
+
+    [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2]
+}
+
+/// Weighted average of two colors
+fn mix(a: [u8; 3], b: [u8; 3], weight_a: u16, weight_b: u16) -> [u8; 3] {
+    let mut result = [0; 3];
+    for ((result, a), b) in result.iter_mut().zip(a).zip(b) {
+        let sum = u16::from(a)
+            .saturating_mul(weight_a)
+            .saturating_add(u16::from(b).saturating_mul(weight_b));
+        let value = sum
+            .checked_div(weight_a.saturating_add(weight_b))
+            .unwrap_or_default();
+        *result = u8::try_from(value).unwrap_or(u8::MAX);
+    }
+
+    result
+}
+
+fn with_alpha([r, g, b]: [u8; 3]) -> [u8; 4] {
+    [r, g, b, 255]
+}
+
+#[test]
+fn bc1_test() {
+    // Red and blue with the two interpolated colors in between
+    let block = [0x00, 0xF8, 0x1F, 0x00, 0b1110_0100, 0, 0, 0];
+    let texels = decode_bc1(block, true);
+
+    assert_eq!(texels[0], [255, 0, 0, 255]);
+    assert_eq!(texels[1], [0, 0, 255, 255]);
+    assert_eq!(texels[2], [170, 0, 85, 255]);
+    assert_eq!(texels[3], [85, 0, 170, 255]);
+
+    // Swapped colors enable the mode with transparent texels
+    let block = [0x1F, 0x00, 0x00, 0xF8, 0b1110_0100, 0, 0, 0];
+    let texels = decode_bc1(block, true);
+
+    assert_eq!(texels[2], [127, 0, 127, 255]);
+    assert_eq!(texels[3], [0, 0, 0, 0]);
+    assert_eq!(decode_bc1(block, false)[3], [0, 0, 0, 255]);
+}
+
+#[test]
+fn bc4_test() {
+    let block = [255, 0, 0b1000_1000, 0b1100_0110, 0b1111_1010, 0, 0, 0];
+    let values = decode_channel(block).to_vec();
+
+    assert_eq!(
+        values.get(..8),
+        Some([255, 0, 218, 182, 145, 109, 72, 36].as_slice())
+    );
+
+    let block = [0, 255, 0b1000_1000, 0b1100_0110, 0b1111_1010, 0, 0, 0];
+    let values = decode_channel(block).to_vec();
+
+    assert_eq!(
+        values.get(..8),
+        Some([0, 255, 51, 102, 153, 204, 0, 255].as_slice())
+    );
+}
@@ -0,0 +1,310 @@
+//! Decoder for ETC2 blocks
+//!
+//! Supports the RGB, RGB with punchthrough alpha, and RGBA variants. ETC1
+//! blocks are a subset of ETC2 RGB blocks. All blocks contain 4×4 texels
+//! that are returned in row-major order.
+
+/// Intensity modifiers for the individual and differential modes
+const MODIFIERS: [[i32; 4]; 8] = [
+    [2, 8, -2, -8],
+    [5, 17, -5, -17],
+    [9, 29, -9, -29],
+    [13, 42, -13, -42],
+    [18, 60, -18, -60],
+    [24, 80, -24, -80],
+    [33, 106, -33, -106],
+    [47, 183, -47, -183],
+];
+
+/// Distances for the T and H modes
+const DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+const ALPHA_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+/// ETC2 RGB block with 8 bytes
+///
+/// With `punchthrough` the block is decoded as RGB8A1 block.
+pub fn decode_rgb(block: [u8; 8], punchthrough: bool) -> [[u8; 4]; 16] {
+    let [b0, b1, b2, b3, b4, b5, b6, b7] = block;
+
+    // For punchthrough alpha, the differential bit marks opaque blocks
+    let differential = b3 & 2 != 0;
+    let opaque = !punchthrough || differential;
+    let indices = Indices {
+        msb: u16::from_be_bytes([b4, b5]),
+        lsb: u16::from_be_bytes([b6, b7]),
+    };
+
+    if !punchthrough && !differential {
+        let c1 = [b0 >> 4, b1 >> 4, b2 >> 4].map(extend4);
+        let c2 = [b0 & 0xF, b1 & 0xF, b2 & 0xF].map(extend4);
+        return decode_subblocks(c1, c2, b3, indices, opaque);
+    }
+
+    let base = [b0 >> 3, b1 >> 3, b2 >> 3];
+    let delta = [b0 & 7, b1 & 7, b2 & 7].map(signed3);
+    let [r2, g2, b2_] = [0, 1, 2].map(|i| i32::from(base[i]).saturating_add(delta[i]));
+
+    if !(0..32).contains(&r2) {
+        decode_t_mode(block, indices, opaque)
+    } else if !(0..32).contains(&g2) {
+        decode_h_mode(block, indices, opaque)
+    } else if !(0..32).contains(&b2_) {
+        decode_planar(block)
+    } else {
+        let c1 = base.map(extend5);
+        let c2 = [r2, g2, b2_].map(|x| extend5(u8::try_from(x).unwrap_or_default()));
+        decode_subblocks(c1, c2, b3, indices, opaque)
+    }
+}
+
+/// ETC2 RGBA block with 16 bytes
+pub fn decode_rgba(block: [u8; 16]) -> [[u8; 4]; 16] {
+    let [a0, a1, a2, a3, a4, a5, a6, a7, c0, c1, c2, c3, c4, c5, c6, c7] = block;
+
+    let mut texels = decode_rgb([c0, c1, c2, c3, c4, c5, c6, c7], false);
+    let alpha = decode_alpha([a0, a1, a2, a3, a4, a5, a6, a7]);
+    for (texel, alpha) in texels.iter_mut().zip(alpha) {
+        texel[3] = alpha;
+    }
+
+    texels
+}
+
+/// Pixel indices that are stored in column-major order
+#[derive(Debug, Clone, Copy)]
+struct Indices {
+    msb: u16,
+    lsb: u16,
+}
+
+impl Indices {
+    /// Index of the texel in row-major order
+    fn get(self, texel: usize) -> usize {
+        // Convert to column-major order
+        let i = (texel & 3) << 2 | texel >> 2;
+        usize::from((self.msb >> i) & 1) << 1 | usize::from((self.lsb >> i) & 1)
+    }
+}
+
+/// Individual and differential mode with two subblocks
+fn decode_subblocks(
+    c1: [u8; 3],
+    c2: [u8; 3],
+    b3: u8,
+    indices: Indices,
+    opaque: bool,
+) -> [[u8; 4]; 16] {
+    let flip = b3 & 1 != 0;
+    let table1 = MODIFIERS[usize::from(b3 >> 5)];
+    let table2 = MODIFIERS[usize::from((b3 >> 2) & 7)];
+
+    let mut texels = [[0; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let (x, y) = (i & 3, i >> 2);
+        let second = if flip { y >= 2 } else { x >= 2 };
+        let (color, table) = if second { (c2, table2) } else { (c1, table1) };
+
+        let index = indices.get(i);
+        *texel = match index {
+            2 if !opaque => [0; 4],
+            0 if !opaque => with_alpha(color),
+            index => with_alpha(color.map(|x| add(x, table[index]))),
+        };
+    }
+
+    texels
+}
+
+fn decode_t_mode(block: [u8; 8], indices: Indices, opaque: bool) -> [[u8; 4]; 16] {
+    let [b0, b1, b2, b3, ..] = block;
+
+    let c1 = [(b0 & 0x18) >> 1 | (b0 & 3), b1 >> 4, b1 & 0xF].map(extend4);
+    let c2 = [b2 >> 4, b2 & 0xF, b3 >> 4].map(extend4);
+    let distance = DISTANCES[usize::from((b3 & 0xC) >> 1 | (b3 & 1))];
+
+    let paint = [
+        c1,
+        c2.map(|x| add(x, distance)),
+        c2,
+        c2.map(|x| add(x, distance.saturating_neg())),
+    ];
+
+    decode_paint(paint, indices, opaque)
+}
+
+fn decode_h_mode(block: [u8; 8], indices: Indices, opaque: bool) -> [[u8; 4]; 16] {
+    let [b0, b1, b2, b3, ..] = block;
+
+    let c1 = [
+        (b0 & 0x78) >> 3,
+        (b0 & 7) << 1 | (b1 & 0x10) >> 4,
+        (b1 & 8) | (b1 & 3) << 1 | b2 >> 7,
+    ];
+    let c2 = [(b2 & 0x78) >> 3, (b2 & 7) << 1 | b3 >> 7, (b3 & 0x78) >> 3];
+
+    // The order of the colors stores an additional bit of the distance
+    let value = |[r, g, b]: [u8; 3]| u16::from(r) << 8 | u16::from(g) << 4 | u16::from(b);
+    let order_bit = usize::from(value(c1) >= value(c2));
+    let distance = DISTANCES[usize::from(b3 & 4) | usize::from(b3 & 1) << 1 | order_bit];
+
+    let [c1, c2] = [c1, c2].map(|c| c.map(extend4));
+    let paint = [
+        c1.map(|x| add(x, distance)),
+        c1.map(|x| add(x, distance.saturating_neg())),
+        c2.map(|x| add(x, distance)),
+        c2.map(|x| add(x, distance.saturating_neg())),
+    ];
+
+    decode_paint(paint, indices, opaque)
+}
+
+/// T and H modes that select one of four colors for each texel
+fn decode_paint(paint: [[u8; 3]; 4], indices: Indices, opaque: bool) -> [[u8; 4]; 16] {
+    let mut texels = [[0; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = match indices.get(i) {
+            2 if !opaque => [0; 4],
+            index => with_alpha(paint[index]),
+        };
+    }
+
+    texels
+}
+
+/// Gradient defined by the colors at the origin, horizontal, and vertical
+fn decode_planar(block: [u8; 8]) -> [[u8; 4]; 16] {
+    let [b0, b1, b2, b3, b4, b5, b6, b7] = block;
+
+    let origin = [
+        extend6((b0 & 0x7E) >> 1),
+        extend7((b0 & 1) << 6 | (b1 & 0x7E) >> 1),
+        extend6((b1 & 1) << 5 | (b2 & 0x18) | (b2 & 3) << 1 | b3 >> 7),
+    ];
+    let horizontal = [
+        extend6((b3 & 0x7C) >> 1 | (b3 & 1)),
+        extend7(b4 >> 1),
+        extend6((b4 & 1) << 5 | b5 >> 3),
+    ];
+    let vertical = [
+        extend6((b5 & 7) << 3 | b6 >> 5),
+        extend7((b6 & 0x1F) << 2 | b7 >> 6),
+        extend6(b7 & 0x3F),
+    ];
+
+    let mut texels = [[0; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let x = i32::try_from(i & 3).unwrap_or_default();
+        let y = i32::try_from(i >> 2).unwrap_or_default();
+
+        let mut color = [0; 3];
+        for (c, value) in color.iter_mut().enumerate() {
+            let o = i32::from(origin[c]);
+            let h = i32::from(horizontal[c]);
+            let v = i32::from(vertical[c]);
+
+            let sum = x
+                .saturating_mul(h.saturating_sub(o))
+                .saturating_add(y.saturating_mul(v.saturating_sub(o)))
+                .saturating_add(o.saturating_mul(4))
+                .saturating_add(2);
+            *value = clamp(sum >> 2);
+        }
+        *texel = with_alpha(color);
+    }
+
+    texels
+}
+
+/// EAC encoded alpha channel
+fn decode_alpha(block: [u8; 8]) -> [u8; 16] {
+    let [base, table, i0, i1, i2, i3, i4, i5] = block;
+    let multiplier = i32::from(table >> 4);
+    let table = ALPHA_MODIFIERS[usize::from(table & 0xF)];
+    let indices = u64::from_be_bytes([0, 0, i0, i1, i2, i3, i4, i5]);
+
+    let mut values = [0; 16];
+    // Indices are stored in column-major order, starting with the most
+    // significant bits
+    for (i, shift) in (0..16).zip((0..=45).rev().step_by(3)) {
+        let index = usize::try_from((indices >> shift) & 7).unwrap_or_default();
+        let value = i32::from(base).saturating_add(table[index].saturating_mul(multiplier));
+        values[(i & 3) << 2 | i >> 2] = clamp(value);
+    }
+
+    values
+}
+
+fn signed3(value: u8) -> i32 {
+    let value = i32::from(value);
+    if value & 4 != 0 {
+        value.saturating_sub(8)
+    } else {
+        value
+    }
+}
+
+fn add(value: u8, modifier: i32) -> u8 {
+    clamp(i32::from(value).saturating_add(modifier))
+}
+
+fn clamp(value: i32) -> u8 {
+    u8::try_from(value.clamp(0, 255)).unwrap_or_default()
+}
+
+fn extend4(value: u8) -> u8 {
+    value << 4 | value
+}
+
+fn extend5(value: u8) -> u8 {
+    value << 3 | value >> 2
+}
+
+fn extend6(value: u8) -> u8 {
+    value << 2 | value >> 4
+}
+
+fn extend7(value: u8) -> u8 {
+    value << 1 | value >> 6
+}
+
+fn with_alpha([r, g, b]: [u8; 3]) -> [u8; 4] {
+    [r, g, b, 255]
+}
+
+#[test]
+fn individual_mode_test() {
+    // Both subblocks use the same color and the smallest modifiers. The
+    // first texel uses the negative modifier.
+    let block = [0x88, 0x88, 0x88, 0x00, 0x00, 0x01, 0x00, 0x00];
+    let texels = decode_rgb(block, false);
+
+    assert_eq!(texels[0], [134, 134, 134, 255]);
+    assert!(texels[1..].iter().all(|x| *x == [138, 138, 138, 255]));
+}
+
+#[test]
+fn alpha_test() {
+    // Base of 128 with a multiplier of 2 and all indices set to the largest
+    // modifier
+    let block = [128, 0x20, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+
+    assert_eq!(decode_alpha(block), [156; 16]);
+}
@@ -0,0 +1,770 @@
+//! Parser for KTX2 texture containers
+//!
+//! Only the first layer, face, and depth slice of each mip level are decoded.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+
+use glycin_utils::safe_math::*;
+use glycin_utils::*;
+
+use crate::astc::{self, Profile};
+use crate::packed::Packed;
+use crate::{bc, etc2};
+
+const IDENTIFIER: &[u8] = b"\xABKTX 20\xBB\r\n\x1A\n";
+
+/// Size of the header including the index, without the level index
+const HEADER_SIZE: usize = 80;
+/// Size of an entry in the level index
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+const SUPERCOMPRESSION_NONE: u32 = 0;
+const SUPERCOMPRESSION_ZLIB: u32 = 3;
+
+/// Key of the value that describes the direction of the texture coordinates
+const KEY_ORIENTATION: &str = "KTXorientation";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Uncompressed(MemoryFormat),
+    Packed(Packed),
+    Bc1 { alpha: bool },
+    Bc2,
+    Bc3,
+    Bc4,
+    Bc5,
+    Etc2Rgb,
+    Etc2RgbA1,
+    Etc2Rgba,
+    Astc { block: (u32, u32), profile: Profile },
+}
+
+impl Format {
+    fn from_vk_format(vk_format: u32) -> Result<Self, ProcessError> {
+        // Block sizes in the order of the Vulkan formats
+        const ASTC_BLOCKS: [(u32, u32); 14] = [
+            (4, 4),
+            (5, 4),
+            (5, 5),
+            (6, 5),
+            (6, 6),
+            (8, 5),
+            (8, 6),
+            (8, 8),
+            (10, 5),
+            (10, 6),
+            (10, 8),
+            (10, 10),
+            (12, 10),
+            (12, 12),
+        ];
+
+        let astc_block = |index: u32| -> Result<(u32, u32), ProcessError> {
+            ASTC_BLOCKS
+                .get(index.try_usize()?)
+                .copied()
+                .internal_error()
+        };
+
+        Ok(match vk_format {
+            2 => Self::Packed(Packed::R4g4b4a4),
+            3 => Self::Packed(Packed::B4g4r4a4),
+            4 => Self::Packed(Packed::R5g6b5),
+            5 => Self::Packed(Packed::B5g6r5),
+            6 => Self::Packed(Packed::R5g5b5a1),
+            7 => Self::Packed(Packed::B5g5r5a1),
+            8 => Self::Packed(Packed::A1r5g5b5),
+            9 => Self::Uncompressed(MemoryFormat::G8),
+            23 | 29 => Self::Uncompressed(MemoryFormat::R8g8b8),
+            30 | 36 => Self::Uncompressed(MemoryFormat::B8g8r8),
+            37 | 43 => Self::Uncompressed(MemoryFormat::R8g8b8a8),
+            44 | 50 => Self::Uncompressed(MemoryFormat::B8g8r8a8),
+            58 => Self::Packed(Packed::A2r10g10b10),
+            64 => Self::Packed(Packed::A2b10g10r10),
+            90 => Self::Uncompressed(MemoryFormat::R16g16b16Float),
+            97 => Self::Uncompressed(MemoryFormat::R16g16b16a16Float),
+            106 => Self::Uncompressed(MemoryFormat::R32g32b32Float),
+            109 => Self::Uncompressed(MemoryFormat::R32g32b32a32Float),
+            131 | 132 => Self::Bc1 { alpha: false },
+            133 | 134 => Self::Bc1 { alpha: true },
+            135 | 136 => Self::Bc2,
+            137 | 138 => Self::Bc3,
+            139 => Self::Bc4,
+            141 => Self::Bc5,
+            147 | 148 => Self::Etc2Rgb,
+            149 | 150 => Self::Etc2RgbA1,
+            151 | 152 => Self::Etc2Rgba,
+            // UNORM and SRGB variants alternate
+            157..=184 => {
+                let index = vk_format.saturating_sub(157);
+                let profile = if index % 2 == 0 {
+                    Profile::Ldr
+                } else {
+                    Profile::LdrSrgb
+                };
+                Self::Astc {
+                    block: astc_block(index / 2)?,
+                    profile,
+                }
+            }
+            1000066000..=1000066013 => Self::Astc {
+                block: astc_block(vk_format.saturating_sub(1000066000))?,
+                profile: Profile::Hdr,
+            },
+            0 => {
+                return Err(ProcessError::UnsupportedImageFormat(String::from(
+                    "KTX2 without Vulkan format, like Basis Universal",
+                )))
+            }
+            _ => {
+                return Err(ProcessError::UnsupportedImageFormat(format!(
+                    "KTX2 Vulkan format {vk_format}"
+                )))
+            }
+        })
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::Uncompressed(_) => String::from("Uncompressed"),
+            Self::Packed(packed) => packed.name().to_string(),
+            Self::Bc1 { .. } => String::from("BC1"),
+            Self::Bc2 => String::from("BC2"),
+            Self::Bc3 => String::from("BC3"),
+            Self::Bc4 => String::from("BC4"),
+            Self::Bc5 => String::from("BC5"),
+            Self::Etc2Rgb => String::from("ETC2"),
+            Self::Etc2RgbA1 => String::from("ETC2 RGB A1"),
+            Self::Etc2Rgba => String::from("ETC2 RGBA"),
+            Self::Astc {
+                block: (width, height),
+                profile: Profile::Hdr,
+            } => format!("ASTC {width}×{height} HDR"),
+            Self::Astc {
+                block: (width, height),
+                ..
+            } => format!("ASTC {width}×{height}"),
+        }
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        match self {
+            Self::Uncompressed(memory_format) => memory_format.has_alpha(),
+            Self::Packed(packed) => packed.has_alpha(),
+            Self::Bc1 { alpha } => *alpha,
+            Self::Bc2 | Self::Bc3 | Self::Etc2RgbA1 | Self::Etc2Rgba | Self::Astc { .. } => true,
+            Self::Bc4 | Self::Bc5 | Self::Etc2Rgb => false,
+        }
+    }
+
+    fn is_hdr(&self) -> bool {
+        match self {
+            Self::Uncompressed(memory_format) => float_size(*memory_format).is_some(),
+            Self::Astc { profile, .. } => *profile == Profile::Hdr,
+            _ => false,
+        }
+    }
+
+    /// Width and height of a block and its size in bytes
+    fn block(&self) -> (u32, u32, usize) {
+        match self {
+            Self::Uncompressed(memory_format) => (1, 1, memory_format.n_bytes().usize()),
+            Self::Packed(packed) => (1, 1, packed.size()),
+            Self::Bc1 { .. } | Self::Bc4 | Self::Etc2Rgb | Self::Etc2RgbA1 => (4, 4, 8),
+            Self::Bc2 | Self::Bc3 | Self::Bc5 | Self::Etc2Rgba => (4, 4, 16),
+            Self::Astc {
+                block: (width, height),
+                ..
+            } => (*width, *height, 16),
+        }
+    }
+
+    fn memory_format(&self) -> MemoryFormat {
+        match self {
+            Self::Uncompressed(memory_format) => *memory_format,
+            Self::Packed(packed) if packed.is_16_bit() => MemoryFormat::R16g16b16a16,
+            Self::Packed(packed) if !packed.has_alpha() => MemoryFormat::R8g8b8,
+            Self::Astc {
+                profile: Profile::Hdr,
+                ..
+            } => MemoryFormat::R16g16b16a16Float,
+            _ => MemoryFormat::R8g8b8a8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Level {
+    offset: usize,
+    length: usize,
+    uncompressed_length: usize,
+}
+
+/// KTX2 container
+///
+/// Only the header, level index, and key/value data are read when creating
+/// it. The data of a mip level is read when it's decoded.
+pub struct Ktx2<R: Read> {
+    source: IncrementalReader<R>,
+    pub format: Format,
+    pub width: u32,
+    pub height: u32,
+    levels: Vec<Level>,
+    supercompression: u32,
+    pub key_value: BTreeMap<String, String>,
+    /// The rows are stored from the bottom to the top
+    pub bottom_up: bool,
+}
+
+impl<R: Read> Ktx2<R> {
+    pub fn new(source: R) -> Result<Self, ProcessError> {
+        let mut source = IncrementalReader::new(source);
+
+        let mut reader = Reader::new(source.data_up_to(HEADER_SIZE).internal_error()?);
+
+        if reader.bytes(IDENTIFIER.len())? != IDENTIFIER {
+            return Err(ProcessError::expected(&"Not a KTX2 file"));
+        }
+
+        let format = Format::from_vk_format(reader.u32()?)?;
+        // Type size
+        reader.skip(4)?;
+        let width = reader.u32()?;
+        // One-dimensional textures have a height of zero
+        let height = reader.u32()?.max(1);
+        // Depth, layer count, and face count
+        reader.skip(12)?;
+        let level_count = reader.u32()?.max(1);
+        let supercompression = reader.u32()?;
+
+        match supercompression {
+            SUPERCOMPRESSION_NONE | SUPERCOMPRESSION_ZLIB => {}
+            1 => return Err(unsupported("KTX2 with BasisLZ supercompression")),
+            2 => return Err(unsupported("KTX2 with Zstandard supercompression")),
+            _ => {
+                return Err(unsupported(&format!(
+                    "KTX2 supercompression {supercompression}"
+                )))
+            }
+        }
+
+        // Data format descriptor
+        reader.skip(8)?;
+        let kvd_offset = reader.u32()?.try_usize()?;
+        let kvd_length = reader.u32()?.try_usize()?;
+        // Supercompression global data
+        reader.skip(16)?;
+
+        let level_index_end = level_count
+            .try_usize()?
+            .smul(LEVEL_INDEX_ENTRY_SIZE)?
+            .sadd(HEADER_SIZE)?;
+        let mut reader = Reader::new(source.data_up_to(level_index_end).internal_error()?);
+        reader.skip(HEADER_SIZE)?;
+
+        let mut levels = Vec::new();
+        for _ in 0..level_count {
+            levels.push(Level {
+                offset: reader.u64()?.try_usize()?,
+                length: reader.u64()?.try_usize()?,
+                uncompressed_length: reader.u64()?.try_usize()?,
+            });
+        }
+
+        let kvd_end = kvd_offset.sadd(kvd_length)?;
+        let key_value = source
+            .data_up_to(kvd_end)
+            .internal_error()?
+            .get(kvd_offset..kvd_end)
+            .map(parse_key_value)
+            .unwrap_or_default();
+
+        let bottom_up = key_value
+            .get(KEY_ORIENTATION)
+            .is_some_and(|x| x.chars().nth(1) == Some('u'));
+
+        Ok(Self {
+            format,
+            width,
+            height,
+            levels,
+            supercompression,
+            key_value,
+            bottom_up,
+            source,
+        })
+    }
+
+    pub fn n_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Dimensions of a mip level
+    pub fn level_size(&self, level: usize) -> (u32, u32) {
+        let shift = u32::try_from(level).unwrap_or(u32::MAX);
+        let size = |x: u32| x.checked_shr(shift).unwrap_or_default().max(1);
+
+        (size(self.width), size(self.height))
+    }
+
+    pub fn decode(&mut self, level: usize) -> Result<Frame, ProcessError> {
+        let level_info = self
+            .levels
+            .get(level)
+            .ok_or_else(|| ProcessError::expected(&format!("No mip level {level}")))?;
+        let (width, height) = self.level_size(level);
+
+        let (block_width, block_height, block_size) = self.format.block();
+        let blocks_x = width.div_ceil(block_width).try_usize()?;
+        let blocks_y = height.div_ceil(block_height).try_usize()?;
+        // Only the first image of the level is decoded
+        let image_length = blocks_x.smul(blocks_y)?.smul(block_size)?;
+
+        let level_end = level_info.offset.sadd(level_info.length)?;
+        let level_data = self
+            .source
+            .data_up_to(level_end)
+            .internal_error()?
+            .get(level_info.offset..level_end)
+            .ok_or_else(|| ProcessError::expected(&"Unexpected end of KTX2 data"))?;
+
+        let decompressed;
+        let image_data = if self.supercompression == SUPERCOMPRESSION_ZLIB {
+            let mut data = Vec::new();
+            flate2::read::ZlibDecoder::new(level_data)
+                .take(level_info.uncompressed_length.try_u64()?)
+                .read_to_end(&mut data)
+                .expected_error()?;
+            decompressed = data;
+            decompressed.as_slice()
+        } else {
+            level_data
+        };
+
+        let image_data = image_data
+            .get(..image_length)
+            .ok_or_else(|| ProcessError::expected(&"KTX2 mip level is too small"))?;
+
+        let memory_format = self.format.memory_format();
+        let pixel_size = memory_format.n_bytes().usize();
+        let stride = width.try_usize()?.smul(pixel_size)?;
+        let mut memory =
+            SharedMemory::new(stride.smul(height.try_usize()?)?.try_u64()?).expected_error()?;
+
+        match self.format {
+            Format::Uncompressed(_) => {
+                memory.copy_from_slice(image_data);
+                bytes_to_native_endian(memory_format, &mut memory);
+            }
+            Format::Packed(packed) => {
+                for (texel, pixel) in image_data
+                    .chunks_exact(packed.size())
+                    .zip(memory.chunks_exact_mut(pixel_size))
+                {
+                    packed.decode(texel, pixel);
+                }
+            }
+            format => {
+                let block_row_size = block_size.smul(blocks_x)?;
+                for (block_y, row) in image_data.chunks_exact(block_row_size).enumerate() {
+                    for (block_x, block) in row.chunks_exact(block_size).enumerate() {
+                        let texels = decode_block(format, block)?;
+
+                        let x0 = block_x.smul(block_width.try_usize()?)?;
+                        let y0 = block_y.smul(block_height.try_usize()?)?;
+                        for (i, texel) in texels.chunks_exact(pixel_size).enumerate() {
+                            let x = x0.sadd(i.srem(block_width.try_usize()?)?)?;
+                            let y =
+                                y0.sadd(i.checked_div(block_width.try_usize()?).internal_error()?)?;
+
+                            // Blocks at the edges can extend beyond the image
+                            if x >= width.try_usize()? || y >= height.try_usize()? {
+                                continue;
+                            }
+
+                            let pos = y.smul(stride)?.sadd(x.smul(pixel_size)?)?;
+                            memory
+                                .get_mut(pos..pos.sadd(pixel_size)?)
+                                .internal_error()?
+                                .copy_from_slice(texel);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut frame = Frame::new(width, height, memory_format, memory.into_binary_data())?;
+
+        frame.details.info_alpha_channel = Some(self.format.has_alpha());
+        if self.format.is_hdr() {
+            // Floating point data is in linear BT.709
+            frame.details.color_cicp = Some([1, 8, 0, 1]);
+            frame.details.info_bit_depth = Some(16);
+        } else if let Format::Packed(packed) = self.format {
+            frame.details.info_bit_depth = packed.bit_depths().into_iter().max();
+            frame.details.channel_bit_depths = packed.channel_bit_depths();
+        } else {
+            frame.details.info_bit_depth = Some(8);
+        }
+
+        Ok(frame)
+    }
+}
+
+/// Texels of a compressed block in the memory format of the frame
+fn decode_block(format: Format, block: &[u8]) -> Result<Vec<u8>, ProcessError> {
+    let texels = match format {
+        Format::Bc1 { alpha } => bc::decode_bc1(block.try_into().internal_error()?, alpha),
+        Format::Bc2 => bc::decode_bc2(block.try_into().internal_error()?),
+        Format::Bc3 => bc::decode_bc3(block.try_into().internal_error()?),
+        Format::Bc4 => bc::decode_bc4(block.try_into().internal_error()?),
+        Format::Bc5 => bc::decode_bc5(block.try_into().internal_error()?),
+        Format::Etc2Rgb => etc2::decode_rgb(block.try_into().internal_error()?, false),
+        Format::Etc2RgbA1 => etc2::decode_rgb(block.try_into().internal_error()?, true),
+        Format::Etc2Rgba => etc2::decode_rgba(block.try_into().internal_error()?),
+        Format::Astc {
+            block: (width, height),
+            profile,
+        } => {
+            let mut texels = vec![[0; 4]; width.smul(height)?.try_usize()?];
+            astc::decode_block(
+                block.try_into().internal_error()?,
+                width,
+                height,
+                profile,
+                &mut texels,
+            );
+
+            return Ok(match profile {
+                Profile::Hdr => texels
+                    .iter()
+                    .flatten()
+                    .flat_map(|x| x.to_ne_bytes())
+                    .collect(),
+                Profile::Ldr | Profile::LdrSrgb => texels
+                    .iter()
+                    .flatten()
+                    .map(|x| x.to_be_bytes()[0])
+                    .collect(),
+            });
+        }
+        Format::Uncompressed(_) | Format::Packed(_) => {
+            return Err(ProcessError::expected(&"Not a compressed format"))
+        }
+    };
+
+    Ok(texels.into_iter().flatten().collect())
+}
+
+/// Size of the channels for floating point formats
+fn float_size(memory_format: MemoryFormat) -> Option<usize> {
+    match memory_format {
+        MemoryFormat::R16g16b16Float | MemoryFormat::R16g16b16a16Float => Some(2),
+        MemoryFormat::R32g32b32Float | MemoryFormat::R32g32b32a32Float => Some(4),
+        _ => None,
+    }
+}
+
+/// Convert floating point data from little endian
+fn bytes_to_native_endian(memory_format: MemoryFormat, buf: &mut [u8]) {
+    if cfg!(target_endian = "big") {
+        if let Some(channel_size) = float_size(memory_format) {
+            for channel in buf.chunks_exact_mut(channel_size) {
+                channel.reverse();
+            }
+        }
+    }
+}
+
+/// Entries with UTF-8 values
+fn parse_key_value(data: &[u8]) -> BTreeMap<String, String> {
+    let mut key_value = BTreeMap::new();
+    let mut reader = Reader::new(data);
+
+    while let Ok(length) = reader.u32() {
+        let Ok(entry) = length
+            .try_usize()
+            .map_err(Into::into)
+            .and_then(|x| reader.bytes(x))
+        else {
+            break;
+        };
+
+        // Key and value are separated by a NUL byte
+        let mut parts = entry.splitn(2, |x| *x == 0);
+        if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+            let value = value.strip_suffix(b"\0").unwrap_or(value);
+            if let (Ok(key), Ok(value)) = (std::str::from_utf8(key), std::str::from_utf8(value)) {
+                key_value.insert(key.to_string(), value.to_string());
+            }
+        }
+
+        // Entries are padded to multiples of four bytes
+        let padding = length.next_multiple_of(4).saturating_sub(length);
+        if padding.try_usize().map(|x| reader.skip(x)).is_err() {
+            break;
+        }
+    }
+
+    key_value
+}
+
+fn unsupported(format: &str) -> ProcessError {
+    ProcessError::UnsupportedImageFormat(format.to_string())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ProcessError> {
+        let end = self.pos.sadd(len)?;
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .ok_or_else(|| ProcessError::expected(&"Unexpected end of KTX2 data"))?;
+        self.pos = end;
+
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), ProcessError> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ProcessError> {
+        self.bytes(N)?.try_into().internal_error()
+    }
+
+    fn u32(&mut self) -> Result<u32, ProcessError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, ProcessError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    /// KTX2 file with the levels stored in the given order
+    fn ktx2(
+        vk_format: u32,
+        (width, height): (u32, u32),
+        supercompression: u32,
+        key_value: &[u8],
+        levels: &[&[u8]],
+    ) -> Vec<u8> {
+        let level_index_len = levels.len().smul(LEVEL_INDEX_ENTRY_SIZE).unwrap();
+        let kvd_offset = HEADER_SIZE.sadd(level_index_len).unwrap();
+
+        let mut data = [
+            IDENTIFIER,
+            &vk_format.to_le_bytes(),
+            // Type size
+            &1_u32.to_le_bytes(),
+            &width.to_le_bytes(),
+            &height.to_le_bytes(),
+            // Depth, layer count, and face count
+            &0_u32.to_le_bytes(),
+            &1_u32.to_le_bytes(),
+            &1_u32.to_le_bytes(),
+            &levels.len().try_u32().unwrap().to_le_bytes(),
+            &supercompression.to_le_bytes(),
+            // Data format descriptor
+            &[0; 8],
+            &kvd_offset.try_u32().unwrap().to_le_bytes(),
+            &key_value.len().try_u32().unwrap().to_le_bytes(),
+            // Supercompression global data
+            &[0; 16],
+        ]
+        .concat();
+
+        let mut offset = kvd_offset.sadd(key_value.len()).unwrap();
+        for level in levels {
+            let length = level.len().try_u64().unwrap();
+            data.extend_from_slice(&offset.try_u64().unwrap().to_le_bytes());
+            data.extend_from_slice(&length.to_le_bytes());
+            data.extend_from_slice(&length.to_le_bytes());
+            offset = offset.sadd(level.len()).unwrap();
+        }
+
+        data.extend_from_slice(key_value);
+        for level in levels {
+            data.extend_from_slice(level);
+        }
+
+        data
+    }
+
+    fn key_value_entry(key: &str, value: &[u8]) -> Vec<u8> {
+        let entry = [key.as_bytes(), b"\0", value].concat();
+        let length = entry.len().try_u32().unwrap();
+        let padding = length.next_multiple_of(4).saturating_sub(length);
+
+        [
+            length.to_le_bytes().as_slice(),
+            &entry,
+            &vec![0; padding.try_usize().unwrap()],
+        ]
+        .concat()
+    }
+
+    fn decode(data: &[u8], level: usize) -> Result<(MemoryFormat, Vec<u8>), ProcessError> {
+        let frame = Ktx2::new(data)?.decode(level)?;
+        Ok((frame.memory_format, frame.texture.get_full().unwrap()))
+    }
+
+    #[test]
+    fn uncompressed() {
+        let pixels = [1, 2, 3, 4, 5, 6, 7, 8];
+        let data = ktx2(37, (2, 1), SUPERCOMPRESSION_NONE, &[], &[&pixels]);
+
+        let ktx2 = Ktx2::new(data.as_slice()).unwrap();
+        assert_eq!(ktx2.format, Format::Uncompressed(MemoryFormat::R8g8b8a8));
+        assert_eq!((ktx2.width, ktx2.height), (2, 1));
+        assert_eq!(ktx2.n_levels(), 1);
+        assert!(!ktx2.bottom_up);
+
+        let (memory_format, texture) = decode(&data, 0).unwrap();
+        assert_eq!(memory_format, MemoryFormat::R8g8b8a8);
+        assert_eq!(texture, pixels);
+    }
+
+    #[test]
+    fn mip_levels() {
+        let level0 = [0; 24];
+        let level1 = [1, 2, 3, 4, 5, 6];
+        let data = ktx2(23, (4, 2), SUPERCOMPRESSION_NONE, &[], &[&level0, &level1]);
+
+        let ktx2 = Ktx2::new(data.as_slice()).unwrap();
+        assert_eq!(ktx2.n_levels(), 2);
+        assert_eq!(ktx2.level_size(0), (4, 2));
+        assert_eq!(ktx2.level_size(1), (2, 1));
+        assert_eq!(ktx2.level_size(5), (1, 1));
+
+        assert_eq!(
+            decode(&data, 1).unwrap(),
+            (MemoryFormat::R8g8b8, level1.to_vec())
+        );
+        assert!(decode(&data, 2).is_err());
+    }
+
+    #[test]
+    fn one_dimensional() {
+        let data = ktx2(9, (3, 0), SUPERCOMPRESSION_NONE, &[], &[&[1, 2, 3]]);
+
+        let ktx2 = Ktx2::new(data.as_slice()).unwrap();
+        assert_eq!((ktx2.width, ktx2.height), (3, 1));
+        assert_eq!(decode(&data, 0).unwrap(), (MemoryFormat::G8, vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn key_value() {
+        let key_value = [
+            key_value_entry("KTXorientation", b"ru\0"),
+            key_value_entry("KTXwriter", b"test"),
+            key_value_entry("invalid", b"\xFF"),
+        ]
+        .concat();
+        let data = ktx2(9, (1, 1), SUPERCOMPRESSION_NONE, &key_value, &[&[0]]);
+
+        let ktx2 = Ktx2::new(data.as_slice()).unwrap();
+        assert!(ktx2.bottom_up);
+        assert_eq!(
+            ktx2.key_value,
+            BTreeMap::from([
+                (String::from("KTXorientation"), String::from("ru")),
+                (String::from("KTXwriter"), String::from("test")),
+            ])
+        );
+
+        // Entries that are longer than the data are ignored
+        let mut key_value = key_value_entry("KTXwriter", b"test");
+        key_value.splice(..4, 100_u32.to_le_bytes());
+        assert!(parse_key_value(&key_value).is_empty());
+    }
+
+    #[test]
+    fn zlib() {
+        let pixels = [1, 2, 3, 4, 5, 6];
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(&pixels).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut data = ktx2(23, (2, 1), SUPERCOMPRESSION_ZLIB, &[], &[&compressed]);
+        // Uncompressed length in the level index
+        data.splice(96..104, 6_u64.to_le_bytes());
+
+        assert_eq!(
+            decode(&data, 0).unwrap(),
+            (MemoryFormat::R8g8b8, pixels.to_vec())
+        );
+    }
+
+    #[test]
+    fn bc1_partial_block() {
+        // White block, which extends beyond the image
+        let block = [0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0];
+        let data = ktx2(131, (2, 3), SUPERCOMPRESSION_NONE, &[], &[&block]);
+
+        let ktx2 = Ktx2::new(data.as_slice()).unwrap();
+        assert_eq!(ktx2.format, Format::Bc1 { alpha: false });
+
+        assert_eq!(
+            decode(&data, 0).unwrap(),
+            (MemoryFormat::R8g8b8a8, vec![255; 24])
+        );
+    }
+
+    #[test]
+    fn unsupported() {
+        for (vk_format, supercompression) in [(0, 0), (1000, 0), (37, 1), (37, 2), (37, 7)] {
+            let data = ktx2(vk_format, (1, 1), supercompression, &[], &[&[0; 4]]);
+            assert!(
+                matches!(
+                    Ktx2::new(data.as_slice()),
+                    Err(ProcessError::UnsupportedImageFormat(_))
+                ),
+                "{vk_format} {supercompression}"
+            );
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        let data = ktx2(37, (2, 1), SUPERCOMPRESSION_NONE, &[], &[&[0; 8]]);
+        assert!(decode(&data, 0).is_ok());
+
+        let mut identifier = data.clone();
+        identifier[1] = b'X';
+        assert!(Ktx2::new(identifier.as_slice()).is_err());
+
+        // Truncated header and level index
+        assert!(Ktx2::new(&data[..40]).is_err());
+        assert!(Ktx2::new(&data[..90]).is_err());
+
+        // Truncated level data
+        assert!(decode(&data[..data.len().saturating_sub(1)], 0).is_err());
+
+        // Level data that is too small for the dimensions
+        let data = ktx2(37, (2, 2), SUPERCOMPRESSION_NONE, &[], &[&[0; 8]]);
+        assert!(decode(&data, 0).is_err());
+
+        // Number of levels that doesn't fit the data
+        let mut data = ktx2(37, (1, 1), SUPERCOMPRESSION_NONE, &[], &[&[0; 4]]);
+        data.splice(40..44, u32::MAX.to_le_bytes());
+        assert!(Ktx2::new(data.as_slice()).is_err());
+    }
+}
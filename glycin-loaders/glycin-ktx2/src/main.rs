@@ -0,0 +1,59 @@
+mod astc;
+mod bc;
+mod etc2;
+mod ktx2;
+mod packed;
+
+use glycin_utils::safe_math::*;
+use glycin_utils::*;
+use gufo_common::orientation::Orientation;
+use ktx2::Ktx2;
+
+init_main_loader!(ImgDecoder);
+
+pub struct ImgDecoder {
+    ktx2: Ktx2<UnixStream>,
+}
+
+impl LoaderImplementation for ImgDecoder {
+    fn init(
+        stream: UnixStream,
+        _mime_type: String,
+        _details: InitializationDetails,
+    ) -> Result<(Self, ImageDetails), ProcessError> {
+        // Only the header is read here, the texture data when decoding
+        let ktx2 = Ktx2::new(stream)?;
+
+        let mut image_info = ImageDetails::new(ktx2.width, ktx2.height);
+        image_info.info_format_name = Some(format!("KTX2 ({})", ktx2.format.name()));
+        image_info.info_alpha_channel = Some(ktx2.format.has_alpha());
+
+        if !ktx2.key_value.is_empty() {
+            image_info.metadata_key_value = Some(ktx2.key_value.clone());
+        }
+
+        if ktx2.bottom_up {
+            image_info.transformation_orientation = Some(Orientation::MirroredRotation180);
+        }
+
+        // Mip levels are selected via the sub-image
+        if ktx2.n_levels() > 1 {
+            image_info.sub_images = Some(
+                (0..ktx2.n_levels())
+                    .map(|level| {
+                        let (width, height) = ktx2.level_size(level);
+                        SubImageInfo::new(width, height, SubImageKind::Mipmap)
+                    })
+                    .collect(),
+            );
+        }
+
+        Ok((Self { ktx2 }, image_info))
+    }
+
+    fn frame(&mut self, frame_request: FrameRequest) -> Result<Frame, ProcessError> {
+        let level = frame_request.sub_image.unwrap_or_default().try_usize()?;
+
+        self.ktx2.decode(level)
+    }
+}
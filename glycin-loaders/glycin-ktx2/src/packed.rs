@@ -0,0 +1,185 @@
+//! Decoder for packed formats
+//!
+//! All channels of a texel are stored in one little endian 16 or 32 bit
+//! word. Texels are returned as RGB or RGBA with 8 or, for channels with more
+//! than 8 bits, 16 bits per channel.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Packed {
+    R4g4b4a4,
+    B4g4r4a4,
+    R5g6b5,
+    B5g6r5,
+    R5g5b5a1,
+    B5g5r5a1,
+    A1r5g5b5,
+    A2r10g10b10,
+    A2b10g10r10,
+}
+
+impl Packed {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::R4g4b4a4 => "R4G4B4A4",
+            Self::B4g4r4a4 => "B4G4R4A4",
+            Self::R5g6b5 => "R5G6B5",
+            Self::B5g6r5 => "B5G6R5",
+            Self::R5g5b5a1 => "R5G5B5A1",
+            Self::B5g5r5a1 => "B5G5R5A1",
+            Self::A1r5g5b5 => "A1R5G5B5",
+            Self::A2r10g10b10 => "A2R10G10B10",
+            Self::A2b10g10r10 => "A2B10G10R10",
+        }
+    }
+
+    /// Size of a texel in bytes
+    pub fn size(self) -> usize {
+        match self {
+            Self::A2r10g10b10 | Self::A2b10g10r10 => 4,
+            _ => 2,
+        }
+    }
+
+    /// Shift and number of bits of the channels in the order R, G, B, A
+    ///
+    /// Formats without alpha have zero bits for the alpha channel.
+    fn channels(self) -> [(u32, u8); 4] {
+        match self {
+            Self::R4g4b4a4 => [(12, 4), (8, 4), (4, 4), (0, 4)],
+            Self::B4g4r4a4 => [(4, 4), (8, 4), (12, 4), (0, 4)],
+            Self::R5g6b5 => [(11, 5), (5, 6), (0, 5), (0, 0)],
+            Self::B5g6r5 => [(0, 5), (5, 6), (11, 5), (0, 0)],
+            Self::R5g5b5a1 => [(11, 5), (6, 5), (1, 5), (0, 1)],
+            Self::B5g5r5a1 => [(1, 5), (6, 5), (11, 5), (0, 1)],
+            Self::A1r5g5b5 => [(10, 5), (5, 5), (0, 5), (15, 1)],
+            Self::A2r10g10b10 => [(20, 10), (10, 10), (0, 10), (30, 2)],
+            Self::A2b10g10r10 => [(0, 10), (10, 10), (20, 10), (30, 2)],
+        }
+    }
+
+    pub fn has_alpha(self) -> bool {
+        self.channels()[3].1 > 0
+    }
+
+    /// Bits of the channels in the order R, G, B, and A if present
+    pub fn bit_depths(self) -> Vec<u8> {
+        self.channels()
+            .into_iter()
+            .map(|(_, bits)| bits)
+            .filter(|bits| *bits > 0)
+            .collect()
+    }
+
+    /// Bits of the channels if they differ between channels
+    pub fn channel_bit_depths(self) -> Option<Vec<u8>> {
+        let bit_depths = self.bit_depths();
+        let uniform = bit_depths.windows(2).all(|x| x[0] == x[1]);
+
+        (!uniform).then_some(bit_depths)
+    }
+
+    /// Channels are expanded to 16 bits instead of 8 bits
+    pub fn is_16_bit(self) -> bool {
+        self.channels().into_iter().any(|(_, bits)| bits > 8)
+    }
+
+    /// Decode a texel into a pixel of 8 or 16 bit channels
+    ///
+    /// The alpha channel is only written if the pixel has space for it.
+    pub fn decode(self, texel: &[u8], pixel: &mut [u8]) {
+        let value = match *texel {
+            [a, b] => u32::from(u16::from_le_bytes([a, b])),
+            [a, b, c, d] => u32::from_le_bytes([a, b, c, d]),
+            _ => 0,
+        };
+
+        let max = if self.is_16_bit() {
+            u32::from(u16::MAX)
+        } else {
+            u32::from(u8::MAX)
+        };
+
+        let samples = self.channels().map(|(shift, bits)| {
+            if bits == 0 {
+                return max;
+            }
+
+            let mask = 1_u32.wrapping_shl(bits.into()).wrapping_sub(1);
+            let sample = value.wrapping_shr(shift) & mask;
+            // Round to the nearest value
+            sample
+                .saturating_mul(max)
+                .saturating_add(mask >> 1)
+                .checked_div(mask)
+                .unwrap_or_default()
+        });
+
+        if self.is_16_bit() {
+            for (channel, sample) in pixel.chunks_exact_mut(2).zip(samples) {
+                let sample = u16::try_from(sample).unwrap_or(u16::MAX);
+                channel.copy_from_slice(&sample.to_ne_bytes());
+            }
+        } else {
+            for (channel, sample) in pixel.iter_mut().zip(samples) {
+                *channel = u8::try_from(sample).unwrap_or(u8::MAX);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(packed: Packed, texel: &[u8]) -> Vec<u8> {
+        let size = match (packed.has_alpha(), packed.is_16_bit()) {
+            (true, true) => 8,
+            (true, false) => 4,
+            (false, _) => 3,
+        };
+        let mut pixel = vec![0; size];
+        packed.decode(texel, &mut pixel);
+        pixel
+    }
+
+    #[test]
+    fn rgb565() {
+        // Full red and half green
+        let texel = 0b1111_1100_0000_0000_u16.to_le_bytes();
+        assert_eq!(decode(Packed::R5g6b5, &texel), [255, 130, 0]);
+        assert_eq!(decode(Packed::B5g6r5, &texel), [0, 130, 255]);
+        assert_eq!(Packed::R5g6b5.channel_bit_depths(), Some(vec![5, 6, 5]));
+        assert!(!Packed::R5g6b5.has_alpha());
+    }
+
+    #[test]
+    fn rgba4444() {
+        let texel = 0b1111_0000_1000_0001_u16.to_le_bytes();
+        assert_eq!(decode(Packed::R4g4b4a4, &texel), [255, 0, 136, 17]);
+        assert_eq!(Packed::R4g4b4a4.bit_depths(), [4, 4, 4, 4]);
+        // All channels have the same depth
+        assert_eq!(Packed::R4g4b4a4.channel_bit_depths(), None);
+    }
+
+    #[test]
+    fn rgba5551() {
+        let texel = 0b0000_0111_1100_0001_u16.to_le_bytes();
+        assert_eq!(decode(Packed::R5g5b5a1, &texel), [0, 255, 0, 255]);
+        assert_eq!(decode(Packed::A1r5g5b5, &texel), [8, 247, 8, 0]);
+        assert_eq!(
+            Packed::A1r5g5b5.channel_bit_depths(),
+            Some(vec![5, 5, 5, 1])
+        );
+    }
+
+    #[test]
+    fn rgb10_a2() {
+        let texel = (3 << 30 | 1023 << 20 | 512_u32).to_le_bytes();
+        let pixel = decode(Packed::A2r10g10b10, &texel)
+            .chunks_exact(2)
+            .map(|x| u16::from_ne_bytes([x[0], x[1]]))
+            .collect::<Vec<_>>();
+        assert_eq!(pixel, [u16::MAX, 0, 32800, u16::MAX]);
+        assert_eq!(Packed::A2b10g10r10.bit_depths(), [10, 10, 10, 2]);
+    }
+}
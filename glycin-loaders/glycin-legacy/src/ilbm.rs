@@ -0,0 +1,519 @@
+//! Decoder for Amiga IFF ILBM images
+//!
+//! Supports palette images with up to eight bitplanes, including the
+//! Extra-Halfbrite (EHB) and Hold-And-Modify (HAM) display modes, as well as
+//! deep ILBMs with 24 or 32 bitplanes. The chunky PBM variant of Deluxe Paint
+//! is supported as well.
+
+use std::ops::Range;
+
+use glycin_utils::safe_math::*;
+use glycin_utils::*;
+
+use crate::reader::{new_frame, Reader};
+
+pub const FORM: &[u8] = b"FORM";
+
+const MASKING_MASK_PLANE: u8 = 1;
+const MASKING_TRANSPARENT_COLOR: u8 = 2;
+const COMPRESSION_BYTE_RUN_1: u8 = 1;
+
+/// Display mode flags of the Amiga viewport in the `CAMG` chunk
+const CAMG_EXTRA_HALFBRITE: u32 = 0x80;
+const CAMG_HOLD_AND_MODIFY: u32 = 0x800;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Palette,
+    /// The upper half of the palette are the colors with half the brightness
+    ExtraHalfbrite,
+    /// The upper two bits modify one channel of the previous pixel
+    HoldAndModify,
+    /// Planes contain 8 bits per channel
+    TrueColor,
+}
+
+pub struct Ilbm {
+    data: Vec<u8>,
+    body: Range<usize>,
+    pub width: u32,
+    pub height: u32,
+    n_planes: u8,
+    masking: u8,
+    compressed: bool,
+    transparent_color: u16,
+    palette: Vec<[u8; 3]>,
+    mode: Mode,
+    /// PBM files store one byte per pixel instead of bitplanes
+    chunky: bool,
+}
+
+impl Ilbm {
+    pub fn new(data: Vec<u8>) -> Result<Self, ProcessError> {
+        let mut reader = Reader::new(&data);
+
+        if reader.bytes(4)? != FORM {
+            return Err(ProcessError::expected(&"Not an IFF file"));
+        }
+        // Size of the FORM
+        reader.skip(4)?;
+        let chunky = match reader.bytes(4)? {
+            b"ILBM" => false,
+            b"PBM " => true,
+            form_type => {
+                return Err(ProcessError::UnsupportedImageFormat(format!(
+                    "IFF {}",
+                    String::from_utf8_lossy(form_type)
+                )))
+            }
+        };
+
+        let mut header = None;
+        let mut palette = Vec::new();
+        let mut camg = None;
+        let mut body = None;
+
+        while !reader.is_empty() {
+            let id = reader.bytes(4)?;
+            let length = reader.u32_be()?.try_usize()?;
+            let start = reader.pos();
+
+            if id == b"BODY" {
+                // Accept truncated files, missing rows are reported when decoding
+                body = Some(start..start.sadd(length)?.min(data.len()));
+                break;
+            }
+
+            let chunk = reader.bytes(length)?;
+            match id {
+                b"BMHD" => header = Some(Header::new(chunk)?),
+                b"CMAP" => palette = chunk.chunks_exact(3).map(|x| [x[0], x[1], x[2]]).collect(),
+                b"CAMG" => camg = Some(Reader::new(chunk).u32_be()?),
+                _ => {}
+            }
+
+            // Chunks are padded to an even length
+            if length % 2 == 1 && !reader.is_empty() {
+                reader.skip(1)?;
+            }
+        }
+
+        let header = header.ok_or_else(|| ProcessError::expected(&"ILBM without BMHD chunk"))?;
+        let body = body.ok_or_else(|| ProcessError::expected(&"ILBM without BODY chunk"))?;
+        let n_planes = header.n_planes;
+        let camg = camg.unwrap_or_default();
+
+        let mode = match n_planes {
+            24 | 32 if !chunky => Mode::TrueColor,
+            6 | 8 if !chunky && camg & CAMG_HOLD_AND_MODIFY != 0 => Mode::HoldAndModify,
+            // Files without CAMG chunk often use EHB with six planes
+            6 if !chunky && (camg & CAMG_EXTRA_HALFBRITE != 0 || palette.len() == 32) => {
+                Mode::ExtraHalfbrite
+            }
+            8 if chunky => Mode::Palette,
+            1..=8 if !chunky => Mode::Palette,
+            _ => {
+                return Err(ProcessError::UnsupportedImageFormat(format!(
+                    "ILBM with {n_planes} planes"
+                )))
+            }
+        };
+
+        if palette.is_empty() && mode != Mode::TrueColor {
+            palette = grayscale_palette(n_planes)?;
+        }
+
+        // Palettes of the original chipset only have four bits per channel
+        if palette.iter().flatten().all(|x| x & 0x0F == 0) {
+            for channel in palette.iter_mut().flatten() {
+                *channel |= *channel >> 4;
+            }
+        }
+
+        if mode == Mode::ExtraHalfbrite {
+            palette.resize(32, [0, 0, 0]);
+            let halfbrite = palette
+                .iter()
+                .map(|color| color.map(|x| x >> 1))
+                .collect::<Vec<_>>();
+            palette.extend(halfbrite);
+        }
+
+        Ok(Self {
+            data,
+            body,
+            width: u32::from(header.width),
+            height: u32::from(header.height),
+            n_planes,
+            masking: header.masking,
+            compressed: header.compression == COMPRESSION_BYTE_RUN_1,
+            transparent_color: header.transparent_color,
+            palette,
+            mode,
+            chunky,
+        })
+    }
+
+    pub fn format_name(&self) -> String {
+        let name = if self.chunky { "PBM" } else { "ILBM" };
+
+        match self.mode {
+            Mode::ExtraHalfbrite => format!("{name} (EHB)"),
+            Mode::HoldAndModify => format!("{name} (HAM)"),
+            Mode::Palette | Mode::TrueColor => name.to_string(),
+        }
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        match self.mode {
+            Mode::TrueColor => self.n_planes == 32 || self.masking == MASKING_MASK_PLANE,
+            Mode::HoldAndModify => self.masking == MASKING_MASK_PLANE,
+            Mode::Palette | Mode::ExtraHalfbrite => {
+                self.masking == MASKING_MASK_PLANE || self.masking == MASKING_TRANSPARENT_COLOR
+            }
+        }
+    }
+
+    pub fn decode(&self) -> Result<Frame, ProcessError> {
+        let memory_format = if self.has_alpha() {
+            MemoryFormat::R8g8b8a8
+        } else {
+            MemoryFormat::R8g8b8
+        };
+        let pixel_size = memory_format.n_bytes().usize();
+        let width = self.width.try_usize()?;
+
+        let has_mask_plane = self.masking == MASKING_MASK_PLANE && !self.chunky;
+        // Rows of bitplanes are padded to 16 bits and chunky rows to 8 bits
+        let plane_length = if self.chunky {
+            width.next_multiple_of(2)
+        } else {
+            width.div_ceil(16).smul(2)?
+        };
+        let n_row_planes = if self.chunky {
+            1
+        } else {
+            u8::from(has_mask_plane).saturating_add(self.n_planes)
+        };
+
+        let mut byte_run = ByteRun1 {
+            reader: Reader::new(self.data.get(self.body.clone()).unwrap_or_default()),
+            compressed: self.compressed,
+            value: None,
+            remaining: 0,
+        };
+        let mut line = vec![0; plane_length.smul(usize::from(n_row_planes))?];
+
+        new_frame(self.width, self.height, memory_format, |rows| {
+            for row in rows {
+                for byte in line.iter_mut() {
+                    *byte = byte_run.next()?;
+                }
+                let planes = line.chunks_exact(plane_length).collect::<Vec<_>>();
+
+                // HAM modifies the border color at the start of each row
+                let mut previous = self.palette.first().copied().unwrap_or_default();
+
+                for (x, pixel) in row.chunks_exact_mut(pixel_size).enumerate() {
+                    let value = if self.chunky {
+                        u32::from(*planes[0].get(x).internal_error()?)
+                    } else {
+                        let mut value = 0_u32;
+                        for (n, plane) in planes.iter().take(self.n_planes.into()).enumerate() {
+                            let bit = u32::from(bit(plane, x)?);
+                            value |= bit.checked_shl(n.try_u32()?).unwrap_or_default();
+                        }
+                        value
+                    };
+
+                    let (color, alpha) = match self.mode {
+                        Mode::Palette | Mode::ExtraHalfbrite => {
+                            let transparent = self.masking == MASKING_TRANSPARENT_COLOR
+                                && value == u32::from(self.transparent_color);
+                            (self.color(value), !transparent)
+                        }
+                        Mode::HoldAndModify => {
+                            previous = self.hold_and_modify(previous, value);
+                            (previous, true)
+                        }
+                        Mode::TrueColor => {
+                            let [r, g, b, _] = value.to_le_bytes();
+                            ([r, g, b], true)
+                        }
+                    };
+
+                    pixel[..3].copy_from_slice(&color);
+
+                    if let Some(pixel_alpha) = pixel.get_mut(3) {
+                        *pixel_alpha = if self.n_planes == 32 {
+                            value.to_le_bytes()[3]
+                        } else if has_mask_plane {
+                            let mask = planes.get(usize::from(self.n_planes)).internal_error()?;
+                            if bit(mask, x)? == 1 {
+                                u8::MAX
+                            } else {
+                                0
+                            }
+                        } else if alpha {
+                            u8::MAX
+                        } else {
+                            0
+                        };
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    fn color(&self, index: u32) -> [u8; 3] {
+        index
+            .try_usize()
+            .ok()
+            .and_then(|x| self.palette.get(x))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn hold_and_modify(&self, previous: [u8; 3], value: u32) -> [u8; 3] {
+        let data_bits = self.n_planes.saturating_sub(2);
+        let control = value.checked_shr(data_bits.into()).unwrap_or_default();
+        let data = value
+            & u32::MAX
+                .checked_shr(32_u32.saturating_sub(data_bits.into()))
+                .unwrap_or_default();
+
+        // Expand the modified channel to 8 bits
+        let [channel, ..] = if data_bits == 4 {
+            (data << 4 | data).to_le_bytes()
+        } else {
+            (data << 2 | data >> 4).to_le_bytes()
+        };
+
+        let [r, g, b] = previous;
+        match control {
+            1 => [r, g, channel],
+            2 => [channel, g, b],
+            3 => [r, channel, b],
+            _ => self.color(data),
+        }
+    }
+}
+
+/// Header from the `BMHD` chunk
+struct Header {
+    width: u16,
+    height: u16,
+    n_planes: u8,
+    masking: u8,
+    compression: u8,
+    transparent_color: u16,
+}
+
+impl Header {
+    fn new(data: &[u8]) -> Result<Self, ProcessError> {
+        let mut reader = Reader::new(data);
+
+        let width = reader.u16_be()?;
+        let height = reader.u16_be()?;
+        // Position
+        reader.skip(4)?;
+        let n_planes = reader.u8()?;
+        let masking = reader.u8()?;
+        let compression = reader.u8()?;
+        // Padding
+        reader.skip(1)?;
+        let transparent_color = reader.u16_be()?;
+
+        Ok(Self {
+            width,
+            height,
+            n_planes,
+            masking,
+            compression,
+            transparent_color,
+        })
+    }
+}
+
+/// Palette for images without `CMAP` chunk
+fn grayscale_palette(n_planes: u8) -> Result<Vec<[u8; 3]>, ProcessError> {
+    let n_colors = 1_u32.checked_shl(n_planes.into()).internal_error()?;
+    let max = n_colors.saturating_sub(1).max(1);
+
+    (0..n_colors)
+        .map(|x| {
+            let gray =
+                u8::try_from(x.smul(255)?.checked_div(max).internal_error()?).internal_error()?;
+            Ok([gray, gray, gray])
+        })
+        .collect()
+}
+
+/// Bit at position `x` with the most significant bit first
+fn bit(plane: &[u8], x: usize) -> Result<u8, ProcessError> {
+    crate::reader::packed_sample(plane, x, 1)
+}
+
+/// Decoder for the ByteRun1 compression
+///
+/// Runs can continue in the next row.
+struct ByteRun1<'a> {
+    reader: Reader<'a>,
+    compressed: bool,
+    /// Repeated value or `None` for a run of literal bytes
+    value: Option<u8>,
+    remaining: u8,
+}
+
+impl ByteRun1<'_> {
+    fn next(&mut self) -> Result<u8, ProcessError> {
+        if !self.compressed {
+            return self.reader.u8();
+        }
+
+        loop {
+            if self.remaining > 0 {
+                self.remaining = self.remaining.saturating_sub(1);
+                return match self.value {
+                    Some(value) => Ok(value),
+                    None => self.reader.u8(),
+                };
+            }
+
+            match self.reader.u8()? {
+                // No operation
+                128 => {}
+                // Literal run of n + 1 bytes
+                n @ 0..128 => {
+                    self.value = None;
+                    self.remaining = n.saturating_add(1);
+                }
+                // Repeated byte for 257 - n times
+                n => {
+                    self.value = Some(self.reader.u8()?);
+                    self.remaining = 1_u8.saturating_add(n.wrapping_neg());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend(u32::try_from(data.len()).unwrap().to_be_bytes());
+        chunk.extend(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn ilbm(form_type: &[u8; 4], n_planes: u8, masking: u8, chunks: &[Vec<u8>]) -> Vec<u8> {
+        // Image of 4×1 pixels with ByteRun1 compression
+        // Transparent color is index 1
+        let mut bmhd = vec![0, 4, 0, 1, 0, 0, 0, 0, n_planes, masking, 1, 0, 0, 1];
+        bmhd.extend([1, 1, 0, 4, 0, 1]);
+
+        let mut form = form_type.to_vec();
+        form.extend(chunk(b"BMHD", &bmhd));
+        for chunk in chunks {
+            form.extend(chunk);
+        }
+
+        chunk(b"FORM", &form)
+    }
+
+    fn pixels(data: Vec<u8>) -> Vec<u8> {
+        Ilbm::new(data)
+            .unwrap()
+            .decode()
+            .unwrap()
+            .texture
+            .get_full()
+            .unwrap()
+    }
+
+    #[test]
+    fn palette() {
+        let cmap = chunk(b"CMAP", &[0, 0, 0, 0xF0, 0, 0, 0, 0xF0, 0, 0, 0, 0xF0]);
+        // Literal run with the two planes, pixel indices are 0, 1, 2, 3
+        let body = chunk(b"BODY", &[3, 0b0101_0000, 0, 0b0011_0000, 0]);
+        let data = ilbm(b"ILBM", 2, 0, &[cmap, body]);
+
+        let ilbm = Ilbm::new(data.clone()).unwrap();
+        assert_eq!(ilbm.format_name(), "ILBM");
+        assert!(!ilbm.has_alpha());
+        assert_eq!(
+            pixels(data),
+            [[0, 0, 0], [0xFF, 0, 0], [0, 0xFF, 0], [0, 0, 0xFF]].as_flattened()
+        );
+    }
+
+    #[test]
+    fn extra_halfbrite() {
+        let mut cmap = vec![0; 32 * 3];
+        cmap[3..6].copy_from_slice(&[0x80, 0x40, 0x22]);
+        // Pixel indices 1, 33, 0, 0 with a repeated byte for the empty planes
+        let body = chunk(b"BODY", &[1, 0b1100_0000, 0, 0xF9, 0, 1, 0b0100_0000, 0]);
+        let data = ilbm(b"ILBM", 6, 0, &[chunk(b"CMAP", &cmap), body]);
+
+        assert_eq!(Ilbm::new(data.clone()).unwrap().format_name(), "ILBM (EHB)");
+        assert_eq!(
+            pixels(data)[..6],
+            [[0x80, 0x40, 0x22], [0x40, 0x20, 0x11]].concat()
+        );
+    }
+
+    #[test]
+    fn hold_and_modify() {
+        let cmap = chunk(b"CMAP", &[0x10, 0x20, 0x30, 0xFF, 0xFF, 0xFF]);
+        let camg = chunk(b"CAMG", &CAMG_HOLD_AND_MODIFY.to_be_bytes());
+        // Palette color 1, then modify red to 0, blue to 0xFF, and green to 0x11
+        let planes = [
+            0b1011_0000,
+            0b0010_0000,
+            0b0010_0000,
+            0b0010_0000,
+            0b0011_0000,
+            0b0101_0000,
+        ];
+        let mut body = Vec::new();
+        for plane in planes {
+            body.extend([1, plane, 0]);
+        }
+        let data = ilbm(b"ILBM", 6, 0, &[cmap, camg, chunk(b"BODY", &body)]);
+
+        assert_eq!(Ilbm::new(data.clone()).unwrap().format_name(), "ILBM (HAM)");
+        assert_eq!(
+            pixels(data),
+            [
+                [0xFF, 0xFF, 0xFF],
+                [0, 0xFF, 0xFF],
+                [0, 0xFF, 0xFF],
+                [0, 0x11, 0xFF]
+            ]
+            .as_flattened()
+        );
+    }
+
+    #[test]
+    fn pbm_transparent_color() {
+        let cmap = chunk(b"CMAP", &[0, 0, 0, 1, 2, 3]);
+        let body = chunk(b"BODY", &[3, 0, 1, 0, 1]);
+        let data = ilbm(b"PBM ", 8, MASKING_TRANSPARENT_COLOR, &[cmap, body]);
+
+        let ilbm = Ilbm::new(data.clone()).unwrap();
+        assert_eq!(ilbm.format_name(), "PBM");
+        assert!(ilbm.has_alpha());
+        assert_eq!(
+            pixels(data),
+            [[0, 0, 0, 0xFF], [1, 2, 3, 0], [0, 0, 0, 0xFF], [1, 2, 3, 0]].as_flattened()
+        );
+    }
+}
@@ -0,0 +1,65 @@
+mod ilbm;
+mod pcx;
+mod reader;
+
+use std::io::Read;
+
+use glycin_utils::*;
+use ilbm::Ilbm;
+use pcx::Pcx;
+
+init_main_loader!(ImgDecoder);
+
+pub enum ImgDecoder {
+    Pcx(Pcx),
+    Ilbm(Ilbm),
+}
+
+impl LoaderImplementation for ImgDecoder {
+    fn init(
+        mut stream: UnixStream,
+        _mime_type: String,
+        _details: InitializationDetails,
+    ) -> Result<(Self, ImageDetails), ProcessError> {
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).internal_error()?;
+
+        // Both formats are identified by their first bytes
+        let decoder = if buf.starts_with(ilbm::FORM) {
+            Self::Ilbm(Ilbm::new(buf)?)
+        } else {
+            Self::Pcx(Pcx::new(buf)?)
+        };
+
+        let (width, height) = match &decoder {
+            Self::Pcx(pcx) => (pcx.width, pcx.height),
+            Self::Ilbm(ilbm) => (ilbm.width, ilbm.height),
+        };
+
+        let mut image_info = ImageDetails::new(width, height);
+        match &decoder {
+            Self::Pcx(pcx) => {
+                image_info.info_format_name = Some(String::from("PCX"));
+                image_info.info_alpha_channel = Some(pcx.has_alpha());
+            }
+            Self::Ilbm(ilbm) => {
+                image_info.info_format_name = Some(ilbm.format_name());
+                image_info.info_alpha_channel = Some(ilbm.has_alpha());
+            }
+        }
+
+        Ok((decoder, image_info))
+    }
+
+    fn frame(&mut self, _frame_request: FrameRequest) -> Result<Frame, ProcessError> {
+        let mut frame = match self {
+            Self::Pcx(pcx) => pcx.decode()?,
+            Self::Ilbm(ilbm) => ilbm.decode()?,
+        };
+
+        frame.details.info_bit_depth = Some(8);
+        frame.details.info_alpha_channel = Some(frame.memory_format.has_alpha());
+
+        Ok(frame)
+    }
+}
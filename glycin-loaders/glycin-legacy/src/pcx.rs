@@ -0,0 +1,321 @@
+//! Decoder for ZSoft PCX images
+//!
+//! Palette images with 1, 2, 4, or 8 bits per pixel in one or more planes are
+//! supported, as well as RGB and RGBA images with one plane per channel.
+
+use glycin_utils::safe_math::*;
+use glycin_utils::*;
+
+use crate::reader::{new_frame, packed_sample, Reader};
+
+const MANUFACTURER: u8 = 0x0A;
+const HEADER_SIZE: usize = 128;
+const ENCODING_RLE: u8 = 1;
+/// Marker in front of the 256 color palette at the end of the file
+const PALETTE_MARKER: u8 = 0x0C;
+/// Version without palette information in the header
+const VERSION_NO_PALETTE: u8 = 3;
+
+/// Default palette of EGA adapters
+const EGA_PALETTE: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0xAA],
+    [0x00, 0xAA, 0x00],
+    [0x00, 0xAA, 0xAA],
+    [0xAA, 0x00, 0x00],
+    [0xAA, 0x00, 0xAA],
+    [0xAA, 0x55, 0x00],
+    [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55],
+    [0x55, 0x55, 0xFF],
+    [0x55, 0xFF, 0x55],
+    [0x55, 0xFF, 0xFF],
+    [0xFF, 0x55, 0x55],
+    [0xFF, 0x55, 0xFF],
+    [0xFF, 0xFF, 0x55],
+    [0xFF, 0xFF, 0xFF],
+];
+
+/// Default palette of CGA adapters in the four color mode
+const CGA_PALETTE: [[u8; 3]; 4] = [
+    [0x00, 0x00, 0x00],
+    [0x55, 0xFF, 0xFF],
+    [0xFF, 0x55, 0xFF],
+    [0xFF, 0xFF, 0xFF],
+];
+
+pub struct Pcx {
+    data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    bits_per_pixel: u8,
+    n_planes: u8,
+    bytes_per_line: usize,
+    compressed: bool,
+    palette: Vec<[u8; 3]>,
+}
+
+impl Pcx {
+    pub fn new(data: Vec<u8>) -> Result<Self, ProcessError> {
+        let mut reader = Reader::new(&data);
+
+        if reader.u8()? != MANUFACTURER {
+            return Err(ProcessError::expected(&"Not a PCX file"));
+        }
+
+        let version = reader.u8()?;
+        let compressed = reader.u8()? == ENCODING_RLE;
+        let bits_per_pixel = reader.u8()?;
+        let x_min = reader.u16_le()?;
+        let y_min = reader.u16_le()?;
+        let x_max = reader.u16_le()?;
+        let y_max = reader.u16_le()?;
+        // Resolution in DPI
+        reader.skip(4)?;
+        let header_palette = reader.bytes(48)?;
+        // Reserved
+        reader.skip(1)?;
+        let n_planes = reader.u8()?;
+        let bytes_per_line = usize::from(reader.u16_le()?);
+
+        let size = |min: u16, max: u16| {
+            max.checked_sub(min)
+                .map(|x| u32::from(x).saturating_add(1))
+                .ok_or_else(|| ProcessError::expected(&"Invalid PCX dimensions"))
+        };
+        let width = size(x_min, x_max)?;
+        let height = size(y_min, y_max)?;
+
+        match (bits_per_pixel, n_planes) {
+            (1, 1..=4) | (2, 1) | (4, 1) | (8, 1) | (8, 3) | (8, 4) => {}
+            _ => {
+                return Err(ProcessError::UnsupportedImageFormat(format!(
+                    "PCX with {n_planes} planes of {bits_per_pixel} bits"
+                )))
+            }
+        }
+
+        if bytes_per_line.smul(8)? < width.try_usize()?.smul(usize::from(bits_per_pixel))? {
+            return Err(ProcessError::expected(&"PCX scanlines are too short"));
+        }
+
+        let palette = if bits_per_pixel == 8 && n_planes == 1 {
+            // The palette is appended to the image data or it's a grayscale image
+            data.len()
+                .checked_sub(769)
+                .and_then(|pos| data.get(pos..))
+                .filter(|x| x.first() == Some(&PALETTE_MARKER))
+                .map(|x| x[1..].chunks_exact(3).map(rgb).collect())
+                .unwrap_or_else(|| (0..=u8::MAX).map(|x| [x, x, x]).collect())
+        } else if bits_per_pixel == 1 && n_planes == 1 {
+            vec![[0, 0, 0], [0xFF, 0xFF, 0xFF]]
+        } else {
+            let palette = header_palette.chunks_exact(3).map(rgb).collect::<Vec<_>>();
+
+            if version != VERSION_NO_PALETTE && palette.iter().any(|x| *x != [0, 0, 0]) {
+                palette
+            } else if bits_per_pixel == 2 {
+                CGA_PALETTE.to_vec()
+            } else {
+                EGA_PALETTE.to_vec()
+            }
+        };
+
+        Ok(Self {
+            data,
+            width,
+            height,
+            bits_per_pixel,
+            n_planes,
+            bytes_per_line,
+            compressed,
+            palette,
+        })
+    }
+
+    /// Images with 8-bit channels and a fourth plane
+    pub fn has_alpha(&self) -> bool {
+        self.is_true_color() && self.n_planes == 4
+    }
+
+    fn is_true_color(&self) -> bool {
+        self.bits_per_pixel == 8 && self.n_planes > 1
+    }
+
+    pub fn decode(&self) -> Result<Frame, ProcessError> {
+        let memory_format = if self.has_alpha() {
+            MemoryFormat::R8g8b8a8
+        } else {
+            MemoryFormat::R8g8b8
+        };
+        let pixel_size = memory_format.n_bytes().usize();
+
+        let mut rle = Rle {
+            reader: Reader::new(self.data.get(HEADER_SIZE..).unwrap_or_default()),
+            compressed: self.compressed,
+            value: 0,
+            remaining: 0,
+        };
+        let mut line = vec![0; self.bytes_per_line.smul(usize::from(self.n_planes))?];
+
+        new_frame(self.width, self.height, memory_format, |rows| {
+            for row in rows {
+                for byte in line.iter_mut() {
+                    *byte = rle.next()?;
+                }
+                let planes = line.chunks_exact(self.bytes_per_line).collect::<Vec<_>>();
+
+                for (x, pixel) in row.chunks_exact_mut(pixel_size).enumerate() {
+                    if self.is_true_color() {
+                        for (channel, plane) in pixel.iter_mut().zip(&planes) {
+                            *channel = *plane.get(x).internal_error()?;
+                        }
+                    } else {
+                        // Each plane contains some of the bits of the index
+                        let mut index = 0_usize;
+                        for (n, plane) in planes.iter().enumerate() {
+                            let sample = usize::from(packed_sample(plane, x, self.bits_per_pixel)?);
+                            let shift = n.smul(usize::from(self.bits_per_pixel))?.try_u32()?;
+                            index |= sample.checked_shl(shift).unwrap_or_default();
+                        }
+
+                        let color = self.palette.get(index).copied().unwrap_or_default();
+                        pixel.copy_from_slice(&color);
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+fn rgb(x: &[u8]) -> [u8; 3] {
+    [x[0], x[1], x[2]]
+}
+
+/// Decoder for the run-length encoding
+///
+/// Runs can continue in the next scanline.
+struct Rle<'a> {
+    reader: Reader<'a>,
+    compressed: bool,
+    value: u8,
+    remaining: u8,
+}
+
+impl Rle<'_> {
+    fn next(&mut self) -> Result<u8, ProcessError> {
+        if self.remaining > 0 {
+            self.remaining = self.remaining.saturating_sub(1);
+            return Ok(self.value);
+        }
+
+        loop {
+            let byte = self.reader.u8()?;
+            if !self.compressed || byte & 0xC0 != 0xC0 {
+                return Ok(byte);
+            }
+
+            self.value = self.reader.u8()?;
+            let count = byte & 0x3F;
+            if count > 0 {
+                self.remaining = count.saturating_sub(1);
+                return Ok(self.value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(bits_per_pixel: u8, n_planes: u8, bytes_per_line: u16) -> Vec<u8> {
+        let mut data = vec![MANUFACTURER, 5, ENCODING_RLE, bits_per_pixel];
+        // Image of 4×2 pixels
+        for x in [0_u16, 0, 3, 1] {
+            data.extend(x.to_le_bytes());
+        }
+        data.resize(65, 0);
+        data.push(n_planes);
+        data.extend(bytes_per_line.to_le_bytes());
+        data.resize(HEADER_SIZE, 0);
+        data
+    }
+
+    fn pixels(pcx: &Pcx) -> Vec<u8> {
+        pcx.decode().unwrap().texture.get_full().unwrap()
+    }
+
+    #[test]
+    fn palette_256() {
+        let mut data = header(8, 1, 4);
+        // Run of four pixels with index 1 and literal indices
+        data.extend([0xC4, 1, 2, 0xC1, 0, 1, 2]);
+        data.push(PALETTE_MARKER);
+        data.extend([[0, 0, 0], [10, 20, 30], [40, 50, 60]].as_flattened());
+        data.extend([0; 759]);
+
+        let pcx = Pcx::new(data).unwrap();
+        assert_eq!((pcx.width, pcx.height), (4, 2));
+        assert!(!pcx.has_alpha());
+        assert_eq!(
+            pixels(&pcx),
+            [
+                [10, 20, 30],
+                [10, 20, 30],
+                [10, 20, 30],
+                [10, 20, 30],
+                [40, 50, 60],
+                [0, 0, 0],
+                [10, 20, 30],
+                [40, 50, 60],
+            ]
+            .as_flattened()
+        );
+    }
+
+    #[test]
+    fn ega_planes() {
+        let mut data = header(1, 4, 2);
+        // Pixel 0 has index 0b0101 and pixel 3 index 0b1111
+        for plane in [0b1001_0000, 0b0001_0000, 0b1001_0000, 0b0001_0000] {
+            data.extend([plane, 0]);
+        }
+        data.extend([0; 8]);
+
+        let pixels = pixels(&Pcx::new(data).unwrap());
+        assert_eq!(pixels[0..3], EGA_PALETTE[0b0101]);
+        assert_eq!(pixels[3..6], EGA_PALETTE[0]);
+        assert_eq!(pixels[9..12], EGA_PALETTE[0b1111]);
+    }
+
+    #[test]
+    fn rgba() {
+        let mut data = header(8, 4, 4);
+        // Runs continue across the planes and scanlines
+        data.extend([
+            0xC4, 1, 0xC4, 2, 0xC4, 3, 0xC8, 4, 0xC4, 5, 0xC4, 6, 0xC4, 7,
+        ]);
+
+        let pcx = Pcx::new(data).unwrap();
+        assert!(pcx.has_alpha());
+        assert_eq!(
+            pixels(&pcx),
+            [[1, 2, 3, 4]; 4]
+                .into_iter()
+                .chain([[4, 5, 6, 7]; 4])
+                .flatten()
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn unsupported() {
+        assert!(matches!(
+            Pcx::new(header(4, 2, 2)),
+            Err(ProcessError::UnsupportedImageFormat(_))
+        ));
+    }
+}
@@ -0,0 +1,100 @@
+use glycin_utils::safe_math::*;
+use glycin_utils::*;
+
+/// Reader for the headers and chunks of binary formats
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], ProcessError> {
+        let end = self.pos.sadd(len)?;
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .ok_or_else(|| ProcessError::expected(&"Unexpected end of image data"))?;
+        self.pos = end;
+
+        Ok(bytes)
+    }
+
+    pub fn skip(&mut self, len: usize) -> Result<(), ProcessError> {
+        self.bytes(len).map(|_| ())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ProcessError> {
+        self.bytes(N)?.try_into().internal_error()
+    }
+
+    pub fn u8(&mut self) -> Result<u8, ProcessError> {
+        Ok(u8::from_le_bytes(self.array()?))
+    }
+
+    pub fn u16_le(&mut self) -> Result<u16, ProcessError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u16_be(&mut self) -> Result<u16, ProcessError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    pub fn u32_be(&mut self) -> Result<u32, ProcessError> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+}
+
+/// Value of `bits` wide samples packed with the most significant bit first
+pub fn packed_sample(data: &[u8], x: usize, bits: u8) -> Result<u8, ProcessError> {
+    let bit = x.smul(usize::from(bits))?;
+    let byte = *data
+        .get(bit.checked_div(8).internal_error()?)
+        .ok_or_else(|| ProcessError::expected(&"Scanline is too short"))?;
+
+    let shift = 8_usize
+        .checked_sub(usize::from(bits))
+        .and_then(|x| x.checked_sub(bit.srem(8).ok()?))
+        .internal_error()?;
+    let mask = u8::MAX.checked_shr(8_u32.saturating_sub(u32::from(bits)));
+
+    Ok(byte.checked_shr(shift.try_u32()?).unwrap_or_default() & mask.unwrap_or_default())
+}
+
+/// Frame with the pixels written by `write_rows`
+///
+/// The rows are passed to `write_rows` from top to bottom.
+pub fn new_frame(
+    width: u32,
+    height: u32,
+    memory_format: MemoryFormat,
+    write_rows: impl FnOnce(std::slice::ChunksExactMut<u8>) -> Result<(), ProcessError>,
+) -> Result<Frame, ProcessError> {
+    let stride = width.try_usize()?.smul(memory_format.n_bytes().usize())?;
+    if stride == 0 || height == 0 {
+        return Err(ProcessError::expected(&"Image has no pixels"));
+    }
+
+    let mut memory =
+        SharedMemory::new(stride.smul(height.try_usize()?)?.try_u64()?).expected_error()?;
+
+    write_rows(memory.chunks_exact_mut(stride))?;
+
+    Ok(Frame::new(
+        width,
+        height,
+        memory_format,
+        memory.into_binary_data(),
+    )?)
+}
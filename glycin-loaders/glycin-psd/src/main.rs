@@ -0,0 +1,47 @@
+mod psd;
+
+use std::io::Read;
+
+use glycin_utils::*;
+use psd::Psd;
+
+init_main_loader!(ImgDecoder);
+
+pub struct ImgDecoder {
+    psd: Psd,
+}
+
+impl LoaderImplementation for ImgDecoder {
+    fn init(
+        mut stream: UnixStream,
+        _mime_type: String,
+        _details: InitializationDetails,
+    ) -> Result<(Self, ImageDetails), ProcessError> {
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).internal_error()?;
+
+        let psd = Psd::new(buf)?;
+
+        let mut image_info = ImageDetails::new(psd.width, psd.height);
+        image_info.info_format_name = Some(String::from(if psd.is_psb { "PSB" } else { "PSD" }));
+        image_info.info_alpha_channel = Some(psd.has_alpha);
+        image_info.metadata_exif = psd
+            .exif
+            .as_ref()
+            .map(BinaryData::from_data)
+            .transpose()
+            .expected_error()?;
+        image_info.metadata_xmp = psd
+            .xmp
+            .as_ref()
+            .map(BinaryData::from_data)
+            .transpose()
+            .expected_error()?;
+
+        Ok((Self { psd }, image_info))
+    }
+
+    fn frame(&mut self, _frame_request: FrameRequest) -> Result<Frame, ProcessError> {
+        self.psd.decode()
+    }
+}
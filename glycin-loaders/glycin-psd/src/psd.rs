@@ -0,0 +1,661 @@
+//! Decoder for the merged composite image of PSD and PSB files
+//!
+//! Photoshop stores a flattened version of all layers after the layer data.
+//! Only this composite is decoded. The layers themselves are skipped.
+
+use glycin_utils::safe_math::*;
+use glycin_utils::*;
+
+const SIGNATURE: &[u8] = b"8BPS";
+const RESOURCE_SIGNATURE: &[u8] = b"8BIM";
+
+const RESOURCE_ICC_PROFILE: u16 = 1039;
+const RESOURCE_EXIF: u16 = 1058;
+const RESOURCE_XMP: u16 = 1060;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    Grayscale,
+    Rgb,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    Raw,
+    Rle,
+}
+
+pub struct Psd {
+    data: Vec<u8>,
+    /// PSB files use larger length fields
+    pub is_psb: bool,
+    pub width: u32,
+    pub height: u32,
+    pub depth: u16,
+    pub color_mode: ColorMode,
+    /// Number of channels stored in the image data
+    channels: u16,
+    /// The first extra channel contains the transparency of the composite
+    pub has_alpha: bool,
+    pub icc_profile: Option<Vec<u8>>,
+    pub exif: Option<Vec<u8>>,
+    pub xmp: Option<Vec<u8>>,
+    image_data_offset: usize,
+}
+
+impl Psd {
+    pub fn new(data: Vec<u8>) -> Result<Self, ProcessError> {
+        let mut reader = Reader::new(&data);
+
+        if reader.bytes(SIGNATURE.len())? != SIGNATURE {
+            return Err(ProcessError::expected(&"Not a PSD file"));
+        }
+
+        let is_psb = match reader.u16()? {
+            1 => false,
+            2 => true,
+            version => {
+                return Err(ProcessError::UnsupportedImageFormat(format!(
+                    "PSD version {version}"
+                )))
+            }
+        };
+
+        // Reserved
+        reader.skip(6)?;
+
+        let channels = reader.u16()?;
+        let height = reader.u32()?;
+        let width = reader.u32()?;
+        let depth = reader.u16()?;
+        let color_mode = match reader.u16()? {
+            1 => ColorMode::Grayscale,
+            3 => ColorMode::Rgb,
+            mode => {
+                return Err(ProcessError::UnsupportedImageFormat(format!(
+                    "PSD color mode {mode}"
+                )))
+            }
+        };
+
+        if !matches!(depth, 8 | 16) {
+            return Err(ProcessError::UnsupportedImageFormat(format!(
+                "PSD bit depth {depth}"
+            )));
+        }
+
+        let color_channels = match color_mode {
+            ColorMode::Grayscale => 1,
+            ColorMode::Rgb => 3,
+        };
+
+        if channels < color_channels {
+            return Err(ProcessError::expected(&format!(
+                "PSD has only {channels} channels"
+            )));
+        }
+
+        // Color mode data, only used for indexed and duotone images
+        let color_mode_data_len = reader.u32()?.try_usize()?;
+        reader.skip(color_mode_data_len)?;
+
+        let resources_len = reader.u32()?.try_usize()?;
+        let resources = parse_resources(reader.bytes(resources_len)?)?;
+
+        // Layer and mask information
+        let layer_and_mask_len = reader.length(is_psb)?;
+        let layer_and_mask = reader.bytes(layer_and_mask_len)?;
+        let has_alpha =
+            channels > color_channels && has_merged_transparency(layer_and_mask, is_psb);
+
+        let mut psd = Self {
+            image_data_offset: reader.pos,
+            data: Vec::new(),
+            is_psb,
+            width,
+            height,
+            depth,
+            color_mode,
+            channels,
+            has_alpha,
+            icc_profile: None,
+            exif: None,
+            xmp: None,
+        };
+
+        for (id, resource) in resources {
+            match id {
+                RESOURCE_ICC_PROFILE => psd.icc_profile = Some(resource.to_vec()),
+                RESOURCE_EXIF => psd.exif = Some(resource.to_vec()),
+                RESOURCE_XMP => psd.xmp = Some(resource.to_vec()),
+                _ => {}
+            }
+        }
+
+        psd.data = data;
+
+        Ok(psd)
+    }
+
+    pub fn memory_format(&self) -> MemoryFormat {
+        match (self.color_mode, self.depth, self.has_alpha) {
+            (ColorMode::Grayscale, 8, false) => MemoryFormat::G8,
+            (ColorMode::Grayscale, 8, true) => MemoryFormat::G8a8,
+            (ColorMode::Grayscale, _, false) => MemoryFormat::G16,
+            (ColorMode::Grayscale, _, true) => MemoryFormat::G16a16,
+            (ColorMode::Rgb, 8, false) => MemoryFormat::R8g8b8,
+            (ColorMode::Rgb, 8, true) => MemoryFormat::R8g8b8a8,
+            (ColorMode::Rgb, _, false) => MemoryFormat::R16g16b16,
+            (ColorMode::Rgb, _, true) => MemoryFormat::R16g16b16a16,
+        }
+    }
+
+    /// Decode the composite image into the memory format
+    pub fn decode(&self) -> Result<Frame, ProcessError> {
+        let memory_format = self.memory_format();
+        let n_channels = usize::from(memory_format.n_channels());
+        let sample_size = usize::from(self.depth / 8);
+        let width = self.width.try_usize()?;
+        let height = self.height.try_usize()?;
+        let row_len = width.smul(sample_size)?;
+
+        let data = self.data.get(self.image_data_offset..).internal_error()?;
+        let mut reader = Reader::new(data);
+
+        let compression = match reader.u16()? {
+            0 => Compression::Raw,
+            1 => Compression::Rle,
+            compression => {
+                return Err(ProcessError::UnsupportedImageFormat(format!(
+                    "PSD compression {compression}"
+                )))
+            }
+        };
+
+        // Length of the compressed rows of all channels
+        let row_lengths = if compression == Compression::Rle {
+            let n_rows = usize::from(self.channels).smul(height)?;
+            let mut row_lengths = Vec::new();
+            for _ in 0..n_rows {
+                row_lengths.push(if self.is_psb {
+                    reader.u32()?.try_usize()?
+                } else {
+                    usize::from(reader.u16()?)
+                });
+            }
+            row_lengths
+        } else {
+            Vec::new()
+        };
+
+        let stride = width.smul(n_channels)?.smul(sample_size)?;
+        let mut memory = SharedMemory::new(stride.smul(height)?.try_u64()?).expected_error()?;
+        let mut row = vec![0; row_len];
+
+        for channel in 0..n_channels {
+            for y in 0..height {
+                match compression {
+                    Compression::Raw => row.copy_from_slice(reader.bytes(row_len)?),
+                    Compression::Rle => {
+                        let index = channel.smul(height)?.sadd(y)?;
+                        let len = *row_lengths.get(index).internal_error()?;
+                        unpack_bits(reader.bytes(len)?, &mut row)?;
+                    }
+                }
+
+                let pixels = memory
+                    .get_mut(y.smul(stride)?..y.sadd(1)?.smul(stride)?)
+                    .internal_error()?;
+
+                for (pixel, sample) in pixels
+                    .chunks_exact_mut(n_channels.smul(sample_size)?)
+                    .zip(row.chunks_exact(sample_size))
+                {
+                    let start = channel.smul(sample_size)?;
+                    let target = pixel
+                        .get_mut(start..start.sadd(sample_size)?)
+                        .internal_error()?;
+
+                    if sample_size == 2 {
+                        // Samples are stored in big endian
+                        target.copy_from_slice(
+                            &u16::from_be_bytes([sample[0], sample[1]]).to_ne_bytes(),
+                        );
+                    } else {
+                        target.copy_from_slice(sample);
+                    }
+                }
+            }
+        }
+
+        if self.has_alpha {
+            remove_white_matte(memory_format, &mut memory);
+        }
+
+        let mut frame = Frame::new(
+            self.width,
+            self.height,
+            memory_format,
+            memory.into_binary_data(),
+        )?;
+
+        frame.details.info_bit_depth = Some(u8::try_from(self.depth).internal_error()?);
+        frame.details.info_alpha_channel = Some(self.has_alpha);
+        frame.details.info_grayscale = Some(self.color_mode == ColorMode::Grayscale);
+
+        if let Some(icc_profile) = &self.icc_profile {
+            frame.details.color_icc_profile =
+                Some(BinaryData::from_data(icc_profile).expected_error()?);
+        }
+
+        Ok(frame)
+    }
+}
+
+/// Image resources as pairs of resource ID and data
+fn parse_resources(data: &[u8]) -> Result<Vec<(u16, &[u8])>, ProcessError> {
+    let mut reader = Reader::new(data);
+    let mut resources = Vec::new();
+
+    while reader.pos < data.len() {
+        if reader.bytes(RESOURCE_SIGNATURE.len())? != RESOURCE_SIGNATURE {
+            // Other signatures are only used by very old versions
+            break;
+        }
+
+        let id = reader.u16()?;
+
+        // Pascal string padded to an even size
+        let name_len = usize::from(reader.u8()?);
+        reader.skip(name_len.sadd(1)?.next_multiple_of(2).saturating_sub(1))?;
+
+        // Data padded to an even size
+        let len = reader.u32()?.try_usize()?;
+        resources.push((id, reader.bytes(len)?));
+        reader.skip(len.srem(2)?)?;
+    }
+
+    Ok(resources)
+}
+
+/// Returns if the first extra channel contains the composite's transparency
+///
+/// This is indicated by a negative layer count.
+fn has_merged_transparency(layer_and_mask: &[u8], is_psb: bool) -> bool {
+    let mut reader = Reader::new(layer_and_mask);
+
+    let Ok(layer_info_len) = reader.length(is_psb) else {
+        return false;
+    };
+
+    layer_info_len > 0 && reader.array().is_ok_and(|x| i16::from_be_bytes(x) < 0)
+}
+
+/// Decompress PackBits encoded data
+fn unpack_bits(mut data: &[u8], out: &mut [u8]) -> Result<(), ProcessError> {
+    let mut pos = 0;
+
+    while let Some((&header, rest)) = data.split_first() {
+        let header = i8::from_be_bytes([header]);
+        match header {
+            // Copy the next `header + 1` bytes
+            0.. => {
+                let len = usize::from(header.unsigned_abs()).sadd(1)?;
+                let (bytes, rest) = rest.split_at_checked(len).ok_or_else(rle_error)?;
+                out.get_mut(pos..pos.sadd(len)?)
+                    .ok_or_else(rle_error)?
+                    .copy_from_slice(bytes);
+                pos = pos.sadd(len)?;
+                data = rest;
+            }
+            // No operation
+            -128 => data = rest,
+            // Repeat the next byte `-header + 1` times
+            _ => {
+                let len = usize::from(header.unsigned_abs()).sadd(1)?;
+                let (&byte, rest) = rest.split_first().ok_or_else(rle_error)?;
+                out.get_mut(pos..pos.sadd(len)?)
+                    .ok_or_else(rle_error)?
+                    .fill(byte);
+                pos = pos.sadd(len)?;
+                data = rest;
+            }
+        }
+    }
+
+    if pos != out.len() {
+        return Err(rle_error());
+    }
+
+    Ok(())
+}
+
+fn rle_error() -> ProcessError {
+    ProcessError::expected(&"Invalid RLE compressed PSD data")
+}
+
+/// Undo the blending of the composite with white
+///
+/// Photoshop blends transparent parts of the composite with a white
+/// background.
+fn remove_white_matte(memory_format: MemoryFormat, buf: &mut [u8]) {
+    let pixel_size = usize::from(memory_format.n_bytes().u8());
+
+    for pixel in buf.chunks_exact_mut(pixel_size) {
+        let [r, g, b, a] = MemoryFormat::to_f32(memory_format, pixel);
+
+        if a > 0. && a < 1. {
+            let unmatte = |x: f32| ((x - (1. - a)) / a).clamp(0., 1.);
+            MemoryFormat::from_f32(
+                [unmatte(r), unmatte(g), unmatte(b), a],
+                memory_format,
+                pixel,
+            );
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ProcessError> {
+        let end = self.pos.sadd(len)?;
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .ok_or_else(|| ProcessError::expected(&"Unexpected end of PSD data"))?;
+        self.pos = end;
+
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), ProcessError> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ProcessError> {
+        self.bytes(N)?.try_into().internal_error()
+    }
+
+    fn u8(&mut self) -> Result<u8, ProcessError> {
+        Ok(u8::from_be_bytes(self.array()?))
+    }
+
+    fn u16(&mut self) -> Result<u16, ProcessError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, ProcessError> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, ProcessError> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    /// Length field that is larger for PSB files
+    fn length(&mut self, is_psb: bool) -> Result<usize, ProcessError> {
+        if is_psb {
+            Ok(self.u64()?.try_usize()?)
+        } else {
+            Ok(self.u32()?.try_usize()?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RGB: u16 = 3;
+    const GRAYSCALE: u16 = 1;
+
+    fn header(
+        version: u16,
+        channels: u16,
+        width: u32,
+        height: u32,
+        depth: u16,
+        mode: u16,
+    ) -> Vec<u8> {
+        [
+            SIGNATURE,
+            &version.to_be_bytes(),
+            &[0; 6],
+            &channels.to_be_bytes(),
+            &height.to_be_bytes(),
+            &width.to_be_bytes(),
+            &depth.to_be_bytes(),
+            &mode.to_be_bytes(),
+            // Color mode data
+            &0_u32.to_be_bytes(),
+        ]
+        .concat()
+    }
+
+    fn psd(header: Vec<u8>, resources: &[u8], layer_and_mask: &[u8], image_data: &[u8]) -> Vec<u8> {
+        let is_psb = header.get(4..6) == Some(&[0, 2]);
+        let layer_and_mask_len = if is_psb {
+            layer_and_mask
+                .len()
+                .try_u64()
+                .unwrap()
+                .to_be_bytes()
+                .to_vec()
+        } else {
+            layer_and_mask
+                .len()
+                .try_u32()
+                .unwrap()
+                .to_be_bytes()
+                .to_vec()
+        };
+
+        [
+            header.as_slice(),
+            &resources.len().try_u32().unwrap().to_be_bytes(),
+            resources,
+            &layer_and_mask_len,
+            layer_and_mask,
+            image_data,
+        ]
+        .concat()
+    }
+
+    fn resource(id: u16, data: &[u8]) -> Vec<u8> {
+        [
+            RESOURCE_SIGNATURE,
+            &id.to_be_bytes(),
+            // Empty name padded to even size
+            &[0, 0],
+            &data.len().try_u32().unwrap().to_be_bytes(),
+            data,
+            &vec![0; data.len() % 2],
+        ]
+        .concat()
+    }
+
+    fn decode(data: Vec<u8>) -> (MemoryFormat, Vec<u8>) {
+        let frame = Psd::new(data).unwrap().decode().unwrap();
+        (frame.memory_format, frame.texture.get_full().unwrap())
+    }
+
+    #[test]
+    fn rgb8_raw() {
+        // Planar red, green, and blue samples of two pixels
+        let image_data = [0, 0, 255, 0, 0, 255, 0, 0];
+        let data = psd(header(1, 3, 2, 1, 8, RGB), &[], &[], &image_data);
+
+        assert_eq!(
+            decode(data),
+            (MemoryFormat::R8g8b8, vec![255, 0, 0, 0, 255, 0])
+        );
+    }
+
+    #[test]
+    fn gray8_rle() {
+        // A literal run of two bytes, followed by a repeated byte
+        let row = [0x01, 1, 2, 0xFF, 9];
+        let image_data = [[0, 1].as_slice(), &5_u16.to_be_bytes(), &row].concat();
+        let data = psd(header(1, 1, 4, 1, 8, GRAYSCALE), &[], &[], &image_data);
+
+        assert_eq!(decode(data), (MemoryFormat::G8, vec![1, 2, 9, 9]));
+    }
+
+    #[test]
+    fn gray16_psb() {
+        // PSB files use 32 bit row lengths
+        let row = [0x01, 0x12, 0x34];
+        let image_data = [[0, 1].as_slice(), &3_u32.to_be_bytes(), &row].concat();
+        let data = psd(header(2, 1, 1, 1, 16, GRAYSCALE), &[], &[], &image_data);
+
+        let psd = Psd::new(data.clone()).unwrap();
+        assert!(psd.is_psb);
+
+        assert_eq!(
+            decode(data),
+            (MemoryFormat::G16, 0x1234_u16.to_ne_bytes().to_vec())
+        );
+    }
+
+    #[test]
+    fn merged_alpha() {
+        // A negative layer count marks the extra channel as transparency
+        let layer_and_mask = [2_u32.to_be_bytes().as_slice(), &(-1_i16).to_be_bytes()].concat();
+        // Opaque red and black with half transparency blended with white
+        let image_data = [0, 0, 255, 127, 0, 127, 0, 127, 255, 128];
+        let data = psd(
+            header(1, 4, 2, 1, 8, RGB),
+            &[],
+            &layer_and_mask,
+            &image_data,
+        );
+
+        assert_eq!(
+            decode(data.clone()),
+            (MemoryFormat::R8g8b8a8, vec![255, 0, 0, 255, 0, 0, 0, 128])
+        );
+
+        // Without the marker, the extra channel is ignored
+        let data = psd(header(1, 4, 2, 1, 8, RGB), &[], &[], &image_data);
+        assert_eq!(
+            decode(data),
+            (MemoryFormat::R8g8b8, vec![255, 0, 0, 127, 127, 127])
+        );
+    }
+
+    #[test]
+    fn resources() {
+        let resources = [
+            resource(RESOURCE_ICC_PROFILE, &[1, 2, 3]),
+            resource(RESOURCE_EXIF, b"Exif"),
+            resource(1000, &[0; 5]),
+            resource(RESOURCE_XMP, b"<x:xmpmeta/>"),
+        ]
+        .concat();
+        let data = psd(
+            header(1, 1, 1, 1, 8, GRAYSCALE),
+            &resources,
+            &[],
+            &[0, 0, 0],
+        );
+
+        let psd = Psd::new(data).unwrap();
+        assert_eq!(psd.icc_profile, Some(vec![1, 2, 3]));
+        assert_eq!(psd.exif, Some(b"Exif".to_vec()));
+        assert_eq!(psd.xmp, Some(b"<x:xmpmeta/>".to_vec()));
+
+        let frame = psd.decode().unwrap();
+        let icc_profile = frame.details.color_icc_profile.unwrap();
+        assert_eq!(icc_profile.get_full().unwrap(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn unsupported() {
+        for header in [
+            // Version
+            header(3, 3, 1, 1, 8, RGB),
+            // CMYK
+            header(1, 4, 1, 1, 8, 4),
+            // Bit depth
+            header(1, 3, 1, 1, 1, RGB),
+        ] {
+            let data = psd(header, &[], &[], &[0; 5]);
+            assert!(matches!(
+                Psd::new(data),
+                Err(ProcessError::UnsupportedImageFormat(_))
+            ));
+        }
+
+        // Compression
+        let data = psd(header(1, 1, 1, 1, 8, GRAYSCALE), &[], &[], &[0, 2, 0]);
+        assert!(matches!(
+            Psd::new(data).unwrap().decode(),
+            Err(ProcessError::UnsupportedImageFormat(_))
+        ));
+    }
+
+    #[test]
+    fn malformed() {
+        let valid = psd(header(1, 3, 1, 1, 8, RGB), &[], &[], &[0, 0, 1, 2, 3]);
+        assert!(Psd::new(valid.clone()).unwrap().decode().is_ok());
+
+        let mut signature = valid.clone();
+        signature[0] = b'9';
+        assert!(Psd::new(signature).is_err());
+
+        // Fewer channels than the color mode requires
+        let data = psd(header(1, 2, 1, 1, 8, RGB), &[], &[], &[0, 0, 1, 2]);
+        assert!(Psd::new(data).is_err());
+
+        // Truncated headers and sections
+        for len in [0, 10, 26, 30] {
+            assert!(Psd::new(valid[..len].to_vec()).is_err(), "{len}");
+        }
+
+        // Truncated image data
+        let image = Psd::new(valid[..valid.len() - 1].to_vec()).unwrap();
+        assert!(image.decode().is_err());
+
+        // Resource that is larger than the resource section
+        let mut resources = resource(RESOURCE_EXIF, b"Exif");
+        resources.truncate(resources.len() - 1);
+        let data = psd(
+            header(1, 1, 1, 1, 8, GRAYSCALE),
+            &resources,
+            &[],
+            &[0, 0, 0],
+        );
+        assert!(Psd::new(data).is_err());
+    }
+
+    #[test]
+    fn malformed_rle() {
+        for row in [
+            // Run longer than the row
+            [0xFE, 1].as_slice(),
+            // Literal longer than the row
+            &[0x02, 1, 2, 3],
+            // Missing data of the run
+            &[0x01, 1],
+            // Row is not filled
+            &[0x00, 1],
+        ] {
+            let image_data = [
+                [0, 1].as_slice(),
+                &u16::try_from(row.len()).unwrap().to_be_bytes(),
+                row,
+            ]
+            .concat();
+            let data = psd(header(1, 1, 2, 1, 8, GRAYSCALE), &[], &[], &image_data);
+            assert!(Psd::new(data).unwrap().decode().is_err(), "{row:?}");
+        }
+    }
+}
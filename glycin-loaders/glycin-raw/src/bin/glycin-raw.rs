@@ -68,6 +68,7 @@ impl LoaderImplementation for ImgDecoder {
         let mut image_info = ImageDetails::new(w, h);
 
         image_info.info_format_name = Some(String::from("RAW"));
+        image_info.info_alpha_channel = Some(false);
         image_info.metadata_xmp = xmp.and_then(|xmp| BinaryData::from_data(xmp).ok());
         image_info.transformation_orientation = orientation
             .try_into()
@@ -6,11 +6,16 @@ use gio::prelude::*;
 use glycin_utils::safe_math::*;
 use glycin_utils::*;
 use rsvg::prelude::*;
+use xml::reader::XmlEvent;
 
 /// Current librsvg limit on maximum dimensions. See
 /// <https://gitlab.gnome.org/GNOME/librsvg/-/issues/938>
 pub const RSVG_MAX_SIZE: u32 = 32_767;
 
+/// User stylesheet that disables antialiasing for shapes and images
+const NO_ANTIALIAS_STYLESHEET: &str =
+    "* { shape-rendering: crispEdges !important; image-rendering: optimizeSpeed !important }";
+
 init_main_loader!(ImgDecoder);
 
 #[derive(Default)]
@@ -27,16 +32,57 @@ pub struct ImgDecoderDetails {
 pub struct Instruction {
     total_size: (u32, u32),
     area: Option<rsvg::Rectangle>,
+    antialias: Option<Antialias>,
 }
 
 pub fn thread(
-    stream: UnixStream,
+    mut stream: UnixStream,
     base_file: Option<gio::File>,
+    reject_external_references: bool,
+    collect_advisories: bool,
     info_send: Sender<Result<ImageDetails, ProcessError>>,
     frame_send: Sender<Result<Frame, ProcessError>>,
     instr_recv: Receiver<Instruction>,
 ) {
-    let input_stream = gio::UnixInputStream::take_fd(stream.into());
+    let mut advisories = None;
+
+    let input_stream: gio::InputStream = if reject_external_references || collect_advisories {
+        // The complete document is needed to search for references
+        let mut data = Vec::new();
+        if let Err(err) = stream.read_to_end(&mut data).internal_error() {
+            info_send.send(Err(err)).unwrap();
+            return;
+        }
+
+        let scan = match scan_document(&data) {
+            Ok(scan) => scan,
+            // Documents that can't be scanned could contain references
+            Err(err) if reject_external_references => {
+                info_send.send(Err(err)).unwrap();
+                return;
+            }
+            Err(_) => DocumentScan::default(),
+        };
+
+        if reject_external_references {
+            if let Some(reference) = scan.references.first() {
+                info_send
+                    .send(Err(ProcessError::expected(&format!(
+                        "SVG references external resource '{reference}'"
+                    ))))
+                    .unwrap();
+                return;
+            }
+        }
+
+        if collect_advisories {
+            advisories = Some(svg_advisories(&scan));
+        }
+
+        gio::MemoryInputStream::from_bytes(&gio::glib::Bytes::from_owned(data)).upcast()
+    } else {
+        gio::UnixInputStream::take_fd(stream.into()).upcast()
+    };
 
     let handle = rsvg::Handle::from_stream_sync(
         &input_stream,
@@ -63,6 +109,7 @@ pub fn thread(
     image_info.info_format_name = Some(String::from("SVG"));
     image_info.info_dimensions_text = dimensions_text(intrinsic_dimensions);
     image_info.dimensions_inch = dimensions_inch(intrinsic_dimensions);
+    image_info.advisories = advisories;
 
     info_send.send(Ok(image_info)).unwrap();
 
@@ -81,11 +128,14 @@ pub fn thread(
         instr.total_size = (total_width.round() as u32, total_height.round() as u32);
 
         // librsvg does not currently support larger images
-        if instr.total_size.0 > RSVG_MAX_SIZE || instr.total_size.1 > RSVG_MAX_SIZE {
-            continue;
-        }
-
-        let frame = render(&handle, instr);
+        let frame = if instr.total_size.0 > RSVG_MAX_SIZE || instr.total_size.1 > RSVG_MAX_SIZE {
+            Err(ProcessError::UnsupportedImageFormat(format!(
+                "Rendering an SVG at {}x{} exceeds the maximum size of {RSVG_MAX_SIZE}x{RSVG_MAX_SIZE}",
+                instr.total_size.0, instr.total_size.1
+            )))
+        } else {
+            render(&handle, instr)
+        };
 
         frame_send.send(frame).unwrap();
     }
@@ -106,6 +156,26 @@ pub fn render(renderer: &rsvg::Handle, instr: Instruction) -> Result<Frame, Proc
 
     let context = cairo::Context::new(&surface).expected_error()?;
 
+    // Shapes and images use the antialiasing from their style properties.
+    // The stylesheet is always set to reset it from previous renderings.
+    let stylesheet = if instr.antialias == Some(Antialias::None) {
+        NO_ANTIALIAS_STYLESHEET
+    } else {
+        ""
+    };
+    renderer
+        .set_stylesheet(stylesheet.as_bytes())
+        .expected_error()?;
+
+    if let Some(antialias) = instr.antialias {
+        let antialias = cairo_antialias(antialias);
+        context.set_antialias(antialias);
+
+        let mut font_options = cairo::FontOptions::new().expected_error()?;
+        font_options.set_antialias(antialias);
+        context.set_font_options(&font_options);
+    }
+
     renderer
         .render_document(
             &context,
@@ -153,12 +223,26 @@ impl LoaderImplementation for ImgDecoder {
         let (frame_send, frame_recv) = channel();
         let (instr_send, instr_recv) = channel();
 
+        let reject_external_references = details.reject_external_references.unwrap_or_default();
+        let collect_advisories = details.collect_advisories.unwrap_or_default();
+
         let base_file = details
             .base_dir
             .as_ref()
+            .filter(|_| !reject_external_references)
             .map(|x| gio::File::for_path(x).child("placeholder.svg"));
 
-        std::thread::spawn(move || thread(stream, base_file, info_send, frame_send, instr_recv));
+        std::thread::spawn(move || {
+            thread(
+                stream,
+                base_file,
+                reject_external_references,
+                collect_advisories,
+                info_send,
+                frame_send,
+                instr_recv,
+            )
+        });
         let image_info = info_recv.recv().unwrap()?;
 
         let decoder = ImgDecoder {
@@ -192,7 +276,11 @@ impl LoaderImplementation for ImgDecoder {
             None
         };
 
-        let instr = Instruction { total_size, area };
+        let instr = Instruction {
+            total_size,
+            area,
+            antialias: frame_request.antialias,
+        };
 
         thread.instr_send.send(instr).unwrap();
 
@@ -200,6 +288,156 @@ impl LoaderImplementation for ImgDecoder {
     }
 }
 
+fn cairo_antialias(antialias: Antialias) -> cairo::Antialias {
+    match antialias {
+        Antialias::None => cairo::Antialias::None,
+        Antialias::Fast => cairo::Antialias::Fast,
+        Antialias::Best => cairo::Antialias::Best,
+        _ => cairo::Antialias::Good,
+    }
+}
+
+/// Features of the document that are relevant for security
+#[derive(Debug, Default)]
+pub struct DocumentScan {
+    /// References to resources outside of the document
+    pub references: Vec<String>,
+    /// Document contains scripts or event handlers
+    pub has_scripts: bool,
+}
+
+/// Searches the parsed document for external references and scripts
+///
+/// References are `href` attributes, `xml-stylesheet` processing
+/// instructions, and CSS `url()` values and `@import` rules in attributes and
+/// `<style>` elements. References to elements inside the document and
+/// `data:` URLs are not considered external.
+///
+/// Scripts are `<script>` and `<handler>` elements, event handler attributes
+/// like `onload`, and `javascript:` URLs. Text and comments that only look
+/// like scripts are not counted. Fails for documents that can't be parsed.
+pub fn scan_document(data: &[u8]) -> Result<DocumentScan, ProcessError> {
+    let mut references = Vec::new();
+    let mut has_scripts = false;
+    let mut in_style = false;
+
+    for event in xml::EventReader::new(data) {
+        match event.expected_error()? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                in_style = name.local_name == "style";
+                has_scripts |= matches!(name.local_name.as_str(), "script" | "handler");
+
+                for attribute in attributes {
+                    has_scripts |= is_event_handler(&attribute.name.local_name)
+                        || is_javascript_url(&attribute.value);
+
+                    if attribute.name.local_name == "href" {
+                        references.push(attribute.value);
+                    } else {
+                        css_references(&attribute.value, &mut references);
+                    }
+                }
+            }
+            XmlEvent::EndElement { .. } => in_style = false,
+            XmlEvent::Characters(text) | XmlEvent::CData(text) if in_style => {
+                css_references(&text, &mut references);
+            }
+            XmlEvent::ProcessingInstruction {
+                name,
+                data: Some(data),
+            } if name == "xml-stylesheet" => {
+                references.extend(quoted_value(&data, "href="));
+            }
+            _ => {}
+        }
+    }
+
+    let references = references
+        .into_iter()
+        .map(|x| x.trim().to_string())
+        .filter(|x| {
+            !x.is_empty()
+                && !x.starts_with('#')
+                && !x.get(..5).is_some_and(|x| x.eq_ignore_ascii_case("data:"))
+                && !is_javascript_url(x)
+        })
+        .collect();
+
+    Ok(DocumentScan {
+        references,
+        has_scripts,
+    })
+}
+
+/// Attributes like `onload` or `onclick`
+fn is_event_handler(name: &str) -> bool {
+    name.len() > 2 && name.get(..2).is_some_and(|x| x.eq_ignore_ascii_case("on"))
+}
+
+/// URLs that run the script they contain
+fn is_javascript_url(value: &str) -> bool {
+    value
+        .trim_start()
+        .get(..11)
+        .is_some_and(|x| x.eq_ignore_ascii_case("javascript:"))
+}
+
+/// Adds the values of `url()` and `@import` in CSS to `references`
+fn css_references(css: &str, references: &mut Vec<String>) {
+    // Lowercase ASCII keeps the positions of characters
+    let lowercase = css.to_ascii_lowercase();
+
+    for (pos, _) in lowercase.match_indices("url(") {
+        let Some(value) = css.get(pos + "url(".len()..) else {
+            continue;
+        };
+        let value = value.trim_start();
+        let reference = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value.get(1..).and_then(|x| x.split_once(quote)),
+            _ => value.split_once(')'),
+        };
+        if let Some((reference, _)) = reference {
+            references.push(reference.to_string());
+        }
+    }
+
+    for (pos, _) in lowercase.match_indices("@import") {
+        // `@import url(…)` is already covered above
+        if let Some(value) = css.get(pos..) {
+            references.extend(quoted_value(value, "@import"));
+        }
+    }
+}
+
+/// Quoted string following `prefix`, like `"b"` in `a="b"` for prefix `a=`
+fn quoted_value(data: &str, prefix: &str) -> Option<String> {
+    let (_, value) = data.split_once(prefix)?;
+    let value = value.trim_start();
+    let quote = value.chars().next().filter(|x| ['"', '\''].contains(x))?;
+    let (value, _) = value.get(1..)?.split_once(quote)?;
+    Some(value.to_string())
+}
+
+/// Risky features of the document
+///
+/// Neither external resources nor scripts are loaded, but they can indicate
+/// documents that try to track viewers or exploit other SVG renderers.
+pub fn svg_advisories(scan: &DocumentScan) -> Vec<String> {
+    let mut advisories: Vec<String> = scan
+        .references
+        .iter()
+        .map(|reference| format!("SVG references external resource '{reference}'"))
+        .collect();
+
+    if scan.has_scripts {
+        advisories.push(String::from("SVG contains scripts"));
+    }
+
+    advisories
+}
+
 pub fn svg_dimensions_float(renderer: &rsvg::Handle) -> (f64, f64) {
     if let Some((width, height)) = renderer.intrinsic_size_in_pixels() {
         (width, height)
@@ -297,3 +535,104 @@ pub fn dimension_inch(length: rsvg::Length) -> Option<f64> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn references(svg: &str) -> Vec<String> {
+        scan_document(svg.as_bytes()).unwrap().references
+    }
+
+    fn has_scripts(svg: &str) -> bool {
+        scan_document(svg.as_bytes()).unwrap().has_scripts
+    }
+
+    #[test]
+    fn href_references() {
+        let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">
+            <image href="https://example.com/a.png"/>
+            <use xlink:href='other.svg#shape'/>
+            <use href="#local"/>
+            <image href="data:image/png;base64,AAAA"/>
+        </svg>"##;
+
+        assert_eq!(
+            references(svg),
+            ["https://example.com/a.png", "other.svg#shape"]
+        );
+    }
+
+    #[test]
+    fn css_references() {
+        let svg = r##"<?xml-stylesheet type="text/css" href="sheet.css"?>
+        <svg xmlns="http://www.w3.org/2000/svg">
+            <style>
+                @import "imported.css";
+                @IMPORT url(other.css);
+                rect { fill: url( 'paint.svg#gradient' ) }
+            </style>
+            <style><![CDATA[ @import 'cdata.css'; ]]></style>
+            <rect style="fill: URL(&quot;style.svg#a&quot;)" stroke="url(#local)"/>
+            <text>@import "not-css.css"</text>
+        </svg>"##;
+
+        assert_eq!(
+            references(svg),
+            [
+                "sheet.css",
+                "other.css",
+                "paint.svg#gradient",
+                "imported.css",
+                "cdata.css",
+                "style.svg#a"
+            ]
+        );
+    }
+
+    #[test]
+    fn scripts() {
+        for svg in [
+            r#"<svg xmlns="http://www.w3.org/2000/svg"><script>alert()</script></svg>"#,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" onload="alert()"/>"#,
+            r#"<svg xmlns="http://www.w3.org/2000/svg"><rect ONCLICK="alert()"/></svg>"#,
+            r#"<svg xmlns="http://www.w3.org/2000/svg"><a href=" JavaScript:alert()"/></svg>"#,
+            r#"<svg xmlns="http://www.w3.org/2000/svg"><handler type="application/ecmascript"/></svg>"#,
+        ] {
+            assert!(has_scripts(svg), "{svg}");
+        }
+    }
+
+    #[test]
+    fn no_scripts() {
+        for svg in [
+            r#"<svg xmlns="http://www.w3.org/2000/svg"><text>&lt;script&gt; onload="x"</text></svg>"#,
+            r#"<svg xmlns="http://www.w3.org/2000/svg"><!-- <script>alert()</script> --></svg>"#,
+            r#"<svg xmlns="http://www.w3.org/2000/svg"><desc>javascript:alert()</desc></svg>"#,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" data-x=" onload=x" opacity="1"/>"#,
+        ] {
+            assert!(!has_scripts(svg), "{svg}");
+        }
+    }
+
+    #[test]
+    fn advisories() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" onload="alert()">
+            <image href="https://example.org/a.png"/>
+            <a href="javascript:alert()"/>
+        </svg>"#;
+
+        assert_eq!(
+            svg_advisories(&scan_document(svg.as_bytes()).unwrap()),
+            [
+                "SVG references external resource 'https://example.org/a.png'",
+                "SVG contains scripts"
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_document() {
+        assert!(scan_document(b"<svg><image href='a.png'></svg>").is_err());
+    }
+}
@@ -3,10 +3,8 @@ use std::ffi::{OsStr, OsString};
 use gio::glib;
 use gio::prelude::*;
 use glycin::MemoryFormatSelection;
-use image::imageops;
 
-const SCALE_FILTER1: imageops::FilterType = imageops::FilterType::Nearest;
-const SCALE_FILTER2: imageops::FilterType = imageops::FilterType::Triangle;
+const SCALE_FILTER: glycin::DownscaleFilter = glycin::DownscaleFilter::Triangle;
 
 fn main() {
     let app = gio::Application::new(None, gio::ApplicationFlags::HANDLES_COMMAND_LINE);
@@ -95,52 +93,20 @@ fn x(
     let thumbnail_width = (frame.width() as f32 * scale).round() as u32;
     let thumbnail_height = (frame.height() as f32 * scale).round() as u32;
 
-    let buf;
-    let color;
-
-    match frame.memory_format() {
-        glycin::MemoryFormat::R8g8b8 => {
-            buf = resize::<image::Rgb<u8>>(&frame, thumbnail_width, thumbnail_height);
-            color = png::ColorType::Rgb;
-        }
-        glycin::MemoryFormat::R8g8b8a8 => {
-            buf = resize::<image::Rgba<u8>>(&frame, thumbnail_width, thumbnail_height);
-            color = png::ColorType::Rgba;
-        }
+    let color = match frame.memory_format() {
+        glycin::MemoryFormat::R8g8b8 => png::ColorType::Rgb,
+        glycin::MemoryFormat::R8g8b8a8 => png::ColorType::Rgba,
         unexpected_format => unreachable!("Unexpected memory format: {unexpected_format:?}"),
     };
 
+    let thumbnail = frame.downscale(thumbnail_width, thumbnail_height, SCALE_FILTER)?;
+
     let mut encoder = png::Encoder::new(buf_writer, thumbnail_width, thumbnail_height);
     encoder.set_color(color);
 
     let mut writer = encoder.write_header()?;
 
-    writer.write_image_data(&buf)?;
+    writer.write_image_data(thumbnail.buf_slice())?;
 
     Ok(())
 }
-
-fn resize<T: image::Pixel<Subpixel = u8> + 'static>(
-    frame: &glycin::Frame,
-    thumbnail_width: u32,
-    thumbnail_height: u32,
-) -> Vec<u8> {
-    let img =
-        image::ImageBuffer::<T, _>::from_raw(frame.width(), frame.height(), frame.buf_slice())
-            .unwrap();
-
-    let rought_scaled = imageops::resize(
-        &img,
-        thumbnail_width * 2,
-        thumbnail_height * 2,
-        SCALE_FILTER1,
-    );
-
-    imageops::resize(
-        &rought_scaled,
-        thumbnail_width,
-        thumbnail_height,
-        SCALE_FILTER2,
-    )
-    .into_raw()
-}
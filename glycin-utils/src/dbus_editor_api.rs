@@ -13,6 +13,7 @@ use zbus::zvariant::{DeserializeDict, OwnedObjectPath, SerializeDict, Type};
 
 use crate::dbus_types::{self, *};
 use crate::error::*;
+use crate::safe_math::*;
 
 #[derive(DeserializeDict, SerializeDict, Type, Debug)]
 #[zvariant(signature = "dict")]
@@ -164,7 +165,13 @@ impl<E: EditorImplementation> Editor<E> {
         new_image: NewImage,
         encoding_options: EncodingOptions,
     ) -> Result<EncodedImage, RemoteError> {
-        E::create(mime_type, new_image, encoding_options).map_err(|x| x.into_editor_error())
+        let encoded_image = if let Some(target_size) = encoding_options.target_size {
+            create_with_target_size::<E>(mime_type, new_image, encoding_options, target_size)
+        } else {
+            E::create(mime_type, new_image, encoding_options)
+        };
+
+        encoded_image.map_err(|x| x.into_editor_error())
     }
 
     async fn edit(
@@ -281,6 +288,47 @@ impl<E: EditorImplementation> EditableImage<E> {
     }
 }
 
+/// Encode with the highest quality that produces at most `target_size` bytes
+///
+/// The quality is determined via binary search. A quality given in the
+/// encoding options is used as the highest quality to consider.
+fn create_with_target_size<E: EditorImplementation>(
+    mime_type: String,
+    new_image: NewImage,
+    mut encoding_options: EncodingOptions,
+    target_size: u64,
+) -> Result<EncodedImage, ProcessError> {
+    let mut lowest = 1;
+    let mut highest = encoding_options.quality.unwrap_or(100).clamp(1, 100);
+    let mut best = None;
+
+    while lowest <= highest {
+        let quality = lowest.midpoint(highest);
+        encoding_options.quality = Some(quality);
+
+        let encoded_image = E::create(
+            mime_type.clone(),
+            new_image.clone(),
+            encoding_options.clone(),
+        )?;
+        let size = encoded_image.data.get().expected_error()?.len().try_u64()?;
+        log::debug!("Encoding with quality {quality} results in {size} bytes");
+
+        if size <= target_size {
+            best = Some(encoded_image);
+            lowest = quality.saturating_add(1);
+        } else {
+            highest = quality.saturating_sub(1);
+        }
+    }
+
+    best.ok_or_else(|| {
+        ProcessError::expected(&format!(
+            "Image can't be encoded with at most {target_size} bytes"
+        ))
+    })
+}
+
 /// Implement this trait to create an image editor
 pub trait EditorImplementation: Send + Sync + Sized + 'static {
     const USEABLE: bool = true;
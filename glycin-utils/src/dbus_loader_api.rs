@@ -5,7 +5,9 @@ use std::os::fd::OwnedFd;
 use std::os::unix::net::UnixStream;
 use std::sync::{Arc, Mutex, MutexGuard};
 
-use futures_util::FutureExt;
+use futures_util::{FutureExt, StreamExt};
+use glycin_common::BinaryData;
+use zbus::object_server::SignalEmitter;
 use zbus::zvariant::OwnedObjectPath;
 
 use crate::dbus_types::*;
@@ -19,6 +21,32 @@ pub trait LoaderImplementation: Send + Sync + Sized + 'static {
     ) -> Result<(Self, ImageDetails), ProcessError>;
 
     fn frame(&mut self, frame_request: FrameRequest) -> Result<Frame, ProcessError>;
+
+    /// Decode a frame while providing intermediate results
+    ///
+    /// Called instead of [`Self::frame`] if [`FrameRequest::progressive`] is
+    /// set. Loaders that support progressive decoding call `progress` with
+    /// increasingly refined versions of the complete frame. By default, only
+    /// the final frame is returned.
+    fn frame_progressive(
+        &mut self,
+        frame_request: FrameRequest,
+        progress: &mut dyn FnMut(Frame),
+    ) -> Result<Frame, ProcessError> {
+        let _ = progress;
+        self.frame(frame_request)
+    }
+
+    /// ICC profile embedded in the image without decoding a frame
+    ///
+    /// Loaders that read the profile during [`Self::init`] or can read it
+    /// cheaply implement this. By default, an error is returned since
+    /// decoding a frame instead could change the state of the loader.
+    fn color_profile(&mut self) -> Result<Option<BinaryData>, ProcessError> {
+        Err(ProcessError::expected(
+            &"Loader doesn't support reading the color profile without decoding",
+        ))
+    }
 }
 
 pub struct Loader<T: LoaderImplementation> {
@@ -93,7 +121,13 @@ impl<T: LoaderImplementation> Image<T> {
 
 #[zbus::interface(name = "org.gnome.glycin.Image")]
 impl<T: LoaderImplementation> Image<T> {
-    async fn frame(&self, frame_request: FrameRequest) -> Result<Frame, RemoteError> {
+    async fn frame(
+        &self,
+        frame_request: FrameRequest,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> Result<Frame, RemoteError> {
+        let (progress_send, mut progress_recv) = futures_channel::mpsc::unbounded();
+
         let loader_implementation = self.loader_implementation.clone();
         let mut frame_request = blocking::unblock(move || {
             let mut loader_implementation = loader_implementation.lock().map_err(|err| {
@@ -102,18 +136,52 @@ impl<T: LoaderImplementation> Image<T> {
                 ))
             })?;
 
-            loader_implementation
-                .frame(frame_request)
-                .map_err(|x| x.into_loader_error())
+            if frame_request.progressive {
+                loader_implementation.frame_progressive(frame_request, &mut |frame| {
+                    let _ = progress_send.unbounded_send(frame);
+                })
+            } else {
+                loader_implementation.frame(frame_request)
+            }
+            .map_err(|x| x.into_loader_error())
         })
         .fuse();
 
-        futures_util::select! {
-            result = frame_request => result,
-            _ = self.dropped.wait().fuse() => Err(RemoteError::Aborted),
+        loop {
+            futures_util::select! {
+                result = frame_request => return result,
+                frame = progress_recv.select_next_some() => {
+                    if let Err(err) = Self::progressive_frame(&emitter, frame).await {
+                        log::warn!("Failed to emit intermediate frame: {err}");
+                    }
+                }
+                _ = self.dropped.wait().fuse() => return Err(RemoteError::Aborted),
+            }
         }
     }
 
+    async fn color_profile(&self) -> Result<RemoteColorProfile, RemoteError> {
+        let loader_implementation = self.loader_implementation.clone();
+        blocking::unblock(move || {
+            let mut loader_implementation = loader_implementation.lock().map_err(|err| {
+                RemoteError::InternalLoaderError(format!(
+                    "Failed to lock loader state for operation: {err}"
+                ))
+            })?;
+
+            let icc_profile = loader_implementation
+                .color_profile()
+                .map_err(|x| x.into_loader_error())?;
+
+            Ok(RemoteColorProfile { icc_profile })
+        })
+        .await
+    }
+
+    /// Intermediate result while decoding a progressive frame request
+    #[zbus(signal)]
+    async fn progressive_frame(emitter: &SignalEmitter<'_>, frame: Frame) -> zbus::Result<()>;
+
     async fn done(
         &self,
         #[zbus(object_server)] object_server: &zbus::ObjectServer,
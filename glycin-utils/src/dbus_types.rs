@@ -26,6 +26,43 @@ pub struct InitRequest {
 #[non_exhaustive]
 pub struct InitializationDetails {
     pub base_dir: Option<std::path::PathBuf>,
+    /// Treat the image as still image without checking for further frames
+    ///
+    /// Loaders can skip decoding additional frames to detect animations.
+    /// Further frames are only decoded if explicitly requested.
+    pub assume_still: Option<bool>,
+    /// Keep the memory format of the image when editing
+    ///
+    /// Editors must not convert the pixel data to a different memory format,
+    /// like expanding grayscale to RGB or reducing the bit depth. If the
+    /// format can't be preserved, an error is returned instead.
+    pub preserve_memory_format: Option<bool>,
+    /// Fail if the image references external resources
+    ///
+    /// This applies to formats like SVG that can include other files or URLs.
+    /// Without this option, such references are silently not loaded.
+    pub reject_external_references: Option<bool>,
+    /// Reconstruct the original JPEG if the image is a recompressed JPEG
+    ///
+    /// The JPEG is returned in [`ImageDetails::jpeg_reconstruction`].
+    pub reconstruct_jpeg: Option<bool>,
+    /// Maximum number of animation frames to decode
+    ///
+    /// After this many frames, [`RemoteError::NoMoreFrames`](crate::RemoteError::NoMoreFrames)
+    /// is returned instead of decoding further frames or looping.
+    pub max_frames: Option<u64>,
+    /// Report risky or deprecated features of the image
+    ///
+    /// The findings are returned in [`ImageDetails::advisories`].
+    pub collect_advisories: Option<bool>,
+    /// Report the CICP even if the image also has an ICC profile
+    ///
+    /// Formats like AVIF and HEIF can store both. By default, loaders report
+    /// the CICP only as [`FrameDetails::color_cicp_fallback`] in this case,
+    /// such that the ICC profile is used. If enabled, the CICP is reported as
+    /// [`FrameDetails::color_cicp`], which takes precedence over the ICC
+    /// profile when converting colors. The ICC profile is still reported.
+    pub prefer_cicp: Option<bool>,
 }
 
 #[derive(Deserialize, Serialize, Type, Debug, Clone, Default)]
@@ -41,6 +78,111 @@ pub struct FrameRequest {
     /// Get first frame, if previously selected frame was the last one
     #[serde(with = "as_value", skip_serializing_if = "std::ops::Not::not", default)]
     pub loop_animation: bool,
+    /// Return an auxiliary image instead of the main image
+    #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
+    pub auxiliary_image: Option<AuxiliaryImage>,
+    /// Return the image with this index from [`ImageDetails::sub_images`]
+    #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
+    pub sub_image: Option<u32>,
+    /// Emit intermediate frames while decoding
+    ///
+    /// Loaders that support progressive decoding emit increasingly refined
+    /// versions of the frame via the `ProgressiveFrame` signal before
+    /// returning the final frame.
+    #[serde(with = "as_value", skip_serializing_if = "std::ops::Not::not", default)]
+    pub progressive: bool,
+    /// Antialiasing for rendering vector images
+    #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
+    pub antialias: Option<Antialias>,
+    /// Return the layer with this name from [`ImageDetails::layers`]
+    #[serde(with = "optional", skip_serializing_if = "Option::is_none", default)]
+    pub layer: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[zvariant(signature = "s")]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+/// Trade-off between speed and quality for rendering vector images
+pub enum Antialias {
+    /// No antialiasing, with crisp edges
+    None,
+    /// Some antialiasing, preferring speed
+    Fast,
+    /// Balance of quality and speed
+    Good,
+    /// Highest quality antialiasing
+    Best,
+}
+
+#[derive(Deserialize, Serialize, Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[zvariant(signature = "s")]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+/// Images that are stored alongside the main image
+pub enum AuxiliaryImage {
+    /// Alpha channel as grayscale image
+    ///
+    /// Returned as [`MemoryFormat::G8`] or [`MemoryFormat::G16`].
+    Alpha,
+    /// Depth map as grayscale image, like in portrait photos
+    ///
+    /// Returned as [`MemoryFormat::G8`] or [`MemoryFormat::G16`]. The
+    /// dimensions can differ from the main image.
+    Depth,
+}
+
+#[derive(DeserializeDict, SerializeDict, Type, Debug, Clone, PartialEq, Eq)]
+#[zvariant(signature = "dict")]
+#[non_exhaustive]
+/// Image inside a container that stores multiple images
+pub struct SubImageInfo {
+    pub width: u32,
+    pub height: u32,
+    pub kind: SubImageKind,
+}
+
+impl SubImageInfo {
+    pub fn new(width: u32, height: u32, kind: SubImageKind) -> Self {
+        Self {
+            width,
+            height,
+            kind,
+        }
+    }
+}
+
+#[derive(DeserializeDict, SerializeDict, Type, Debug, Clone, PartialEq, Eq)]
+#[zvariant(signature = "dict")]
+#[non_exhaustive]
+/// Layer of an image that stores multiple layers, like render passes in OpenEXR
+pub struct LayerInfo {
+    /// Name of the layer, empty for channels without layer name
+    pub name: String,
+    /// Names of the channels in the layer, like `R`, `G`, `B`, or `Z`
+    pub channels: Vec<String>,
+}
+
+impl LayerInfo {
+    pub fn new(name: String, channels: Vec<String>) -> Self {
+        Self { name, channels }
+    }
+}
+
+#[derive(Deserialize, Serialize, Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[zvariant(signature = "s")]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+/// Relation of a sub-image to the other images in the container
+pub enum SubImageKind {
+    /// Page of a document, like in multi-page TIFFs
+    Page,
+    /// Variant of the same image, for example with a different size like in ICOs
+    Variant,
+    /// Reduced size level of a mipmap, like in DDS files
+    Mipmap,
+    /// Independent image of a collection, like in HEIF files
+    Item,
 }
 
 /// Various image metadata
@@ -61,6 +203,23 @@ impl RemoteImage {
     }
 }
 
+/// Key in [`ImageDetails::metadata_key_value`] for the byte offset of the
+/// video embedded in a motion photo
+pub const KEY_MOTION_PHOTO_VIDEO_OFFSET: &str = "MotionPhotoVideoOffset";
+/// Key in [`ImageDetails::metadata_key_value`] for the length of the video
+/// embedded in a motion photo
+pub const KEY_MOTION_PHOTO_VIDEO_LENGTH: &str = "MotionPhotoVideoLength";
+/// Key in [`ImageDetails::metadata_key_value`] for the `EXPOSURE` of a
+/// Radiance HDR image
+///
+/// Color values divided by the exposure are the physical radiance.
+pub const KEY_RADIANCE_EXPOSURE: &str = "Exposure";
+/// Key in [`ImageDetails::metadata_key_value`] for the `PRIMARIES` of a
+/// Radiance HDR image
+///
+/// The value contains the chromaticities as `rx ry gx gy bx by wx wy`.
+pub const KEY_RADIANCE_PRIMARIES: &str = "Primaries";
+
 #[derive(DeserializeDict, SerializeDict, Type, Debug, Clone, Default)]
 #[zvariant(signature = "dict")]
 #[non_exhaustive]
@@ -74,15 +233,46 @@ pub struct ImageDetails {
     pub height: u32,
     /// Image dimensions in inch
     pub dimensions_inch: Option<(f64, f64)>,
+    /// Horizontal and vertical resolution in dots per inch
+    pub resolution_dpi: Option<(f64, f64)>,
     pub info_format_name: Option<String>,
     /// Textual description of the image dimensions
     pub info_dimensions_text: Option<String>,
+    /// Alpha channel presence as stored in the header
+    ///
+    /// `None` if the header doesn't allow to determine it. Then
+    /// [`FrameDetails::info_alpha_channel`] has to be checked after decoding.
+    pub info_alpha_channel: Option<bool>,
     pub metadata_exif: Option<BinaryData>,
     pub metadata_xmp: Option<BinaryData>,
     pub metadata_key_value: Option<BTreeMap<String, String>>,
     pub transformation_ignore_exif: bool,
     /// Explicit orientation. If `None` check Exif or XMP.
     pub transformation_orientation: Option<Orientation>,
+    /// Images stored in the container, if the format stores multiple images
+    ///
+    /// They can be selected via [`FrameRequest::sub_image`].
+    pub sub_images: Option<Vec<SubImageInfo>>,
+    /// Layers with their channels, if the format stores multiple layers
+    ///
+    /// They can be selected via [`FrameRequest::layer`].
+    pub layers: Option<Vec<LayerInfo>>,
+    /// Bit-exact original JPEG for losslessly recompressed JPEGs
+    ///
+    /// Only set if requested via [`InitializationDetails::reconstruct_jpeg`].
+    pub jpeg_reconstruction: Option<BinaryData>,
+    /// Width and height of the tiles for images that are stored in tiles
+    pub tile_size: Option<(u32, u32)>,
+    /// Risky or deprecated features used by the image
+    ///
+    /// These are no errors and the image is loaded regardless. Only set if
+    /// requested via [`InitializationDetails::collect_advisories`].
+    pub advisories: Option<Vec<String>>,
+    /// Background color of the animation canvas as specified by the image
+    ///
+    /// Non-premultiplied RGBA with 8 bits per channel. Frames are still
+    /// composited onto a transparent canvas.
+    pub animation_background_color: Option<[u8; 4]>,
 }
 
 impl ImageDetails {
@@ -91,18 +281,26 @@ impl ImageDetails {
             width,
             height,
             dimensions_inch: None,
+            resolution_dpi: None,
             info_dimensions_text: None,
             info_format_name: None,
+            info_alpha_channel: None,
             metadata_exif: None,
             metadata_xmp: None,
             metadata_key_value: None,
             transformation_ignore_exif: false,
             transformation_orientation: None,
+            sub_images: None,
+            layers: None,
+            jpeg_reconstruction: None,
+            tile_size: None,
+            advisories: None,
+            animation_background_color: None,
         }
     }
 }
 
-#[derive(Deserialize, Serialize, Type, Debug)]
+#[derive(Deserialize, Serialize, Type, Debug, Clone)]
 pub struct Frame {
     pub width: u32,
     pub height: u32,
@@ -131,11 +329,27 @@ pub struct FrameDetails {
     /// ICC color profile
     pub color_icc_profile: Option<BinaryData>,
     /// Coding-independent code points (HDR information)
+    ///
+    /// The value is evaluated for each frame individually. Loaders have to
+    /// set it for every frame of an animation.
     pub color_cicp: Option<[u8; 4]>,
+    /// CICP to use if the ICC profile can't be applied
+    ///
+    /// Some formats describe the color space in addition to the ICC profile,
+    /// like the NCLX box of HEIF images, or the `gAMA` and `cHRM` chunks of
+    /// PNGs. These are only used if the ICC profile is broken.
+    pub color_cicp_fallback: Option<[u8; 4]>,
     /// Bit depth per channel
     ///
     /// Only set if it can differ for the format
     pub info_bit_depth: Option<u8>,
+    /// Bit depth of each channel in the source data
+    ///
+    /// Only set if the channels have different depths, like for RGB565 or
+    /// RGB10A2. The depths are in the order R, G, B, and A. The memory format
+    /// of the frame has the same channels, but with a uniform depth.
+    /// [`FrameDetails::info_bit_depth`] is the largest depth.
+    pub channel_bit_depths: Option<Vec<u8>>,
     /// Image has alpha channel
     ///
     /// Only set if it can differ for the format
@@ -144,7 +358,64 @@ pub struct FrameDetails {
     ///
     /// Only set if it can differ for the format
     pub info_grayscale: Option<bool>,
+    /// Image is stored as CMYK
+    ///
+    /// The loader converts the image to RGB via the ICC profile.
+    pub info_cmyk: Option<bool>,
+    /// Animation frame is stored with this blend method
+    ///
+    /// Frames are always returned composited onto the full canvas. This only
+    /// describes the source file. When creating animations, the editor
+    /// stores the frame with this method.
+    pub info_animation_blend: Option<FrameBlend>,
+    /// Animation frame is stored with this dispose method
+    ///
+    /// See [`FrameDetails::info_animation_blend`].
+    pub info_animation_dispose: Option<FrameDispose>,
+    /// Position of the frame on the canvas when creating animations
+    ///
+    /// The canvas has the dimensions of the first frame. Loaders don't set
+    /// it since they return frames composited onto the full canvas.
+    pub animation_offset: Option<(u32, u32)>,
     pub n_frame: Option<u64>,
+    /// Time at which the animation frame is shown
+    ///
+    /// The sum of the delays of all previous frames. Loaders should compute
+    /// it from the exact delays instead of adding up rounded values. It
+    /// restarts at zero when the animation loops.
+    pub timestamp: Option<Duration>,
+    /// Risky or deprecated features used by this frame
+    ///
+    /// Only for features that are specific to the frame, the ones of the
+    /// whole image belong in [`ImageDetails::advisories`]. Only set if
+    /// requested via [`InitializationDetails::collect_advisories`].
+    pub advisories: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Serialize, Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[zvariant(signature = "s")]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+/// How an animation frame is drawn onto the canvas
+pub enum FrameBlend {
+    /// Replace the area of the frame, including transparency
+    Source,
+    /// Alpha-blend the frame over the canvas
+    Over,
+}
+
+#[derive(Deserialize, Serialize, Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[zvariant(signature = "s")]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+/// What happens to the area of an animation frame before the next frame
+pub enum FrameDispose {
+    /// Leave the frame on the canvas
+    None,
+    /// Clear the area to the transparent background
+    Background,
+    /// Restore the area to the canvas before the frame was drawn
+    Previous,
 }
 
 impl Frame {
@@ -184,6 +455,15 @@ impl Frame {
     }
 }
 
+#[derive(DeserializeDict, SerializeDict, Type, Debug, Clone, Default)]
+#[zvariant(signature = "dict")]
+#[non_exhaustive]
+/// Color profile of an image that is read without decoding a frame
+pub struct RemoteColorProfile {
+    /// ICC color profile
+    pub icc_profile: Option<BinaryData>,
+}
+
 /// Editable image
 #[derive(Deserialize, Serialize, Type, Debug, Clone)]
 pub struct RemoteEditableImage {
@@ -198,26 +478,44 @@ impl RemoteEditableImage {
     }
 }
 
-#[derive(DeserializeDict, SerializeDict, Type, Debug)]
+#[derive(DeserializeDict, SerializeDict, Type, Debug, Clone)]
 #[zvariant(signature = "dict")]
 #[non_exhaustive]
 pub struct NewImage {
     pub image_info: ImageDetails,
     pub frames: Vec<Frame>,
+    /// JPEG data to recompress losslessly instead of encoding the frames
+    pub jpeg_lossless: Option<BinaryData>,
+    /// Preview image to embed in the format's thumbnail structure
+    pub thumbnail: Option<Frame>,
 }
 
 impl NewImage {
     pub fn new(image_info: ImageDetails, frames: Vec<Frame>) -> Self {
-        Self { image_info, frames }
+        Self {
+            image_info,
+            frames,
+            jpeg_lossless: None,
+            thumbnail: None,
+        }
     }
 }
 
-#[derive(DeserializeDict, SerializeDict, Type, Debug, Default)]
+#[derive(DeserializeDict, SerializeDict, Type, Debug, Default, Clone)]
 #[zvariant(signature = "dict")]
 #[non_exhaustive]
 pub struct EncodingOptions {
     pub quality: Option<u8>,
     pub compression: Option<u8>,
+    /// Maximum size of the encoded image in bytes
+    ///
+    /// The highest quality that stays within this size is used.
+    pub target_size: Option<u64>,
+    /// Write a progressive JPEG or an interlaced PNG
+    ///
+    /// These images can be displayed with increasing detail while they are
+    /// still being loaded.
+    pub progressive: Option<bool>,
 }
 
 #[derive(DeserializeDict, SerializeDict, Type, Debug)]
@@ -191,4 +191,18 @@ mod test {
             .1;
         assert_eq!(x.as_slice(), &[255, 126, 0, 127, 127, 63, 0, 255]);
     }
+
+    #[test]
+    fn same_format_untouched() {
+        let (a, _) = std::os::unix::net::UnixStream::pair().unwrap();
+        let texture = BinaryData::from(unsafe { OwnedFd::from_raw_fd(a.into_raw_fd()) });
+        let data = vec![1, 2, 3, 4, 0, 0, 5, 6, 7, 8, 0, 0];
+        let mut frame = Frame::new(2, 2, crate::MemoryFormat::G16, texture).unwrap();
+        frame.stride = 6;
+        let (frame, x) =
+            change_memory_format(ImgBuf::Vec(data.clone()), frame, MemoryFormat::G16).unwrap();
+        assert_eq!(frame.memory_format, MemoryFormat::G16);
+        assert_eq!(frame.stride, 6);
+        assert_eq!(x.as_slice(), data);
+    }
 }
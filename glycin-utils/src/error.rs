@@ -1,5 +1,11 @@
 use std::any::Any;
 
+/// Message written to stderr when the sandbox blocks a syscall
+///
+/// The message is followed by the syscall name and the syscall number in
+/// parentheses, for example `read (0)`.
+pub const BLOCKED_SYSCALL_MESSAGE: &str = "glycin sandbox: Blocked syscall used: ";
+
 #[derive(zbus::DBusError, Debug, Clone)]
 #[zbus(prefix = "org.gnome.glycin.Error")]
 #[non_exhaustive]
@@ -172,3 +178,9 @@ impl std::fmt::Display for DimensionTooLargerError {
 }
 
 impl std::error::Error for DimensionTooLargerError {}
+
+impl From<DimensionTooLargerError> for std::io::Error {
+    fn from(err: DimensionTooLargerError) -> Self {
+        Self::new(std::io::ErrorKind::InvalidInput, err)
+    }
+}
@@ -11,6 +11,7 @@ pub struct Handler {
     pub default_bit_depth: Option<u8>,
     pub supports_two_alpha_modes: bool,
     pub supports_two_grayscale_modes: bool,
+    pub channel_bit_depths: Option<Vec<u8>>,
 }
 
 impl Handler {
@@ -38,10 +39,19 @@ impl Handler {
         self
     }
 
+    /// Bit depths of the channels if they differ, see
+    /// [`FrameDetails::channel_bit_depths`]
+    pub fn channel_bit_depths(mut self, channel_bit_depths: Option<Vec<u8>>) -> Self {
+        self.channel_bit_depths = channel_bit_depths;
+
+        self
+    }
+
     pub fn info(&self, decoder: &mut impl image::ImageDecoder) -> ImageDetails {
         let (width, height) = decoder.dimensions();
         let mut info = ImageDetails::new(width, height);
         info.info_format_name.clone_from(&self.format_name);
+        info.info_alpha_channel = Some(decoder.color_type().has_alpha());
 
         info
     }
@@ -116,6 +126,11 @@ impl Handler {
             }
         }
 
+        if let Some(channel_bit_depths) = &self.channel_bit_depths {
+            details.info_bit_depth = channel_bit_depths.iter().max().copied();
+            details.channel_bit_depths = Some(channel_bit_depths.clone());
+        }
+
         Ok(details)
     }
 }
@@ -0,0 +1,220 @@
+//! Incrementally read image data during initialization
+
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+
+use crate::safe_math::*;
+
+/// Number of bytes requested from the source at once
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Reader that only reads as much data from the source as needed
+///
+/// Loaders usually get the image data as a stream in
+/// [`LoaderImplementation::init`](crate::LoaderImplementation::init). Reading
+/// the complete stream before parsing the header delays returning the image
+/// details. This reader instead reads data on demand and keeps everything that
+/// was read, such that decoders can seek back. The remaining data can be read
+/// later, for example in
+/// [`LoaderImplementation::frame`](crate::LoaderImplementation::frame), via
+/// [`IncrementalReader::into_data`].
+///
+/// ```
+/// # use std::io::Read;
+/// # use glycin_utils::IncrementalReader;
+/// let mut reader = IncrementalReader::new(&[1, 2, 3, 4][..]);
+///
+/// let mut header = [0; 2];
+/// reader.read_exact(&mut header).unwrap();
+/// assert_eq!(header, [1, 2]);
+///
+/// assert_eq!(reader.into_data().unwrap(), vec![1, 2, 3, 4]);
+/// ```
+#[derive(Debug)]
+pub struct IncrementalReader<R: Read> {
+    source: R,
+    data: Vec<u8>,
+    position: usize,
+    source_finished: bool,
+}
+
+impl<R: Read> IncrementalReader<R> {
+    pub fn new(source: R) -> Self {
+        Self {
+            source,
+            data: Vec::new(),
+            position: 0,
+            source_finished: false,
+        }
+    }
+
+    /// Number of bytes that have been read from the source so far
+    pub fn n_bytes_read(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Data that has been read from the source so far
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Reads from the source until at least `len` bytes are available
+    ///
+    /// Returns all data that has been read so far. This is less than `len`
+    /// bytes if the source ends early. The read position is not changed.
+    pub fn data_up_to(&mut self, len: usize) -> io::Result<&[u8]> {
+        self.fill_to(len)?;
+        Ok(&self.data)
+    }
+
+    /// Reads the remaining data from the source and returns all data
+    pub fn into_data(mut self) -> io::Result<Vec<u8>> {
+        self.source.read_to_end(&mut self.data)?;
+        Ok(self.data)
+    }
+
+    /// Reads the remaining data from the source and returns a reader over all data
+    pub fn into_cursor(self) -> io::Result<io::Cursor<Vec<u8>>> {
+        let position = self.position;
+        let mut cursor = io::Cursor::new(self.into_data()?);
+        cursor.set_position(position.try_u64()?);
+        Ok(cursor)
+    }
+
+    fn fill_to(&mut self, len: usize) -> io::Result<()> {
+        while self.data.len() < len && !self.source_finished {
+            let old_len = self.data.len();
+            self.data.resize(old_len.sadd(CHUNK_SIZE)?, 0);
+
+            let result = loop {
+                match self
+                    .source
+                    .read(self.data.get_mut(old_len..).unwrap_or_default())
+                {
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    result => break result,
+                }
+            };
+
+            match result {
+                Ok(n) => {
+                    self.data.truncate(old_len.sadd(n)?);
+                    self.source_finished = n == 0;
+                }
+                Err(err) => {
+                    self.data.truncate(old_len);
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn available(&self) -> &[u8] {
+        self.data.get(self.position..).unwrap_or_default()
+    }
+}
+
+impl<R: Read> Read for IncrementalReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        if let (Some(target), Some(src)) = (buf.get_mut(..n), available.get(..n)) {
+            target.copy_from_slice(src);
+        }
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read> BufRead for IncrementalReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.available().is_empty() {
+            self.fill_to(self.position.sadd(1)?)?;
+        }
+
+        Ok(self.available())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position = self.position.saturating_add(amt).min(self.data.len());
+    }
+}
+
+impl<R: Read> Seek for IncrementalReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.try_u64()?.checked_add_signed(offset),
+            SeekFrom::End(offset) => {
+                // The end is only known after reading everything
+                self.source.read_to_end(&mut self.data)?;
+                self.source_finished = true;
+                self.data.len().try_u64()?.checked_add_signed(offset)
+            }
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek position"))?;
+
+        let position = position.try_usize()?;
+        self.fill_to(position)?;
+
+        if position > self.data.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Seek position beyond end of data",
+            ));
+        }
+
+        self.position = position;
+        position.try_u64().map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_incrementally() {
+        let source: Vec<u8> = (0..=255).cycle().take(CHUNK_SIZE * 2 + 10).collect();
+        let mut reader = IncrementalReader::new(source.as_slice());
+
+        let mut header = [0; 4];
+        reader.read_exact(&mut header).unwrap();
+        assert_eq!(header, [0, 1, 2, 3]);
+        assert_eq!(reader.n_bytes_read(), CHUNK_SIZE);
+
+        reader.seek(SeekFrom::Start(1)).unwrap();
+        reader.read_exact(&mut header).unwrap();
+        assert_eq!(header, [1, 2, 3, 4]);
+
+        assert_eq!(reader.into_data().unwrap(), source);
+    }
+
+    #[test]
+    fn seek() {
+        let source: Vec<u8> = (0..10).collect();
+        let mut reader = IncrementalReader::new(source.as_slice());
+
+        assert_eq!(reader.seek(SeekFrom::End(-2)).unwrap(), 8);
+        assert_eq!(reader.seek(SeekFrom::Current(-3)).unwrap(), 5);
+
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, [5, 6, 7, 8, 9]);
+
+        assert!(reader.seek(SeekFrom::Start(11)).is_err());
+        assert!(reader.seek(SeekFrom::Current(-20)).is_err());
+    }
+
+    #[test]
+    fn into_cursor() {
+        let source: Vec<u8> = (0..10).collect();
+        let mut reader = IncrementalReader::new(source.as_slice());
+        reader.seek(SeekFrom::Start(3)).unwrap();
+
+        let cursor = reader.into_cursor().unwrap();
+        assert_eq!(cursor.position(), 3);
+        assert_eq!(cursor.into_inner(), source);
+    }
+}
@@ -0,0 +1,64 @@
+//! Complete image data passed to a loader
+
+use std::io::{self, Read};
+use std::os::unix::net::UnixStream;
+
+use nix::fcntl::{fcntl, FcntlArg, SealFlag};
+
+/// Image data read from the stream passed to a loader
+///
+/// If the client passes a sealed memfd, for example, via `Loader::new_memfd`,
+/// the data is mapped into memory instead of copying it. Otherwise, the
+/// stream is read completely. In both cases, the stream's file descriptor is
+/// closed when [`InputData::read`] returns.
+pub enum InputData {
+    MMap(memmap::Mmap),
+    Vec(Vec<u8>),
+}
+
+impl InputData {
+    pub fn read(mut stream: UnixStream) -> io::Result<Self> {
+        // Only sealed memfds can be mapped, others could change or shrink
+        let required_seals = SealFlag::F_SEAL_SHRINK | SealFlag::F_SEAL_WRITE;
+        let sealed = fcntl(&stream, FcntlArg::F_GET_SEALS)
+            .is_ok_and(|seals| SealFlag::from_bits_truncate(seals).contains(required_seals));
+
+        if sealed {
+            // SAFETY: The seals ensure that the data doesn't change
+            if let Ok(mmap) = unsafe { memmap::Mmap::map(&stream) } {
+                return Ok(Self::MMap(mmap));
+            }
+        }
+
+        let mut data = Vec::new();
+        stream.read_to_end(&mut data)?;
+        Ok(Self::Vec(data))
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        match self {
+            Self::MMap(mmap) => mmap.as_ref(),
+            Self::Vec(v) => v.as_slice(),
+        }
+    }
+
+    pub fn into_vec(self) -> Vec<u8> {
+        match self {
+            Self::MMap(mmap) => mmap.to_vec(),
+            Self::Vec(v) => v,
+        }
+    }
+}
+
+impl std::ops::Deref for InputData {
+    type Target = [u8];
+    fn deref(&self) -> &Self::Target {
+        self.as_slice()
+    }
+}
+
+impl AsRef<[u8]> for InputData {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
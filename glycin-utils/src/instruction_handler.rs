@@ -38,20 +38,13 @@ impl DbusServer {
 
         log::info!("Loader {description} startup");
 
-        let mut dbus_fd_str = None;
-        let mut args = std::env::args().skip(1);
-        while let Some(arg) = args.next() {
-            match arg.as_str() {
-                "--dbus-fd" => {
-                    dbus_fd_str = args.next();
-                }
-
-                _ => {
-                    log::warn!("Stopping command line parsing at unknown argument: {arg:?}");
-                    break;
-                }
+        let dbus_fd_str = match Args::parse(std::env::args().skip(1)) {
+            Args::Version => {
+                println!("{description}");
+                std::process::exit(0);
             }
-        }
+            Args::Run { dbus_fd } => dbus_fd,
+        };
 
         let Some(dbus_fd_str) = dbus_fd_str else {
             log::error!("FD that facilitates the D-Bus connection not specified via --dbus-fd");
@@ -106,6 +99,39 @@ impl DbusServer {
     }
 }
 
+/// Command line arguments of loaders
+#[derive(Debug, PartialEq)]
+enum Args {
+    /// Print the loader version and exit
+    Version,
+    Run {
+        dbus_fd: Option<String>,
+    },
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Self {
+        let mut dbus_fd = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--dbus-fd" => {
+                    dbus_fd = args.next();
+                }
+                "--version" => {
+                    return Self::Version;
+                }
+
+                _ => {
+                    log::warn!("Stopping command line parsing at unknown argument: {arg:?}");
+                    break;
+                }
+            }
+        }
+
+        Self::Run { dbus_fd }
+    }
+}
+
 #[allow(non_camel_case_types)]
 extern "C" fn sigsys_handler(_: c_int, info: *mut siginfo_t, _: *mut c_void) {
     // Reimplement siginfo_t since the libc crate doesn't support _sigsys
@@ -130,7 +156,7 @@ extern "C" fn sigsys_handler(_: c_int, info: *mut siginfo_t, _: *mut c_void) {
 
     let name = libseccomp::ScmpSyscall::from(syscall).get_name().ok();
 
-    libc_eprint("glycin sandbox: Blocked syscall used: ");
+    libc_eprint(crate::BLOCKED_SYSCALL_MESSAGE);
     libc_eprint(&name.unwrap_or_else(|| String::from("Unknown Syscall")));
     libc_eprint(" (");
     libc_eprint(&syscall.to_string());
@@ -206,3 +232,34 @@ fn libc_eprint(s: &str) {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Args {
+        Args::parse(args.iter().map(|x| x.to_string()))
+    }
+
+    #[test]
+    fn args_version() {
+        assert_eq!(parse(&["--version"]), Args::Version);
+        assert_eq!(parse(&["--dbus-fd", "3", "--version"]), Args::Version);
+    }
+
+    #[test]
+    fn args_dbus_fd() {
+        assert_eq!(
+            parse(&["--dbus-fd", "3"]),
+            Args::Run {
+                dbus_fd: Some("3".into())
+            }
+        );
+        assert_eq!(parse(&[]), Args::Run { dbus_fd: None });
+        // Parsing stops at unknown arguments
+        assert_eq!(
+            parse(&["--unknown", "--version"]),
+            Args::Run { dbus_fd: None }
+        );
+    }
+}
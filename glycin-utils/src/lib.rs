@@ -22,6 +22,10 @@ pub mod error;
 pub mod image_rs;
 mod img_buf;
 #[cfg(feature = "loader-utils")]
+mod incremental_reader;
+#[cfg(feature = "loader-utils")]
+mod input_data;
+#[cfg(feature = "loader-utils")]
 pub mod instruction_handler;
 pub mod safe_math;
 
@@ -40,4 +44,8 @@ pub use glycin_common::{
 };
 pub use img_buf::ImgBuf;
 #[cfg(feature = "loader-utils")]
+pub use incremental_reader::IncrementalReader;
+#[cfg(feature = "loader-utils")]
+pub use input_data::InputData;
+#[cfg(feature = "loader-utils")]
 pub use instruction_handler::*;
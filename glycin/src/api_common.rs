@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+use std::os::fd::{AsRawFd, OwnedFd};
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -73,11 +75,127 @@ impl SandboxSelector {
     }
 }
 
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+/// Action for syscalls that are not allowed by the seccomp filter
+///
+/// Only applies to the [`SandboxMechanism::NativeSandbox`]. See
+/// [`PoolConfig::seccomp_default_action`](crate::PoolConfig::seccomp_default_action).
+pub enum SeccompAction {
+    #[default]
+    /// Terminate the process via `SIGSYS`
+    ///
+    /// The process reports the blocked syscall, which is available via
+    /// [`ErrorCtx::denied_syscalls`](crate::ErrorCtx::denied_syscalls). This
+    /// works with tools like valgrind.
+    Trap,
+    /// Kill the process immediately
+    ///
+    /// The blocked syscall can be logged by auditd, but is not reported by
+    /// the process itself. This doesn't work with tools like valgrind.
+    KillProcess,
+}
+
+impl SeccompAction {
+    /// Action from the `GLYCIN_SECCOMP_DEFAULT_ACTION` environment variable
+    ///
+    /// The value `KILL_PROCESS` selects [`Self::KillProcess`].
+    pub(crate) fn from_env() -> Self {
+        if std::env::var("GLYCIN_SECCOMP_DEFAULT_ACTION")
+            .ok()
+            .as_deref()
+            == Some("KILL_PROCESS")
+        {
+            Self::KillProcess
+        } else {
+            Self::Trap
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 #[non_exhaustive]
 pub enum ColorState {
     Srgb,
     Cicp(crate::Cicp),
+    /// Colors are returned as decoded without conversion
+    ///
+    /// Used for frames requested via
+    /// [`FrameRequest::raw`](crate::FrameRequest::raw). The ICC profile or
+    /// CICP of the image are still available via the frame details.
+    Unspecified,
+}
+
+/// Expected hash of the source data
+///
+/// See [`Loader::expect_content_hash`](crate::Loader::expect_content_hash).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentHash {
+    checksum_type: gio::glib::ChecksumType,
+    digest: String,
+}
+
+impl ContentHash {
+    /// Hash with the given algorithm and hexadecimal digest
+    ///
+    /// The digest is the format returned by tools like `sha256sum`. The case
+    /// of the digest is ignored.
+    pub fn new(checksum_type: gio::glib::ChecksumType, digest: &str) -> Self {
+        Self {
+            checksum_type,
+            digest: digest.to_ascii_lowercase(),
+        }
+    }
+
+    /// SHA-256 hash with the given hexadecimal digest
+    pub fn sha256(digest: &str) -> Self {
+        Self::new(gio::glib::ChecksumType::Sha256, digest)
+    }
+
+    pub fn checksum_type(&self) -> gio::glib::ChecksumType {
+        self.checksum_type
+    }
+
+    /// Hexadecimal digest in lowercase
+    pub fn digest(&self) -> &str {
+        &self.digest
+    }
+
+    /// Starts computing the hash of data that is read in chunks
+    pub(crate) fn hasher(&self) -> ContentHasher {
+        ContentHasher {
+            expected: self.clone(),
+            checksum: gio::glib::Checksum::new(self.checksum_type),
+        }
+    }
+}
+
+/// Hash of the source data while it's streamed to the loader
+pub(crate) struct ContentHasher {
+    expected: ContentHash,
+    checksum: Option<gio::glib::Checksum>,
+}
+
+impl ContentHasher {
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        if let Some(checksum) = &mut self.checksum {
+            checksum.update(data);
+        }
+    }
+
+    /// Returns [`Error::ContentHashMismatch`] if the data has a different hash
+    pub(crate) fn verify(self) -> Result<(), Error> {
+        let actual = self.checksum.and_then(|x| x.string()).unwrap_or_default();
+
+        if actual == self.expected.digest {
+            Ok(())
+        } else {
+            Err(Error::ContentHashMismatch {
+                expected: self.expected.digest,
+                actual,
+            })
+        }
+    }
 }
 
 pub(crate) struct RemoteProcessContext<P: ZbusProxy<'static> + 'static> {
@@ -114,6 +232,8 @@ impl GInputStreamSend {
 pub(crate) enum Source {
     File(gio::File),
     Stream(GInputStreamSend),
+    /// Sealed memfd that is passed to the loader without copying
+    Memfd(Arc<OwnedFd>),
     TransferredStream,
 }
 
@@ -132,10 +252,32 @@ impl Source {
                 .map(|x| x.upcast())
                 .map_err(Into::into),
             Self::Stream(stream) => Ok(stream.0.clone()),
+            Self::Memfd(memfd) => gio::File::for_path(memfd_path(memfd))
+                .read(Some(cancellable))
+                .map(|x| x.upcast())
+                .map_err(Into::into),
             Self::TransferredStream => Err(Error::TransferredStream),
         }
     }
 
+    /// Get another [`Source`] that reads the data from the start
+    ///
+    /// Only possible for files and memfds, since streams can only be read once.
+    pub fn reopen(&self) -> Option<Self> {
+        match self {
+            Self::File(file) => Some(Self::File(file.clone())),
+            Self::Memfd(memfd) => Some(Self::Memfd(memfd.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn memfd(&self) -> Option<Arc<OwnedFd>> {
+        match self {
+            Self::Memfd(memfd) => Some(memfd.clone()),
+            _ => None,
+        }
+    }
+
     /// Get a [`Source`] for sending to [`GFileWorker`]
     ///
     /// This will remove the stored stream from `self` to avoid it getting used
@@ -150,6 +292,32 @@ impl Source {
     }
 }
 
+/// Adds the seals against writing, shrinking, and growing if necessary
+pub(crate) fn seal_memfd(fd: &OwnedFd) -> Result<(), Error> {
+    let memfd = memfd::Memfd::try_from_fd(fd.try_clone()?).map_err(|_| Error::NotMemfd)?;
+
+    let required_seals = [
+        memfd::FileSeal::SealShrink,
+        memfd::FileSeal::SealGrow,
+        memfd::FileSeal::SealWrite,
+    ];
+
+    let seals = memfd.seals()?;
+    if !required_seals.iter().all(|seal| seals.contains(seal)) {
+        if seals.contains(&memfd::FileSeal::SealSeal) {
+            return Err(Error::MemfdNotSealable);
+        }
+        memfd.add_seals(&required_seals)?;
+    }
+
+    Ok(())
+}
+
+/// Path that opens the memfd with a separate file offset
+pub(crate) fn memfd_path(memfd: &OwnedFd) -> PathBuf {
+    PathBuf::from(format!("/proc/self/fd/{}", memfd.as_raw_fd()))
+}
+
 #[derive(Debug)]
 pub(crate) struct ProcessBasics<T> {
     pub mime_type: MimeType,
@@ -190,19 +358,33 @@ impl GetConfig for ImageEditorConfig {
     }
 }
 
+/// Guesses the mime type and selects the config entry
+///
+/// The mime type is only guessed if `mime_type` is `None`. The entry is
+/// obtained via `config_entry`, which usually is
+/// [`GetConfig::config_entry`], but can return a different entry to override
+/// the config files.
 pub(crate) async fn spin_up<T: GetConfig + Clone>(
     source: Source,
+    mime_type: Option<MimeType>,
     use_expose_base_dir: bool,
+    read_buffer_size: usize,
+    content_hash: Option<ContentHash>,
     cancellable: &gio::Cancellable,
     sandbox_selector: &SandboxSelector,
+    config_entry: impl FnOnce(&Config, &MimeType) -> Result<T, Error>,
 ) -> Result<ProcessBasics<T>, Error> {
     let file = source.file();
 
-    let g_file_worker: GFileWorker = GFileWorker::spawn(source, cancellable.clone());
-    let mime_type = guess_mime_type(&g_file_worker).await?;
+    let g_file_worker: GFileWorker =
+        GFileWorker::spawn(source, read_buffer_size, content_hash, cancellable.clone());
+    let mime_type = match mime_type {
+        Some(mime_type) => mime_type,
+        None => guess_mime_type(&g_file_worker).await?,
+    };
 
     let config = config::Config::cached().await;
-    let config_entry = T::config_entry(config, &mime_type)?.clone().clone();
+    let config_entry = config_entry(config, &mime_type)?;
 
     let base_dir = if use_expose_base_dir && config_entry.expose_base_dir() {
         file.and_then(|x| x.parent()).and_then(|x| x.path())
@@ -227,12 +409,22 @@ pub(crate) async fn spin_up_editor<'a>(
     cancellable: &gio::Cancellable,
     sandbox_selector: &SandboxSelector,
 ) -> Result<RemoteProcessContext<EditorProxy<'static>>, Error> {
-    let process_basics =
-        spin_up::<ImageEditorConfig>(source, false, cancellable, sandbox_selector).await?;
+    let process_basics = spin_up(
+        source,
+        None,
+        false,
+        pool.read_buffer_size(),
+        None,
+        cancellable,
+        sandbox_selector,
+        |config, mime_type| ImageEditorConfig::config_entry(config, mime_type).cloned(),
+    )
+    .await?;
 
     let (process, usage_tracker) = pool
         .get_editor(
             process_basics.config_entry,
+            &process_basics.mime_type,
             process_basics.sandbox_mechanism,
             process_basics.base_dir,
             cancellable,
@@ -258,7 +450,13 @@ pub(crate) async fn spin_up_encoder<'a>(
     let sandbox_mechanism = sandbox_selector.determine_sandbox_mechanism().await;
 
     let (process, usage_tracker) = pool
-        .get_editor(config_entry.clone(), sandbox_mechanism, None, cancellable)
+        .get_editor(
+            config_entry.clone(),
+            &mime_type,
+            sandbox_mechanism,
+            None,
+            cancellable,
+        )
         .await?;
 
     Ok(RemoteProcessContext {
@@ -272,20 +470,47 @@ pub(crate) async fn spin_up_encoder<'a>(
 
 pub(crate) async fn spin_up_loader<'a>(
     source: Source,
+    mime_type: Option<MimeType>,
     use_expose_base_dir: bool,
+    font_dir: Option<PathBuf>,
+    loader_overrides: &BTreeMap<MimeType, PathBuf>,
     pool: Arc<Pool>,
+    content_hash: Option<ContentHash>,
     cancellable: &gio::Cancellable,
     sandbox_selector: &SandboxSelector,
 ) -> Result<RemoteProcessContext<LoaderProxy<'static>>, Error> {
-    let process_basics =
-        spin_up(source, use_expose_base_dir, cancellable, sandbox_selector).await?;
+    let process_basics: ProcessBasics<ImageLoaderConfig> = spin_up(
+        source,
+        mime_type,
+        use_expose_base_dir,
+        pool.read_buffer_size(),
+        content_hash,
+        cancellable,
+        sandbox_selector,
+        |config, mime_type| match loader_overrides.get(mime_type) {
+            Some(exec) => {
+                // Keep the options of the configured loader if there is one
+                let configured = config.loader(mime_type).ok();
+                Ok(ImageLoaderConfig {
+                    exec: exec.clone(),
+                    expose_base_dir: configured.is_some_and(|x| x.expose_base_dir),
+                    fontconfig: configured.is_some_and(|x| x.fontconfig),
+                    max_dimensions: configured.and_then(|x| x.max_dimensions),
+                })
+            }
+            None => config.loader(mime_type).cloned(),
+        },
+    )
+    .await?;
 
     let (process, usage_tracker) = pool
         .clone()
         .get_loader(
             process_basics.config_entry,
+            &process_basics.mime_type,
             process_basics.sandbox_mechanism,
             process_basics.base_dir,
+            font_dir,
             cancellable,
         )
         .await?;
@@ -326,3 +551,39 @@ pub(crate) async fn guess_mime_type(gfile_worker: &GFileWorker) -> Result<MimeTy
 
     mime_type.map(|x| MimeType::new(x.to_string()))
 }
+
+/// Other mime types that might fit the data if [`guess_mime_type`] is wrong
+///
+/// The candidates are the type guessed only from the content and the type
+/// guessed only from the file name. Only types for which a loader is available
+/// are returned.
+pub(crate) async fn fallback_mime_types(
+    gfile_worker: &GFileWorker,
+    loader_overrides: &BTreeMap<MimeType, PathBuf>,
+) -> Result<Vec<MimeType>, Error> {
+    let head = gfile_worker.head().await?;
+    let guessed = guess_mime_type(gfile_worker).await.ok();
+    let config = Config::cached().await;
+
+    let mut content_types = vec![gio::content_type_guess(None::<String>, head.as_slice()).0];
+    if let Some(filename) = gfile_worker.file().and_then(|x| x.basename()) {
+        content_types.push(gio::content_type_guess(Some(filename), &[0u8; 0][..]).0);
+    }
+
+    let mut mime_types = Vec::new();
+    for content_type in content_types {
+        let Some(mime_type) = gio::content_type_get_mime_type(&content_type) else {
+            continue;
+        };
+        let mime_type = MimeType::new(mime_type.to_string());
+
+        let available =
+            loader_overrides.contains_key(&mime_type) || config.loader(&mime_type).is_ok();
+
+        if available && Some(&mime_type) != guessed.as_ref() && !mime_types.contains(&mime_type) {
+            mime_types.push(mime_type);
+        }
+    }
+
+    Ok(mime_types)
+}
@@ -1,20 +1,21 @@
 use std::collections::BTreeMap;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use glib::object::IsA;
 use glib::prelude::*;
 use glycin_common::{BinaryData, MemoryFormatInfo};
-use glycin_utils::{DimensionTooLargerError, MemoryFormat};
+use glycin_utils::{DimensionTooLargerError, FrameBlend, FrameDispose, MemoryFormat};
 
 use crate::config::{Config, ImageEditorConfig};
 use crate::error::ResultExt;
 use crate::pool::Pool;
-use crate::{spin_up_encoder, Error, ErrorCtx, MimeType, SandboxSelector};
+use crate::{spin_up_encoder, Error, ErrorCtx, Frame, MimeType, SandboxSelector};
 
 #[derive(Debug)]
 pub struct Creator {
     mime_type: MimeType,
-    config: ImageEditorConfig,
+    pub(crate) config: ImageEditorConfig,
     pool: Arc<Pool>,
     pub(crate) cancellable: gio::Cancellable,
     pub(crate) sandbox_selector: SandboxSelector,
@@ -22,11 +23,13 @@ pub struct Creator {
     new_image: glycin_utils::NewImage,
 
     new_frames: Vec<Arc<NewFrame>>,
+    jpeg_lossless: Option<Vec<u8>>,
+    thumbnail: Option<NewFrame>,
 }
 
 static_assertions::assert_impl_all!(Creator: Send, Sync);
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FeatureNotSupported;
 
 impl std::fmt::Display for FeatureNotSupported {
@@ -51,6 +54,8 @@ impl Creator {
             encoding_options: glycin_utils::EncodingOptions::default(),
             new_image: glycin_utils::NewImage::new(glycin_utils::ImageDetails::new(1, 1), vec![]),
             new_frames: vec![],
+            jpeg_lossless: None,
+            thumbnail: None,
         })
     }
 
@@ -134,6 +139,50 @@ impl Creator {
         Ok(new_frame)
     }
 
+    /// Add a frame that has been loaded via a [`Loader`](crate::Loader)
+    ///
+    /// Dimensions, memory format, and pixel data are taken from the frame.
+    /// The frame's stride is removed and multi-byte channels are converted
+    /// to native byte order if necessary.
+    ///
+    /// If the format supports it, the ICC profile is stored as well, unless
+    /// the loader already converted the colors with it. This is the case for
+    /// [raw](crate::FrameRequest::raw) frames and profiles that couldn't be
+    /// applied. For animations, the delay is copied. The blend and dispose
+    /// methods are not copied since loaded frames are already composited onto
+    /// the full canvas. The orientation is not stored since loaders already
+    /// return oriented frames, except for raw frames or if
+    /// [`Loader::apply_transformations`](crate::Loader::apply_transformations)
+    /// is disabled.
+    pub fn add_frame_from(&mut self, frame: &Frame) -> Result<Arc<NewFrame>, Error> {
+        let mut texture = frame.buf_slice().to_vec();
+        if !frame.byte_order().is_native() && frame.memory_format().channel_type().size() == 2 {
+            texture.chunks_exact_mut(2).for_each(|x| x.swap(0, 1));
+        }
+
+        let new_frame = self.add_frame_with_stride(
+            frame.width(),
+            frame.height(),
+            frame.stride(),
+            frame.memory_format(),
+            texture,
+        )?;
+
+        let details = frame.details();
+
+        if self.config.creator_color_icc_profile && !frame.icc_profile_applied {
+            if let Some(icc_profile) = details.color_icc_profile() {
+                let _ = new_frame.set_color_icc_profile(Some(icc_profile.get_full()?));
+            }
+        }
+
+        if self.config.creator_animation && frame.delay().is_some() {
+            let _ = new_frame.set_delay(frame.delay());
+        }
+
+        Ok(new_frame)
+    }
+
     /// Encode an image
     pub async fn create(self) -> Result<EncodedImage, ErrorCtx> {
         let process_context = spin_up_encoder(
@@ -155,6 +204,18 @@ impl Creator {
                 .push((frame).frame().err_no_context(&self.cancellable)?);
         }
 
+        if let Some(thumbnail) = self.thumbnail {
+            new_image.thumbnail = Some(thumbnail.frame().err_no_context(&self.cancellable)?);
+        }
+
+        if let Some(jpeg) = self.jpeg_lossless {
+            new_image.jpeg_lossless = Some(
+                BinaryData::from_data(jpeg)
+                    .map_err(Error::from)
+                    .err_no_context(&self.cancellable)?,
+            );
+        }
+
         Ok(EncodedImage::new(
             process
                 .create(&self.mime_type, new_image, self.encoding_options)
@@ -185,6 +246,39 @@ impl Creator {
         Ok(())
     }
 
+    /// Set the maximum size of the encoded image in bytes
+    ///
+    /// The image is encoded with the highest quality that results in at most
+    /// `bytes` bytes. The quality is found by encoding the image multiple
+    /// times. A quality set via
+    /// [`set_encoding_quality`](Self::set_encoding_quality) is used as the
+    /// highest quality. Creating the image fails if even the lowest quality
+    /// exceeds the target size.
+    ///
+    /// This is only supported for lossy formats.
+    pub fn set_target_size(&mut self, bytes: u64) -> Result<(), FeatureNotSupported> {
+        if !self.config.creator_encoding_target_size {
+            return Err(FeatureNotSupported);
+        }
+
+        self.encoding_options.target_size = Some(bytes);
+        Ok(())
+    }
+
+    /// Write a progressive JPEG or an interlaced PNG
+    ///
+    /// Viewers can show these images with increasing detail while they are
+    /// still being loaded, which is useful for slow connections. PNGs use the
+    /// Adam7 interlacing. The encoded images are usually a bit larger.
+    pub fn set_progressive(&mut self, progressive: bool) -> Result<(), FeatureNotSupported> {
+        if !self.config.creator_progressive {
+            return Err(FeatureNotSupported);
+        }
+
+        self.encoding_options.progressive = Some(progressive);
+        Ok(())
+    }
+
     pub fn set_metadata_key_value(
         &mut self,
         key_value: BTreeMap<String, String>,
@@ -217,6 +311,70 @@ impl Creator {
         Ok(())
     }
 
+    /// Set the physical resolution of the image
+    ///
+    /// The horizontal and vertical resolution in dots per inch (DPI) is stored
+    /// in the format's resolution fields, like the JFIF header, the PNG
+    /// `pHYs` chunk, or the TIFF resolution tags. Both values have to be
+    /// positive and finite.
+    pub fn set_resolution(&mut self, dpi_x: f64, dpi_y: f64) -> Result<(), Error> {
+        if !self.config.creator_resolution {
+            return Err(FeatureNotSupported.into());
+        }
+
+        let is_valid = |dpi: f64| dpi.is_finite() && dpi > 0.;
+        if !is_valid(dpi_x) || !is_valid(dpi_y) {
+            return Err(Error::InvalidResolution { dpi_x, dpi_y });
+        }
+
+        self.new_image.image_info.resolution_dpi = Some((dpi_x, dpi_y));
+        Ok(())
+    }
+
+    /// Losslessly recompress an existing JPEG
+    ///
+    /// Instead of encoding frames, the JPEG data is stored such that the
+    /// original JPEG can be reconstructed bit-exact from the created image.
+    /// For JPEG XL, the reconstructed JPEG is available via
+    /// [`Loader::reconstruct_jpeg`](crate::Loader::reconstruct_jpeg).
+    pub fn add_jpeg_lossless(&mut self, jpeg_bytes: Vec<u8>) -> Result<(), FeatureNotSupported> {
+        if !self.config.creator_jpeg_lossless {
+            return Err(FeatureNotSupported);
+        }
+
+        self.jpeg_lossless = Some(jpeg_bytes);
+        Ok(())
+    }
+
+    /// Embed a preview image
+    ///
+    /// The thumbnail is stored in the format's thumbnail structure, like the
+    /// Exif thumbnail for JPEG or a thumbnail item for HEIF and AVIF. This
+    /// allows viewers to show a preview without decoding the full image. The
+    /// thumbnail should already be scaled down, since formats limit its size.
+    /// For JPEG, the encoded thumbnail has to fit into the Exif segment of
+    /// 64 KiB.
+    pub fn set_thumbnail(
+        &mut self,
+        width: u32,
+        height: u32,
+        memory_format: MemoryFormat,
+        texture: Vec<u8>,
+    ) -> Result<(), FeatureNotSupported> {
+        if !self.config.creator_thumbnail {
+            return Err(FeatureNotSupported);
+        }
+
+        self.thumbnail = Some(NewFrame::new(
+            self.config.clone(),
+            width,
+            height,
+            memory_format,
+            texture,
+        ));
+        Ok(())
+    }
+
     /// Sets the method by which the sandbox mechanism is selected.
     ///
     /// The default without calling this function is [`SandboxSelector::Auto`].
@@ -240,7 +398,10 @@ pub struct NewFrame {
     //stride: Option<u32>,
     memory_format: MemoryFormat,
     texture: Vec<u8>,
-    //delay: Option<Duration>,
+    delay: Mutex<Option<Duration>>,
+    blend: Mutex<Option<FrameBlend>>,
+    dispose: Mutex<Option<FrameDispose>>,
+    offset: Mutex<Option<(u32, u32)>>,
     details: glycin_utils::FrameDetails,
     icc_profile: Mutex<Option<Vec<u8>>>,
 }
@@ -260,7 +421,10 @@ impl NewFrame {
             memory_format,
             texture,
             //stride: None,
-            //delay: None,
+            delay: Default::default(),
+            blend: Default::default(),
+            dispose: Default::default(),
+            offset: Default::default(),
             details: Default::default(),
             icc_profile: Default::default(),
         }
@@ -278,12 +442,68 @@ impl NewFrame {
         Ok(())
     }
 
+    /// Set the duration to show the frame in animations
+    pub fn set_delay(&self, delay: Option<Duration>) -> Result<(), FeatureNotSupported> {
+        if !self.config.creator_animation {
+            return Err(FeatureNotSupported);
+        }
+
+        *self.delay.lock().unwrap() = delay;
+        Ok(())
+    }
+
+    /// Set how the frame is drawn onto the canvas in animations
+    ///
+    /// By default, GIF frames are drawn over the canvas and APNG frames
+    /// replace their area. GIF doesn't support [`FrameBlend::Source`].
+    pub fn set_blend(&self, blend: Option<FrameBlend>) -> Result<(), FeatureNotSupported> {
+        if !self.config.creator_animation {
+            return Err(FeatureNotSupported);
+        }
+
+        *self.blend.lock().unwrap() = blend;
+        Ok(())
+    }
+
+    /// Set what happens to the frame's area before the next frame is drawn
+    ///
+    /// By default, GIF frames are disposed to the background and APNG frames
+    /// are left on the canvas.
+    pub fn set_dispose(&self, dispose: Option<FrameDispose>) -> Result<(), FeatureNotSupported> {
+        if !self.config.creator_animation {
+            return Err(FeatureNotSupported);
+        }
+
+        *self.dispose.lock().unwrap() = dispose;
+        Ok(())
+    }
+
+    /// Set the position of the frame on the canvas in animations
+    ///
+    /// The canvas has the dimensions of the first frame, which has to be at
+    /// the origin. Together with [`set_blend`](Self::set_blend) and
+    /// [`set_dispose`](Self::set_dispose), this allows to store frames that
+    /// only cover the changed part of the canvas. Creating the image fails if
+    /// a frame doesn't fit onto the canvas.
+    pub fn set_offset(&self, x: u32, y: u32) -> Result<(), FeatureNotSupported> {
+        if !self.config.creator_animation {
+            return Err(FeatureNotSupported);
+        }
+
+        *self.offset.lock().unwrap() = Some((x, y));
+        Ok(())
+    }
+
     fn frame(&self) -> Result<glycin_utils::Frame, Error> {
         let texture = BinaryData::from_data(&self.texture)?;
         let mut frame =
             glycin_utils::Frame::new(self.width, self.height, self.memory_format, texture)?;
 
         frame.details = self.details.clone();
+        frame.delay = (*self.delay.lock().unwrap()).into();
+        frame.details.info_animation_blend = *self.blend.lock().unwrap();
+        frame.details.info_animation_dispose = *self.dispose.lock().unwrap();
+        frame.details.animation_offset = *self.offset.lock().unwrap();
 
         if let Some(icc_profile) = self.icc_profile.lock().unwrap().as_ref() {
             let icc_profile = BinaryData::from_data(icc_profile)?;
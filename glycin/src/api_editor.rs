@@ -22,6 +22,7 @@ pub struct Editor {
     pool: Arc<Pool>,
     cancellable: gio::Cancellable,
     pub(crate) sandbox_selector: SandboxSelector,
+    preserve_memory_format: bool,
 }
 
 static_assertions::assert_impl_all!(Editor: Send, Sync);
@@ -34,6 +35,7 @@ impl Editor {
             pool: Pool::global(),
             cancellable: gio::Cancellable::new(),
             sandbox_selector: SandboxSelector::default(),
+            preserve_memory_format: false,
         }
     }
 
@@ -55,20 +57,20 @@ impl Editor {
             .edit(
                 &process_context.g_file_worker.unwrap(),
                 &process_context.mime_type,
+                self.preserve_memory_format,
             )
             .await
             .err_context(&process, &self.cancellable)?;
 
-        self.cancellable.connect_cancelled(glib::clone!(
-            #[strong(rename_to=process)]
-            process_context.process,
-            #[strong(rename_to=path)]
-            editable_image.edit_request,
-            move |_| {
+        // Only a weak reference, since the cancellable can outlive the image
+        let process = Arc::downgrade(&process_context.process);
+        let path = editable_image.edit_request.clone();
+        self.cancellable.connect_cancelled(move |_| {
+            if let Some(process) = process.upgrade() {
                 tracing::debug!("Terminating loader");
                 crate::util::spawn_detached(process.use_().done(path))
             }
-        ));
+        });
 
         Ok(EditableImage {
             _active_sandbox_mechanism: process_context.sandbox_mechanism,
@@ -88,6 +90,18 @@ impl Editor {
         self
     }
 
+    /// Keep the memory format of the image when applying operations.
+    ///
+    /// By default, editors might convert the image data, for example from
+    /// grayscale to RGB, before encoding the edited image. With this option
+    /// enabled, operations like clipping or rotating keep the channels and
+    /// bit depth of the original image exactly. If that is not possible, an
+    /// error is returned instead.
+    pub fn preserve_memory_format(&mut self, preserve_memory_format: bool) -> &mut Self {
+        self.preserve_memory_format = preserve_memory_format;
+        self
+    }
+
     /// Set [`Cancellable`](gio::Cancellable) to cancel any editing operations.
     pub fn cancellable(&mut self, cancellable: impl IsA<gio::Cancellable>) -> &mut Self {
         self.cancellable = cancellable.upcast();
@@ -110,7 +124,7 @@ impl Drop for EditableImage {
     fn drop(&mut self) {
         self.process.use_().done_background(self);
         *self.editor_alive.lock().unwrap() = Arc::new(());
-        spawn_detached(self.editor.pool.clone().clean_loaders());
+        spawn_detached(self.editor.pool.clone().clean_processes());
     }
 }
 
@@ -145,6 +159,71 @@ impl EditableImage {
         })
     }
 
+    /// Apply operations to the image with both potentially sparse and complete
+    /// results.
+    ///
+    /// Works like [`apply_sparse()`](Self::apply_sparse), but if the changes
+    /// are sparse, the complete image data is additionally created from the
+    /// original file and the byte changes. This avoids a second editor call
+    /// if it turns out that the sparse changes can't be used, for example,
+    /// because the image has to be written to a different location.
+    pub async fn apply(self, operations: &Operations) -> Result<EditResult, ErrorCtx> {
+        let process = self.process.use_();
+
+        let editor_output = process
+            .editor_apply_sparse(operations, &self)
+            .await
+            .err_context(&process, &self.editor.cancellable)?;
+
+        let lossless = editor_output.info.lossless;
+
+        match SparseEdit::try_from(editor_output).err_no_context(&self.editor.cancellable)? {
+            SparseEdit::Sparse(byte_changes) => {
+                let data = self
+                    .apply_byte_changes(&byte_changes)
+                    .await
+                    .err_no_context(&self.editor.cancellable)?;
+
+                Ok(EditResult {
+                    byte_changes: Some(byte_changes),
+                    complete: Edit::new(data, lossless),
+                })
+            }
+            SparseEdit::Complete(data) => Ok(EditResult {
+                byte_changes: None,
+                complete: Edit::new(data, lossless),
+            }),
+        }
+    }
+
+    /// Original image data with the byte changes applied
+    async fn apply_byte_changes(&self, byte_changes: &ByteChanges) -> Result<BinaryData, Error> {
+        let file = self.editor.source.file().ok_or(Error::TransferredStream)?;
+        let cancellable = self.editor.cancellable.clone();
+        let byte_changes = byte_changes.clone();
+
+        util::spawn_blocking(move || {
+            let (data, _) = file.load_contents(Some(&cancellable))?;
+            let mut data = data.to_vec();
+
+            for change in byte_changes.changes {
+                let byte = usize::try_from(change.offset)
+                    .ok()
+                    .and_then(|offset| data.get_mut(offset))
+                    .ok_or_else(|| {
+                        Error::RemoteError(glycin_utils::RemoteError::InternalLoaderError(format!(
+                            "Byte change at offset {} is outside of the image",
+                            change.offset
+                        )))
+                    })?;
+                *byte = change.new_value;
+            }
+
+            Ok(BinaryData::from_data(data)?)
+        })
+        .await
+    }
+
     /// List all configured image editors
     pub async fn supported_formats() -> BTreeMap<MimeType, config::ImageEditorConfig> {
         let config = config::Config::cached().await;
@@ -175,6 +254,12 @@ pub struct Edit {
 }
 
 impl Edit {
+    fn new(data: BinaryData, lossless: bool) -> Self {
+        let mut inner = CompleteEditorOutput::new(data);
+        inner.info.lossless = lossless;
+        Self { inner }
+    }
+
     pub fn data(&self) -> BinaryData {
         self.inner.data.clone()
     }
@@ -184,6 +269,50 @@ impl Edit {
     }
 }
 
+#[derive(Debug)]
+/// Result of [`EditableImage::apply()`]
+///
+/// Always contains the complete image data. If the operations could be
+/// applied by only changing a few bytes, the byte changes are available as
+/// well.
+pub struct EditResult {
+    byte_changes: Option<ByteChanges>,
+    complete: Edit,
+}
+
+impl EditResult {
+    /// Returns `true` if the operations can be applied via byte changes
+    pub fn is_sparse(&self) -> bool {
+        self.byte_changes.is_some()
+    }
+
+    /// Byte changes that apply the operations to the original file
+    pub fn byte_changes(&self) -> Option<&ByteChanges> {
+        self.byte_changes.as_ref()
+    }
+
+    /// Complete image data with the operations applied
+    pub fn complete(&self) -> &Edit {
+        &self.complete
+    }
+
+    /// Apply the sparse changes if available.
+    ///
+    /// See [`SparseEdit::apply_to()`]. If [`EditOutcome::Unchanged`] is
+    /// returned, the [`complete()`](Self::complete()) data has to be written
+    /// instead.
+    pub async fn apply_to(&self, file: gio::File) -> Result<EditOutcome, Error> {
+        match &self.byte_changes {
+            Some(byte_changes) => {
+                SparseEdit::Sparse(byte_changes.clone())
+                    .apply_to(file)
+                    .await
+            }
+            None => Ok(EditOutcome::Unchanged),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 #[must_use]
 /// Whether an image could be changed via the chosen method.
@@ -0,0 +1,202 @@
+use gio::prelude::*;
+
+use crate::api_common::{guess_mime_type, GetConfig, Source};
+use crate::config::{Config, ImageLoaderConfig};
+use crate::dbus::GFileWorker;
+use crate::error::ResultExt;
+use crate::{util, Error, ErrorCtx, Loader, MimeType};
+
+/// Get the width and height of an image with minimal work
+///
+/// For PNG, GIF, and QOI files, the dimensions are read from the beginning
+/// of the file without spawning a loader. Only the first bytes of the file
+/// are read in this case. For PNGs, the chunk headers are checked for Exif
+/// and XMP data that might change the orientation. For all other formats,
+/// for PNGs with such metadata, and for streams, this falls back to
+/// [`Loader::load`].
+///
+/// As with [`Image::details`](crate::Image::details), the dimensions are
+/// returned after applying the image orientation.
+pub async fn image_size(loader: Loader) -> Result<(u32, u32), ErrorCtx> {
+    let cancellable = loader.cancellable.clone();
+
+    if let (Some(source), Some(chunks_source)) = (loader.source.reopen(), loader.source.reopen()) {
+        let g_file_worker = GFileWorker::spawn(
+            source,
+            loader.pool.read_buffer_size(),
+            None,
+            cancellable.clone(),
+        );
+
+        match header_size(&g_file_worker, chunks_source, &cancellable).await {
+            Ok(Some(size)) => return Ok(size),
+            Ok(None) => {}
+            Err(err) => return Err(err).err_no_context(&cancellable),
+        }
+    }
+
+    let details = loader.load().await?.details();
+
+    Ok((details.width(), details.height()))
+}
+
+/// Dimensions parsed from the head of the file, if the format supports it
+async fn header_size(
+    g_file_worker: &GFileWorker,
+    source: Source,
+    cancellable: &gio::Cancellable,
+) -> Result<Option<(u32, u32)>, Error> {
+    let head = g_file_worker.head().await?;
+    let mime_type = guess_mime_type(g_file_worker).await?;
+
+    // Fail like `load()` for formats without a loader
+    let config = Config::cached().await;
+    ImageLoaderConfig::config_entry(config, &mime_type)?;
+
+    let size = match mime_type {
+        x if x == MimeType::PNG => {
+            let cancellable = cancellable.clone();
+            util::spawn_blocking(move || png_size(source_reader(&head, &source, &cancellable)))
+                .await
+        }
+        x if x == MimeType::GIF => gif_size(&head),
+        x if x == MimeType::QOI => qoi_size(&head),
+        _ => None,
+    };
+
+    // Leave reporting errors for invalid dimensions to the loader
+    Ok(size.filter(|(width, height)| *width > 0 && *height > 0))
+}
+
+/// Keyword of `iTXt` chunks with XMP data, including the null separator
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp\0";
+
+/// Dimensions from the IHDR chunk
+///
+/// Returns `None` if the file contains an `eXIf` chunk or XMP data that might
+/// change the orientation, or if it ends before the `IEND` chunk. Only the
+/// chunk headers are read via `read_at`.
+fn png_size(mut read_at: impl FnMut(u64, &mut [u8]) -> Option<()>) -> Option<(u32, u32)> {
+    let mut ihdr = [0; 8];
+    read_at(16, &mut ihdr)?;
+    let size = (be_u32(&ihdr, 0)?, be_u32(&ihdr, 4)?);
+
+    let mut pos = 8_u64;
+    loop {
+        let mut header = [0; 8];
+        read_at(pos, &mut header)?;
+        let length = be_u32(&header, 0)?;
+
+        match header.get(4..8)? {
+            b"eXIf" => return None,
+            b"iTXt" if usize::try_from(length).ok()? >= PNG_XMP_KEYWORD.len() => {
+                let mut keyword = [0; PNG_XMP_KEYWORD.len()];
+                read_at(pos.checked_add(8)?, &mut keyword)?;
+                if keyword == PNG_XMP_KEYWORD {
+                    return None;
+                }
+            }
+            b"IEND" => return Some(size),
+            _ => {}
+        }
+
+        // Length, type, data, and CRC
+        pos = pos.checked_add(length.into())?.checked_add(12)?;
+    }
+}
+
+/// Reads from `head` or from the source for data beyond it
+///
+/// Returns `None` if the data can't be read completely.
+fn source_reader<'a>(
+    head: &'a [u8],
+    source: &'a Source,
+    cancellable: &'a gio::Cancellable,
+) -> impl FnMut(u64, &mut [u8]) -> Option<()> + 'a {
+    let mut stream = None;
+
+    move |offset, buf| {
+        let start = usize::try_from(offset).ok()?;
+        if let Some(data) = head.get(start..start.checked_add(buf.len())?) {
+            buf.copy_from_slice(data);
+            return Some(());
+        }
+
+        if stream.is_none() {
+            stream = source
+                .to_stream(cancellable)
+                .ok()?
+                .downcast::<gio::FileInputStream>()
+                .ok();
+        }
+        let stream = stream.as_ref()?;
+
+        stream
+            .seek(
+                i64::try_from(offset).ok()?,
+                glib::SeekType::Set,
+                Some(cancellable),
+            )
+            .ok()?;
+        let len = buf.len();
+        let (n, _) = stream.read_all(buf, Some(cancellable)).ok()?;
+
+        (n == len).then_some(())
+    }
+}
+
+/// Dimensions of the logical screen
+fn gif_size(head: &[u8]) -> Option<(u32, u32)> {
+    let width = u16::from_le_bytes(head.get(6..8)?.try_into().ok()?);
+    let height = u16::from_le_bytes(head.get(8..10)?.try_into().ok()?);
+
+    Some((width.into(), height.into()))
+}
+
+fn qoi_size(head: &[u8]) -> Option<(u32, u32)> {
+    Some((be_u32(head, 4)?, be_u32(head, 8)?))
+}
+
+fn be_u32(data: &[u8], pos: usize) -> Option<u32> {
+    let bytes = data.get(pos..pos.checked_add(4)?)?;
+    Some(u32::from_be_bytes(bytes.try_into().ok()?))
+}
+
+#[test]
+fn png_size_test() {
+    let ihdr = [
+        0, 0, 0, 13, b'I', b'H', b'D', b'R', 0, 0, 1, 0, 0, 0, 0, 2, 8, 6, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let chunk = |name: &[u8], data: &[u8]| {
+        let length = u32::try_from(data.len()).unwrap().to_be_bytes();
+        [length.as_slice(), name, data, &[0; 4]].concat()
+    };
+    let size = |chunks: &[Vec<u8>]| {
+        let png = [b"\x89PNG\r\n\x1a\n".as_slice(), &ihdr, &chunks.concat()].concat();
+        png_size(|offset, buf| {
+            let start = usize::try_from(offset).ok()?;
+            buf.copy_from_slice(png.get(start..start + buf.len())?);
+            Some(())
+        })
+    };
+
+    let idat = chunk(b"IDAT", &[1, 2, 3]);
+    let iend = chunk(b"IEND", &[]);
+    let text = chunk(b"iTXt", b"Comment\0\0\0\0\0text");
+    let xmp = chunk(b"iTXt", b"XML:com.adobe.xmp\0\0\0\0\0<x:xmpmeta/>");
+
+    assert_eq!(size(&[idat.clone(), iend.clone()]), Some((256, 2)));
+    assert_eq!(size(&[text, idat.clone(), iend.clone()]), Some((256, 2)));
+
+    // Orientation metadata
+    assert_eq!(
+        size(&[chunk(b"eXIf", &[]), idat.clone(), iend.clone()]),
+        None
+    );
+    assert_eq!(size(&[xmp.clone(), idat.clone(), iend.clone()]), None);
+    // XMP is allowed after the image data
+    assert_eq!(size(&[idat.clone(), xmp, iend]), None);
+
+    // Truncated file
+    assert_eq!(size(&[idat]), None);
+}
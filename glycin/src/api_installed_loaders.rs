@@ -0,0 +1,106 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use futures_util::future;
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+
+use crate::{config, util, MimeType};
+
+/// Information about a configured loader
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct LoaderInfo {
+    mime_type: MimeType,
+    exec: PathBuf,
+    version: Option<String>,
+}
+
+impl LoaderInfo {
+    /// Mime type handled by the loader
+    pub fn mime_type(&self) -> &MimeType {
+        &self.mime_type
+    }
+
+    /// Path of the loader binary
+    pub fn exec(&self) -> &Path {
+        &self.exec
+    }
+
+    /// Version reported by the loader binary
+    ///
+    /// This usually has the form `<name> v<version>`. It is `None` if the
+    /// binary doesn't support the `--version` argument or couldn't be run.
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+}
+
+/// Returns the configured loaders and their versions
+///
+/// The versions are obtained by running each loader binary with the
+/// `--version` argument outside of the sandbox. This is intended for
+/// diagnostics, like including the loader versions in bug reports.
+pub async fn installed_loaders() -> Vec<LoaderInfo> {
+    let loaders = config::Config::cached().await.image_loader.clone();
+
+    // Many mime types are handled by the same binary
+    let execs = loaders
+        .values()
+        .map(|loader| loader.exec.clone())
+        .collect::<BTreeSet<_>>();
+
+    // Run all binaries concurrently
+    let versions = future::join_all(execs.into_iter().map(|exec| async move {
+        let version = loader_version(exec.clone(), LOADER_VERSION_TIMEOUT).await;
+        (exec, version)
+    }))
+    .await
+    .into_iter()
+    .collect::<BTreeMap<_, _>>();
+
+    loaders
+        .into_iter()
+        .map(|(mime_type, loader)| LoaderInfo {
+            version: versions.get(&loader.exec).cloned().flatten(),
+            mime_type,
+            exec: loader.exec,
+        })
+        .collect()
+}
+
+/// Time after which a loader that doesn't exit is killed
+const LOADER_VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn loader_version(exec: PathBuf, timeout: Duration) -> Option<String> {
+    let child = std::process::Command::new(&exec)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .inspect_err(|err| tracing::debug!("Failed to run loader {exec:?} for version: {err}"))
+        .ok()?;
+
+    let pid = Pid::from_raw(i32::try_from(child.id()).ok()?);
+    // Dropping the handle stops the timer once the loader exited
+    let _timeout = util::spawn_timeout(timeout, async move {
+        tracing::debug!("Killing loader {exec:?} since it didn't report a version in time");
+        let _result = signal::kill(pid, Signal::SIGKILL);
+    });
+
+    let output = util::spawn_blocking(move || child.wait_with_output())
+        .await
+        .inspect_err(|err| tracing::debug!("Failed to get loader version: {err}"))
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    (!version.is_empty()).then_some(version)
+}
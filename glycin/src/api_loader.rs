@@ -1,11 +1,20 @@
+use std::collections::BTreeMap;
+use std::os::fd::OwnedFd;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
+use futures_util::{FutureExt, Stream, StreamExt};
 use gio::glib;
 use gio::prelude::*;
 pub use glycin_common::MemoryFormat;
-use glycin_common::{BinaryData, MemoryFormatSelection};
-#[cfg(feature = "gdk4")]
+use glycin_common::{BinaryData, MemoryFormatInfo, MemoryFormatSelection};
 use glycin_utils::safe_math::*;
+use glycin_utils::InitializationDetails;
+pub use glycin_utils::{
+    Antialias, AuxiliaryImage, FrameBlend, FrameDispose, LayerInfo, SubImageInfo, SubImageKind,
+    KEY_MOTION_PHOTO_VIDEO_LENGTH, KEY_MOTION_PHOTO_VIDEO_OFFSET, KEY_RADIANCE_EXPOSURE,
+    KEY_RADIANCE_PRIMARIES,
+};
 use gufo_common::orientation::{Orientation, Rotation};
 use zbus::zvariant::OwnedObjectPath;
 
@@ -13,20 +22,56 @@ use crate::api_common::*;
 pub use crate::config::MimeType;
 use crate::dbus::*;
 use crate::error::ResultExt;
-use crate::pool::{Pool, PooledProcess, UsageTracker};
-use crate::util::spawn_detached;
-use crate::{config, ErrorCtx};
+use crate::pool::{DecodeMemoryReservation, Pool, PooledProcess, UsageTracker};
+use crate::progress::ProgressEntry;
+use crate::util::{self, spawn_detached};
+use crate::{
+    config, CameraInfo, Error, ErrorCtx, ExifValue, Lut3D, MemoryPressure, ProgressAggregator,
+};
+
+/// Loader process with an initialized image
+struct InitializedLoader {
+    process_basics: RemoteProcessContext<LoaderProxy<'static>>,
+    remote_image: glycin_utils::RemoteImage,
+    spawn_duration: std::time::Duration,
+    encoded_size: Option<u64>,
+}
 
 /// Image request builder
 #[derive(Debug)]
 pub struct Loader {
-    source: Source,
-    pool: Arc<Pool>,
-    cancellable: gio::Cancellable,
+    pub(crate) source: Source,
+    pub(crate) pool: Arc<Pool>,
+    pub(crate) cancellable: gio::Cancellable,
     use_expose_base_dir: bool,
+    font_dir: Option<PathBuf>,
+    loader_overrides: BTreeMap<MimeType, PathBuf>,
+    fallback_formats: bool,
     pub(crate) apply_transformations: bool,
+    /// Convert colors to sRGB via the ICC profile
+    pub(crate) apply_icc_profile: bool,
+    pub(crate) icc_error_policy: IccErrorPolicy,
+    zero_dimensions_policy: ZeroDimensionsPolicy,
+    pub(crate) rendering_intent: RenderingIntent,
+    pub(crate) black_point_compensation: bool,
     pub(crate) sandbox_selector: SandboxSelector,
     pub(crate) memory_format_selection: MemoryFormatSelection,
+    pub(crate) byte_order: ByteOrder,
+    pub(crate) linearize: bool,
+    pub(crate) stride_alignment: Option<usize>,
+    assume_still: bool,
+    reject_external_references: bool,
+    reconstruct_jpeg: bool,
+    prefer_cicp: bool,
+    pub(crate) deterministic: bool,
+    max_frames: Option<usize>,
+    collect_advisories: bool,
+    pub(crate) lut: Option<Arc<Lut3D>>,
+    memory_pressure: Option<MemoryPressure>,
+    pub(crate) progress: Option<Arc<ProgressEntry>>,
+    pub(crate) collect_timings: bool,
+    pin_process: bool,
+    content_hash: Option<ContentHash>,
 }
 
 static_assertions::assert_impl_all!(Loader: Send, Sync);
@@ -54,15 +99,51 @@ impl Loader {
         Self::new_bytes(bytes)
     }
 
+    /// Create a loader with a memfd as source
+    ///
+    /// The memfd is passed to the loader without copying the data. If the
+    /// memfd isn't already sealed against writing, shrinking, and growing,
+    /// these seals are added. Loaders map the sealed memfd into memory if they
+    /// support it. If an error is returned, the `fd` is closed.
+    pub fn new_memfd(fd: OwnedFd) -> Result<Self, Error> {
+        crate::api_common::seal_memfd(&fd)?;
+
+        Ok(Self::new_source(Source::Memfd(Arc::new(fd))))
+    }
+
     pub(crate) fn new_source(source: Source) -> Self {
         Self {
             source,
             pool: Pool::global(),
             cancellable: gio::Cancellable::new(),
             apply_transformations: true,
+            apply_icc_profile: true,
+            icc_error_policy: IccErrorPolicy::default(),
+            zero_dimensions_policy: ZeroDimensionsPolicy::default(),
+            rendering_intent: RenderingIntent::default(),
+            black_point_compensation: false,
             use_expose_base_dir: false,
+            font_dir: None,
+            loader_overrides: BTreeMap::new(),
+            fallback_formats: false,
             sandbox_selector: SandboxSelector::default(),
             memory_format_selection: MemoryFormatSelection::all(),
+            byte_order: ByteOrder::default(),
+            linearize: false,
+            stride_alignment: None,
+            assume_still: false,
+            reject_external_references: false,
+            reconstruct_jpeg: false,
+            prefer_cicp: false,
+            deterministic: false,
+            max_frames: None,
+            collect_advisories: false,
+            lut: None,
+            memory_pressure: None,
+            progress: None,
+            collect_timings: false,
+            pin_process: false,
+            content_hash: None,
         }
     }
 
@@ -103,6 +184,71 @@ impl Loader {
         self
     }
 
+    /// Sets the byte order of 16-bit channels in returned frames
+    ///
+    /// This affects all memory formats with 16-bit channels, like
+    /// [`MemoryFormat::R16g16b16`] or [`MemoryFormat::R16g16b16a16Float`]. If
+    /// the byte order differs from the system's byte order, the data is
+    /// converted.
+    ///
+    /// The default is [`ByteOrder::Native`].
+    pub fn byte_order(&mut self, byte_order: ByteOrder) -> &mut Self {
+        self.byte_order = byte_order;
+        self
+    }
+
+    /// Sets if frames should be converted to linear light
+    ///
+    /// When enabled, all frames are returned as
+    /// [`MemoryFormat::R32g32b32Float`] or, if they have an alpha channel,
+    /// [`MemoryFormat::R32g32b32a32Float`], independent of the image format.
+    /// The transfer function of the frame's CICP is inverted, or the sRGB
+    /// transfer function if the ICC profile has been applied or the image has
+    /// no color information. The primaries are kept and the resulting
+    /// [`Frame::color_state`] has the linear transfer function. For PQ and
+    /// HLG, the SDR reference white is mapped to 1.0.
+    ///
+    /// This overrides the [`Loader::accepted_memory_formats`]. The time for
+    /// the conversion is part of [`Timings::memory_format`].
+    ///
+    /// This option is disabled by default.
+    pub fn linearize(&mut self, linearize: bool) -> &mut Self {
+        self.linearize = linearize;
+        self
+    }
+
+    /// Sets the alignment of the rows in returned frames
+    ///
+    /// Each row is padded such that [`Frame::stride`] is a multiple of
+    /// `alignment` bytes. This is useful for texture uploads that require a
+    /// specific alignment, like 256 bytes for wgpu. The padding bytes are set
+    /// to zero.
+    ///
+    /// The `alignment` has to be a power of two and at least the size of a
+    /// pixel in the returned memory format. Otherwise
+    /// [`Error::InvalidStrideAlignment`] is returned when requesting a frame.
+    ///
+    /// By default, the stride is returned as produced by the loader.
+    pub fn stride_alignment(&mut self, alignment: usize) -> &mut Self {
+        self.stride_alignment = Some(alignment);
+        self
+    }
+
+    /// Sets if the image should be treated as a still image
+    ///
+    /// Some formats, like GIF, can contain animations but often only contain
+    /// a single frame. Loaders usually decode the first two frames to detect
+    /// if an image is animated. With this option enabled, only the first frame
+    /// is decoded and it is returned without [`Frame::delay`]. Further frames
+    /// are only decoded if they are explicitly requested via
+    /// [`Image::next_frame`] or [`Image::specific_frame`].
+    ///
+    /// This option is disabled by default.
+    pub fn assume_still(&mut self, assume_still: bool) -> &mut Self {
+        self.assume_still = assume_still;
+        self
+    }
+
     /// Sets if the file's directory can be exposed to loaders
     ///
     /// Some loaders have the `use_base_dir` option enabled to load external
@@ -117,33 +263,384 @@ impl Loader {
         self
     }
 
+    /// Uses the given loader binary for a mime type
+    ///
+    /// The config files are bypassed for images of this mime type and
+    /// `exec` is spawned instead of the configured loader. This allows to
+    /// test a new loader build without installing its config files or to
+    /// pin a specific decoder. If a loader is configured for the mime type,
+    /// its other options, like the use of fontconfig, are kept.
+    ///
+    /// The binary runs in the sandbox selected via
+    /// [`sandbox_selector`](Self::sandbox_selector) like any configured
+    /// loader.
+    pub fn loader_override(&mut self, mime_type: MimeType, exec: impl Into<PathBuf>) -> &mut Self {
+        self.loader_overrides.insert(mime_type, exec.into());
+        self
+    }
+
+    /// Sets if other loaders are tried if loading fails
+    ///
+    /// The format is usually guessed once from the content and, if the
+    /// content is ambiguous, from the file name. With this option enabled, a
+    /// failed load is retried with the format guessed only from the content
+    /// and the format guessed only from the file name, as long as loaders for
+    /// them are available. This helps with files that have a wrong extension.
+    /// At most [`MAX_FALLBACK_ATTEMPTS`](Self::MAX_FALLBACK_ATTEMPTS) other
+    /// loaders are tried.
+    ///
+    /// The format of the loader that succeeded is reported by
+    /// [`Image::mime_type`] and the formats that failed before by
+    /// [`Image::failed_mime_types`]. If all loaders fail, the error of the
+    /// first attempt is returned.
+    ///
+    /// Retrying is only possible for sources that can be read again, that
+    /// is files and memfds. For streams, this option has no effect.
+    ///
+    /// This option is disabled by default.
+    pub fn fallback_formats(&mut self, fallback_formats: bool) -> &mut Self {
+        self.fallback_formats = fallback_formats;
+        self
+    }
+
+    /// Maximum number of other loaders tried with
+    /// [`fallback_formats`](Self::fallback_formats)
+    pub const MAX_FALLBACK_ATTEMPTS: usize = 2;
+
+    /// Sets an additional font directory for loaders that render text
+    ///
+    /// The directory is made available read-only inside the sandbox of
+    /// loaders that use fontconfig, like the SVG loader. The fonts in the
+    /// directory are available in addition to the system fonts. Separate
+    /// sandboxes are needed for different font directories.
+    pub fn font_dir(&mut self, font_dir: impl Into<PathBuf>) -> &mut Self {
+        self.font_dir = Some(font_dir.into());
+        self
+    }
+
+    /// Sets if images with references to external resources are rejected
+    ///
+    /// SVGs can reference other files or remote URLs, for example via
+    /// `<image href="…">`. Loading these is not possible inside the sandbox
+    /// unless [`use_expose_base_dir`](Self::use_expose_base_dir) is enabled
+    /// and, otherwise, the image is rendered without them. With this option
+    /// enabled, loading fails instead and the base directory is not used.
+    ///
+    /// This option is disabled by default.
+    pub fn reject_external_references(&mut self, reject_external_references: bool) -> &mut Self {
+        self.reject_external_references = reject_external_references;
+        self
+    }
+
+    /// Sets the maximum number of animation frames to decode
+    ///
+    /// After the given number of frames, [`Image::next_frame`] returns an
+    /// error for which [`Error::is_no_more_frames`] is `true` instead of
+    /// decoding further frames or looping the animation. This bounds the work
+    /// for animations with many frames, for example, if only the first frame
+    /// is shown.
+    ///
+    /// By default, the number of frames is not limited.
+    pub fn max_frames(&mut self, max_frames: usize) -> &mut Self {
+        self.max_frames = Some(max_frames);
+        self
+    }
+
+    /// Sets if risky or deprecated features of the image are reported
+    ///
+    /// Loaders report features like references to external resources in
+    /// SVGs or unusual compression methods in TIFFs. They don't prevent
+    /// loading the image and are available via
+    /// [`ImageDetails::advisories`]. This allows, for example, to flag or
+    /// reject uploaded images.
+    ///
+    /// This option is disabled by default.
+    pub fn collect_advisories(&mut self, collect_advisories: bool) -> &mut Self {
+        self.collect_advisories = collect_advisories;
+        self
+    }
+
+    /// Sets how failures to apply the ICC profile are handled
+    ///
+    /// Broken profiles or profiles that don't match the image data can't be
+    /// applied. By default, the untransformed colors are returned in this
+    /// case. The error is available via [`FrameDetails::color_icc_error`]
+    /// regardless of the policy, unless the frame request fails. If the image
+    /// describes its color space in another way, like via CICP, this is used
+    /// for the untransformed colors instead of sRGB. See
+    /// [`FrameDetails::color_fallback`].
+    ///
+    /// If [`deterministic`](Self::deterministic) mode is enabled, failures
+    /// always result in an error.
+    pub fn icc_error_policy(&mut self, icc_error_policy: IccErrorPolicy) -> &mut Self {
+        self.icc_error_policy = icc_error_policy;
+        self
+    }
+
+    /// Sets how images with a width or height of zero are handled
+    ///
+    /// By default, images whose header reports zero dimensions are loaded
+    /// and only a warning is logged. This allows, for example, to read the
+    /// metadata of placeholder images. With [`ZeroDimensionsPolicy::Error`],
+    /// loading fails with [`Error::WidthZero`] or [`Error::HeightZero`]
+    /// instead. Frame requests always fail for frames without pixels.
+    pub fn zero_dimensions_policy(
+        &mut self,
+        zero_dimensions_policy: ZeroDimensionsPolicy,
+    ) -> &mut Self {
+        self.zero_dimensions_policy = zero_dimensions_policy;
+        self
+    }
+
+    /// Sets the rendering intent for the ICC profile transformation
+    ///
+    /// The rendering intent determines how colors that are outside of the
+    /// sRGB gamut are mapped. Photos usually look best with the perceptual
+    /// intent, while the relative colorimetric intent keeps the in-gamut
+    /// colors unchanged.
+    ///
+    /// The default is [`RenderingIntent::Perceptual`].
+    pub fn rendering_intent(&mut self, rendering_intent: RenderingIntent) -> &mut Self {
+        self.rendering_intent = rendering_intent;
+        self
+    }
+
+    /// Sets if black point compensation is used for the ICC profile
+    /// transformation
+    ///
+    /// Black point compensation maps the darkest color of the image's profile
+    /// to the black of sRGB. This preserves shadow details of profiles with a
+    /// lighter black, like the ones for print, and is most useful with
+    /// [`RenderingIntent::RelativeColorimetric`]. It is ignored for
+    /// [`RenderingIntent::AbsoluteColorimetric`].
+    ///
+    /// Defaults to `false`.
+    pub fn black_point_compensation(&mut self, black_point_compensation: bool) -> &mut Self {
+        self.black_point_compensation = black_point_compensation;
+        self
+    }
+
+    /// Sets if the color pipeline is fixed to produce reproducible output
+    ///
+    /// This is intended for regression tests and archival, where the pixel
+    /// data has to be byte-identical across systems. In this mode
+    ///
+    /// - the ICC profile transformation doesn't use dithering or the
+    ///   approximations of lcms2's optimized pipelines,
+    /// - failing to apply the ICC profile results in an error instead of
+    ///   returning the untransformed colors.
+    ///
+    /// The memory format of the returned frames does not depend on this mode.
+    /// It is always the result of [`MemoryFormatSelection::best_format_for`]
+    /// with the format provided by the loader and the selection set via
+    /// [`accepted_memory_formats`](Self::accepted_memory_formats).
+    ///
+    /// The output still depends on the versions of the loaders and their
+    /// decoding libraries.
+    ///
+    /// This option is disabled by default.
+    pub fn deterministic(&mut self, deterministic: bool) -> &mut Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Sets if the original JPEG is reconstructed for recompressed JPEGs
+    ///
+    /// JPEG XL can store JPEGs losslessly, for example via
+    /// [`Creator::add_jpeg_lossless`](crate::Creator::add_jpeg_lossless). If
+    /// enabled, the bit-exact original JPEG is available via
+    /// [`ImageDetails::jpeg_reconstruction`]. If the JPEG can't be
+    /// reconstructed, the image is still loaded without it.
+    ///
+    /// This option is disabled by default.
+    pub fn reconstruct_jpeg(&mut self, reconstruct_jpeg: bool) -> &mut Self {
+        self.reconstruct_jpeg = reconstruct_jpeg;
+        self
+    }
+
+    /// Sets if the CICP is used instead of the ICC profile
+    ///
+    /// AVIF and HEIF images can carry an ICC profile as well as CICP values.
+    /// By default, only the ICC profile is used for those images. HDR images
+    /// often contain an ICC profile for SDR displays, while only the CICP
+    /// describes the HDR transfer characteristics like PQ or HLG. If enabled,
+    /// the CICP is used for the color conversion and available via
+    /// [`FrameDetails::color_cicp`]. The ICC profile is still available via
+    /// [`FrameDetails::color_icc_profile`].
+    ///
+    /// This option is disabled by default.
+    pub fn prefer_cicp(&mut self, prefer_cicp: bool) -> &mut Self {
+        self.prefer_cicp = prefer_cicp;
+        self
+    }
+
+    /// Sets a 3D LUT that is applied to all frames
+    ///
+    /// The LUT is applied to the sRGB colors after the ICC profile has been
+    /// applied and before the frame is converted to the requested memory
+    /// format. Since the LUT only operates on sRGB input, it is skipped for
+    /// frames that are returned with a different color state, like HDR
+    /// images with CICP values.
+    ///
+    /// ```no_run
+    /// # async fn x() -> Result<(), glycin::Error> {
+    /// let lut = glycin::Lut3D::from_cube(&std::fs::read_to_string("grading.cube").unwrap())?;
+    ///
+    /// let file = gio::File::for_path("image.jpg");
+    /// let mut loader = glycin::Loader::new(file);
+    /// loader.apply_lut(lut);
+    /// # Ok(()) }
+    /// ```
+    pub fn apply_lut(&mut self, lut: Lut3D) -> &mut Self {
+        self.lut = Some(Arc::new(lut));
+        self
+    }
+
+    /// Sets a signal to cancel the load when the system is low on memory
+    ///
+    /// When [`MemoryPressure::trigger`] is called, the load and all further
+    /// operations on the [`Image`] are canceled via the
+    /// [`cancellable`](Self::cancellable). The returned errors report
+    /// [`CancelReason::MemoryPressure`](crate::CancelReason::MemoryPressure).
+    pub fn memory_pressure(&mut self, memory_pressure: &MemoryPressure) -> &mut Self {
+        self.memory_pressure = Some(memory_pressure.clone());
+        self
+    }
+
+    /// Adds the load to the combined progress of `progress_aggregator`
+    ///
+    /// The load counts as started once the loader process is ready and as
+    /// completed after the first frame was loaded or loading failed.
+    /// [`ProgressAggregator::cancel`] cancels the load via the
+    /// [`cancellable`](Self::cancellable).
+    pub fn progress_aggregator(&mut self, progress_aggregator: &ProgressAggregator) -> &mut Self {
+        self.progress = Some(progress_aggregator.attach());
+        self
+    }
+
     pub fn pool(&mut self, pool: Arc<Pool>) -> &mut Self {
         self.pool = pool;
         self
     }
 
+    /// Sets if the loader process is kept alive until the image is dropped
+    ///
+    /// An [`Image`] always keeps its loader from being terminated after the
+    /// [`loader_retention_time`](crate::PoolConfig::loader_retention_time).
+    /// However, if a frame stream like [`Image::frames`] is dropped while a
+    /// frame is decoded, the image is canceled and its loader is terminated.
+    /// If the process is pinned, the frame is discarded instead and the image
+    /// can still be used. This avoids respawning loaders in long editing
+    /// sessions, at the cost of the loader finishing the discarded frame.
+    ///
+    /// This option is disabled by default.
+    pub fn pin_process(&mut self, pin_process: bool) -> &mut Self {
+        self.pin_process = pin_process;
+        self
+    }
+
+    /// Sets if the time spent on the steps of loading a frame is recorded
+    ///
+    /// The recorded [`Timings`] are available via [`Frame::timings`].
+    ///
+    /// This option is disabled by default.
+    pub fn collect_timings(&mut self, collect_timings: bool) -> &mut Self {
+        self.collect_timings = collect_timings;
+        self
+    }
+
+    /// Sets the hash that the source data must have
+    ///
+    /// The hash is computed while the source is passed to the loader. If it
+    /// doesn't match, loading fails with [`Error::ContentHashMismatch`] before
+    /// the image is returned. For memfds, the seals against modifications are
+    /// checked before the hash is computed.
+    ///
+    /// ```no_run
+    /// # async fn x() -> Result<(), glycin::ErrorCtx> {
+    /// let file = gio::File::for_path("image.jpg");
+    /// let mut loader = glycin::Loader::new(file);
+    /// loader.expect_content_hash(glycin::ContentHash::sha256(
+    ///     "5e2b4a7d9c1f3e8a0b6d2c4f7a9e1b3d5c7f9a2e4b6d8c0f1a3e5b7d9c2f4a6e",
+    /// ));
+    /// let image = loader.load().await?;
+    /// # Ok(()) }
+    /// ```
+    pub fn expect_content_hash(&mut self, content_hash: ContentHash) -> &mut Self {
+        self.content_hash = Some(content_hash);
+        self
+    }
+
+    fn initialization_details(&self) -> Result<InitializationDetails, Error> {
+        let mut details = InitializationDetails::default();
+        details.assume_still = self.assume_still.then_some(true);
+        details.reject_external_references = self.reject_external_references.then_some(true);
+        details.reconstruct_jpeg = self.reconstruct_jpeg.then_some(true);
+        details.max_frames = self.max_frames.map(|x| x.try_u64()).transpose()?;
+        details.collect_advisories = self.collect_advisories.then_some(true);
+        details.prefer_cicp = self.prefer_cicp.then_some(true);
+
+        Ok(details)
+    }
+
     /// Load basic image information and enable further operations
-    pub async fn load(mut self) -> Result<Image, ErrorCtx> {
-        let source = self.source.send();
+    pub async fn load(self) -> Result<Image, ErrorCtx> {
+        let progress = self.progress.clone();
+        let result = self.load_image().await;
 
-        let process_basics = spin_up_loader(
-            source,
-            self.use_expose_base_dir,
-            self.pool.clone(),
-            &self.cancellable,
-            &self.sandbox_selector,
-        )
-        .await
-        .err_no_context(&self.cancellable)?;
+        if let (Some(progress), Err(_)) = (progress, &result) {
+            progress.complete(&result);
+        }
 
-        let process = process_basics.process.use_();
-        let mut remote_image = process
-            .init(
-                process_basics.g_file_worker.unwrap(),
-                &process_basics.mime_type,
-            )
-            .await
-            .err_context(&process, &self.cancellable)?;
+        result
+    }
+
+    async fn load_image(mut self) -> Result<Image, ErrorCtx> {
+        if let Some(memory_pressure) = &self.memory_pressure {
+            memory_pressure.register(&self.cancellable);
+        }
+
+        if let Some(progress) = &self.progress {
+            progress.register(&self.cancellable);
+        }
+
+        // Sources that can be read again to try other loaders
+        let retry_source = self.source.reopen().filter(|_| self.fallback_formats);
+
+        let source = self.source.send();
+        let mut failed_mime_types = Vec::new();
+
+        let InitializedLoader {
+            process_basics,
+            mut remote_image,
+            spawn_duration,
+            encoded_size,
+        } = match self.load_as(source, None).await {
+            Ok(loaded) => loaded,
+            Err(err) => match retry_source {
+                Some(source) if !self.cancellable.is_cancelled() => {
+                    self.load_fallback(source, err, &mut failed_mime_types)
+                        .await?
+                }
+                _ => return Err(err),
+            },
+        };
+
+        let (width, height) = (remote_image.details.width, remote_image.details.height);
+        if width == 0 || height == 0 {
+            let dimensions = format!("{width}x{height}");
+            match self.zero_dimensions_policy {
+                ZeroDimensionsPolicy::Error => {
+                    let process = process_basics.process.use_();
+                    spawn_detached(process.done(remote_image.frame_request));
+                    return Err(Error::zero_dimensions(width, dimensions))
+                        .err_no_context(&self.cancellable);
+                }
+                ZeroDimensionsPolicy::Warn => {
+                    tracing::warn!("Image has no pixels: {dimensions}");
+                }
+            }
+        }
 
         match Image::transformation_orientation_internal(&remote_image.details).rotate() {
             Rotation::_90 | Rotation::_270 => {
@@ -155,15 +652,15 @@ impl Loader {
             _ => {}
         }
 
+        // Only a weak reference, since the cancellable can outlive the image
+        let process = Arc::downgrade(&process_basics.process);
         let path = remote_image.frame_request.clone();
-        self.cancellable.connect_cancelled(glib::clone!(
-            #[strong(rename_to=process)]
-            process_basics.process,
-            move |_| {
+        self.cancellable.connect_cancelled(move |_| {
+            if let Some(process) = process.upgrade() {
                 tracing::debug!("Terminating loader");
                 crate::util::spawn_detached(process.use_().done(path))
             }
-        ));
+        });
 
         Ok(Image {
             process: process_basics.process,
@@ -171,11 +668,122 @@ impl Loader {
             details: Arc::new(remote_image.details),
             loader: self,
             mime_type: process_basics.mime_type,
+            failed_mime_types,
             active_sandbox_mechanism: process_basics.sandbox_mechanism,
             usage_tracker: Mutex::new(Some(process_basics.usage_tracker)),
+            spawn_duration,
+            encoded_size,
+        })
+    }
+
+    /// Spin up a loader and initialize the image
+    ///
+    /// The mime type is guessed if `mime_type` is `None`.
+    async fn load_as(
+        &self,
+        source: Source,
+        mime_type: Option<MimeType>,
+    ) -> Result<InitializedLoader, ErrorCtx> {
+        let spawn_start = std::time::Instant::now();
+        let mut process_basics = spin_up_loader(
+            source,
+            mime_type,
+            self.use_expose_base_dir && !self.reject_external_references,
+            self.font_dir.clone(),
+            &self.loader_overrides,
+            self.pool.clone(),
+            self.content_hash.clone(),
+            &self.cancellable,
+            &self.sandbox_selector,
+        )
+        .await
+        .err_no_context(&self.cancellable)?;
+        let spawn_duration = spawn_start.elapsed();
+
+        if let Some(progress) = &self.progress {
+            progress.start();
+        }
+
+        let g_file_worker = process_basics
+            .g_file_worker
+            .take()
+            .ok_or(Error::TransferredStream)
+            .err_no_context(&self.cancellable)?;
+        let encoded_size = g_file_worker.encoded_size();
+
+        // Loaders hold the complete source in memory during init
+        let _reservation = self
+            .pool
+            .reserve_decode_memory(source_size(&g_file_worker).await.unwrap_or_default())
+            .await;
+
+        let process = process_basics.process.use_();
+        let remote_image = process
+            .init(
+                g_file_worker,
+                &process_basics.mime_type,
+                self.initialization_details()
+                    .err_no_context(&self.cancellable)?,
+            )
+            .await
+            .err_context(&process, &self.cancellable)?;
+
+        // Loaders read the complete source during init, the reader is done soon
+        let encoded_size = encoded_size.await;
+
+        Ok(InitializedLoader {
+            process_basics,
+            remote_image,
+            spawn_duration,
+            encoded_size,
         })
     }
 
+    /// Try the loaders of other plausible formats after `err` occurred
+    ///
+    /// The mime types of loaders that failed are added to
+    /// `failed_mime_types`.
+    async fn load_fallback(
+        &self,
+        source: Source,
+        err: ErrorCtx,
+        failed_mime_types: &mut Vec<MimeType>,
+    ) -> Result<InitializedLoader, ErrorCtx> {
+        let g_file_worker = GFileWorker::spawn(
+            source.clone(),
+            self.pool.read_buffer_size(),
+            self.content_hash.clone(),
+            self.cancellable.clone(),
+        );
+
+        if let Ok(mime_type) = guess_mime_type(&g_file_worker).await {
+            failed_mime_types.push(mime_type);
+        }
+
+        let Ok(candidates) = fallback_mime_types(&g_file_worker, &self.loader_overrides).await
+        else {
+            return Err(err);
+        };
+
+        for mime_type in candidates.into_iter().take(Self::MAX_FALLBACK_ATTEMPTS) {
+            if self.cancellable.is_cancelled() {
+                break;
+            }
+
+            tracing::debug!("Loading failed, trying loader for {mime_type}");
+
+            match self.load_as(source.clone(), Some(mime_type.clone())).await {
+                Ok(loaded) => return Ok(loaded),
+                Err(err) => {
+                    tracing::debug!("Loader for {mime_type} failed: {err}");
+                    failed_mime_types.push(mime_type);
+                }
+            }
+        }
+
+        Err(err)
+    }
+
     /// Returns a list of mime types for which loaders are configured
     pub async fn supported_mime_types() -> Vec<MimeType> {
         config::Config::cached()
@@ -211,13 +819,110 @@ impl Loader {
         "image/heif",
         // JXL
         "image/jxl",
+        // KTX2
+        "image/ktx2",
+        // Legacy
+        "image/vnd.zbrush.pcx",
+        "image/x-pcx",
+        "image/x-ilbm",
+        // PSD
+        "image/vnd.adobe.photoshop",
         // SVG
         "image/svg+xml",
         "image/svg+xml-compressed",
     ];
 }
 
+/// Byte order of channels with more than one byte
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ByteOrder {
+    /// Byte order of the system
+    #[default]
+    Native,
+    LittleEndian,
+    BigEndian,
+}
+
+impl ByteOrder {
+    /// Returns `true` if the byte order matches the system's byte order
+    pub fn is_native(self) -> bool {
+        match self {
+            Self::Native => true,
+            Self::LittleEndian => cfg!(target_endian = "little"),
+            Self::BigEndian => cfg!(target_endian = "big"),
+        }
+    }
+}
+
+/// Handling of ICC profiles that can't be applied
+///
+/// See [`Loader::icc_error_policy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum IccErrorPolicy {
+    /// Return the untransformed colors and keep the profile in the details
+    #[default]
+    Ignore,
+    /// Fail the frame request with [`Error::IccProfile`]
+    Error,
+    /// Return the untransformed colors and remove the profile from the
+    /// details
+    Strip,
+}
+
+/// Color information used instead of a broken ICC profile
+///
+/// See [`FrameDetails::color_fallback`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ColorFallback {
+    /// Color space that the image describes in addition to the ICC profile
+    ///
+    /// This is, for example, the NCLX box of HEIF images or the `gAMA` and
+    /// `cHRM` chunks of PNGs. It's available via the
+    /// [`Frame::color_state`].
+    Cicp,
+    /// The colors are treated as sRGB
+    Srgb,
+}
+
+/// Handling of images with a width or height of zero
+///
+/// See [`Loader::zero_dimensions_policy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ZeroDimensionsPolicy {
+    /// Load the image and log a warning
+    #[default]
+    Warn,
+    /// Fail loading with [`Error::WidthZero`] or [`Error::HeightZero`]
+    Error,
+}
+
+/// Rendering intent for ICC profile transformations
+///
+/// See [`Loader::rendering_intent`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RenderingIntent {
+    /// Compress the colors of the whole image into the target gamut
+    #[default]
+    Perceptual,
+    /// Keep in-gamut colors and clip the others, adapting the white point
+    RelativeColorimetric,
+    /// Preserve the saturation at the cost of hue and lightness
+    Saturation,
+    /// Keep in-gamut colors and clip the others, keeping the white point
+    AbsoluteColorimetric,
+}
+
 /// Image handle containing metadata and allowing frame requests
+///
+/// The loader process stays alive until the image is dropped, independent of
+/// the [`loader_retention_time`](crate::PoolConfig::loader_retention_time).
+/// Frames can be requested at any time without respawning the loader. See
+/// [`Loader::pin_process`] for abandoned frame requests.
 #[derive(Debug)]
 pub struct Image {
     pub(crate) loader: Loader,
@@ -225,8 +930,11 @@ pub struct Image {
     frame_request: OwnedObjectPath,
     details: Arc<glycin_utils::ImageDetails>,
     mime_type: MimeType,
+    failed_mime_types: Vec<MimeType>,
     active_sandbox_mechanism: SandboxMechanism,
     usage_tracker: Mutex<Option<Arc<UsageTracker>>>,
+    pub(crate) spawn_duration: std::time::Duration,
+    encoded_size: Option<u64>,
 }
 
 static_assertions::assert_impl_all!(Image: Send, Sync);
@@ -244,42 +952,411 @@ impl Drop for Image {
             drop(loader_alive);
         });
     }
-}
-
-impl Image {
-    /// Loads next frame
-    ///
-    /// Loads texture and information of the next frame. For single still
-    /// images, this can only be called once. For animated images, this
-    /// function will loop to the first frame, when the last frame is reached.
-    pub async fn next_frame(&self) -> Result<Frame, ErrorCtx> {
-        let process = self.process.use_();
+}
+
+/// Estimated memory for decoding a frame
+///
+/// The memory format of the frame is only known after decoding. Therefore,
+/// the largest pixel size among the accepted memory formats is assumed.
+fn frame_memory_estimate(
+    width: u32,
+    height: u32,
+    memory_format_selection: MemoryFormatSelection,
+) -> u64 {
+    let pixel_size = memory_format_selection
+        .memory_formats()
+        .into_iter()
+        .map(|x| u64::from(x.n_bytes().u8()))
+        .max()
+        .unwrap_or_default();
+
+    u64::from(width)
+        .saturating_mul(u64::from(height))
+        .saturating_mul(pixel_size)
+}
+
+/// Size of the source in bytes, if it's known without reading the source
+///
+/// The size of streams is unknown.
+async fn source_size(g_file_worker: &GFileWorker) -> Option<u64> {
+    if let Some(memfd) = g_file_worker.memfd() {
+        let file = std::fs::File::from(memfd.try_clone().ok()?);
+        return file.metadata().ok().map(|x| x.len());
+    }
+
+    let file = g_file_worker.file()?.clone();
+    util::spawn_blocking(move || {
+        file.query_info(
+            gio::FILE_ATTRIBUTE_STANDARD_SIZE,
+            gio::FileQueryInfoFlags::NONE,
+            gio::Cancellable::NONE,
+        )
+        .ok()
+        .and_then(|info| u64::try_from(info.size()).ok())
+    })
+    .await
+}
+
+/// Stops the loader if a frame request is dropped before it completes
+struct AbandonedFrameGuard<'a>(Option<&'a Image>);
+
+impl Drop for AbandonedFrameGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(image) = self.0.take() {
+            tracing::debug!("Frame request abandoned, canceling image");
+            image.cancellable().cancel();
+
+            if image.process.n_users() <= 1 {
+                image.process.use_().terminate();
+            }
+        }
+    }
+}
+
+impl Image {
+    /// Loads next frame
+    ///
+    /// Loads texture and information of the next frame. For single still
+    /// images, this can only be called once. For animated images, this
+    /// function will loop to the first frame, when the last frame is reached.
+    pub async fn next_frame(&self) -> Result<Frame, ErrorCtx> {
+        let _reservation = self.reserve_decode_memory().await;
+        let process = self.process.use_();
+
+        let mut frame_request = glycin_utils::FrameRequest::default();
+        frame_request.loop_animation = true;
+
+        let result = process
+            .request_frame(frame_request, self, false)
+            .await
+            .err_context(&process, &self.cancellable());
+        self.report_progress(&result);
+
+        result
+    }
+
+    /// Loads next frame with intermediate results
+    ///
+    /// Like [`Self::next_frame`], but the stream returns increasingly refined
+    /// versions of the complete frame while it is decoded, followed by the
+    /// final frame. This allows to show a preview of large images earlier.
+    /// The stream ends after the final frame or the first error.
+    ///
+    /// Currently, only interlaced PNGs and progressive JPEGs are decoded
+    /// progressively. For other formats, the stream only returns the final
+    /// frame.
+    pub fn next_frame_progressive(
+        &self,
+    ) -> impl futures_util::Stream<Item = Result<Frame, ErrorCtx>> + '_ {
+        let (send, recv) = futures_channel::mpsc::unbounded();
+
+        let decode = async move {
+            let _reservation = self.reserve_decode_memory().await;
+            let process = self.process.use_();
+
+            let mut frame_request = glycin_utils::FrameRequest::default();
+            frame_request.loop_animation = true;
+            frame_request.progressive = true;
+
+            let result = process
+                .request_frame_progressive(frame_request, self, |frame| {
+                    let _ = send.unbounded_send(Ok(frame));
+                })
+                .await
+                .err_context(&process, &self.cancellable());
+            self.report_progress(&result);
+
+            let _ = send.unbounded_send(result);
+        };
+
+        // Drive the decoding while returning the frames from the channel
+        futures_util::stream::select(
+            recv,
+            futures_util::stream::once(decode).filter_map(|()| std::future::ready(None)),
+        )
+    }
+
+    /// Loads a specific frame
+    ///
+    /// Loads a specific frame from the file. Loaders can ignore parts of the
+    /// instructions in the `FrameRequest`.
+    pub async fn specific_frame(&self, frame_request: FrameRequest) -> Result<Frame, ErrorCtx> {
+        let _reservation = self.reserve_decode_memory().await;
+        let process = self.process.use_();
+
+        let mut request = frame_request.request;
+        if frame_request.clip_space == CoordinateSpace::Oriented {
+            request.clip = request
+                .clip
+                .map(|clip| self.clip_to_stored(clip))
+                .transpose()
+                .err_no_context(&self.cancellable())?;
+        }
+
+        let result = process
+            .request_frame(request, self, frame_request.raw)
+            .await
+            .err_context(&process, &self.cancellable());
+        self.report_progress(&result);
+
+        result
+    }
+
+    /// Completes the load in the [`ProgressAggregator`] of the loader
+    fn report_progress(&self, result: &Result<Frame, ErrorCtx>) {
+        if let Some(progress) = &self.loader.progress {
+            progress.complete_frame(result);
+        }
+    }
+
+    /// Loads a region of the image
+    ///
+    /// Returns a frame that contains exactly the region `(x, y, width,
+    /// height)`. With [`CoordinateSpace::Oriented`], the region refers to the
+    /// image as it's displayed, matching the dimensions in [`ImageDetails`],
+    /// and is mapped through the orientation of the image as necessary. This
+    /// allows tiling viewers to request tiles without caring about how the
+    /// image is stored.
+    ///
+    /// Loaders that support it, like for tiled TIFFs and SVGs, only decode
+    /// the region. For other formats, the complete frame is decoded and
+    /// cropped afterwards. The frame is oriented if
+    /// [`Loader::apply_transformations`] is enabled.
+    ///
+    /// Returns [`Error::RegionOutsideImage`] if the region isn't completely
+    /// within the image.
+    pub async fn decode_region(
+        &self,
+        region: (u32, u32, u32, u32),
+        coordinate_space: CoordinateSpace,
+    ) -> Result<Frame, ErrorCtx> {
+        let orientation = self.clip_orientation();
+        let (stored_width, stored_height) = self.stored_dimensions();
+
+        let stored_region = match coordinate_space {
+            CoordinateSpace::Stored => Some(region),
+            CoordinateSpace::Oriented => {
+                crate::orientation::clip_to_stored(region, stored_width, stored_height, orientation)
+            }
+        }
+        .filter(|(x, y, width, height)| {
+            *width > 0
+                && *height > 0
+                && x.checked_add(*width).is_some_and(|x| x <= stored_width)
+                && y.checked_add(*height).is_some_and(|y| y <= stored_height)
+        })
+        .ok_or_else(|| {
+            let (width, height) = match coordinate_space {
+                CoordinateSpace::Stored => (stored_width, stored_height),
+                CoordinateSpace::Oriented => (self.details.width, self.details.height),
+            };
+            Error::RegionOutsideImage {
+                region,
+                width,
+                height,
+            }
+        })
+        .err_no_context(&self.cancellable())?;
+
+        // Region and dimensions in the coordinates of the returned frame
+        let oriented = self.loader.apply_transformations && orientation != Orientation::Id;
+        let (frame_region, full_size) = if oriented {
+            (
+                crate::orientation::clip_to_oriented(
+                    stored_region,
+                    stored_width,
+                    stored_height,
+                    orientation,
+                )
+                .unwrap_or(stored_region),
+                (self.details.width, self.details.height),
+            )
+        } else {
+            (stored_region, (stored_width, stored_height))
+        };
+
+        let (x, y, width, height) = stored_region;
+        let frame = self
+            .specific_frame(FrameRequest::new().clip(x, y, width, height))
+            .await?;
+
+        // Loaders that don't support clips return the complete frame
+        if (frame.width, frame.height) != full_size
+            || (frame.width, frame.height) == (frame_region.2, frame_region.3)
+        {
+            return Ok(frame);
+        }
+
+        frame.crop(frame_region).err_no_context(&self.cancellable())
+    }
+
+    /// ICC color profile embedded in the image
+    ///
+    /// The profile is read without decoding a frame, which is much cheaper
+    /// than getting it via [`FrameDetails::color_icc_profile`]. This is
+    /// useful for tools that only inspect or strip profiles. Unlike for
+    /// frames, the profile is returned even if the loader converts the colors
+    /// itself, like for CMYK images.
+    ///
+    /// Currently, the image-rs, JPEG XL, and HEIF loaders support this. For
+    /// other loaders, an error is returned.
+    pub async fn color_profile(&self) -> Result<Option<BinaryData>, ErrorCtx> {
+        let process = self.process.use_();
+
+        process
+            .color_profile(self)
+            .await
+            .err_context(&process, &self.cancellable())
+    }
+
+    /// Loads the next frame and returns a pyramid of successively halved
+    /// copies of it
+    ///
+    /// The [`Pyramid`] yields up to `levels` frames, starting with the full
+    /// resolution frame. This provides the mipmap levels needed by deep-zoom
+    /// viewers. The image is only decoded once, in full resolution. The
+    /// smaller levels are created via [`Frame::downscale()`].
+    #[cfg(feature = "downscale")]
+    pub async fn pyramid(&self, levels: u32) -> Result<Pyramid, ErrorCtx> {
+        let frame = self.next_frame().await?;
+        Ok(Pyramid::new(frame, levels))
+    }
+
+    /// Returns all frames of the image
+    ///
+    /// The stream ends after the last frame of an animation, without looping,
+    /// or after the only frame of a still image. With
+    /// [`Loader::max_frames`], the stream ends after this many frames.
+    ///
+    /// If the stream is dropped while a frame is decoded, the image is
+    /// canceled via its [`cancellable`](Self::cancellable). The loader process
+    /// is terminated if no other image uses it, since loaders can't be
+    /// interrupted while decoding a frame. Neither happens if the process is
+    /// pinned via [`Loader::pin_process`].
+    pub fn frames(&self) -> impl Stream<Item = Result<Frame, ErrorCtx>> + '_ {
+        self.frames_with_deadline(None)
+    }
+
+    /// Returns the frames of the image that are decoded before the deadline
+    ///
+    /// Works like [`Self::frames`] but the stream ends without an error once
+    /// the `deadline` is reached. The frame that is being decoded at this
+    /// point is discarded and the image is canceled, like when dropping the
+    /// stream of [`Self::frames`]. This allows to show as many frames of an
+    /// animation as could be decoded within a time budget.
+    pub fn frames_until(
+        &self,
+        deadline: std::time::Instant,
+    ) -> impl Stream<Item = Result<Frame, ErrorCtx>> + '_ {
+        self.frames_with_deadline(Some(deadline))
+    }
+
+    fn frames_with_deadline(
+        &self,
+        deadline: Option<std::time::Instant>,
+    ) -> impl Stream<Item = Result<Frame, ErrorCtx>> + '_ {
+        futures_util::stream::unfold(true, move |more_frames| async move {
+            if !more_frames {
+                return None;
+            }
+
+            let frame_request = FrameRequest::new().loop_animation(false);
+            let mut abandon_guard = AbandonedFrameGuard((!self.loader.pin_process).then_some(self));
+
+            let frame = if let Some(deadline) = deadline {
+                let frame = self.specific_frame(frame_request).fuse();
+                let timeout =
+                    util::sleep(deadline.saturating_duration_since(std::time::Instant::now()))
+                        .fuse();
+                futures_util::pin_mut!(frame, timeout);
+
+                futures_util::select! {
+                    frame = frame => frame,
+                    _ = timeout => {
+                        tracing::debug!("Reached deadline for decoding frames");
+                        return None;
+                    }
+                }
+            } else {
+                self.specific_frame(frame_request).await
+            };
+            abandon_guard.0 = None;
+
+            match frame {
+                // Still images don't have a delay and only one frame
+                Ok(frame) => {
+                    let more_frames = frame.delay().is_some();
+                    Some((Ok(frame), more_frames))
+                }
+                Err(err) if err.is_no_more_frames() => None,
+                Err(err) => Some((Err(err), false)),
+            }
+        })
+    }
 
-        let mut frame_request = glycin_utils::FrameRequest::default();
-        frame_request.loop_animation = true;
+    /// Waits for the memory budget of the pool to decode a frame
+    async fn reserve_decode_memory(&self) -> DecodeMemoryReservation {
+        let bytes = frame_memory_estimate(
+            self.details.width,
+            self.details.height,
+            self.loader.memory_format_selection,
+        );
 
-        process
-            .request_frame(frame_request, self)
-            .await
-            .err_context(&process, &self.cancellable())
+        self.loader.pool.reserve_decode_memory(bytes).await
     }
 
-    /// Loads a specific frame
+    /// Converts a clip from oriented to stored coordinates
     ///
-    /// Loads a specific frame from the file. Loaders can ignore parts of the
-    /// instructions in the `FrameRequest`.
-    pub async fn specific_frame(&self, frame_request: FrameRequest) -> Result<Frame, ErrorCtx> {
-        let process = self.process.use_();
+    /// Returns [`Error::RegionOutsideImage`] if the clip isn't within the
+    /// oriented image.
+    fn clip_to_stored(&self, clip: (u32, u32, u32, u32)) -> Result<(u32, u32, u32, u32), Error> {
+        let (stored_width, stored_height) = self.stored_dimensions();
+        let (x, y, width, height) = clip;
+
+        let within_image = x
+            .checked_add(width)
+            .is_some_and(|x| x <= self.details.width)
+            && y.checked_add(height)
+                .is_some_and(|y| y <= self.details.height);
+
+        within_image
+            .then(|| {
+                crate::orientation::clip_to_stored(
+                    clip,
+                    stored_width,
+                    stored_height,
+                    self.clip_orientation(),
+                )
+            })
+            .flatten()
+            .ok_or(Error::RegionOutsideImage {
+                region: clip,
+                width: self.details.width,
+                height: self.details.height,
+            })
+    }
 
-        process
-            .request_frame(frame_request.request, self)
-            .await
-            .err_context(&process, &self.cancellable())
+    /// Orientation between the stored and the oriented coordinates
+    fn clip_orientation(&self) -> Orientation {
+        if self.details.transformation_ignore_exif {
+            Orientation::Id
+        } else {
+            self.transformation_orientation()
+        }
+    }
+
+    /// Dimensions of the image before applying the orientation
+    fn stored_dimensions(&self) -> (u32, u32) {
+        // The dimensions in the details are oriented
+        match self.transformation_orientation().rotate() {
+            Rotation::_90 | Rotation::_270 => (self.details.height, self.details.width),
+            _ => (self.details.width, self.details.height),
+        }
     }
 
     /// Returns already obtained info
     pub fn details(&self) -> ImageDetails {
-        ImageDetails::new(self.details.clone())
+        ImageDetails::new(self.details.clone(), self.encoded_size)
     }
 
     /// Returns already obtained info
@@ -288,10 +1365,22 @@ impl Image {
     }
 
     /// Returns detected MIME type of the file
+    ///
+    /// This is the format of the loader that loaded the image. It can differ
+    /// from the initially guessed format if
+    /// [`Loader::fallback_formats`] is enabled.
     pub fn mime_type(&self) -> MimeType {
         self.mime_type.clone()
     }
 
+    /// Formats whose loaders failed before the image could be loaded
+    ///
+    /// Only populated if [`Loader::fallback_formats`] is enabled. Empty if the
+    /// first loader succeeded.
+    pub fn failed_mime_types(&self) -> &[MimeType] {
+        &self.failed_mime_types
+    }
+
     /// File the image was loaded from
     ///
     /// Is `None` if the file was loaded from a stream or binary data.
@@ -333,21 +1422,47 @@ impl Image {
                     }
                     Ok(x) => x.orientation(),
                 })
+                // Some tools only write the orientation to XMP
+                .or_else(|| Self::xmp_orientation(details))
                 .unwrap_or(Orientation::Id)
         } else {
             Orientation::Id
         }
     }
+
+    /// Orientation from the `tiff:Orientation` XMP tag
+    fn xmp_orientation(details: &glycin_utils::ImageDetails) -> Option<Orientation> {
+        let data = details.metadata_xmp.as_ref()?.get_full().ok()?;
+
+        let xmp = match gufo_xmp::Xmp::new(data) {
+            Err(err) => {
+                tracing::warn!("xmp: Failed to parse data: {err:?}");
+                return None;
+            }
+            Ok(xmp) => xmp,
+        };
+
+        let tag = gufo_xmp::Tag::new(
+            gufo_common::xmp::Namespace::Tiff,
+            String::from("Orientation"),
+        );
+
+        xmp.get_u16(tag).and_then(|x| Orientation::try_from(x).ok())
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct ImageDetails {
     inner: Arc<glycin_utils::ImageDetails>,
+    encoded_size: Option<u64>,
 }
 
 impl ImageDetails {
-    fn new(inner: Arc<glycin_utils::ImageDetails>) -> Self {
-        Self { inner }
+    fn new(inner: Arc<glycin_utils::ImageDetails>, encoded_size: Option<u64>) -> Self {
+        Self {
+            inner,
+            encoded_size,
+        }
     }
 
     pub fn width(&self) -> u32 {
@@ -362,6 +1477,53 @@ impl ImageDetails {
         self.inner.dimensions_inch
     }
 
+    /// Size of the encoded image in bytes
+    ///
+    /// This is the number of bytes read from the source. It is only known
+    /// once the loader has read the complete source, which is usually the
+    /// case after the first frame has been loaded.
+    pub fn encoded_size(&self) -> Option<u64> {
+        self.encoded_size
+    }
+
+    /// Ratio between the decoded and the encoded size
+    ///
+    /// The decoded size is `width × height` times the pixel size of the
+    /// given memory format, usually [`Frame::memory_format`]. Returns `None`
+    /// if the [`encoded_size`](Self::encoded_size) is not known or zero.
+    pub fn compression_ratio(&self, memory_format: MemoryFormat) -> Option<f64> {
+        let encoded_size = self.encoded_size.filter(|x| *x > 0)?;
+
+        let decoded_size = f64::from(self.width())
+            * f64::from(self.height())
+            * f64::from(memory_format.n_bytes().u8());
+
+        Some(decoded_size / encoded_size as f64)
+    }
+
+    /// Risky or deprecated features used by the image
+    ///
+    /// Only available if enabled via [`Loader::collect_advisories`]. The
+    /// list is empty if no such features have been found.
+    pub fn advisories(&self) -> &[String] {
+        self.inner.advisories.as_deref().unwrap_or_default()
+    }
+
+    /// Tile size for tiled images, like tiled TIFFs
+    pub fn tile_size(&self) -> Option<(u32, u32)> {
+        self.inner.tile_size
+    }
+
+    /// Background color specified by animated GIFs and WebPs
+    ///
+    /// The color is non-premultiplied RGBA. Frames are always composited onto
+    /// a transparent canvas. To get the authored look, for example, when
+    /// displaying the animation on a non-transparent surface, the frames can
+    /// be drawn over this color instead.
+    pub fn animation_background_color(&self) -> Option<[u8; 4]> {
+        self.inner.animation_background_color
+    }
+
     /// A textual representation of the image format
     pub fn info_format_name(&self) -> Option<&str> {
         self.inner.info_format_name.as_deref()
@@ -371,10 +1533,55 @@ impl ImageDetails {
         self.inner.info_dimensions_text.as_deref()
     }
 
+    /// If the image has an alpha channel, as known before decoding
+    ///
+    /// This allows, for example, to decide on a checkerboard background
+    /// before the frame is available. `None` if the format's header doesn't
+    /// allow to determine it. Then [`FrameDetails::info_alpha_channel`] has
+    /// to be used.
+    pub fn info_alpha_channel(&self) -> Option<bool> {
+        self.inner.info_alpha_channel
+    }
+
     pub fn metadata_exif(&self) -> Option<BinaryData> {
         self.inner.metadata_exif.clone()
     }
 
+    /// Value of the Exif field with the given tag id
+    ///
+    /// The tag is looked up in the primary, Exif, GPS, and interoperability
+    /// IFDs, in this order. For example, `0x010F` returns the camera make and
+    /// `0x8827` the ISO speed.
+    pub fn exif_tag(&self, tag: u16) -> Option<ExifValue> {
+        let data = self.inner.metadata_exif.as_ref()?.get_full().ok()?;
+        crate::exif::exif_tag(data, tag)
+    }
+
+    /// Camera and shooting parameters from the Exif data
+    ///
+    /// The lens is read from the `LensModel` field. If it's missing, the lens
+    /// is read from the `MakerNote` for cameras where the format is known.
+    /// All fields are `None` if the image has no Exif data.
+    pub fn camera_info(&self) -> CameraInfo {
+        match self.inner.metadata_exif.as_ref().map(|x| x.get_full()) {
+            Some(Ok(data)) => crate::exif::camera_info(data),
+            _ => CameraInfo::default(),
+        }
+    }
+
+    /// Image dimensions declared in the Exif data
+    ///
+    /// These are the `PixelXDimension` and `PixelYDimension` fields. They can
+    /// differ from [`width`](Self::width) and [`height`](Self::height), for
+    /// example, if the image has been cropped or padded without updating the
+    /// Exif data. Unlike the decoded dimensions, they are not swapped for the
+    /// [`transformation_orientation`](Self::transformation_orientation).
+    /// `None` if the image has no Exif data or any of the fields is missing.
+    pub fn exif_dimensions(&self) -> Option<(u32, u32)> {
+        let data = self.inner.metadata_exif.as_ref()?.get_full().ok()?;
+        crate::exif::pixel_dimensions(data)
+    }
+
     pub fn transformation_orientation(&self) -> Option<Orientation> {
         self.inner.transformation_orientation
     }
@@ -383,13 +1590,64 @@ impl ImageDetails {
         self.inner.metadata_xmp.clone()
     }
 
+    /// Original JPEG if the image is a losslessly recompressed JPEG
+    ///
+    /// Only available if enabled via [`Loader::reconstruct_jpeg`] and if the
+    /// reconstruction succeeded.
+    pub fn jpeg_reconstruction(&self) -> Option<BinaryData> {
+        self.inner.jpeg_reconstruction.clone()
+    }
+
     pub fn metadata_key_value(&self) -> Option<&std::collections::BTreeMap<String, String>> {
         self.inner.metadata_key_value.as_ref()
     }
 
+    /// Byte range of the video embedded in a motion photo
+    ///
+    /// Motion photos store a short video after the image data. The range
+    /// refers to the bytes of the loaded file and can be used to extract the
+    /// video for playback. The video itself is not decoded by glycin.
+    ///
+    /// The values are also available via [`metadata_key_value`](Self::metadata_key_value)
+    /// with the keys [`KEY_MOTION_PHOTO_VIDEO_OFFSET`] and
+    /// [`KEY_MOTION_PHOTO_VIDEO_LENGTH`].
+    pub fn motion_photo_video(&self) -> Option<std::ops::Range<u64>> {
+        let key_value = self.inner.metadata_key_value.as_ref()?;
+        let offset = key_value
+            .get(KEY_MOTION_PHOTO_VIDEO_OFFSET)?
+            .parse::<u64>()
+            .ok()?;
+        let length = key_value
+            .get(KEY_MOTION_PHOTO_VIDEO_LENGTH)?
+            .parse::<u64>()
+            .ok()?;
+
+        Some(offset..offset.checked_add(length)?)
+    }
+
     pub fn transformation_ignore_exif(&self) -> bool {
         self.inner.transformation_ignore_exif
     }
+
+    /// Images stored in the container
+    ///
+    /// Some formats can store multiple images, like pages, variants of
+    /// different sizes, or independent images. A specific image can be
+    /// requested via [`FrameRequest::sub_image`]. The list is empty if the
+    /// format or loader doesn't support multiple images.
+    pub fn sub_images(&self) -> Vec<SubImageInfo> {
+        self.inner.sub_images.clone().unwrap_or_default()
+    }
+
+    /// Layers stored in the image
+    ///
+    /// Some formats store additional data like depth or normals as named
+    /// layers with their own channels, like render passes in OpenEXR files. A
+    /// specific layer can be requested via [`FrameRequest::layer`]. The list
+    /// is empty if the format or loader doesn't support layers.
+    pub fn layers(&self) -> Vec<LayerInfo> {
+        self.inner.layers.clone().unwrap_or_default()
+    }
 }
 
 /// A frame of an image often being the complete image
@@ -404,9 +1662,96 @@ pub struct Frame {
     pub(crate) delay: Option<std::time::Duration>,
     pub(crate) details: Arc<glycin_utils::FrameDetails>,
     pub(crate) color_state: ColorState,
+    pub(crate) byte_order: ByteOrder,
+    pub(crate) timings: Option<Timings>,
+    pub(crate) icc_error: Option<Arc<String>>,
+    pub(crate) color_fallback: Option<ColorFallback>,
+    /// The colors have been converted via the ICC profile in the details
+    pub(crate) icc_profile_applied: bool,
 }
 
 impl Frame {
+    /// Create a frame from raw pixel data
+    ///
+    /// The `buffer` has to contain `height` rows of `stride` bytes in the
+    /// given `memory_format`. This is useful for tests and for creating
+    /// frames that don't come from a loader. The frame has no further
+    /// details and multi-byte channels are in native byte order.
+    pub fn from_raw(
+        buffer: glib::Bytes,
+        width: u32,
+        height: u32,
+        stride: u32,
+        memory_format: MemoryFormat,
+        color_state: ColorState,
+    ) -> Result<Self, Error> {
+        crate::dbus::validate_frame_layout(
+            width,
+            height,
+            stride,
+            memory_format,
+            buffer.len(),
+            &(width, height, stride, memory_format),
+        )?;
+
+        Ok(Frame {
+            buffer,
+            width,
+            height,
+            stride,
+            memory_format,
+            delay: None,
+            details: Default::default(),
+            color_state,
+            byte_order: ByteOrder::Native,
+            timings: None,
+            icc_error: None,
+            color_fallback: None,
+            icc_profile_applied: false,
+        })
+    }
+
+    /// Copy of the region `(x, y, width, height)` of the frame
+    fn crop(&self, (x, y, width, height): (u32, u32, u32, u32)) -> Result<Frame, Error> {
+        let pixel_n_bytes = self.memory_format.n_bytes().usize();
+        let offset = x.try_usize()?.smul(pixel_n_bytes)?;
+        let row_n_bytes = width.try_usize()?.smul(pixel_n_bytes)?;
+
+        let mut buf = Vec::with_capacity(row_n_bytes.smul(height.try_usize()?)?);
+        for row in self
+            .buf_slice()
+            .chunks(self.stride.try_usize()?)
+            .skip(y.try_usize()?)
+            .take(height.try_usize()?)
+        {
+            buf.extend_from_slice(row.get(offset..offset.sadd(row_n_bytes)?).ok_or_else(|| {
+                Error::TextureWrongSize {
+                    texture_size: self.buffer.len(),
+                    frame: format!("{self:?}"),
+                }
+            })?);
+        }
+
+        let frame = Frame {
+            buffer: glib::Bytes::from_owned(buf),
+            width,
+            height,
+            stride: row_n_bytes.try_u32()?,
+            ..self.clone()
+        };
+
+        crate::dbus::validate_frame_layout(
+            frame.width,
+            frame.height,
+            frame.stride,
+            frame.memory_format,
+            frame.buffer.len(),
+            &frame,
+        )?;
+
+        Ok(frame)
+    }
+
     pub fn buf_bytes(&self) -> glib::Bytes {
         self.buffer.clone()
     }
@@ -434,6 +1779,88 @@ impl Frame {
         self.memory_format
     }
 
+    /// Returns `true` if the color channels are premultiplied with alpha
+    pub fn is_premultiplied(&self) -> bool {
+        self.memory_format.is_premultiplied()
+    }
+
+    /// Copy of the frame with straight, not premultiplied, alpha
+    ///
+    /// If the frame is not premultiplied, an unchanged copy is returned.
+    pub fn to_straight_alpha(&self) -> Result<Frame, Error> {
+        self.to_memory_format(self.memory_format.straight_alpha())
+    }
+
+    /// Copy of the frame with premultiplied alpha
+    ///
+    /// If the frame has no alpha channel or is already premultiplied, an
+    /// unchanged copy is returned. See [`MemoryFormat::premultiplied_alpha`]
+    /// for the resulting memory format.
+    pub fn to_premultiplied(&self) -> Result<Frame, Error> {
+        self.to_memory_format(self.memory_format.premultiplied_alpha())
+    }
+
+    pub(crate) fn to_memory_format(&self, memory_format: MemoryFormat) -> Result<Frame, Error> {
+        if memory_format == self.memory_format {
+            return Ok(self.clone());
+        }
+
+        let src_pixel_n_bytes = self.memory_format.n_bytes().usize();
+        let src_row_n_bytes = self.width.try_usize()?.smul(src_pixel_n_bytes)?;
+        let pixel_n_bytes = memory_format.n_bytes().usize();
+        let stride = self.width.smul(memory_format.n_bytes().u32())?;
+
+        let mut buf = vec![0; stride.try_usize()?.smul(self.height.try_usize()?)?];
+
+        // The conversion works on data in native byte order
+        let swap_src =
+            !self.byte_order.is_native() && self.memory_format.channel_type().size() == 2;
+        let swap_target = !self.byte_order.is_native() && memory_format.channel_type().size() == 2;
+
+        let mut src_buf = self.buf_slice().to_vec();
+        if swap_src {
+            swap_bytes(&mut src_buf, self.stride.try_usize()?, src_row_n_bytes);
+        }
+
+        let src_rows = src_buf.chunks(self.stride.try_usize()?);
+        for (src_row, row) in src_rows.zip(buf.chunks_exact_mut(stride.try_usize()?)) {
+            let src_row = src_row
+                .get(..src_row_n_bytes)
+                .ok_or(Error::TextureWrongSize {
+                    texture_size: self.buffer.len(),
+                    frame: format!("{self:?}"),
+                })?;
+
+            for (src, target) in src_row
+                .chunks_exact(src_pixel_n_bytes)
+                .zip(row.chunks_exact_mut(pixel_n_bytes))
+            {
+                MemoryFormat::transform(self.memory_format, src, memory_format, target);
+            }
+        }
+
+        if swap_target {
+            let stride = stride.try_usize()?;
+            swap_bytes(&mut buf, stride, stride);
+        }
+
+        Ok(Frame {
+            buffer: glib::Bytes::from_owned(buf),
+            stride,
+            memory_format,
+            ..self.clone()
+        })
+    }
+
+    /// Byte order of 16-bit channels
+    ///
+    /// Only differs from [`ByteOrder::Native`] if a different byte order has
+    /// been requested via [`Loader::byte_order`]. Textures created from the
+    /// frame assume data in the system's byte order.
+    pub fn byte_order(&self) -> ByteOrder {
+        self.byte_order
+    }
+
     pub fn color_state(&self) -> &ColorState {
         &self.color_state
     }
@@ -446,9 +1873,28 @@ impl Frame {
     }
 
     pub fn details(&self) -> FrameDetails {
-        FrameDetails::new(self.details.clone())
+        FrameDetails::new(
+            self.details.clone(),
+            self.icc_error.clone(),
+            self.color_fallback,
+        )
+    }
+
+    /// Time spent on the steps of loading the frame
+    ///
+    /// Only available if enabled via [`Loader::collect_timings`].
+    pub fn timings(&self) -> Option<Timings> {
+        self.timings
     }
 
+    /// Create a GDK texture from the frame
+    ///
+    /// The memory format is passed to GDK as is. GDK converts formats with
+    /// straight alpha, like the ones selected via
+    /// [`Loader::accepted_memory_formats`], to premultiplied alpha when the
+    /// texture is used, since it composites with premultiplied alpha. The data
+    /// are assumed to be in the system's byte order. For frames with a
+    /// different [`Frame::byte_order`], use [`Frame::texture_premultiplied`].
     #[cfg(feature = "gdk4")]
     pub fn texture(&self) -> gdk::Texture {
         let color_state = crate::util::gdk_color_state(&self.color_state).unwrap_or_else(|_| {
@@ -466,6 +1912,365 @@ impl Frame {
             .set_color_state(&color_state)
             .build()
     }
+
+    /// Create a GDK texture with premultiplied alpha in native byte order
+    ///
+    /// Unlike [`Frame::texture`], the data are converted before creating the
+    /// texture if necessary. Frames with straight alpha are converted as by
+    /// [`Frame::to_premultiplied`] and 16-bit channels are converted to the
+    /// system's byte order. This guarantees data that GDK can composite
+    /// without further conversion and allows to do the conversion on a
+    /// different thread than the rendering.
+    #[cfg(feature = "gdk4")]
+    pub fn texture_premultiplied(&self) -> Result<gdk::Texture, Error> {
+        let frame = self.to_premultiplied()?;
+
+        let frame =
+            if frame.byte_order.is_native() || frame.memory_format.channel_type().size() != 2 {
+                frame
+            } else {
+                let mut buf = frame.buf_slice().to_vec();
+                let row_n_bytes = frame
+                    .width
+                    .try_usize()?
+                    .smul(frame.memory_format.n_bytes().usize())?;
+                swap_bytes(&mut buf, frame.stride.try_usize()?, row_n_bytes);
+
+                Frame {
+                    buffer: glib::Bytes::from_owned(buf),
+                    byte_order: ByteOrder::Native,
+                    ..frame
+                }
+            };
+
+        Ok(frame.texture())
+    }
+
+    /// Copy of the frame scaled down to the given size
+    ///
+    /// Large reductions are done in two passes, a fast one that reduces the
+    /// frame to twice the target size and a second one with the given
+    /// `filter`. Frames with straight alpha are premultiplied for scaling and
+    /// converted back afterwards. The returned frame has the same memory
+    /// format as the original frame.
+    ///
+    /// Frames with 16-bit or float channels are scaled as 32-bit float RGB or
+    /// RGBA. The frame is never scaled up. If `width` or `height` are larger than the frame's
+    /// dimensions, the frame's dimensions are used instead.
+    #[cfg(feature = "downscale")]
+    pub fn downscale(
+        &self,
+        width: u32,
+        height: u32,
+        filter: DownscaleFilter,
+    ) -> Result<Frame, Error> {
+        let memory_format = self.memory_format;
+        let is_u8 = memory_format.channel_type() == glycin_common::ChannelType::U8;
+
+        if width == 0 || height == 0 {
+            return Err(Error::zero_dimensions(width, format!("{width}x{height}")));
+        }
+
+        let width = width.min(self.width);
+        let height = height.min(self.height);
+
+        let src = if !is_u8 && memory_format.has_alpha() {
+            self.to_memory_format(MemoryFormat::R32g32b32a32FloatPremultiplied)?
+        } else if !is_u8 {
+            self.to_memory_format(MemoryFormat::R32g32b32Float)?
+        } else if memory_format.has_alpha() && !memory_format.is_premultiplied() {
+            self.to_premultiplied()?
+        } else {
+            self.clone()
+        };
+
+        // Remove stride since the image buffers don't support it
+        let src_pixel_n_bytes = src.memory_format.n_bytes().usize();
+        let src_row_n_bytes = src.width.try_usize()?.smul(src_pixel_n_bytes)?;
+        let mut buf = Vec::with_capacity(src_row_n_bytes.smul(src.height.try_usize()?)?);
+        for row in src
+            .buf_slice()
+            .chunks(src.stride.try_usize()?)
+            .take(src.height.try_usize()?)
+        {
+            buf.extend_from_slice(row.get(..src_row_n_bytes).ok_or_else(|| {
+                Error::TextureWrongSize {
+                    texture_size: src.buffer.len(),
+                    frame: format!("{src:?}"),
+                }
+            })?);
+        }
+
+        let buf = match (is_u8, src.memory_format.n_channels()) {
+            (true, 1) => downscale_buf::<image::Luma<u8>>(buf, &src, width, height, filter),
+            (true, 2) => downscale_buf::<image::LumaA<u8>>(buf, &src, width, height, filter),
+            (true, 3) => downscale_buf::<image::Rgb<u8>>(buf, &src, width, height, filter),
+            (true, 4) => downscale_buf::<image::Rgba<u8>>(buf, &src, width, height, filter),
+            (false, 3) => downscale_buf_f32::<image::Rgb<f32>>(&buf, &src, width, height, filter),
+            (false, 4) => downscale_buf_f32::<image::Rgba<f32>>(&buf, &src, width, height, filter),
+            _ => None,
+        }
+        .ok_or_else(|| Error::TextureWrongSize {
+            texture_size: src.buffer.len(),
+            frame: format!("{src:?}"),
+        })?;
+
+        let frame = Frame {
+            buffer: glib::Bytes::from_owned(buf),
+            width,
+            height,
+            stride: width.smul(src.memory_format.n_bytes().u32())?,
+            ..src
+        };
+
+        frame.to_memory_format(memory_format)
+    }
+
+    /// ThumbHash of the frame as base64 string
+    ///
+    /// A [ThumbHash](https://evanw.github.io/thumbhash/) is a very compact
+    /// placeholder for an image that also encodes the aspect ratio and the
+    /// transparency. The frame is converted to
+    /// [`MemoryFormat::R8g8b8a8`] and scaled down to at most 100×100 pixels
+    /// via [`Frame::downscale()`] to compute the hash. The color state of the
+    /// frame is not taken into account.
+    #[cfg(feature = "thumbhash")]
+    pub fn thumbhash(&self) -> Result<String, Error> {
+        let (width, height) = crate::thumbhash::encode_dimensions(self.width, self.height);
+
+        let frame = self.to_memory_format(MemoryFormat::R8g8b8a8)?.downscale(
+            width,
+            height,
+            DownscaleFilter::Triangle,
+        )?;
+
+        let row_n_bytes = frame.width.try_usize()?.smul(4)?;
+        let mut rgba = Vec::with_capacity(row_n_bytes.smul(frame.height.try_usize()?)?);
+        for row in frame
+            .buf_slice()
+            .chunks(frame.stride.try_usize()?)
+            .take(frame.height.try_usize()?)
+        {
+            rgba.extend_from_slice(row.get(..row_n_bytes).ok_or_else(|| {
+                Error::TextureWrongSize {
+                    texture_size: frame.buffer.len(),
+                    frame: format!("{frame:?}"),
+                }
+            })?);
+        }
+
+        let hash = crate::thumbhash::encode(frame.width, frame.height, &rgba);
+
+        Ok(glib::base64_encode(&hash).to_string())
+    }
+
+    /// Placeholder frame from a ThumbHash
+    ///
+    /// The `thumbhash` is a base64 string as returned by
+    /// [`Frame::thumbhash()`]. The returned frame is at most 32×32 pixels
+    /// large, has the approximate aspect ratio of the original image, and
+    /// uses [`MemoryFormat::R8g8b8a8`].
+    #[cfg(feature = "thumbhash")]
+    pub fn from_thumbhash(thumbhash: &str) -> Result<Frame, Error> {
+        let hash = glib::base64_decode(thumbhash);
+        let (width, height, rgba) = crate::thumbhash::decode(&hash)?;
+
+        Ok(Frame {
+            buffer: glib::Bytes::from_owned(rgba),
+            width,
+            height,
+            stride: width.smul(MemoryFormat::R8g8b8a8.n_bytes().u32())?,
+            memory_format: MemoryFormat::R8g8b8a8,
+            delay: None,
+            details: Default::default(),
+            color_state: ColorState::Srgb,
+            byte_order: ByteOrder::Native,
+            timings: None,
+            icc_error: None,
+            color_fallback: None,
+            icc_profile_applied: false,
+        })
+    }
+
+    /// Data and metadata for creating a `wgpu::Texture`
+    ///
+    /// The frame is converted to a memory format supported by wgpu if
+    /// necessary and the rows are padded to
+    /// [`WGPU_BYTES_PER_ROW_ALIGNMENT`](crate::WGPU_BYTES_PER_ROW_ALIGNMENT).
+    #[cfg(feature = "wgpu")]
+    pub fn wgpu_texture(&self) -> Result<crate::WgpuTexture, Error> {
+        crate::wgpu::texture(self)
+    }
+}
+
+/// Swaps the bytes of 16-bit channels, leaving the padding of rows untouched
+fn swap_bytes(buf: &mut [u8], stride: usize, row_n_bytes: usize) {
+    for row in buf.chunks_mut(stride.max(1)) {
+        let n_bytes = row_n_bytes.min(row.len());
+        if let Some(row) = row.get_mut(..n_bytes) {
+            row.chunks_exact_mut(2).for_each(|x| x.swap(0, 1));
+        }
+    }
+}
+
+/// Filters for [`Frame::downscale()`]
+#[cfg(feature = "downscale")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DownscaleFilter {
+    /// Nearest neighbor, fastest but with aliasing
+    Nearest,
+    /// Linear filter
+    #[default]
+    Triangle,
+    /// Cubic filter
+    CatmullRom,
+    /// Gaussian filter, softer result
+    Gaussian,
+    /// Lanczos filter with window 3, sharpest result
+    Lanczos3,
+}
+
+#[cfg(feature = "downscale")]
+impl From<DownscaleFilter> for image::imageops::FilterType {
+    fn from(filter: DownscaleFilter) -> Self {
+        match filter {
+            DownscaleFilter::Nearest => Self::Nearest,
+            DownscaleFilter::Triangle => Self::Triangle,
+            DownscaleFilter::CatmullRom => Self::CatmullRom,
+            DownscaleFilter::Gaussian => Self::Gaussian,
+            DownscaleFilter::Lanczos3 => Self::Lanczos3,
+        }
+    }
+}
+
+/// Successively halved levels of a frame
+///
+/// Returned by [`Image::pyramid()`]. Each level has half the width and height
+/// of the previous level, rounded up, until a size of 1×1 is reached. A level
+/// is only created when it's requested, by scaling down the previous level
+/// with [`DownscaleFilter::Lanczos3`]. Only the last returned level is kept,
+/// such that levels that are no longer used can be freed.
+#[cfg(feature = "downscale")]
+#[derive(Debug)]
+pub struct Pyramid {
+    level: Option<Frame>,
+    n_level: u32,
+    levels: u32,
+}
+
+#[cfg(feature = "downscale")]
+impl Pyramid {
+    fn new(frame: Frame, levels: u32) -> Self {
+        Self {
+            level: Some(frame),
+            n_level: 0,
+            levels,
+        }
+    }
+}
+
+#[cfg(feature = "downscale")]
+impl Iterator for Pyramid {
+    type Item = Result<Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.n_level >= self.levels {
+            return None;
+        }
+
+        let previous = self.level.take()?;
+
+        let level = if self.n_level == 0 {
+            previous
+        } else if previous.width() == 1 && previous.height() == 1 {
+            return None;
+        } else {
+            let width = previous.width().div_ceil(2);
+            let height = previous.height().div_ceil(2);
+            match previous.downscale(width, height, DownscaleFilter::Lanczos3) {
+                Ok(level) => level,
+                Err(err) => return Some(Err(err)),
+            }
+        };
+
+        self.n_level = self.n_level.saturating_add(1);
+        self.level = Some(level.clone());
+
+        Some(Ok(level))
+    }
+}
+
+/// Same as [`downscale_buf`] for native endian `f32` samples
+#[cfg(feature = "downscale")]
+fn downscale_buf_f32<T: image::Pixel<Subpixel = f32> + 'static>(
+    buf: &[u8],
+    frame: &Frame,
+    width: u32,
+    height: u32,
+    filter: DownscaleFilter,
+) -> Option<Vec<u8>> {
+    let samples = buf
+        .chunks_exact(4)
+        .filter_map(|x| x.try_into().ok())
+        .map(f32::from_ne_bytes)
+        .collect();
+
+    let samples = downscale_buf::<T>(samples, frame, width, height, filter)?;
+
+    Some(samples.into_iter().flat_map(f32::to_ne_bytes).collect())
+}
+
+#[cfg(feature = "downscale")]
+fn downscale_buf<T: image::Pixel + 'static>(
+    buf: Vec<T::Subpixel>,
+    frame: &Frame,
+    width: u32,
+    height: u32,
+    filter: DownscaleFilter,
+) -> Option<Vec<T::Subpixel>> {
+    use image::imageops;
+
+    let img = image::ImageBuffer::<T, _>::from_raw(frame.width, frame.height, buf)?;
+
+    // Use a fast first pass for large reductions
+    let rough_width = width.saturating_mul(2);
+    let rough_height = height.saturating_mul(2);
+    let img = if rough_width < frame.width && rough_height < frame.height {
+        imageops::resize(
+            &img,
+            rough_width,
+            rough_height,
+            imageops::FilterType::Nearest,
+        )
+    } else {
+        img
+    };
+
+    Some(imageops::resize(&img, width, height, filter.into()).into_raw())
+}
+
+/// Time spent on the steps of loading a frame
+///
+/// Steps that were not necessary for the frame have a duration of zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Timings {
+    /// Time until the loader process was ready for the image
+    ///
+    /// This includes spawning the process if no suitable process was
+    /// available in the [`Pool`]. It's the same for all frames of an image.
+    pub spawn: std::time::Duration,
+    /// Time the loader needed to decode the frame
+    pub decode: std::time::Duration,
+    /// Time for applying the image orientation
+    pub orientation: std::time::Duration,
+    /// Time for applying the ICC profile
+    pub icc_profile: std::time::Duration,
+    /// Time for applying the 3D LUT
+    pub lut: std::time::Duration,
+    /// Time for converting the memory format
+    pub memory_format: std::time::Duration,
 }
 
 #[derive(Debug, Clone)]
@@ -473,6 +2278,22 @@ impl Frame {
 /// Request information to get a specific frame
 pub struct FrameRequest {
     pub(crate) request: glycin_utils::FrameRequest,
+    clip_space: CoordinateSpace,
+    raw: bool,
+}
+
+/// Coordinate space for [`FrameRequest::clip`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CoordinateSpace {
+    /// Coordinates of the image as stored in the file, before applying the
+    /// orientation
+    #[default]
+    Stored,
+    /// Coordinates of the image after applying the orientation
+    ///
+    /// These coordinates match the dimensions reported in [`ImageDetails`].
+    Oriented,
 }
 
 impl Default for FrameRequest {
@@ -486,7 +2307,11 @@ impl FrameRequest {
         let mut request = glycin_utils::FrameRequest::default();
         request.loop_animation = true;
 
-        Self { request }
+        Self {
+            request,
+            clip_space: CoordinateSpace::default(),
+            raw: false,
+        }
     }
 
     pub fn scale(mut self, width: u32, height: u32) -> Self {
@@ -494,11 +2319,32 @@ impl FrameRequest {
         self
     }
 
+    /// Only decode the given region of the image
+    ///
+    /// By default, the coordinates refer to the image as stored in the file.
+    /// For images with an orientation, like rotated photos, this differs from
+    /// the oriented image and the dimensions in [`ImageDetails`]. Use
+    /// [`clip_space`](Self::clip_space) with [`CoordinateSpace::Oriented`] to
+    /// specify the region in the coordinates of the oriented image instead.
+    ///
+    /// The returned frame has the orientation applied in both cases, if
+    /// [`Loader::apply_transformations`] is enabled.
     pub fn clip(mut self, x: u32, y: u32, width: u32, height: u32) -> Self {
         self.request.clip = Some((x, y, width, height));
         self
     }
 
+    /// Sets the coordinate space of [`clip`](Self::clip)
+    ///
+    /// The default is [`CoordinateSpace::Stored`]. With
+    /// [`CoordinateSpace::Oriented`], loading the frame fails with
+    /// [`Error::RegionOutsideImage`] if the clip isn't within the oriented
+    /// image.
+    pub fn clip_space(mut self, clip_space: CoordinateSpace) -> Self {
+        self.clip_space = clip_space;
+        self
+    }
+
     /// Controls if first frame is returned after last frame
     ///
     /// By default, this option is set to `true`, returning the first frame, if
@@ -507,16 +2353,83 @@ impl FrameRequest {
         self.request.loop_animation = loop_animation;
         self
     }
+
+    /// Request an auxiliary image instead of the main image
+    ///
+    /// Currently, the HEIF loader supports [`AuxiliaryImage::Alpha`] and the
+    /// JPEG loader supports [`AuxiliaryImage::Depth`]. Other loaders return
+    /// the main image.
+    pub fn auxiliary_image(mut self, auxiliary_image: AuxiliaryImage) -> Self {
+        self.request.auxiliary_image = Some(auxiliary_image);
+        self
+    }
+
+    /// Return the frame exactly as provided by the loader
+    ///
+    /// The buffer, memory format, and stride are passed through unchanged.
+    /// The orientation, ICC profile, and memory format conversion are not
+    /// applied, independent of the settings of the [`Loader`]. The
+    /// [`Frame::color_state`] is [`ColorState::Unspecified`].
+    ///
+    /// This is useful for archival or forensic tools that need the decoder
+    /// output verbatim.
+    pub fn raw(mut self, raw: bool) -> Self {
+        self.raw = raw;
+        self
+    }
+
+    /// Set the antialiasing for rendering vector images
+    ///
+    /// Lower quality renders faster, which is useful when rendering many
+    /// images, for example, for icon caches. Currently, only the SVG loader
+    /// supports this option. By default, the loader's default quality is
+    /// used.
+    pub fn antialias(mut self, antialias: Antialias) -> Self {
+        self.request.antialias = Some(antialias);
+        self
+    }
+
+    /// Request the image with this index from [`ImageDetails::sub_images`]
+    ///
+    /// Without this option, loaders return the default image of the
+    /// container, like the primary image of a HEIF file. Currently, this
+    /// option is supported for HEIF items, KTX2 mipmap levels, and pages of
+    /// TIFFs. Loaders for other formats ignore it and return the main image.
+    pub fn sub_image(mut self, index: u32) -> Self {
+        self.request.sub_image = Some(index);
+        self
+    }
+
+    /// Request the layer with this name from [`ImageDetails::layers`]
+    ///
+    /// The layer is returned with floating point channels. Layers without
+    /// color channels, like depth, are returned as gray. Currently, only the
+    /// OpenEXR loader supports this option. Other loaders return the main
+    /// image.
+    pub fn layer(mut self, name: impl Into<String>) -> Self {
+        self.request.layer = Some(name.into());
+        self
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct FrameDetails {
     inner: Arc<glycin_utils::FrameDetails>,
+    icc_error: Option<Arc<String>>,
+    color_fallback: Option<ColorFallback>,
 }
 
 impl FrameDetails {
-    fn new(inner: Arc<glycin_utils::FrameDetails>) -> Self {
-        Self { inner }
+    fn new(
+        inner: Arc<glycin_utils::FrameDetails>,
+        icc_error: Option<Arc<String>>,
+        color_fallback: Option<ColorFallback>,
+    ) -> Self {
+        Self {
+            inner,
+            icc_error,
+            color_fallback,
+        }
     }
 
     pub fn color_cicp(&self) -> Option<crate::Cicp> {
@@ -525,10 +2438,36 @@ impl FrameDetails {
             .and_then(|x| crate::Cicp::from_bytes(&x).ok())
     }
 
+    /// CICP as stored by the loader
+    ///
+    /// The components are in the order color primaries, transfer
+    /// characteristics, matrix coefficients, and video full range flag. Unlike
+    /// [`FrameDetails::color_cicp`], the values are returned even if they are
+    /// not known to glycin.
+    pub fn color_cicp_raw(&self) -> Option<[u8; 4]> {
+        self.inner.color_cicp
+    }
+
     pub fn color_icc_profile(&self) -> Option<BinaryData> {
         self.inner.color_icc_profile.clone()
     }
 
+    /// Reason why the ICC profile could not be applied
+    ///
+    /// If this is set, the colors of the frame are untransformed. See
+    /// [`Loader::icc_error_policy`] for how these failures are handled.
+    pub fn color_icc_error(&self) -> Option<&str> {
+        self.icc_error.as_deref().map(String::as_str)
+    }
+
+    /// Color information that was used since the ICC profile could not be
+    /// applied
+    ///
+    /// Only set if [`color_icc_error`](Self::color_icc_error) is set.
+    pub fn color_fallback(&self) -> Option<ColorFallback> {
+        self.color_fallback
+    }
+
     pub fn info_alpha_channel(&self) -> Option<bool> {
         self.inner.info_alpha_channel
     }
@@ -537,13 +2476,68 @@ impl FrameDetails {
         self.inner.info_bit_depth
     }
 
+    /// Bit depth of each channel in the source image
+    ///
+    /// Only available if the channels have different depths, like for RGB565
+    /// or RGB10A2. The depths are in the order R, G, B, and A, independent of
+    /// the order in the source format. The frame has the same channels, but
+    /// expanded to a uniform depth, which is at least
+    /// [`info_bit_depth`](Self::info_bit_depth).
+    pub fn channel_bit_depths(&self) -> Option<&[u8]> {
+        self.inner.channel_bit_depths.as_deref()
+    }
+
     pub fn info_grayscale(&self) -> Option<bool> {
         self.inner.info_grayscale
     }
 
+    /// Image is stored as CMYK and has been converted to RGB by the loader
+    pub fn info_cmyk(&self) -> Option<bool> {
+        self.inner.info_cmyk
+    }
+
+    /// Animation frame is stored with this blend method
+    ///
+    /// Frames are always returned composited onto the full canvas, such that
+    /// the blend method has already been applied. It only describes how the
+    /// file stores the animation. Use
+    /// [`NewFrame::set_blend`](crate::NewFrame::set_blend) to set the method
+    /// when creating animations.
+    pub fn info_animation_blend(&self) -> Option<FrameBlend> {
+        self.inner.info_animation_blend
+    }
+
+    /// Animation frame is stored with this dispose method
+    ///
+    /// See [`info_animation_blend`](Self::info_animation_blend).
+    pub fn info_animation_dispose(&self) -> Option<FrameDispose> {
+        self.inner.info_animation_dispose
+    }
+
     pub fn n_frame(&self) -> Option<u64> {
         self.inner.n_frame
     }
+
+    /// Time at which the animation frame is shown
+    ///
+    /// This is the sum of the [`Frame::delay`] of all previous frames, but
+    /// computed from the exact delays stored in the image. Scheduling frames
+    /// by their timestamp therefore avoids a drift that rounded delays can
+    /// accumulate, for example, to keep an animation in sync with audio. The
+    /// timestamp starts at zero again when the animation loops. `None` for
+    /// still images and for loaders that don't provide timestamps.
+    pub fn timestamp(&self) -> Option<std::time::Duration> {
+        self.inner.timestamp
+    }
+
+    /// Risky or deprecated features used by the frame
+    ///
+    /// Contains the [`ImageDetails::advisories`] of the image, followed by
+    /// the ones that are specific to this frame. Only available if enabled
+    /// via [`Loader::collect_advisories`].
+    pub fn advisories(&self) -> &[String] {
+        self.inner.advisories.as_deref().unwrap_or_default()
+    }
 }
 
 #[cfg(test)]
@@ -557,4 +2551,55 @@ mod test {
             image.next_frame().await.unwrap();
         });
     }
+
+    #[cfg(feature = "downscale")]
+    #[test]
+    fn pyramid() {
+        let buffer = glib::Bytes::from_owned(vec![0x80_u8; 5 * 3 * 8]);
+        let frame = Frame::from_raw(
+            buffer,
+            5,
+            3,
+            5 * 8,
+            MemoryFormat::R16g16b16a16,
+            ColorState::Srgb,
+        )
+        .unwrap();
+
+        let levels = Pyramid::new(frame.clone(), 10)
+            .map(|level| {
+                let level = level.unwrap();
+                assert_eq!(level.memory_format(), MemoryFormat::R16g16b16a16);
+                (level.width(), level.height())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(levels, [(5, 3), (3, 2), (2, 1), (1, 1)]);
+
+        assert_eq!(Pyramid::new(frame.clone(), 2).count(), 2);
+        assert_eq!(Pyramid::new(frame, 0).count(), 0);
+    }
+
+    #[test]
+    fn decode_memory_estimate() {
+        assert_eq!(
+            frame_memory_estimate(10, 20, MemoryFormatSelection::R8g8b8),
+            10 * 20 * 3
+        );
+        assert_eq!(
+            frame_memory_estimate(
+                10,
+                20,
+                MemoryFormatSelection::G8 | MemoryFormatSelection::R16g16b16a16
+            ),
+            10 * 20 * 8
+        );
+        assert_eq!(
+            frame_memory_estimate(10, 20, MemoryFormatSelection::all()),
+            10 * 20 * 16
+        );
+        assert_eq!(
+            frame_memory_estimate(u32::MAX, u32::MAX, MemoryFormatSelection::all()),
+            u64::MAX
+        );
+    }
 }
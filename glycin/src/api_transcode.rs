@@ -0,0 +1,362 @@
+use futures_util::{Stream, StreamExt};
+
+use crate::api_common::Source;
+use crate::dbus::GFileWorker;
+use crate::error::ResultExt;
+use crate::{Creator, EncodedImage, ErrorCtx, FrameRequest, Loader, MimeType};
+
+/// Options for [`transcode`]
+#[derive(Debug, Clone, Default)]
+pub struct TranscodeOptions {
+    quality: Option<u8>,
+    compression: Option<u8>,
+    match_source_quality: bool,
+}
+
+impl TranscodeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the encoding quality, if supported by the target format
+    pub fn quality(&mut self, quality: u8) -> &mut Self {
+        self.quality = Some(quality);
+        self
+    }
+
+    /// Sets if the quality of the source image should be kept
+    ///
+    /// When converting a JPEG to JPEG, the quality of the source image is
+    /// estimated from its quantization tables and used for encoding. This
+    /// avoids degrading the image further by re-encoding it with a lower
+    /// quality, for example, when only changing the orientation. A quality
+    /// set via [`TranscodeOptions::quality`] takes precedence.
+    ///
+    /// The quality can only be detected for files and memfds, and only if the
+    /// quantization tables are stored within the first chunk that is read
+    /// from the source. Otherwise, the default quality of the encoder is used.
+    ///
+    /// This option is disabled by default.
+    pub fn match_source_quality(&mut self, match_source_quality: bool) -> &mut Self {
+        self.match_source_quality = match_source_quality;
+        self
+    }
+
+    /// Sets the compression level, if supported by the target format
+    ///
+    /// See [`Creator::set_encoding_compression`] for details.
+    pub fn compression(&mut self, compression: u8) -> &mut Self {
+        self.compression = Some(compression);
+        self
+    }
+}
+
+/// Convert an image into a different format
+///
+/// Loads the image via `loader` and encodes it as `target_mime`. The
+/// `key_value` metadata and ICC profiles are kept if the target format supports
+/// them. If the target format doesn't support ICC profiles, the colors are
+/// converted to sRGB instead.
+///
+/// For animated images, all frames are transcoded if the target format
+/// supports animations, keeping their delays. The frames are stored
+/// composited onto the full canvas, such that the result animates like the
+/// source. Otherwise, only the first frame is used.
+pub async fn transcode(
+    loader: Loader,
+    target_mime: MimeType,
+    options: &TranscodeOptions,
+) -> Result<EncodedImage, ErrorCtx> {
+    // The image is only completed once it's encoded
+    let progress = loader.progress.clone();
+    if let Some(progress) = &progress {
+        progress.defer_completion();
+    }
+
+    let result = transcode_image(loader, target_mime, options).await;
+
+    if let Some(progress) = progress {
+        progress.complete(&result);
+    }
+
+    result
+}
+
+async fn transcode_image(
+    mut loader: Loader,
+    target_mime: MimeType,
+    options: &TranscodeOptions,
+) -> Result<EncodedImage, ErrorCtx> {
+    let cancellable = loader.cancellable.clone();
+
+    let mut creator = Creator::new(target_mime.clone())
+        .await
+        .err_no_context(&cancellable)?;
+    creator.sandbox_selector(loader.sandbox_selector);
+    creator.cancellable(cancellable.clone());
+
+    let mut quality = options.quality;
+    if quality.is_none() && options.match_source_quality && target_mime == MimeType::JPEG {
+        quality = source_jpeg_quality(&loader).await;
+        tracing::debug!("Estimated quality of source JPEG: {quality:?}");
+    }
+
+    if let Some(quality) = quality {
+        if creator.set_encoding_quality(quality).is_err() {
+            tracing::debug!("Target format doesn't support encoding quality");
+        }
+    }
+
+    if let Some(compression) = options.compression {
+        if creator.set_encoding_compression(compression).is_err() {
+            tracing::debug!("Target format doesn't support encoding compression");
+        }
+    }
+
+    // Keep the original color data if the profile can be stored
+    let keep_icc_profile = creator.config.creator_color_icc_profile;
+    let supports_animation = creator.config.creator_animation;
+    loader.apply_icc_profile = !keep_icc_profile;
+
+    let image = loader.load().await?;
+
+    if let Some(key_value) = image.details().metadata_key_value() {
+        if creator.set_metadata_key_value(key_value.clone()).is_err() {
+            tracing::debug!("Target format doesn't support key-value metadata");
+        }
+    }
+
+    if let Some((width_inch, height_inch)) = image
+        .details()
+        .dimensions_inch()
+        .filter(|(width, height)| *width > 0. && *height > 0.)
+    {
+        let dpi_x = f64::from(image.details().width()) / width_inch;
+        let dpi_y = f64::from(image.details().height()) / height_inch;
+        if let Err(err) = creator.set_resolution(dpi_x, dpi_y) {
+            tracing::debug!("Not keeping the resolution: {err}");
+        }
+    }
+
+    loop {
+        let frame = match image
+            .specific_frame(FrameRequest::new().loop_animation(false))
+            .await
+        {
+            Ok(frame) => frame,
+            Err(err) if err.is_no_more_frames() => break,
+            Err(err) => return Err(err),
+        };
+
+        // Also keeps the ICC profile and the animation details if supported
+        creator
+            .add_frame_from(&frame)
+            .err_no_context(&cancellable)?;
+
+        if frame.delay().is_none() || !supports_animation {
+            break;
+        }
+    }
+
+    creator.create().await
+}
+
+/// Quality of the source image if it's a JPEG
+async fn source_jpeg_quality(loader: &Loader) -> Option<u8> {
+    let source = match &loader.source {
+        Source::File(file) => Source::File(file.clone()),
+        Source::Memfd(memfd) => Source::Memfd(memfd.clone()),
+        _ => return None,
+    };
+
+    let g_file_worker = GFileWorker::spawn(
+        source,
+        loader.pool.read_buffer_size(),
+        None,
+        loader.cancellable.clone(),
+    );
+    let head = g_file_worker.head().await.ok()?;
+
+    jpeg_quality(&head)
+}
+
+/// Standard luminance quantization table of the JPEG specification
+const STD_LUMINANCE_TABLE: [u8; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62, 18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113,
+    92, 49, 64, 78, 87, 103, 121, 120, 101, 72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+/// Position in [`STD_LUMINANCE_TABLE`] for the zigzag order of `DQT` segments
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// Estimate the quality of a JPEG from its luminance quantization table
+///
+/// Returns the quality that results in the same average scaling of the
+/// standard table as used by libjpeg.
+fn jpeg_quality(data: &[u8]) -> Option<u8> {
+    if data.get(..2)? != [0xFF, 0xD8] {
+        return None;
+    }
+
+    let mut pos = 2_usize;
+    loop {
+        let [0xFF, marker] = *data.get(pos..pos.checked_add(2)?)? else {
+            return None;
+        };
+        let length = u16::from_be_bytes(
+            data.get(pos.checked_add(2)?..pos.checked_add(4)?)?
+                .try_into()
+                .ok()?,
+        );
+        let segment =
+            data.get(pos.checked_add(4)?..pos.checked_add(2)?.checked_add(length.into())?)?;
+
+        match marker {
+            // DQT
+            0xDB => {
+                let mut tables = segment;
+                while let Some((info, rest)) = tables.split_first() {
+                    let precision_16bit = info >> 4 == 1;
+                    let table_len = if precision_16bit { 128 } else { 64 };
+                    let table = rest.get(..table_len)?;
+
+                    if info & 0x0F == 0 {
+                        let values = if precision_16bit {
+                            table
+                                .chunks_exact(2)
+                                .map(|x| x.iter().fold(0_u16, |a, b| a << 8 | u16::from(*b)))
+                                .collect::<Vec<_>>()
+                        } else {
+                            table.iter().copied().map(u16::from).collect()
+                        };
+
+                        let scaling = values
+                            .iter()
+                            .zip(ZIGZAG)
+                            .filter_map(|(value, index)| {
+                                let std_value = STD_LUMINANCE_TABLE.get(index)?;
+                                Some(f64::from(*value) * 100. / f64::from(*std_value))
+                            })
+                            .sum::<f64>()
+                            / 64.;
+
+                        let quality = if scaling <= 100. {
+                            (200. - scaling) / 2.
+                        } else {
+                            5000. / scaling
+                        };
+
+                        // The value is clamped to the range of `u8`
+                        #[allow(clippy::cast_possible_truncation)]
+                        return Some(quality.round().clamp(1., 100.) as u8);
+                    }
+
+                    tables = rest.get(table_len..)?;
+                }
+            }
+            // Start of scan, no more tables
+            0xDA => return None,
+            _ => {}
+        }
+
+        pos = pos.checked_add(2)?.checked_add(length.into())?;
+    }
+}
+
+#[test]
+fn jpeg_quality_test() {
+    let jpeg = |scale: u16| {
+        let table = ZIGZAG.map(|index| {
+            let std_value = STD_LUMINANCE_TABLE.get(index).copied().map_or(1, u16::from);
+            u8::try_from((std_value * scale + 50) / 100).unwrap_or(u8::MAX)
+        });
+
+        [
+            [0xFF, 0xD8].as_slice(),
+            // APP0 segment before the table
+            &[0xFF, 0xE0, 0, 4, 0, 0],
+            &[0xFF, 0xDB, 0, 67, 0],
+            &table,
+            &[0xFF, 0xDA],
+        ]
+        .concat()
+    };
+
+    // Scaling per libjpeg's `jpeg_quality_scaling`
+    assert_eq!(jpeg_quality(&jpeg(100)), Some(50));
+    assert_eq!(jpeg_quality(&jpeg(200 - 2 * 90)), Some(90));
+    assert_eq!(jpeg_quality(&jpeg(5000 / 25)), Some(25));
+
+    assert_eq!(jpeg_quality(b"\x89PNG"), None);
+}
+
+/// A single conversion for [`transcode_many`]
+#[derive(Debug)]
+pub struct TranscodeJob {
+    loader: Loader,
+    target_mime: MimeType,
+    options: TranscodeOptions,
+}
+
+static_assertions::assert_impl_all!(TranscodeJob: Send, Sync);
+
+impl TranscodeJob {
+    /// Convert the image from `loader` to `target_mime`
+    pub fn new(loader: Loader, target_mime: MimeType) -> Self {
+        Self {
+            loader,
+            target_mime,
+            options: TranscodeOptions::default(),
+        }
+    }
+
+    /// Sets the options for the conversion
+    pub fn options(&mut self, options: TranscodeOptions) -> &mut Self {
+        self.options = options;
+        self
+    }
+
+    /// Cancellable of the job
+    ///
+    /// This is the cancellable of the loader. Cancelling it only aborts this
+    /// job and not the other jobs of the batch.
+    pub fn cancellable(&self) -> gio::Cancellable {
+        self.loader.cancellable.clone()
+    }
+}
+
+/// Convert multiple images with bounded concurrency
+///
+/// Runs [`transcode`] for all `jobs` with at most `concurrency` conversions
+/// at the same time. A `concurrency` of zero is treated as one. The loaders
+/// and editors are taken from the pools of the jobs' loaders, such that
+/// [`PoolConfig::max_parallel_operations`](crate::PoolConfig::max_parallel_operations)
+/// applies as for individual conversions.
+///
+/// The stream returns the index of each job in `jobs` together with its
+/// result, in the order the conversions finish. A failed job doesn't abort the
+/// remaining jobs.
+///
+/// To show the combined progress of the batch, attach the loaders of the jobs
+/// to a [`ProgressAggregator`](crate::ProgressAggregator) via
+/// [`Loader::progress_aggregator`]. Jobs count as completed once their
+/// conversion is done.
+pub fn transcode_many(
+    jobs: impl IntoIterator<Item = TranscodeJob>,
+    concurrency: usize,
+) -> impl Stream<Item = (usize, Result<EncodedImage, ErrorCtx>)> + Send {
+    // Collected, such that the stream is `Send` for any iterator
+    let jobs = jobs.into_iter().collect::<Vec<_>>();
+
+    futures_util::stream::iter(jobs.into_iter().enumerate())
+        .map(|(index, job)| async move {
+            let result = transcode(job.loader, job.target_mime, &job.options).await;
+            (index, result)
+        })
+        .buffer_unordered(concurrency.max(1))
+}
@@ -0,0 +1,76 @@
+use crate::error::ResultExt;
+use crate::{Error, ErrorCtx, ImageDetails, Loader};
+
+/// Constraints for [`validate`]
+#[derive(Debug, Clone, Default)]
+pub struct ValidationConstraints {
+    max_width: Option<u32>,
+    max_height: Option<u32>,
+    max_pixels: Option<u64>,
+}
+
+impl ValidationConstraints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum width of the image
+    pub fn max_width(&mut self, max_width: u32) -> &mut Self {
+        self.max_width = Some(max_width);
+        self
+    }
+
+    /// Sets the maximum height of the image
+    pub fn max_height(&mut self, max_height: u32) -> &mut Self {
+        self.max_height = Some(max_height);
+        self
+    }
+
+    /// Sets the maximum number of pixels of the image
+    pub fn max_pixels(&mut self, max_pixels: u64) -> &mut Self {
+        self.max_pixels = Some(max_pixels);
+        self
+    }
+
+    fn check(&self, details: &ImageDetails) -> Result<(), Error> {
+        let (width, height) = (details.width(), details.height());
+
+        if width == 0 || height == 0 {
+            return Err(Error::zero_dimensions(width, format!("{width}x{height}")));
+        }
+
+        let exceeds_width = self.max_width.is_some_and(|x| width > x);
+        let exceeds_height = self.max_height.is_some_and(|x| height > x);
+        let exceeds_pixels = self
+            .max_pixels
+            .is_some_and(|x| u64::from(width).saturating_mul(u64::from(height)) > x);
+
+        if exceeds_width || exceeds_height || exceeds_pixels {
+            return Err(Error::ConstraintsViolated { width, height });
+        }
+
+        Ok(())
+    }
+}
+
+/// Check that a file is a decodable image within the given constraints
+///
+/// Only the image header is parsed by the loader, without decoding any frames.
+/// This is considerably cheaper than loading the image and requesting a
+/// frame, but doesn't detect errors in the image data itself.
+///
+/// Returns the image details if the image satisfies the constraints. The
+/// dimensions are checked after applying the image orientation.
+pub async fn validate(
+    loader: Loader,
+    constraints: &ValidationConstraints,
+) -> Result<ImageDetails, ErrorCtx> {
+    let cancellable = loader.cancellable.clone();
+
+    let image = loader.load().await?;
+    let details = image.details();
+
+    constraints.check(&details).err_no_context(&cancellable)?;
+
+    Ok(details)
+}
@@ -57,6 +57,8 @@ impl MimeType {
 
     pub const JXL: Self = Self::new_static("image/jxl");
 
+    pub const SVG: Self = Self::new_static("image/svg+xml");
+
     const EXTENSIONS: &[(Self, &'static str)] = &[
         (Self::AVIF, "avif"),
         (Self::BMP, "bmp"),
@@ -96,6 +98,55 @@ impl MimeType {
             .find(|x| x.0.as_str() == self.as_str())
             .map(|x| x.1)
     }
+
+    /// Returns `true` if a loader is installed for the format
+    pub async fn can_load(&self) -> bool {
+        Config::cached().await.image_loader.contains_key(self)
+    }
+
+    /// Returns `true` if an editor is installed for the format
+    ///
+    /// Which operations are supported can be checked via
+    /// [`ImageEditorConfig::operations`].
+    pub async fn can_edit(&self) -> bool {
+        Config::cached().await.image_editor.contains_key(self)
+    }
+
+    /// Returns `true` if new images of the format can be created
+    ///
+    /// This is the case if an installed editor supports the
+    /// [`Creator`](crate::Creator).
+    pub async fn can_create(&self) -> bool {
+        Config::cached()
+            .await
+            .image_editor
+            .get(self)
+            .is_some_and(|x| x.creator)
+    }
+
+    /// Largest width and height that the loader can return
+    ///
+    /// Frames requested via [`FrameRequest::scale`](crate::FrameRequest::scale)
+    /// with larger dimensions fail to load. Loaders announce their limit via
+    /// their config file, like `MaxDimensions = 32767;32767`. Currently, only
+    /// the SVG loader has a limit, since librsvg can't render images larger
+    /// than 32767×32767 pixels. Returns `None` if the loader doesn't announce
+    /// a limit or no loader is installed for the format.
+    ///
+    /// ```no_run
+    /// # async fn x() {
+    /// let max_dimensions = glycin::MimeType::SVG.max_dimensions().await;
+    /// let (width, height) = max_dimensions.unwrap_or((u32::MAX, u32::MAX));
+    /// let request = glycin::FrameRequest::new().scale(u32::min(50_000, width), u32::min(50_000, height));
+    /// # }
+    /// ```
+    pub async fn max_dimensions(&self) -> Option<(u32, u32)> {
+        Config::cached()
+            .await
+            .image_loader
+            .get(self)
+            .and_then(|x| x.max_dimensions)
+    }
 }
 
 impl From<&str> for MimeType {
@@ -130,6 +181,8 @@ pub struct ImageLoaderConfig {
     pub exec: PathBuf,
     pub expose_base_dir: bool,
     pub fontconfig: bool,
+    /// Largest width and height the loader can return
+    pub max_dimensions: Option<(u32, u32)>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -138,6 +191,7 @@ pub struct ConfigEntryHash {
     exec: PathBuf,
     expose_base_dir: bool,
     base_dir: Option<PathBuf>,
+    font_dir: Option<PathBuf>,
     sandbox_mechanism: SandboxMechanism,
 }
 
@@ -157,13 +211,20 @@ pub struct ImageEditorConfig {
     pub creator_color_icc_profile: bool,
     pub creator_encoding_quality: bool,
     pub creator_encoding_compression: bool,
+    pub creator_encoding_target_size: bool,
     pub creator_metadata_key_value: bool,
+    pub creator_animation: bool,
+    pub creator_resolution: bool,
+    pub creator_jpeg_lossless: bool,
+    pub creator_thumbnail: bool,
+    pub creator_progressive: bool,
 }
 
 impl ConfigEntry {
     pub fn hash_value(
         &self,
         base_dir: Option<PathBuf>,
+        font_dir: Option<PathBuf>,
         sandbox_mechanism: SandboxMechanism,
     ) -> ConfigEntryHash {
         ConfigEntryHash {
@@ -171,6 +232,7 @@ impl ConfigEntry {
             exec: self.exec().to_owned(),
             expose_base_dir: self.expose_base_dir(),
             base_dir,
+            font_dir,
             sandbox_mechanism,
         }
     }
@@ -220,9 +282,13 @@ impl Config {
     }
 
     pub fn editor(&self, mime_type: &MimeType) -> Result<&ImageEditorConfig, Error> {
-        self.image_editor
-            .get(mime_type)
-            .ok_or_else(|| Error::UnknownImageFormat(mime_type.to_string(), self.clone()))
+        self.image_editor.get(mime_type).ok_or_else(|| {
+            if self.image_loader.contains_key(mime_type) {
+                Error::EditingNotSupported(mime_type.to_string())
+            } else {
+                Error::UnknownImageFormat(mime_type.to_string(), self.clone())
+            }
+        })
     }
 
     async fn load() -> Self {
@@ -281,10 +347,19 @@ impl Config {
                             let fontconfig =
                                 keyfile.boolean(group, "Fontconfig").unwrap_or_default();
 
+                            let max_dimensions =
+                                match keyfile.integer_list(group, "MaxDimensions").as_deref() {
+                                    Ok(&[width, height]) => {
+                                        u32::try_from(width).ok().zip(u32::try_from(height).ok())
+                                    }
+                                    _ => None,
+                                };
+
                             let cfg = ImageLoaderConfig {
                                 exec: exec.into(),
                                 expose_base_dir,
                                 fontconfig,
+                                max_dimensions,
                             };
 
                             config.image_loader.insert(mime_type, cfg);
@@ -322,10 +397,34 @@ impl Config {
                                 .boolean(group, "CreatorEncodingQuality")
                                 .unwrap_or_default();
 
+                            let creator_encoding_target_size = keyfile
+                                .boolean(group, "CreatorEncodingTargetSize")
+                                .unwrap_or_default();
+
                             let creator_metadata_key_value = keyfile
                                 .boolean(group, "CreatorMetadataKeyValue")
                                 .unwrap_or_default();
 
+                            let creator_animation = keyfile
+                                .boolean(group, "CreatorAnimation")
+                                .unwrap_or_default();
+
+                            let creator_resolution = keyfile
+                                .boolean(group, "CreatorResolution")
+                                .unwrap_or_default();
+
+                            let creator_jpeg_lossless = keyfile
+                                .boolean(group, "CreatorJpegLossless")
+                                .unwrap_or_default();
+
+                            let creator_thumbnail = keyfile
+                                .boolean(group, "CreatorThumbnail")
+                                .unwrap_or_default();
+
+                            let creator_progressive = keyfile
+                                .boolean(group, "CreatorProgressive")
+                                .unwrap_or_default();
+
                             let cfg = ImageEditorConfig {
                                 exec: exec.into(),
                                 expose_base_dir,
@@ -335,7 +434,13 @@ impl Config {
                                 creator_color_icc_profile,
                                 creator_encoding_compression,
                                 creator_encoding_quality,
+                                creator_encoding_target_size,
                                 creator_metadata_key_value,
+                                creator_animation,
+                                creator_resolution,
+                                creator_jpeg_lossless,
+                                creator_thumbnail,
+                                creator_progressive,
                             };
 
                             config.image_editor.insert(mime_type, cfg);
@@ -5,6 +5,7 @@
 use std::io::{BufRead, Read};
 use std::mem;
 use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::FileExt;
 use std::os::unix::net::UnixStream;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -12,15 +13,15 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use futures_channel::oneshot;
-use futures_util::{future, FutureExt};
+use futures_util::{future, FutureExt, StreamExt};
 use gio::glib;
 use gio::prelude::*;
-use glycin_common::{MemoryFormatInfo, Operations};
+use glycin_common::{BinaryData, MemoryFormat, MemoryFormatInfo, Operations};
 use glycin_utils::safe_math::{SafeConversion, SafeMath};
 use glycin_utils::{
     CompleteEditorOutput, EditRequest, EncodedImage, EncodingOptions, Frame, FrameRequest, ImgBuf,
-    InitRequest, InitializationDetails, NewImage, RemoteEditableImage, RemoteError, RemoteImage,
-    SparseEditorOutput,
+    InitRequest, InitializationDetails, NewImage, RemoteColorProfile, RemoteEditableImage,
+    RemoteError, RemoteImage, SparseEditorOutput,
 };
 use gufo_common::cicp::Cicp;
 use gufo_common::math::ToI64;
@@ -30,15 +31,19 @@ use zbus::zvariant::{self, OwnedObjectPath};
 use crate::sandbox::Sandbox;
 use crate::util::{self, block_on, spawn_blocking, spawn_blocking_detached};
 use crate::{
-    api_loader, config, icc, orientation, ColorState, EditableImage, Error, Image, MimeType,
-    SandboxMechanism, Source,
+    api_loader, config, icc, linearize, lut, orientation, ColorState, ContentHash, EditableImage,
+    Error, IccErrorPolicy, Image, MimeType, SandboxMechanism, SeccompAction, Source,
 };
 
 /// Max texture size 8 GB in bytes
 pub(crate) const MAX_TEXTURE_SIZE: u64 = 8 * 10u64.pow(9);
 
+/// Called with the pid and exit status once the process exited
+pub type ProcessExitCallback = Box<dyn FnOnce(u32, Option<std::process::ExitStatus>) + Send>;
+
 #[derive(Debug)]
 pub struct RemoteProcess<P: ZbusProxy<'static> + 'static> {
+    pub pid: u32,
     dbus_connection: zbus::Connection,
     proxy: P,
     pub stderr_content: Arc<Mutex<String>>,
@@ -79,6 +84,10 @@ impl<P: ZbusProxy<'static>> RemoteProcess<P> {
         config_entry: config::ConfigEntry,
         sandbox_mechanism: SandboxMechanism,
         base_dir: Option<PathBuf>,
+        font_dir: Option<PathBuf>,
+        cache_dir: Option<PathBuf>,
+        seccomp_action: SeccompAction,
+        on_exit: Option<ProcessExitCallback>,
         cancellable: &gio::Cancellable,
     ) -> Result<Self, Error> {
         // UnixStream which facilitates the D-Bus connection. The stream is passed as
@@ -88,10 +97,19 @@ impl<P: ZbusProxy<'static>> RemoteProcess<P> {
         loader_stdin.set_nonblocking(true)?;
 
         let mut sandbox = Sandbox::new(sandbox_mechanism, config_entry.clone(), loader_stdin);
+        sandbox.set_seccomp_action(seccomp_action);
         // Mount dir that contains the file as read only for formats like SVG
         if let Some(base_dir) = &base_dir {
             sandbox.add_ro_bind(base_dir.clone());
         }
+        // Additional fonts for formats that render text
+        if let Some(font_dir) = font_dir {
+            sandbox.add_font_dir(font_dir);
+        }
+        // Only loaders that use fontconfig are allowed to write files
+        if let Some(cache_dir) = cache_dir.filter(|_| config_entry.fontconfig()) {
+            sandbox.set_cache_dir(cache_dir);
+        }
 
         let spawned_sandbox = sandbox.spawn().await?;
 
@@ -151,6 +169,9 @@ impl<P: ZbusProxy<'static>> RemoteProcess<P> {
                     "Process exited: {:?} {result:?}",
                     result.as_ref().ok().map(|x| x.code())
                 );
+                if let Some(on_exit) = on_exit {
+                    on_exit(child.id(), result.as_ref().ok().copied());
+                }
                 if let Err(err) = sender_child_return.send(result) {
                     tracing::debug!(
                         "Failed to send process return value to coordinating thread: {err:?}"
@@ -188,7 +209,8 @@ impl<P: ZbusProxy<'static>> RemoteProcess<P> {
             .build()
             .shared();
 
-        let subprocess_id = nix::unistd::Pid::from_raw(child_process.2.try_into().unwrap());
+        let pid = child_process.2;
+        let subprocess_id = nix::unistd::Pid::from_raw(pid.try_into().unwrap());
 
         futures_util::select! {
             _result = dbus_result.clone().fuse() => Ok(()),
@@ -220,6 +242,7 @@ impl<P: ZbusProxy<'static>> RemoteProcess<P> {
             .await?;
 
         Ok(Self {
+            pid,
             dbus_connection,
             proxy: decoding_instruction,
             stderr_content,
@@ -230,16 +253,29 @@ impl<P: ZbusProxy<'static>> RemoteProcess<P> {
         })
     }
 
+    /// Kills the process
+    ///
+    /// Processes can't be interrupted while they handle a request. This stops
+    /// them from doing work that is no longer needed.
+    pub fn terminate(&self) {
+        self.cancellable.cancel();
+    }
+
     fn init_request(
         &self,
         gfile_worker: &GFileWorker,
         mime_type: &MimeType,
     ) -> Result<InitRequest, Error> {
-        let (remote_reader, writer) = std::os::unix::net::UnixStream::pair()?;
-
-        gfile_worker.write_to(writer)?;
+        let fd = if let Some(memfd) = gfile_worker.memfd() {
+            // Reopen the memfd read-only and with its own file offset
+            OwnedFd::from(std::fs::File::open(crate::api_common::memfd_path(memfd))?)
+        } else {
+            let (remote_reader, writer) = std::os::unix::net::UnixStream::pair()?;
+            gfile_worker.write_to(writer)?;
+            OwnedFd::from(remote_reader)
+        };
 
-        let fd = zvariant::OwnedFd::from(OwnedFd::from(remote_reader));
+        let fd = zvariant::OwnedFd::from(fd);
 
         let mime_type = mime_type.to_string();
 
@@ -259,8 +295,19 @@ impl RemoteProcess<LoaderProxy<'static>> {
         &self,
         gfile_worker: GFileWorker,
         mime_type: &MimeType,
+        mut details: InitializationDetails,
     ) -> Result<RemoteImage, Error> {
-        let init_request = self.init_request(&gfile_worker, mime_type)?;
+        let mut init_request = match self.init_request(&gfile_worker, mime_type) {
+            Ok(init_request) => init_request,
+            // The reader already stopped, report why
+            Err(Error::InternalCommunicationCanceled) => {
+                gfile_worker.error().await?;
+                return Err(Error::InternalCommunicationCanceled);
+            }
+            Err(err) => return Err(err),
+        };
+        details.base_dir = init_request.details.base_dir.take();
+        init_request.details = details;
 
         let image_info = self.proxy.init(init_request).shared();
 
@@ -274,6 +321,11 @@ impl RemoteProcess<LoaderProxy<'static>> {
 
         let image_info = image_info.await?;
 
+        // Loaders read the complete stream during init, the hash is available now
+        if gfile_worker.verifies_content_hash() {
+            gfile_worker.error().await?;
+        }
+
         // Seal all memfds
         if let Some(exif) = &image_info.details.metadata_exif {
             seal_fd(exif).await?;
@@ -295,10 +347,31 @@ impl RemoteProcess<LoaderProxy<'static>> {
         loader_proxy.done().await.map_err(Into::into)
     }
 
+    /// Request the ICC profile from the loader without decoding a frame
+    pub async fn color_profile(&self, image: &Image) -> Result<Option<BinaryData>, Error> {
+        let loader_proxy = LoaderStateProxy::builder(&self.dbus_connection)
+            .destination("org.gnome.glycin")?
+            .path(image.frame_request_path())?
+            .build()
+            .await?;
+
+        let color_profile = loader_proxy.color_profile().await?;
+
+        if let Some(icc_profile) = &color_profile.icc_profile {
+            seal_fd(icc_profile).await?;
+        }
+
+        Ok(color_profile.icc_profile)
+    }
+
+    /// Request a frame from the loader
+    ///
+    /// If `raw` is set, the frame is returned without any processing.
     pub async fn request_frame(
         &self,
         frame_request: FrameRequest,
         image: &Image,
+        raw: bool,
     ) -> Result<api_loader::Frame, Error> {
         let frame_request_path = image.frame_request_path();
 
@@ -308,25 +381,100 @@ impl RemoteProcess<LoaderProxy<'static>> {
             .build()
             .await?;
 
-        let mut frame = loader_proxy.frame(frame_request).await?;
+        let mut timings = api_loader::Timings {
+            spawn: image.spawn_duration,
+            ..Default::default()
+        };
+
+        let start = Instant::now();
+        let frame = loader_proxy.frame(frame_request).await?;
+        timings.decode = start.elapsed();
+
+        if raw {
+            return Self::raw_frame(frame, image, timings).await;
+        }
+
+        self.process_frame(frame, image, timings).await
+    }
+
+    /// Same as [`Self::request_frame`] but with intermediate frames
+    ///
+    /// The `progress` function is called for every intermediate frame the
+    /// loader provides before the final frame is returned.
+    pub async fn request_frame_progressive(
+        &self,
+        frame_request: FrameRequest,
+        image: &Image,
+        mut progress: impl FnMut(api_loader::Frame),
+    ) -> Result<api_loader::Frame, Error> {
+        let frame_request_path = image.frame_request_path();
+
+        let loader_proxy = LoaderStateProxy::builder(&self.dbus_connection)
+            .destination("org.gnome.glycin")?
+            .path(frame_request_path)?
+            .build()
+            .await?;
+
+        let timings = api_loader::Timings {
+            spawn: image.spawn_duration,
+            ..Default::default()
+        };
+
+        let mut intermediate_frames = loader_proxy.receive_progressive_frame().await?;
 
+        let start = Instant::now();
+        let frame = loader_proxy.frame(frame_request).fuse();
+        futures_util::pin_mut!(frame);
+
+        let frame = loop {
+            futures_util::select! {
+                frame = frame => break frame?,
+                signal = intermediate_frames.select_next_some() => {
+                    let intermediate_frame = signal.args()?.frame;
+                    let mut timings = timings;
+                    timings.decode = start.elapsed();
+                    progress(self.process_frame(intermediate_frame, image, timings).await?);
+                }
+            }
+        };
+
+        let mut timings = timings;
+        timings.decode = start.elapsed();
+
+        self.process_frame(frame, image, timings).await
+    }
+
+    /// Apply transformations and color conversions to a frame from the loader
+    async fn process_frame(
+        &self,
+        mut frame: Frame,
+        image: &Image,
+        mut timings: api_loader::Timings,
+    ) -> Result<api_loader::Frame, Error> {
         // Seal all constant data
         if let Some(icc_profile) = &frame.details.color_icc_profile {
             seal_fd(icc_profile).await?;
         }
 
+        add_image_advisories(&mut frame.details, image);
+
         let raw_fd = frame.texture.as_raw_fd();
         let img_buf = unsafe { ImgBuf::from_raw_fd(raw_fd)? };
 
         validate_frame(&frame, &img_buf)?;
 
+        let start = Instant::now();
         let img_buf = if image.loader.apply_transformations {
             orientation::apply_exif_orientation(img_buf, &mut frame, image)
         } else {
             img_buf
         };
+        timings.orientation = start.elapsed();
 
         let mut color_state = ColorState::Srgb;
+        let mut icc_error = None;
+        let mut color_fallback = None;
+        let mut icc_profile_applied = false;
 
         let img_buf = if let Some(cicp) = frame
             .details
@@ -336,25 +484,63 @@ impl RemoteProcess<LoaderProxy<'static>> {
         {
             color_state = ColorState::Cicp(cicp);
             img_buf
-        } else if let Some(Ok(icc_profile)) =
-            frame.details.color_icc_profile.as_ref().map(|x| x.get())
+        } else if let Some(Ok(icc_profile)) = frame
+            .details
+            .color_icc_profile
+            .as_ref()
+            .filter(|_| image.loader.apply_icc_profile)
+            .map(|x| x.get())
         {
             // Align stride with pixel size if necessary
             let mut img_buf = remove_stride_if_needed(img_buf, &mut frame)?;
 
             let memory_format = frame.memory_format;
+            let rendering_intent = image.loader.rendering_intent;
+            let black_point_compensation = image.loader.black_point_compensation;
+            let deterministic = image.loader.deterministic;
+            let icc_error_policy = image.loader.icc_error_policy;
+            let start = Instant::now();
             let (icc_mmap, icc_result) = spawn_blocking(move || {
-                let result = icc::apply_transformation(&icc_profile, memory_format, &mut img_buf);
+                let result = icc::apply_transformation(
+                    &icc_profile,
+                    memory_format,
+                    &mut img_buf,
+                    rendering_intent,
+                    black_point_compensation,
+                    deterministic,
+                );
                 (img_buf, result)
             })
             .await;
+            timings.icc_profile = start.elapsed();
 
             match icc_result {
+                Err(err) if deterministic || icc_error_policy == IccErrorPolicy::Error => {
+                    return Err(err);
+                }
                 Err(err) => {
                     tracing::warn!("Failed to apply ICC profile: {err}");
+                    if icc_error_policy == IccErrorPolicy::Strip {
+                        frame.details.color_icc_profile = None;
+                    }
+                    icc_error = Some(Arc::new(err.to_string()));
+
+                    // Use other color information of the image instead of sRGB
+                    if let Some(cicp) = frame
+                        .details
+                        .color_cicp_fallback
+                        .and_then(|x| Cicp::from_bytes(&x).ok())
+                    {
+                        tracing::debug!("Using CICP {cicp:?} instead of the ICC profile");
+                        color_state = ColorState::Cicp(cicp);
+                        color_fallback = Some(api_loader::ColorFallback::Cicp);
+                    } else {
+                        color_fallback = Some(api_loader::ColorFallback::Srgb);
+                    }
                 }
                 Ok(new_color_state) => {
                     color_state = new_color_state;
+                    icc_profile_applied = true;
                 }
             }
 
@@ -363,7 +549,51 @@ impl RemoteProcess<LoaderProxy<'static>> {
             img_buf
         };
 
-        let (frame, img_buf) = if let Some(target_format) = image
+        let img_buf = match (&image.loader.lut, &color_state) {
+            (Some(lut), ColorState::Srgb) => {
+                let mut img_buf = remove_stride_if_needed(img_buf, &mut frame)?;
+
+                let lut = lut.clone();
+                let memory_format = frame.memory_format;
+                let start = Instant::now();
+                let img_buf = spawn_blocking(move || {
+                    lut::apply_lut(&lut, memory_format, &mut img_buf);
+                    img_buf
+                })
+                .await;
+                timings.lut = start.elapsed();
+
+                img_buf
+            }
+            (Some(_), color_state) => {
+                tracing::warn!("Not applying 3D LUT to image with color state {color_state:?}");
+                img_buf
+            }
+            (None, _) => img_buf,
+        };
+
+        let start = Instant::now();
+        let (mut frame, img_buf) = if image.loader.linearize {
+            // The format might already be the target format
+            let img_buf = remove_stride_if_needed(img_buf, &mut frame)?;
+
+            let target_format = linearize::linear_memory_format(frame.memory_format);
+            let (frame, mut img_buf) = util::spawn_blocking(move || {
+                glycin_utils::editing::change_memory_format(img_buf, frame, target_format)
+            })
+            .await?;
+
+            let current_color_state = color_state.clone();
+            let (img_buf, cicp) = spawn_blocking(move || {
+                let cicp =
+                    linearize::linearize(&current_color_state, frame.memory_format, &mut img_buf);
+                (img_buf, cicp)
+            })
+            .await;
+            color_state = ColorState::Cicp(cicp);
+
+            (frame, img_buf)
+        } else if let Some(target_format) = image
             .loader
             .memory_format_selection
             .best_format_for(frame.memory_format)
@@ -375,18 +605,23 @@ impl RemoteProcess<LoaderProxy<'static>> {
         } else {
             (frame, img_buf)
         };
+        timings.memory_format = start.elapsed();
 
-        let bytes = match img_buf {
-            ImgBuf::MMap { mmap, raw_fd } => {
-                drop(mmap);
-                seal_fd(raw_fd).await?;
-                unsafe { gbytes_from_mmap(raw_fd)? }
-            }
-            ImgBuf::Vec(vec) => glib::Bytes::from_owned(vec),
+        let byte_order = image.loader.byte_order;
+        let img_buf = if byte_order.is_native() {
+            img_buf
+        } else {
+            swap_16bit_byte_order(img_buf, &frame)?
+        };
+
+        let img_buf = if let Some(alignment) = image.loader.stride_alignment {
+            align_stride(img_buf, &mut frame, alignment)?
+        } else {
+            img_buf
         };
 
         Ok(api_loader::Frame {
-            buffer: bytes,
+            buffer: into_bytes(img_buf).await?,
             width: frame.width,
             height: frame.height,
             stride: frame.stride,
@@ -394,10 +629,61 @@ impl RemoteProcess<LoaderProxy<'static>> {
             delay: frame.delay.into(),
             details: Arc::new(frame.details),
             color_state,
+            byte_order,
+            timings: image.loader.collect_timings.then_some(timings),
+            icc_error,
+            color_fallback,
+            icc_profile_applied,
+        })
+    }
+
+    /// Frame from the loader without any transformations or conversions
+    async fn raw_frame(
+        mut frame: Frame,
+        image: &Image,
+        timings: api_loader::Timings,
+    ) -> Result<api_loader::Frame, Error> {
+        if let Some(icc_profile) = &frame.details.color_icc_profile {
+            seal_fd(icc_profile).await?;
+        }
+
+        add_image_advisories(&mut frame.details, image);
+
+        let raw_fd = frame.texture.as_raw_fd();
+        let img_buf = unsafe { ImgBuf::from_raw_fd(raw_fd)? };
+
+        validate_frame(&frame, &img_buf)?;
+
+        Ok(api_loader::Frame {
+            buffer: into_bytes(img_buf).await?,
+            width: frame.width,
+            height: frame.height,
+            stride: frame.stride,
+            memory_format: frame.memory_format,
+            delay: frame.delay.into(),
+            details: Arc::new(frame.details),
+            color_state: ColorState::Unspecified,
+            byte_order: api_loader::ByteOrder::Native,
+            timings: image.loader.collect_timings.then_some(timings),
+            icc_error: None,
+            color_fallback: None,
+            icc_profile_applied: false,
         })
     }
 }
 
+/// Seal the buffer and wrap it as bytes
+async fn into_bytes(img_buf: ImgBuf) -> Result<glib::Bytes, Error> {
+    Ok(match img_buf {
+        ImgBuf::MMap { mmap, raw_fd } => {
+            drop(mmap);
+            seal_fd(raw_fd).await?;
+            unsafe { gbytes_from_mmap(raw_fd)? }
+        }
+        ImgBuf::Vec(vec) => glib::Bytes::from_owned(vec),
+    })
+}
+
 impl RemoteProcess<EditorProxy<'static>> {
     pub async fn create(
         &self,
@@ -415,8 +701,10 @@ impl RemoteProcess<EditorProxy<'static>> {
         &self,
         gfile_worker: &GFileWorker,
         mime_type: &MimeType,
+        preserve_memory_format: bool,
     ) -> Result<RemoteEditableImage, Error> {
-        let init_request = self.init_request(gfile_worker, mime_type)?;
+        let mut init_request = self.init_request(gfile_worker, mime_type)?;
+        init_request.details.preserve_memory_format = preserve_memory_format.then_some(true);
 
         self.proxy.edit(init_request).await.map_err(Into::into)
     }
@@ -478,7 +766,10 @@ impl RemoteProcess<EditorProxy<'static>> {
 }
 
 use std::io::{BufReader, Write};
-const BUF_SIZE: usize = u16::MAX as usize;
+/// Default and minimum size of the buffer for reading the source
+///
+/// The first chunk that is read is used to guess the mime type.
+pub(crate) const BUF_SIZE: usize = u16::MAX as usize;
 
 #[zbus::proxy(interface = "org.gnome.glycin.Loader")]
 pub trait Loader {
@@ -488,7 +779,11 @@ pub trait Loader {
 #[zbus::proxy(name = "org.gnome.glycin.Image")]
 pub trait LoaderState {
     async fn frame(&self, frame_request: FrameRequest) -> Result<Frame, RemoteError>;
+    async fn color_profile(&self) -> Result<RemoteColorProfile, RemoteError>;
     async fn done(&self) -> Result<(), RemoteError>;
+    /// Intermediate result while decoding a progressive frame request
+    #[zbus(signal)]
+    fn progressive_frame(&self, frame: Frame) -> zbus::Result<()>;
 }
 
 #[zbus::proxy(
@@ -524,23 +819,71 @@ pub trait EditableImage {
 #[derive(Debug)]
 pub struct GFileWorker {
     file: Option<gio::File>,
+    memfd: Option<Arc<OwnedFd>>,
     writer_send: Mutex<Option<oneshot::Sender<UnixStream>>>,
     first_bytes_recv: future::Shared<oneshot::Receiver<Arc<Vec<u8>>>>,
     error_recv: future::Shared<oneshot::Receiver<Result<(), Error>>>,
+    encoded_size: Arc<OnceLock<u64>>,
+    verifies_content_hash: bool,
 }
-use std::sync::Mutex;
+use std::sync::{Mutex, OnceLock};
 impl GFileWorker {
-    pub fn spawn(source: Source, cancellable: gio::Cancellable) -> GFileWorker {
+    pub fn spawn(
+        source: Source,
+        read_buffer_size: usize,
+        content_hash: Option<ContentHash>,
+        cancellable: gio::Cancellable,
+    ) -> GFileWorker {
         let file = source.file();
+        let memfd = source.memfd();
 
         let (error_send, error_recv) = oneshot::channel();
         let (first_bytes_send, first_bytes_recv) = oneshot::channel();
         let (writer_send, writer_recv) = oneshot::channel();
+        let encoded_size = Arc::new(OnceLock::new());
+        let encoded_size_ = encoded_size.clone();
+        let verifies_content_hash = content_hash.is_some();
 
         spawn_blocking_detached(move || {
             Self::handle_errors(error_send, move || {
+                let mut hasher = content_hash.as_ref().map(ContentHash::hasher);
+
+                // The memfd is directly passed to the loader, only the head is needed here
+                if let Some(memfd) = source.memfd() {
+                    // The seals guarantee that the loader gets the verified data
+                    if hasher.is_some() {
+                        crate::api_common::seal_memfd(&memfd)?;
+                    }
+
+                    let file = std::fs::File::from(memfd.try_clone()?);
+                    let _result = encoded_size_.set(file.metadata()?.len());
+
+                    let mut buf = vec![0; BUF_SIZE];
+                    let n = file.read_at(&mut buf, 0)?;
+                    buf.truncate(n);
+                    first_bytes_send
+                        .send(Arc::new(buf))
+                        .or(Err(Error::InternalCommunicationCanceled))?;
+
+                    if let Some(mut hasher) = hasher {
+                        let mut buf = vec![0; read_buffer_size];
+                        let mut offset = 0_u64;
+                        loop {
+                            let n = file.read_at(&mut buf, offset)?;
+                            if n == 0 {
+                                break;
+                            }
+                            hasher.update(&buf[..n]);
+                            offset = offset.saturating_add(n.try_u64()?);
+                        }
+                        hasher.verify()?;
+                    }
+
+                    return Ok(());
+                }
+
                 let reader = source.to_stream(&cancellable)?;
-                let mut buf = vec![0; BUF_SIZE];
+                let mut buf = vec![0; read_buffer_size];
 
                 let n = reader.read(&mut buf, Some(&cancellable))?;
                 let first_bytes = Arc::new(buf[..n].to_vec());
@@ -551,6 +894,10 @@ impl GFileWorker {
                 let mut writer: UnixStream = block_on(writer_recv)?;
 
                 writer.write_all(&first_bytes)?;
+                if let Some(hasher) = &mut hasher {
+                    hasher.update(&first_bytes);
+                }
+                let mut total_size = first_bytes.len().try_u64()?;
                 drop(first_bytes);
 
                 loop {
@@ -559,6 +906,16 @@ impl GFileWorker {
                         break;
                     }
                     writer.write_all(&buf[..n])?;
+                    if let Some(hasher) = &mut hasher {
+                        hasher.update(&buf[..n]);
+                    }
+                    total_size = total_size.saturating_add(n.try_u64()?);
+                }
+
+                let _result = encoded_size_.set(total_size);
+
+                if let Some(hasher) = hasher {
+                    hasher.verify()?;
                 }
 
                 Ok(())
@@ -567,9 +924,12 @@ impl GFileWorker {
 
         GFileWorker {
             file,
+            memfd,
             writer_send: Mutex::new(Some(writer_send)),
             first_bytes_recv: first_bytes_recv.shared(),
             error_recv: error_recv.shared(),
+            encoded_size,
+            verifies_content_hash,
         }
     }
 
@@ -595,6 +955,30 @@ impl GFileWorker {
         self.file.as_ref()
     }
 
+    /// Memfd that can be passed to the loader instead of streaming the data
+    pub fn memfd(&self) -> Option<&OwnedFd> {
+        self.memfd.as_deref()
+    }
+
+    /// Number of bytes of the source
+    ///
+    /// Resolves once the reader is done and returns `None` if the source
+    /// wasn't read completely.
+    pub fn encoded_size(&self) -> impl std::future::Future<Output = Option<u64>> {
+        let done = self.error_recv.clone();
+        let encoded_size = self.encoded_size.clone();
+
+        async move {
+            let _result = done.await;
+            encoded_size.get().copied()
+        }
+    }
+
+    /// The source is checked against a [`ContentHash`]
+    pub fn verifies_content_hash(&self) -> bool {
+        self.verifies_content_hash
+    }
+
     pub async fn error(&self) -> Result<(), Error> {
         match self.error_recv.clone().await {
             Ok(result) => result,
@@ -648,30 +1032,70 @@ async fn seal_fd(fd: impl AsRawFd) -> Result<(), memfd::Error> {
     Ok(())
 }
 
+/// Prepend the advisories of the image to the ones of the frame
+fn add_image_advisories(details: &mut glycin_utils::FrameDetails, image: &Image) {
+    let image_details = image.details();
+    if image_details.advisories().is_empty() {
+        return;
+    }
+
+    let mut advisories = image_details.advisories().to_vec();
+    for advisory in details.advisories.take().unwrap_or_default() {
+        if !advisories.contains(&advisory) {
+            advisories.push(advisory);
+        }
+    }
+
+    details.advisories = Some(advisories);
+}
+
 fn validate_frame(frame: &Frame, img_buf: &ImgBuf) -> Result<(), Error> {
-    if img_buf.len() < frame.n_bytes()? {
+    validate_frame_layout(
+        frame.width,
+        frame.height,
+        frame.stride,
+        frame.memory_format,
+        img_buf.len(),
+        frame,
+    )
+}
+
+/// Check that the dimensions and stride are valid for a buffer of `buf_len`
+///
+/// The `frame` is only used for error messages.
+pub(crate) fn validate_frame_layout(
+    width: u32,
+    height: u32,
+    stride: u32,
+    memory_format: MemoryFormat,
+    buf_len: usize,
+    frame: &impl std::fmt::Debug,
+) -> Result<(), Error> {
+    if buf_len < stride.try_usize()?.smul(height.try_usize()?)? {
         return Err(Error::TextureWrongSize {
-            texture_size: img_buf.len(),
+            texture_size: buf_len,
             frame: format!("{:?}", frame),
         });
     }
 
-    if frame.stride < frame.width.smul(frame.memory_format.n_bytes().u32())? {
+    if stride < width.smul(memory_format.n_bytes().u32())? {
         return Err(Error::StrideTooSmall(format!("{:?}", frame)));
     }
 
-    if frame.width < 1 || frame.height < 1 {
-        return Err(Error::WidgthOrHeightZero(format!("{:?}", frame)));
+    if width < 1 || height < 1 {
+        return Err(Error::zero_dimensions(width, format!("{:?}", frame)));
     }
 
-    if (frame.stride as u64).smul(frame.height as u64)? > MAX_TEXTURE_SIZE {
+    if (stride as u64).smul(height as u64)? > MAX_TEXTURE_SIZE {
         return Err(Error::TextureTooLarge);
     }
 
+    if width.try_i32().is_err() || height.try_i32().is_err() {
+        return Err(Error::DimensionsTooLarge(format!("{:?}", frame)));
+    }
+
     // Ensure
-    frame.width.try_i32()?;
-    frame.height.try_i32()?;
-    frame.stride.try_usize()?;
+    stride.try_usize()?;
 
     Ok(())
 }
@@ -713,6 +1137,87 @@ fn remove_stride_if_needed(mut img_buf: ImgBuf, frame: &mut Frame) -> Result<Img
     Ok(img_buf.resize(frame.n_bytes()?.i64()?)?)
 }
 
+/// Pad rows such that the stride is a multiple of `alignment`
+fn align_stride(img_buf: ImgBuf, frame: &mut Frame, alignment: usize) -> Result<ImgBuf, Error> {
+    let pixel_size = frame.memory_format.n_bytes().usize();
+    if !alignment.is_power_of_two() || alignment < pixel_size {
+        return Err(Error::InvalidStrideAlignment {
+            alignment,
+            pixel_size,
+        });
+    }
+
+    let width = frame.width.try_usize()?.smul(pixel_size)?;
+    let height = frame.height.try_usize()?;
+    let old_stride = frame.stride.try_usize()?;
+    let stride = width
+        .checked_next_multiple_of(alignment)
+        .ok_or(Error::TextureTooLarge)?;
+
+    if stride == old_stride {
+        return Ok(img_buf);
+    }
+
+    if stride.try_u64()?.smul(height.try_u64()?)? > MAX_TEXTURE_SIZE {
+        return Err(Error::TextureTooLarge);
+    }
+
+    let n_bytes = stride.smul(height)?;
+
+    let move_row = |img_buf: &mut ImgBuf, row: usize| -> Result<(), Error> {
+        let start = row.smul(stride)?;
+        img_buf.copy_within(
+            row.smul(old_stride)?..row.smul(old_stride)?.sadd(width)?,
+            start,
+        );
+        if let Some(padding) = img_buf.get_mut(start.sadd(width)?..start.sadd(stride)?) {
+            padding.fill(0);
+        }
+        Ok(())
+    };
+
+    let img_buf = if stride > old_stride {
+        // Move rows from the end to not overwrite rows that haven't been moved
+        let mut img_buf = img_buf.resize(n_bytes.i64()?)?;
+        for row in (0..height).rev() {
+            move_row(&mut img_buf, row)?;
+        }
+        img_buf
+    } else {
+        let mut img_buf = img_buf;
+        for row in 0..height {
+            move_row(&mut img_buf, row)?;
+        }
+        img_buf.resize(n_bytes.i64()?)?
+    };
+
+    frame.stride = stride.try_u32()?;
+
+    Ok(img_buf)
+}
+
+/// Swap the bytes of all 16-bit channels
+fn swap_16bit_byte_order(mut img_buf: ImgBuf, frame: &Frame) -> Result<ImgBuf, Error> {
+    if frame.memory_format.channel_type().size() != 2 {
+        return Ok(img_buf);
+    }
+
+    let width = frame
+        .width
+        .try_usize()?
+        .smul(frame.memory_format.n_bytes().usize())?;
+    let stride = frame.stride.try_usize()?;
+
+    for row in img_buf.chunks_mut(stride) {
+        let row_len = row.len().min(width);
+        if let Some(row) = row.get_mut(..row_len) {
+            row.chunks_exact_mut(2).for_each(|x| x.swap(0, 1));
+        }
+    }
+
+    Ok(img_buf)
+}
+
 fn spawn_stdio_reader(
     stdio: &mut Option<impl Read + Send + 'static>,
     store: &Arc<Mutex<String>>,
@@ -747,3 +1252,41 @@ fn spawn_stdio_reader(
         });
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn swap_16bit_byte_order_padding() {
+        // Two G16 pixels per row with two bytes of padding
+        let data = vec![1, 2, 3, 4, 0xaa, 0xbb, 5, 6, 7, 8, 0xcc, 0xdd];
+        let mut frame = Frame::new(
+            2,
+            2,
+            MemoryFormat::G16,
+            BinaryData::from_data(data.clone()).unwrap(),
+        )
+        .unwrap();
+        frame.stride = 6;
+
+        let img_buf = swap_16bit_byte_order(ImgBuf::Vec(data), &frame).unwrap();
+        assert_eq!(
+            img_buf.to_vec(),
+            [2, 1, 4, 3, 0xaa, 0xbb, 6, 5, 8, 7, 0xcc, 0xdd]
+        );
+
+        // 8-bit formats are not changed
+        let data = vec![1, 2, 3, 4];
+        let frame = Frame::new(
+            2,
+            1,
+            MemoryFormat::G8a8,
+            BinaryData::from_data(data.clone()).unwrap(),
+        )
+        .unwrap();
+        let img_buf = swap_16bit_byte_order(ImgBuf::Vec(data.clone()), &frame).unwrap();
+        assert_eq!(img_buf.to_vec(), data);
+    }
+}
@@ -8,8 +8,8 @@ use gio::prelude::CancellableExt;
 use glycin_utils::{DimensionTooLargerError, RemoteError};
 use libseccomp::error::SeccompError;
 
-use crate::config;
 use crate::dbus::{RemoteProcess, ZbusProxy, MAX_TEXTURE_SIZE};
+use crate::{config, memory_pressure};
 
 #[derive(Debug, Clone)]
 
@@ -63,6 +63,66 @@ impl ErrorCtx {
     pub fn error(&self) -> &Error {
         &self.error
     }
+
+    /// Syscalls that were blocked by the sandbox
+    ///
+    /// Loaders and editors are terminated when they use a syscall that is not
+    /// allowed by the seccomp filter. The terminated process then reports the
+    /// blocked syscall via stderr. This is useful for finding syscalls that
+    /// are missing from the allow list when a loader only works unsandboxed.
+    ///
+    /// No syscalls are reported if the
+    /// [`PoolConfig::seccomp_default_action`](crate::PoolConfig::seccomp_default_action)
+    /// is [`SeccompAction::KillProcess`](crate::SeccompAction::KillProcess).
+    /// In that case, the syscalls are only logged via auditd.
+    pub fn denied_syscalls(&self) -> Vec<DeniedSyscall> {
+        let Some(stderr) = &self.stderr else {
+            return Vec::new();
+        };
+
+        stderr
+            .lines()
+            .filter_map(|line| line.strip_prefix(glycin_utils::BLOCKED_SYSCALL_MESSAGE))
+            .filter_map(DeniedSyscall::parse)
+            .collect()
+    }
+}
+
+/// Syscall that was blocked by the sandbox
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeniedSyscall {
+    number: i32,
+    name: Option<String>,
+}
+
+impl DeniedSyscall {
+    /// Parse the syscall from the form `name (number)`
+    fn parse(s: &str) -> Option<Self> {
+        let (name, number) = s.trim_end().strip_suffix(')')?.rsplit_once(" (")?;
+        let number = number.parse().ok()?;
+        let name = (name != "Unknown Syscall").then(|| name.to_string());
+
+        Some(Self { number, name })
+    }
+
+    /// Syscall number for the architecture of the loader
+    pub fn number(&self) -> i32 {
+        self.number
+    }
+
+    /// Name of the syscall, if known
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
+
+impl std::fmt::Display for DeniedSyscall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{name} ({})", self.number),
+            None => write!(f, "{}", self.number),
+        }
+    }
 }
 
 pub trait ResultExt<T> {
@@ -87,7 +147,7 @@ impl<T> ResultExt<T> for Result<T, Error> {
                 let stdout = process.stdout_content.lock().ok().map(|x| x.clone());
 
                 let error = if cancellable.is_cancelled() {
-                    Error::Canceled(Some(err.to_string()))
+                    Error::canceled(cancellable, err)
                 } else {
                     err
                 };
@@ -106,7 +166,7 @@ impl<T> ResultExt<T> for Result<T, Error> {
             Ok(x) => Ok(x),
             Err(err) => {
                 if cancellable.is_cancelled() {
-                    Err(ErrorCtx::from_error(Error::Canceled(Some(err.to_string()))))
+                    Err(ErrorCtx::from_error(Error::canceled(cancellable, err)))
                 } else {
                     Err(ErrorCtx::from_error(err))
                 }
@@ -139,6 +199,8 @@ pub enum Error {
     NoLoadersConfigured(config::Config),
     #[error("Unknown image format: {0}\nUsed config: {1:#?}")]
     UnknownImageFormat(String, config::Config),
+    #[error("Image format {0} can be loaded, but no editor is installed for it")]
+    EditingNotSupported(String),
     #[error("Unknown content type: {0}")]
     UnknownContentType(String),
     #[error("Loader process exited early with status '{}'Command:\n {cmd}", .status.code().unwrap_or_default())]
@@ -161,16 +223,49 @@ pub enum Error {
     TextureTooLarge,
     #[error("Stride is smaller than possible: {0}")]
     StrideTooSmall(String),
-    #[error("Width or height is zero: {0}")]
-    WidgthOrHeightZero(String),
+    #[error("Stride alignment {alignment} is not a power of two or smaller than the pixel size {pixel_size}")]
+    InvalidStrideAlignment { alignment: usize, pixel_size: usize },
+    #[error("Width is zero: {0}")]
+    WidthZero(String),
+    #[error("Height is zero: {0}")]
+    HeightZero(String),
+    #[error("Width or height exceeds the maximum of {max}: {0}", max = i32::MAX)]
+    DimensionsTooLarge(String),
+    #[error("Image dimensions {width}x{height} violate the constraints")]
+    ConstraintsViolated { width: u32, height: u32 },
+    #[error("Region {region:?} is not within the image of {width}x{height} pixels")]
+    RegionOutsideImage {
+        region: (u32, u32, u32, u32),
+        width: u32,
+        height: u32,
+    },
+    #[error("Source data has the hash {actual} instead of the expected {expected}")]
+    ContentHashMismatch { expected: String, actual: String },
     #[error("Memfd: {0}")]
     MemFd(Arc<memfd::Error>),
+    #[error("File descriptor is not a memfd")]
+    NotMemfd,
+    #[error("Memfd can still be modified but can't be sealed anymore")]
+    MemfdNotSealable,
     #[error("Seccomp: {0}")]
     Seccomp(Arc<SeccompError>),
     #[error("ICC profile: {0}")]
     IccProfile(#[from] lcms2::Error),
     #[error("Operation was explicitly canceled.\nOriginal error: {0:?}")]
     Canceled(Option<String>),
+    #[error("Operation was canceled because of memory pressure.\nOriginal error: {0:?}")]
+    CanceledMemoryPressure(Option<String>),
+    #[error("Memory format {0:?} is not supported by this operation")]
+    UnsupportedMemoryFormat(glycin_common::MemoryFormat),
+    #[error("3D LUT: {0}")]
+    Lut(String),
+    #[error("{0}")]
+    FeatureNotSupported(#[from] crate::FeatureNotSupported),
+    #[error("Invalid resolution {dpi_x}x{dpi_y} DPI")]
+    InvalidResolution { dpi_x: f64, dpi_y: f64 },
+    #[cfg(feature = "thumbhash")]
+    #[error("Invalid ThumbHash")]
+    InvalidThumbhash,
     #[error("Editing: {0}")]
     Editing(#[from] glycin_utils::editing::Error),
     #[error("Trying to access already trasferred GInputStream")]
@@ -184,7 +279,45 @@ pub enum Error {
     CommonError(#[from] glycin_common::Error),
 }
 
+/// Reason why an operation was canceled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CancelReason {
+    /// The [`Cancellable`](gio::Cancellable) was canceled by the caller
+    Explicit,
+    /// Canceled via [`MemoryPressure::trigger`](crate::MemoryPressure::trigger)
+    MemoryPressure,
+}
+
 impl Error {
+    /// [`Error::WidthZero`] or [`Error::HeightZero`]
+    pub(crate) fn zero_dimensions(width: u32, info: String) -> Self {
+        if width == 0 {
+            Self::WidthZero(info)
+        } else {
+            Self::HeightZero(info)
+        }
+    }
+
+    fn canceled(cancellable: &gio::Cancellable, err: Error) -> Self {
+        if memory_pressure::canceled_by_memory_pressure(cancellable) {
+            Self::CanceledMemoryPressure(Some(err.to_string()))
+        } else {
+            Self::Canceled(Some(err.to_string()))
+        }
+    }
+
+    /// Returns why the operation was canceled
+    ///
+    /// Returns [`None`] if the error is not caused by canceling.
+    pub fn cancel_reason(&self) -> Option<CancelReason> {
+        match self {
+            Self::Canceled(_) => Some(CancelReason::Explicit),
+            Self::CanceledMemoryPressure(_) => Some(CancelReason::MemoryPressure),
+            _ => None,
+        }
+    }
+
     /// Returns if the error is related to unsupported formats.
     ///
     /// Return the mime type of the unsupported format or [`None`] if the error
@@ -192,6 +325,7 @@ impl Error {
     pub fn unsupported_format(&self) -> Option<String> {
         match self {
             Self::UnknownImageFormat(mime_type, _) => Some(mime_type.to_string()),
+            Self::EditingNotSupported(mime_type) => Some(mime_type.to_string()),
             Self::RemoteError(RemoteError::UnsupportedImageFormat(msg)) => Some(msg.clone()),
             _ => None,
         }
@@ -247,3 +381,72 @@ impl From<DimensionTooLargerError> for Error {
         Self::ConversionTooLargerError
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+
+    fn error_ctx(stderr: &str) -> ErrorCtx {
+        ErrorCtx {
+            error: Error::TextureTooLarge,
+            stderr: Some(stderr.to_string()),
+            stdout: None,
+        }
+    }
+
+    #[test]
+    fn denied_syscall_parse() {
+        let syscall = DeniedSyscall::parse("memfd_secret (447)").unwrap();
+        assert_eq!(syscall.name(), Some("memfd_secret"));
+        assert_eq!(syscall.number(), 447);
+        assert_eq!(syscall.to_string(), "memfd_secret (447)");
+
+        let syscall = DeniedSyscall::parse("Unknown Syscall (1000)\n").unwrap();
+        assert_eq!(syscall.name(), None);
+        assert_eq!(syscall.number(), 1000);
+        assert_eq!(syscall.to_string(), "1000");
+    }
+
+    #[test]
+    fn denied_syscall_malformed() {
+        for s in [
+            "",
+            "memfd_secret",
+            "memfd_secret (447",
+            "memfd_secret 447)",
+            "memfd_secret (ioctl)",
+            "memfd_secret (447) trailing",
+            "memfd_secret ()",
+        ] {
+            assert_eq!(DeniedSyscall::parse(s), None, "{s:?}");
+        }
+    }
+
+    #[test]
+    fn denied_syscalls_stderr() {
+        let stderr = format!(
+            "[INFO] Loader startup\n\
+             {BLOCKED_SYSCALL_MESSAGE}ioctl (16)\n\
+             unrelated (1)\n\
+             [WARN] Something else happened\n\
+             {BLOCKED_SYSCALL_MESSAGE}Unknown Syscall (1000)\n\
+             {BLOCKED_SYSCALL_MESSAGE}broken (\n",
+            BLOCKED_SYSCALL_MESSAGE = glycin_utils::BLOCKED_SYSCALL_MESSAGE
+        );
+
+        let syscalls = error_ctx(&stderr).denied_syscalls();
+        assert_eq!(
+            syscalls
+                .iter()
+                .map(|x| (x.name(), x.number()))
+                .collect::<Vec<_>>(),
+            [(Some("ioctl"), 16), (None, 1000)]
+        );
+
+        assert!(error_ctx("").denied_syscalls().is_empty());
+        assert!(ErrorCtx::from_error(Error::TextureTooLarge)
+            .denied_syscalls()
+            .is_empty());
+    }
+}
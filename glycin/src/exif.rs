@@ -0,0 +1,441 @@
+use gufo_exif::internal::{ExifRaw, Ifd, Tag, TagIfd, Type, ValueOffset};
+
+/// Value of an Exif field
+///
+/// Fields can contain a list of values. For single-value fields the vector
+/// contains exactly one element.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ExifValue {
+    /// Text of `ASCII` and `UTF-8` fields
+    String(String),
+    /// Values of `BYTE`, `SHORT`, and `LONG` fields
+    Unsigned(Vec<u32>),
+    /// Values of `SLONG` fields
+    Signed(Vec<i32>),
+    /// Numerator and denominator of `RATIONAL` fields
+    Rational(Vec<(u32, u32)>),
+    /// Numerator and denominator of `SRATIONAL` fields
+    SignedRational(Vec<(i32, i32)>),
+    /// Raw data of `UNDEFINED` and unknown fields
+    Undefined(Vec<u8>),
+}
+
+/// IFDs that are searched for a tag, in this order
+const IFDS: [Ifd; 4] = [Ifd::Primary, Ifd::Exif, Ifd::Gps, Ifd::Interoperability];
+
+pub(crate) fn exif_tag(data: Vec<u8>, tag: u16) -> Option<ExifValue> {
+    let mut exif = parse(data)?;
+    let mut decoder = exif.decoder();
+
+    IFDS.into_iter()
+        .map(|ifd| TagIfd::new(Tag(tag), ifd))
+        .find(|tagifd| decoder.lookup_entry(*tagifd).is_some())
+        .and_then(|tagifd| lookup(&mut decoder, tagifd))
+}
+
+/// Camera and shooting parameters from Exif
+///
+/// Fields are `None` if the information is not stored in the image.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct CameraInfo {
+    /// Manufacturer of the camera
+    pub make: Option<String>,
+    /// Model name of the camera
+    pub model: Option<String>,
+    /// Model name of the lens
+    pub lens: Option<String>,
+    /// Focal length in millimeters
+    pub focal_length: Option<f64>,
+    /// Aperture as f-number
+    pub aperture: Option<f64>,
+    /// Exposure time in seconds as numerator and denominator
+    pub exposure_time: Option<(u32, u32)>,
+    /// ISO speed
+    pub iso: Option<u32>,
+}
+
+pub(crate) fn camera_info(data: Vec<u8>) -> CameraInfo {
+    let Some(mut exif) = parse(data) else {
+        return CameraInfo::default();
+    };
+    let mut decoder = exif.decoder();
+
+    let make = string(&mut decoder, Tag(0x010F), Ifd::Primary);
+    let model = string(&mut decoder, Tag(0x0110), Ifd::Primary);
+
+    let lens = string(&mut decoder, Tag(0xA434), Ifd::Exif).or_else(|| {
+        // MakerNotes are vendor specific, only Canon stores the lens model in
+        // a standard IFD
+        if make.as_ref().is_some_and(|x| x.starts_with("Canon")) {
+            if let Err(err) = decoder.makernote_register() {
+                tracing::debug!("exif: Failed to read MakerNote: {err:?}");
+            }
+            string(&mut decoder, Tag(0x0095), Ifd::MakerNote)
+        } else {
+            None
+        }
+    });
+
+    let exposure_time = match lookup(&mut decoder, TagIfd::new(Tag(0x829A), Ifd::Exif)) {
+        Some(ExifValue::Rational(x)) => x.first().copied().filter(|(_, y)| *y != 0),
+        _ => None,
+    };
+
+    let iso = match lookup(&mut decoder, TagIfd::new(Tag(0x8827), Ifd::Exif)) {
+        Some(ExifValue::Unsigned(x)) => x.first().copied(),
+        _ => None,
+    };
+
+    CameraInfo {
+        make,
+        model,
+        lens,
+        focal_length: rational(&mut decoder, Tag(0x920A), Ifd::Exif),
+        aperture: rational(&mut decoder, Tag(0x829D), Ifd::Exif),
+        exposure_time,
+        iso,
+    }
+}
+
+/// `PixelXDimension` and `PixelYDimension` from the Exif IFD
+pub(crate) fn pixel_dimensions(data: Vec<u8>) -> Option<(u32, u32)> {
+    let mut exif = parse(data)?;
+    let mut decoder = exif.decoder();
+
+    let mut dimension = |tag| match lookup(&mut decoder, TagIfd::new(Tag(tag), Ifd::Exif)) {
+        Some(ExifValue::Unsigned(x)) => x.first().copied(),
+        _ => None,
+    };
+
+    Some((dimension(0xA002)?, dimension(0xA003)?))
+}
+
+fn parse(data: Vec<u8>) -> Option<gufo_exif::Exif> {
+    gufo_exif::Exif::new(data)
+        .inspect_err(|err| tracing::warn!("exif: Failed to parse data: {err:?}"))
+        .ok()
+}
+
+fn string(decoder: &mut ExifRaw, tag: Tag, ifd: Ifd) -> Option<String> {
+    match lookup(decoder, TagIfd::new(tag, ifd)) {
+        Some(ExifValue::String(x)) => Some(x.trim().to_string()).filter(|x| !x.is_empty()),
+        _ => None,
+    }
+}
+
+fn rational(decoder: &mut ExifRaw, tag: Tag, ifd: Ifd) -> Option<f64> {
+    match lookup(decoder, TagIfd::new(tag, ifd)) {
+        Some(ExifValue::Rational(x)) => x
+            .first()
+            .filter(|(_, y)| *y != 0)
+            .map(|(x, y)| f64::from(*x) / f64::from(*y)),
+        _ => None,
+    }
+}
+
+fn lookup(decoder: &mut ExifRaw, tagifd: TagIfd) -> Option<ExifValue> {
+    let entry = decoder.lookup_entry(tagifd)?;
+
+    let big_endian = decoder.raw.big_endian;
+
+    let data = match entry.value_offset {
+        ValueOffset::Value(value) => {
+            // Value is read as `u32` in the file's byte order
+            let bytes = if big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            };
+            let len = usize::try_from(entry.data_len().ok()?).ok()?;
+            bytes.get(..len)?.to_vec()
+        }
+        ValueOffset::Offset(_) => decoder.lookup_data(tagifd).ok()??.1,
+    };
+
+    let value = match entry.data_type {
+        Type::Ascii | Type::Utf8 => ExifValue::String(
+            String::from_utf8_lossy(&data)
+                .trim_end_matches('\0')
+                .to_string(),
+        ),
+        Type::Byte => ExifValue::Unsigned(data.into_iter().map(u32::from).collect()),
+        Type::Short => ExifValue::Unsigned(
+            words::<2>(&data)
+                .map(|x| {
+                    if big_endian {
+                        u16::from_be_bytes(x)
+                    } else {
+                        u16::from_le_bytes(x)
+                    }
+                })
+                .map(u32::from)
+                .collect(),
+        ),
+        Type::Long => ExifValue::Unsigned(u32_values(&data, big_endian).collect()),
+        Type::SLong => ExifValue::Signed(i32_values(&data, big_endian).collect()),
+        Type::Rational => {
+            let values = u32_values(&data, big_endian).collect::<Vec<_>>();
+            ExifValue::Rational(values.chunks_exact(2).map(pair).collect::<Option<_>>()?)
+        }
+        Type::SRational => {
+            let values = i32_values(&data, big_endian).collect::<Vec<_>>();
+            ExifValue::SignedRational(values.chunks_exact(2).map(pair).collect::<Option<_>>()?)
+        }
+        Type::Undefined | Type::Unknown(_) => ExifValue::Undefined(data),
+    };
+
+    Some(value)
+}
+
+fn words<const N: usize>(data: &[u8]) -> impl Iterator<Item = [u8; N]> + '_ {
+    data.chunks_exact(N).filter_map(|x| x.try_into().ok())
+}
+
+fn u32_values(data: &[u8], big_endian: bool) -> impl Iterator<Item = u32> + '_ {
+    words::<4>(data).map(move |x| {
+        if big_endian {
+            u32::from_be_bytes(x)
+        } else {
+            u32::from_le_bytes(x)
+        }
+    })
+}
+
+fn i32_values(data: &[u8], big_endian: bool) -> impl Iterator<Item = i32> + '_ {
+    words::<4>(data).map(move |x| {
+        if big_endian {
+            i32::from_be_bytes(x)
+        } else {
+            i32::from_le_bytes(x)
+        }
+    })
+}
+
+fn pair<T: Copy>(x: &[T]) -> Option<(T, T)> {
+    Some((*x.first()?, *x.get(1)?))
+}
+
+#[test]
+fn camera_info_test() {
+    fn entry(tag: u16, data_type: u16, count: u32, value: u32) -> Vec<u8> {
+        [
+            tag.to_le_bytes().as_slice(),
+            &data_type.to_le_bytes(),
+            &count.to_le_bytes(),
+            &value.to_le_bytes(),
+        ]
+        .concat()
+    }
+
+    let data = [
+        b"II\x2a\x00\x08\x00\x00\x00".as_slice(),
+        // IFD0 with make, model, and pointer to Exif IFD
+        &3_u16.to_le_bytes(),
+        &entry(0x010F, 2, 6, 50),
+        &entry(0x0110, 2, 4, u32::from_le_bytes(*b"EOS\0")),
+        &entry(0x8769, 4, 1, 56),
+        &[0; 4],
+        b"Canon\0",
+        // Exif IFD
+        &5_u16.to_le_bytes(),
+        &entry(0x829A, 5, 1, 122),
+        &entry(0x829D, 5, 1, 130),
+        &entry(0x8827, 3, 1, 400),
+        &entry(0x920A, 5, 1, 138),
+        &entry(0x927C, 7, 18, 146),
+        &[0; 4],
+        &[1, 0, 0, 0, 250, 0, 0, 0],
+        &[28, 0, 0, 0, 10, 0, 0, 0],
+        &[50, 0, 0, 0, 1, 0, 0, 0],
+        // Canon MakerNote with lens model
+        &1_u16.to_le_bytes(),
+        &entry(0x0095, 2, 8, 164),
+        &[0; 4],
+        b"EF 50mm\0",
+    ]
+    .concat();
+
+    let info = camera_info(data);
+
+    assert_eq!(info.make.as_deref(), Some("Canon"));
+    assert_eq!(info.model.as_deref(), Some("EOS"));
+    assert_eq!(info.lens.as_deref(), Some("EF 50mm"));
+    assert_eq!(info.exposure_time, Some((1, 250)));
+    assert_eq!(info.aperture, Some(2.8));
+    assert_eq!(info.iso, Some(400));
+    assert_eq!(info.focal_length, Some(50.));
+
+    assert_eq!(camera_info(Vec::new()), CameraInfo::default());
+}
+
+#[test]
+fn pixel_dimensions_test() {
+    fn entry(tag: u16, data_type: u16, value: u32) -> Vec<u8> {
+        [
+            tag.to_le_bytes().as_slice(),
+            &data_type.to_le_bytes(),
+            &1_u32.to_le_bytes(),
+            &value.to_le_bytes(),
+        ]
+        .concat()
+    }
+
+    let data = [
+        b"II\x2a\x00\x08\x00\x00\x00".as_slice(),
+        // IFD0 with pointer to Exif IFD
+        &1_u16.to_le_bytes(),
+        &entry(0x8769, 4, 26),
+        &[0; 4],
+        // Exif IFD with the width as `SHORT` and the height as `LONG`
+        &2_u16.to_le_bytes(),
+        &entry(0xA002, 3, 640),
+        &entry(0xA003, 4, 70000),
+        &[0; 4],
+    ]
+    .concat();
+
+    assert_eq!(pixel_dimensions(data), Some((640, 70000)));
+    assert_eq!(pixel_dimensions(Vec::new()), None);
+}
+
+#[test]
+fn exif_tag_test() {
+    fn entry(tag: u16, data_type: u16, count: u32, value: u32) -> Vec<u8> {
+        [
+            tag.to_le_bytes().as_slice(),
+            &data_type.to_le_bytes(),
+            &count.to_le_bytes(),
+            &value.to_le_bytes(),
+        ]
+        .concat()
+    }
+
+    let data = [
+        b"II\x2a\x00\x08\x00\x00\x00".as_slice(),
+        // IFD0 with make, resolution, and pointer to Exif IFD
+        &3_u16.to_le_bytes(),
+        &entry(0x010F, 2, 6, 50),
+        &entry(0x011A, 5, 1, 56),
+        &entry(0x8769, 4, 1, 64),
+        &[0; 4],
+        b"Canon\0",
+        &[72, 0, 0, 0, 1, 0, 0, 0],
+        // Exif IFD
+        &3_u16.to_le_bytes(),
+        &entry(0x8827, 3, 1, 400),
+        &entry(0x9000, 7, 4, u32::from_le_bytes(*b"0231")),
+        &entry(0x9204, 10, 1, 106),
+        &[0; 4],
+        &(-1_i32).to_le_bytes(),
+        &3_i32.to_le_bytes(),
+    ]
+    .concat();
+
+    assert_eq!(
+        exif_tag(data.clone(), 0x010F),
+        Some(ExifValue::String("Canon".into()))
+    );
+    assert_eq!(
+        exif_tag(data.clone(), 0x011A),
+        Some(ExifValue::Rational(vec![(72, 1)]))
+    );
+    assert_eq!(
+        exif_tag(data.clone(), 0x8827),
+        Some(ExifValue::Unsigned(vec![400]))
+    );
+    assert_eq!(
+        exif_tag(data.clone(), 0x9000),
+        Some(ExifValue::Undefined(b"0231".to_vec()))
+    );
+    assert_eq!(
+        exif_tag(data.clone(), 0x9204),
+        Some(ExifValue::SignedRational(vec![(-1, 3)]))
+    );
+    assert_eq!(exif_tag(data, 0x0110), None);
+}
+
+#[test]
+fn exif_tag_big_endian_test() {
+    fn entry(tag: u16, data_type: u16, count: u32, value: [u8; 4]) -> Vec<u8> {
+        [
+            tag.to_be_bytes().as_slice(),
+            &data_type.to_be_bytes(),
+            &count.to_be_bytes(),
+            &value,
+        ]
+        .concat()
+    }
+
+    let data = [
+        b"MM\x00\x2a\x00\x00\x00\x08".as_slice(),
+        // IFD0 with width as `SHORT`, height as `LONG`, and model
+        &3_u16.to_be_bytes(),
+        &entry(0x0100, 3, 1, [0x02, 0x80, 0, 0]),
+        &entry(0x0101, 4, 1, 70000_u32.to_be_bytes()),
+        &entry(0x0110, 2, 8, 50_u32.to_be_bytes()),
+        &[0; 4],
+        b"EOS R5 \0",
+    ]
+    .concat();
+
+    assert_eq!(
+        exif_tag(data.clone(), 0x0100),
+        Some(ExifValue::Unsigned(vec![640]))
+    );
+    assert_eq!(
+        exif_tag(data.clone(), 0x0101),
+        Some(ExifValue::Unsigned(vec![70000]))
+    );
+    assert_eq!(
+        exif_tag(data, 0x0110),
+        Some(ExifValue::String("EOS R5 ".into()))
+    );
+}
+
+#[test]
+fn exif_tag_malformed_test() {
+    fn entry(tag: u16, data_type: u16, count: u32, value: u32) -> Vec<u8> {
+        [
+            tag.to_le_bytes().as_slice(),
+            &data_type.to_le_bytes(),
+            &count.to_le_bytes(),
+            &value.to_le_bytes(),
+        ]
+        .concat()
+    }
+
+    assert_eq!(exif_tag(Vec::new(), 0x010F), None);
+    assert_eq!(
+        exif_tag(b"XX\x2a\x00\x08\x00\x00\x00".to_vec(), 0x010F),
+        None
+    );
+    // IFD offset outside of the data
+    assert_eq!(
+        exif_tag(b"II\x2a\x00\xff\x00\x00\x00".to_vec(), 0x010F),
+        None
+    );
+
+    let data = [
+        b"II\x2a\x00\x08\x00\x00\x00".as_slice(),
+        &2_u16.to_le_bytes(),
+        // Value offset outside of the data
+        &entry(0x010F, 2, 20, 1000),
+        // Unknown type, read as raw data
+        &entry(0x0110, 99, 4, u32::from_le_bytes([1, 2, 3, 4])),
+        &[0; 4],
+    ]
+    .concat();
+
+    assert_eq!(exif_tag(data.clone(), 0x010F), None);
+    assert_eq!(
+        exif_tag(data.clone(), 0x0110),
+        Some(ExifValue::Undefined(vec![1, 2, 3, 4]))
+    );
+
+    // IFD with more entries than the data contains
+    let mut truncated = data;
+    truncated.truncate(20);
+    assert_eq!(exif_tag(truncated, 0x010F), None);
+}
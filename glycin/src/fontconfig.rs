@@ -1,10 +1,12 @@
 use std::collections::BTreeSet;
 use std::ffi::{c_char, CStr};
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::ptr::NonNull;
 use std::sync::OnceLock;
 
 use fontconfig_sys as fc;
+use gio::glib;
 
 pub fn cached_paths() -> &'static Option<BTreeSet<PathBuf>> {
     static DIRS: OnceLock<Option<BTreeSet<PathBuf>>> = OnceLock::new();
@@ -27,6 +29,73 @@ fn dirs() -> Option<BTreeSet<PathBuf>> {
     }
 }
 
+/// Writes a config file that adds `font_dir` to the default config
+///
+/// Unlike `FONTCONFIG_PATH`, which replaces the default config, the file
+/// includes the default config. The file is named after its content, such that
+/// processes with the same font directory share it.
+pub fn config_file_with_font_dir(font_dir: &Path) -> io::Result<PathBuf> {
+    let mut config = String::from(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE fontconfig SYSTEM \"urn:fontconfig:fonts.dtd\">\n\
+         <fontconfig>\n",
+    );
+    if let Some(default_config) = default_config_file() {
+        config.push_str(&format!(
+            "  <include ignore_missing=\"yes\">{}</include>\n",
+            xml_escape(default_config)?
+        ));
+    }
+    config.push_str(&format!(
+        "  <dir>{}</dir>\n</fontconfig>\n",
+        xml_escape(font_dir)?
+    ));
+
+    let checksum = glib::compute_checksum_for_string(glib::ChecksumType::Sha256, &config)
+        .ok_or_else(|| io::Error::other("fontconfig: Failed to compute checksum"))?;
+    let dir = glib::user_runtime_dir().join("glycin");
+    let path = dir.join(format!("fontconfig-{checksum}.conf"));
+
+    if !path.exists() {
+        std::fs::create_dir_all(&dir)?;
+        // Rename to avoid that other processes read an incomplete file
+        let tmp_path = dir.join(format!("fontconfig-{checksum}.{}.tmp", std::process::id()));
+        std::fs::write(&tmp_path, config)?;
+        std::fs::rename(&tmp_path, &path)?;
+    }
+
+    Ok(path)
+}
+
+/// Config file that fontconfig loads by default
+fn default_config_file() -> &'static Option<PathBuf> {
+    static FILE: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+    FILE.get_or_init(|| unsafe {
+        let file = NonNull::new(fc::FcConfigFilename(std::ptr::null()))?.as_ptr();
+        let path = CStr::from_ptr(file as *const c_char)
+            .to_str()
+            .ok()
+            .map(PathBuf::from);
+        fc::FcStrFree(file);
+        path
+    })
+}
+
+fn xml_escape(path: &Path) -> io::Result<String> {
+    let path = path.to_str().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "fontconfig: Path is not valid UTF-8",
+        )
+    })?;
+
+    Ok(path
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;"))
+}
+
 unsafe fn cache_dirs(config: *mut fc::FcConfig) -> Option<BTreeSet<PathBuf>> {
     str_list_to_set(fc::FcConfigGetCacheDirs(config))
 }
@@ -77,3 +146,28 @@ unsafe fn str_list_to_set(list: *mut fc::FcStrList) -> Option<BTreeSet<PathBuf>>
 
     Some(vec)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn config_with_font_dir() {
+        let path = config_file_with_font_dir(Path::new("/tmp/fonts & <more>")).unwrap();
+        let config = std::fs::read_to_string(&path).unwrap();
+
+        assert!(config.contains("<dir>/tmp/fonts &amp; &lt;more&gt;</dir>"));
+        if let Some(default_config) = default_config_file() {
+            assert!(config.contains(&format!(
+                "<include ignore_missing=\"yes\">{}</include>",
+                default_config.display()
+            )));
+        }
+
+        // Same file for the same directory
+        assert_eq!(
+            config_file_with_font_dir(Path::new("/tmp/fonts & <more>")).unwrap(),
+            path
+        );
+    }
+}
@@ -1,39 +1,65 @@
 use glycin_common::{MemoryFormat, MemoryFormatInfo};
 
-use crate::{ColorState, Error};
+use crate::{ColorState, Error, RenderingIntent};
 
 pub fn apply_transformation(
     icc_profile: &[u8],
     memory_format: MemoryFormat,
     mmap: &mut [u8],
+    rendering_intent: RenderingIntent,
+    black_point_compensation: bool,
+    deterministic: bool,
 ) -> Result<ColorState, Error> {
-    transform(icc_profile, memory_format, mmap).map_err(Into::into)
+    transform(
+        icc_profile,
+        memory_format,
+        mmap,
+        rendering_intent,
+        black_point_compensation,
+        deterministic,
+    )
+    .map_err(Into::into)
 }
 
 fn transformation<P: lcms2::Pod>(
     icc_profile: &[u8],
     memory_format: MemoryFormat,
+    rendering_intent: RenderingIntent,
+    black_point_compensation: bool,
+    deterministic: bool,
 ) -> std::result::Result<lcms2::Transform<P, P>, lcms2::Error> {
     tracing::debug!("Conveting to sRGB via ICC profile");
 
     let icc_pixel_format = lcms_pixel_format(memory_format);
     let src_profile = lcms2::Profile::new_icc(icc_profile)?;
 
-    let target_profile;
+    // Gray images stay gray but use the same white point and transfer
+    // function as sRGB
+    let target_profile = if memory_format.n_channels() > 2 {
+        lcms2::Profile::new_srgb()
+    } else {
+        srgb_gray_profile()?
+    };
 
-    if memory_format.n_channels() > 2 {
-        target_profile = lcms2::Profile::new_srgb();
+    // Optimized pipelines are approximations that can differ between lcms2
+    // versions and builds
+    let mut flags = if deterministic {
+        lcms2::Flags::NO_OPTIMIZE
     } else {
-        target_profile =
-            lcms2::Profile::new_gray(lcms2_sys::ffi::CIExyY::d50(), &lcms2::ToneCurve::new(2.2))?;
+        lcms2::Flags::default()
     };
 
-    lcms2::Transform::new(
+    if black_point_compensation {
+        flags = flags | lcms2::Flags::BLACKPOINT_COMPENSATION;
+    }
+
+    lcms2::Transform::new_flags(
         &src_profile,
         icc_pixel_format,
         &target_profile,
         icc_pixel_format,
-        lcms2::Intent::Perceptual,
+        lcms_intent(rendering_intent),
+        flags,
     )
 }
 
@@ -41,6 +67,9 @@ fn transform(
     icc_profile: &[u8],
     memory_format: MemoryFormat,
     buf: &mut [u8],
+    rendering_intent: RenderingIntent,
+    black_point_compensation: bool,
+    deterministic: bool,
 ) -> std::result::Result<ColorState, lcms2::Error> {
     let multiple = std::thread::available_parallelism().map_or(2, |x| x.get());
     tracing::trace!("Applying ICC profiles while using {multiple} threads");
@@ -49,18 +78,58 @@ fn transform(
         * memory_format.n_bytes().usize();
 
     std::thread::scope(|s| {
-        for chunk in buf.chunks_mut(chunk_size) {
-            s.spawn(move || {
-                let transform = transformation(icc_profile, memory_format)?;
-                transform.transform_in_place(chunk);
-                Ok::<(), lcms2::Error>(())
-            });
-        }
-    });
+        let handles = buf
+            .chunks_mut(chunk_size)
+            .map(|chunk| {
+                s.spawn(move || {
+                    let transform = transformation(
+                        icc_profile,
+                        memory_format,
+                        rendering_intent,
+                        black_point_compensation,
+                        deterministic,
+                    )?;
+                    transform.transform_in_place(chunk);
+                    Ok::<(), lcms2::Error>(())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles.into_iter().try_for_each(|handle| {
+            handle
+                .join()
+                .unwrap_or(Err(lcms2::Error::ObjectCreationError))
+        })
+    })?;
 
     Ok(ColorState::Srgb)
 }
 
+/// Gray profile with the white point and transfer function of sRGB
+fn srgb_gray_profile() -> std::result::Result<lcms2::Profile, lcms2::Error> {
+    let d65 = lcms2_sys::ffi::CIExyY {
+        x: 0.3127,
+        y: 0.3290,
+        Y: 1.,
+    };
+    // Piecewise sRGB transfer function as parametric curve of type 4
+    let transfer_function = lcms2::ToneCurve::new_parametric(
+        4,
+        &[2.4, 1. / 1.055, 0.055 / 1.055, 1. / 12.92, 0.04045],
+    )?;
+
+    lcms2::Profile::new_gray(&d65, &transfer_function)
+}
+
+const fn lcms_intent(rendering_intent: RenderingIntent) -> lcms2::Intent {
+    match rendering_intent {
+        RenderingIntent::Perceptual => lcms2::Intent::Perceptual,
+        RenderingIntent::RelativeColorimetric => lcms2::Intent::RelativeColorimetric,
+        RenderingIntent::Saturation => lcms2::Intent::Saturation,
+        RenderingIntent::AbsoluteColorimetric => lcms2::Intent::AbsoluteColorimetric,
+    }
+}
+
 const fn lcms_pixel_format(format: MemoryFormat) -> lcms2::PixelFormat {
     match format {
         MemoryFormat::B8g8r8a8Premultiplied => premul(lcms2::PixelFormat::BGRA_8),
@@ -100,3 +169,121 @@ fn premul_test() {
     assert!(!lcms2::PixelFormat::RGBA_8.premultiplied());
     assert!(premul(lcms2::PixelFormat::RGBA_8).premultiplied());
 }
+
+#[cfg(test)]
+fn gray_profile(gamma: f64) -> Result<Vec<u8>, Error> {
+    let profile =
+        lcms2::Profile::new_gray(lcms2_sys::ffi::CIExyY::d50(), &lcms2::ToneCurve::new(gamma))?;
+    Ok(profile.icc()?)
+}
+
+#[test]
+fn gray_icc_test() -> Result<(), Error> {
+    let icc_profile = gray_profile(1.)?;
+
+    let mut buf = [0, 128, 255];
+    apply_transformation(
+        &icc_profile,
+        MemoryFormat::G8,
+        &mut buf,
+        RenderingIntent::Perceptual,
+        false,
+        true,
+    )?;
+
+    // Linear 50 % gray is 73.5 % in sRGB
+    let [black, gray, white] = buf;
+    assert_eq!(black, 0);
+    assert!(gray.abs_diff(188) <= 1, "{gray}");
+    assert_eq!(white, 255);
+
+    Ok(())
+}
+
+#[test]
+fn gray_alpha_icc_test() -> Result<(), Error> {
+    let icc_profile = gray_profile(1.)?;
+
+    let mut buf = [0x8000_u16, 0x1234, 0xffff, 0]
+        .into_iter()
+        .flat_map(u16::to_ne_bytes)
+        .collect::<Vec<_>>();
+    apply_transformation(
+        &icc_profile,
+        MemoryFormat::G16a16,
+        &mut buf,
+        RenderingIntent::Perceptual,
+        false,
+        true,
+    )?;
+
+    let buf = buf
+        .chunks_exact(2)
+        .flat_map(<[u8; 2]>::try_from)
+        .map(u16::from_ne_bytes)
+        .collect::<Vec<_>>();
+    let Ok([gray, gray_alpha, white, white_alpha]) = <[u16; 4]>::try_from(buf) else {
+        panic!("Unexpected buffer length");
+    };
+
+    // The alpha channel is unchanged
+    assert!(gray.abs_diff(0xbc42) <= 0x80, "{gray:x}");
+    assert_eq!(gray_alpha, 0x1234);
+    assert_eq!(white, 0xffff);
+    assert_eq!(white_alpha, 0);
+
+    Ok(())
+}
+
+#[test]
+fn gray_srgb_icc_test() -> Result<(), Error> {
+    let icc_profile = srgb_gray_profile()?.icc()?;
+
+    let mut buf = (0..=255).collect::<Vec<u8>>();
+    apply_transformation(
+        &icc_profile,
+        MemoryFormat::G8,
+        &mut buf,
+        RenderingIntent::Perceptual,
+        false,
+        true,
+    )?;
+
+    assert!(buf
+        .into_iter()
+        .zip(0..=255)
+        .all(|(x, y)| x.abs_diff(y) <= 1));
+
+    Ok(())
+}
+
+#[test]
+fn black_point_compensation_test() -> Result<(), Error> {
+    // Gray profile with a dark gray as black
+    let tone_curve = lcms2::ToneCurve::new_tabulated(&[0x2000, 0xffff]);
+    let icc_profile =
+        lcms2::Profile::new_gray(lcms2_sys::ffi::CIExyY::d50(), &tone_curve)?.icc()?;
+
+    let transform = |black_point_compensation| {
+        let mut buf = [0, 255];
+        apply_transformation(
+            &icc_profile,
+            MemoryFormat::G8,
+            &mut buf,
+            RenderingIntent::RelativeColorimetric,
+            black_point_compensation,
+            true,
+        )
+        .map(|_| buf)
+    };
+
+    let [black, white] = transform(false)?;
+    assert!(black > 0, "{black}");
+    assert_eq!(white, 255);
+
+    let [black, white] = transform(true)?;
+    assert_eq!(black, 0);
+    assert_eq!(white, 255);
+
+    Ok(())
+}
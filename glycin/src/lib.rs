@@ -54,6 +54,13 @@
 //!
 //! - `gdk4` --- Enables interoperability with [`gdk4`](gdk) by enabling to get
 //!   a [`gdk::Texture`] directly.
+//! - `downscale` --- Enables [`Frame::downscale()`] to create smaller
+//!   versions of frames, for example, for thumbnails, and
+//!   [`Image::pyramid()`] for deep-zoom viewers.
+//! - `thumbhash` --- Enables [`Frame::thumbhash()`] and
+//!   [`Frame::from_thumbhash()`] for compact image placeholders.
+//! - `wgpu` --- Enables preparing frames for uploading them as `wgpu::Texture`
+//!   via [`Frame::wgpu_texture()`]. This does not add a dependency on `wgpu`.
 //! - `tokio` --- Makes glycin compatible with [`zbus`] using [`tokio`].
 //!
 //! [`gtk4::Image::from_paintable()`]: https://gtk-rs.org/gtk4-rs/git/docs/gtk4/struct.Image.html#method.from_paintable
@@ -69,19 +76,32 @@ mod error_message {
 mod api_common;
 mod api_creator;
 mod api_editor;
+mod api_image_size;
+mod api_installed_loaders;
 mod api_loader;
+mod api_transcode;
+mod api_validate;
 #[cfg(feature = "unstable-config")]
 pub mod config;
 #[cfg(not(feature = "unstable-config"))]
 mod config;
 mod dbus;
 mod error;
+mod exif;
 mod fontconfig;
 mod icc;
+mod linearize;
+mod lut;
+mod memory_pressure;
 mod orientation;
 mod pool;
+mod progress;
 mod sandbox;
+#[cfg(feature = "thumbhash")]
+mod thumbhash;
 mod util;
+#[cfg(feature = "wgpu")]
+mod wgpu;
 
 #[cfg(feature = "gobject")]
 pub mod gobject;
@@ -89,13 +109,23 @@ pub mod gobject;
 pub use api_common::*;
 pub use api_creator::*;
 pub use api_editor::*;
+pub use api_image_size::*;
+pub use api_installed_loaders::*;
 pub use api_loader::*;
+pub use api_transcode::*;
+pub use api_validate::*;
 pub use config::COMPAT_VERSION;
-pub use error::{Error, ErrorCtx};
+pub use error::{CancelReason, DeniedSyscall, Error, ErrorCtx};
+pub use exif::{CameraInfo, ExifValue};
 pub use glycin_common::{
     BinaryData, MemoryFormat, MemoryFormatSelection, Operation, OperationId, Operations,
 };
 pub use gufo_common::cicp::Cicp;
-pub use pool::{Pool, PoolConfig};
+pub use lut::Lut3D;
+pub use memory_pressure::MemoryPressure;
+pub use pool::{Pool, PoolConfig, ProcessInfo};
+pub use progress::{Progress, ProgressAggregator};
 #[cfg(feature = "gdk4")]
 pub use util::gdk_memory_format;
+#[cfg(feature = "wgpu")]
+pub use wgpu::{WgpuTexture, WgpuTextureFormat, WGPU_BYTES_PER_ROW_ALIGNMENT};
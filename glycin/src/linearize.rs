@@ -0,0 +1,168 @@
+use glycin_common::{MemoryFormat, MemoryFormatInfo};
+use gufo_common::cicp::{Cicp, MatrixCoefficients, TransferCharacteristics, VideoRangeFlag};
+
+use crate::ColorState;
+
+/// Luminance of the SDR reference white in cd/m² per ITU-R BT.2408
+const REFERENCE_WHITE: f32 = 203.;
+
+/// Float format in which the linearized frame is stored
+pub fn linear_memory_format(memory_format: MemoryFormat) -> MemoryFormat {
+    if memory_format.has_alpha() {
+        MemoryFormat::R32g32b32a32Float
+    } else {
+        MemoryFormat::R32g32b32Float
+    }
+}
+
+/// Invert the transfer function of all pixels
+///
+/// The `buf` must be in a format returned by [`linear_memory_format`]. The
+/// primaries are kept and returned as part of the new color state. For HDR
+/// transfer functions, the SDR reference white is mapped to 1.0. The alpha
+/// channel is kept as is.
+pub fn linearize(color_state: &ColorState, memory_format: MemoryFormat, buf: &mut [u8]) -> Cicp {
+    let cicp = match color_state {
+        ColorState::Srgb | ColorState::Unspecified => Cicp::SRGB,
+        ColorState::Cicp(cicp) => *cicp,
+    };
+
+    tracing::debug!(
+        "Linearizing image with transfer characteristics {:?}",
+        cicp.transfer_characteristics
+    );
+
+    let transfer: fn(f32) -> f32 = match cicp.transfer_characteristics {
+        TransferCharacteristics::Gamma22
+        | TransferCharacteristics::Gamma22_
+        | TransferCharacteristics::Gamma22Bit10
+        | TransferCharacteristics::Gamma22Bit12 => bt709_inverse_oetf,
+        // Unknown transfer functions are treated like sRGB, the same as for display
+        TransferCharacteristics::Gamma24
+        | TransferCharacteristics::Unspecified
+        | TransferCharacteristics::Unknown(_) => srgb_eotf,
+        TransferCharacteristics::Linear => |x| x,
+        TransferCharacteristics::Pq => pq_eotf,
+        TransferCharacteristics::Dci => |x| x.powf(2.6),
+        TransferCharacteristics::Hlg => hlg_inverse_oetf,
+    };
+
+    if cicp.transfer_characteristics != TransferCharacteristics::Linear {
+        let pixel_size = memory_format.n_bytes().usize();
+        let multiple = std::thread::available_parallelism().map_or(2, |x| x.get());
+        let n_pixels = buf.len().checked_div(pixel_size).unwrap_or_default();
+        let chunk_size = n_pixels
+            .div_ceil(multiple)
+            .saturating_mul(pixel_size)
+            .max(pixel_size);
+
+        std::thread::scope(|s| {
+            for chunk in buf.chunks_mut(chunk_size) {
+                s.spawn(move || {
+                    for pixel in chunk.chunks_exact_mut(pixel_size) {
+                        let [r, g, b, a] = MemoryFormat::to_f32(memory_format, pixel);
+                        let [r, g, b] = [r, g, b].map(|x| transfer(x.abs()).copysign(x));
+                        MemoryFormat::from_f32([r, g, b, a], memory_format, pixel);
+                    }
+                });
+            }
+        });
+    }
+
+    Cicp {
+        transfer_characteristics: TransferCharacteristics::Linear,
+        matrix_coefficients: MatrixCoefficients::Identity,
+        video_full_range_flag: VideoRangeFlag::Full,
+        ..cicp
+    }
+}
+
+fn srgb_eotf(x: f32) -> f32 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn bt709_inverse_oetf(x: f32) -> f32 {
+    if x < 0.081 {
+        x / 4.5
+    } else {
+        ((x + 0.099) / 1.099).powf(1. / 0.45)
+    }
+}
+
+/// SMPTE ST 2084
+fn pq_eotf(x: f32) -> f32 {
+    const M1: f32 = 2610. / 16384.;
+    const M2: f32 = 2523. / 4096. * 128.;
+    const C1: f32 = 3424. / 4096.;
+    const C2: f32 = 2413. / 4096. * 32.;
+    const C3: f32 = 2392. / 4096. * 32.;
+
+    let p = x.powf(1. / M2);
+    let luminance = ((p - C1).max(0.) / (C2 - C3 * p)).powf(1. / M1) * 10000.;
+
+    luminance / REFERENCE_WHITE
+}
+
+/// ITU-R BT.2100 with the reference white at a signal of 0.75
+fn hlg_inverse_oetf(x: f32) -> f32 {
+    const REFERENCE_WHITE_SCENE: f32 = 0.264_962_15;
+
+    hlg_inverse_oetf_unscaled(x) / REFERENCE_WHITE_SCENE
+}
+
+fn hlg_inverse_oetf_unscaled(x: f32) -> f32 {
+    const A: f32 = 0.178_832_77;
+    const B: f32 = 0.284_668_92;
+    const C: f32 = 0.559_910_7;
+
+    if x <= 0.5 {
+        x * x / 3.
+    } else {
+        (((x - C) / A).exp() + B) / 12.
+    }
+}
+
+#[test]
+fn transfer_functions_test() {
+    let close = |a: f32, b: f32| (a - b).abs() < 1e-3;
+
+    assert!(close(srgb_eotf(0.), 0.));
+    assert!(close(srgb_eotf(0.5), 0.214));
+    assert!(close(srgb_eotf(1.), 1.));
+
+    assert!(close(bt709_inverse_oetf(1.), 1.));
+    assert!(close(bt709_inverse_oetf(0.0405), 0.009));
+
+    assert!(close(pq_eotf(1.), 10000. / REFERENCE_WHITE));
+    assert!(close(pq_eotf(0.58069), 1.));
+
+    assert!(close(hlg_inverse_oetf(0.75), 1.));
+    assert!(close(hlg_inverse_oetf_unscaled(1.), 1.));
+}
+
+#[test]
+fn linearize_test() {
+    let memory_format = MemoryFormat::R32g32b32a32Float;
+    let mut buf = [0.5_f32, -0.5, 1., 0.25]
+        .into_iter()
+        .flat_map(f32::to_ne_bytes)
+        .collect::<Vec<_>>();
+
+    let cicp = linearize(&ColorState::Srgb, memory_format, &mut buf);
+
+    assert_eq!(
+        cicp.transfer_characteristics,
+        TransferCharacteristics::Linear
+    );
+    assert_eq!(cicp.color_primaries, Cicp::SRGB.color_primaries);
+
+    let [r, g, b, a] = MemoryFormat::to_f32(memory_format, &buf);
+    assert!((r - 0.214).abs() < 1e-3);
+    assert!((g + 0.214).abs() < 1e-3);
+    assert_eq!(b, 1.);
+    assert_eq!(a, 0.25);
+}
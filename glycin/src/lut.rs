@@ -0,0 +1,291 @@
+use glycin_common::{MemoryFormat, MemoryFormatInfo};
+
+use crate::Error;
+
+/// Three-dimensional color lookup table
+///
+/// Maps RGB colors to new RGB colors, for example, to apply a color grading.
+/// Colors between the table entries are interpolated trilinearly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut3D {
+    size: usize,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+    table: Vec<[f32; 3]>,
+}
+
+impl Lut3D {
+    /// Create a LUT from its table entries
+    ///
+    /// The `table` must contain `size³` RGB entries with the red index
+    /// changing fastest, followed by the green and the blue index. The input
+    /// domain is 0 to 1 for all channels.
+    pub fn new(size: usize, table: Vec<[f32; 3]>) -> Result<Self, Error> {
+        Self::with_domain(size, [0.; 3], [1.; 3], table)
+    }
+
+    fn with_domain(
+        size: usize,
+        domain_min: [f32; 3],
+        domain_max: [f32; 3],
+        table: Vec<[f32; 3]>,
+    ) -> Result<Self, Error> {
+        if size < 2 {
+            return Err(Error::Lut(format!("Size must be at least 2, not {size}")));
+        }
+
+        let n_entries = size
+            .checked_mul(size)
+            .and_then(|x| x.checked_mul(size))
+            .ok_or_else(|| Error::Lut(format!("Size {size} is too large")))?;
+
+        if table.len() != n_entries {
+            return Err(Error::Lut(format!(
+                "Expected {n_entries} entries for size {size} but got {}",
+                table.len()
+            )));
+        }
+
+        if domain_min
+            .iter()
+            .zip(domain_max)
+            .any(|(min, max)| *min >= max)
+        {
+            return Err(Error::Lut(format!(
+                "Invalid domain from {domain_min:?} to {domain_max:?}"
+            )));
+        }
+
+        Ok(Self {
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    /// Parse a LUT in the `.cube` format
+    ///
+    /// Only 3D LUTs are supported. The `DOMAIN_MIN`, `DOMAIN_MAX`, and
+    /// `LUT_3D_INPUT_RANGE` keywords are respected.
+    pub fn from_cube(data: &str) -> Result<Self, Error> {
+        let mut size = None;
+        let mut domain_min = [0.; 3];
+        let mut domain_max = [1.; 3];
+        let mut table = Vec::new();
+
+        for line in data.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let Some(keyword) = words.next() else {
+                continue;
+            };
+
+            match keyword {
+                "TITLE" => {}
+                "LUT_3D_SIZE" => {
+                    size = Some(
+                        words
+                            .next()
+                            .and_then(|x| x.parse::<usize>().ok())
+                            .ok_or_else(|| Error::Lut(format!("Invalid size: {line}")))?,
+                    );
+                }
+                "DOMAIN_MIN" => domain_min = parse_floats(words, line)?,
+                "DOMAIN_MAX" => domain_max = parse_floats(words, line)?,
+                "LUT_3D_INPUT_RANGE" => {
+                    let [min, max] = parse_floats(words, line)?;
+                    domain_min = [min; 3];
+                    domain_max = [max; 3];
+                }
+                "LUT_1D_SIZE" | "LUT_1D_INPUT_RANGE" => {
+                    return Err(Error::Lut(String::from("1D LUTs are not supported")));
+                }
+                _ if keyword.starts_with(|x: char| x.is_ascii_alphabetic()) => {
+                    tracing::debug!("Ignoring unknown LUT keyword: {keyword}");
+                }
+                _ => table.push(parse_floats(line.split_whitespace(), line)?),
+            }
+        }
+
+        let size = size.ok_or_else(|| Error::Lut(String::from("LUT_3D_SIZE is missing")))?;
+
+        Self::with_domain(size, domain_min, domain_max, table)
+    }
+
+    /// Number of entries per dimension
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Map a color via the LUT
+    pub fn lookup(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let [r, g, b] = rgb;
+        let [r_min, g_min, b_min] = self.domain_min;
+        let [r_max, g_max, b_max] = self.domain_max;
+
+        let (r0, r1, fr) = self.position(r, r_min, r_max);
+        let (g0, g1, fg) = self.position(g, g_min, g_max);
+        let (b0, b1, fb) = self.position(b, b_min, b_max);
+
+        let c00 = lerp(self.entry(r0, g0, b0), self.entry(r1, g0, b0), fr);
+        let c10 = lerp(self.entry(r0, g1, b0), self.entry(r1, g1, b0), fr);
+        let c01 = lerp(self.entry(r0, g0, b1), self.entry(r1, g0, b1), fr);
+        let c11 = lerp(self.entry(r0, g1, b1), self.entry(r1, g1, b1), fr);
+
+        let c0 = lerp(c00, c10, fg);
+        let c1 = lerp(c01, c11, fg);
+
+        lerp(c0, c1, fb)
+    }
+
+    /// Indices of the surrounding entries and the position between them
+    fn position(&self, value: f32, min: f32, max: f32) -> (usize, usize, f32) {
+        let max_index = self.size.saturating_sub(1);
+        let value = ((value - min) / (max - min)).clamp(0., 1.) * max_index as f32;
+
+        let lower = value.floor();
+        // The value is clamped to the table range
+        #[allow(clippy::cast_possible_truncation)]
+        let index = (lower as usize).min(max_index);
+
+        (index, index.saturating_add(1).min(max_index), value - lower)
+    }
+
+    fn entry(&self, r: usize, g: usize, b: usize) -> [f32; 3] {
+        b.checked_mul(self.size)
+            .and_then(|x| x.checked_add(g))
+            .and_then(|x| x.checked_mul(self.size))
+            .and_then(|x| x.checked_add(r))
+            .and_then(|index| self.table.get(index))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Apply the LUT to all pixels
+///
+/// The alpha channel is kept as is.
+pub fn apply_lut(lut: &Lut3D, memory_format: MemoryFormat, buf: &mut [u8]) {
+    tracing::debug!("Applying 3D LUT");
+
+    let pixel_size = memory_format.n_bytes().usize();
+    let multiple = std::thread::available_parallelism().map_or(2, |x| x.get());
+    let n_pixels = buf.len().checked_div(pixel_size).unwrap_or_default();
+    let chunk_size = n_pixels
+        .div_ceil(multiple)
+        .saturating_mul(pixel_size)
+        .max(pixel_size);
+
+    std::thread::scope(|s| {
+        for chunk in buf.chunks_mut(chunk_size) {
+            s.spawn(move || {
+                for pixel in chunk.chunks_exact_mut(pixel_size) {
+                    let [r, g, b, a] = MemoryFormat::to_f32(memory_format, pixel);
+                    let [r, g, b] = lut.lookup([r, g, b]);
+                    MemoryFormat::from_f32([r, g, b, a], memory_format, pixel);
+                }
+            });
+        }
+    });
+}
+
+fn lerp(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    let [a0, a1, a2] = a;
+    let [b0, b1, b2] = b;
+
+    [a0 + (b0 - a0) * t, a1 + (b1 - a1) * t, a2 + (b2 - a2) * t]
+}
+
+fn parse_floats<'a, const N: usize>(
+    mut words: impl Iterator<Item = &'a str>,
+    line: &str,
+) -> Result<[f32; N], Error> {
+    let mut values = [0.; N];
+
+    for value in values.iter_mut() {
+        *value = words
+            .next()
+            .and_then(|x| x.parse().ok())
+            .ok_or_else(|| Error::Lut(format!("Invalid line: {line}")))?;
+    }
+
+    if words.next().is_some() {
+        return Err(Error::Lut(format!("Invalid line: {line}")));
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const IDENTITY: &str = "# Identity
+TITLE \"Identity\"
+LUT_3D_SIZE 2
+
+0 0 0
+1 0 0
+0 1 0
+1 1 0
+0 0 1
+1 0 1
+0 1 1
+1 1 1
+";
+
+    #[test]
+    fn from_cube() {
+        let lut = Lut3D::from_cube(IDENTITY).unwrap();
+        assert_eq!(lut.size(), 2);
+        assert_eq!(lut.lookup([0., 0., 0.]), [0., 0., 0.]);
+        assert_eq!(lut.lookup([1., 0., 1.]), [1., 0., 1.]);
+        assert_eq!(lut.lookup([0.25, 0.5, 0.75]), [0.25, 0.5, 0.75]);
+
+        // Values outside of the domain are clamped
+        assert_eq!(lut.lookup([-1., 2., 0.5]), [0., 1., 0.5]);
+    }
+
+    #[test]
+    fn from_cube_domain() {
+        let cube = IDENTITY.replace(
+            "LUT_3D_SIZE 2",
+            "LUT_3D_SIZE 2\nDOMAIN_MIN 0 0 0\nDOMAIN_MAX 2 4 1",
+        );
+        let lut = Lut3D::from_cube(&cube).unwrap();
+        assert_eq!(lut.lookup([1., 1., 1.]), [0.5, 0.25, 1.]);
+
+        let cube = IDENTITY.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE 0 2");
+        let lut = Lut3D::from_cube(&cube).unwrap();
+        assert_eq!(lut.lookup([1., 2., 0.]), [0.5, 1., 0.]);
+    }
+
+    #[test]
+    fn from_cube_invalid() {
+        let invalid = [
+            // No size
+            IDENTITY.replace("LUT_3D_SIZE 2", ""),
+            IDENTITY.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE two"),
+            IDENTITY.replace("LUT_3D_SIZE 2", "LUT_1D_SIZE 2"),
+            // Too few and too many entries
+            IDENTITY.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 3"),
+            format!("{IDENTITY}0 0 0\n"),
+            IDENTITY.replace("1 1 1", "1 1"),
+            IDENTITY.replace("1 1 1", "1 1 1 1"),
+            IDENTITY.replace("1 1 1", "1 1 x"),
+            IDENTITY.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 2\nDOMAIN_MIN 1 0 0"),
+            String::from("LUT_3D_SIZE 1\n0 0 0\n"),
+        ];
+
+        for cube in invalid {
+            assert!(
+                matches!(Lut3D::from_cube(&cube), Err(Error::Lut(_))),
+                "{cube}"
+            );
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::sync::{Arc, Mutex};
+
+use gio::glib;
+use gio::prelude::*;
+
+/// Cancellables that were canceled via [`MemoryPressure::trigger`]
+static CANCELED_BY_MEMORY_PRESSURE: Mutex<Vec<glib::WeakRef<gio::Cancellable>>> =
+    Mutex::new(Vec::new());
+
+/// Signal to abort loads when the system is low on memory
+///
+/// Loaders run with an address space limit. A decode that exceeds this limit
+/// is killed and fails with [`Error::PrematureExit`](crate::Error::PrematureExit).
+/// If the host detects memory pressure earlier, it can call
+/// [`trigger`](Self::trigger) to cancel all loads that use this signal via
+/// [`Loader::memory_pressure`](crate::Loader::memory_pressure). The loads are
+/// canceled via their [`Cancellable`](gio::Cancellable) and the resulting
+/// errors report [`CancelReason::MemoryPressure`](crate::CancelReason::MemoryPressure).
+///
+/// ```no_run
+/// # async fn x() {
+/// let memory_pressure = glycin::MemoryPressure::new();
+///
+/// let file = gio::File::for_path("image.jpg");
+/// let mut loader = glycin::Loader::new(file);
+/// loader.memory_pressure(&memory_pressure);
+///
+/// // For example, when `gio::MemoryMonitor` emits `low-memory-warning`
+/// memory_pressure.trigger();
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MemoryPressure {
+    cancellables: Arc<Mutex<Vec<glib::WeakRef<gio::Cancellable>>>>,
+}
+
+impl MemoryPressure {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel all loads that are currently using this signal
+    ///
+    /// Loads that start using the signal afterwards are not affected.
+    pub fn trigger(&self) {
+        let cancellables = match self.cancellables.lock() {
+            Ok(mut cancellables) => std::mem::take(&mut *cancellables),
+            Err(_) => return,
+        };
+
+        for cancellable in cancellables.iter().filter_map(|x| x.upgrade()) {
+            if cancellable.is_cancelled() {
+                continue;
+            }
+
+            tracing::debug!("Canceling load because of memory pressure");
+
+            if let Ok(mut canceled) = CANCELED_BY_MEMORY_PRESSURE.lock() {
+                canceled.retain(|x| x.upgrade().is_some());
+                canceled.push(cancellable.downgrade());
+            }
+
+            cancellable.cancel();
+        }
+    }
+
+    pub(crate) fn register(&self, cancellable: &gio::Cancellable) {
+        if let Ok(mut cancellables) = self.cancellables.lock() {
+            cancellables.retain(|x| x.upgrade().is_some());
+            cancellables.push(cancellable.downgrade());
+        }
+    }
+}
+
+/// Returns if the cancellable was canceled by [`MemoryPressure::trigger`]
+pub(crate) fn canceled_by_memory_pressure(cancellable: &gio::Cancellable) -> bool {
+    CANCELED_BY_MEMORY_PRESSURE
+        .lock()
+        .map(|canceled| {
+            canceled
+                .iter()
+                .any(|x| x.upgrade().as_ref() == Some(cancellable))
+        })
+        .unwrap_or_default()
+}
@@ -1,4 +1,5 @@
 use glycin_utils::{Frame, ImgBuf};
+use gufo_common::orientation::{Orientation, Rotation};
 
 use crate::Image;
 
@@ -10,3 +11,94 @@ pub fn apply_exif_orientation(img_buf: ImgBuf, frame: &mut Frame, image: &Image)
         glycin_utils::editing::change_orientation(img_buf, frame, orientation)
     }
 }
+
+/// Converts a clip in the coordinates of the oriented image to the coordinates of
+/// the stored image
+///
+/// Returns `None` if the clip is not within the image.
+pub fn clip_to_stored(
+    (x, y, width, height): (u32, u32, u32, u32),
+    stored_width: u32,
+    stored_height: u32,
+    orientation: Orientation,
+) -> Option<(u32, u32, u32, u32)> {
+    // Undo the rotation, which is applied after mirroring
+    let (x, y, width, height) = match orientation.rotate() {
+        Rotation::_0 => (x, y, width, height),
+        Rotation::_90 => (
+            stored_width.checked_sub(y)?.checked_sub(height)?,
+            x,
+            height,
+            width,
+        ),
+        Rotation::_180 => (
+            stored_width.checked_sub(x)?.checked_sub(width)?,
+            stored_height.checked_sub(y)?.checked_sub(height)?,
+            width,
+            height,
+        ),
+        Rotation::_270 => (
+            y,
+            stored_height.checked_sub(x)?.checked_sub(width)?,
+            height,
+            width,
+        ),
+    };
+
+    let x = if orientation.mirror() {
+        stored_width.checked_sub(x)?.checked_sub(width)?
+    } else {
+        x
+    };
+
+    Some((x, y, width, height))
+}
+
+/// Converts a clip in the coordinates of the stored image to the coordinates of
+/// the oriented image
+///
+/// Returns `None` if the clip is not within the image.
+pub fn clip_to_oriented(
+    clip: (u32, u32, u32, u32),
+    stored_width: u32,
+    stored_height: u32,
+    orientation: Orientation,
+) -> Option<(u32, u32, u32, u32)> {
+    let (oriented_width, oriented_height) = match orientation.rotate() {
+        Rotation::_90 | Rotation::_270 => (stored_height, stored_width),
+        _ => (stored_width, stored_height),
+    };
+
+    // Only the pure rotations are not their own inverse
+    let inverse = match orientation {
+        Orientation::Rotation90 => Orientation::Rotation270,
+        Orientation::Rotation270 => Orientation::Rotation90,
+        orientation => orientation,
+    };
+
+    clip_to_stored(clip, oriented_width, oriented_height, inverse)
+}
+
+#[test]
+fn clip_round_trip() {
+    let clip = (10, 20, 30, 40);
+    let (stored_width, stored_height) = (200, 100);
+
+    for orientation in [
+        Orientation::Id,
+        Orientation::Mirrored,
+        Orientation::Rotation90,
+        Orientation::MirroredRotation90,
+        Orientation::Rotation180,
+        Orientation::MirroredRotation180,
+        Orientation::Rotation270,
+        Orientation::MirroredRotation270,
+    ] {
+        let oriented = clip_to_oriented(clip, stored_width, stored_height, orientation).unwrap();
+        assert_eq!(
+            clip_to_stored(oriented, stored_width, stored_height, orientation),
+            Some(clip),
+            "{orientation:?}"
+        );
+    }
+}
@@ -1,19 +1,21 @@
 static DEFAULT_POOL: LazyLock<Arc<Pool>> = LazyLock::new(|| Arc::new(Pool::default()));
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::path::PathBuf;
-use std::sync::atomic::Ordering;
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, LazyLock, Mutex};
 use std::time::{Duration, Instant};
 use std::usize;
 
+use futures_channel::oneshot;
 use gio::glib;
 use gio::prelude::*;
 
-use crate::config::{ConfigEntry, ConfigEntryHash};
+use crate::config::{Config, ConfigEntry, ConfigEntryHash};
 use crate::dbus::ZbusProxy;
 use crate::util::{spawn_timeout, AsyncMutex, TimerHandle};
-use crate::{config, dbus, Error, SandboxMechanism};
+use crate::{config, dbus, Error, MimeType, SandboxMechanism, SandboxSelector, SeccompAction};
 
 #[derive(Debug)]
 pub struct PooledProcess<P: ZbusProxy<'static> + 'static> {
@@ -43,7 +45,7 @@ impl Drop for UsageTracker {
         *self.timeout.lock().unwrap() = Some(spawn_timeout(
             self.pool.config.loader_retention_time,
             async {
-                pool.clean_loaders().await;
+                pool.clean_processes().await;
             },
         ));
     }
@@ -70,12 +72,100 @@ pub struct Pool {
         BTreeMap<config::ConfigEntryHash, Vec<Arc<PooledProcess<dbus::EditorProxy<'static>>>>>,
     >,
     config: PoolConfig,
+    decode_memory: Mutex<DecodeMemory>,
+    active_processes: Arc<AtomicUsize>,
 }
 
+/// Counts a process as active until dropped
+///
+/// Moved into the exit callback of the process, which is dropped once the
+/// process has exited and has been reaped, or if spawning failed.
+struct ActiveProcess(Arc<AtomicUsize>);
+
+impl ActiveProcess {
+    fn new(active_processes: Arc<AtomicUsize>) -> Self {
+        active_processes.fetch_add(1, Ordering::SeqCst);
+        Self(active_processes)
+    }
+}
+
+impl Drop for ActiveProcess {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Whether the spawn of a process has been reported via
+/// [`PoolConfig::on_spawn`]
+///
+/// The exit of a process is only reported if its spawn has been reported.
+/// Processes can exit before that, for example, if their initialization fails.
+#[derive(Debug, Default)]
+enum ReportedSpawn {
+    #[default]
+    Pending,
+    Reported,
+    /// The process exited before its spawn was reported
+    Exited(Option<ExitStatus>),
+}
+
+/// Memory that is reserved for frame decodes with
+/// [`PoolConfig::max_total_decode_memory`]
+#[derive(Debug, Default)]
+struct DecodeMemory {
+    reserved: u64,
+    waiting: VecDeque<(u64, oneshot::Sender<DecodeMemoryReservation>)>,
+}
+
+impl DecodeMemory {
+    /// A decode that exceeds the limit on its own is only run if no other
+    /// decode is running
+    fn fits(&self, bytes: u64, max_total_decode_memory: u64) -> bool {
+        self.reserved == 0 || self.reserved.saturating_add(bytes) <= max_total_decode_memory
+    }
+}
+
+/// Releases the reserved memory when dropped
 #[derive(Debug)]
+pub(crate) struct DecodeMemoryReservation {
+    pool: Option<Arc<Pool>>,
+    bytes: u64,
+}
+
+impl Drop for DecodeMemoryReservation {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            pool.release_decode_memory(self.bytes);
+        }
+    }
+}
+
+/// Information about a pooled loader or editor process
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ProcessInfo {
+    /// Mime type for which the process has been spawned
+    ///
+    /// The process might be reused for other mime types that are handled by
+    /// the same binary.
+    pub mime_type: MimeType,
+    /// Process id
+    pub pid: u32,
+    pub sandbox_mechanism: SandboxMechanism,
+}
+
+type SpawnHook = Arc<dyn Fn(&ProcessInfo) + Send + Sync>;
+type ExitHook = Arc<dyn Fn(&ProcessInfo, Option<ExitStatus>) + Send + Sync>;
+
 pub struct PoolConfig {
     loader_retention_time: Duration,
     max_parallel_operations: usize,
+    max_total_decode_memory: Option<u64>,
+    read_buffer_size: usize,
+    cache_dir: Option<PathBuf>,
+    seccomp_default_action: Option<SeccompAction>,
+    on_spawn: Option<SpawnHook>,
+    on_exit: Option<ExitHook>,
 }
 
 impl Default for PoolConfig {
@@ -83,15 +173,48 @@ impl Default for PoolConfig {
         Self {
             loader_retention_time: Duration::from_secs(30),
             max_parallel_operations: usize::MAX,
+            max_total_decode_memory: None,
+            read_buffer_size: dbus::BUF_SIZE,
+            cache_dir: None,
+            seccomp_default_action: None,
+            on_spawn: None,
+            on_exit: None,
         }
     }
 }
 
+impl std::fmt::Debug for PoolConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PoolConfig")
+            .field("loader_retention_time", &self.loader_retention_time)
+            .field("max_parallel_operations", &self.max_parallel_operations)
+            .field("max_total_decode_memory", &self.max_total_decode_memory)
+            .field("read_buffer_size", &self.read_buffer_size)
+            .field("cache_dir", &self.cache_dir)
+            .field("seccomp_default_action", &self.seccomp_default_action)
+            .field("on_spawn", &self.on_spawn.is_some())
+            .field("on_exit", &self.on_exit.is_some())
+            .finish()
+    }
+}
+
 impl PoolConfig {
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Sets how long unused processes are kept in the pool
+    ///
+    /// Processes that haven't been used for this time are terminated. The
+    /// default is 30 seconds. A loader is in use as long as an
+    /// [`Image`](crate::Image) that has been loaded with it exists, even if
+    /// the image doesn't request any frames. The loader is therefore never
+    /// terminated and respawned while the image is kept around.
+    pub fn loader_retention_time(&mut self, loader_retention_time: Duration) -> &mut Self {
+        self.loader_retention_time = loader_retention_time;
+        self
+    }
+
     pub fn max_parallel_operations(&mut self, max_parallel_operations: usize) -> &mut Self {
         if max_parallel_operations == 0 {
             self.max_parallel_operations = usize::MAX;
@@ -100,6 +223,91 @@ impl PoolConfig {
         }
         self
     }
+
+    /// Sets the memory budget for all decodes of the pool in bytes
+    ///
+    /// The memory use of a frame decode is estimated from the image dimensions
+    /// and the largest pixel size among the
+    /// [accepted memory formats](crate::Loader::accepted_memory_formats). The
+    /// initialization of an image is estimated with the size of the source,
+    /// if it's known. Requests that would exceed the budget are queued until
+    /// enough running decodes have finished. A single request that exceeds
+    /// the budget on its own is run once no other decode is running. This
+    /// limits the combined memory use of all loaders, while the memory limit
+    /// of each loader process only applies to a single process. By default,
+    /// there is no budget.
+    pub fn max_total_decode_memory(&mut self, max_total_decode_memory: u64) -> &mut Self {
+        self.max_total_decode_memory = Some(max_total_decode_memory);
+        self
+    }
+
+    /// Sets the size of the buffer for passing image data to the loaders
+    ///
+    /// Image data that is not already in memory is read in chunks of this
+    /// size and written to the loader. Larger buffers can reduce the overhead
+    /// for large files on fast storage, at the cost of more memory per load.
+    ///
+    /// The first chunk is also used to guess the image format. Therefore,
+    /// values below the default of 64 KiB are raised to the default.
+    pub fn read_buffer_size(&mut self, read_buffer_size: usize) -> &mut Self {
+        self.read_buffer_size = read_buffer_size.max(dbus::BUF_SIZE);
+        self
+    }
+
+    /// Sets a writable directory for caches of the loaders
+    ///
+    /// Loaders that use fontconfig, like the SVG loader, write their font
+    /// caches to the default cache location. With this option, the directory
+    /// is made available inside the sandbox instead and `XDG_CACHE_HOME` is
+    /// pointed to it. This is useful if the default location is read-only,
+    /// for example, to use a tmpfs in a container with a read-only root
+    /// filesystem. Other loaders are not allowed to write files by the
+    /// sandbox and don't get access to the directory.
+    pub fn cache_dir(&mut self, cache_dir: impl Into<PathBuf>) -> &mut Self {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    /// Sets the action for syscalls that are not allowed by the sandbox
+    ///
+    /// Without this option, the action is read from the
+    /// `GLYCIN_SECCOMP_DEFAULT_ACTION` environment variable, where the value
+    /// `KILL_PROCESS` selects [`SeccompAction::KillProcess`]. Otherwise,
+    /// [`SeccompAction::Trap`] is used.
+    ///
+    /// With [`SeccompAction::KillProcess`], blocked syscalls can be logged by
+    /// auditd, but are not available via
+    /// [`ErrorCtx::denied_syscalls`](crate::ErrorCtx::denied_syscalls).
+    /// It also doesn't work with tools like valgrind, which is why it's not
+    /// the default. The option only applies to the
+    /// [`SandboxMechanism::NativeSandbox`].
+    pub fn seccomp_default_action(&mut self, seccomp_default_action: SeccompAction) -> &mut Self {
+        self.seccomp_default_action = Some(seccomp_default_action);
+        self
+    }
+
+    /// Sets a function that is called when a new process has been spawned
+    pub fn on_spawn(
+        &mut self,
+        on_spawn: impl Fn(&ProcessInfo) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.on_spawn = Some(Arc::new(on_spawn));
+        self
+    }
+
+    /// Sets a function that is called when a spawned process has exited
+    ///
+    /// Only called for processes whose spawn has been reported via
+    /// [`on_spawn`](Self::on_spawn). Processes that exit during their
+    /// initialization are not reported. The exit status is `None` if it
+    /// couldn't be determined.
+    pub fn on_exit(
+        &mut self,
+        on_exit: impl Fn(&ProcessInfo, Option<ExitStatus>) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.on_exit = Some(Arc::new(on_exit));
+        self
+    }
 }
 
 impl Pool {
@@ -110,15 +318,190 @@ impl Pool {
         Arc::new(pool)
     }
 
+    /// Size of the buffer for reading the image source
+    pub(crate) fn read_buffer_size(&self) -> usize {
+        self.config.read_buffer_size
+    }
+
+    /// Action for syscalls that are not allowed by the sandbox
+    fn seccomp_default_action(&self) -> SeccompAction {
+        self.config
+            .seccomp_default_action
+            .unwrap_or_else(SeccompAction::from_env)
+    }
+
+    /// Waits until `bytes` fit into the decode memory budget and reserves them
+    ///
+    /// Requests are granted in the order they arrived.
+    pub(crate) async fn reserve_decode_memory(
+        self: &Arc<Self>,
+        bytes: u64,
+    ) -> DecodeMemoryReservation {
+        let Some(max_total_decode_memory) = self.config.max_total_decode_memory else {
+            return DecodeMemoryReservation { pool: None, bytes };
+        };
+
+        let receiver = {
+            let mut decode_memory = self.decode_memory.lock().unwrap();
+            decode_memory
+                .waiting
+                .retain(|(_, sender)| !sender.is_canceled());
+
+            if decode_memory.waiting.is_empty()
+                && decode_memory.fits(bytes, max_total_decode_memory)
+            {
+                decode_memory.reserved = decode_memory.reserved.saturating_add(bytes);
+                return DecodeMemoryReservation {
+                    pool: Some(self.clone()),
+                    bytes,
+                };
+            }
+
+            tracing::debug!(
+                "Decode memory budget exhausted ({} of {max_total_decode_memory} bytes reserved). Queuing request.",
+                decode_memory.reserved
+            );
+            let (sender, receiver) = oneshot::channel();
+            decode_memory.waiting.push_back((bytes, sender));
+            receiver
+        };
+
+        // The sender is only dropped after sending a reservation
+        receiver
+            .await
+            .unwrap_or(DecodeMemoryReservation { pool: None, bytes })
+    }
+
+    fn release_decode_memory(self: &Arc<Self>, bytes: u64) {
+        let Some(max_total_decode_memory) = self.config.max_total_decode_memory else {
+            return;
+        };
+
+        let mut granted = Vec::new();
+        {
+            let mut decode_memory = self.decode_memory.lock().unwrap();
+            decode_memory.reserved = decode_memory.reserved.saturating_sub(bytes);
+            decode_memory
+                .waiting
+                .retain(|(_, sender)| !sender.is_canceled());
+
+            while let Some((bytes, _)) = decode_memory.waiting.front() {
+                let bytes = *bytes;
+                if !decode_memory.fits(bytes, max_total_decode_memory) {
+                    break;
+                }
+
+                if let Some((_, sender)) = decode_memory.waiting.pop_front() {
+                    decode_memory.reserved = decode_memory.reserved.saturating_add(bytes);
+                    granted.push((sender, bytes));
+                }
+            }
+        }
+
+        // Sending without holding the lock, since a reservation that can't be
+        // delivered is dropped and releases its memory again
+        for (sender, bytes) in granted {
+            let _ = sender.send(DecodeMemoryReservation {
+                pool: Some(self.clone()),
+                bytes,
+            });
+        }
+    }
+
     pub fn global() -> Arc<Self> {
         DEFAULT_POOL.clone()
     }
 
+    /// Number of loader and editor processes that have not exited yet
+    ///
+    /// This includes unused processes that are kept in the pool and
+    /// processes that have been dropped from the pool but are still shutting
+    /// down. Once all images and editors of the pool have been dropped, the
+    /// count drops to zero after the
+    /// [`loader_retention_time`](PoolConfig::loader_retention_time). This
+    /// allows to check that no processes are leaked.
+    pub fn active_process_count(&self) -> usize {
+        self.active_processes.load(Ordering::SeqCst)
+    }
+
+    /// Spawn loaders for the given mime types ahead of time
+    ///
+    /// The loader processes are kept in the pool, such that the first
+    /// [`Loader::load`](crate::Loader::load) for one of these formats doesn't
+    /// have to wait for a new loader to spawn. If a usable loader is already
+    /// in the pool, no additional one is spawned. Like other unused processes,
+    /// the loaders are dropped after the retention time.
+    ///
+    /// The loaders are spawned with the default [`SandboxSelector`]. If
+    /// spawning a loader fails, the remaining mime types are still prewarmed
+    /// and the first error is returned.
+    pub async fn prewarm(self: &Arc<Self>, mime_types: &[MimeType]) -> Result<(), Error> {
+        self.prewarm_processes(&self.loaders, mime_types, |config, mime_type| {
+            Ok(ConfigEntry::Loader(config.loader(mime_type)?.clone()))
+        })
+        .await
+    }
+
+    /// Spawn editors for the given mime types ahead of time
+    ///
+    /// Works like [`Pool::prewarm`] but for editors that are used by
+    /// [`Editor`](crate::Editor) and [`Creator`](crate::Creator).
+    pub async fn prewarm_editors(self: &Arc<Self>, mime_types: &[MimeType]) -> Result<(), Error> {
+        self.prewarm_processes(&self.editors, mime_types, |config, mime_type| {
+            Ok(ConfigEntry::Editor(config.editor(mime_type)?.clone()))
+        })
+        .await
+    }
+
+    async fn prewarm_processes<P: ZbusProxy<'static> + 'static>(
+        self: &Arc<Self>,
+        pooled_processes: &AsyncMutex<BTreeMap<ConfigEntryHash, Vec<Arc<PooledProcess<P>>>>>,
+        mime_types: &[MimeType],
+        config_entry: impl Fn(&Config, &MimeType) -> Result<ConfigEntry, Error>,
+    ) -> Result<(), Error> {
+        let config = Config::cached().await;
+        let sandbox_mechanism = SandboxSelector::default()
+            .determine_sandbox_mechanism()
+            .await;
+        let cancellable = gio::Cancellable::new();
+
+        let mut result = Ok(());
+        for mime_type in mime_types {
+            let prewarmed = match config_entry(config, mime_type) {
+                Ok(config_entry) => self
+                    .clone()
+                    .get_process(
+                        pooled_processes,
+                        config_entry,
+                        mime_type,
+                        sandbox_mechanism,
+                        None,
+                        None,
+                        &cancellable,
+                    )
+                    .await
+                    .map(|_| ()),
+                Err(err) => Err(err),
+            };
+
+            if let Err(err) = prewarmed {
+                tracing::warn!("Failed to prewarm process for {mime_type}: {err}");
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+
+        result
+    }
+
     pub(crate) async fn get_loader(
         self: Arc<Self>,
         loader_config: config::ImageLoaderConfig,
+        mime_type: &MimeType,
         sandbox_mechanism: SandboxMechanism,
         base_dir: Option<PathBuf>,
+        font_dir: Option<PathBuf>,
         cancellable: &gio::Cancellable,
     ) -> Result<
         (
@@ -134,8 +517,10 @@ impl Pool {
             .get_process(
                 pooled_loaders,
                 ConfigEntry::Loader(loader_config.clone()),
+                mime_type,
                 sandbox_mechanism,
                 base_dir,
+                font_dir,
                 cancellable,
             )
             .await?;
@@ -146,6 +531,7 @@ impl Pool {
     pub(crate) async fn get_editor(
         self: Arc<Self>,
         editor_config: config::ImageEditorConfig,
+        mime_type: &MimeType,
         sandbox_mechanism: SandboxMechanism,
         base_dir: Option<PathBuf>,
         cancellable: &gio::Cancellable,
@@ -163,8 +549,10 @@ impl Pool {
             .get_process(
                 pooled_editors,
                 ConfigEntry::Editor(editor_config.clone()),
+                mime_type,
                 sandbox_mechanism,
                 base_dir,
+                None,
                 cancellable,
             )
             .await?;
@@ -176,11 +564,18 @@ impl Pool {
         self: Arc<Self>,
         pooled_processes: &AsyncMutex<BTreeMap<ConfigEntryHash, Vec<Arc<PooledProcess<P>>>>>,
         config: config::ConfigEntry,
+        mime_type: &MimeType,
         sandbox_mechanism: SandboxMechanism,
         base_dir: Option<PathBuf>,
+        font_dir: Option<PathBuf>,
         cancellable: &gio::Cancellable,
     ) -> Result<(Arc<PooledProcess<P>>, Arc<UsageTracker>), Error> {
-        let config_hash = config.hash_value(base_dir.clone(), sandbox_mechanism);
+        // Only directories that the process uses are part of the key. Otherwise,
+        // processes that would behave identically, like prewarmed ones, are not
+        // reused.
+        let base_dir = base_dir.filter(|_| config.expose_base_dir());
+        let font_dir = font_dir.filter(|_| config.fontconfig());
+        let config_hash = config.hash_value(base_dir.clone(), font_dir.clone(), sandbox_mechanism);
         let mut pooled_processes = pooled_processes.lock().await;
         let pooled_processes = pooled_processes.entry(config_hash).or_default();
 
@@ -213,11 +608,51 @@ impl Pool {
             return Err(Error::Canceled(None));
         };
 
+        let active_process = ActiveProcess::new(self.active_processes.clone());
+        let reported_spawn = Arc::new(Mutex::new(ReportedSpawn::default()));
+        let on_exit = Box::new(glib::clone!(
+            #[strong(rename_to = on_exit)]
+            self.config.on_exit,
+            #[strong]
+            reported_spawn,
+            #[strong]
+            mime_type,
+            move |pid: u32, status: Option<ExitStatus>| {
+                let reported = {
+                    let mut reported_spawn = reported_spawn.lock().unwrap();
+                    match *reported_spawn {
+                        ReportedSpawn::Pending => {
+                            *reported_spawn = ReportedSpawn::Exited(status);
+                            false
+                        }
+                        ReportedSpawn::Reported => true,
+                        ReportedSpawn::Exited(..) => false,
+                    }
+                };
+
+                if let Some(on_exit) = on_exit.filter(|_| reported) {
+                    on_exit(
+                        &ProcessInfo {
+                            mime_type,
+                            pid,
+                            sandbox_mechanism,
+                        },
+                        status,
+                    )
+                }
+                drop(active_process);
+            }
+        )) as dbus::ProcessExitCallback;
+
         let process = Arc::new(
             dbus::RemoteProcess::new(
                 config.clone(),
                 sandbox_mechanism,
                 base_dir,
+                font_dir,
+                self.config.cache_dir.clone(),
+                self.seccomp_default_action(),
+                Some(on_exit),
                 &process_cancellable,
             )
             .await?,
@@ -225,6 +660,26 @@ impl Pool {
 
         cancellable.disconnect_cancelled(process_cancellable_tie);
 
+        let process_info = ProcessInfo {
+            mime_type: mime_type.clone(),
+            pid: process.pid,
+            sandbox_mechanism,
+        };
+        let reported_spawn = {
+            // Hold the lock such that the exit can't be reported before the spawn
+            let mut reported_spawn = reported_spawn.lock().unwrap();
+            if let Some(on_spawn) = &self.config.on_spawn {
+                on_spawn(&process_info);
+            }
+            std::mem::replace(&mut *reported_spawn, ReportedSpawn::Reported)
+        };
+        // Report an exit that happened before the spawn was reported
+        if let (ReportedSpawn::Exited(status), Some(on_exit)) =
+            (reported_spawn, &self.config.on_exit)
+        {
+            on_exit(&process_info, status);
+        }
+
         let _timeout = Arc::new(Mutex::new(None));
 
         let usage_tracker = Arc::new(UsageTracker::new(self.clone(), _timeout.clone()));
@@ -241,18 +696,28 @@ impl Pool {
         Ok((pp, usage_tracker))
     }
 
-    pub(crate) async fn clean_loaders(self: Arc<Self>) {
+    /// Drop processes that have been unused for the retention time
+    pub(crate) async fn clean_processes(self: Arc<Self>) {
         tracing::debug!("Cleaning up loaders");
-        let mut loader_map = self.loaders.lock().await;
+        self.clean(&self.loaders).await;
+        tracing::debug!("Cleaning up editors");
+        self.clean(&self.editors).await;
+    }
+
+    async fn clean<P: ZbusProxy<'static> + 'static>(
+        &self,
+        pooled_processes: &AsyncMutex<BTreeMap<ConfigEntryHash, Vec<Arc<PooledProcess<P>>>>>,
+    ) {
+        let mut process_map = pooled_processes.lock().await;
 
-        for (cfg, loaders) in loader_map.iter_mut() {
-            loaders.retain(|loader| {
-                let n_users = loader.n_users();
-                let idle = loader.last_use.lock().unwrap().elapsed();
+        for (cfg, processes) in process_map.iter_mut() {
+            processes.retain(|process| {
+                let n_users = process.n_users();
+                let idle = process.last_use.lock().unwrap().elapsed();
                 let drop = n_users == 0 && idle > self.config.loader_retention_time;
 
                 tracing::debug!(
-                    "Loader {:?}: drop {drop} users {n_users} (max {}), idle {idle:?} (max {:?})",
+                    "Process {:?}: drop {drop} users {n_users} (max {}), idle {idle:?} (max {:?})",
                     cfg.exec(),
                     self.config.max_parallel_operations,
                     self.config.loader_retention_time
@@ -260,13 +725,76 @@ impl Pool {
 
                 if drop {
                     tracing::debug!(
-                        "Dropping loader {:?} {}",
+                        "Dropping process {:?} {}",
                         cfg.exec(),
-                        Arc::strong_count(&loader.process)
+                        Arc::strong_count(&process.process)
                     )
                 }
                 !drop
             });
         }
+
+        process_map.retain(|_, processes| !processes.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures_util::FutureExt;
+
+    use super::*;
+
+    #[test]
+    fn decode_memory_queue() {
+        let mut config = PoolConfig::new();
+        config.max_total_decode_memory(100);
+        let pool = Pool::new(config);
+
+        let first = pool.reserve_decode_memory(60).now_or_never().unwrap();
+        let mut second = Box::pin(pool.reserve_decode_memory(60));
+        assert!((&mut second).now_or_never().is_none());
+
+        // Later requests wait for the earlier ones, even if they would fit
+        let mut third = Box::pin(pool.reserve_decode_memory(10));
+        assert!((&mut third).now_or_never().is_none());
+
+        drop(first);
+        let second = second.now_or_never().unwrap();
+        let third = third.now_or_never().unwrap();
+        assert_eq!(pool.decode_memory.lock().unwrap().reserved, 70);
+
+        // A request that exceeds the budget on its own runs alone
+        let mut large = Box::pin(pool.reserve_decode_memory(1000));
+        assert!((&mut large).now_or_never().is_none());
+        drop((second, third));
+        let large = large.now_or_never().unwrap();
+        assert_eq!(pool.decode_memory.lock().unwrap().reserved, 1000);
+
+        drop(large);
+        assert_eq!(pool.decode_memory.lock().unwrap().reserved, 0);
+    }
+
+    #[test]
+    fn read_buffer_size() {
+        let mut config = PoolConfig::new();
+        assert_eq!(config.read_buffer_size, dbus::BUF_SIZE);
+
+        config.read_buffer_size(1 << 20);
+        assert_eq!(config.read_buffer_size, 1 << 20);
+
+        // Smaller buffers would break guessing the mime type
+        config.read_buffer_size(16);
+        assert_eq!(config.read_buffer_size, dbus::BUF_SIZE);
+        config.read_buffer_size(0);
+        assert_eq!(config.read_buffer_size, dbus::BUF_SIZE);
+    }
+
+    #[test]
+    fn decode_memory_unlimited() {
+        let pool = Pool::new(PoolConfig::new());
+
+        let _reservation = pool.reserve_decode_memory(u64::MAX).now_or_never().unwrap();
+        assert!(pool.reserve_decode_memory(1).now_or_never().is_some());
+        assert_eq!(pool.decode_memory.lock().unwrap().reserved, 0);
     }
 }
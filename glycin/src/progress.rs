@@ -0,0 +1,259 @@
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use gio::glib;
+use gio::prelude::*;
+
+use crate::ErrorCtx;
+
+/// Combined progress of many loads
+///
+/// Loaders are attached via
+/// [`Loader::progress_aggregator`](crate::Loader::progress_aggregator). An
+/// image counts as started when its loader process is ready and as completed
+/// when its first frame is loaded or loading fails. For jobs of
+/// [`transcode_many`](crate::transcode_many), the image is completed when the
+/// conversion is done. The counters are updated without locking, such that
+/// [`progress`](Self::progress) can be polled frequently, for example, to
+/// update a progress bar.
+///
+/// ```no_run
+/// # async fn x() {
+/// let aggregator = glycin::ProgressAggregator::new();
+///
+/// for path in ["a.jpg", "b.png"] {
+///     let mut loader = glycin::Loader::new(gio::File::for_path(path));
+///     loader.progress_aggregator(&aggregator);
+///     // Use the loader
+/// }
+///
+/// let progress = aggregator.progress();
+/// println!(
+///     "{}/{} done, {} failed",
+///     progress.completed(),
+///     progress.total,
+///     progress.failed
+/// );
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ProgressAggregator {
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    total: AtomicUsize,
+    started: AtomicUsize,
+    finished: AtomicUsize,
+    failed: AtomicUsize,
+    canceled: AtomicUsize,
+    first_start: OnceLock<Instant>,
+    cancellables: Mutex<Vec<glib::WeakRef<gio::Cancellable>>>,
+}
+
+impl ProgressAggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current state of all attached loads
+    pub fn progress(&self) -> Progress {
+        let counters = &self.counters;
+
+        // Completed loads first, such that they are also counted as started.
+        // Loads are counted as started before they complete, and the acquire
+        // loads synchronize with the release increments of the earlier steps.
+        let finished = counters.finished.load(Ordering::Acquire);
+        let failed = counters.failed.load(Ordering::Acquire);
+        let canceled = counters.canceled.load(Ordering::Acquire);
+        let started = counters.started.load(Ordering::Acquire);
+        let total = counters.total.load(Ordering::Acquire);
+
+        Progress {
+            total,
+            started,
+            finished,
+            failed,
+            canceled,
+            elapsed: counters
+                .first_start
+                .get()
+                .map(Instant::elapsed)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Cancel all attached loads that are running
+    ///
+    /// The loads are canceled via their [`Cancellable`](gio::Cancellable) and
+    /// count as canceled. Loads that start afterwards are not affected.
+    pub fn cancel(&self) {
+        let cancellables = match self.counters.cancellables.lock() {
+            Ok(mut cancellables) => std::mem::take(&mut *cancellables),
+            Err(_) => return,
+        };
+
+        for cancellable in cancellables.iter().filter_map(|x| x.upgrade()) {
+            cancellable.cancel();
+        }
+    }
+
+    pub(crate) fn attach(&self) -> Arc<ProgressEntry> {
+        self.counters.total.fetch_add(1, Ordering::Release);
+
+        Arc::new(ProgressEntry {
+            aggregator: self.clone(),
+            state: AtomicU8::new(ProgressEntry::PENDING),
+            defer_completion: AtomicBool::new(false),
+        })
+    }
+}
+
+/// Snapshot of the state of a [`ProgressAggregator`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Progress {
+    /// Number of attached loads
+    ///
+    /// Loaders that are dropped without being loaded are not counted.
+    pub total: usize,
+    /// Loads whose loader process was ready, including completed loads
+    pub started: usize,
+    /// Loads that completed successfully
+    pub finished: usize,
+    /// Loads that completed with an error
+    pub failed: usize,
+    /// Loads that were canceled
+    ///
+    /// This includes images that were dropped before a frame was loaded.
+    pub canceled: usize,
+    /// Time since the first load started
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Number of loads that are finished, failed, or canceled
+    pub fn completed(&self) -> usize {
+        self.finished
+            .saturating_add(self.failed)
+            .saturating_add(self.canceled)
+    }
+
+    /// Number of loads that are not completed yet
+    pub fn remaining(&self) -> usize {
+        self.total.saturating_sub(self.completed())
+    }
+
+    /// Estimated time until all loads are completed
+    ///
+    /// Assumes that the remaining loads take as long on average as the
+    /// completed ones. Returns `None` if no load has completed yet.
+    pub fn eta(&self) -> Option<Duration> {
+        let completed = self.completed();
+        if completed == 0 {
+            return None;
+        }
+
+        let seconds = self.elapsed.as_secs_f64() * self.remaining() as f64 / completed as f64;
+        Duration::try_from_secs_f64(seconds).ok()
+    }
+}
+
+/// Progress of a single load
+#[derive(Debug)]
+pub(crate) struct ProgressEntry {
+    aggregator: ProgressAggregator,
+    state: AtomicU8,
+    defer_completion: AtomicBool,
+}
+
+impl ProgressEntry {
+    const PENDING: u8 = 0;
+    const STARTED: u8 = 1;
+    const COMPLETED: u8 = 2;
+
+    /// Allows [`ProgressAggregator::cancel`] to cancel the load
+    pub(crate) fn register(&self, cancellable: &gio::Cancellable) {
+        if let Ok(mut cancellables) = self.aggregator.counters.cancellables.lock() {
+            cancellables.retain(|x| x.upgrade().is_some());
+            cancellables.push(cancellable.downgrade());
+        }
+    }
+
+    /// The loader process is ready
+    pub(crate) fn start(&self) {
+        if self
+            .state
+            .compare_exchange(
+                Self::PENDING,
+                Self::STARTED,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+        {
+            self.count_start();
+        }
+    }
+
+    /// Only complete the load via [`Self::complete`] and not via frames
+    pub(crate) fn defer_completion(&self) {
+        self.defer_completion.store(true, Ordering::Relaxed);
+    }
+
+    /// A frame was loaded or failed to load
+    pub(crate) fn complete_frame<T>(&self, result: &Result<T, ErrorCtx>) {
+        if self.defer_completion.load(Ordering::Relaxed) {
+            return;
+        }
+
+        match result {
+            Err(err) if err.is_no_more_frames() => {}
+            result => self.complete(result),
+        }
+    }
+
+    /// The load is done
+    ///
+    /// Only the first call is counted.
+    pub(crate) fn complete<T>(&self, result: &Result<T, ErrorCtx>) {
+        let previous = self.state.swap(Self::COMPLETED, Ordering::Relaxed);
+        if previous == Self::COMPLETED {
+            return;
+        }
+        if previous == Self::PENDING {
+            self.count_start();
+        }
+
+        let counters = &self.aggregator.counters;
+        let counter = match result {
+            Ok(_) => &counters.finished,
+            Err(err) if err.error().cancel_reason().is_some() => &counters.canceled,
+            Err(_) => &counters.failed,
+        };
+        counter.fetch_add(1, Ordering::Release);
+    }
+
+    fn count_start(&self) {
+        let counters = &self.aggregator.counters;
+        counters.first_start.get_or_init(Instant::now);
+        counters.started.fetch_add(1, Ordering::Release);
+    }
+}
+
+impl Drop for ProgressEntry {
+    fn drop(&mut self) {
+        let counters = &self.aggregator.counters;
+        match self.state.load(Ordering::Relaxed) {
+            Self::PENDING => {
+                counters.total.fetch_sub(1, Ordering::Relaxed);
+            }
+            Self::STARTED => {
+                counters.canceled.fetch_add(1, Ordering::Release);
+            }
+            _ => {}
+        }
+    }
+}
@@ -17,7 +17,7 @@ use nix::sys::resource;
 
 use crate::config::{ConfigEntry, ImageLoaderConfig};
 use crate::util::{self, new_async_mutex, spawn_blocking, AsyncMutex};
-use crate::{Error, SandboxMechanism};
+use crate::{Error, SandboxMechanism, SeccompAction};
 
 type SystemSetupStore = Arc<Result<SystemSetup, Arc<io::Error>>>;
 
@@ -171,6 +171,10 @@ pub struct Sandbox {
     config_entry: ConfigEntry,
     dbus_socket: UnixStream,
     ro_bind_extra: Vec<PathBuf>,
+    font_dir: Option<PathBuf>,
+    fontconfig_file: Option<PathBuf>,
+    cache_dir: Option<PathBuf>,
+    seccomp_action: SeccompAction,
 }
 
 static_assertions::assert_impl_all!(Sandbox: Send, Sync);
@@ -195,6 +199,10 @@ impl Sandbox {
             config_entry,
             dbus_socket,
             ro_bind_extra: Vec::new(),
+            font_dir: None,
+            fontconfig_file: None,
+            cache_dir: None,
+            seccomp_action: SeccompAction::from_env(),
         }
     }
 
@@ -206,7 +214,48 @@ impl Sandbox {
         self.ro_bind_extra.push(path);
     }
 
-    pub async fn spawn(self) -> Result<SpawnedSandbox, Error> {
+    /// Expose an additional font directory
+    ///
+    /// The directory is added to the default fontconfig config via a config
+    /// file that is passed as `FONTCONFIG_FILE`.
+    pub fn add_font_dir(&mut self, path: PathBuf) {
+        self.add_ro_bind(path.clone());
+        self.font_dir = Some(path);
+    }
+
+    /// Point fontconfig to the config with the additional font directory
+    fn set_fontconfig_file(&self, command: &mut Command) {
+        if let Some(fontconfig_file) = &self.fontconfig_file {
+            command.env("FONTCONFIG_FILE", fontconfig_file);
+        }
+    }
+
+    /// Use a writable cache directory via `XDG_CACHE_HOME`
+    pub fn set_cache_dir(&mut self, path: PathBuf) {
+        self.cache_dir = Some(path);
+    }
+
+    /// Action for syscalls that are not allowed by the seccomp filter
+    pub fn set_seccomp_action(&mut self, seccomp_action: SeccompAction) {
+        self.seccomp_action = seccomp_action;
+    }
+
+    /// Point caches like fontconfig's to the cache directory
+    fn set_cache_home(&self, command: &mut Command) {
+        if let Some(cache_dir) = &self.cache_dir {
+            command.env("XDG_CACHE_HOME", cache_dir);
+        }
+    }
+
+    pub async fn spawn(mut self) -> Result<SpawnedSandbox, Error> {
+        if let Some(font_dir) = self.font_dir.clone() {
+            let fontconfig_file =
+                spawn_blocking(move || crate::fontconfig::config_file_with_font_dir(&font_dir))
+                    .await?;
+            self.add_ro_bind(fontconfig_file.clone());
+            self.fontconfig_file = Some(fontconfig_file);
+        }
+
         let dbus_fd = self.dbus_socket.as_raw_fd();
 
         let mut shared_fds = Vec::new();
@@ -275,48 +324,51 @@ impl Sandbox {
             }
         }
 
-        let config_entry = self.config_entry.clone();
+        self.set_fontconfig_file(&mut command);
+        self.set_cache_home(&mut command);
 
-//        fn allow_open_readonly(filter: &mut libseccomp::ScmpFilterContext) -> Result<(), std::io::Error> {
-//            use libseccomp::{ScmpAction, ScmpSyscall, ScmpArgCompare, ScmpCompareOp};
-//
-//            // Allow open with O_RDONLY only (flags == 0)
-//            let open_sys = ScmpSyscall::from_name("open")
-//                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("seccomp: {e:?}")))?;
-//            filter.add_rule_conditional(
-//                ScmpAction::Allow,
-//                open_sys,
-//                &[ScmpArgCompare::new(1, ScmpCompareOp::Eq, libc::O_RDONLY as u64)],
-//            ).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("seccomp: {e:?}")))?;
-//
-//            // Allow openat with O_RDONLY only (flags == 0)
-//            let openat_sys = ScmpSyscall::from_name("openat")
-//                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("seccomp: {e:?}")))?;
-//            filter.add_rule_conditional(
-//                ScmpAction::Allow,
-//                openat_sys,
-//                &[ScmpArgCompare::new(2, ScmpCompareOp::Eq, libc::O_RDONLY as u64)],
-//            ).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("seccomp: {e:?}")))?;
-//
-//            Ok(())
-//        }
-//
-//        // --- Helper function for filtered socket() ---
-//        fn allow_af_unix_socket(filter: &mut libseccomp::ScmpFilterContext) -> Result<(), std::io::Error> {
-//            use libseccomp::{ScmpAction, ScmpSyscall, ScmpArgCompare, ScmpCompareOp};
-//
-//            // Allow socket(AF_UNIX, ...), i.e., domain == AF_UNIX (1)
-//            let socket_sys = ScmpSyscall::from_name("socket")
-//                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("seccomp: {e:?}")))?;
-//            filter.add_rule_conditional(
-//                ScmpAction::Allow,
-//                socket_sys,
-//                &[ScmpArgCompare::new(0, ScmpCompareOp::Eq, libc::AF_UNIX as u64)],
-//            ).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("seccomp: {e:?}")))?;
-//
-//            Ok(())
-//        }
+        let config_entry = self.config_entry.clone();
+        let seccomp_action = self.scmp_action();
 
+        //        fn allow_open_readonly(filter: &mut libseccomp::ScmpFilterContext) -> Result<(), std::io::Error> {
+        //            use libseccomp::{ScmpAction, ScmpSyscall, ScmpArgCompare, ScmpCompareOp};
+        //
+        //            // Allow open with O_RDONLY only (flags == 0)
+        //            let open_sys = ScmpSyscall::from_name("open")
+        //                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("seccomp: {e:?}")))?;
+        //            filter.add_rule_conditional(
+        //                ScmpAction::Allow,
+        //                open_sys,
+        //                &[ScmpArgCompare::new(1, ScmpCompareOp::Eq, libc::O_RDONLY as u64)],
+        //            ).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("seccomp: {e:?}")))?;
+        //
+        //            // Allow openat with O_RDONLY only (flags == 0)
+        //            let openat_sys = ScmpSyscall::from_name("openat")
+        //                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("seccomp: {e:?}")))?;
+        //            filter.add_rule_conditional(
+        //                ScmpAction::Allow,
+        //                openat_sys,
+        //                &[ScmpArgCompare::new(2, ScmpCompareOp::Eq, libc::O_RDONLY as u64)],
+        //            ).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("seccomp: {e:?}")))?;
+        //
+        //            Ok(())
+        //        }
+        //
+        //        // --- Helper function for filtered socket() ---
+        //        fn allow_af_unix_socket(filter: &mut libseccomp::ScmpFilterContext) -> Result<(), std::io::Error> {
+        //            use libseccomp::{ScmpAction, ScmpSyscall, ScmpArgCompare, ScmpCompareOp};
+        //
+        //            // Allow socket(AF_UNIX, ...), i.e., domain == AF_UNIX (1)
+        //            let socket_sys = ScmpSyscall::from_name("socket")
+        //                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("seccomp: {e:?}")))?;
+        //            filter.add_rule_conditional(
+        //                ScmpAction::Allow,
+        //                socket_sys,
+        //                &[ScmpArgCompare::new(0, ScmpCompareOp::Eq, libc::AF_UNIX as u64)],
+        //            ).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("seccomp: {e:?}")))?;
+        //
+        //            Ok(())
+        //        }
 
         unsafe {
             command.pre_exec(move || {
@@ -326,17 +378,13 @@ impl Sandbox {
                 // Rebuild and load seccomp filter in child
                 let filter = {
                     // Reconstruct the filter as in seccomp_filter()
-                    let mut filter = if std::env::var("GLYCIN_SECCOMP_DEFAULT_ACTION")
-                        .ok()
-                        .as_deref()
-                        == Some("KILL_PROCESS")
-                    {
-                        libseccomp::ScmpFilterContext::new(libseccomp::ScmpAction::KillProcess)
-                            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("seccomp: {e:?}")))?
-                    } else {
-                        libseccomp::ScmpFilterContext::new(libseccomp::ScmpAction::Trap)
-                            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("seccomp: {e:?}")))?
-                    };
+                    let mut filter =
+                        libseccomp::ScmpFilterContext::new(seccomp_action).map_err(|e| {
+                            std::io::Error::new(
+                                std::io::ErrorKind::Other,
+                                format!("seccomp: {e:?}"),
+                            )
+                        })?;
 
                     let mut syscalls = vec![ALLOWED_SYSCALLS];
                     if config_entry.fontconfig() {
@@ -344,10 +392,21 @@ impl Sandbox {
                     }
 
                     for syscall_name in syscalls.into_iter().flatten() {
-                        let syscall = libseccomp::ScmpSyscall::from_name(syscall_name)
-                            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("seccomp: {e:?}")))?;
-                        filter.add_rule(libseccomp::ScmpAction::Allow, syscall)
-                            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("seccomp: {e:?}")))?;
+                        let syscall =
+                            libseccomp::ScmpSyscall::from_name(syscall_name).map_err(|e| {
+                                std::io::Error::new(
+                                    std::io::ErrorKind::Other,
+                                    format!("seccomp: {e:?}"),
+                                )
+                            })?;
+                        filter
+                            .add_rule(libseccomp::ScmpAction::Allow, syscall)
+                            .map_err(|e| {
+                                std::io::Error::new(
+                                    std::io::ErrorKind::Other,
+                                    format!("seccomp: {e:?}"),
+                                )
+                            })?;
                     }
                     filter
                 };
@@ -395,6 +454,21 @@ impl Sandbox {
         // Forward dbus connection
         command.arg(format!("--forward-fd={dbus_fd}"));
 
+        // Expose additional paths, like the font directory
+        for path in &self.ro_bind_extra {
+            command.arg(format!("--sandbox-expose-path-ro={}", path.display()));
+        }
+
+        if let Some(fontconfig_file) = &self.fontconfig_file {
+            command.arg(format!("--env=FONTCONFIG_FILE={}", fontconfig_file.display()));
+        }
+
+        // Expose writable cache directory
+        if let Some(cache_dir) = &self.cache_dir {
+            command.arg(format!("--sandbox-expose-path={}", cache_dir.display()));
+            command.arg(format!("--env=XDG_CACHE_HOME={}", cache_dir.display()));
+        }
+
         // Start loader with memory limit
         command.arg("prlimit");
         command.arg(format!("--as={memory_limit}"));
@@ -424,6 +498,9 @@ impl Sandbox {
             }
         }
 
+        self.set_fontconfig_file(&mut command);
+        self.set_cache_home(&mut command);
+
         // Set sandbox memory limit
         unsafe {
             command.pre_exec(|| {
@@ -509,18 +586,18 @@ impl Sandbox {
         }
     }
 
-    fn seccomp_filter(&self) -> Result<ScmpFilterContext, SeccompError> {
+    /// Default action of the seccomp filter
+    fn scmp_action(&self) -> ScmpAction {
         // Using `KillProcess` allows rejected syscalls to be logged by auditd. But it
         // doesn't work with tools like valgrind. That's why it's not used by default.
-        let mut filter = if std::env::var("GLYCIN_SECCOMP_DEFAULT_ACTION")
-            .ok()
-            .as_deref()
-            == Some("KILL_PROCESS")
-        {
-            ScmpFilterContext::new(ScmpAction::KillProcess)?
-        } else {
-            ScmpFilterContext::new(ScmpAction::Trap)?
-        };
+        match self.seccomp_action {
+            SeccompAction::Trap => ScmpAction::Trap,
+            SeccompAction::KillProcess => ScmpAction::KillProcess,
+        }
+    }
+
+    fn seccomp_filter(&self) -> Result<ScmpFilterContext, SeccompError> {
+        let mut filter = ScmpFilterContext::new(self.scmp_action())?;
 
         let mut syscalls = vec![ALLOWED_SYSCALLS];
         if self.config_entry.fontconfig() {
@@ -558,6 +635,7 @@ impl Sandbox {
             exec: PathBuf::from("/bin/true"),
             expose_base_dir: false,
             fontconfig: false,
+            max_dimensions: None,
         });
 
         let (dbus_socket, _) = UnixStream::pair()?;
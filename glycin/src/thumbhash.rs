@@ -0,0 +1,465 @@
+//! Implementation of the ThumbHash placeholder format
+//!
+//! See <https://evanw.github.io/thumbhash/> for a description of the format.
+//! The hash stores the DCT coefficients of the image in the LPQA color space
+//! with luminance, yellow-blue, red-green, and alpha channels.
+
+use std::f64::consts::PI;
+
+use crate::Error;
+
+/// Maximum width and height of images that are encoded
+///
+/// Larger images take longer to encode without improving the result.
+pub const MAX_ENCODE_SIZE: u32 = 100;
+
+/// Width and height of decoded images along the longer side
+const DECODE_SIZE: f64 = 32.;
+
+/// Dimensions that fit within [`MAX_ENCODE_SIZE`] with the same aspect ratio
+// The scaled dimensions are at most `MAX_ENCODE_SIZE`
+#[allow(clippy::arithmetic_side_effects, clippy::cast_possible_truncation)]
+pub fn encode_dimensions(width: u32, height: u32) -> (u32, u32) {
+    let max = width.max(height);
+    if max <= MAX_ENCODE_SIZE {
+        return (width, height);
+    }
+
+    let scale = |x: u32| {
+        ((u64::from(x) * u64::from(MAX_ENCODE_SIZE)).div_ceil(u64::from(max)) as u32).max(1)
+    };
+
+    (scale(width), scale(height))
+}
+
+/// Encode RGBA data with straight alpha into a ThumbHash
+///
+/// The `rgba` data must not contain any padding between rows.
+// The dimensions are bounded by `MAX_ENCODE_SIZE` and the quantized values
+// by the format
+#[allow(clippy::arithmetic_side_effects, clippy::cast_possible_truncation)]
+pub fn encode(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    let w = width as usize;
+    let h = height as usize;
+    let n_pixels = w * h;
+
+    // Average color weighted by alpha
+    let (mut avg_r, mut avg_g, mut avg_b, mut avg_a) = (0., 0., 0., 0.);
+    for pixel in rgba.chunks_exact(4) {
+        let [r, g, b, alpha] = values(pixel);
+        avg_r += alpha / 255. * r;
+        avg_g += alpha / 255. * g;
+        avg_b += alpha / 255. * b;
+        avg_a += alpha;
+    }
+    if avg_a > 0. {
+        avg_r /= avg_a;
+        avg_g /= avg_a;
+        avg_b /= avg_a;
+    }
+
+    let has_alpha = avg_a < n_pixels as f64;
+    // Use fewer luminance coefficients if there is an alpha channel
+    let l_limit = if has_alpha { 5. } else { 7. };
+    let max_side = w.max(h) as f64;
+    let lx = ((l_limit * w as f64 / max_side).round() as usize).max(1);
+    let ly = ((l_limit * h as f64 / max_side).round() as usize).max(1);
+
+    let mut l = Vec::with_capacity(n_pixels);
+    let mut p = Vec::with_capacity(n_pixels);
+    let mut q = Vec::with_capacity(n_pixels);
+    let mut a = Vec::with_capacity(n_pixels);
+
+    // Convert to LPQA with the transparent parts composed on the average color
+    for pixel in rgba.chunks_exact(4) {
+        let [r, g, b, alpha] = values(pixel);
+        let r = avg_r * (1. - alpha) + alpha / 255. * r;
+        let g = avg_g * (1. - alpha) + alpha / 255. * g;
+        let b = avg_b * (1. - alpha) + alpha / 255. * b;
+
+        l.push((r + g + b) / 3.);
+        p.push((r + g) / 2. - b);
+        q.push(r - g);
+        a.push(alpha);
+    }
+
+    let (l_dc, l_ac, l_scale) = encode_channel(&l, w, h, lx.max(3), ly.max(3));
+    let (p_dc, p_ac, p_scale) = encode_channel(&p, w, h, 3, 3);
+    let (q_dc, q_ac, q_scale) = encode_channel(&q, w, h, 3, 3);
+    let (a_dc, a_ac, a_scale) = if has_alpha {
+        encode_channel(&a, w, h, 5, 5)
+    } else {
+        (1., Vec::new(), 1.)
+    };
+
+    let is_landscape = w > h;
+    let header24 = (63. * l_dc).round() as u32
+        | ((31.5 + 31.5 * p_dc).round() as u32) << 6
+        | ((31.5 + 31.5 * q_dc).round() as u32) << 12
+        | ((31. * l_scale).round() as u32) << 18
+        | u32::from(has_alpha) << 23;
+    let header16 = (if is_landscape { ly } else { lx }) as u16
+        | ((63. * p_scale).round() as u16) << 3
+        | ((63. * q_scale).round() as u16) << 9
+        | u16::from(is_landscape) << 15;
+
+    let mut hash = Vec::with_capacity(25);
+    hash.extend_from_slice(&header24.to_le_bytes()[..3]);
+    hash.extend_from_slice(&header16.to_le_bytes());
+
+    if has_alpha {
+        hash.push((15. * a_dc).round() as u8 | ((15. * a_scale).round() as u8) << 4);
+    }
+
+    // Two coefficients per byte, starting with the low bits
+    let nibbles = l_ac
+        .into_iter()
+        .chain(p_ac)
+        .chain(q_ac)
+        .chain(a_ac)
+        .map(|x| (15. * x).round() as u8)
+        .collect::<Vec<_>>();
+    hash.extend(
+        nibbles
+            .chunks(2)
+            .map(|x| x.iter().rev().fold(0, |byte, nibble| byte << 4 | nibble)),
+    );
+
+    hash
+}
+
+/// Decode a ThumbHash into RGBA data with straight alpha
+///
+/// Returns the width, height, and the data without padding between rows.
+// The decoded image is at most 32×32 pixels and the values are clamped
+#[allow(clippy::arithmetic_side_effects, clippy::cast_possible_truncation)]
+pub fn decode(hash: &[u8]) -> Result<(u32, u32, Vec<u8>), Error> {
+    let [b0, b1, b2, b3, b4, rest @ ..] = hash else {
+        return Err(Error::InvalidThumbhash);
+    };
+
+    let header24 = u32::from_le_bytes([*b0, *b1, *b2, 0]);
+    let header16 = u16::from_le_bytes([*b3, *b4]);
+
+    let l_dc = (header24 & 63) as f64 / 63.;
+    let p_dc = ((header24 >> 6) & 63) as f64 / 31.5 - 1.;
+    let q_dc = ((header24 >> 12) & 63) as f64 / 31.5 - 1.;
+    let l_scale = ((header24 >> 18) & 31) as f64 / 31.;
+    let has_alpha = (header24 >> 23) != 0;
+    let p_scale = ((header16 >> 3) & 63) as f64 / 63.;
+    let q_scale = ((header16 >> 9) & 63) as f64 / 63.;
+    let is_landscape = (header16 >> 15) != 0;
+
+    let l_max = if has_alpha { 5 } else { 7 };
+    let l_min = usize::from(header16 & 7);
+    let (lx, ly) = if is_landscape {
+        (l_max, l_min)
+    } else {
+        (l_min, l_max)
+    };
+
+    let (a_dc, a_scale, rest) = if has_alpha {
+        let [header8, rest @ ..] = rest else {
+            return Err(Error::InvalidThumbhash);
+        };
+        (
+            f64::from(header8 & 15) / 15.,
+            f64::from(header8 >> 4) / 15.,
+            rest,
+        )
+    } else {
+        (1., 1., rest)
+    };
+
+    let mut nibbles = rest.iter().flat_map(|x| [x & 15, x >> 4]);
+    // Boost saturation to compensate for quantization
+    let l_ac = decode_channel(&mut nibbles, lx.max(3), ly.max(3), l_scale)?;
+    let p_ac = decode_channel(&mut nibbles, 3, 3, p_scale * 1.25)?;
+    let q_ac = decode_channel(&mut nibbles, 3, 3, q_scale * 1.25)?;
+    let a_ac = if has_alpha {
+        decode_channel(&mut nibbles, 5, 5, a_scale)?
+    } else {
+        Vec::new()
+    };
+
+    let ratio = lx as f64 / ly as f64;
+    let (w, h) = if ratio > 1. {
+        (DECODE_SIZE, (DECODE_SIZE / ratio).round().max(1.))
+    } else {
+        ((DECODE_SIZE * ratio).round().max(1.), DECODE_SIZE)
+    };
+    let (w, h) = (w as u32, h as u32);
+
+    let mut rgba = Vec::with_capacity((w * h * 4) as usize);
+    for y in 0..h {
+        let fy2 = |cy: usize| (PI / f64::from(h) * (f64::from(y) + 0.5) * cy as f64).cos() * 2.;
+        for x in 0..w {
+            let fx = |cx: usize| (PI / f64::from(w) * (f64::from(x) + 0.5) * cx as f64).cos();
+
+            let l = decode_value(l_dc, &l_ac, |value, cx, cy| value * fx(cx) * fy2(cy));
+            let p = decode_value(p_dc, &p_ac, |value, cx, cy| value * (fx(cx) * fy2(cy)));
+            let q = decode_value(q_dc, &q_ac, |value, cx, cy| value * (fx(cx) * fy2(cy)));
+            let a = decode_value(a_dc, &a_ac, |value, cx, cy| value * fx(cx) * fy2(cy));
+
+            let b = l - 2. / 3. * p;
+            let r = (3. * l - b + q) / 2.;
+            let g = r - q;
+
+            // Truncated like the reference implementation
+            rgba.extend([r, g, b, a].map(|x| (x.clamp(0., 1.) * 255.) as u8));
+        }
+    }
+
+    Ok((w, h, rgba))
+}
+
+/// Color values and the normalized alpha value
+///
+/// The order of the floating point operations follows the reference
+/// implementation to get identical hashes.
+fn values(pixel: &[u8]) -> [f64; 4] {
+    let &[r, g, b, a] = pixel else {
+        return [0.; 4];
+    };
+    [
+        f64::from(r),
+        f64::from(g),
+        f64::from(b),
+        f64::from(a) / 255.,
+    ]
+}
+
+/// Indices `(cx, cy)` of the AC coefficients for `nx` × `ny` coefficients
+///
+/// Only the upper left triangle of the coefficients is stored.
+// At most 7 coefficients per direction
+#[allow(clippy::arithmetic_side_effects)]
+fn ac_indices(nx: usize, ny: usize) -> impl Iterator<Item = (usize, usize)> {
+    (0..ny)
+        .flat_map(move |cy| {
+            (0..nx)
+                .take_while(move |cx| cx * ny < nx * (ny - cy))
+                .map(move |cx| (cx, cy))
+        })
+        .skip(1)
+}
+
+/// DC value, normalized AC values, and scale of the AC values
+// The dimensions are bounded by `MAX_ENCODE_SIZE`
+#[allow(clippy::arithmetic_side_effects)]
+fn encode_channel(
+    channel: &[f64],
+    w: usize,
+    h: usize,
+    nx: usize,
+    ny: usize,
+) -> (f64, Vec<f64>, f64) {
+    let coefficient = |cx: usize, cy: usize| {
+        let fx = (0..w)
+            .map(|x| (PI / w as f64 * cx as f64 * (x as f64 + 0.5)).cos())
+            .collect::<Vec<_>>();
+
+        let mut sum = 0.;
+        for (y, row) in channel.chunks_exact(w).enumerate() {
+            let fy = (PI / h as f64 * cy as f64 * (y as f64 + 0.5)).cos();
+            for (value, fx) in row.iter().zip(&fx) {
+                sum += value * fx * fy;
+            }
+        }
+
+        sum / (w * h) as f64
+    };
+
+    let dc = coefficient(0, 0);
+    let mut ac = ac_indices(nx, ny)
+        .map(|(cx, cy)| coefficient(cx, cy))
+        .collect::<Vec<_>>();
+
+    let scale = ac.iter().fold(0., |scale: f64, x| scale.max(x.abs()));
+    if scale > 0. {
+        for x in ac.iter_mut() {
+            *x = 0.5 + 0.5 / scale * *x;
+        }
+    }
+
+    (dc, ac, scale)
+}
+
+/// AC coefficients with their indices
+fn decode_channel(
+    nibbles: &mut impl Iterator<Item = u8>,
+    nx: usize,
+    ny: usize,
+    scale: f64,
+) -> Result<Vec<(usize, usize, f64)>, Error> {
+    ac_indices(nx, ny)
+        .map(|(cx, cy)| {
+            let bits = nibbles.next().ok_or(Error::InvalidThumbhash)?;
+            Ok((cx, cy, (f64::from(bits) / 7.5 - 1.) * scale))
+        })
+        .collect()
+}
+
+/// Sum of the DC value and the `term` of each AC coefficient
+///
+/// The terms are added in the same order as in the reference implementation.
+fn decode_value(
+    dc: f64,
+    ac: &[(usize, usize, f64)],
+    term: impl Fn(f64, usize, usize) -> f64,
+) -> f64 {
+    ac.iter()
+        .fold(dc, |sum, (cx, cy, value)| sum + term(*value, *cx, *cy))
+}
+
+#[cfg(test)]
+#[allow(
+    clippy::arithmetic_side_effects,
+    clippy::cast_possible_truncation,
+    clippy::indexing_slicing,
+    clippy::unwrap_used
+)]
+mod test {
+    use super::*;
+
+    fn image(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 4]) -> Vec<u8> {
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| pixel(x, y))
+            .collect()
+    }
+
+    fn opaque() -> Vec<u8> {
+        image(8, 4, |x, y| [x as u8 * 32, y as u8 * 64, 128, 255])
+    }
+
+    fn alpha() -> Vec<u8> {
+        image(4, 6, |x, y| {
+            [255, x as u8 * 60, y as u8 * 40, if x < 2 { 0 } else { 255 }]
+        })
+    }
+
+    /// Average color of decoded RGBA data
+    fn average(rgba: &[u8]) -> [f64; 4] {
+        let mut sum = [0.; 4];
+        for pixel in rgba.chunks_exact(4) {
+            for (sum, x) in sum.iter_mut().zip(pixel) {
+                *sum += f64::from(*x);
+            }
+        }
+        sum.map(|x| x / (rgba.len() / 4) as f64)
+    }
+
+    #[test]
+    fn encode_dimensions_test() {
+        assert_eq!(encode_dimensions(80, 20), (80, 20));
+        assert_eq!(encode_dimensions(1000, 500), (100, 50));
+        assert_eq!(encode_dimensions(300, 1000), (30, 100));
+        assert_eq!(encode_dimensions(10000, 1), (100, 1));
+    }
+
+    // Values from the reference implementation at
+    // <https://github.com/evanw/thumbhash>
+    #[test]
+    fn reference() {
+        assert_eq!(
+            encode(8, 4, &opaque()),
+            [
+                92, 23, 10, 52, 154, 112, 135, 119, 128, 136, 120, 135, 136, 135, 128, 112, 7, 247,
+                136
+            ]
+        );
+        assert_eq!(
+            encode(4, 6, &alpha()),
+            [
+                42, 203, 134, 51, 6, 88, 182, 192, 120, 119, 120, 151, 63, 168, 128, 120, 112, 123,
+                120, 136, 135, 136, 119
+            ]
+        );
+
+        let (width, height, rgba) = decode(&encode(8, 4, &opaque())).unwrap();
+        assert_eq!((width, height), (32, 18));
+        assert_eq!(rgba[..8], [13, 0, 148, 255, 15, 0, 149, 255]);
+        assert_eq!(rgba[rgba.len() - 4..], [221, 200, 114, 255]);
+        assert_eq!(rgba.iter().map(|x| u64::from(*x)).sum::<u64>(), 345200);
+
+        let (width, height, rgba) = decode(&encode(4, 6, &alpha())).unwrap();
+        assert_eq!((width, height), (19, 32));
+        assert_eq!(rgba[..8], [253, 169, 73, 43, 253, 167, 72, 30]);
+        assert_eq!(rgba[rgba.len() - 4..], [244, 185, 190, 138]);
+        assert_eq!(rgba.iter().map(|x| u64::from(*x)).sum::<u64>(), 385674);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let color = [200, 100, 50, 255];
+        let hash = encode(20, 10, &image(20, 10, |_, _| color));
+        // No alpha information
+        assert_eq!(hash[2] >> 7, 0);
+
+        // The aspect ratio is approximated via the number of coefficients
+        let (width, height, rgba) = decode(&hash).unwrap();
+        assert_eq!((width, height), (32, 18));
+        for (average, color) in average(&rgba).into_iter().zip(color) {
+            assert!((average - f64::from(color)).abs() < 4., "{average} {color}");
+        }
+    }
+
+    #[test]
+    fn roundtrip_alpha() {
+        // Left half is transparent
+        let hash = encode(
+            10,
+            20,
+            &image(10, 20, |x, _| [0, 0, 255, if x < 5 { 0 } else { 255 }]),
+        );
+        assert_eq!(hash[2] >> 7, 1);
+
+        let (width, height, rgba) = decode(&hash).unwrap();
+        assert_eq!((width, height), (19, 32));
+
+        let [r, g, b, a] = average(&rgba);
+        assert!(r < 8. && g < 8. && b > 247., "{r} {g} {b}");
+        assert!((a - 127.5).abs() < 16., "{a}");
+
+        // Transparent on the left, opaque on the right
+        let (left, right) = (rgba[3], rgba[width as usize * 4 - 1]);
+        assert!(left < 128 && right > 128, "{left} {right}");
+    }
+
+    #[test]
+    fn invalid() {
+        let opaque = encode(8, 4, &opaque());
+        let alpha = encode(4, 6, &alpha());
+
+        for hash in [
+            &[][..],
+            &opaque[..4],
+            &opaque[..opaque.len() - 1],
+            &alpha[..5],
+            &alpha[..alpha.len() - 1],
+        ] {
+            assert!(
+                matches!(decode(hash), Err(Error::InvalidThumbhash)),
+                "{hash:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn from_thumbhash() {
+        let frame = crate::Frame::from_thumbhash("XBcKNJpwh3eAiHiHiIeAcAf3iA==").unwrap();
+        assert_eq!((frame.width(), frame.height()), (32, 18));
+        assert_eq!(frame.memory_format(), crate::MemoryFormat::R8g8b8a8);
+
+        for thumbhash in ["", "XBcKNA==", "XBcKNJpwh3eAiHiHiIeAcAf3"] {
+            assert!(
+                matches!(
+                    crate::Frame::from_thumbhash(thumbhash),
+                    Err(Error::InvalidThumbhash)
+                ),
+                "{thumbhash}"
+            );
+        }
+    }
+}
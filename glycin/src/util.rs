@@ -45,7 +45,8 @@ pub const fn gdk_memory_format(format: MemoryFormat) -> gdk::MemoryFormat {
 #[cfg(feature = "gdk4")]
 pub fn gdk_color_state(format: &ColorState) -> Result<gdk::ColorState, crate::Error> {
     match format {
-        ColorState::Srgb => Ok(gdk::ColorState::srgb()),
+        // Raw frames are displayed as sRGB
+        ColorState::Srgb | ColorState::Unspecified => Ok(gdk::ColorState::srgb()),
         ColorState::Cicp(cicp) => {
             use gufo_common::cicp::VideoRangeFlag;
 
@@ -0,0 +1,153 @@
+use glycin_common::{MemoryFormat, MemoryFormatInfo};
+use glycin_utils::safe_math::*;
+
+use crate::{Error, Frame};
+
+/// Alignment of rows required by wgpu for buffer to texture copies
+///
+/// This is the value of `wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`.
+pub const WGPU_BYTES_PER_ROW_ALIGNMENT: u32 = 256;
+
+/// Texture formats used for wgpu uploads
+///
+/// The variants correspond to the `wgpu::TextureFormat` variants of the same
+/// name. Grayscale frames use one or two channel formats with the gray value
+/// in the red channel and the alpha in the green channel. Shaders have to
+/// swizzle these values accordingly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum WgpuTextureFormat {
+    Bgra8Unorm,
+    Rgba8Unorm,
+    Rgba16Unorm,
+    Rgba16Float,
+    Rgba32Float,
+    R8Unorm,
+    Rg8Unorm,
+    R16Unorm,
+    Rg16Unorm,
+}
+
+impl WgpuTextureFormat {
+    /// Returns if the format requires `wgpu::Features::TEXTURE_FORMAT_16BIT_NORM`
+    pub fn requires_16bit_norm(self) -> bool {
+        matches!(self, Self::Rgba16Unorm | Self::R16Unorm | Self::Rg16Unorm)
+    }
+}
+
+/// Frame data prepared for creating a `wgpu::Texture`
+///
+/// The fields correspond to the values needed for `wgpu::TextureDescriptor`
+/// and `wgpu::TexelCopyBufferLayout`. The texture has a depth of one layer,
+/// a single mip level, and a sample count of one.
+///
+/// The colors are not converted. For [`ColorState::Srgb`](crate::ColorState::Srgb)
+/// the 8-bit data can also be used with the corresponding `*UnormSrgb`
+/// formats to let the GPU decode the transfer function.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct WgpuTexture {
+    pub width: u32,
+    pub height: u32,
+    pub format: WgpuTextureFormat,
+    /// Row stride of `data`, a multiple of [`WGPU_BYTES_PER_ROW_ALIGNMENT`]
+    pub bytes_per_row: u32,
+    pub rows_per_image: u32,
+    /// If the color channels are premultiplied with the alpha channel
+    pub premultiplied_alpha: bool,
+    /// Pixel data with padded rows in the system's byte order
+    pub data: Vec<u8>,
+}
+
+/// Memory format that can be uploaded directly and its wgpu format
+///
+/// wgpu has no three channel formats and no formats with alpha first.
+/// These formats are converted to formats with four channels.
+fn upload_format(memory_format: MemoryFormat) -> (MemoryFormat, WgpuTextureFormat) {
+    match memory_format {
+        MemoryFormat::B8g8r8a8Premultiplied | MemoryFormat::B8g8r8a8 => {
+            (memory_format, WgpuTextureFormat::Bgra8Unorm)
+        }
+        MemoryFormat::R8g8b8a8Premultiplied | MemoryFormat::R8g8b8a8 => {
+            (memory_format, WgpuTextureFormat::Rgba8Unorm)
+        }
+        MemoryFormat::A8r8g8b8Premultiplied => (
+            MemoryFormat::R8g8b8a8Premultiplied,
+            WgpuTextureFormat::Rgba8Unorm,
+        ),
+        MemoryFormat::A8r8g8b8
+        | MemoryFormat::A8b8g8r8
+        | MemoryFormat::R8g8b8
+        | MemoryFormat::B8g8r8 => (MemoryFormat::R8g8b8a8, WgpuTextureFormat::Rgba8Unorm),
+        MemoryFormat::R16g16b16 => (MemoryFormat::R16g16b16a16, WgpuTextureFormat::Rgba16Unorm),
+        MemoryFormat::R16g16b16a16Premultiplied | MemoryFormat::R16g16b16a16 => {
+            (memory_format, WgpuTextureFormat::Rgba16Unorm)
+        }
+        MemoryFormat::R16g16b16Float => (
+            MemoryFormat::R16g16b16a16Float,
+            WgpuTextureFormat::Rgba16Float,
+        ),
+        MemoryFormat::R16g16b16a16Float => (memory_format, WgpuTextureFormat::Rgba16Float),
+        MemoryFormat::R32g32b32Float => (
+            MemoryFormat::R32g32b32a32Float,
+            WgpuTextureFormat::Rgba32Float,
+        ),
+        MemoryFormat::R32g32b32a32FloatPremultiplied | MemoryFormat::R32g32b32a32Float => {
+            (memory_format, WgpuTextureFormat::Rgba32Float)
+        }
+        MemoryFormat::G8a8Premultiplied | MemoryFormat::G8a8 => {
+            (memory_format, WgpuTextureFormat::Rg8Unorm)
+        }
+        MemoryFormat::G8 => (memory_format, WgpuTextureFormat::R8Unorm),
+        MemoryFormat::G16a16Premultiplied | MemoryFormat::G16a16 => {
+            (memory_format, WgpuTextureFormat::Rg16Unorm)
+        }
+        MemoryFormat::G16 => (memory_format, WgpuTextureFormat::R16Unorm),
+    }
+}
+
+pub(crate) fn texture(frame: &Frame) -> Result<WgpuTexture, Error> {
+    let (memory_format, format) = upload_format(frame.memory_format());
+    let frame = frame.to_memory_format(memory_format)?;
+
+    let row_n_bytes = frame.width().smul(memory_format.n_bytes().u32())?;
+    let bytes_per_row = row_n_bytes
+        .checked_next_multiple_of(WGPU_BYTES_PER_ROW_ALIGNMENT)
+        .ok_or(Error::ConversionTooLargerError)?;
+
+    let mut data = vec![
+        0;
+        bytes_per_row
+            .try_usize()?
+            .smul(frame.height().try_usize()?)?
+    ];
+
+    let src_rows = frame.buf_slice().chunks(frame.stride().try_usize()?);
+    for (src_row, row) in src_rows.zip(data.chunks_exact_mut(bytes_per_row.try_usize()?)) {
+        let src_row = src_row
+            .get(..row_n_bytes.try_usize()?)
+            .ok_or(Error::TextureWrongSize {
+                texture_size: frame.buf_slice().len(),
+                frame: format!("{frame:?}"),
+            })?;
+
+        row.get_mut(..src_row.len())
+            .ok_or(Error::ConversionTooLargerError)?
+            .copy_from_slice(src_row);
+    }
+
+    // GPUs expect the data in native byte order
+    if !frame.byte_order().is_native() && memory_format.channel_type().size() == 2 {
+        data.chunks_exact_mut(2).for_each(|x| x.swap(0, 1));
+    }
+
+    Ok(WgpuTexture {
+        width: frame.width(),
+        height: frame.height(),
+        format,
+        bytes_per_row,
+        rows_per_image: frame.height(),
+        premultiplied_alpha: memory_format.is_premultiplied(),
+        data,
+    })
+}
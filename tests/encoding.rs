@@ -14,10 +14,6 @@ fn roundtrip_all() {
         let loader = Loader::new(gio::File::for_path(reference_path));
         let image = loader.load().await.unwrap();
         let frame = image.next_frame().await.unwrap();
-        let width = frame.width();
-        let height = frame.height();
-        let texture = frame.buf_slice();
-        let memory_format = frame.memory_format();
 
         for mime_type in [
             MimeType::AVIF,
@@ -42,9 +38,7 @@ fn roundtrip_all() {
             eprintln!("- {}", mime_type.as_str());
 
             let mut encoder = Creator::new(mime_type.clone()).await.unwrap();
-            encoder
-                .add_frame(width, height, memory_format, texture.to_vec())
-                .unwrap();
+            encoder.add_frame_from(&frame).unwrap();
 
             let encoded_image = encoder.create().await.unwrap();
 
@@ -100,6 +94,46 @@ fn write_jpeg() {
     });
 }
 
+#[test]
+fn jxl_jpeg_reconstruction() {
+    block_on(async {
+        init();
+
+        let jpeg = std::fs::read("test-images/images/color/color.jpg").unwrap();
+        let mut creator = Creator::new(MimeType::JXL).await.unwrap();
+        creator.add_jpeg_lossless(jpeg.clone()).unwrap();
+        let jxl = creator.create().await.unwrap().data_full().unwrap();
+
+        let mut loader = glycin::Loader::new_vec(jxl.clone());
+        loader.reconstruct_jpeg(true);
+        let image = loader.load().await.unwrap();
+        assert_eq!(
+            image
+                .details()
+                .jpeg_reconstruction()
+                .unwrap()
+                .get_full()
+                .unwrap(),
+            jpeg
+        );
+
+        // Broken reconstruction data doesn't prevent loading the image
+        let mut broken = jxl;
+        let jbrd = broken.windows(4).position(|x| x == b"jbrd").unwrap();
+        let size = usize::try_from(u32::from_be_bytes(
+            broken[jbrd - 4..jbrd].try_into().unwrap(),
+        ))
+        .unwrap();
+        broken[jbrd + 4..jbrd - 4 + size].fill(0xff);
+
+        let mut loader = glycin::Loader::new_vec(broken);
+        loader.reconstruct_jpeg(true);
+        let image = loader.load().await.unwrap();
+        assert!(image.details().jpeg_reconstruction().is_none());
+        image.next_frame().await.unwrap();
+    });
+}
+
 #[test]
 fn write_jpeg_stride() {
     block_on(async {
@@ -320,6 +354,212 @@ fn write_png() {
     });
 }
 
+#[test]
+fn write_png_resolution() {
+    block_on(async {
+        init();
+
+        let mut encoder = Creator::new(MimeType::PNG).await.unwrap();
+
+        for (dpi_x, dpi_y) in [(0., 72.), (72., -1.), (f64::NAN, 72.), (72., f64::INFINITY)] {
+            assert!(matches!(
+                encoder.set_resolution(dpi_x, dpi_y),
+                Err(glycin::Error::InvalidResolution { .. })
+            ));
+        }
+
+        encoder.set_resolution(254., 127.).unwrap();
+        encoder
+            .add_frame(2, 1, glycin::MemoryFormat::G8, vec![0, 255])
+            .unwrap();
+
+        let encoded_image = encoder.create().await.unwrap();
+
+        let loader = glycin::Loader::new_vec(encoded_image.data_full().unwrap());
+        let image = loader.load().await.unwrap();
+
+        let (width_inch, height_inch) = image.details().dimensions_inch().unwrap();
+        assert!((width_inch - 2. / 254.).abs() < 1e-4, "{width_inch}");
+        assert!((height_inch - 1. / 127.).abs() < 1e-4, "{height_inch}");
+    });
+}
+
+#[test]
+fn write_gray16() {
+    block_on(async {
+        init();
+
+        let width = 2;
+        let height = 1;
+        let memory_format = glycin::MemoryFormat::G16;
+        let texture = [0x0102_u16, 0xfffe]
+            .into_iter()
+            .flat_map(u16::to_ne_bytes)
+            .collect::<Vec<u8>>();
+
+        for mime_type in [MimeType::PNG, MimeType::TIFF] {
+            eprintln!("- {}", mime_type.as_str());
+
+            let mut encoder = Creator::new(mime_type).await.unwrap();
+            encoder
+                .add_frame(width, height, memory_format, texture.clone())
+                .unwrap();
+
+            let encoded_image = encoder.create().await.unwrap();
+
+            let mut loader = glycin::Loader::new_vec(encoded_image.data_full().unwrap());
+            loader.accepted_memory_formats(glycin::MemoryFormatSelection::G16);
+            let image = loader.load().await.unwrap();
+            let frame = image.next_frame().await.unwrap();
+
+            assert_eq!(frame.memory_format(), memory_format);
+            assert_eq!(frame.buf_slice(), texture);
+        }
+    });
+}
+
+#[test]
+fn write_rgb16() {
+    block_on(async {
+        init();
+
+        let width = 2;
+        let height = 1;
+        let samples = [
+            0x0102_u16, 0xfffe, 0x8001, 0x7f80, 0x00ff, 0xabcd, 0x1234, 0x4321,
+        ];
+
+        for (memory_format, selection, n_channels) in [
+            (
+                glycin::MemoryFormat::R16g16b16,
+                glycin::MemoryFormatSelection::R16g16b16,
+                3,
+            ),
+            (
+                glycin::MemoryFormat::R16g16b16a16,
+                glycin::MemoryFormatSelection::R16g16b16a16,
+                4,
+            ),
+        ] {
+            let texture = samples[..n_channels * 2]
+                .iter()
+                .flat_map(|x| x.to_ne_bytes())
+                .collect::<Vec<u8>>();
+
+            for mime_type in [MimeType::PNG, MimeType::TIFF] {
+                eprintln!("- {} {memory_format:?}", mime_type.as_str());
+
+                let mut encoder = Creator::new(mime_type).await.unwrap();
+                encoder
+                    .add_frame(width, height, memory_format, texture.clone())
+                    .unwrap();
+
+                let encoded_image = encoder.create().await.unwrap();
+
+                let mut loader = glycin::Loader::new_vec(encoded_image.data_full().unwrap());
+                loader.accepted_memory_formats(selection);
+                let image = loader.load().await.unwrap();
+                let frame = image.next_frame().await.unwrap();
+
+                assert_eq!(frame.memory_format(), memory_format);
+                assert_eq!(frame.buf_slice(), texture);
+            }
+        }
+    });
+}
+
+#[test]
+fn write_jpeg_thumbnail() {
+    block_on(async {
+        init();
+
+        let memory_format = glycin::MemoryFormat::R8g8b8;
+
+        let mut encoder = Creator::new(MimeType::JPEG).await.unwrap();
+        encoder
+            .add_frame(16, 16, memory_format, [255, 0, 0].repeat(16 * 16))
+            .unwrap();
+        encoder
+            .set_thumbnail(4, 4, memory_format, [0, 0, 255].repeat(4 * 4))
+            .unwrap();
+        let encoded_image = encoder.create().await.unwrap();
+
+        let loader = glycin::Loader::new_vec(encoded_image.data_full().unwrap());
+        let image = loader.load().await.unwrap();
+        let exif = image.details().metadata_exif().unwrap().get().unwrap();
+
+        let frame = image.next_frame().await.unwrap();
+        assert_eq!((frame.width(), frame.height()), (16, 16));
+        assert!(frame.buf_slice()[0] >= 250);
+
+        let thumbnail = glycin::Loader::new_vec(exif_jpeg_thumbnail(&exif))
+            .load()
+            .await
+            .unwrap()
+            .next_frame()
+            .await
+            .unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (4, 4));
+        let pixel = &thumbnail.buf_slice()[..3];
+        assert!(pixel[0] < 20 && pixel[2] > 230, "{pixel:?}");
+
+        let mut encoder = Creator::new(MimeType::PNG).await.unwrap();
+        assert!(encoder
+            .set_thumbnail(1, 1, memory_format, vec![0, 0, 0])
+            .is_err());
+    });
+}
+
+/// JPEG thumbnail stored in IFD1 of little-endian Exif data
+fn exif_jpeg_thumbnail(exif: &[u8]) -> Vec<u8> {
+    assert_eq!(&exif[..4], b"II\x2a\x00");
+
+    let u16_at = |pos: usize| usize::from(u16::from_le_bytes([exif[pos], exif[pos + 1]]));
+    let u32_at = |pos: usize| u32::from_le_bytes(exif[pos..pos + 4].try_into().unwrap()) as usize;
+
+    let ifd0 = u32_at(4);
+    let ifd1 = u32_at(ifd0 + 2 + u16_at(ifd0) * 12);
+
+    let entry = |tag: usize| {
+        (0..u16_at(ifd1))
+            .map(|i| ifd1 + 2 + i * 12)
+            .find(|pos| u16_at(*pos) == tag)
+            .map(|pos| u32_at(pos + 8))
+            .unwrap()
+    };
+
+    let offset = entry(0x0201);
+    let length = entry(0x0202);
+    exif[offset..offset + length].to_vec()
+}
+
+#[test]
+fn write_progressive() {
+    block_on(async {
+        init();
+
+        let memory_format = glycin::MemoryFormat::R8g8b8;
+
+        let mut encoder = Creator::new(MimeType::PNG).await.unwrap();
+        encoder
+            .add_frame(3, 3, memory_format, (0..27).collect())
+            .unwrap();
+        encoder.set_progressive(true).unwrap();
+        let data = encoder.create().await.unwrap().data_full().unwrap();
+
+        // Interlace method in the IHDR chunk
+        assert_eq!(data[28], 1);
+
+        let loader = glycin::Loader::new_vec(data);
+        let image = loader.load().await.unwrap();
+        let frame = image.next_frame().await.unwrap();
+        assert_eq!(frame.buf_slice(), (0..27).collect::<Vec<u8>>());
+
+        let mut encoder = Creator::new(MimeType::GIF).await.unwrap();
+        assert!(encoder.set_progressive(true).is_err());
+    });
+}
+
 #[test]
 fn write_avif() {
     block_on(async {
@@ -347,3 +587,464 @@ fn write_avif() {
         assert!(frame.buf_slice()[2] <= 2);
     });
 }
+
+#[test]
+fn write_avif_thumbnail() {
+    block_on(async {
+        init();
+
+        let memory_format = glycin::MemoryFormat::R8g8b8;
+
+        let mut encoder = Creator::new(MimeType::AVIF).await.unwrap();
+        encoder
+            .add_frame(16, 16, memory_format, [255, 0, 0].repeat(16 * 16))
+            .unwrap();
+        encoder
+            .set_thumbnail(4, 4, memory_format, [0, 0, 255].repeat(4 * 4))
+            .unwrap();
+        let data = encoder.create().await.unwrap().data_full().unwrap();
+
+        let mut top_level = parse_boxes(&data);
+        let meta = box_content(&mut top_level, b"meta");
+        let mut children = parse_boxes(&meta[4..]);
+
+        let pitm = box_content(&mut children, b"pitm");
+        let id_size = if pitm[0] == 0 { 2 } else { 4 };
+        let primary_id = be_uint(&pitm[4..4 + id_size]);
+
+        // The thumbnail item references the primary item
+        let iref = box_content(&mut children, b"iref");
+        let id_size = if iref[0] == 0 { 2 } else { 4 };
+        let references = parse_boxes(&iref[4..]);
+        let (_, thmb) = references.iter().find(|(x, _)| x == b"thmb").unwrap();
+        let to_ids = thmb[id_size + 2..]
+            .chunks_exact(id_size)
+            .map(be_uint)
+            .collect::<Vec<_>>();
+        assert_eq!(to_ids, [primary_id]);
+
+        // Dimensions of the thumbnail
+        let iprp = box_content(&mut children, b"iprp");
+        let mut iprp_children = parse_boxes(iprp);
+        let ipco = box_content(&mut iprp_children, b"ipco");
+        let dimensions = parse_boxes(ipco)
+            .into_iter()
+            .filter(|(x, _)| x == b"ispe")
+            .map(|(_, ispe)| (be_uint(&ispe[4..8]), be_uint(&ispe[8..12])))
+            .collect::<Vec<_>>();
+        assert!(dimensions.contains(&(4, 4)), "{dimensions:?}");
+
+        let image = Loader::new_vec(data).load().await.unwrap();
+        assert_eq!(
+            (image.details().width(), image.details().height()),
+            (16, 16)
+        );
+    });
+}
+
+#[test]
+fn avif_clean_aperture() {
+    block_on(async {
+        init();
+
+        // Red center surrounded by a blue border
+        let (width, height) = (32, 16);
+        let texture = (0..height)
+            .flat_map(|y| {
+                (0..width).flat_map(move |x| {
+                    if (8..24).contains(&x) && (4..12).contains(&y) {
+                        [255, 0, 0]
+                    } else {
+                        [0, 0, 255]
+                    }
+                })
+            })
+            .collect();
+
+        let mut encoder = Creator::new(MimeType::AVIF).await.unwrap();
+        encoder.set_encoding_quality(100).unwrap();
+        encoder
+            .add_frame(width, height, glycin::MemoryFormat::R8g8b8, texture)
+            .unwrap();
+        let encoded_image = encoder.create().await.unwrap();
+
+        let data = add_clean_aperture(&encoded_image.data_full().unwrap(), 16, 8);
+
+        let image = Loader::new_vec(data).load().await.unwrap();
+        assert_eq!((image.details().width(), image.details().height()), (16, 8));
+
+        let frame = image.next_frame().await.unwrap();
+        assert_eq!((frame.width(), frame.height()), (16, 8));
+
+        // The center of the crop is red
+        let pos = 4 * frame.stride() as usize + 8 * 3;
+        let pixel = &frame.buf_slice()[pos..pos + 3];
+        assert!(pixel[0] > 200 && pixel[2] < 60, "{pixel:?}");
+    });
+}
+
+#[test]
+fn avif_prefer_cicp() {
+    block_on(async {
+        init();
+
+        let mut encoder = Creator::new(MimeType::AVIF).await.unwrap();
+        let frame = encoder
+            .add_frame(1, 1, glycin::MemoryFormat::R8g8b8, vec![255, 0, 0])
+            .unwrap();
+        frame.set_color_icc_profile(Some(vec![1, 2, 3])).unwrap();
+        let encoded_image = encoder.create().await.unwrap();
+
+        // BT.2020 with PQ, which the ICC profile doesn't describe
+        let nclx = [b"nclx".as_slice(), &[0, 9, 0, 16, 0, 9, 0x80]].concat();
+        let data = add_property(&encoded_image.data_full().unwrap(), *b"colr", nclx);
+
+        // By default, the ICC profile is used and the CICP only as fallback
+        let image = Loader::new_vec(data.clone()).load().await.unwrap();
+        let frame = image.next_frame().await.unwrap();
+        assert_eq!(
+            frame
+                .details()
+                .color_icc_profile()
+                .unwrap()
+                .get_full()
+                .unwrap(),
+            [1, 2, 3]
+        );
+        assert_eq!(frame.details().color_cicp_raw(), None);
+
+        let mut loader = Loader::new_vec(data);
+        loader.prefer_cicp(true);
+        let image = loader.load().await.unwrap();
+        let frame = image.next_frame().await.unwrap();
+        assert_eq!(frame.details().color_cicp_raw(), Some([9, 16, 9, 1]));
+        assert_eq!(
+            frame
+                .details()
+                .color_icc_profile()
+                .unwrap()
+                .get_full()
+                .unwrap(),
+            [1, 2, 3]
+        );
+    });
+}
+
+/// Adds a centered `clap` crop to the primary item of a HEIF file
+fn add_clean_aperture(heif: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let clap = [width, 1, height, 1, 0, 1, 0, 1];
+    add_property(
+        heif,
+        *b"clap",
+        clap.iter().flat_map(|x| x.to_be_bytes()).collect(),
+    )
+}
+
+/// Adds a property to the primary item of a HEIF file
+///
+/// Expects the `meta` box in front of the image data, as written by libheif.
+fn add_property(heif: &[u8], box_type: [u8; 4], content: Vec<u8>) -> Vec<u8> {
+    let mut top_level = parse_boxes(heif);
+    let meta = box_content(&mut top_level, b"meta");
+    let old_meta_len = meta.len();
+
+    // The `meta` box is a full box with version and flags
+    let mut children = parse_boxes(&meta[4..]);
+
+    let pitm = box_content(&mut children, b"pitm");
+    let primary_id = if pitm[0] == 0 {
+        be_uint(&pitm[4..6])
+    } else {
+        be_uint(&pitm[4..8])
+    };
+
+    let iprp = box_content(&mut children, b"iprp");
+    let mut iprp_children = parse_boxes(iprp);
+
+    let ipco = box_content(&mut iprp_children, b"ipco");
+    let mut properties = parse_boxes(ipco);
+    properties.push((box_type, content));
+    let property_index = properties.len();
+    *ipco = write_boxes(&properties);
+
+    let ipma = box_content(&mut iprp_children, b"ipma");
+    *ipma = add_association(ipma, primary_id, property_index);
+
+    *box_content(&mut children, b"iprp") = write_boxes(&iprp_children);
+
+    let new_meta_len = 4 + write_boxes(&children).len();
+    let delta = (new_meta_len - old_meta_len) as u64;
+    shift_iloc(box_content(&mut children, b"iloc"), delta);
+
+    let meta = box_content(&mut top_level, b"meta");
+    *meta = [&meta[..4], write_boxes(&children).as_slice()].concat();
+
+    write_boxes(&top_level)
+}
+
+/// Type and content of ISOBMFF boxes
+fn parse_boxes(mut data: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+    let mut boxes = Vec::new();
+    while !data.is_empty() {
+        let size = be_uint(&data[..4]) as usize;
+        boxes.push((data[4..8].try_into().unwrap(), data[8..size].to_vec()));
+        data = &data[size..];
+    }
+    boxes
+}
+
+fn write_boxes(boxes: &[([u8; 4], Vec<u8>)]) -> Vec<u8> {
+    boxes
+        .iter()
+        .flat_map(|(box_type, content)| {
+            let size = u32::try_from(content.len() + 8).unwrap();
+            [size.to_be_bytes().as_slice(), box_type, content].concat()
+        })
+        .collect()
+}
+
+fn box_content<'a>(boxes: &'a mut [([u8; 4], Vec<u8>)], box_type: &[u8; 4]) -> &'a mut Vec<u8> {
+    &mut boxes.iter_mut().find(|(x, _)| x == box_type).unwrap().1
+}
+
+/// Adds an essential property to an item in an `ipma` box
+fn add_association(ipma: &[u8], item_id: u64, property_index: usize) -> Vec<u8> {
+    let version = ipma[0];
+    let large_index = ipma[3] & 1 == 1;
+    let id_size = if version == 0 { 2 } else { 4 };
+    let association_size = if large_index { 2 } else { 1 };
+
+    let mut new = ipma[..8].to_vec();
+    let mut pos = 8;
+    for _ in 0..be_uint(&ipma[4..8]) {
+        let id = be_uint(&ipma[pos..pos + id_size]);
+        let count = ipma[pos + id_size];
+        let end = pos + id_size + 1 + usize::from(count) * association_size;
+
+        if id == item_id {
+            new.extend_from_slice(&ipma[pos..pos + id_size]);
+            new.push(count + 1);
+            new.extend_from_slice(&ipma[pos + id_size + 1..end]);
+            if large_index {
+                new.extend((0x8000 | u16::try_from(property_index).unwrap()).to_be_bytes());
+            } else {
+                new.push(0x80 | u8::try_from(property_index).unwrap());
+            }
+        } else {
+            new.extend_from_slice(&ipma[pos..end]);
+        }
+
+        pos = end;
+    }
+
+    new
+}
+
+/// Moves the file offsets in an `iloc` box by `delta` bytes
+fn shift_iloc(iloc: &mut [u8], delta: u64) {
+    let version = iloc[0];
+    let offset_size = usize::from(iloc[4] >> 4);
+    let length_size = usize::from(iloc[4] & 0xF);
+    let base_offset_size = usize::from(iloc[5] >> 4);
+    let index_size = if version == 0 {
+        0
+    } else {
+        usize::from(iloc[5] & 0xF)
+    };
+    let id_size = if version < 2 { 2 } else { 4 };
+
+    let mut pos = 6 + id_size;
+    for _ in 0..be_uint(&iloc[6..6 + id_size]) {
+        pos += id_size;
+        // Offsets with other construction methods are not file offsets
+        let file_offsets = if version == 0 {
+            true
+        } else {
+            let construction_method = be_uint(&iloc[pos..pos + 2]) & 0xF;
+            pos += 2;
+            construction_method == 0
+        };
+        // Data reference index
+        pos += 2;
+
+        let base_offset = pos..pos + base_offset_size;
+        pos += base_offset_size;
+        if file_offsets && base_offset_size > 0 {
+            add_be_uint(&mut iloc[base_offset], delta);
+        }
+
+        let extent_count = be_uint(&iloc[pos..pos + 2]);
+        pos += 2;
+        for _ in 0..extent_count {
+            pos += index_size;
+            if file_offsets && base_offset_size == 0 {
+                add_be_uint(&mut iloc[pos..pos + offset_size], delta);
+            }
+            pos += offset_size + length_size;
+        }
+    }
+}
+
+fn be_uint(data: &[u8]) -> u64 {
+    data.iter().fold(0, |value, x| value << 8 | u64::from(*x))
+}
+
+fn add_be_uint(data: &mut [u8], delta: u64) {
+    let mut value = be_uint(data) + delta;
+    for byte in data.iter_mut().rev() {
+        *byte = value as u8;
+        value >>= 8;
+    }
+}
+
+#[test]
+fn create_load_only_format() {
+    block_on(async {
+        init();
+
+        assert!(MimeType::PNG.can_load().await);
+        assert!(MimeType::PNG.can_edit().await);
+        assert!(MimeType::PNG.can_create().await);
+
+        // DDS can only be loaded
+        assert!(MimeType::DDS.can_load().await);
+        assert!(!MimeType::DDS.can_edit().await);
+        assert!(!MimeType::DDS.can_create().await);
+
+        let err = Creator::new(MimeType::DDS).await.unwrap_err();
+        assert!(matches!(err, glycin::Error::EditingNotSupported(_)));
+
+        let unknown = MimeType::new_static("image/x-unknown");
+        assert!(!unknown.can_load().await);
+        let err = Creator::new(unknown).await.unwrap_err();
+        assert!(matches!(err, glycin::Error::UnknownImageFormat(..)));
+    });
+}
+
+#[test]
+fn write_animated_png_partial_frames() {
+    block_on(async {
+        init();
+
+        let mut encoder = Creator::new(MimeType::PNG).await.unwrap();
+        let delay = Some(std::time::Duration::from_millis(100));
+
+        let frame = encoder
+            .add_frame(2, 2, glycin::MemoryFormat::R8g8b8, [255, 0, 0].repeat(4))
+            .unwrap();
+        frame.set_delay(delay).unwrap();
+
+        // Only changes the bottom right pixel
+        let frame = encoder
+            .add_frame(1, 1, glycin::MemoryFormat::R8g8b8, vec![0, 0, 255])
+            .unwrap();
+        frame.set_delay(delay).unwrap();
+        frame.set_offset(1, 1).unwrap();
+        frame.set_blend(Some(glycin::FrameBlend::Over)).unwrap();
+        frame.set_dispose(Some(glycin::FrameDispose::None)).unwrap();
+
+        let encoded_image = encoder.create().await.unwrap();
+
+        let loader = glycin::Loader::new_vec(encoded_image.data_full().unwrap());
+        let image = loader.load().await.unwrap();
+
+        let _ = image.next_frame().await.unwrap();
+        let frame = image.next_frame().await.unwrap();
+        assert_eq!((frame.width(), frame.height()), (2, 2));
+        assert_eq!(
+            frame.details().info_animation_blend(),
+            Some(glycin::FrameBlend::Over)
+        );
+
+        assert_eq!(frame.memory_format(), glycin::MemoryFormat::R8g8b8a8);
+        let pixel = |x: usize, y: usize| {
+            let start = y * frame.stride() as usize + x * 4;
+            frame.buf_slice()[start..start + 4].to_vec()
+        };
+        assert_eq!(pixel(0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(1, 1), [0, 0, 255, 255]);
+    });
+}
+
+#[test]
+fn write_animated_gif() {
+    block_on(async {
+        init();
+
+        let mut encoder = Creator::new(MimeType::GIF).await.unwrap();
+        let delays = [40, 250].map(std::time::Duration::from_millis);
+        for (color, delay) in [[255, 0, 0], [0, 0, 255]].into_iter().zip(delays) {
+            let frame = encoder
+                .add_frame(1, 1, glycin::MemoryFormat::R8g8b8, color.to_vec())
+                .unwrap();
+            frame.set_delay(Some(delay)).unwrap();
+        }
+
+        let encoded_image = encoder.create().await.unwrap();
+
+        let loader = glycin::Loader::new_vec(encoded_image.data_full().unwrap());
+        let image = loader.load().await.unwrap();
+
+        for delay in delays {
+            let frame = image.next_frame().await.unwrap();
+            assert_eq!(frame.delay(), Some(delay));
+        }
+    });
+}
+
+#[test]
+fn transcode_many() {
+    use futures_util::StreamExt;
+
+    block_on(async {
+        init();
+
+        // Widths of gray PNMs or `None` for invalid data
+        let inputs = [Some(1), None, Some(2), Some(3), None, Some(4)];
+
+        for concurrency in [0, 1, 3] {
+            let jobs = inputs.iter().map(|width| {
+                let data = match width {
+                    Some(width) => {
+                        let mut data = format!("P5\n{width} 1\n255\n").into_bytes();
+                        data.extend((0..*width).map(|x| x * 10));
+                        data
+                    }
+                    None => b"not an image".to_vec(),
+                };
+                glycin::TranscodeJob::new(Loader::new_vec(data), MimeType::PNG)
+            });
+
+            let mut results = glycin::transcode_many(jobs, concurrency)
+                .collect::<Vec<_>>()
+                .await;
+            results.sort_by_key(|(index, _)| *index);
+
+            // Every job returns exactly one result, also after failed jobs
+            assert_eq!(
+                results.iter().map(|(index, _)| *index).collect::<Vec<_>>(),
+                (0..inputs.len()).collect::<Vec<_>>(),
+                "concurrency {concurrency}"
+            );
+
+            for ((index, result), width) in results.into_iter().zip(inputs) {
+                let Some(width) = width else {
+                    assert!(result.is_err(), "job {index}");
+                    continue;
+                };
+
+                let encoded = result.unwrap();
+                let loader = Loader::new_vec(encoded.data_full().unwrap());
+                let image = loader.load().await.unwrap();
+                let frame = image.next_frame().await.unwrap();
+
+                // The width identifies the input of the job
+                assert_eq!(
+                    (frame.width(), frame.height()),
+                    (u32::from(width), 1),
+                    "job {index}"
+                );
+            }
+        }
+    });
+}
@@ -0,0 +1,37 @@
+use gio::glib;
+use glycin::{ColorState, Error, Frame, MemoryFormat};
+
+#[test]
+fn frame_from_raw() {
+    let buffer = glib::Bytes::from_owned(vec![0_u8; 2 * 8]);
+    let frame = Frame::from_raw(buffer, 2, 2, 8, MemoryFormat::R8g8b8, ColorState::Srgb).unwrap();
+
+    assert_eq!(frame.width(), 2);
+    assert_eq!(frame.height(), 2);
+    assert_eq!(frame.stride(), 8);
+    assert_eq!(frame.memory_format(), MemoryFormat::R8g8b8);
+    assert_eq!(frame.buf_slice().len(), 16);
+}
+
+#[test]
+fn frame_from_raw_invalid() {
+    let frame = |len, width, height, stride| {
+        let buffer = glib::Bytes::from_owned(vec![0_u8; len]);
+        Frame::from_raw(
+            buffer,
+            width,
+            height,
+            stride,
+            MemoryFormat::R8g8b8a8,
+            ColorState::Srgb,
+        )
+    };
+
+    assert!(matches!(
+        frame(15, 2, 2, 8),
+        Err(Error::TextureWrongSize { .. })
+    ));
+    assert!(matches!(frame(16, 2, 2, 7), Err(Error::StrideTooSmall(_))));
+    assert!(matches!(frame(16, 0, 2, 8), Err(Error::WidthZero(_))));
+    assert!(matches!(frame(0, 2, 0, 8), Err(Error::HeightZero(_))));
+}
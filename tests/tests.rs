@@ -1,6 +1,7 @@
 use std::path::Path;
 
-use gio::prelude::FileExt;
+use futures_util::StreamExt;
+use gio::prelude::{CancellableExt, FileExt};
 use utils::*;
 
 mod utils;
@@ -50,11 +51,128 @@ fn animated_numbers() {
     block_on(test_dir_animated("test-images/images/animated-numbers"));
 }
 
+#[test]
+fn animated_numbers_frames() {
+    block_on(test_dir_animated_frames(
+        "test-images/images/animated-numbers",
+    ));
+}
+
 #[test]
 fn input_stream() {
     block_on(test_input_stream());
 }
 
+#[test]
+fn stride_alignment() {
+    block_on(test_stride_alignment());
+}
+
+#[test]
+fn byte_order() {
+    block_on(test_byte_order());
+}
+
+#[test]
+fn raw_frame() {
+    block_on(test_raw_frame());
+}
+
+#[test]
+fn advisories() {
+    block_on(test_advisories());
+}
+
+#[test]
+fn svg_antialias() {
+    block_on(test_svg_antialias());
+}
+
+#[test]
+fn color_profile() {
+    block_on(test_color_profile());
+}
+
+#[test]
+fn loader_override() {
+    block_on(test_loader_override());
+}
+
+#[test]
+fn fallback_formats() {
+    block_on(test_fallback_formats());
+}
+
+#[test]
+fn no_leaked_processes() {
+    block_on(test_no_leaked_processes());
+}
+
+#[test]
+fn image_keeps_loader() {
+    block_on(test_image_keeps_loader());
+}
+
+#[test]
+fn prewarm() {
+    block_on(test_prewarm());
+}
+
+#[test]
+fn exit_hook_requires_spawn() {
+    block_on(test_exit_hook_requires_spawn());
+}
+
+#[test]
+fn content_hash() {
+    block_on(test_content_hash());
+}
+
+#[test]
+fn max_dimensions() {
+    block_on(test_max_dimensions());
+}
+
+#[test]
+fn zero_dimensions() {
+    block_on(test_zero_dimensions());
+}
+
+#[test]
+fn encoded_size() {
+    block_on(test_encoded_size());
+}
+
+#[test]
+fn decode_region() {
+    block_on(test_decode_region());
+}
+
+#[test]
+fn seccomp_default_action() {
+    block_on(test_seccomp_default_action());
+}
+
+#[test]
+fn progress_aggregator() {
+    block_on(test_progress_aggregator());
+}
+
+#[test]
+fn progress_aggregator_cancel() {
+    block_on(test_progress_aggregator_cancel());
+}
+
+#[test]
+fn read_buffer_size() {
+    block_on(test_read_buffer_size());
+}
+
+#[test]
+fn motion_photo() {
+    block_on(test_motion_photo());
+}
+
 fn test_dir(dir: impl AsRef<Path>) {
     block_on(test_dir_options(dir, true));
 }
@@ -111,6 +229,68 @@ async fn test_dir_animated(dir: impl AsRef<Path>) {
     }
 }
 
+async fn test_dir_animated_frames(dir: impl AsRef<Path>) {
+    init();
+
+    let images = std::fs::read_dir(&dir).unwrap();
+
+    for entry in images {
+        let path = entry.unwrap().path();
+        eprintln!("  - {path:?}");
+
+        if skip_file(&path) {
+            eprintln!("    (skipped)");
+            continue;
+        }
+
+        let file = gio::File::for_path(&path);
+        let image = glycin::Loader::new(file.clone()).load().await.unwrap();
+        let frames = image.frames().collect::<Vec<_>>().await;
+        let n_frames = frames
+            .into_iter()
+            .map(|frame| frame.unwrap().details().n_frame())
+            .collect::<Vec<_>>();
+        assert_eq!(n_frames, [Some(0), Some(1), Some(2), Some(3)]);
+
+        // The loader is stopped at the deadline, while the image is still alive
+        let pool = glycin::Pool::new(glycin::PoolConfig::new());
+        let mut loader = glycin::Loader::new(file.clone());
+        loader.pool(pool.clone());
+        let image = loader.load().await.unwrap();
+        let frames = image
+            .frames_until(std::time::Instant::now())
+            .collect::<Vec<_>>()
+            .await;
+        assert!(frames.is_empty());
+        assert!(image.cancellable().is_cancelled());
+
+        let start = std::time::Instant::now();
+        while pool.active_process_count() > 0 {
+            assert!(
+                start.elapsed() < std::time::Duration::from_secs(10),
+                "Loader still running after deadline"
+            );
+            async_io::Timer::after(std::time::Duration::from_millis(10)).await;
+        }
+
+        // Pinned loaders keep running and the image stays usable
+        let mut loader = glycin::Loader::new(file);
+        loader.pool(pool.clone());
+        loader.pin_process(true);
+        let image = loader.load().await.unwrap();
+        let frames = image
+            .frames_until(std::time::Instant::now())
+            .collect::<Vec<_>>()
+            .await;
+        assert!(frames.is_empty());
+        assert!(!image.cancellable().is_cancelled());
+        let frames = image.frames().collect::<Vec<_>>().await;
+        assert!(!frames.is_empty());
+        assert!(frames.into_iter().all(|frame| frame.is_ok()));
+        assert_eq!(pool.active_process_count(), 1);
+    }
+}
+
 async fn test_dir_options(dir: impl AsRef<Path>, exif: bool) {
     init();
 
@@ -151,3 +331,713 @@ async fn test_input_stream() {
 
     assert_eq!(image.details().width(), 600);
 }
+
+async fn test_stride_alignment() {
+    use glycin_utils::MemoryFormatInfo;
+
+    let file = gio::File::for_path("test-images/images/color/color.jpg");
+
+    let reference = glycin::Loader::new(file.clone()).load().await.unwrap();
+    let reference = reference.next_frame().await.unwrap();
+
+    let mut loader = glycin::Loader::new(file.clone());
+    loader.stride_alignment(256);
+    let image = loader.load().await.unwrap();
+    let frame = image.next_frame().await.unwrap();
+
+    assert_eq!(frame.stride() % 256, 0);
+    assert_eq!(
+        frame.buf_slice().len(),
+        frame.stride() as usize * frame.height() as usize
+    );
+
+    let width = frame.width() as usize * frame.memory_format().n_bytes().usize();
+    for (row, reference_row) in frame
+        .buf_slice()
+        .chunks(frame.stride() as usize)
+        .zip(reference.buf_slice().chunks(reference.stride() as usize))
+    {
+        assert_eq!(row[..width], reference_row[..width]);
+        assert!(row[width..].iter().all(|x| *x == 0));
+    }
+
+    let mut loader = glycin::Loader::new(file);
+    loader.stride_alignment(3);
+    let image = loader.load().await.unwrap();
+
+    let err = image.next_frame().await.unwrap_err();
+
+    assert!(matches!(
+        err.error(),
+        glycin::Error::InvalidStrideAlignment { .. }
+    ));
+}
+
+async fn test_byte_order() {
+    init();
+
+    // 16-bit PNM with three pixels per row, which requires row padding
+    let mut pnm = b"P5\n3 2\n65535\n".to_vec();
+    pnm.extend([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+
+    let frame = |byte_order| {
+        let pnm = pnm.clone();
+        async move {
+            let mut loader = glycin::Loader::new_vec(pnm);
+            loader.byte_order(byte_order).stride_alignment(8);
+            let image = loader.load().await.unwrap();
+            image.next_frame().await.unwrap()
+        }
+    };
+
+    let native = frame(glycin::ByteOrder::Native).await;
+    assert_eq!(native.memory_format(), glycin::MemoryFormat::G16);
+    assert_eq!(native.stride(), 8);
+
+    for byte_order in [
+        glycin::ByteOrder::LittleEndian,
+        glycin::ByteOrder::BigEndian,
+    ] {
+        let frame = frame(byte_order).await;
+        assert_eq!(frame.stride(), native.stride());
+
+        for (row, native_row) in frame
+            .buf_slice()
+            .chunks(frame.stride() as usize)
+            .zip(native.buf_slice().chunks(native.stride() as usize))
+        {
+            let (pixels, padding) = row.split_at(6);
+            let (native_pixels, native_padding) = native_row.split_at(6);
+
+            if byte_order.is_native() {
+                assert_eq!(pixels, native_pixels, "{byte_order:?}");
+            } else {
+                let swapped = native_pixels
+                    .chunks_exact(2)
+                    .flat_map(|x| [x[1], x[0]])
+                    .collect::<Vec<_>>();
+                assert_eq!(pixels, swapped, "{byte_order:?}");
+            }
+            assert_eq!(padding, native_padding, "{byte_order:?}");
+        }
+    }
+}
+
+async fn test_raw_frame() {
+    let file = gio::File::for_path("test-images/images/color/color.jpg");
+
+    let mut loader = glycin::Loader::new(file);
+    loader.accepted_memory_formats(glycin::MemoryFormatSelection::R8g8b8a8);
+    let image = loader.load().await.unwrap();
+
+    // The format is passed through despite the accepted memory formats
+    let frame = image
+        .specific_frame(glycin::FrameRequest::new().raw(true))
+        .await
+        .unwrap();
+
+    assert_eq!(frame.memory_format(), glycin::MemoryFormat::R8g8b8);
+    assert!(matches!(
+        frame.color_state(),
+        glycin::ColorState::Unspecified
+    ));
+}
+
+async fn test_advisories() {
+    let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10" onload="alert()">
+        <image href="https://example.org/tracker.png" width="10" height="10"/>
+    </svg>"#;
+
+    let mut loader = glycin::Loader::new_vec(svg.to_vec());
+    loader.collect_advisories(true);
+    let image = loader.load().await.unwrap();
+
+    assert_eq!(
+        image.details().advisories(),
+        [
+            "SVG references external resource 'https://example.org/tracker.png'",
+            "SVG contains scripts",
+        ]
+    );
+
+    // Frames contain the advisories of the image
+    let frame = image.next_frame().await.unwrap();
+    assert_eq!(frame.details().advisories(), image.details().advisories());
+
+    // Not reported without the option
+    let image = glycin::Loader::new_vec(svg.to_vec()).load().await.unwrap();
+    assert!(image.details().advisories().is_empty());
+}
+
+async fn test_svg_antialias() {
+    let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="32" height="32">
+        <circle cx="16" cy="16" r="11.3"/>
+    </svg>"#;
+
+    let alpha_values = |antialias| async move {
+        let mut loader = glycin::Loader::new_vec(svg.to_vec());
+        loader.accepted_memory_formats(glycin::MemoryFormatSelection::R8g8b8a8);
+        let image = loader.load().await.unwrap();
+        let frame = image
+            .specific_frame(glycin::FrameRequest::new().antialias(antialias))
+            .await
+            .unwrap();
+
+        frame
+            .buf_slice()
+            .chunks_exact(4)
+            .map(|x| x[3])
+            .collect::<std::collections::BTreeSet<u8>>()
+    };
+
+    // Without antialiasing, the edges are either opaque or transparent
+    assert_eq!(alpha_values(glycin::Antialias::None).await, [0, 255].into());
+    assert!(alpha_values(glycin::Antialias::Best).await.len() > 2);
+}
+
+async fn test_color_profile() {
+    init();
+
+    for entry in std::fs::read_dir("test-images/images/color-iccp-pro").unwrap() {
+        let path = entry.unwrap().path();
+        eprintln!("  - {path:?}");
+
+        if skip_file(&path) {
+            eprintln!("    (skipped)");
+            continue;
+        }
+
+        let file = gio::File::for_path(&path);
+        let image = glycin::Loader::new(file).load().await.unwrap();
+
+        // Not all loaders support reading the profile without decoding
+        let Ok(icc_profile) = image.color_profile().await else {
+            eprintln!("    (not supported)");
+            continue;
+        };
+
+        // Same profile as for the frame with the profile not applied
+        let frame = image
+            .specific_frame(glycin::FrameRequest::new().raw(true))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            icc_profile.map(|x| x.get_full().unwrap()),
+            frame
+                .details()
+                .color_icc_profile()
+                .map(|x| x.get_full().unwrap()),
+            "{path:?}"
+        );
+    }
+}
+
+async fn test_loader_override() {
+    let file = gio::File::for_path("test-images/images/color/color.jpg");
+
+    // Overrides for other mime types have no effect
+    let mut loader = glycin::Loader::new(file.clone());
+    loader.loader_override(glycin::MimeType::PNG, "/nonexistent/glycin-loader");
+    assert!(loader.load().await.is_ok());
+
+    let mut loader = glycin::Loader::new(file);
+    loader.loader_override(glycin::MimeType::JPEG, "/nonexistent/glycin-loader");
+    assert!(loader.load().await.is_err());
+}
+
+async fn test_fallback_formats() {
+    init();
+
+    let mut creator = glycin::Creator::new(glycin::MimeType::TIFF).await.unwrap();
+    creator
+        .add_frame(1, 1, glycin::MemoryFormat::R8g8b8, vec![255, 0, 0])
+        .unwrap();
+    let data = creator.create().await.unwrap().data_full().unwrap();
+
+    // The extension is preferred for TIFF data since it might be a RAW format
+    let mut path = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    path.push("fallback-formats.png");
+    std::fs::write(&path, data).unwrap();
+    let file = gio::File::for_path(&path);
+
+    let loader = glycin::Loader::new(file.clone());
+    assert!(loader.load().await.is_err());
+
+    let mut loader = glycin::Loader::new(file);
+    loader.fallback_formats(true);
+    let image = loader.load().await.unwrap();
+
+    assert_eq!(image.mime_type(), glycin::MimeType::TIFF);
+    assert_eq!(image.failed_mime_types(), [glycin::MimeType::PNG]);
+}
+
+async fn test_no_leaked_processes() {
+    init();
+
+    let mut config = glycin::PoolConfig::new();
+    config.loader_retention_time(std::time::Duration::ZERO);
+    let pool = glycin::Pool::new(config);
+
+    // A cancellable that outlives all images
+    let cancellable = gio::Cancellable::new();
+
+    for i in 0..100 {
+        let file = gio::File::for_path("test-images/images/color/color.jpg");
+        let mut loader = glycin::Loader::new(file);
+        loader.pool(pool.clone());
+
+        if i % 2 == 0 {
+            // Cancel some loads at different stages
+            let cancellable = gio::Cancellable::new();
+            loader.cancellable(cancellable.clone());
+            let cancel = async {
+                async_io::Timer::after(std::time::Duration::from_millis(i / 2)).await;
+                cancellable.cancel();
+            };
+            let (_result, ()) = futures_util::join!(loader.load(), cancel);
+        } else {
+            loader.cancellable(cancellable.clone());
+            let image = loader.load().await.unwrap();
+            let _result = image.next_frame().await;
+        }
+    }
+
+    let start = std::time::Instant::now();
+    while pool.active_process_count() > 0 {
+        assert!(
+            start.elapsed() < std::time::Duration::from_secs(10),
+            "{} processes leaked",
+            pool.active_process_count()
+        );
+        async_io::Timer::after(std::time::Duration::from_millis(10)).await;
+    }
+}
+
+async fn test_image_keeps_loader() {
+    init();
+
+    let n_spawned = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let mut config = glycin::PoolConfig::new();
+    config.loader_retention_time(std::time::Duration::ZERO);
+    config.on_spawn({
+        let n_spawned = n_spawned.clone();
+        move |_| {
+            n_spawned.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    });
+    let pool = glycin::Pool::new(config);
+
+    let file = gio::File::for_path("test-images/images/color/color.jpg");
+    let mut loader = glycin::Loader::new(file);
+    loader.pool(pool.clone());
+    let image = loader.load().await.unwrap();
+
+    // Idle for longer than the retention time
+    async_io::Timer::after(std::time::Duration::from_millis(500)).await;
+    assert_eq!(pool.active_process_count(), 1);
+
+    image.next_frame().await.unwrap();
+    assert_eq!(n_spawned.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    drop(image);
+    let start = std::time::Instant::now();
+    while pool.active_process_count() > 0 {
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+        async_io::Timer::after(std::time::Duration::from_millis(10)).await;
+    }
+}
+
+async fn test_prewarm() {
+    init();
+
+    let n_spawned = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let mut config = glycin::PoolConfig::new();
+    config.on_spawn({
+        let n_spawned = n_spawned.clone();
+        move |_| {
+            n_spawned.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    });
+    let pool = glycin::Pool::new(config);
+
+    pool.prewarm(&[glycin::MimeType::JPEG]).await.unwrap();
+    assert_eq!(n_spawned.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    // The prewarmed loader is already in the pool
+    pool.prewarm(&[glycin::MimeType::JPEG]).await.unwrap();
+    assert_eq!(n_spawned.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    // Loading uses the prewarmed loader, also with a font directory that the
+    // loader doesn't use
+    let file = gio::File::for_path("test-images/images/color/color.jpg");
+    let mut loader = glycin::Loader::new(file);
+    loader.pool(pool.clone());
+    loader.font_dir("test-images");
+    let image = loader.load().await.unwrap();
+    image.next_frame().await.unwrap();
+    assert_eq!(n_spawned.load(std::sync::atomic::Ordering::SeqCst), 1);
+    drop(image);
+
+    pool.prewarm_editors(&[glycin::MimeType::PNG])
+        .await
+        .unwrap();
+    assert_eq!(n_spawned.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+    pool.prewarm_editors(&[glycin::MimeType::PNG])
+        .await
+        .unwrap();
+    assert_eq!(n_spawned.load(std::sync::atomic::Ordering::SeqCst), 2);
+}
+
+async fn test_exit_hook_requires_spawn() {
+    init();
+
+    let n_spawned = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let n_exited = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let mut config = glycin::PoolConfig::new();
+    config.on_spawn({
+        let n_spawned = n_spawned.clone();
+        move |_| {
+            n_spawned.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    });
+    config.on_exit({
+        let n_exited = n_exited.clone();
+        move |_, _| {
+            n_exited.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    });
+    let pool = glycin::Pool::new(config);
+
+    // Exits before it is initialized
+    let file = gio::File::for_path("test-images/images/color/color.jpg");
+    let mut loader = glycin::Loader::new(file);
+    loader.pool(pool.clone());
+    loader.sandbox_selector(glycin::SandboxSelector::NotSandboxed);
+    loader.loader_override(glycin::MimeType::JPEG, "/bin/true");
+    assert!(loader.load().await.is_err());
+
+    let start = std::time::Instant::now();
+    while pool.active_process_count() > 0 {
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+        async_io::Timer::after(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(n_spawned.load(std::sync::atomic::Ordering::SeqCst), 0);
+    assert_eq!(n_exited.load(std::sync::atomic::Ordering::SeqCst), 0);
+}
+
+async fn test_content_hash() {
+    init();
+
+    let path = "test-images/images/color/color.jpg";
+    let data = std::fs::read(path).unwrap();
+    let digest = gio::glib::compute_checksum_for_data(gio::glib::ChecksumType::Sha256, &data)
+        .unwrap()
+        .to_ascii_uppercase();
+
+    let mut loader = glycin::Loader::new(gio::File::for_path(path));
+    loader.expect_content_hash(glycin::ContentHash::sha256(&digest));
+    let image = loader.load().await.unwrap();
+    image.next_frame().await.unwrap();
+
+    // Memfd with the correct hash
+    let memfd = memfd::MemfdOptions::new()
+        .allow_sealing(true)
+        .create("content-hash")
+        .unwrap();
+    std::io::Write::write_all(&mut memfd.as_file(), &data).unwrap();
+    let mut loader =
+        glycin::Loader::new_memfd(std::os::fd::OwnedFd::from(memfd.into_file())).unwrap();
+    loader.expect_content_hash(glycin::ContentHash::sha256(&digest));
+    let image = loader.load().await.unwrap();
+    image.next_frame().await.unwrap();
+
+    // Data from memory with a wrong hash
+    let mut loader = glycin::Loader::new_vec(data);
+    loader.expect_content_hash(glycin::ContentHash::sha256(&"0".repeat(64)));
+    let err = loader.load().await.unwrap_err();
+    assert!(
+        matches!(err.error(), glycin::Error::ContentHashMismatch { actual, .. } if actual.eq_ignore_ascii_case(&digest)),
+        "{err}"
+    );
+}
+
+async fn test_encoded_size() {
+    use glycin_utils::MemoryFormatInfo;
+
+    init();
+
+    let path = "test-images/images/color/color.jpg";
+    let data = std::fs::read(path).unwrap();
+    let len = data.len() as u64;
+
+    // Known directly after loading, without waiting for a frame
+    let image = glycin::Loader::new(gio::File::for_path(path))
+        .load()
+        .await
+        .unwrap();
+    assert_eq!(image.details().encoded_size(), Some(len));
+
+    let image = glycin::Loader::new_vec(data).load().await.unwrap();
+    let details = image.details();
+    assert_eq!(details.encoded_size(), Some(len));
+
+    let frame = image.next_frame().await.unwrap();
+    let ratio = details.compression_ratio(frame.memory_format()).unwrap();
+    let decoded_size = frame.width() * frame.height() * frame.memory_format().n_bytes().u32();
+    assert_eq!(ratio, f64::from(decoded_size) / len as f64);
+}
+
+async fn test_max_dimensions() {
+    init();
+
+    assert!(glycin::MimeType::SVG.can_load().await);
+    assert_eq!(
+        glycin::MimeType::SVG.max_dimensions().await,
+        Some((32767, 32767))
+    );
+
+    assert!(glycin::MimeType::PNG.can_load().await);
+    assert_eq!(glycin::MimeType::PNG.max_dimensions().await, None);
+
+    // Oversized requests fail instead of never returning a frame
+    let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="32" height="32"/>"#;
+    let image = glycin::Loader::new_vec(svg.to_vec()).load().await.unwrap();
+    assert!(image
+        .specific_frame(glycin::FrameRequest::new().scale(40_000, 40_000))
+        .await
+        .is_err());
+    assert!(image
+        .specific_frame(glycin::FrameRequest::new().scale(1000, 1000))
+        .await
+        .is_ok());
+}
+
+async fn test_zero_dimensions() {
+    init();
+
+    // PNM header without pixel data
+    for (pnm, width, height) in [(b"P5\n0 2\n255\n", 0, 2), (b"P5\n2 0\n255\n", 2, 0)] {
+        // Loaded by default, but there are no frames
+        let image = glycin::Loader::new_vec(pnm.to_vec()).load().await.unwrap();
+        let details = image.details();
+        assert_eq!((details.width(), details.height()), (width, height));
+        assert!(image.next_frame().await.is_err());
+
+        let mut loader = glycin::Loader::new_vec(pnm.to_vec());
+        loader.zero_dimensions_policy(glycin::ZeroDimensionsPolicy::Error);
+        let err = loader.load().await.unwrap_err();
+
+        if width == 0 {
+            assert!(
+                matches!(err.error(), glycin::Error::WidthZero(_)),
+                "{err:?}"
+            );
+        } else {
+            assert!(
+                matches!(err.error(), glycin::Error::HeightZero(_)),
+                "{err:?}"
+            );
+        }
+    }
+}
+
+async fn test_decode_region() {
+    use glycin_utils::MemoryFormatInfo;
+
+    init();
+
+    let file = gio::File::for_path("test-images/images/color/color.jpg");
+    let image = glycin::Loader::new(file.clone()).load().await.unwrap();
+    let full = image.next_frame().await.unwrap();
+
+    let image = glycin::Loader::new(file.clone()).load().await.unwrap();
+    let region = image
+        .decode_region((10, 20, 30, 40), glycin::CoordinateSpace::Oriented)
+        .await
+        .unwrap();
+    assert_eq!((region.width(), region.height()), (30, 40));
+
+    let pixel_size = full.memory_format().n_bytes().usize();
+    let row_size = 30 * pixel_size;
+    for (y, row) in region
+        .buf_slice()
+        .chunks(region.stride() as usize)
+        .enumerate()
+    {
+        let start = (20 + y) * full.stride() as usize + 10 * pixel_size;
+        assert_eq!(row[..row_size], full.buf_slice()[start..start + row_size]);
+    }
+
+    let image = glycin::Loader::new(file.clone()).load().await.unwrap();
+    let err = image
+        .decode_region((590, 0, 20, 20), glycin::CoordinateSpace::Stored)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.error(),
+        glycin::Error::RegionOutsideImage { .. }
+    ));
+
+    // Oriented clips of frame requests are checked as well
+    let image = glycin::Loader::new(file).load().await.unwrap();
+    let err = image
+        .specific_frame(
+            glycin::FrameRequest::new()
+                .clip(590, 0, 20, 20)
+                .clip_space(glycin::CoordinateSpace::Oriented),
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.error(),
+        glycin::Error::RegionOutsideImage { .. }
+    ));
+}
+
+async fn test_seccomp_default_action() {
+    init();
+
+    let mut config = glycin::PoolConfig::new();
+    config.seccomp_default_action(glycin::SeccompAction::KillProcess);
+    let pool = glycin::Pool::new(config);
+
+    let file = gio::File::for_path("test-images/images/color/color.jpg");
+    let mut loader = glycin::Loader::new(file);
+    loader.pool(pool);
+    let image = loader.load().await.unwrap();
+    assert!(image.next_frame().await.is_ok());
+}
+
+async fn test_progress_aggregator() {
+    init();
+
+    let aggregator = glycin::ProgressAggregator::new();
+
+    let file = gio::File::for_path("test-images/images/color/color.jpg");
+    let mut loader = glycin::Loader::new(file);
+    loader.progress_aggregator(&aggregator);
+    let image = loader.load().await.unwrap();
+    assert_eq!(aggregator.progress().started, 1);
+    assert_eq!(aggregator.progress().completed(), 0);
+    image.next_frame().await.unwrap();
+
+    let mut loader = glycin::Loader::new_vec(b"not an image".to_vec());
+    loader.progress_aggregator(&aggregator);
+    assert!(loader.load().await.is_err());
+
+    // Loaders that are never loaded are not counted
+    let mut loader = glycin::Loader::new_vec(Vec::new());
+    loader.progress_aggregator(&aggregator);
+    drop(loader);
+
+    let progress = aggregator.progress();
+    assert_eq!(progress.total, 2);
+    assert_eq!(progress.finished, 1);
+    assert_eq!(progress.failed, 1);
+    assert_eq!(progress.remaining(), 0);
+    assert_eq!(progress.eta(), Some(std::time::Duration::ZERO));
+}
+
+async fn test_progress_aggregator_cancel() {
+    init();
+
+    let aggregator = glycin::ProgressAggregator::new();
+
+    let threads = (0..20)
+        .map(|_| {
+            let aggregator = aggregator.clone();
+            std::thread::spawn(move || {
+                block_on(async {
+                    let file = gio::File::for_path("test-images/images/color/color.jpg");
+                    let mut loader = glycin::Loader::new(file);
+                    loader.progress_aggregator(&aggregator);
+                    if let Ok(image) = loader.load().await {
+                        let _result = image.next_frame().await;
+                    }
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // Cancel while loads are running and check that no load is completed
+    // without being counted as started
+    let mut canceled = false;
+    while !threads.iter().all(|x| x.is_finished()) {
+        let progress = aggregator.progress();
+        assert!(progress.completed() <= progress.started);
+        assert!(progress.started <= progress.total);
+
+        if !canceled && progress.started > 0 {
+            aggregator.cancel();
+            canceled = true;
+        }
+
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let progress = aggregator.progress();
+    assert_eq!(progress.total, 20);
+    assert_eq!(progress.started, 20);
+    assert_eq!(progress.remaining(), 0);
+    let finished = progress.finished;
+
+    // Loads that start after canceling are not affected
+    let file = gio::File::for_path("test-images/images/color/color.jpg");
+    let mut loader = glycin::Loader::new(file);
+    loader.progress_aggregator(&aggregator);
+    let image = loader.load().await.unwrap();
+    image.next_frame().await.unwrap();
+
+    let progress = aggregator.progress();
+    assert_eq!(progress.finished, finished + 1);
+    assert_eq!(progress.remaining(), 0);
+}
+
+async fn test_motion_photo() {
+    init();
+
+    let mut data = std::fs::read("test-images/images/color/color.jpg").unwrap();
+    let image = glycin::Loader::new_vec(data.clone()).load().await.unwrap();
+    assert_eq!(image.details().motion_photo_video(), None);
+
+    // Samsung motion photo with the video after a marker
+    data.extend_from_slice(b"MotionPhoto_Data");
+    let offset = data.len() as u64;
+    data.extend_from_slice(b"video");
+
+    let image = glycin::Loader::new_vec(data).load().await.unwrap();
+    let details = image.details();
+    assert_eq!(details.motion_photo_video(), Some(offset..offset + 5));
+
+    let key_value = details.metadata_key_value().unwrap();
+    assert_eq!(
+        key_value.get(glycin::KEY_MOTION_PHOTO_VIDEO_OFFSET),
+        Some(&offset.to_string())
+    );
+    assert_eq!(
+        key_value.get(glycin::KEY_MOTION_PHOTO_VIDEO_LENGTH),
+        Some(&String::from("5"))
+    );
+}
+
+async fn test_read_buffer_size() {
+    init();
+
+    // A one byte buffer doesn't prevent detecting the format
+    let mut config = glycin::PoolConfig::new();
+    config.read_buffer_size(1);
+    let pool = glycin::Pool::new(config);
+
+    let file = gio::File::for_path("test-images/images/color/color.jpg");
+    let mut loader = glycin::Loader::new(file);
+    loader.pool(pool);
+    let image = loader.load().await.unwrap();
+    assert_eq!(image.mime_type(), glycin::MimeType::JPEG);
+    assert!(image.next_frame().await.is_ok());
+}